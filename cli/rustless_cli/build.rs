@@ -0,0 +1,32 @@
+use std::process::Command;
+
+/// Runs a command and returns the trimmed output, or None if it fails
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8(output.stdout).ok()?;
+    Some(output.trim().to_string())
+}
+
+/// Embeds the git SHA and build date so they can be shown in the version details
+fn main() {
+    let git_sha = run("git", &["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let build_date = run("date", &["-u", "+%Y-%m-%dT%H:%M:%SZ"]).unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=RUSTLESS_GIT_SHA={}", git_sha);
+    println!("cargo:rustc-env=RUSTLESS_BUILD_DATE={}", build_date);
+
+    // Rebuild when the commit changes so the SHA is kept up to date
+    if let Some(head) = run("git", &["rev-parse", "--git-path", "HEAD"]) {
+        println!("cargo:rerun-if-changed={}", head);
+    }
+    if let Some(git_ref) = run("git", &["symbolic-ref", "-q", "HEAD"]) {
+        if let Some(ref_path) = run("git", &["rev-parse", "--git-path", &git_ref]) {
+            println!("cargo:rerun-if-changed={}", ref_path);
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,136 @@
+use chrono::{DateTime, Local, TimeZone};
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::AppAnalytics;
+
+use crate::error::CliError;
+use crate::output::{self, OutputArgs};
+use crate::overview;
+use crate::server;
+
+/// The columns of the analytics table
+const HEADERS: [&str; 9] = ["NAME", "NAMESPACE", "DEPLOYS", "DEPLOYS/WEEK", "BUILDS", "BUILD SUCCESS", "MEAN BUILD", "CRASHES", "MTTR"];
+
+/// The columns of the table of weekly figures for an app
+const WEEK_HEADERS: [&str; 5] = ["WEEK OF", "DEPLOYS", "BUILDS", "FAILED BUILDS", "CRASHES"];
+
+/// Formats the day a week started, in local time
+fn format_week(timestamp: u64) -> String {
+    let starts_at: Option<DateTime<Local>> = Local.timestamp_opt(timestamp as i64, 0).single();
+    match starts_at {
+        Some(starts_at) => starts_at.format("%d-%m-%Y").to_string(),
+        None => format!("{} (Unix time)", timestamp),
+    }
+}
+
+/// Formats an average time in seconds, or - if there isn't one
+fn format_mean_secs(secs: Option<f64>) -> String {
+    match secs {
+        Some(secs) => overview::format_duration(secs.round() as u64),
+        None => "-".to_string(),
+    }
+}
+
+/// Gets the cells for an app in the analytics table
+fn get_row(app: &AppAnalytics) -> Vec<String> {
+    vec![
+        app.name.to_string(),
+        app.namespace.to_string(),
+        app.deployments.to_string(),
+        format!("{:.1}", app.deploys_per_week),
+        app.builds.to_string(),
+        app.build_success_rate.map(|rate| format!("{:.0}%", rate)).unwrap_or_else(|| "-".to_string()),
+        format_mean_secs(app.mean_build_secs),
+        app.failures.to_string(),
+        format_mean_secs(app.mean_time_to_recovery_secs),
+    ]
+}
+
+/// Writes out the analytics for each app as CSV or markdown rows, with times in seconds so they can be charted
+fn print_analytics_rows(output: &OutputArgs, apps: &[AppAnalytics]) {
+    let headers = ["Name", "Namespace", "Deployments", "Deploys per week", "Builds", "Build success rate (%)", "Mean build time (s)", "Crashes", "Mean time to recovery (s)"];
+
+    let rows: Vec<Vec<String>> = apps.iter().map(|app| vec![
+        app.name.to_string(),
+        app.namespace.to_string(),
+        app.deployments.to_string(),
+        format!("{:.2}", app.deploys_per_week),
+        app.builds.to_string(),
+        app.build_success_rate.map(|rate| format!("{:.1}", rate)).unwrap_or_default(),
+        app.mean_build_secs.map(|secs| format!("{:.0}", secs)).unwrap_or_default(),
+        app.failures.to_string(),
+        app.mean_time_to_recovery_secs.map(|secs| format!("{:.0}", secs)).unwrap_or_default(),
+    ]).collect();
+
+    output::print_rows(output, &headers, &rows);
+}
+
+/// Prints a table with each column sized to fit the widest cell in it
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = headers.iter().zip(widths.iter()).map(|(header, width)| format!("{:<width$}", header, width = width)).collect();
+    println!("{}", header.join("   ").trim_end().bold());
+
+    for row in rows {
+        let cells: Vec<String> = row.iter().zip(widths.iter()).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+        println!("{}", cells.join("   ").trim_end());
+    }
+}
+
+/// Prints the figures for each week for an app, oldest first
+fn print_weeks(app: &AppAnalytics) {
+    let rows: Vec<Vec<String>> = app.weeks.iter().map(|week| vec![
+        format_week(week.starts_at),
+        week.deployments.to_string(),
+        week.builds.to_string(),
+        week.failed_builds.to_string(),
+        week.failures.to_string(),
+    ]).collect();
+
+    println!();
+    println!("{}", format!("Each week for '{}':", app.name).blue());
+    print_table(&WEEK_HEADERS, &rows);
+}
+
+/// Shows how often each function app has been deployed over the last few weeks, how often and how quickly it
+/// builds, and how long it takes to recover from crashes, or only the given app or the apps in the given namespace.
+/// A single app also shows the figures for each week
+pub async fn show_analytics(conn: &Connection, weeks: u32, app: &Option<String>, namespace: &Option<String>, output: &OutputArgs) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    let report = match client.analytics(weeks, app, namespace).await {
+        Ok(Some(report)) => report,
+        Ok(None) => return Err(CliError::AppNotFound(app.clone().unwrap_or_default())),
+        Err(e) => return Err(CliError::Message(format!("Error getting deployment analytics: {}", e))),
+    };
+
+    if !output.is_table() {
+        print_analytics_rows(output, &report.apps);
+        return Ok(());
+    }
+
+    if report.apps.is_empty() {
+        println!("{}", "No function apps registered".blue());
+        return Ok(());
+    }
+
+    let weeks = match report.weeks {
+        1 => "week".to_string(),
+        weeks => format!("{} weeks", weeks),
+    };
+    println!("{}", format!("Deployment analytics for the last {}, since {}:", weeks, format_week(report.since)).blue());
+    let rows: Vec<Vec<String>> = report.apps.iter().map(get_row).collect();
+    print_table(&HEADERS, &rows);
+
+    if let (Some(_), [app]) = (app, report.apps.as_slice()) {
+        print_weeks(app);
+    }
+
+    Ok(())
+}
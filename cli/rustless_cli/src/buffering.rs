@@ -0,0 +1,59 @@
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::BufferingReport;
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// Formats a threshold, noting if it is a default from the namespace or server
+fn format_threshold(threshold: u64, is_default: bool) -> String {
+    match is_default {
+        true => format!("{} bytes (default)", threshold),
+        false => format!("{} bytes", threshold),
+    }
+}
+
+/// Prints the buffering thresholds for a function app and how its bodies have been buffered
+fn print_buffering(name: &String, report: &BufferingReport) {
+    println!("{}", format!("Buffering for '{}':", name).blue());
+    println!("  Requests:  buffered up to {}", format_threshold(report.request_threshold, report.request_threshold_default));
+    println!("  Responses: buffered up to {}", format_threshold(report.response_threshold, report.response_threshold_default));
+
+    let metrics = &report.metrics;
+    println!("Since the server started:");
+    println!("  Requests:  {} buffered, {} streamed", metrics.requests_buffered, metrics.requests_streamed);
+    println!("  Responses: {} buffered, {} streamed", metrics.responses_buffered, metrics.responses_streamed);
+    println!("  Buffered {} bytes in total, largest body {} bytes", metrics.bytes_buffered, metrics.largest_buffer);
+    println!("  Buffered in flight: {} bytes now, {} bytes at peak", metrics.buffered_in_flight, metrics.peak_buffered_in_flight);
+}
+
+/// Sets how many bytes of request and response bodies the gateway buffers for a function app before streaming them,
+/// retrying by name if the cached ID is stale. Thresholds that are None use the namespace or server default
+pub async fn set_buffering(conn: &Connection, name: &String, request_threshold: Option<u64>, response_threshold: Option<u64>) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.set_buffering(&app, request_threshold, response_threshold).await }).await;
+
+    match result {
+        Ok(true) => println!("{}", format!("✅ Buffering set for '{}'", name).green()),
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error setting buffering: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the buffering thresholds for a function app and how the gateway has buffered and streamed its bodies
+pub async fn show_buffering(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.buffering(&app).await }).await;
+
+    match result {
+        Ok(Some(report)) => print_buffering(name, &report),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting buffering details: {}", e))),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use chrono::{DateTime, Local, TimeZone};
+use colored::Colorize;
+use rusqlite::Connection;
+use uuid::Uuid;
+
+use rustless_shared::{BuildLog, BUILD_CANCELLED, BUILD_SUCCEEDED};
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// Formats when a build started or finished in the local timezone
+fn format_timestamp(timestamp: u64) -> String {
+    let time: Option<DateTime<Local>> = Local.timestamp_opt(timestamp as i64, 0).single();
+    match time {
+        Some(time) => time.format("%d-%m-%Y %H:%M:%S").to_string(),
+        None => format!("{} (Unix time)", timestamp),
+    }
+}
+
+/// Prints the result of a build, colored by whether it succeeded
+fn print_result(build_id: &Uuid, result: &str) {
+    match result {
+        BUILD_SUCCEEDED => println!("{}", format!("✅ Build {} succeeded", build_id).green()),
+        BUILD_CANCELLED => println!("{}", format!("Build {} was cancelled", build_id).yellow()),
+        result => println!("{}", format!("Build {} {}", build_id, result).red().bold()),
+    }
+}
+
+/// Prints a build log, with when the build started and how it finished
+fn print_build_log(log: &BuildLog) {
+    println!("{}", format!("Build {} started {}", log.build_id, format_timestamp(log.started_at)).blue());
+    print!("{}", log.output);
+
+    match (&log.result, log.finished_at) {
+        (Some(result), Some(finished_at)) => {
+            print_result(&log.build_id, result);
+            println!("{}", format!("Finished {}", format_timestamp(finished_at)).blue());
+        },
+        _ => println!("{}", "The build is still running. Use --follow to watch it finish".yellow()),
+    }
+}
+
+/// Shows the output of the latest build of a function app, or of the given build
+async fn show_build_log(conn: &Connection, name: &String, build: &Option<Uuid>) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.build_log(&app, build).await }).await;
+
+    match result {
+        Ok(Some(log)) => print_build_log(&log),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting build log: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Follows the output of the latest build of a function app, or of the given build, printing each line as the
+/// build writes it until the build finishes
+async fn follow_build_log(conn: &Connection, name: &String, build: &Option<Uuid>) -> Result<(), CliError> {
+    let print_line = |line: &str| println!("{}", line);
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.follow_build_log(&app, build, print_line).await }).await;
+
+    match result {
+        Ok(Some(result)) => match result.as_str() {
+            BUILD_SUCCEEDED => println!("{}", "✅ Build succeeded".green()),
+            BUILD_CANCELLED => println!("{}", "Build was cancelled".yellow()),
+            result => return Err(CliError::Message(format!("Build {}", result))),
+        },
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error following build log: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the output of the latest build of a function app, or of the given build, optionally following it until
+/// the build finishes
+pub async fn show_build_logs(conn: &Connection, name: &String, build: &Option<Uuid>, follow: bool) -> Result<(), CliError> {
+    match follow {
+        true => follow_build_log(conn, name, build).await,
+        false => show_build_log(conn, name, build).await,
+    }
+}
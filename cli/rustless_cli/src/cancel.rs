@@ -0,0 +1,26 @@
+use std::future::Future;
+
+use colored::Colorize;
+
+use rustless_client::RustlessClient;
+
+use crate::server::FunctionAppRef;
+
+/// Runs a future until it completes or the user presses Ctrl-C, returning None if it was cancelled
+///
+/// The future is dropped when Ctrl-C is pressed, which aborts any request it has in flight.
+pub async fn until_cancelled<F: Future>(future: F) -> Option<F::Output> {
+    tokio::select! {
+        result = future => Some(result),
+        _ = tokio::signal::ctrl_c() => None,
+    }
+}
+
+/// Asks the server to stop the build for a function app after the upload was cancelled, so the host doesn't keep building
+pub async fn cancel_build(client: &RustlessClient, app: &FunctionAppRef) {
+    match client.cancel_build(app).await {
+        Ok(true) => println!("{}", "Cancelled the build on the server".yellow()),
+        Ok(false) => {},
+        Err(e) => println!("{}", format!("Error cancelling the build on the server: {}", e).red()),
+    }
+}
@@ -1,5 +1,8 @@
+use std::fs;
+use std::io::Write;
 use std::time::SystemTime;
-use std::{path::PathBuf, time::Duration};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use chrono::prelude::{DateTime, Local, Utc};
 use colored::Colorize;
@@ -9,21 +12,33 @@ use tokio::sync::mpsc::channel;
 use tokio::time::sleep;
 use uuid::Uuid;
 
-use rustless_shared::FunctionAppStatus;
+use futures::future::join_all;
 
+use rustless_client::RustlessClient;
+use rustless_shared::{AppExit, AppStop, BuildOptions, FunctionApp, FunctionAppStatus, FunctionAppStatusResult, MirrorConfig};
+
+use crate::cancel;
 use crate::code;
+use crate::diagnostics;
+use crate::error::CliError;
+use crate::output::{self, OutputArgs};
 use crate::server;
+use crate::server::FunctionAppRef;
 use crate::storage;
+use crate::top;
+
+/// The environment variable containing the approver key, used if it isn't passed to the approve command
+const APPROVER_KEY_ENV: &str = "RUSTLESS_APPROVER_KEY";
 
 /// Formats a time into a string
-fn format_date(date_time: SystemTime) -> String
+pub fn format_date(date_time: SystemTime) -> String
 {
-    let dt: DateTime<Utc> = date_time.clone().into();
+    let dt: DateTime<Utc> = date_time.into();
     format!("{}", dt.with_timezone(&Local).format("%d-%m-%Y %H:%M:%S"))
 }
 
 /// Creates a progress bar
-fn create_progress_bar() -> ProgressBar {
+pub fn create_progress_bar() -> ProgressBar {
     let pb = ProgressBar::new_spinner();
     pb.enable_steady_tick(Duration::from_millis(120));
     pb.set_style(
@@ -35,7 +50,7 @@ fn create_progress_bar() -> ProgressBar {
 }
 
 /// Test that the code compiles
-async fn test_compile_code(code_path: &String) {
+async fn test_compile_code(code_path: &String) -> Result<(), CliError> {
     // Create a message channel to send messages to the progress bar
     let (tx, mut rx) = channel(1);
 
@@ -51,14 +66,19 @@ async fn test_compile_code(code_path: &String) {
         pb.finish_and_clear();
     });
 
-    code::compile_code(code_path);
+    let compiled = code::try_compile_code(code_path);
 
     tx.send(true).await.unwrap();
 
     handle.await.unwrap();
+
+    compiled.map_err(CliError::from)
 }
 
-async fn get_new_id_for_function_app(conn: &Connection, name: &String) -> Uuid {
+async fn get_new_id_for_function_app(conn: &Connection, name: &String, namespace: &String) -> Result<Uuid, CliError> {
+    // Check we have a server set
+    let client = server::get_server_client(conn)?;
+
     // Create a message channel to send messages to the progress bar
     let (tx, mut rx) = channel(1);
 
@@ -74,38 +94,25 @@ async fn get_new_id_for_function_app(conn: &Connection, name: &String) -> Uuid {
         pb.finish_and_clear();
     });
 
-    // Check we have a server set
-    let server = storage::get_server(&conn);
-
-    // If not, report an error and exit
-    if server.is_err() {
-        let error_message =
-            format!("No server set. Use the 'set-server' command to set the server.")
-                .red()
-                .bold();
-        println!("{}", error_message);
-        std::process::exit(-1);
-    }
-
     // Construct the function app and get it's ID
-    let id = server::register_function_app(conn, name).await;
+    let id = client.create_app(name, namespace).await;
 
     // Send a message to stop the spinner
-    tx.send(id.to_string()).await.unwrap();
+    tx.send(true).await.unwrap();
 
     handle.await.unwrap();
 
-    id
+    id.map_err(|e| CliError::Message(format!("Error adding function app: {}", e)))
 }
 
 /// Test that the code compiles
-async fn get_base64_zip_file(zip_file: PathBuf) -> String {
+async fn zip_code(code_path: &String) -> Result<PathBuf, CliError> {
     // Create a message channel to send messages to the progress bar
     let (tx, mut rx) = channel(1);
 
     let handle = tokio::spawn(async move {
         let pb = create_progress_bar();
-        pb.set_message("Building packet to send to server...");
+        pb.set_message("Zipping function app...");
 
         while rx.try_recv().is_err() {
             pb.tick();
@@ -115,74 +122,131 @@ async fn get_base64_zip_file(zip_file: PathBuf) -> String {
         pb.finish_and_clear();
     });
 
-    let zip_file_base64 = code::zip_file_to_base64(&zip_file);
+    let zip_file = code::zip_function_app_code(code_path);
 
     tx.send(true).await.unwrap();
 
     handle.await.unwrap();
 
-    zip_file_base64
+    zip_file.map_err(CliError::from)
 }
 
-/// Test that the code compiles
-async fn zip_code(code_path: &String) -> PathBuf {
-    // Create a message channel to send messages to the progress bar
-    let (tx, mut rx) = channel(1);
-
-    let handle = tokio::spawn(async move {
-        let pb = create_progress_bar();
-        pb.set_message("Zipping function app...");
-
-        while rx.try_recv().is_err() {
-            pb.tick();
-            sleep(Duration::from_millis(120)).await;
-        }
-
-        pb.finish_and_clear();
-    });
-
-    let zip_file = code::zip_function_app_code(code_path).await;
+/// Formats a number as an ordinal, such as 1st, 2nd, 3rd
+fn format_ordinal(number: usize) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
 
-    tx.send(true).await.unwrap();
+    format!("{}{}", number, suffix)
+}
 
-    handle.await.unwrap();
+/// Formats the build queue position and estimated wait for the spinner, such as "3rd in queue, ~4 min"
+fn format_queue_position(position: usize, estimated_wait_secs: Option<u64>) -> String {
+    match estimated_wait_secs {
+        Some(wait) => format!("{} in queue, ~{} min", format_ordinal(position), wait.div_ceil(60).max(1)),
+        None => format!("{} in queue", format_ordinal(position)),
+    }
+}
 
-    zip_file
+/// Uploads the code to the server, returning the error from the server if the build fails
+///
+/// If the server requires deployments to be approved, this returns the number of the deployment waiting for approval
+async fn post_app_code(client: &RustlessClient, app: &FunctionAppRef, zip_file: &Path, options: &BuildOptions) -> Result<server::UploadResult, CliError> {
+    match client.upload_code(app, zip_file, options).await {
+        Ok(uploaded) => Ok(uploaded),
+        Err(e) => Err(e.into()),
+    }
 }
 
-/// Sends the code to the server as a base64 encoded zip file
-async fn send_zip_file_to_server(conn: &Connection, id: &Uuid, zip_file_base_64: &String) {
+/// Sends the zip file with the code to the server, showing the compiler errors if the build fails
+async fn send_zip_file_to_server(conn: &Connection, app: FunctionAppRef, zip_file: &Path, options: &BuildOptions) -> Result<(), CliError> {
     // Create a message channel to send messages to the progress bar
     let (tx, mut rx) = channel(1);
 
+    // Get a client for the server, and one for the spinner so it can check the build queue while the code is sent
+    let client = server::get_server_client(conn)?;
+    let queue_client = client.clone();
+    let queue_app = match &app {
+        FunctionAppRef::Id(id) => FunctionAppRef::Id(*id),
+        FunctionAppRef::Name(name) => FunctionAppRef::Name(name.to_string()),
+    };
+
     let handle = tokio::spawn(async move {
         let pb = create_progress_bar();
         pb.set_message("Sending function app code to server...");
 
+        let mut ticks = 0;
         while rx.try_recv().is_err() {
             pb.tick();
             sleep(Duration::from_millis(120)).await;
+
+            // Every couple of seconds, check if the build is waiting in the queue on the server
+            ticks += 1;
+            if ticks % 16 == 0 {
+                match queue_client.build_queue_position(&queue_app).await {
+                    Ok(Some((position, estimated_wait_secs))) => pb.set_message(format!(
+                        "Waiting to build - {}...",
+                        format_queue_position(position, estimated_wait_secs)
+                    )),
+                    Ok(None) => pb.set_message("Building function app on server..."),
+                    Err(_) => {}
+                }
+            }
         }
 
         pb.finish_and_clear();
     });
 
-    // Send the app code
-    server::post_app_code(conn, id, zip_file_base_64).await;
+    // Send the app code, stopping if the user presses Ctrl-C
+    let sent = cancel::until_cancelled(post_app_code(&client, &app, zip_file, options)).await;
 
     tx.send(true).await.unwrap();
 
     handle.await.unwrap();
+
+    // The spinner has been cleared, so tell the user and stop the build on the server
+    let uploaded = match sent {
+        Some(Ok(uploaded)) => uploaded,
+        Some(Err(CliError::Message(e))) => {
+            // If the build failed, show the compiler errors rather than the raw build output
+            if diagnostics::print_build_errors(&e) {
+                return Err(CliError::Failed);
+            }
+            return Err(CliError::Message(e));
+        },
+        Some(Err(e)) => return Err(e),
+        None => {
+            println!("{}", "Cancelled".yellow().bold());
+
+            cancel::cancel_build(&client, &app).await;
+
+            return Err(CliError::Failed);
+        }
+    };
+
+    if let Some(number) = uploaded.unchanged_deployment {
+        println!("{}", format!("The code hasn't changed since deployment {}, so it wasn't built again. Use --force to build it anyway", number).blue());
+    }
+
+    if let Some(number) = uploaded.pending_deployment {
+        println!("{}", format!("Deployment {} is waiting for approval before the function app can be started. Approve it with the 'approve' command", number).yellow());
+    }
+
+    Ok(())
 }
 
-/// Gets the ID for the function app
-async fn get_function_app_id(conn: &Connection, name: &String) -> Uuid {
+/// Start the function app, starting the apps it depends on first if with_deps is set
+pub async fn start_function_app_on_server(conn: &Connection, name: &String, with_deps: bool) -> Result<(), CliError> {
     // Create a message channel to send messages to the progress bar
     let (tx, mut rx) = channel(1);
 
     let handle = tokio::spawn(async move {
         let pb = create_progress_bar();
-        pb.set_message("Sending function app code to server...");
+        pb.set_message("Starting the function app...");
 
         while rx.try_recv().is_err() {
             pb.tick();
@@ -192,119 +256,332 @@ async fn get_function_app_id(conn: &Connection, name: &String) -> Uuid {
         pb.finish_and_clear();
     });
 
-    // Get the ID for the function app
-    let id = server::get_id_for_function_app(conn, name).await;
+    // start the function app, using the cached ID if we have one
+    let client = &server::get_server_client(conn)?;
+    let started = with_app_ref(conn, name, |app| async move { client.start(&app, with_deps).await }).await;
 
     tx.send(true).await.unwrap();
 
     handle.await.unwrap();
 
-    id
+    match started {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => Err(e.into()),
+    }
 }
 
-/// Start the function app
-pub async fn start_function_app_on_server(conn: &Connection, name: &String) {
-    // Create a message channel to send messages to the progress bar
-    let (tx, mut rx) = channel(1);
+/// Sets the server
+/// 
+/// This starts by testing the connection to the server, making sure it is valid. If so
+/// the server is stored in the database. There can be only one server, so adding one deletes any
+/// previous entry.
+pub async fn set_server(conn: Connection, new_server: &storage::Server) -> Result<(), CliError> {
+    // Write to the console that we are testing the server
+    let message = format!("Testing server: {}...", server::format_server(new_server)).blue();
+    print!("{}", message);
+
+    // TODO - add a spinner here for long running tests
+
+    // Test the connection to the server
+    let result = match server::get_client(new_server) {
+        Ok(client) => client.test().await,
+        Err(e) => Err(e),
+    };
 
-    let handle = tokio::spawn(async move {
-        let pb = create_progress_bar();
-        pb.set_message("Starting the function app...");
+    // Check if the test worked. If it did, write the server details to the database
+    match result {
+        Ok(_) => {
+            // Write a message to the console to show it worked
+            println!("✅");
+
+            // Add the server to the database
+            match storage::add_server(&conn, &new_server.hostname, new_server.port, &new_server.base_path) {
+                Ok(_) => {
+                    let ok_message = "Server set!".green().bold();
+                    println!("{}", ok_message);
+
+                    Ok(())
+                },
+                Err(e) => Err(CliError::Message(format!("Error adding server to storage: {}", e)))
+            }
+        },
+        Err(_) => {
+            // If the server is not found, report back to the user
+            println!("❌");
+            let error_message = format!("Server {} not found.\n", server::format_server(new_server)).red().bold();
+            println!("{}",error_message);
+
+            // If there is a server already set, report this so the user knows which server will be used
+            // If no server is set, also report this back to the user
+            let current_message = match storage::get_server(&conn) {
+                Ok(server) => format!("Current server: {}\n", server::format_server(&server)).bold().blue().to_string(),
+                Err(_) => "No server set".bold().blue().to_string()
+            };
+            println!("{}", current_message);
+
+            // Return an error, the reason has already been shown
+            Err(CliError::Failed)
+        }
+    }
+}
 
-        while rx.try_recv().is_err() {
-            pb.tick();
-            sleep(Duration::from_millis(120)).await;
+/// Logs in to the server with an API key, storing the key with the server once the server has accepted it
+///
+/// The key is read from the terminal if it isn't passed in, so it isn't kept in the shell history
+pub async fn login(conn: &Connection, key: &Option<String>) -> Result<(), CliError> {
+    let server = match storage::get_server(conn) {
+        Ok(server) => server,
+        Err(_) => return Err(CliError::NoServer),
+    };
+
+    let key = match key {
+        Some(key) => key.trim().to_string(),
+        None => {
+            print!("API key for {}: ", server::format_server(&server));
+            let _ = std::io::stdout().flush();
+
+            let mut key = String::new();
+            if let Err(e) = std::io::stdin().read_line(&mut key) {
+                return Err(CliError::Message(format!("Error reading API key: {}", e)));
+            }
+            key.trim().to_string()
         }
+    };
 
-        pb.finish_and_clear();
-    });
+    if key.is_empty() {
+        return Err(CliError::Message("An API key is required. Create one on the host with rustless-hostctl keys create".to_string()));
+    }
 
-    // Get the function app ID
-    let id = server::get_id_for_function_app(conn, name).await;
+    // The stored key isn't sent, so a key that was revoked doesn't stop a new one being checked
+    let checked = match RustlessClient::new(&server.hostname, server.port) {
+        Ok(client) => client.with_base_path(&server.base_path).check_api_key(&key).await,
+        Err(e) => Err(e),
+    };
 
-    // start the function app
-    server::start_function_app(conn, &id).await;
+    match checked {
+        Ok(true) => {},
+        Ok(false) => return Err(CliError::Message(format!("{} rejected the API key. It may have been revoked", server::format_server(&server)))),
+        Err(e) => return Err(CliError::Message(format!("Error checking API key: {}", e))),
+    }
 
-    tx.send(true).await.unwrap();
+    match storage::set_api_key(conn, Some(&key)) {
+        Ok(_) => println!("{}", format!("✅ Logged in to {}", server::format_server(&server)).green()),
+        Err(e) => return Err(CliError::Message(format!("Error storing API key: {}", e))),
+    }
 
-    handle.await.unwrap();
+    Ok(())
 }
 
-async fn add_function_app_impl(conn: &Connection, name: &String, code_path: &String, id: Option<Uuid>) {
-    // Compile the code to ensure it is valid before we start
-    test_compile_code(code_path).await;
-    println!("{}", format!("✅ Function app code compiled successfully").green());
+/// Logs out of the server, forgetting the API key stored with it
+pub fn logout(conn: &Connection) -> Result<(), CliError> {
+    match storage::set_api_key(conn, None) {
+        Ok(true) => println!("{}", "✅ Logged out".green()),
+        Ok(false) => return Err(CliError::NoServer),
+        Err(e) => return Err(CliError::Message(format!("Error removing API key: {}", e))),
+    }
 
-    // Get the ID for the function app
-    let id = match id {
-        Some(id) => id,
-        None => get_new_id_for_function_app(conn, name).await,
-    };
-    println!("{}", format!("✅ App registered with ID {}", id).green());
+    Ok(())
+}
 
-    // Upload the code for the app
-    let zip_file = zip_code(code_path).await;
-    println!("{}", format!("✅ Function app zipped").green());
+pub use rustless_cli::with_app_ref;
 
-    // Convert the Zip file to a base64 string
-    let zip_file_base64 = get_base64_zip_file(zip_file).await;
-    println!("{}", format!("✅ Function app packet built").green());
+async fn add_function_app_impl(conn: &Connection, code_path: &String, app: FunctionAppRef, options: &BuildOptions) -> Result<(), CliError> {
+    // Upload the code for the app
+    let zip_file = zip_code(code_path).await?;
+    match fs::metadata(&zip_file) {
+        Ok(metadata) => println!("{}", format!("✅ Function app zipped ({})", top::format_bytes(metadata.len())).green()),
+        Err(_) => println!("{}", "✅ Function app zipped".green()),
+    }
 
     // Send the request to the server
-    send_zip_file_to_server(&conn, &id, &zip_file_base64).await;
-    println!("{}", format!("✅ Function app code sent").green());
+    send_zip_file_to_server(conn, app, &zip_file, options).await?;
+    println!("{}", "✅ Function app code sent".green());
+
+    Ok(())
 }
 
 /// Adds a function app to the host
-pub async fn add_function_app(conn: &Connection, name: &String, code_path: &String) {
+pub async fn add_function_app(conn: &Connection, name: &String, code_path: &String, namespace: &String, options: &BuildOptions) -> Result<(), CliError> {
     println!("{}", format!("Adding new function app '{}'", name).blue());
 
-    add_function_app_impl(conn, name, code_path, None).await;
+    // Compile the code to ensure it is valid before we start
+    test_compile_code(code_path).await?;
+    println!("{}", "✅ Function app code compiled successfully".green());
+
+    // Register the function app to get a new ID
+    let id = get_new_id_for_function_app(conn, name, namespace).await?;
+    println!("{}", format!("✅ App registered with ID {}", id).green());
+
+    add_function_app_impl(conn, code_path, FunctionAppRef::Id(id), options).await?;
+
+    // Cache the ID so later commands can skip the lookup by name
+    let _ = storage::set_function_app_id(conn, name, &id);
 
     println!("{}", format!("✅ Function app '{}' registered!", name).green());
+    Ok(())
 }
 
 /// Adds a function app to the host
-pub async fn update_function_app(conn: &Connection, name: &String, code_path: &String) {
+pub async fn update_function_app(conn: &Connection, name: &String, code_path: &String, options: &BuildOptions) -> Result<(), CliError> {
     println!("{}", format!("Adding new function app '{}'", name).blue());
 
-    // get the ID for the function app
-    let id = get_function_app_id(conn, name).await;
-    println!("{}", format!("✅ Retrieved app id").green());
+    // Compile the code to ensure it is valid before we start
+    test_compile_code(code_path).await?;
+    println!("{}", "✅ Function app code compiled successfully".green());
 
-    // upload the code for the app
-    add_function_app_impl(conn, name, code_path, Some(id)).await;
+    // upload the code for the app, addressing it by name
+    add_function_app_impl(conn, code_path, FunctionAppRef::Name(name.to_string()), options).await?;
 
     println!("{}", format!("✅ Function app '{}' updated!", name).green());
+    Ok(())
 }
 
 /// Lists the function apps on the server
-pub async fn list_function_apps(conn: &Connection) {
+pub async fn list_function_apps(conn: &Connection, output: &OutputArgs) -> Result<(), CliError> {
     // Get the function apps
-    let function_apps = server::list_function_apps(&conn).await;
+    let client = server::get_server_client(conn)?;
+    let function_apps = client.list().await?;
+
+    if !output.is_table() {
+        print_function_app_rows(output, &function_apps, None);
+        return Ok(());
+    }
+
+    if function_apps.is_empty() {
+        println!("{}", "No function apps registered".blue());
+        return Ok(());
+    };
+
+    print_function_app_table(&function_apps, None);
+    Ok(())
+}
+
+/// Gets all the configured servers, returning an error if there aren't any
+fn get_all_servers(conn: &Connection) -> Result<Vec<storage::Profile>, CliError> {
+    let profiles = match storage::get_all_servers(conn) {
+        Ok(profiles) => profiles,
+        Err(e) => return Err(CliError::Message(format!("Error getting server profiles: {}", e))),
+    };
+
+    if profiles.is_empty() {
+        return Err(CliError::Message("No servers set. Use the 'set-server' or 'profile add' commands to add a server.".to_string()));
+    }
+
+    Ok(profiles)
+}
+
+/// Lists the function apps on every configured server, querying all the servers at the same time
+pub async fn list_function_apps_on_all_servers(conn: &Connection, output: &OutputArgs) -> Result<(), CliError> {
+    let profiles = get_all_servers(conn)?;
+
+    let results = join_all(profiles.iter()
+        .map(|profile| async move { server::get_client(&profile.server)?.list().await })).await;
+
+    // Merge the results, keeping track of which server each app came from
+    let mut function_apps = Vec::new();
+    let mut server_names = Vec::new();
+    for (profile, result) in profiles.iter().zip(results) {
+        match result {
+            Ok(apps) => {
+                for app in apps {
+                    server_names.push(profile.name.clone());
+                    function_apps.push(app);
+                }
+            },
+            Err(e) => println!("{}", format!("Error listing function apps on {}: {}", profile.name, e).red()),
+        }
+    }
+
+    if !output.is_table() {
+        print_function_app_rows(output, &function_apps, Some(&server_names));
+        return Ok(());
+    }
 
     if function_apps.is_empty() {
-        println!("{}", format!("No function apps registered").blue());
-        return;
+        println!("{}", "No function apps registered".blue());
+        return Ok(());
     };
 
+    print_function_app_table(&function_apps, Some(&server_names));
+    Ok(())
+}
+
+/// Gets the name of a function app status to show
+pub fn get_status_name(status: &FunctionAppStatus) -> &'static str {
+    match status {
+        FunctionAppStatus::NotRegistered => "Not registered",
+        FunctionAppStatus::Registered => "Registered",
+        FunctionAppStatus::Running => "Running",
+        FunctionAppStatus::Ready => "Ready",
+        FunctionAppStatus::Error => "Error",
+        FunctionAppStatus::Building => "Building",
+        FunctionAppStatus::Cancelled => "Cancelled",
+    }
+}
+
+/// Writes out function apps as CSV or markdown rows. If server names are given, a server column is added showing
+/// which server each app is on
+fn print_function_app_rows(output: &OutputArgs, function_apps: &[FunctionApp], server_names: Option<&[String]>) {
+    let mut headers = vec!["Name", "ID", "Namespace", "Status", "Created date"];
+    if server_names.is_some() {
+        headers.insert(0, "Server");
+    }
+
+    let rows: Vec<Vec<String>> = function_apps.iter().enumerate().map(|(index, function_app)| {
+        let created_at = SystemTime::UNIX_EPOCH + Duration::from_secs(function_app.created_at);
+        let mut row = vec![
+            function_app.name.to_string(),
+            function_app.id.to_string(),
+            function_app.namespace.to_string(),
+            get_status_name(&function_app.status).to_string(),
+            format_date(created_at),
+        ];
+        if let Some(server_names) = server_names {
+            row.insert(0, server_names[index].to_string());
+        }
+        row
+    }).collect();
+
+    output::print_rows(output, &headers, &rows);
+}
+
+/// Prints a table of function apps. If server names are given, a server column is added showing
+/// which server each app is on
+fn print_function_app_table(function_apps: &[FunctionApp], server_names: Option<&[String]>) {
     // Build the table
     // First we need the size of the larges name
-    let mut max_name_length = 0;
-    for function_app in &function_apps {
+    let mut max_name_length = 4;
+    for function_app in function_apps {
         if function_app.name.len() > max_name_length {
             max_name_length = function_app.name.len();
         }
     }
 
+    // The server column is only shown when listing multiple servers, so build the start of each line
+    let max_server_length = server_names
+        .map(|server_names| server_names.iter().map(|name| name.len()).max().unwrap_or(0).max(6))
+        .unwrap_or(0);
+    let (top, header, divider, bottom) = match server_names {
+        Some(_) => (
+            format!("┌-{}-┬", "-".repeat(max_server_length)),
+            format!("| {}{} ", "Server".bold(), " ".repeat(max_server_length - 6)),
+            format!("|-{}-┼", "-".repeat(max_server_length)),
+            format!("└-{}-┴", "-".repeat(max_server_length)),
+        ),
+        None => ("┌".to_string(), String::new(), "|".to_string(), "└".to_string()),
+    };
+
     // Now we can build the table
-    // The table is ID } Name | Status
+    // The table is Server | Name | ID | Status | Created date
     println!(
-        "┌-{}-┬--------------------------------------┬----------------┬---------------------┐",
+        "{}-{}-┬--------------------------------------┬----------------┬---------------------┐",
+        top,
         "-".repeat(max_name_length)
     );
     println!(
-        "| {}{} | {}                                   | {}         | {}        |",
+        "{}| {}{} | {}                                   | {}         | {}        |",
+        header,
         "Name".bold(),
         " ".repeat(max_name_length - 4),
         "ID".bold(),
@@ -312,23 +589,33 @@ pub async fn list_function_apps(conn: &Connection) {
         "Created date".bold()
     );
     println!(
-        "|-{}-┼--------------------------------------┼----------------┼---------------------|",
+        "{}-{}-┼--------------------------------------┼----------------┼---------------------|",
+        divider,
         "-".repeat(max_name_length)
     );
-    for function_app in &function_apps {
+    for (index, function_app) in function_apps.iter().enumerate() {
+        let status_name = get_status_name(&function_app.status);
         let status_string = match function_app.status {
-            FunctionAppStatus::NotRegistered => "Not registered".red(),
-            FunctionAppStatus::Registered => "Registered".blue(),
-            FunctionAppStatus::Running => "Running".green(),
-            FunctionAppStatus::Ready => "Ready".blue(),
-            FunctionAppStatus::Error => "Error".red(),
-            FunctionAppStatus::Building => "Building".blue(),
+            FunctionAppStatus::NotRegistered => status_name.red(),
+            FunctionAppStatus::Registered => status_name.blue(),
+            FunctionAppStatus::Running => status_name.green(),
+            FunctionAppStatus::Ready => status_name.blue(),
+            FunctionAppStatus::Error => status_name.red(),
+            FunctionAppStatus::Building => status_name.blue(),
+            FunctionAppStatus::Cancelled => status_name.yellow(),
         };
-        let created_at = SystemTime::from(SystemTime::UNIX_EPOCH + Duration::from_secs(function_app.created_at));
+        let created_at = SystemTime::UNIX_EPOCH + Duration::from_secs(function_app.created_at);
         let created_at = format_date(created_at);
 
+        // Add the server name if we are showing the server column
+        let server_cell = match server_names {
+            Some(server_names) => format!("| {}{} ", server_names[index], " ".repeat(max_server_length - server_names[index].len())),
+            None => String::new(),
+        };
+
         println!(
-            "| {}{} | {} | {}{} | {} |",
+            "{}| {}{} | {} | {}{} | {} |",
+            server_cell,
             function_app.name.blue().bold(),
             " ".repeat(max_name_length - function_app.name.len()),
             function_app.id,
@@ -338,34 +625,543 @@ pub async fn list_function_apps(conn: &Connection) {
         );
     }
     println!(
-        "└-{}-┴--------------------------------------┴----------------┴---------------------┘",
+        "{}-{}-┴--------------------------------------┴----------------┴---------------------┘",
+        bottom,
         "-".repeat(max_name_length)
     );
 }
 
 /// Calls the server to start a function app
-pub async fn start_function_app(conn: &Connection, name: &String) {
+pub async fn start_function_app(conn: &Connection, name: &String, with_deps: bool) -> Result<(), CliError> {
     println!("{}", format!("Adding new function app '{}'", name).blue());
 
     // Start the function app
-    start_function_app_on_server(conn, name).await;
+    start_function_app_on_server(conn, name, with_deps).await?;
 
     println!("{}", format!("Function app '{}' running!", name).blue());
+    Ok(())
 }
 
 /// Calls the server to get the status of a function app
-pub async fn get_function_app_status(conn: &Connection, name: &String) {
-    let id = server::get_id_for_function_app(conn, name).await;
-    let status = server::get_status_for_function_app(conn, &id).await;
+pub async fn get_function_app_status(conn: &Connection, name: &String) -> Result<(), CliError> {
+    // Get the status, using the cached ID if we have one
+    let result = match rustless_cli::get_function_app_status(conn, name).await {
+        Ok(Some(result)) => result,
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(e.into()),
+    };
 
-    let status_string = match status {
+    print_status(name, &result);
+    Ok(())
+}
+
+/// Prints the status of a function app, with how it last stopped or crashed and any deployment waiting for approval
+pub fn print_status(name: &String, result: &FunctionAppStatusResult) {
+    let status_string = match result.status {
         FunctionAppStatus::NotRegistered => "Not registered".red(),
         FunctionAppStatus::Registered => "Registered".blue(),
         FunctionAppStatus::Running => "Running".green(),
         FunctionAppStatus::Ready => "Ready".blue(),
         FunctionAppStatus::Error => "Error".red(),
         FunctionAppStatus::Building => "Building".blue(),
+        FunctionAppStatus::Cancelled => "Cancelled".yellow(),
     };
 
     println!("Function app {} is {}", name, status_string);
+
+    if let Some(number) = result.pending_deployment {
+        println!("{}", format!("Deployment {} is waiting for approval", number).yellow());
+    }
+
+    if let Some(stop) = &result.last_stop {
+        print_stop("Last stopped", stop);
+    }
+
+    if let Some(exit) = &result.last_exit {
+        print_exit(exit);
+    }
+
+    // Only starts that had to try another port are worth mentioning
+    if let Some(start) = result.last_start.as_ref().filter(|start| start.port_attempts > 1) {
+        let started_at = format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(start.started_at));
+        println!("{}", format!("Started at {} after trying {} ports, as the ports picked first were taken", started_at, start.port_attempts).yellow());
+    }
+}
+
+/// Prints why the container for a function app last exited without being stopped
+fn print_exit(exit: &AppExit) {
+    let exited_at = format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(exit.exited_at));
+
+    match exit.last_memory_bytes {
+        Some(memory) => println!("{}", format!("Last crashed at {}: {} (using {} when last sampled)", exited_at, exit.reason, top::format_bytes(memory)).red()),
+        None => println!("{}", format!("Last crashed at {}: {}", exited_at, exit.reason).red()),
+    }
+}
+
+/// Prints how a function app stopped
+fn print_stop(prefix: &str, stop: &AppStop) {
+    let stopped_at = format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(stop.stopped_at));
+
+    if stop.clean {
+        println!("{}", format!("{} cleanly at {} in {}s (exit code {})", prefix, stopped_at, stop.duration_secs, stop.exit_code).blue());
+    } else {
+        println!("{}", format!("{} at {} by being killed, as it didn't stop within the {}s grace period", prefix, stopped_at, stop.grace_period_secs).yellow());
+    }
+}
+
+/// Stops a running function app, giving it the grace period on the server to finish the requests in flight
+pub async fn stop_function_app(conn: &Connection, name: &String) -> Result<(), CliError> {
+    // The server waits for the app to stop, which can take the whole grace period
+    let pb = create_progress_bar();
+    pb.set_message(format!("Stopping function app '{}'...", name));
+
+    let client = &server::get_server_client(conn)?;
+    let result = with_app_ref(conn, name, |app| async move { client.stop(&app).await }).await;
+
+    pb.finish_and_clear();
+
+    match result {
+        Ok(Some(stop)) => print_stop(&format!("Function app '{}' stopped", name), &stop),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error stopping function app: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Restarts a running function app on a new container without dropping requests, showing how long the new
+/// container took to become healthy and how the old one stopped
+pub async fn restart_function_app(conn: &Connection, name: &String) -> Result<(), CliError> {
+    // The server waits for the new container to pass its health check and for the old one to stop
+    let pb = create_progress_bar();
+    pb.set_message(format!("Restarting function app '{}'...", name));
+
+    let client = &server::get_server_client(conn)?;
+    let result = with_app_ref(conn, name, |app| async move { client.restart(&app).await }).await;
+
+    pb.finish_and_clear();
+
+    let restart = match result {
+        Ok(Some(restart)) => restart,
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error restarting function app: {}", e))),
+    };
+
+    println!("{}", format!("✅ Function app '{}' restarted on port {}, healthy after {}ms", name, restart.port, restart.health_check_ms).green());
+
+    if restart.port_attempts > 1 {
+        println!("{}", format!("Started after trying {} ports, as the ports picked first were taken", restart.port_attempts).yellow());
+    }
+
+    print_stop("The old container stopped", &restart.old_container);
+    Ok(())
+}
+
+/// Asks the user to confirm deleting a function app, returning true if they answer yes
+fn confirm_delete(name: &String) -> bool {
+    print!("{}", format!("Delete function app '{}' and everything the server has for it? This can't be undone [y/N] ", name).yellow());
+    let _ = std::io::stdout().flush();
+
+    // No input, such as when stdin is closed, is taken as no
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(0) => {
+            println!();
+            false
+        },
+        Ok(_) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+/// Deletes a function app from the server, stopping it first if it is running. The user is asked to confirm first
+/// unless yes is set
+pub async fn delete_function_app(conn: &Connection, name: &String, yes: bool) -> Result<(), CliError> {
+    if !yes && !confirm_delete(name) {
+        println!("{}", format!("Function app '{}' was not deleted", name).blue());
+        return Ok(());
+    }
+
+    // The server stops the app before deleting it, which can take the whole grace period
+    let pb = create_progress_bar();
+    pb.set_message(format!("Deleting function app '{}'...", name));
+
+    let client = &server::get_server_client(conn)?;
+    let result = with_app_ref(conn, name, |app| async move { client.delete(&app).await }).await;
+
+    pb.finish_and_clear();
+
+    match result {
+        Ok(Some(_)) => {
+            // The ID belongs to the deleted app, so a new app with the same name shouldn't use it
+            let _ = storage::remove_function_app_id(conn, name);
+            println!("{}", format!("✅ Function app '{}' deleted", name).green());
+        },
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error deleting function app: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Gets the status of a function app on every configured server, querying all the servers at the same time
+pub async fn get_function_app_status_on_all_servers(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let profiles = get_all_servers(conn)?;
+    let app = &FunctionAppRef::Name(name.to_string());
+
+    let results = join_all(profiles.iter()
+        .map(|profile| async move { server::get_client(&profile.server)?.status(app).await })).await;
+
+    let mut found = false;
+    for (profile, result) in profiles.iter().zip(results) {
+        match result {
+            Ok(Some(result)) => {
+                found = true;
+                let status_string = match result.status {
+                    FunctionAppStatus::NotRegistered => "Not registered".red(),
+                    FunctionAppStatus::Registered => "Registered".blue(),
+                    FunctionAppStatus::Running => "Running".green(),
+                    FunctionAppStatus::Ready => "Ready".blue(),
+                    FunctionAppStatus::Error => "Error".red(),
+                    FunctionAppStatus::Building => "Building".blue(),
+                    FunctionAppStatus::Cancelled => "Cancelled".yellow(),
+                };
+
+                println!("{}: Function app {} is {}", profile.name.bold(), name, status_string);
+            },
+            Ok(None) => {},
+            Err(e) => println!("{}", format!("Error getting status from {}: {}", profile.name, e).red()),
+        }
+    }
+
+    if !found {
+        return Err(CliError::Message(format!("No function app with the name '{}' exists on any server", name)));
+    }
+
+    Ok(())
+}
+
+/// Adds a server profile, testing the server first
+pub async fn add_profile(conn: &Connection, name: &String, profile_server: &storage::Server) -> Result<(), CliError> {
+    let tested = match server::get_client(profile_server) {
+        Ok(client) => client.test().await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = tested {
+        return Err(CliError::Message(format!("Server {} not found: {}", server::format_server(profile_server), e)));
+    }
+
+    match storage::add_profile(conn, name, &profile_server.hostname, profile_server.port, &profile_server.base_path) {
+        Ok(_) => println!("{}", format!("✅ Profile '{}' added for {}", name, server::format_server(profile_server)).green()),
+        Err(e) => return Err(CliError::Message(format!("Error adding profile: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Removes a server profile
+pub fn remove_profile(conn: &Connection, name: &String) -> Result<(), CliError> {
+    match storage::remove_profile(conn, name) {
+        Ok(true) => println!("{}", format!("✅ Profile '{}' removed", name).green()),
+        Ok(false) => return Err(CliError::Message(format!("No profile named '{}' exists", name))),
+        Err(e) => return Err(CliError::Message(format!("Error removing profile: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Lists the server profiles
+pub fn list_profiles(conn: &Connection) -> Result<(), CliError> {
+    let profiles = match storage::get_profiles(conn) {
+        Ok(profiles) => profiles,
+        Err(e) => return Err(CliError::Message(format!("Error getting profiles: {}", e))),
+    };
+
+    if profiles.is_empty() {
+        println!("{}", "No profiles added".blue());
+        return Ok(());
+    }
+
+    for profile in profiles {
+        println!("{}: {}", profile.name.bold(), server::format_server(&profile.server));
+    }
+
+    Ok(())
+}
+
+/// Calls the server to turn maintenance mode on or off for a function app
+pub async fn set_maintenance(conn: &Connection, name: &String, enabled: bool, message: &Option<String>) -> Result<(), CliError> {
+    // Use the cached ID if we have one
+    let client = &server::get_server_client(conn)?;
+    let found = with_app_ref(conn, name, |app| async move { client.set_maintenance(&app, enabled, message).await }).await;
+
+    match found {
+        Ok(true) => {},
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(e.into()),
+    }
+
+    if enabled {
+        println!("{}", format!("✅ Function app '{}' is in maintenance mode", name).green());
+    } else {
+        println!("{}", format!("✅ Function app '{}' is out of maintenance mode", name).green());
+    }
+
+    Ok(())
+}
+
+/// Turns request mirroring on or off for a function app. Mirroring is turned on if there is a config
+pub async fn set_mirror(conn: &Connection, name: &String, config: &Option<MirrorConfig>) -> Result<(), CliError> {
+    // Use the cached ID if we have one
+    let client = &server::get_server_client(conn)?;
+    let found = with_app_ref(conn, name, |app| async move { client.set_mirror(&app, config).await }).await;
+
+    match found {
+        Ok(true) => {},
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(e.into()),
+    }
+
+    match config {
+        Some(config) => println!(
+            "{}",
+            format!("✅ Mirroring {}% of requests to '{}' to {}", config.sample_rate * 100.0, name, config.sink).green()
+        ),
+        None => println!("{}", format!("✅ Stopped mirroring requests to '{}'", name).green()),
+    }
+
+    Ok(())
+}
+
+/// Turns recording recent requests on or off for a function app
+pub async fn set_recording(conn: &Connection, name: &String, enabled: bool, capacity: usize) -> Result<(), CliError> {
+    // Use the cached ID if we have one
+    let client = &server::get_server_client(conn)?;
+    let found = with_app_ref(conn, name, |app| async move { client.set_recording(&app, enabled, capacity).await }).await;
+
+    match found {
+        Ok(true) => {},
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(e.into()),
+    }
+
+    if enabled {
+        println!("{}", format!("✅ Recording the last {} requests to '{}'", capacity, name).green());
+    } else {
+        println!("{}", format!("✅ Stopped recording requests to '{}'", name).green());
+    }
+
+    Ok(())
+}
+
+/// Calls the server to get the default function app
+pub async fn show_default_app(conn: &Connection) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    match client.default_app().await? {
+        Some(name) => println!("Default function app: {}", name.green()),
+        None => println!("{}", "No default function app set".blue()),
+    }
+
+    Ok(())
+}
+
+/// Calls the server to set or clear the default function app
+pub async fn set_default_app(conn: &Connection, name: Option<String>) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    if !client.set_default_app(name.clone()).await? {
+        return Err(CliError::AppNotFound(name.unwrap_or_default()));
+    }
+
+    match name {
+        Some(name) => println!("{}", format!("✅ Function app '{}' is now the default app", name).green()),
+        None => println!("{}", "✅ Default function app cleared".green()),
+    }
+
+    Ok(())
+}
+
+/// Approves a deployment for a function app so it can be started
+///
+/// The approver key is read from the RUSTLESS_APPROVER_KEY environment variable if it isn't passed in
+pub async fn approve_deployment(conn: &Connection, name: &String, number: u32, key: &Option<String>) -> Result<(), CliError> {
+    let key = &match key.clone().or_else(|| std::env::var(APPROVER_KEY_ENV).ok()) {
+        Some(key) => key,
+        None => return Err(CliError::Message(format!("An approver key is required. Pass it with --key or set {}", APPROVER_KEY_ENV))),
+    };
+
+    let client = &server::get_server_client(conn)?;
+    let result = with_app_ref(conn, name, |app| async move { client.approve_deployment(&app, number, key).await }).await;
+
+    match result {
+        Ok(true) => println!("{}", format!("✅ Deployment {} of '{}' approved. The function app can now be started", number, name).green()),
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error approving deployment: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Lists the routes handled by a running function app
+pub async fn list_routes(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = with_app_ref(conn, name, |app| async move { client.routes(&app).await }).await;
+
+    let manifest = match result {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting routes: {}", e))),
+    };
+
+    println!("{}", format!("Routes for '{}' ({} {}):", name, manifest.app, manifest.version).blue());
+
+    if manifest.routes.is_empty() {
+        println!("  No routes");
+        return Ok(());
+    }
+
+    for route in manifest.routes.iter() {
+        println!("  {:7} /api/{}{}", route.method.bold(), name, route.path);
+    }
+
+    Ok(())
+}
+
+/// Gets the bill of materials for a deployment of a function app, writing it to a file or printing it
+pub async fn get_deployment_sbom(conn: &Connection, name: &String, deployment: &str, output_path: &Option<String>) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = with_app_ref(conn, name, |app| async move { client.deployment_sbom(&app, deployment).await }).await;
+
+    let sbom = match result {
+        Ok(Some(sbom)) => sbom,
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting bill of materials: {}", e))),
+    };
+
+    // Print the bill of materials on its own so it can be piped to other tools
+    let output_path = match output_path {
+        Some(output_path) => output_path,
+        None => {
+            println!("{}", sbom);
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = fs::write(output_path, sbom) {
+        return Err(CliError::Message(format!("Error writing bill of materials: {}", e)));
+    }
+
+    println!("{}", format!("✅ Bill of materials for '{}' written to {}", name, output_path).green());
+    Ok(())
+}
+
+/// Checks the image for a function app was signed by the server and hasn't changed since it was built
+pub async fn verify_image(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = with_app_ref(conn, name, |app| async move { client.verify_image(&app).await }).await;
+
+    let verification = match result {
+        Ok(Some(verification)) => verification,
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error checking image signature: {}", e))),
+    };
+
+    if let Some(image_digest) = &verification.image_digest {
+        println!("Image: {}", image_digest);
+    }
+
+    if let (Some(deployment), Some(signed_at)) = (verification.deployment, verification.signed_at) {
+        println!("Signed: deployment {} at {}", deployment, format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(signed_at)));
+    }
+
+    println!("Enforcement on the server: {}", verification.enforcement);
+
+    match verification.message {
+        None => println!("{}", format!("✅ The image for '{}' is signed by the server", name).green()),
+        Some(message) => return Err(CliError::Message(format!("The image for '{}' is not verified: {}", name, message))),
+    }
+
+    Ok(())
+}
+
+/// Shows the limits the server puts on request headers and slow clients, and how many requests it has rejected
+pub async fn show_request_limits(conn: &Connection) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    let report = match client.request_limits().await {
+        Ok(report) => report,
+        Err(e) => return Err(CliError::Message(format!("Error getting request limits: {}", e))),
+    };
+
+    let limits = &report.limits;
+    println!("{}", "Request limits:".blue());
+    println!("  Headers:            at most {} headers, {} bytes", limits.max_headers, limits.max_header_bytes);
+    println!("  Request line:       at most {} bytes", limits.max_request_line_bytes);
+    println!("  Header timeout:     {}s", limits.header_timeout_secs);
+    println!("  Body idle timeout:  {}s", limits.body_idle_timeout_secs);
+    println!("  Keep-alive:         {}s", limits.keep_alive_secs);
+    println!("  TLS handshake:      {}s", limits.tls_handshake_timeout_secs);
+
+    let rejected = &report.rejected;
+    println!("Rejected since the server started:");
+    println!("  Too many headers:       {}", rejected.too_many_headers);
+    println!("  Headers too large:      {}", rejected.headers_too_large);
+    println!("  Request line too long:  {}", rejected.request_line_too_long);
+    println!("  Slow request body:      {}", rejected.slow_body);
+    Ok(())
+}
+
+/// Gets the public key the server signs images with, writing it to a file or printing it
+pub async fn get_signing_key(conn: &Connection, output_path: &Option<String>) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    let pem = match client.signing_key().await {
+        Ok(pem) => pem,
+        Err(e) => return Err(CliError::Message(format!("Error getting signing key: {}", e))),
+    };
+
+    // Print the key on its own so it can be piped to other tools
+    let output_path = match output_path {
+        Some(output_path) => output_path,
+        None => {
+            print!("{}", pem);
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = fs::write(output_path, pem) {
+        return Err(CliError::Message(format!("Error writing signing key: {}", e)));
+    }
+
+    println!("{}", format!("✅ Signing key written to {}. Check signatures with cosign verify-blob --key {}", output_path, output_path).green());
+    Ok(())
+}
+
+/// Backs up the database for a namespace on the server to a local file
+pub async fn backup_namespace(conn: &Connection, namespace: &String, output_path: &String) -> Result<(), CliError> {
+    println!("{}", format!("Backing up namespace '{}'", namespace).blue());
+
+    let client = server::get_server_client(conn)?;
+    let backup = client.backup_namespace(namespace).await?;
+
+    if let Err(e) = fs::write(output_path, backup) {
+        return Err(CliError::Message(format!("Error writing backup to {}: {}", output_path, e)));
+    }
+
+    println!("{}", format!("✅ Namespace '{}' backed up to {}", namespace, output_path).green());
+    Ok(())
+}
+
+/// Restores the database for a namespace on the server from a local backup file
+pub async fn restore_namespace(conn: &Connection, namespace: &String, input_path: &String) -> Result<(), CliError> {
+    println!("{}", format!("Restoring namespace '{}'", namespace).blue());
+
+    let backup = match fs::read(input_path) {
+        Ok(backup) => backup,
+        Err(e) => return Err(CliError::Message(format!("Error reading backup from {}: {}", input_path, e))),
+    };
+
+    let client = server::get_server_client(conn)?;
+    client.restore_namespace(namespace, backup).await?;
+
+    println!("{}", format!("✅ Namespace '{}' restored from {}", namespace, input_path).green());
+    Ok(())
 }
\ No newline at end of file
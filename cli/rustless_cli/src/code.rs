@@ -1,148 +1,260 @@
 use std::path::PathBuf;
-use std::{process::Command, path::Path};
-use std::fs::{self, File};
-use std::io::{BufReader, Read};
+use std::{process::Command, path::{Component, Path}};
+use std::fs::{self, File, Metadata};
+use std::io;
 
-use colored::Colorize;
+use ignore::WalkBuilder;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
 
-/// Compiles the code in the given path to verify it is valid
-pub fn compile_code(code_path: &String) {
+/// The file listing files in the code to leave out of the zip file, in the same format as a .gitignore. It takes
+/// priority over .gitignore files, so it can bring back files they leave out with !
+pub const RUSTLESS_IGNORE_FILE_NAME: &str = ".rustlessignore";
+
+/// The folder cargo builds into, which is never zipped
+const TARGET_FOLDER_NAME: &str = "target";
+
+/// The folder git keeps the repository in, which is never zipped
+const GIT_FOLDER_NAME: &str = ".git";
+
+/// Compiles the code in the given path to verify it is valid, returning an error if it doesn't compile
+pub fn try_compile_code(code_path: &String) -> Result<(), String> {
     // Create a new process to run the build command
     let compile_process = Command::new("cargo")
         .arg("build")
         .arg("--release")
         .current_dir(code_path)
-        .output()
-        .expect("Failed to run cargo build");
+        .output();
 
     // Check the result
-    match compile_process.status.code() {
-        Some(code) => {
-            if code != 0 {
-                println!(
-                    "{}",
-                    format!("Error compiling the function app code. Is the code valid?")
-                        .red()
-                        .bold()
-                );
-                std::process::exit(-1);
-            }
-        }
-        None => {
-            println!(
-                "{}",
-                format!("Error compiling the function app code. Is the code valid?")
-                    .red()
-                    .bold()
-            );
-            std::process::exit(-1);
-        }
+    match compile_process {
+        Ok(output) if output.status.code() == Some(0) => {},
+        _ => return Err("Error compiling the function app code. Is the code valid?".to_string()),
     };
 
     // Clean the code if everything worked so it is ready to zip and upload
     let compile_process = Command::new("cargo")
         .arg("clean")
         .current_dir(code_path)
-        .output()
-        .expect("Failed to run cargo clean");
+        .output();
 
     // Check the result
-    match compile_process.status.code() {
-        Some(code) => {
-            if code != 0 {
-                println!(
-                    "{}",
-                    format!("Error compiling the function app code. Is the code valid?")
-                        .red()
-                        .bold()
-                );
-                std::process::exit(-1);
-            }
-        }
-        None => {
-            println!(
-                "{}",
-                format!("Error compiling the function app code. Is the code valid?")
-                    .red()
-                    .bold()
-            );
-            std::process::exit(-1);
-        }
-    };
+    match compile_process {
+        Ok(output) if output.status.code() == Some(0) => Ok(()),
+        _ => Err("Error compiling the function app code. Is the code valid?".to_string()),
+    }
 }
 
-/// Uploads code to the server as a zip file
-pub async fn zip_function_app_code(code_path: &String) -> PathBuf {
+/// Zips the code in the given path into code.zip next to it, returning the path of the zip file
+pub fn zip_function_app_code(code_path: &String) -> Result<PathBuf, String> {
     // Get the folder to run this in - the parent folder of the path to the code
-    let run_dir = Path::new(code_path).parent();
-    let run_dir = match run_dir {
+    let run_dir = match Path::new(code_path).parent() {
         Some(z) => z,
-        None => {
-            let error_message = format!("Error getting the parent directory of the code path").red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
-        }
+        None => return Err("Error getting the parent directory of the code path".to_string()),
     };
 
-    // Get the folder in the run directory that contains the code
-    let zip_dir = Path::new(code_path).strip_prefix(run_dir);
-    let zip_dir = match zip_dir {
+    let zip_file = Path::new(run_dir).join("code.zip");
+    zip_function_app_code_to(code_path, &zip_file)?;
+
+    Ok(zip_file)
+}
+
+/// Zips the code in the given path into the given zip file, replacing the zip file if it exists
+pub fn zip_function_app_code_to(code_path: &String, zip_file: &Path) -> Result<(), String> {
+    // Get the parent folder of the path to the code
+    let run_dir = match Path::new(code_path).parent() {
+        Some(z) => z,
+        None => return Err("Error getting the parent directory of the code path".to_string()),
+    };
+
+    // Get the folder in the parent folder that contains the code, which the files are put in in the zip file
+    let zip_dir = match Path::new(code_path).strip_prefix(run_dir) {
         Ok(z) => z,
-        Err(_) => {
-            let error_message = format!("Error getting the parent directory of the code path").red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
-        }
+        Err(_) => return Err("Error getting the parent directory of the code path".to_string()),
     };
 
     // Delete the existing zip file if it exists
-    let zip_file = Path::new(run_dir).join("code.zip");
-    let _ = fs::remove_file(&zip_file);
-    
-    let zip_result = Command::new("zip")
-        .arg("-r")
-        .arg("code.zip")
-        .arg(zip_dir)
-        .current_dir(run_dir)
-        .output();
+    let _ = fs::remove_file(zip_file);
 
-    match zip_result {
-        Ok(zip_result) => {
-            if zip_result.status.code() != Some(0) {
-                let error_message = format!("Error zipping the code").red().bold();
-                println!("{}", error_message);
-                std::process::exit(-1);
-            }
-        }
-        Err(e) => {
-            let error_message = format!("Error zipping the code: {}", e).red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
-        }
+    let file = match File::create(zip_file) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Error creating the zip file: {}", e)),
     };
 
-    zip_file
+    let mut writer = ZipWriter::new(file);
+    add_code_to_zip(&mut writer, Path::new(code_path), zip_dir)?;
+
+    match writer.finish() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error zipping the code: {}", e)),
+    }
 }
 
-/// Converts the zip file to a base64 encoded string
-pub fn zip_file_to_base64(zip_file: &PathBuf) -> String {
-    // Open the zip file
-    let file = File::open(zip_file).unwrap();
-
-    // Read the file into a buffer
-    let mut reader = BufReader::new(file);
-    let mut buffer = Vec::new();
-
-    let result = reader.read_to_end(&mut buffer);
-    match result {
-        Ok(_) => (),
-        Err(e) => {
-            let error_message = format!("Error reading the zip file: {}", e).red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
+/// Adds the code to the zip file under the given folder in the zip file, leaving out files ignored by any .gitignore
+/// or .rustlessignore files, the target folder, and .git folders
+///
+/// Files are added in name order without directory entries, and with a fixed modified time, so zipping the same code
+/// always gives the same zip file and the server can skip building code it has already deployed
+fn add_code_to_zip(writer: &mut ZipWriter<File>, code_path: &Path, zip_dir: &Path) -> Result<(), String> {
+    let walker = WalkBuilder::new(code_path)
+        .hidden(false)
+        .require_git(false)
+        .follow_links(true)
+        .add_custom_ignore_filename(RUSTLESS_IGNORE_FILE_NAME)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(|entry| entry.file_name() != GIT_FOLDER_NAME && !(entry.depth() == 1 && entry.file_name() == TARGET_FOLDER_NAME))
+        .build();
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("Error reading the code: {}", e)),
+        };
+
+        if entry.file_type().is_none_or(|file_type| file_type.is_dir()) {
+            continue;
         }
+
+        let name = match entry.path().strip_prefix(code_path) {
+            Ok(relative_path) => zip_dir.join(relative_path),
+            Err(_) => return Err(format!("Error getting the path of {} in the code", entry.path().display())),
+        };
+
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => return Err(format!("Error reading {}: {}", entry.path().display(), e)),
+        };
+
+        add_file_to_zip(writer, entry.path(), &name, &metadata)?;
+    }
+
+    Ok(())
+}
+
+/// Adds a file to the zip file under the given path in the zip file, which always uses / between folders
+fn add_file_to_zip(writer: &mut ZipWriter<File>, path: &Path, zip_path: &Path, metadata: &Metadata) -> Result<(), String> {
+    let name: Vec<String> = zip_path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(DateTime::default())
+        .unix_permissions(get_permissions(metadata));
+
+    if let Err(e) = writer.start_file(name.join("/"), options) {
+        return Err(format!("Error zipping {}: {}", path.display(), e));
+    }
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Error reading {}: {}", path.display(), e)),
     };
 
-    // return the string as a bae64 encoded string
-    base64::encode(&buffer)
-}
\ No newline at end of file
+    match io::copy(&mut file, writer) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error zipping {}: {}", path.display(), e)),
+    }
+}
+
+/// Gets the permissions to store for a file, so executable scripts stay executable when unzipped
+#[cfg(unix)]
+fn get_permissions(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o777
+}
+
+/// Gets the permissions to store for a file, so executable scripts stay executable when unzipped
+#[cfg(not(unix))]
+fn get_permissions(_metadata: &Metadata) -> u32 {
+    0o644
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+    use zip::ZipArchive;
+
+    /// Creates the code for an app in a temporary folder, with the given files and their contents
+    fn create_code(files: &[(&str, &str)]) -> (TempDir, String) {
+        let temp_dir = TempDir::new().unwrap();
+        let code_path = temp_dir.path().join("app");
+        for (file, contents) in files {
+            let path = code_path.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        (temp_dir, code_path.to_string_lossy().to_string())
+    }
+
+    /// Zips the code, returning the names of the files in the zip file
+    fn zip_names(temp_dir: &TempDir, code_path: &String) -> Vec<String> {
+        let zip_file = temp_dir.path().join("code.zip");
+        zip_function_app_code_to(code_path, &zip_file).unwrap();
+
+        let archive = ZipArchive::new(File::open(zip_file).unwrap()).unwrap();
+        archive.file_names().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn zips_files_in_name_order_under_the_folder() {
+        let (temp_dir, code_path) = create_code(&[("src/main.rs", ""), ("Cargo.toml", ""), (".env", "")]);
+        assert_eq!(zip_names(&temp_dir, &code_path), vec!["app/.env", "app/Cargo.toml", "app/src/main.rs"]);
+    }
+
+    #[test]
+    fn leaves_out_gitignored_files_without_a_repository() {
+        let (temp_dir, code_path) = create_code(&[("Cargo.toml", ""), (".gitignore", "*.log\n"), ("build.log", ""), ("src/debug.log", "")]);
+        assert_eq!(zip_names(&temp_dir, &code_path), vec!["app/.gitignore", "app/Cargo.toml"]);
+    }
+
+    #[test]
+    fn leaves_out_target_and_git_folders() {
+        let (temp_dir, code_path) = create_code(&[
+            ("Cargo.toml", ""),
+            ("target/release/app", ""),
+            (".git/HEAD", ""),
+            ("vendor/dep/.git/HEAD", ""),
+            ("src/target/mod.rs", ""),
+        ]);
+
+        // Only the target folder at the root is cargo's, so a module named target is kept
+        assert_eq!(zip_names(&temp_dir, &code_path), vec!["app/Cargo.toml", "app/src/target/mod.rs"]);
+    }
+
+    #[test]
+    fn rustlessignore_leaves_out_files() {
+        let (temp_dir, code_path) = create_code(&[("Cargo.toml", ""), (RUSTLESS_IGNORE_FILE_NAME, "secrets/\n"), ("secrets/key.pem", "")]);
+        assert_eq!(zip_names(&temp_dir, &code_path), vec!["app/.rustlessignore", "app/Cargo.toml"]);
+    }
+
+    #[test]
+    fn rustlessignore_brings_back_gitignored_files() {
+        let (temp_dir, code_path) = create_code(&[
+            ("Cargo.toml", ""),
+            (".gitignore", "*.json\n"),
+            (RUSTLESS_IGNORE_FILE_NAME, "!config.json\n"),
+            ("config.json", ""),
+            ("cache.json", ""),
+        ]);
+
+        assert_eq!(zip_names(&temp_dir, &code_path), vec!["app/.gitignore", "app/.rustlessignore", "app/Cargo.toml", "app/config.json"]);
+    }
+
+    #[test]
+    fn zipping_the_same_code_gives_the_same_zip_file() {
+        let (temp_dir, code_path) = create_code(&[("Cargo.toml", "[package]"), ("src/main.rs", "fn main() {}")]);
+        let first = temp_dir.path().join("first.zip");
+        let second = temp_dir.path().join("second.zip");
+        zip_function_app_code_to(&code_path, &first).unwrap();
+        zip_function_app_code_to(&code_path, &second).unwrap();
+
+        assert_eq!(fs::read(first).unwrap(), fs::read(second).unwrap());
+    }
+}
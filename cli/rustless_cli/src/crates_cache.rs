@@ -0,0 +1,51 @@
+use colored::Colorize;
+use rusqlite::Connection;
+
+use crate::error::CliError;
+use crate::server;
+
+/// Shows how the crates.io cache on the server has been used since it started and what it has stored
+pub async fn show_stats(conn: &Connection) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    let stats = match client.crates_cache_stats().await {
+        Ok(stats) => stats,
+        Err(e) => return Err(CliError::Message(format!("Error getting crates cache stats: {}", e))),
+    };
+
+    match (&stats.build_url, stats.enabled) {
+        (_, true) => println!("{}", "The crates cache is running on the server".blue()),
+        (Some(url), false) => println!("{}", format!("Builds download crates through the external cache at {}", url).blue()),
+        (None, false) => {
+            println!("{}", "The crates cache is turned off. Set RUSTLESS_CRATES_CACHE on the server to turn it on".yellow());
+            return Ok(());
+        }
+    }
+
+    if let Some(url) = &stats.build_url {
+        println!("  Builds use:    {}", url);
+    }
+
+    if !stats.enabled {
+        return Ok(());
+    }
+
+    println!("  Cached:        {} files, {} bytes", stats.cached_files, stats.cached_bytes);
+    println!("Since the server started:");
+    println!("  Index files:   {} from the cache, {} from crates.io", stats.index_hits, stats.index_misses);
+    println!("  Crates:        {} from the cache, {} from crates.io", stats.crate_hits, stats.crate_misses);
+    println!("  Served:        {} bytes", stats.bytes_served);
+    println!("  Stale served:  {} index files while crates.io couldn't be reached", stats.stale_served);
+    println!("  Errors:        {} calls to crates.io failed", stats.upstream_errors);
+    Ok(())
+}
+
+/// Removes everything from the crates.io cache on the server
+pub async fn purge(conn: &Connection) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    match client.purge_crates_cache().await {
+        Ok(purged) => println!("{}", format!("✅ Removed {} files, {} bytes from the crates cache", purged.files_removed, purged.bytes_removed).green()),
+        Err(e) => return Err(CliError::Message(format!("Error purging crates cache: {}", e))),
+    }
+
+    Ok(())
+}
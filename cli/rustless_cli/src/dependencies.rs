@@ -0,0 +1,85 @@
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::{BulkStartReport, DependenciesReport};
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// Prints the function apps a function app depends on, and the apps that depend on it
+fn print_dependencies(name: &String, report: &DependenciesReport) {
+    match report.depends_on.is_empty() {
+        true => println!("{}", format!("'{}' doesn't depend on any function apps", name).blue()),
+        false => println!("{}", format!("'{}' depends on: {}", name, report.depends_on.join(", ")).blue()),
+    }
+
+    match report.required_by.is_empty() {
+        true => println!("No function apps depend on '{}'", name),
+        false => println!("Function apps that depend on '{}': {}", name, report.required_by.join(", ")),
+    }
+}
+
+/// Prints what happened to each function app started, in the order they were started
+fn print_start_report(report: &BulkStartReport) -> Result<(), CliError> {
+    if report.apps.is_empty() {
+        println!("{}", "No function apps are ready to start".blue());
+        return Ok(());
+    }
+
+    for app in report.apps.iter() {
+        match (&app.error, app.already_running) {
+            (Some(e), _) => println!("{}", format!("❌ '{}' couldn't be started: {}", app.name, e).red()),
+            (None, true) => println!("{}", format!("'{}' was already running", app.name).blue()),
+            (None, false) => println!("{}", format!("✅ '{}' started", app.name).green()),
+        }
+    }
+
+    match report.apps.iter().any(|app| app.error.is_some()) {
+        true => Err(CliError::Failed),
+        false => Ok(()),
+    }
+}
+
+/// Sets the function apps a function app depends on, or removes them if the list is empty, retrying by name if the
+/// cached ID is stale
+pub async fn set_dependencies(conn: &Connection, name: &String, depends_on: &[String]) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.set_dependencies(&app, depends_on).await }).await;
+
+    match result {
+        Ok(true) => match depends_on.is_empty() {
+            true => println!("{}", format!("✅ '{}' doesn't depend on any function apps", name).green()),
+            false => println!("{}", format!("✅ '{}' depends on: {}", name, depends_on.join(", ")).green()),
+        },
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error setting dependencies: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the function apps a function app depends on, and the apps that depend on it
+pub async fn show_dependencies(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.dependencies(&app).await }).await;
+
+    match result {
+        Ok(Some(report)) => print_dependencies(name, &report),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting dependencies: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Starts every function app that is ready, each after the apps it depends on are healthy
+pub async fn start_all_function_apps(conn: &Connection) -> Result<(), CliError> {
+    println!("{}", "Starting all function apps that are ready...".blue());
+
+    let client = server::get_server_client(conn)?;
+    match client.start_apps(&[]).await {
+        Ok(report) => print_start_report(&report),
+        Err(e) => Err(CliError::Message(format!("Error starting function apps: {}", e))),
+    }
+}
@@ -0,0 +1,345 @@
+use std::cell::Cell;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use futures::stream::{self, StreamExt};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rusqlite::Connection;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use rustless_cli::DeployStage;
+use rustless_shared::{BuildOptions, DeployAction, ScaleProfile};
+
+use crate::cancel;
+use crate::diagnostics;
+use crate::dry_run;
+use crate::error::CliError;
+use crate::server::{self, FunctionAppRef};
+
+/// The list of function apps to deploy, loaded from a YAML file
+#[derive(Deserialize)]
+struct DeployManifest {
+    // The function apps to deploy
+    apps: Vec<AppManifest>,
+}
+
+/// A function app to deploy
+#[derive(Deserialize)]
+struct AppManifest {
+    // The name of the function app
+    name: String,
+
+    // The path to the code for the function app, relative to the manifest file
+    path: String,
+
+    // The namespace to create the function app in if it doesn't exist
+    #[serde(default = "rustless_shared::default_namespace")]
+    namespace: String,
+
+    // Fail the build on the server if there are any compiler or clippy warnings
+    #[serde(default)]
+    strict: bool,
+
+    // The Dockerfile template to build with. The server default is used if this isn't set
+    #[serde(default)]
+    template: Option<String>,
+
+    // The Rust toolchain to build with. The template default is used if this isn't set
+    #[serde(default)]
+    toolchain: Option<String>,
+
+    // The base image to build on. The template default is used if this isn't set
+    #[serde(default)]
+    base_image: Option<String>,
+
+    // The profiles that scale the app on cron schedules. These replace the app's profiles when it is deployed, and
+    // an empty list clears them. The app's profiles are left alone if this isn't set
+    #[serde(default)]
+    scale_profiles: Option<Vec<ScaleProfile>>,
+}
+
+impl AppManifest {
+    /// Gets the options for building the app on the server
+    fn build_options(&self) -> BuildOptions {
+        BuildOptions {
+            strict: self.strict,
+            template: self.template.clone(),
+            toolchain: self.toolchain.clone(),
+            base_image: self.base_image.clone(),
+            force: false,
+        }
+    }
+}
+
+/// The result of deploying a single function app
+struct DeployResult {
+    // The name of the function app
+    name: String,
+
+    // Whether the app was created or updated
+    action: &'static str,
+
+    // How long the deployment took
+    duration: Duration,
+
+    // The error if the deployment failed
+    error: Option<String>,
+}
+
+/// Creates a progress bar for one app in a multi progress display
+fn create_app_progress_bar(multi_progress: &MultiProgress, name: &str) -> ProgressBar {
+    let pb = multi_progress.add(ProgressBar::new_spinner());
+    pb.enable_steady_tick(Duration::from_millis(120));
+    pb.set_style(
+        ProgressStyle::with_template("{spinner:.blue} {prefix:.bold} {msg}")
+            .unwrap()
+            .tick_strings(&["◜", "◠", "◝", "◞", "◡", "◟"]),
+    );
+    pb.set_prefix(name.to_string());
+    pb.set_message("Waiting...");
+    pb
+}
+
+/// Loads the deploy manifest, resolving the app paths relative to the manifest file
+fn load_manifest(manifest_path: &String) -> Result<DeployManifest, String> {
+    let contents = match fs::read_to_string(manifest_path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("Error reading {}: {}", manifest_path, e)),
+    };
+
+    let mut manifest: DeployManifest = match serde_yaml::from_str(&contents) {
+        Ok(manifest) => manifest,
+        Err(e) => return Err(format!("Error parsing {}: {}", manifest_path, e)),
+    };
+
+    let manifest_dir = std::path::Path::new(manifest_path).parent().unwrap_or(std::path::Path::new(""));
+    for app in manifest.apps.iter_mut() {
+        app.path = manifest_dir.join(&app.path).to_string_lossy().to_string();
+    }
+
+    Ok(manifest)
+}
+
+/// Gets the message to show on the progress bar for a stage of deploying an app
+fn get_stage_message(stage: &DeployStage) -> &'static str {
+    match stage {
+        DeployStage::Compiling => "Compiling function app...",
+        DeployStage::Zipping => "Zipping function app...",
+        DeployStage::Checking => "Checking function app...",
+        DeployStage::Registering => "Registering app...",
+        DeployStage::Building(_) => "Uploading and building...",
+    }
+}
+
+/// Deploys a single function app, creating it if it doesn't exist or updating the code if it does
+///
+/// While the code is uploading and building, the ID of the app is kept in building so the build can be cancelled
+async fn deploy_app(conn: &Connection, app: &AppManifest, force: bool, pb: &ProgressBar, building: &Mutex<Vec<Uuid>>) -> Result<&'static str, String> {
+    let building_id = Cell::new(None);
+
+    let options = BuildOptions { force, ..app.build_options() };
+    let result = rustless_cli::deploy_function_app(conn, &app.name, &app.path, &app.namespace, &options, |stage| {
+        pb.set_message(get_stage_message(&stage));
+
+        if let DeployStage::Building(id) = stage {
+            building_id.set(Some(id));
+            if let Ok(mut building) = building.lock() {
+                building.push(id);
+            }
+        }
+    }).await;
+
+    if let Some(id) = building_id.get() {
+        if let Ok(mut building) = building.lock() {
+            building.retain(|building_id| *building_id != id);
+        }
+    }
+
+    // Apps waiting for approval can't be started until an approver approves the deployment. Apps whose code
+    // hasn't changed are skipped by the server
+    let outcome = result?;
+
+    if let Some(profiles) = &app.scale_profiles {
+        pb.set_message("Setting scale profiles...");
+        let client = server::get_server_client(conn)?;
+        match client.set_scale_profiles(&FunctionAppRef::Id(outcome.id), profiles).await {
+            Ok(Some(_)) => {},
+            Ok(None) => return Err(format!("No function app with the name '{}' exists", app.name)),
+            Err(e) => return Err(format!("Error setting scale profiles: {}", e)),
+        }
+    }
+
+    match (outcome.pending_deployment, outcome.unchanged_deployment, outcome.action) {
+        (Some(_), _, _) => Ok("Pending"),
+        (None, Some(_), _) => Ok("Skipped"),
+        (None, None, DeployAction::Create) => Ok("Created"),
+        (None, None, DeployAction::Update) => Ok("Updated"),
+    }
+}
+
+/// Deploys all the function apps in a manifest file, deploying up to the given number of apps at the same time
+///
+/// The server skips building apps whose code hasn't changed since their last deployment, unless force is set
+pub async fn deploy(conn: &Connection, manifest_path: &String, parallel: usize, force: bool) -> Result<(), CliError> {
+    let manifest = load_manifest(manifest_path)?;
+
+    if manifest.apps.is_empty() {
+        println!("{}", format!("No function apps in {}", manifest_path).blue());
+        return Ok(());
+    }
+
+    let client = server::get_server_client(conn)?;
+
+    println!("{}", format!("Deploying {} function apps, {} at a time", manifest.apps.len(), parallel.max(1)).blue());
+
+    // Each app gets its own progress bar
+    let multi_progress = MultiProgress::new();
+    let progress_bars: Vec<ProgressBar> = manifest.apps.iter()
+        .map(|app| create_app_progress_bar(&multi_progress, &app.name))
+        .collect();
+
+    // The apps currently uploading and building, so their builds can be cancelled on Ctrl-C
+    let building = Mutex::new(Vec::new());
+
+    // Deploy the apps, keeping at most the given number in flight. The results are returned in the
+    // order of the manifest
+    let deployment = stream::iter(manifest.apps.iter().zip(progress_bars.iter()))
+        .map(|(app, pb)| {
+            let building = &building;
+            async move {
+                let start = Instant::now();
+                let result = deploy_app(conn, app, force, pb, building).await;
+
+                match &result {
+                    Ok(action) => pb.finish_with_message(format!("✅ {}", action).green().to_string()),
+                    Err(_) => pb.finish_with_message("❌ Failed".red().to_string()),
+                }
+
+                DeployResult {
+                    name: app.name.to_string(),
+                    action: match &result {
+                        Ok(action) => action,
+                        Err(_) => "-",
+                    },
+                    duration: start.elapsed(),
+                    error: result.err(),
+                }
+            }
+        })
+        .buffered(parallel.max(1))
+        .collect::<Vec<DeployResult>>();
+
+    // Stop if the user presses Ctrl-C. This drops any requests in flight, so clear the spinners
+    // and stop the builds that were running on the server
+    let results = match cancel::until_cancelled(deployment).await {
+        Some(results) => results,
+        None => {
+            for pb in progress_bars.iter() {
+                pb.finish_and_clear();
+            }
+            let _ = multi_progress.clear();
+
+            println!("{}", "Deployment cancelled".yellow().bold());
+
+            let ids = building.lock().map(|building| building.clone()).unwrap_or_default();
+            for id in ids {
+                cancel::cancel_build(&client, &FunctionAppRef::Id(id)).await;
+            }
+
+            return Err(CliError::Failed);
+        }
+    };
+
+    print_summary(&results);
+
+    // Show the errors for any failed apps, including compiler errors from the server
+    for result in results.iter() {
+        if let Some(error) = &result.error {
+            println!("\n{}", format!("Errors for {}:", result.name).red().bold());
+            if !diagnostics::print_build_errors(error) {
+                println!("{}", error.red());
+            }
+        }
+    }
+
+    // The summary and errors have been shown, so there is nothing more to print
+    if results.iter().any(|result| result.error.is_some()) {
+        return Err(CliError::Failed);
+    }
+
+    Ok(())
+}
+
+/// Prints what deploying all the function apps in a manifest file would do, without making any changes
+pub async fn plan_deploy(conn: &Connection, manifest_path: &String) -> Result<(), CliError> {
+    let manifest = load_manifest(manifest_path)?;
+
+    println!("{}", "Dry run - no changes will be made".blue().bold());
+
+    if manifest.apps.is_empty() {
+        println!("{}", format!("No function apps in {}", manifest_path).blue());
+        return Ok(());
+    }
+
+    let client = dry_run::get_client(conn)?;
+
+    // Apps in a manifest are created or updated as needed, so either action is fine
+    let mut failed = 0;
+    for app in manifest.apps.iter() {
+        if !dry_run::print_deploy_plan(&client, &app.name, &app.path, &app.namespace, &app.build_options(), None).await {
+            failed += 1;
+        }
+        println!();
+    }
+
+    if failed > 0 {
+        return Err(CliError::Message(format!("{} of {} function apps would fail to deploy", failed, manifest.apps.len())));
+    }
+
+    println!("{}", format!("✅ All {} function apps can be deployed", manifest.apps.len()).green());
+    Ok(())
+}
+
+/// Prints a table summarizing the result of deploying each app
+fn print_summary(results: &[DeployResult]) {
+    let max_name_length = results.iter().map(|result| result.name.len()).max().unwrap_or(0).max(4);
+
+    println!("\n┌-{}-┬---------┬---------┬----------┐", "-".repeat(max_name_length));
+    println!(
+        "| {}{} | {}  | {}  | {} |",
+        "Name".bold(),
+        " ".repeat(max_name_length - 4),
+        "Action".bold(),
+        "Result".bold(),
+        "Duration".bold()
+    );
+    println!("|-{}-┼---------┼---------┼----------|", "-".repeat(max_name_length));
+
+    for result in results {
+        let result_string = match result.error {
+            Some(_) => "Failed ".red(),
+            None => "Success".green(),
+        };
+
+        println!(
+            "| {}{} | {:7} | {} | {:>7}s |",
+            result.name.blue().bold(),
+            " ".repeat(max_name_length - result.name.len()),
+            result.action,
+            result_string,
+            result.duration.as_secs()
+        );
+    }
+
+    println!("└-{}-┴---------┴---------┴----------┘", "-".repeat(max_name_length));
+
+    let failed = results.iter().filter(|result| result.error.is_some()).count();
+    if failed == 0 {
+        println!("{}", format!("✅ Deployed {} function apps", results.len()).green());
+    } else {
+        println!("{}", format!("{} of {} function apps failed to deploy", failed, results.len()).red().bold());
+    }
+}
@@ -0,0 +1,216 @@
+use colored::{ColoredString, Colorize};
+use serde::Deserialize;
+
+/// A single line of JSON output from `cargo build --message-format=json`
+#[derive(Deserialize)]
+struct CargoMessage {
+    // The type of message, we only care about compiler-message
+    reason: String,
+
+    // The compiler diagnostic, if this is a compiler message
+    message: Option<Diagnostic>,
+}
+
+/// A compiler diagnostic, such as an error or a warning
+#[derive(Deserialize)]
+pub struct Diagnostic {
+    // The main message
+    pub message: String,
+
+    // The error code, such as E0308
+    pub code: Option<DiagnosticCode>,
+
+    // The level - error, warning, note, help etc.
+    pub level: String,
+
+    // The locations in the source code this diagnostic refers to
+    pub spans: Vec<DiagnosticSpan>,
+
+    // Any additional notes or help messages
+    pub children: Vec<Diagnostic>,
+}
+
+/// The error code for a diagnostic
+#[derive(Deserialize)]
+pub struct DiagnosticCode {
+    pub code: String,
+}
+
+/// A location in the source code
+#[derive(Deserialize)]
+pub struct DiagnosticSpan {
+    // The file the span is in
+    pub file_name: String,
+
+    // The line and column the span starts at
+    pub line_start: usize,
+    pub column_start: usize,
+
+    // Is this the primary span for the diagnostic
+    pub is_primary: bool,
+
+    // The source code lines covered by the span
+    pub text: Vec<DiagnosticSpanLine>,
+
+    // An optional label to show under the highlighted code
+    pub label: Option<String>,
+}
+
+/// A line of source code covered by a span
+#[derive(Deserialize)]
+pub struct DiagnosticSpanLine {
+    // The line of source code
+    pub text: String,
+
+    // The 1-based columns to highlight
+    pub highlight_start: usize,
+    pub highlight_end: usize,
+}
+
+/// Extracts the compiler diagnostics from a build log
+///
+/// Build logs from the host contain the docker build output, with the JSON messages from cargo
+/// mixed in with other output and prefixed with docker step information. This looks for the start
+/// of a JSON object on each line and tries to parse it as a cargo message.
+pub fn extract_diagnostics(build_log: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for line in build_log.lines() {
+        // Find the start of any JSON on the line
+        let json_start = match line.find("{\"") {
+            Some(json_start) => json_start,
+            None => continue,
+        };
+
+        // Parse the message, ignoring anything that isn't a compiler message
+        let cargo_message = serde_json::from_str::<CargoMessage>(&line[json_start..]);
+        if let Ok(CargoMessage {
+            reason,
+            message: Some(message),
+        }) = cargo_message
+        {
+            if reason == "compiler-message" {
+                diagnostics.push(message);
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Colors a piece of text based on the diagnostic level
+fn color_for_level(level: &str, text: &str) -> ColoredString {
+    match level {
+        "error" | "error: internal compiler error" => text.red().bold(),
+        "warning" => text.yellow().bold(),
+        "note" => text.green().bold(),
+        "help" => text.cyan().bold(),
+        _ => text.bold(),
+    }
+}
+
+/// Prints a single span with the source code and the highlighted section
+fn print_span(level: &str, span: &DiagnosticSpan) {
+    // Work out how wide the line number gutter needs to be
+    let last_line = span.line_start + span.text.len().saturating_sub(1);
+    let gutter_width = last_line.to_string().len();
+    let gutter = " ".repeat(gutter_width);
+
+    println!(
+        "{}{} {}:{}:{}",
+        gutter,
+        "-->".blue().bold(),
+        span.file_name,
+        span.line_start,
+        span.column_start
+    );
+    println!("{} {}", gutter, "|".blue().bold());
+
+    for (index, line) in span.text.iter().enumerate() {
+        let line_number = format!("{:>width$}", span.line_start + index, width = gutter_width);
+        println!("{} {} {}", line_number.blue().bold(), "|".blue().bold(), line.text);
+
+        // Underline the highlighted part of the line
+        let highlight_start = line.highlight_start.saturating_sub(1);
+        let highlight_length = line.highlight_end.saturating_sub(line.highlight_start).max(1);
+        let marker = if span.is_primary { "^" } else { "-" };
+        let underline = marker.repeat(highlight_length);
+
+        // Only show the label on the last line of the span
+        let label = match (&span.label, index == span.text.len() - 1) {
+            (Some(label), true) => format!(" {}", label),
+            _ => String::new(),
+        };
+
+        println!(
+            "{} {} {}{}",
+            gutter,
+            "|".blue().bold(),
+            " ".repeat(highlight_start),
+            color_for_level(level, &format!("{}{}", underline, label))
+        );
+    }
+}
+
+/// Prints a diagnostic in the same style as cargo
+fn print_diagnostic(diagnostic: &Diagnostic) {
+    // Show the level, code, and message
+    let header = match &diagnostic.code {
+        Some(code) => format!("{}[{}]", diagnostic.level, code.code),
+        None => diagnostic.level.to_string(),
+    };
+    println!(
+        "{}{} {}",
+        color_for_level(&diagnostic.level, &header),
+        ":".bold(),
+        diagnostic.message.bold()
+    );
+
+    // Show the primary spans first, then any others
+    let mut spans: Vec<&DiagnosticSpan> = diagnostic.spans.iter().collect();
+    spans.sort_by_key(|span| !span.is_primary);
+    for span in spans {
+        print_span(&diagnostic.level, span);
+    }
+
+    // Show any notes or help as indented lines
+    for child in &diagnostic.children {
+        println!(
+            "  {} {}{} {}",
+            "=".blue().bold(),
+            color_for_level(&child.level, &child.level),
+            ":".bold(),
+            child.message
+        );
+    }
+
+    println!();
+}
+
+/// Prints the compiler errors from a failed build, with a summary of the warnings
+///
+/// Returns true if any errors were found in the build log
+pub fn print_build_errors(build_log: &str) -> bool {
+    let diagnostics = extract_diagnostics(build_log);
+
+    let errors: Vec<&Diagnostic> = diagnostics
+        .iter()
+        .filter(|d| d.level.starts_with("error") && !d.message.starts_with("aborting due to"))
+        .collect();
+
+    if errors.is_empty() {
+        return false;
+    }
+
+    println!("{}", "The function app failed to compile on the server:\n".red().bold());
+
+    for error in &errors {
+        print_diagnostic(error);
+    }
+
+    let warning_count = diagnostics.iter().filter(|d| d.level == "warning").count();
+    let summary = format!("{} error(s), {} warning(s)", errors.len(), warning_count);
+    println!("{}", summary.red().bold());
+
+    true
+}
@@ -0,0 +1,284 @@
+use std::path::Path;
+
+use colored::{ColoredString, Colorize};
+use rusqlite::Connection;
+use uuid::Uuid;
+
+use rustless_client::{FunctionAppRef, RustlessClient};
+use rustless_shared::{BuildOptions, DeployAction, DeployPlan, FunctionAppStatus};
+
+use crate::error::CliError;
+use crate::server;
+use crate::storage;
+
+/// Gets a client for the server to plan against, returning an error if no server is set
+pub fn get_client(conn: &Connection) -> Result<RustlessClient, CliError> {
+    match storage::get_server(conn) {
+        Ok(server) => Ok(server::get_client(&server)?),
+        Err(_) => Err(CliError::NoServer),
+    }
+}
+
+/// Checks the code path looks like a function app, returning the problem if it doesn't
+fn check_code_path(code_path: &String) -> Option<String> {
+    let path = Path::new(code_path);
+    if !path.is_dir() {
+        return Some(format!("The code path {} does not exist or is not a folder", code_path));
+    }
+
+    if !path.join("Cargo.toml").is_file() {
+        return Some(format!("The code path {} does not contain a Cargo.toml file", code_path));
+    }
+
+    None
+}
+
+/// Formats a function app status for the plan output
+fn format_status(status: FunctionAppStatus) -> &'static str {
+    match status {
+        FunctionAppStatus::NotRegistered => "not registered",
+        FunctionAppStatus::Registered => "registered",
+        FunctionAppStatus::Building => "building",
+        FunctionAppStatus::Ready => "ready",
+        FunctionAppStatus::Running => "running",
+        FunctionAppStatus::Error => "in error",
+        FunctionAppStatus::Cancelled => "cancelled",
+    }
+}
+
+/// Formats the build options as the query string sent with the code, leaving out any that aren't set
+fn format_build_query(options: &BuildOptions) -> String {
+    let mut query = vec![format!("strict={}", options.strict)];
+
+    if let Some(template) = &options.template {
+        query.push(format!("template={}", template));
+    }
+
+    if let Some(toolchain) = &options.toolchain {
+        query.push(format!("toolchain={}", toolchain));
+    }
+
+    if let Some(base_image) = &options.base_image {
+        query.push(format!("base_image={}", base_image));
+    }
+
+    query.join("&")
+}
+
+/// Prints what deploying code for a function app would do, without making any changes
+///
+/// If expected is set, the plan is an error if the server would take a different action, such as adding
+/// an app that already exists. This returns false if the deployment would fail.
+pub async fn print_deploy_plan(client: &RustlessClient, name: &String, code_path: &String, namespace: &String, options: &BuildOptions, expected: Option<DeployAction>) -> bool {
+    println!("{}", format!("Plan for function app '{}':", name).bold());
+
+    // Ask the server what it would do
+    let mut plan = match client.deploy_plan(name, namespace).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            println!("  {}", format!("Error getting the plan from the server: {}", e).red().bold());
+            return false;
+        }
+    };
+
+    // Check the local code
+    if let Some(error) = check_code_path(code_path) {
+        plan.errors.push(error);
+    }
+
+    // Check the server would do what the command expects
+    match (expected, plan.action) {
+        (Some(DeployAction::Create), DeployAction::Update) => plan.errors.push(format!("A function app already exists that is named '{}'", name)),
+        (Some(DeployAction::Update), DeployAction::Create) => plan.errors.push(format!("No function app exists that is named '{}'", name)),
+        _ => {}
+    }
+
+    print_plan(client, name, code_path, namespace, options, &plan);
+
+    plan.errors.is_empty()
+}
+
+/// Gets the url of the function apps endpoints on the server, for showing the API calls a command would make
+fn get_function_apps_url(client: &RustlessClient) -> String {
+    format!("https://{}{}/function-apps", client.address(), client.base_path())
+}
+
+/// Prints the local steps, API calls and host changes for a plan, followed by any warnings and errors
+fn print_plan(client: &RustlessClient, name: &String, code_path: &String, namespace: &String, options: &BuildOptions, plan: &DeployPlan) {
+    let base_url = get_function_apps_url(client);
+    let query = format_build_query(options);
+
+    // The steps run on this machine
+    println!("  {}", "Local steps:".blue());
+    println!("    Compile the code in {}", code_path);
+    println!("    Zip the code in {}", code_path);
+
+    // The calls made to the server, and what they would change
+    println!("  {}", "API calls:".blue());
+    match plan.id {
+        Some(id) => {
+            println!("    POST {}/{}/code?{}", base_url, id, query);
+        }
+        None => {
+            println!("    POST {} {{\"name\":\"{}\",\"namespace\":\"{}\"}}", base_url, name, namespace);
+            println!("    POST {}/<new id>/code?{}", base_url, query);
+        }
+    }
+
+    println!("  {}", "Changes on the host:".blue());
+    match (plan.action, plan.id) {
+        (DeployAction::Update, Some(id)) => {
+            let status = plan.status.map(format_status).unwrap_or("unknown");
+            println!("    {}", format!("~ Replace the code for '{}' ({}), which is currently {}", name, id, status).yellow());
+            println!("    {}", format!("~ Rebuild the container image for '{}'", name).yellow());
+        }
+        _ => {
+            println!("    {}", format!("+ Register function app '{}' in namespace '{}'", name, namespace).green());
+            println!("    {}", format!("+ Build a container image for '{}'", name).green());
+        }
+    }
+
+    for warning in plan.warnings.iter() {
+        println!("  {}", format!("Warning: {}", warning).yellow().bold());
+    }
+
+    for error in plan.errors.iter() {
+        println!("  {}", format!("Error: {}", error).red().bold());
+    }
+}
+
+/// Prints what adding a function app would do, without making any changes
+pub async fn plan_add_function_app(conn: &Connection, name: &String, code_path: &String, namespace: &String, options: &BuildOptions) -> Result<(), CliError> {
+    println!("{}", "Dry run - no changes will be made".blue().bold());
+
+    let client = get_client(conn)?;
+    if !print_deploy_plan(&client, name, code_path, namespace, options, Some(DeployAction::Create)).await {
+        return Err(CliError::Failed);
+    }
+
+    Ok(())
+}
+
+/// Prints what updating the code of a function app would do, without making any changes
+pub async fn plan_update_function_app(conn: &Connection, name: &String, code_path: &String, options: &BuildOptions) -> Result<(), CliError> {
+    println!("{}", "Dry run - no changes will be made".blue().bold());
+
+    let client = get_client(conn)?;
+    if !print_deploy_plan(&client, name, code_path, &rustless_shared::default_namespace(), options, Some(DeployAction::Update)).await {
+        return Err(CliError::Failed);
+    }
+
+    Ok(())
+}
+
+/// Gets the ID and status of a function app to plan against, looking it up by name so a stale cached ID isn't used.
+/// Returns an error if the server doesn't have the app
+async fn get_app_status(client: &RustlessClient, name: &String) -> Result<(Uuid, FunctionAppStatus), CliError> {
+    match client.status(&FunctionAppRef::Name(name.to_string())).await {
+        Ok(Some(result)) => Ok((result.id, result.status)),
+        Ok(None) => Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => Err(CliError::Message(format!("Error getting the plan from the server: {}", e))),
+    }
+}
+
+/// Prints the API call and host change for a command on a single function app, followed by the error if the server
+/// would reject it. This returns false if the command would fail
+fn print_app_plan(name: &String, call: &str, change: ColoredString, error: Option<&str>) -> bool {
+    println!("{}", format!("Plan for function app '{}':", name).bold());
+
+    println!("  {}", "API calls:".blue());
+    println!("    {}", call);
+
+    println!("  {}", "Changes on the host:".blue());
+    println!("    {}", change);
+
+    match error {
+        Some(error) => {
+            println!("  {}", format!("Error: {}", error).red().bold());
+            false
+        }
+        None => true,
+    }
+}
+
+/// Prints what starting a function app would do, without making any changes
+pub async fn plan_start_function_app(conn: &Connection, name: &String, with_deps: bool) -> Result<(), CliError> {
+    println!("{}", "Dry run - no changes will be made".blue().bold());
+
+    let client = get_client(conn)?;
+    let (id, status) = get_app_status(&client, name).await?;
+    let call = format!("POST {}/{}/start?with_deps={}", get_function_apps_url(&client), id, with_deps);
+
+    let (change, error) = match status {
+        FunctionAppStatus::Running => (format!("  '{}' ({}) is already running, so nothing would change", name, id).normal(), None),
+        FunctionAppStatus::Ready => {
+            let change = match with_deps {
+                true => format!("+ Start '{}' ({}), after starting the apps it depends on that aren't running", name, id),
+                false => format!("+ Start '{}' ({}) on a new container", name, id),
+            };
+            (change.green(), None)
+        }
+        _ => (format!("  '{}' ({}) is {}", name, id, format_status(status)).normal(), Some("The function app can't be started until it has been built")),
+    };
+
+    match print_app_plan(name, &call, change, error) {
+        true => Ok(()),
+        false => Err(CliError::Failed),
+    }
+}
+
+/// Prints what stopping a function app would do, without making any changes
+pub async fn plan_stop_function_app(conn: &Connection, name: &String) -> Result<(), CliError> {
+    println!("{}", "Dry run - no changes will be made".blue().bold());
+
+    let client = get_client(conn)?;
+    let (id, status) = get_app_status(&client, name).await?;
+    let call = format!("POST {}/{}/stop", get_function_apps_url(&client), id);
+
+    let (change, error) = match status {
+        FunctionAppStatus::Running => (format!("~ Stop '{}' ({}) once the requests in flight finish, or the grace period ends", name, id).yellow(), None),
+        _ => (format!("  '{}' ({}) is {}", name, id, format_status(status)).normal(), Some("The function app is not running")),
+    };
+
+    match print_app_plan(name, &call, change, error) {
+        true => Ok(()),
+        false => Err(CliError::Failed),
+    }
+}
+
+/// Prints what restarting a function app would do, without making any changes
+pub async fn plan_restart_function_app(conn: &Connection, name: &String) -> Result<(), CliError> {
+    println!("{}", "Dry run - no changes will be made".blue().bold());
+
+    let client = get_client(conn)?;
+    let (id, status) = get_app_status(&client, name).await?;
+    let call = format!("POST {}/{}/restart", get_function_apps_url(&client), id);
+
+    let (change, error) = match status {
+        FunctionAppStatus::Running => (format!("~ Move '{}' ({}) to a new container, stopping the old one once the new one is healthy", name, id).yellow(), None),
+        _ => (format!("  '{}' ({}) is {}", name, id, format_status(status)).normal(), Some("The function app is not running")),
+    };
+
+    match print_app_plan(name, &call, change, error) {
+        true => Ok(()),
+        false => Err(CliError::Failed),
+    }
+}
+
+/// Prints what deleting a function app would do, without making any changes. No confirmation is asked for, as
+/// nothing is deleted
+pub async fn plan_delete_function_app(conn: &Connection, name: &String) -> Result<(), CliError> {
+    println!("{}", "Dry run - no changes will be made".blue().bold());
+
+    let client = get_client(conn)?;
+    let (id, status) = get_app_status(&client, name).await?;
+    let call = format!("DELETE {}/{}", get_function_apps_url(&client), id);
+
+    let change = match status {
+        FunctionAppStatus::Running => format!("- Stop '{}' ({}), then remove its image and everything stored for it", name, id),
+        _ => format!("- Remove '{}' ({}), which is {}, along with its image and everything stored for it", name, id, format_status(status)),
+    };
+
+    print_app_plan(name, &call, change.red(), None);
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Local, TimeZone};
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::EgressReport;
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// Formats when a destination was last called in the local timezone
+fn format_last_seen(timestamp: u64) -> String {
+    let last_seen: Option<DateTime<Local>> = Local.timestamp_opt(timestamp as i64, 0).single();
+    match last_seen {
+        Some(last_seen) => last_seen.format("%d-%m-%Y %H:%M:%S").to_string(),
+        None => format!("{} (Unix time)", timestamp),
+    }
+}
+
+/// Prints the egress allowlist for a function app and the destinations it has called
+fn print_egress(name: &String, report: &EgressReport) {
+    if !report.proxy_enabled {
+        println!("{}", "The egress proxy is not running on the server, so the allowlist is not enforced and calls are not recorded".yellow());
+    }
+
+    match &report.allowlist {
+        Some(allowlist) if allowlist.is_empty() => println!("{}", format!("'{}' can't call any destinations", name).blue()),
+        Some(allowlist) => println!("{}", format!("'{}' can only call: {}", name, allowlist.join(", ")).blue()),
+        None => println!("{}", format!("'{}' can call any destination", name).blue()),
+    }
+
+    if report.destinations.is_empty() {
+        println!("No destinations called since the server started");
+        return;
+    }
+
+    println!("Destinations called since the server started:");
+    for destination in report.destinations.iter() {
+        let line = format!(
+            "  {}:{}  allowed {}  denied {}  sent {} bytes  received {} bytes  last {}",
+            destination.host,
+            destination.port,
+            destination.allowed,
+            destination.denied,
+            destination.bytes_sent,
+            destination.bytes_received,
+            format_last_seen(destination.last_seen)
+        );
+
+        match destination.denied {
+            0 => println!("{}", line),
+            _ => println!("{}", line.red()),
+        }
+    }
+}
+
+/// Restricts the destinations a function app can call to the allowlist, or lifts the restriction if the allowlist
+/// is None, retrying by name if the cached ID is stale
+pub async fn set_egress(conn: &Connection, name: &String, allowlist: &Option<Vec<String>>) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.set_egress(&app, allowlist).await }).await;
+
+    match result {
+        Ok(true) => match allowlist {
+            Some(allowlist) if allowlist.is_empty() => println!("{}", format!("✅ '{}' can't call any destinations", name).green()),
+            Some(allowlist) => println!("{}", format!("✅ '{}' can only call: {}", name, allowlist.join(", ")).green()),
+            None => println!("{}", format!("✅ '{}' can call any destination, unless its namespace has a default allowlist", name).green()),
+        },
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error setting egress allowlist: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the egress allowlist for a function app and the destinations it has called through the egress proxy
+pub async fn show_egress(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.egress(&app).await }).await;
+
+    match result {
+        Ok(Some(report)) => print_egress(name, &report),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting egress details: {}", e))),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use colored::Colorize;
+use thiserror::Error;
+
+/// The exit code for errors running a command, such as the server not being found
+const ERROR_EXIT_CODE: i32 = -1;
+
+/// The exit code for command lines that can't be run, the same as clap uses for invalid arguments
+const USAGE_EXIT_CODE: i32 = 2;
+
+/// An error that stops a command. Commands return these to main, which shows them and exits, so the CLI exits in
+/// one place and anything a command holds is cleaned up first
+#[derive(Debug, Error)]
+pub enum CliError {
+    /// No server has been set with the set-server command
+    #[error("No server set. Use the 'set-server' command to set the server.")]
+    NoServer,
+
+    /// The server doesn't have a function app with the given name
+    #[error("No function app with the name '{0}' exists")]
+    AppNotFound(String),
+
+    /// The command line can't be run, such as options that can't be used together
+    #[error("{0}")]
+    Usage(String),
+
+    /// Any other error, with the message to show
+    #[error("{0}")]
+    Message(String),
+
+    /// The command failed and has already shown why, such as a build that printed the compiler errors
+    #[error("The command failed")]
+    Failed,
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        CliError::Message(message)
+    }
+}
+
+impl CliError {
+    /// Gets the code the CLI exits with for the error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => USAGE_EXIT_CODE,
+            _ => ERROR_EXIT_CODE,
+        }
+    }
+
+    /// Shows the error, unless the command has already shown why it failed, and exits with the exit code
+    pub fn exit(self) -> ! {
+        if !matches!(self, CliError::Failed) {
+            println!("{}", self.to_string().red().bold());
+        }
+
+        std::process::exit(self.exit_code());
+    }
+}
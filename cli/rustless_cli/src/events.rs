@@ -0,0 +1,66 @@
+use chrono::{DateTime, Local, TimeZone};
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::{EventsOptions, HostEvent, CONTAINER_CRASHED_EVENT, DEPLOY_PROGRESS_EVENT, QUOTA_WARNING_EVENT, STATUS_CHANGED_EVENT};
+
+use crate::error::CliError;
+use crate::server;
+
+/// Formats when an event happened in the local timezone
+fn format_timestamp(timestamp: u64) -> String {
+    let time: Option<DateTime<Local>> = Local.timestamp_opt(timestamp as i64, 0).single();
+    match time {
+        Some(time) => time.format("%d-%m-%Y %H:%M:%S").to_string(),
+        None => format!("{} (Unix time)", timestamp),
+    }
+}
+
+/// Prints an event from the server on a single line
+fn print_event(event: HostEvent) {
+    let app = format!("{} [{}]", event.app, event.namespace);
+
+    let line = match event.event.as_str() {
+        STATUS_CHANGED_EVENT => match event.status {
+            Some(status) => format!("{}  {}  status is now {:?}", format_timestamp(event.timestamp), app, status).blue(),
+            None => format!("{}  {}  status changed", format_timestamp(event.timestamp), app).blue(),
+        },
+        DEPLOY_PROGRESS_EVENT => {
+            let mut line = format!("{}  {}  deployment {}", format_timestamp(event.timestamp), app, event.stage.unwrap_or_default());
+            if let Some(deployment) = event.deployment {
+                line = format!("{} (deployment {})", line, deployment);
+            }
+            if let Some(message) = event.message {
+                line = format!("{}: {}", line, message);
+            }
+            line.normal()
+        },
+        CONTAINER_CRASHED_EVENT => match (event.message, event.exit_code) {
+            (Some(reason), _) => format!("{}  {}  crashed: {}", format_timestamp(event.timestamp), app, reason).red(),
+            (None, Some(exit_code)) => format!("{}  {}  crashed with exit code {}", format_timestamp(event.timestamp), app, exit_code).red(),
+            (None, None) => format!("{}  {}  crashed", format_timestamp(event.timestamp), app).red(),
+        },
+        QUOTA_WARNING_EVENT => format!("{}  {}  {}", format_timestamp(event.timestamp), app, event.message.unwrap_or_default()).yellow(),
+        other => format!("{}  {}  {}", format_timestamp(event.timestamp), app, other).normal(),
+    };
+
+    println!("{}", line);
+}
+
+/// Shows events from the server as they happen, optionally only for one function app or namespace
+pub async fn watch_events(conn: &Connection, app: &Option<String>, namespace: &Option<String>) -> Result<(), CliError> {
+    let options = EventsOptions {
+        app: app.clone(),
+        namespace: namespace.clone(),
+    };
+
+    println!("{}", "Watching for events. Press Ctrl+C to stop".blue());
+
+    let client = server::get_server_client(conn)?;
+    match client.watch_events(&options, print_event).await {
+        Ok(_) => println!("{}", "The server closed the event stream".yellow()),
+        Err(e) => return Err(CliError::Message(format!("Error watching events: {}", e))),
+    }
+
+    Ok(())
+}
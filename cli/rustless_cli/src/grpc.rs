@@ -0,0 +1,97 @@
+use chrono::{DateTime, Local, TimeZone};
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::GrpcReport;
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// Formats when a method was last called in the local timezone
+fn format_last_called(timestamp: u64) -> String {
+    let last_called: Option<DateTime<Local>> = Local.timestamp_opt(timestamp as i64, 0).single();
+    match last_called {
+        Some(last_called) => last_called.format("%d-%m-%Y %H:%M:%S").to_string(),
+        None => format!("{} (Unix time)", timestamp),
+    }
+}
+
+/// Prints the gRPC services a function app serves and the metrics for each method called
+fn print_grpc(name: &String, report: &GrpcReport) {
+    if !report.gateway_enabled {
+        println!("{}", "The gRPC gateway is not running on the server, so gRPC calls can't reach function apps".yellow());
+    }
+
+    match report.services.is_empty() {
+        true => println!("{}", format!("'{}' doesn't serve any gRPC services", name).blue()),
+        false => println!("{}", format!("'{}' serves: {}", name, report.services.join(", ")).blue()),
+    }
+
+    if report.methods.is_empty() {
+        println!("No methods called since the server started");
+        return;
+    }
+
+    println!("Methods called since the server started:");
+    for method in report.methods.iter() {
+        let finished = method.calls - method.in_flight;
+        let average = match finished {
+            0 => 0,
+            _ => method.total_duration_ms / finished,
+        };
+
+        let status_codes: Vec<String> = method.status_codes.iter().map(|(code, count)| format!("{}={}", code, count)).collect();
+
+        let line = format!(
+            "  {}  calls {}  in flight {}  errors {}  statuses [{}]  avg {}ms  max {}ms  received {} bytes  sent {} bytes  last {}",
+            method.method,
+            method.calls,
+            method.in_flight,
+            method.errors,
+            status_codes.join(", "),
+            average,
+            method.max_duration_ms,
+            method.bytes_received,
+            method.bytes_sent,
+            format_last_called(method.last_called)
+        );
+
+        match method.errors {
+            0 => println!("{}", line),
+            _ => println!("{}", line.red()),
+        }
+    }
+}
+
+/// Sets the gRPC services a function app serves, or stops it serving any if the list is empty, retrying by name
+/// if the cached ID is stale
+pub async fn set_grpc_services(conn: &Connection, name: &String, services: &[String]) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.set_grpc_services(&app, services).await }).await;
+
+    match result {
+        Ok(true) => match services.is_empty() {
+            true => println!("{}", format!("✅ '{}' doesn't serve any gRPC services", name).green()),
+            false => println!("{}", format!("✅ '{}' serves: {}", name, services.join(", ")).green()),
+        },
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error setting gRPC services: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the gRPC services a function app serves and the metrics for each method called through the gRPC gateway
+pub async fn show_grpc(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.grpc(&app).await }).await;
+
+    match result {
+        Ok(Some(report)) => print_grpc(name, &report),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting gRPC details: {}", e))),
+    }
+
+    Ok(())
+}
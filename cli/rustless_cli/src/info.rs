@@ -0,0 +1,242 @@
+use std::time::{Duration, SystemTime};
+
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_client::RustlessClient;
+use rustless_shared::{FunctionAppInfo, FunctionAppStatus, SettingSource};
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server::{self, FunctionAppRef};
+use crate::top;
+
+/// Shown in place of the values of the settings made on an app, as they can hold credentials
+const MASKED_VALUE: &str = "********";
+
+/// The most lines of a failed build that are shown. The whole output is in the build logs
+const BUILD_ERROR_LINES: usize = 10;
+
+/// Formats a Unix timestamp as a local date and time
+fn format_timestamp(timestamp: u64) -> String {
+    cli::format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp))
+}
+
+/// Gets the details of a function app, retrying by name if the cached ID is stale
+async fn get_info(conn: &Connection, client: &RustlessClient, name: &String) -> Result<FunctionAppInfo, CliError> {
+    let result = cli::with_app_ref(conn, name, |app| async move { client.info(&app).await }).await;
+
+    match result {
+        Ok(Some(info)) => Ok(info),
+        Ok(None) => Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => Err(CliError::Message(format!("Error getting function app details: {}", e))),
+    }
+}
+
+/// Prints a heading for a section of the details
+fn print_heading(heading: &str) {
+    println!();
+    println!("{}", heading.blue().bold());
+}
+
+/// Prints the status of a function app and why it is in error, with the end of the build output if the build failed
+fn print_status(name: &String, info: &FunctionAppInfo) {
+    cli::print_status(name, &info.status);
+    println!("Namespace: {}", info.namespace);
+
+    if let Some(build_error) = &info.status.build_error {
+        let lines: Vec<&str> = build_error.trim_end().lines().collect();
+        println!("{}", "The latest build failed:".red());
+        for line in lines.iter().skip(lines.len().saturating_sub(BUILD_ERROR_LINES)) {
+            println!("  {}", line);
+        }
+    }
+}
+
+/// Prints the URL the gateway serves a function app on, and the routes it handles if it is running
+async fn print_routes(client: &RustlessClient, name: &String, info: &FunctionAppInfo) {
+    print_heading("Routes");
+
+    println!("Gateway URL: https://{}{}/api/{}/", client.address(), client.base_path(), name);
+
+    if !matches!(info.status.status, FunctionAppStatus::Running) {
+        println!("The app isn't running, so its routes can't be listed");
+        return;
+    }
+
+    match client.routes(&FunctionAppRef::Id(info.status.id)).await {
+        Ok(Some(manifest)) if manifest.routes.is_empty() => println!("No routes"),
+        Ok(Some(manifest)) => {
+            for route in manifest.routes.iter() {
+                println!("  {:7} /api/{}{}", route.method.bold(), name, route.path);
+            }
+        },
+        Ok(None) => println!("The app doesn't serve a route manifest. Build it with rustless_app to list its routes"),
+        Err(e) => println!("{}", format!("Error getting routes: {}", e).yellow()),
+    }
+}
+
+/// Prints the names of the settings made on a function app, with the values masked
+fn print_config(info: &FunctionAppInfo) {
+    print_heading("Config");
+
+    if info.config.is_empty() {
+        println!("No settings have been made on the app");
+        return;
+    }
+
+    for key in info.config.iter() {
+        println!("  {} = {}", key, MASKED_VALUE);
+    }
+}
+
+/// Prints the limits that apply to a function app and the containers running it
+fn print_limits(info: &FunctionAppInfo) {
+    print_heading("Limits");
+
+    let limits = &info.limits;
+    println!("Request bodies buffered up to {}, responses up to {}", top::format_bytes(limits.request_buffer_threshold), top::format_bytes(limits.response_buffer_threshold));
+    match limits.memory_limit_bytes {
+        Some(memory_limit) => println!("Each container can use {} of memory", top::format_bytes(memory_limit)),
+        None => println!("Memory isn't limited"),
+    }
+    println!("Replicas: {} (up to {})", info.replicas.replicas, limits.max_replicas);
+
+    for replica in info.replicas.running.iter() {
+        match replica.healthy {
+            true => println!("  port {}  healthy", replica.port),
+            false => println!("  port {}  {}", replica.port, "starting or unhealthy".yellow()),
+        }
+    }
+}
+
+/// Describes where the value a function app uses for a setting comes from
+fn format_source(source: SettingSource) -> &'static str {
+    match source {
+        SettingSource::App => "set on the app",
+        SettingSource::Namespace => "namespace default",
+        SettingSource::Host => "server default",
+    }
+}
+
+/// Prints the settings a function app uses, with whether each is set on the app or comes from the defaults for its
+/// namespace or the server
+async fn print_effective_config(client: &RustlessClient, info: &FunctionAppInfo) {
+    print_heading("Effective config");
+
+    let config = match client.effective_config(&FunctionAppRef::Id(info.status.id)).await {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("The server doesn't report the effective config");
+            return;
+        },
+        Err(e) => {
+            println!("{}", format!("Error getting effective config: {}", e).yellow());
+            return;
+        }
+    };
+
+    let setting = &config.buffer_request_threshold;
+    println!("  buffer_request_threshold = {}  ({})", top::format_bytes(setting.value), format_source(setting.source));
+    let setting = &config.buffer_response_threshold;
+    println!("  buffer_response_threshold = {}  ({})", top::format_bytes(setting.value), format_source(setting.source));
+
+    let setting = &config.egress_allowlist;
+    let allowlist = match &setting.value {
+        Some(allowlist) if allowlist.is_empty() => "no destinations".to_string(),
+        Some(allowlist) => allowlist.join(", "),
+        None => "any destination".to_string(),
+    };
+    println!("  egress_allowlist = {}  ({})", allowlist, format_source(setting.source));
+
+    let setting = &config.max_replicas;
+    println!("  max_replicas = {}  ({})", setting.value, format_source(setting.source));
+}
+
+/// Prints the most recent deployments of a function app
+fn print_deployments(info: &FunctionAppInfo) {
+    print_heading("Latest deployments");
+
+    if info.deployments.is_empty() {
+        println!("The app hasn't been deployed");
+        return;
+    }
+
+    for deployment in info.deployments.iter() {
+        let approval = match (deployment.approved, deployment.approved_at) {
+            (true, Some(approved_at)) => format!("approved {}", format_timestamp(approved_at)),
+            (true, None) => "approved".to_string(),
+            (false, _) => "waiting for approval".yellow().to_string(),
+        };
+        let signed = if deployment.signed_at.is_some() { ", signed" } else { "" };
+
+        println!("  #{}  {}  {}{}", deployment.number, format_timestamp(deployment.created_at), approval, signed);
+
+        if let Some(promotion) = &deployment.promoted_from {
+            println!("      promoted from {} deployment #{} {}", promotion.host, promotion.deployment, format_timestamp(promotion.promoted_at));
+        }
+        for promotion in deployment.promoted_to.iter() {
+            println!("      promoted to {} as deployment #{} {}", promotion.host, promotion.deployment, format_timestamp(promotion.promoted_at));
+        }
+    }
+}
+
+/// Prints the timer trigger and scale profiles for a function app
+fn print_schedules(info: &FunctionAppInfo) {
+    print_heading("Schedules");
+
+    match &info.timer_trigger {
+        Some(trigger) => {
+            println!("Timer trigger: POST {} on '{}' (UTC)", trigger.route, trigger.schedule);
+            if let Some(next_run) = info.next_timer_run {
+                println!("  Next fires at {}", format_timestamp(next_run));
+            }
+        },
+        None => println!("No timer trigger"),
+    }
+
+    let report = &info.scale_profiles;
+    if report.profiles.is_empty() {
+        println!("No scale profiles");
+        return;
+    }
+
+    println!("Scale profiles (UTC):");
+    for profile in report.profiles.iter() {
+        println!("  {}  {} replicas", profile.schedule, profile.replicas);
+    }
+
+    if let (Some(change_at), Some(replicas)) = (report.next_change_at, report.next_replicas) {
+        println!("  Scales to {} replicas at {}", replicas, format_timestamp(change_at));
+    }
+}
+
+/// Shows the README from the code for the latest deployment of a function app, which describes how to call it
+async fn show_readme(client: &RustlessClient, name: &String, info: &FunctionAppInfo) {
+    print_heading("README");
+
+    match client.readme(&FunctionAppRef::Id(info.status.id)).await {
+        Ok(Some(Some(readme))) => println!("{}", readme.trim_end()),
+        Ok(Some(None)) => println!("{}", format!("'{}' has no README. Add a README.md next to its Cargo.toml to describe how to call it", name).yellow()),
+        Ok(None) => println!("{}", format!("No function app with the name '{}' exists", name).yellow()),
+        Err(e) => println!("{}", format!("Error getting README: {}", e).yellow()),
+    }
+}
+
+/// Shows everything about a function app in one view: its status and why it is in error, the gateway URL and routes,
+/// the settings made on it, its limits and replicas, the settings it uses with where they come from, the latest
+/// deployments, its schedules, and the README
+pub async fn show_info(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    let info = get_info(conn, &client, name).await?;
+
+    print_status(name, &info);
+    print_routes(&client, name, &info).await;
+    print_config(&info);
+    print_limits(&info);
+    print_effective_config(&client, &info).await;
+    print_deployments(&info);
+    print_schedules(&info);
+    show_readme(&client, name, &info).await;
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::Path;
+
+use colored::Colorize;
+use rusqlite::Connection;
+
+use crate::error::CliError;
+use crate::server::{self, InvokeResponse};
+
+/// Parses a header given on the command line as NAME=VALUE, such as "Accept=application/json". The header is split
+/// at the first =, so the value can contain any characters
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    match header.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("Invalid header '{}'. Headers are set as NAME=VALUE, such as \"Accept=application/json\"", header)),
+    }
+}
+
+/// Prints the status, headers, and body of a response, with the status colored by whether the request succeeded
+fn print_response(response: &InvokeResponse) {
+    let status = format!("HTTP {}", response.status);
+    match response.status {
+        200..=299 => println!("{}", status.green().bold()),
+        300..=499 => println!("{}", status.yellow().bold()),
+        _ => println!("{}", status.red().bold()),
+    }
+
+    for (name, value) in response.headers.iter() {
+        println!("{}: {}", name.blue(), value);
+    }
+
+    if response.body.is_empty() {
+        return;
+    }
+
+    println!();
+    match std::str::from_utf8(&response.body) {
+        Ok(body) => println!("{}", body.trim_end()),
+        Err(_) => println!("{}", format!("<{} bytes of binary data>", response.body.len()).blue()),
+    }
+}
+
+/// Calls a route of a function app through the server, printing the response status, headers, and body. The body
+/// is read from a file if one is given, and sent as JSON if the file is a .json file and no content type is set
+pub async fn invoke(conn: &Connection, name: &String, route: &str, method: &str, body: &Option<String>, headers: &[String]) -> Result<(), CliError> {
+    let mut parsed_headers = Vec::new();
+    for header in headers.iter() {
+        match parse_header(header) {
+            Ok(header) => parsed_headers.push(header),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let body = match body {
+        Some(path) => match fs::read(path) {
+            Ok(body) => {
+                let has_content_type = parsed_headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type"));
+                let is_json = Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+                if is_json && !has_content_type {
+                    parsed_headers.push(("Content-Type".to_string(), "application/json".to_string()));
+                }
+                body
+            },
+            Err(e) => return Err(CliError::Message(format!("Error reading body from {}: {}", path, e))),
+        },
+        None => Vec::new(),
+    };
+
+    let client = server::get_server_client(conn)?;
+    match client.invoke(name, route, method, &parsed_headers, body).await {
+        Ok(response) => print_response(&response),
+        Err(e) => return Err(CliError::Message(format!("Error calling function app: {}", e))),
+    }
+
+    Ok(())
+}
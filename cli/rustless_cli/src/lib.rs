@@ -0,0 +1,202 @@
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+
+use rusqlite::Connection;
+use uuid::Uuid;
+
+use rustless_client::RustlessClient;
+use rustless_shared::{BuildOptions, DeployAction, FunctionApp, FunctionAppStatusResult};
+
+use server::{FunctionAppRef, UploadResult};
+
+pub mod code;
+mod migrations;
+pub mod server;
+pub mod storage;
+
+// The CLI logic, so build scripts and other tools can drive deployments without running the CLI.
+// Nothing here prints or exits - errors are returned for the caller to show. The functions use the server
+// set with the CLI, stored in the database opened with storage::create_connection. Tools that manage their own
+// servers can use the rustless_client crate directly
+
+/// A stage of deploying a function app, reported as the deployment reaches it so callers can show progress
+#[derive(Clone, Copy, Debug)]
+pub enum DeployStage {
+    /// Compiling - the code is compiled locally to check it is valid before it is sent
+    Compiling,
+
+    /// Zipping - the code is zipped to send to the server
+    Zipping,
+
+    /// Checking - the server is checked to see if the function app already exists
+    Checking,
+
+    /// Registering - the function app doesn't exist, so it is being registered
+    Registering,
+
+    /// Building - the code is being uploaded and built on the server. The build can be cancelled with the app ID
+    Building(Uuid),
+}
+
+/// The result of deploying a function app
+#[derive(Debug)]
+pub struct DeployOutcome {
+    // The ID of the function app
+    pub id: Uuid,
+
+    // Whether the function app was created or updated
+    pub action: DeployAction,
+
+    // The number of the deployment waiting for approval, if the server requires deployments to be approved
+    pub pending_deployment: Option<u32>,
+
+    // The number of the latest deployment if the code matched it, so nothing was built
+    pub unchanged_deployment: Option<u32>,
+}
+
+/// Gets the reference to use for a function app, using the cached ID if there is one, otherwise the name
+pub fn get_function_app_ref(conn: &Connection, name: &String) -> FunctionAppRef {
+    match storage::get_function_app_id(conn, name) {
+        Ok(id) => FunctionAppRef::Id(id),
+        Err(_) => FunctionAppRef::Name(name.to_string()),
+    }
+}
+
+/// A result from a call to the server for a function app, which can say the server doesn't have the app
+pub trait AppResult {
+    /// Gets if the server doesn't have the function app
+    fn is_app_not_found(&self) -> bool;
+}
+
+/// Calls that return false if the server doesn't have the function app
+impl<E> AppResult for Result<bool, E> {
+    fn is_app_not_found(&self) -> bool {
+        matches!(self, Ok(false))
+    }
+}
+
+/// Calls that return None if the server doesn't have the function app
+impl<T, E> AppResult for Result<Option<T>, E> {
+    fn is_app_not_found(&self) -> bool {
+        matches!(self, Ok(None))
+    }
+}
+
+/// Makes a call to the server for a function app, using the cached ID if there is one, otherwise the name
+///
+/// The cached ID can be stale if the app was deleted and recreated, so if the server doesn't have the app the ID is
+/// forgotten and the call is made again by name
+pub async fn with_app_ref<T, F, Fut>(conn: &Connection, name: &String, call: F) -> T
+where
+    T: AppResult,
+    F: Fn(FunctionAppRef) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let app = get_function_app_ref(conn, name);
+    let by_id = matches!(app, FunctionAppRef::Id(_));
+    let result = call(app).await;
+
+    if by_id && result.is_app_not_found() {
+        let _ = storage::remove_function_app_id(conn, name);
+        return call(FunctionAppRef::Name(name.to_string())).await;
+    }
+
+    result
+}
+
+/// Gets all the function apps from the current server
+pub async fn list_function_apps(conn: &Connection) -> Result<Vec<FunctionApp>, String> {
+    server::get_server_client(conn)?.list().await
+}
+
+/// Gets the status of a function app, or None if the server doesn't have it
+///
+/// The cached ID is used if there is one, and the ID is cached so later calls can skip the lookup by name
+pub async fn get_function_app_status(conn: &Connection, name: &String) -> Result<Option<FunctionAppStatusResult>, String> {
+    let client = &server::get_server_client(conn)?;
+    let result = with_app_ref(conn, name, |app| async move { client.status(&app).await }).await?;
+
+    if let Some(result) = &result {
+        let _ = storage::set_function_app_id(conn, name, &result.id);
+    }
+
+    Ok(result)
+}
+
+/// Registers the function app if it doesn't exist, then uploads the zipped code and waits for the build, returning the
+/// ID of the app, whether it was created or updated, and what the server did with the code
+async fn upload_function_app(
+    conn: &Connection,
+    client: &RustlessClient,
+    name: &String,
+    namespace: &String,
+    zip_file: &Path,
+    options: &BuildOptions,
+    on_stage: &impl Fn(DeployStage),
+) -> Result<(Uuid, DeployAction, UploadResult), String> {
+    // Check if the app exists, and if not register it
+    on_stage(DeployStage::Checking);
+    let existing = client.status(&FunctionAppRef::Name(name.to_string())).await?;
+
+    let (id, action) = match existing {
+        Some(status) => (status.id, DeployAction::Update),
+        None => {
+            on_stage(DeployStage::Registering);
+            (client.create_app(name, namespace).await?, DeployAction::Create)
+        }
+    };
+    let _ = storage::set_function_app_id(conn, name, &id);
+
+    // Upload the code and wait for the build
+    on_stage(DeployStage::Building(id));
+    let uploaded = client.upload_code(&FunctionAppRef::Id(id), zip_file, options).await?;
+
+    Ok((id, action, uploaded))
+}
+
+/// Deploys a function app, creating it in the given namespace if it doesn't exist or updating the code if it does
+///
+/// The code is compiled locally first so invalid code is never sent. Each stage is passed to on_stage as it is
+/// reached. If the build fails, the error is the build output from the server. If the code matches the latest
+/// deployment the server skips the build, unless options.force is set
+pub async fn deploy_function_app(
+    conn: &Connection,
+    name: &String,
+    code_path: &str,
+    namespace: &String,
+    options: &BuildOptions,
+    on_stage: impl Fn(DeployStage),
+) -> Result<DeployOutcome, String> {
+    let client = server::get_server_client(conn)?;
+
+    // Compile the code to ensure it is valid before we start
+    on_stage(DeployStage::Compiling);
+    let path = code_path.to_string();
+    match tokio::task::spawn_blocking(move || code::try_compile_code(&path)).await {
+        Ok(result) => result?,
+        Err(e) => return Err(format!("Error compiling the function app code: {}", e)),
+    };
+
+    // Zip the code. Each deployment gets its own zip file so apps in the same folder don't overwrite each other
+    on_stage(DeployStage::Zipping);
+    let zip_file = std::env::temp_dir().join(format!("rustless-{}.zip", Uuid::new_v4()));
+    let path = code_path.to_string();
+    let zip_path = zip_file.clone();
+    match tokio::task::spawn_blocking(move || code::zip_function_app_code_to(&path, &zip_path)).await {
+        Ok(result) => result?,
+        Err(e) => return Err(format!("Error zipping the code: {}", e)),
+    };
+
+    // The zip file is streamed to the server, so it is only deleted once the upload has finished or failed
+    let uploaded = upload_function_app(conn, &client, name, namespace, &zip_file, options, &on_stage).await;
+    let _ = fs::remove_file(&zip_file);
+    let (id, action, uploaded) = uploaded?;
+
+    Ok(DeployOutcome {
+        id,
+        action,
+        pending_deployment: uploaded.pending_deployment,
+        unchanged_deployment: uploaded.unchanged_deployment,
+    })
+}
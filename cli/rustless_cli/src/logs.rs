@@ -0,0 +1,46 @@
+use colored::Colorize;
+use rusqlite::Connection;
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// Shows the most recent output from the container for a function app
+async fn show_recent_logs(conn: &Connection, name: &String, tail: usize) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.logs(&app, tail).await }).await;
+
+    match result {
+        Ok(Some(logs)) if logs.trim().is_empty() => println!("{}", format!("'{}' hasn't written any output", name).yellow()),
+        Ok(Some(logs)) => println!("{}", logs.trim_end()),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting logs: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Follows the output from the container for a function app, printing the most recent lines and then each line as
+/// the app writes it until the container exits or this is stopped with Ctrl+C
+async fn follow_logs(conn: &Connection, name: &String, tail: usize) -> Result<(), CliError> {
+    let print_line = |line: &str| println!("{}", line);
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.follow_logs(&app, tail, print_line).await }).await;
+
+    match result {
+        Ok(Some(())) => println!("{}", format!("'{}' has stopped", name).yellow()),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error following logs: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the most recent output from the container for a function app, optionally following it as the app writes
+/// more
+pub async fn show_logs(conn: &Connection, name: &String, tail: usize, follow: bool) -> Result<(), CliError> {
+    match follow {
+        true => follow_logs(conn, name, tail).await,
+        false => show_recent_logs(conn, name, tail).await,
+    }
+}
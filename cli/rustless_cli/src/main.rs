@@ -1,129 +1,1234 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
+use uuid::Uuid;
 
+use rustless_cli::{code, server, storage};
+use rustless_shared::{default_log_lines, BuildOptions, NamespaceDefaults, RedactionRules};
+
+use error::CliError;
+use output::OutputArgs;
+
+mod analytics;
+mod buffering;
+mod build_logs;
+mod cancel;
 mod cli;
-mod code;
-mod server;
-mod storage;
+mod crates_cache;
+mod dependencies;
+mod deploy;
+mod diagnostics;
+mod dry_run;
+mod egress;
+mod error;
+mod events;
+mod grpc;
+mod info;
+mod invoke;
+mod logs;
+mod namespace_defaults;
+mod output;
+mod overview;
+mod promote;
+mod redaction;
+mod replay;
+mod scale;
+mod self_update;
+mod server_info;
+mod top;
+mod triggers;
+mod version;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
-#[command(propagate_version = true)]
+#[command(disable_version_flag = true)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+
+    /// Print the version. Add --verbose to also show the build details and the version of the server
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// Show more details. Used with --version
+    #[arg(long, global = true)]
+    verbose: bool,
+
+    /// Run the command against every server profile at once. Only supported by read-only commands such as list and status
+    #[arg(long, global = true)]
+    all_servers: bool,
+
+    /// Show the API calls that would be made and what would change on the host, without making any changes.
+    /// Supported by add-function-app, update-function-app, deploy, start, stop, restart and delete. The other commands
+    /// that make changes set a single value that is given in full on the command line, so there is nothing more to show
+    #[arg(long, global = true)]
+    dry_run: bool,
+}
+
+impl Cli {
+    /// Gets if the command writes CSV or markdown, which would be broken by the header
+    fn is_machine_readable(&self) -> bool {
+        match &self.command {
+            Some(Commands::List { output }) => !output.is_table(),
+            Some(Commands::Analytics { output, .. }) => !output.is_table(),
+            Some(Commands::Trigger(TriggerCommands::History { output, .. })) => !output.is_table(),
+            _ => false,
+        }
+    }
+}
+
+/// The options for building a function app on the server
+#[derive(Args)]
+struct BuildArgs {
+    /// Fail the build on the server if there are any compiler or clippy warnings
+    #[arg(long)]
+    strict: bool,
+
+    /// The Dockerfile template to build with, such as rust or rust-slim. Uses the server default if not set
+    #[arg(long)]
+    template: Option<String>,
+
+    /// The Rust toolchain to build with, such as stable, beta or 1.75. Uses the template default if not set
+    #[arg(long)]
+    toolchain: Option<String>,
+
+    /// The base image to build on. Uses the template default if not set
+    #[arg(long)]
+    base_image: Option<String>,
+
+    /// Build the code even if it hasn't changed since the last deployment
+    #[arg(long)]
+    force: bool,
+}
+
+impl BuildArgs {
+    /// Converts the arguments to the build options sent to the server
+    fn to_options(&self) -> BuildOptions {
+        BuildOptions {
+            strict: self.strict,
+            template: self.template.clone(),
+            toolchain: self.toolchain.clone(),
+            base_image: self.base_image.clone(),
+            force: self.force,
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Adds a function app to the rustless host
-    AddFunctionApp { name: String, code_path: String },
+    AddFunctionApp {
+        name: String,
+
+        /// The folder with the crate for the function app. Files ignored by .gitignore or .rustlessignore files, the
+        /// target folder, and .git aren't uploaded
+        code_path: String,
+
+        /// The namespace to add the function app to
+        #[arg(long, default_value_t = rustless_shared::default_namespace())]
+        namespace: String,
+
+        #[command(flatten)]
+        build: BuildArgs,
+    },
 
     /// Updates the code of a function app
-    UpdateFunctionApp { name: String, code_path: String },
+    UpdateFunctionApp {
+        name: String,
+
+        /// The folder with the crate for the function app. Files ignored by .gitignore or .rustlessignore files, the
+        /// target folder, and .git aren't uploaded
+        code_path: String,
 
-    /// Sets the server to use when running commands
+        #[command(flatten)]
+        build: BuildArgs,
+    },
+
+    /// Deploys all the function apps listed in a YAML file, creating any that don't exist and updating the rest
+    Deploy {
+        /// The YAML file listing the apps to deploy
+        #[arg(short = 'f', long = "file")]
+        file: String,
+
+        /// The maximum number of apps to deploy at the same time
+        #[arg(long, default_value_t = 4)]
+        parallel: usize,
+
+        /// Build every app even if its code hasn't changed since its last deployment
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Sets the server to use when running commands. IPv6 addresses are written in brackets, such as [::1] or
+    /// [::1]:8080. A server behind a reverse proxy under a path is written with the path, such as example.com/rustless
     SetServer {
         hostname: String,
 
-        #[arg(default_value_t = 80)]
-        port: u16,
+        /// The port of the server. Defaults to 80, or the port written after an IPv6 address in brackets
+        port: Option<u16>,
+
+        /// The path the server is served under behind a reverse proxy, such as /rustless for
+        /// https://example.com/rustless/
+        #[arg(long)]
+        base_path: Option<String>,
     },
 
     /// Shows the current server
     ShowServer,
 
+    /// Logs in to the current server with an API key, which is sent with every request to it. Create keys on the
+    /// host with rustless-hostctl keys create. A key from the host's key file or a token from its identity provider
+    /// can be used in the same way
+    Login {
+        /// The API key or token. If it isn't given, it is asked for so it isn't kept in the shell history
+        #[arg(long)]
+        key: Option<String>,
+    },
+
+    /// Logs out of the current server, forgetting its API key
+    Logout,
+
     /// Lists all the function apps on the current server
-    List,
+    List {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Starts a function app, or every function app that is ready with --all. Apps are started after the apps they
+    /// depend on are healthy
+    Start {
+        #[arg(required_unless_present = "all")]
+        name: Option<String>,
+
+        /// Start the apps the function app depends on first if they aren't running
+        #[arg(long)]
+        with_deps: bool,
+
+        /// Start every function app that is ready, along with the apps they depend on
+        #[arg(long, conflicts_with_all = ["name", "with_deps"])]
+        all: bool,
+    },
+
+    /// Stops a function app, giving it time to finish the requests in flight before it is killed
+    Stop { name: String },
+
+    /// Restarts a running function app on a new container without dropping requests. The old container is stopped once the new one is healthy
+    Restart { name: String },
+
+    /// Deletes a function app, stopping it if it is running and removing everything the server has for it
+    Delete {
+        name: String,
+
+        /// Delete without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+
+    /// Gets the status of a function app, or an overview of every app with --all
+    Status {
+        #[arg(required_unless_present = "all")]
+        name: Option<String>,
+
+        /// Show every app with its status, deployment, uptime, last deploy, pending deployment, and any error
+        #[arg(long, conflicts_with = "name")]
+        all: bool,
+    },
+
+    /// Shows everything about a function app in one view: its status, routes, config, limits, replicas, latest
+    /// deployments, schedules, and the README from its code that describes how to call it
+    Info {
+        name: String,
+    },
+
+    /// Shows the CPU, memory, and network each running function app is using in a table that refreshes until stopped
+    /// with Ctrl+C. The busiest apps are at the top
+    Top {
+        /// Only show the function app with this name
+        #[arg(long)]
+        app: Option<String>,
+
+        /// How often to refresh, in seconds
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+    },
+
+    /// Shows deployment analytics for each function app over the last few weeks: how often it was deployed, the
+    /// build success rate and mean build time, and how many times it crashed with the mean time to recovery
+    Analytics {
+        /// The number of weeks to cover, ending now
+        #[arg(long, default_value_t = rustless_shared::default_analytics_weeks(), value_parser = clap::value_parser!(u32).range(1..=520))]
+        weeks: u32,
+
+        /// Only show the function app with this name, along with its figures for each week
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Only show the function apps in this namespace
+        #[arg(long)]
+        namespace: Option<String>,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Watches status changes, deploy progress, and crashes on the server as they happen, until stopped with Ctrl+C
+    Events {
+        /// Only show events for the function app with this name
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Only show events for function apps in this namespace
+        #[arg(long)]
+        namespace: Option<String>,
+    },
+
+    /// Turns maintenance mode on or off for a function app. While it is on, requests to the app get a maintenance page
+    Maintenance {
+        name: String,
+        state: ToggleState,
+
+        /// A message to show on the maintenance page, such as when the app will be back
+        #[arg(long)]
+        message: Option<String>,
+    },
+
+    /// Turns mirroring a sample of requests to a function app on or off. Mirrored requests are sent to a URL or
+    /// written to a file on the host, with sensitive headers redacted
+    Mirror {
+        name: String,
+        state: ToggleState,
+
+        /// The URL to post mirrored requests to, or the path of a file to write them to, relative to the mirror folder on the host
+        #[arg(long)]
+        sink: Option<String>,
+
+        /// The fraction of requests to mirror, from 0 to 1
+        #[arg(long, default_value_t = rustless_shared::default_mirror_sample_rate())]
+        sample_rate: f64,
+
+        /// The maximum number of body bytes to mirror. Longer bodies are truncated
+        #[arg(long, default_value_t = rustless_shared::default_mirror_max_body_bytes())]
+        max_body_bytes: usize,
+
+        /// A header to redact, as well as the ones that are always redacted such as Authorization and Cookie.
+        /// Can be used more than once
+        #[arg(long = "redact-header")]
+        redact_headers: Vec<String>,
+    },
+
+    /// Turns recording recent requests to a function app on or off, so they can be replayed.
+    /// Requests are kept in memory on the host, with sensitive headers and the app's redaction rules redacted
+    Record {
+        name: String,
+        state: ToggleState,
+
+        /// The number of recent requests to keep, up to 1000
+        #[arg(long, default_value_t = rustless_shared::default_recording_capacity())]
+        capacity: usize,
+    },
+
+    /// Lists the routes handled by a running function app. The app must be built with rustless_app
+    Routes {
+        name: String,
+    },
+
+    /// Checks the image for a function app was signed by the server when it was built, and hasn't changed since
+    Verify { name: String },
+
+    /// Shows the limits the server puts on request headers and slow clients, and how many requests it has rejected
+    Limits,
+
+    /// Gets the public key the server signs images with, to check signatures with cosign
+    SigningKey {
+        /// The file to write the key to. If this isn't set, it is printed
+        #[arg(long)]
+        output: Option<String>,
+    },
+
+    /// Shows the output of the latest build of a function app, such as the compiler errors from a failed build
+    BuildLogs {
+        name: String,
+
+        /// The ID of the build to show, as returned when the code was uploaded. If this isn't set, the latest build is shown
+        #[arg(long)]
+        build: Option<Uuid>,
+
+        /// Keep printing the output as the build writes it, until the build finishes
+        #[arg(long)]
+        follow: bool,
+    },
+
+    /// Shows the most recent output from a running function app, with when each line was written
+    Logs {
+        name: String,
+
+        /// The number of most recent lines to show
+        #[arg(long, default_value_t = default_log_lines())]
+        tail: usize,
+
+        /// Keep printing the output as the app writes it, until the app stops
+        #[arg(long)]
+        follow: bool,
+    },
+
+    /// Gets the CycloneDX bill of materials generated when a deployment of a function app was built
+    Sbom {
+        name: String,
+
+        /// The deployment number, or latest for the most recent deployment
+        #[arg(long, default_value = "latest")]
+        deployment: String,
+
+        /// The file to write the bill of materials to. If this isn't set, it is printed
+        #[arg(long)]
+        output: Option<String>,
+    },
+
+    /// Calls a route of a function app through the server and prints the response status, headers, and body
+    Invoke {
+        name: String,
+
+        /// The route in the app to call, such as hello or orders?status=open. The app root is called if this isn't set
+        #[arg(default_value = "")]
+        route: String,
+
+        /// The HTTP method to call the route with. The server passes GET and POST requests to apps
+        #[arg(long, default_value = "GET")]
+        method: String,
+
+        /// A file containing the request body. A .json file is sent as JSON unless a content type header is set
+        #[arg(long)]
+        body: Option<String>,
+
+        /// A header to send as NAME=VALUE, such as Accept=application/json. Can be set more than once
+        #[arg(long = "header")]
+        headers: Vec<String>,
+    },
 
-    /// Starts a function app
-    Start { name: String },
+    /// Re-sends the most recent recorded requests to a function app and compares the responses with the recorded ones
+    Replay {
+        name: String,
 
-    /// Gets the status of a function app
-    Status { name: String },
+        /// The number of most recent requests to replay
+        #[arg(long, default_value_t = 10)]
+        last: usize,
+    },
+
+    /// Approves a deployment so the function app can be started, when the server requires approval
+    Approve {
+        name: String,
+        deployment: u32,
+
+        /// The approver key. If this isn't set, the RUSTLESS_APPROVER_KEY environment variable is used
+        #[arg(long)]
+        key: Option<String>,
+    },
+
+    /// Promotes the latest deployment of a function app from the server in one profile to another, such as from
+    /// staging to prod. The approved image is copied as it is rather than built again, and both servers record the
+    /// promotion
+    Promote {
+        name: String,
+
+        /// The profile of the server to promote from
+        #[arg(long)]
+        from: String,
+
+        /// The profile of the server to promote to
+        #[arg(long)]
+        to: String,
+
+        /// Import the deployment without starting or restarting the app
+        #[arg(long)]
+        no_start: bool,
+    },
+
+    /// Manages the triggers that call a function app without a request through the gateway
+    #[command(subcommand)]
+    Trigger(TriggerCommands),
+
+    /// Manages the destinations a function app can call through the server's egress proxy
+    #[command(subcommand)]
+    Egress(EgressCommands),
+
+    /// Manages the gRPC services a function app serves through the server's gRPC gateway
+    #[command(subcommand)]
+    Grpc(GrpcCommands),
+
+    /// Manages the function apps a function app depends on, which are started and healthy before it starts
+    #[command(subcommand)]
+    Dependencies(DependenciesCommands),
+
+    /// Scales a function app to a number of replicas, or manages the profiles that scale it on cron schedules
+    Scale(ScaleArgs),
+
+    /// Manages when the gateway buffers or streams request and response bodies for a function app
+    #[command(subcommand)]
+    Buffering(BufferingCommands),
+
+    /// Manages what is redacted from requests to a function app, and their responses, before they are mirrored or
+    /// recorded
+    #[command(subcommand)]
+    Redaction(RedactionCommands),
+
+    /// Shows or purges the crates.io cache on the server that builds download crates through
+    #[command(subcommand)]
+    CratesCache(CratesCacheCommands),
+
+    /// Shows details of the current server
+    #[command(subcommand)]
+    Server(ServerCommands),
+
+    /// Manages the server profiles used by --all-servers
+    #[command(subcommand)]
+    Profile(ProfileCommands),
+
+    /// Manages the default function app, which receives requests that don't match any other route on the server
+    #[command(subcommand)]
+    DefaultApp(DefaultAppCommands),
+
+    /// Backs up the database for a namespace to a local file. The host must store each namespace separately
+    BackupNamespace { namespace: String, output_path: String },
+
+    /// Restores the database for a namespace from a local backup file, replacing the current database
+    RestoreNamespace { namespace: String, input_path: String },
+
+    /// Manages the default settings for the function apps in a namespace, which apps use for the settings that
+    /// aren't made on them
+    #[command(subcommand)]
+    NamespaceDefaults(NamespaceDefaultsCommands),
+
+    /// Manages the CLI itself
+    #[command(name = "self", subcommand)]
+    SelfCommand(SelfCommands),
+}
+
+/// Whether a setting, such as maintenance mode, is on or off
+#[derive(Clone, Copy, ValueEnum)]
+enum ToggleState {
+    On,
+    Off,
+}
+
+#[derive(Subcommand)]
+enum TriggerCommands {
+    /// Sets a timer trigger that calls a route on the function app on a cron schedule, evaluated in UTC
+    SetTimer {
+        name: String,
+
+        /// The cron schedule, such as "*/5 * * * *" for every 5 minutes. 6 or 7 fields start with seconds
+        #[arg(long)]
+        schedule: String,
+
+        /// The route on the function app to POST to each time the trigger fires
+        #[arg(long)]
+        route: String,
+    },
+
+    /// Removes the timer trigger from a function app
+    RemoveTimer { name: String },
+
+    /// Shows the upcoming runs of the timer trigger for a function app in your timezone
+    Next {
+        name: String,
+
+        /// The number of upcoming runs to show
+        #[arg(long, default_value_t = rustless_shared::default_next_runs_count())]
+        count: usize,
+
+        /// Preview this schedule instead of the one set for the app, to check it before setting it
+        #[arg(long)]
+        schedule: Option<String>,
+    },
+
+    /// Fires a trigger on a function app now, for testing. The app must be running
+    Run {
+        name: String,
 
-    // /// Stops a function app
-    // Stop { name: String },
+        /// The trigger to fire, such as timer
+        trigger: String,
+    },
+
+    /// Shows the most recent trigger invocations for a function app, with the response code and how long they took
+    History {
+        name: String,
 
-    // /// Restarts a function app
-    // Restart { name: String },
+        /// The number of most recent invocations to show
+        #[arg(long, default_value_t = rustless_shared::default_trigger_runs_count())]
+        last: usize,
 
-    // /// Deletes a function app
-    // Delete { name: String },
+        /// Only show the invocations of this trigger, such as timer
+        #[arg(long)]
+        trigger: Option<String>,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum EgressCommands {
+    /// Restricts a function app to calling only the given destinations, such as api.example.com, *.example.com,
+    /// or api.example.com:443. This replaces any existing allowlist
+    Allow {
+        name: String,
+
+        #[arg(required = true)]
+        destinations: Vec<String>,
+    },
+
+    /// Blocks a function app from calling any destinations through the egress proxy
+    Deny { name: String },
+
+    /// Removes the allowlist for a function app, so it can call any destination unless its namespace has a default
+    /// allowlist. Calls are still recorded
+    Unrestrict { name: String },
+
+    /// Shows the allowlist for a function app and the destinations it has called since the server started
+    Show { name: String },
+}
+
+#[derive(Subcommand)]
+enum GrpcCommands {
+    /// Sets the gRPC services a function app serves, such as helloworld.Greeter. Calls to these services through
+    /// the gRPC gateway are routed to the app. This replaces any existing services
+    Set {
+        name: String,
+
+        #[arg(required = true)]
+        services: Vec<String>,
+    },
+
+    /// Stops the gRPC gateway routing any calls to a function app
+    Clear { name: String },
+
+    /// Shows the gRPC services a function app serves and the metrics for each method called since the server started
+    Show { name: String },
+}
+
+#[derive(Subcommand)]
+enum DependenciesCommands {
+    /// Sets the function apps a function app depends on. When the app is started with --with-deps, or with other apps,
+    /// these are started first and must be healthy before it starts. This replaces any existing dependencies
+    Set {
+        name: String,
+
+        #[arg(required = true)]
+        depends_on: Vec<String>,
+    },
+
+    /// Removes all the dependencies of a function app
+    Clear { name: String },
+
+    /// Shows the function apps a function app depends on, and the apps that depend on it
+    Show { name: String },
+}
+
+#[derive(Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct ScaleArgs {
+    #[command(subcommand)]
+    command: Option<ScaleCommands>,
+
+    /// The function app to scale
+    #[arg(required = true)]
+    name: Option<String>,
+
+    /// The number of containers to run the app on. Requests are shared between them in turn. 0 stops the app
+    #[arg(required = true)]
+    replicas: Option<u32>,
+}
+
+#[derive(Subcommand)]
+enum ScaleCommands {
+    /// Sets the profiles that scale a function app. When a profile's schedule starts, the app is scaled to its
+    /// replicas until the next profile starts. This replaces any existing profiles
+    Set {
+        name: String,
+
+        /// A profile as SCHEDULE=REPLICAS, where SCHEDULE is a cron expression in UTC and REPLICAS is the number
+        /// of containers to run, or 0 to stop the app. For example, "0 9 * * 1-5=3" and "0 18 * * *=0" run the app
+        /// on 3 replicas from 9am to 6pm on weekdays
+        #[arg(long = "profile", required = true)]
+        profiles: Vec<String>,
+    },
+
+    /// Clears the scale profiles for a function app, so it is no longer started or stopped on a schedule
+    Clear { name: String },
+
+    /// Shows the scale profiles for a function app, and the replicas they set now and next
+    Show { name: String },
+}
+
+#[derive(Subcommand)]
+enum BufferingCommands {
+    /// Sets how many bytes of bodies are buffered before they are streamed. Bodies within the threshold are sent
+    /// with a content length, for apps and clients that need one. Thresholds that aren't given use the namespace or
+    /// server default
+    Set {
+        name: String,
+
+        /// The threshold for request bodies sent to the app, in bytes
+        #[arg(long)]
+        request: Option<u64>,
+
+        /// The threshold for response bodies returned from the app, in bytes
+        #[arg(long)]
+        response: Option<u64>,
+    },
+
+    /// Shows the buffering thresholds for a function app and how bodies have been buffered since the server started
+    Show { name: String },
+}
+
+#[derive(Subcommand)]
+enum RedactionCommands {
+    /// Sets the headers and JSON fields to redact, replacing the ones set before. Authorization, Cookie, and other
+    /// headers that hold credentials are always redacted. Redacted headers are left out when requests are replayed
+    Set {
+        name: String,
+
+        /// A header to redact. Can be given more than once
+        #[arg(long = "header")]
+        headers: Vec<String>,
+
+        /// The path of a field to redact in JSON bodies, such as user.password, with * for any field or array item,
+        /// such as cards.*.number. Can be given more than once
+        #[arg(long = "json-field")]
+        json_fields: Vec<String>,
+    },
+
+    /// Clears the headers and JSON fields to redact, so only the headers that are always redacted are
+    Clear { name: String },
+
+    /// Shows the headers and JSON fields redacted from a function app's mirrored and recorded requests
+    Show { name: String },
+}
+
+#[derive(Subcommand)]
+enum NamespaceDefaultsCommands {
+    /// Sets the default settings for the apps in a namespace. This replaces all the defaults, so settings that
+    /// aren't given use the server defaults
+    Set {
+        namespace: String,
+
+        /// The threshold for request bodies sent to the apps, in bytes
+        #[arg(long)]
+        buffer_request: Option<u64>,
+
+        /// The threshold for response bodies returned from the apps, in bytes
+        #[arg(long)]
+        buffer_response: Option<u64>,
+
+        /// A destination apps without their own allowlist can call through the egress proxy, such as
+        /// api.example.com or *.example.com. Can be given more than once
+        #[arg(long = "egress-allow")]
+        egress_allow: Vec<String>,
+
+        /// The most replicas an app can be scaled to, up to the most the server allows
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        max_replicas: Option<u32>,
+    },
+
+    /// Clears the default settings for the apps in a namespace, so they use the server defaults
+    Clear { namespace: String },
+
+    /// Shows the default settings for the apps in a namespace
+    Show { namespace: String },
+}
+
+#[derive(Subcommand)]
+enum CratesCacheCommands {
+    /// Shows how the cache has been used since the server started and what it has stored
+    Stats,
+
+    /// Removes everything from the cache, so builds download it from crates.io again
+    Purge,
+}
+
+#[derive(Subcommand)]
+enum ServerCommands {
+    /// Shows the server's version and how much of its app, disk, and memory quotas are used
+    Info,
+}
+
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// Adds a server profile, replacing any existing profile with the same name
+    Add {
+        name: String,
+
+        /// The hostname or address of the server. IPv6 addresses are written in brackets, such as [::1] or [::1]:8080,
+        /// and a server behind a reverse proxy under a path is written with the path, such as example.com/rustless
+        hostname: String,
+
+        /// The port of the server. Defaults to 80, or the port written after an IPv6 address in brackets
+        port: Option<u16>,
+
+        /// The path the server is served under behind a reverse proxy, such as /rustless for
+        /// https://example.com/rustless/
+        #[arg(long)]
+        base_path: Option<String>,
+    },
+
+    /// Removes a server profile
+    Remove { name: String },
+
+    /// Lists the server profiles
+    List,
+}
+
+#[derive(Subcommand)]
+enum DefaultAppCommands {
+    /// Shows the default function app
+    Show,
+
+    /// Sets the default function app
+    Set { name: String },
+
+    /// Clears the default function app, so unknown routes return a 404 page
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum SelfCommands {
+    /// Updates the CLI to the latest release
+    Update {
+        /// Only check if an update is available, without installing it
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 /// Shows the CLI header
 fn show_header() {
-    println!("{}", format!(
-        "\n
+    println!("{}", "\n
     ______          _   _                 _____  _     _____ 
     | ___ \\        | | | |               /  __ \\| |   |_   _|
     | |_/ /   _ ___| |_| | ___  ___ ___  | /  \\/| |     | |  
     |    / | | / __| __| |/ _ \\/ __/ __| | |    | |     | |  
     | |\\ \\ |_| \\__ \\ |_| |  __/\\__ \\__ \\ | \\__/\\| |_____| |_ 
     \\_| \\_\\__,_|___/\\__|_|\\___||___/___/  \\____/\\_____/\\___/ \n\n"
-    )
     .bold()
     .blue());
 }
 
 #[tokio::main]
 async fn main() {
-    // Show the header
-    show_header();
+    // Parse the command line arguments, showing the header above any help or errors
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            show_header();
+            e.exit();
+        }
+    };
 
-    // Parse the command line arguments
-    let cli = Cli::parse();
+    // Show the header, unless the output is going to be piped into another tool
+    if !cli.is_machine_readable() {
+        show_header();
+    }
+
+    // Commands return their errors here, so they are shown and turned into the exit code in one place
+    if let Err(e) = run(&cli).await {
+        e.exit();
+    }
+}
 
+/// Runs the command given on the command line
+async fn run(cli: &Cli) -> Result<(), CliError> {
     // Create the connection
-    let conn = storage::create_connection();
-    let conn = match conn {
+    let conn = match storage::create_connection() {
         Ok(conn) => conn,
-        Err(_) => {
-            println!("{}", format!("Error connecting to database.").red().bold());
-            std::process::exit(-1);
+        Err(e) => return Err(CliError::Message(e)),
+    };
+
+    // Show the version instead of running a command
+    if cli.version {
+        version::print_version(&conn, cli.verbose).await;
+        return Ok(());
+    }
+
+    let command = match &cli.command {
+        Some(command) => command,
+        None => {
+            let _ = Cli::command().print_help();
+            return Err(CliError::Failed);
         }
     };
 
+    // Read-only commands can be run against all the servers at once
+    if cli.all_servers {
+        return match command {
+            Commands::List { output } => cli::list_function_apps_on_all_servers(&conn, output).await,
+            Commands::Status { name: Some(name), .. } => cli::get_function_app_status_on_all_servers(&conn, name).await,
+            _ => Err(CliError::Usage("--all-servers is only supported by the list and status commands".to_string())),
+        };
+    }
+
+    // Mutating commands can show what they would do without doing it
+    if cli.dry_run {
+        return match command {
+            Commands::AddFunctionApp { name, code_path, namespace, build } => {
+                dry_run::plan_add_function_app(&conn, name, code_path, namespace, &build.to_options()).await
+            }
+            Commands::UpdateFunctionApp { name, code_path, build } => {
+                dry_run::plan_update_function_app(&conn, name, code_path, &build.to_options()).await
+            }
+            Commands::Deploy { file, .. } => deploy::plan_deploy(&conn, file).await,
+            Commands::Start { name: Some(name), with_deps, .. } => dry_run::plan_start_function_app(&conn, name, *with_deps).await,
+            Commands::Stop { name } => dry_run::plan_stop_function_app(&conn, name).await,
+            Commands::Restart { name } => dry_run::plan_restart_function_app(&conn, name).await,
+            Commands::Delete { name, .. } => dry_run::plan_delete_function_app(&conn, name).await,
+            _ => Err(CliError::Usage("--dry-run is only supported by the add-function-app, update-function-app, deploy, start, stop, restart and delete commands, and not by start --all".to_string())),
+        };
+    }
+
     // You can check for the existence of subcommands, and if found use their
     // matches just as you would the top level cmd
-    match &cli.command {
-        Commands::AddFunctionApp { name, code_path } => {
-            cli::add_function_app(&conn, name, code_path).await;
+    match command {
+        Commands::AddFunctionApp { name, code_path, namespace, build } => {
+            cli::add_function_app(&conn, name, code_path, namespace, &build.to_options()).await
+        }
+
+        Commands::UpdateFunctionApp { name, code_path, build } => {
+            cli::update_function_app(&conn, name, code_path, &build.to_options()).await
         }
 
-        Commands::UpdateFunctionApp { name, code_path } => {
-            cli::update_function_app(&conn, name, code_path).await;
+        Commands::Deploy { file, parallel, force } => {
+            deploy::deploy(&conn, file, *parallel, *force).await
         }
 
         // Set the server
-        Commands::SetServer { hostname, port } => {
+        Commands::SetServer { hostname, port, base_path } => {
+            let new_server = server::parse_server(hostname, *port, base_path)?;
+
             // Message the user
-            println!("{}", format!("Setting server: {}:{}", hostname, port).green());
+            println!("{}", format!("Setting server: {}", server::format_server(&new_server)).green());
 
-            if storage::set_server(conn, hostname, *port).await.is_err() {
-                std::process::exit(-1)
-            }
+            cli::set_server(conn, &new_server).await
         }
 
         // Show the server that we have set. If this fails, report that no server is set
-        Commands::ShowServer => match storage::get_server(&conn) {
-            Ok(server) => println!("{}", format!("Server: {}:{}", server.hostname, server.port).green()),
-            Err(_) => println!("{}", format!("No server set.").red())
+        Commands::ShowServer => {
+            match storage::get_server(&conn) {
+                Ok(server) => {
+                    println!("{}", format!("Server: {}", server::format_server(&server)).green());
+                    if let Ok(Some(_)) = storage::get_api_key(&conn) {
+                        println!("{}", "Logged in with an API key".green());
+                    }
+                },
+                Err(_) => println!("{}", "No server set.".red())
+            }
+
+            Ok(())
         },
 
+        Commands::Login { key } => {
+            cli::login(&conn, key).await
+        }
+
+        Commands::Logout => {
+            cli::logout(&conn)
+        }
+
         // List out all the function apps on the server
-        Commands::List => {
-            cli::list_function_apps(&conn).await;
+        Commands::List { output } => {
+            cli::list_function_apps(&conn, output).await
+        }
+
+        Commands::Events { app, namespace } => {
+            events::watch_events(&conn, app, namespace).await
         }
 
         // Start a function app
-        Commands::Start { name } => {
-            cli::start_function_app(&conn, name).await;
+        Commands::Start { name: Some(name), with_deps, .. } => {
+            cli::start_function_app(&conn, name, *with_deps).await
+        }
+
+        // Start every function app that is ready
+        Commands::Start { name: None, .. } => {
+            dependencies::start_all_function_apps(&conn).await
+        }
+
+        // Stop a function app
+        Commands::Stop { name } => {
+            cli::stop_function_app(&conn, name).await
+        }
+
+        // Restart a function app
+        Commands::Restart { name } => {
+            cli::restart_function_app(&conn, name).await
+        }
+
+        // Delete a function app
+        Commands::Delete { name, yes } => {
+            cli::delete_function_app(&conn, name, *yes).await
+        }
+
+        Commands::Status { all: true, .. } => {
+            overview::show_status_overview(&conn).await
+        }
+
+        Commands::Status { name: Some(name), .. } => {
+            cli::get_function_app_status(&conn, name).await
+        }
+
+        // Clap requires a name unless --all is given
+        Commands::Status { name: None, .. } => Ok(()),
+
+        Commands::Info { name } => {
+            info::show_info(&conn, name).await
+        }
+
+        Commands::Top { app, interval } => {
+            top::show_top(&conn, app, *interval).await
+        }
+
+        Commands::Analytics { weeks, app, namespace, output } => {
+            analytics::show_analytics(&conn, *weeks, app, namespace, output).await
+        }
+
+        Commands::Maintenance { name, state, message } => {
+            cli::set_maintenance(&conn, name, matches!(state, ToggleState::On), message).await
+        }
+
+        Commands::Mirror { name, state, sink, sample_rate, max_body_bytes, redact_headers } => {
+            // The mirror settings are only needed when turning mirroring on
+            let config = match (state, sink) {
+                (ToggleState::On, Some(sink)) => Some(rustless_shared::MirrorConfig {
+                    sink: sink.to_string(),
+                    sample_rate: *sample_rate,
+                    max_body_bytes: *max_body_bytes,
+                    redact_headers: redact_headers.clone(),
+                }),
+                (ToggleState::On, None) => {
+                    return Err(CliError::Usage("A sink is required to turn mirroring on. Set it with --sink".to_string()));
+                },
+                (ToggleState::Off, _) => None,
+            };
+
+            cli::set_mirror(&conn, name, &config).await
+        }
+
+        Commands::Record { name, state, capacity } => {
+            cli::set_recording(&conn, name, matches!(state, ToggleState::On), *capacity).await
+        }
+
+        Commands::Routes { name } => {
+            cli::list_routes(&conn, name).await
+        }
+
+        Commands::Verify { name } => {
+            cli::verify_image(&conn, name).await
+        }
+
+        Commands::Limits => {
+            cli::show_request_limits(&conn).await
+        }
+
+        Commands::SigningKey { output } => {
+            cli::get_signing_key(&conn, output).await
+        }
+
+        Commands::BuildLogs { name, build, follow } => {
+            build_logs::show_build_logs(&conn, name, build, *follow).await
+        }
+
+        Commands::Logs { name, tail, follow } => {
+            logs::show_logs(&conn, name, *tail, *follow).await
+        }
+
+        Commands::Sbom { name, deployment, output } => {
+            cli::get_deployment_sbom(&conn, name, deployment, output).await
+        }
+
+        Commands::Invoke { name, route, method, body, headers } => {
+            invoke::invoke(&conn, name, route, method, body, headers).await
+        }
+
+        Commands::Replay { name, last } => {
+            replay::replay(&conn, name, *last).await
+        }
+
+        Commands::Approve { name, deployment, key } => {
+            cli::approve_deployment(&conn, name, *deployment, key).await
+        }
+
+        Commands::Promote { name, from, to, no_start } => {
+            promote::promote(&conn, name, from, to, *no_start).await
+        }
+
+        Commands::Egress(EgressCommands::Allow { name, destinations }) => {
+            egress::set_egress(&conn, name, &Some(destinations.clone())).await
+        }
+
+        Commands::Egress(EgressCommands::Deny { name }) => {
+            egress::set_egress(&conn, name, &Some(Vec::new())).await
+        }
+
+        Commands::Egress(EgressCommands::Unrestrict { name }) => {
+            egress::set_egress(&conn, name, &None).await
+        }
+
+        Commands::Egress(EgressCommands::Show { name }) => {
+            egress::show_egress(&conn, name).await
+        }
+
+        Commands::Grpc(GrpcCommands::Set { name, services }) => {
+            grpc::set_grpc_services(&conn, name, services).await
+        }
+
+        Commands::Grpc(GrpcCommands::Clear { name }) => {
+            grpc::set_grpc_services(&conn, name, &[]).await
+        }
+
+        Commands::Grpc(GrpcCommands::Show { name }) => {
+            grpc::show_grpc(&conn, name).await
+        }
+
+        Commands::Dependencies(DependenciesCommands::Set { name, depends_on }) => {
+            dependencies::set_dependencies(&conn, name, depends_on).await
+        }
+
+        Commands::Dependencies(DependenciesCommands::Clear { name }) => {
+            dependencies::set_dependencies(&conn, name, &[]).await
+        }
+
+        Commands::Dependencies(DependenciesCommands::Show { name }) => {
+            dependencies::show_dependencies(&conn, name).await
+        }
+
+        Commands::Scale(ScaleArgs { command: None, name: Some(name), replicas: Some(replicas) }) => {
+            scale::scale_function_app(&conn, name, *replicas).await
+        }
+
+        Commands::Scale(ScaleArgs { command: None, .. }) => {
+            Err(CliError::Usage("Give the function app to scale and the number of replicas".to_string()))
+        }
+
+        Commands::Scale(ScaleArgs { command: Some(ScaleCommands::Set { name, profiles }), .. }) => {
+            scale::set_scale_profiles(&conn, name, profiles).await
+        }
+
+        Commands::Scale(ScaleArgs { command: Some(ScaleCommands::Clear { name }), .. }) => {
+            scale::clear_scale_profiles(&conn, name).await
+        }
+
+        Commands::Scale(ScaleArgs { command: Some(ScaleCommands::Show { name }), .. }) => {
+            scale::show_scale_profiles(&conn, name).await
+        }
+
+        Commands::Buffering(BufferingCommands::Set { name, request, response }) => {
+            buffering::set_buffering(&conn, name, *request, *response).await
+        }
+
+        Commands::CratesCache(CratesCacheCommands::Stats) => {
+            crates_cache::show_stats(&conn).await
+        }
+
+        Commands::CratesCache(CratesCacheCommands::Purge) => {
+            crates_cache::purge(&conn).await
+        }
+
+        Commands::Buffering(BufferingCommands::Show { name }) => {
+            buffering::show_buffering(&conn, name).await
+        }
+
+        Commands::Redaction(RedactionCommands::Set { name, headers, json_fields }) => {
+            let rules = RedactionRules {
+                headers: headers.clone(),
+                json_fields: json_fields.clone(),
+            };
+            redaction::set_redaction(&conn, name, &rules).await
+        }
+
+        Commands::Redaction(RedactionCommands::Clear { name }) => {
+            redaction::set_redaction(&conn, name, &RedactionRules::default()).await
+        }
+
+        Commands::Redaction(RedactionCommands::Show { name }) => {
+            redaction::show_redaction(&conn, name).await
+        }
+
+        Commands::Trigger(TriggerCommands::SetTimer { name, schedule, route }) => {
+            triggers::set_timer_trigger(&conn, name, schedule, route).await
+        }
+
+        Commands::Trigger(TriggerCommands::RemoveTimer { name }) => {
+            triggers::remove_timer_trigger(&conn, name).await
+        }
+
+        Commands::Trigger(TriggerCommands::Next { name, count, schedule }) => {
+            triggers::show_next_runs(&conn, name, *count, schedule).await
+        }
+
+        Commands::Trigger(TriggerCommands::Run { name, trigger }) => {
+            triggers::run_trigger(&conn, name, trigger).await
+        }
+
+        Commands::Trigger(TriggerCommands::History { name, last, trigger, output }) => {
+            triggers::show_trigger_runs(&conn, name, *last, trigger, output).await
+        }
+
+        Commands::Server(ServerCommands::Info) => {
+            server_info::show_server_info(&conn).await
+        }
+
+        Commands::Profile(ProfileCommands::Add { name, hostname, port, base_path }) => {
+            let profile_server = server::parse_server(hostname, *port, base_path)?;
+            cli::add_profile(&conn, name, &profile_server).await
+        }
+
+        Commands::Profile(ProfileCommands::Remove { name }) => {
+            cli::remove_profile(&conn, name)
+        }
+
+        Commands::Profile(ProfileCommands::List) => {
+            cli::list_profiles(&conn)
+        }
+
+        Commands::DefaultApp(DefaultAppCommands::Show) => {
+            cli::show_default_app(&conn).await
+        }
+
+        Commands::DefaultApp(DefaultAppCommands::Set { name }) => {
+            cli::set_default_app(&conn, Some(name.to_string())).await
+        }
+
+        Commands::DefaultApp(DefaultAppCommands::Clear) => {
+            cli::set_default_app(&conn, None).await
+        }
+
+        Commands::BackupNamespace { namespace, output_path } => {
+            cli::backup_namespace(&conn, namespace, output_path).await
+        }
+
+        Commands::RestoreNamespace { namespace, input_path } => {
+            cli::restore_namespace(&conn, namespace, input_path).await
+        }
+
+        Commands::NamespaceDefaults(NamespaceDefaultsCommands::Set { namespace, buffer_request, buffer_response, egress_allow, max_replicas }) => {
+            // Apps can call any destination unless an allowlist is given
+            let defaults = NamespaceDefaults {
+                buffer_request_threshold: *buffer_request,
+                buffer_response_threshold: *buffer_response,
+                egress_allowlist: if egress_allow.is_empty() { None } else { Some(egress_allow.clone()) },
+                max_replicas: *max_replicas,
+            };
+            namespace_defaults::set_defaults(&conn, namespace, &defaults).await
+        }
+
+        Commands::NamespaceDefaults(NamespaceDefaultsCommands::Clear { namespace }) => {
+            namespace_defaults::clear_defaults(&conn, namespace).await
+        }
+
+        Commands::NamespaceDefaults(NamespaceDefaultsCommands::Show { namespace }) => {
+            namespace_defaults::show_defaults(&conn, namespace).await
         }
 
-        Commands::Status { name } => {
-            cli::get_function_app_status(&conn, name).await;
+        // Update the CLI
+        Commands::SelfCommand(SelfCommands::Update { check }) => {
+            Ok(self_update::update(*check).await?)
         }
     }
 }
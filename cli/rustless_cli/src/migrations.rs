@@ -0,0 +1,97 @@
+use std::time::SystemTime;
+
+use rusqlite::{Connection, TransactionBehavior};
+
+use crate::storage;
+
+/// A change to the database schema. Each migration is applied once, in order of version, and recorded in the
+/// schema_migrations table so it isn't applied again
+///
+/// To change the schema, add a migration to the end of MIGRATIONS with the next version rather than changing the
+/// existing ones, which have already been applied to existing databases
+struct Migration {
+    // The version the database is at once the migration has been applied
+    version: u32,
+
+    // What the migration does, recorded when it is applied
+    name: &'static str,
+
+    // Applies the migration. It runs in a transaction, so if it fails the database is left as it was
+    apply: fn(&Connection) -> Result<(), String>,
+}
+
+/// The migrations, in order of version. The first creates the tables, and upgrades databases created before
+/// migrations were recorded, as it only adds what is missing
+const MIGRATIONS: [Migration; 2] = [
+    Migration { version: 1, name: "create tables", apply: storage::create_tables },
+    Migration { version: 2, name: "add base paths", apply: storage::add_base_path_columns },
+];
+
+/// Gets the version of the schema this version of the CLI upgrades the database to
+fn get_latest_version() -> u32 {
+    MIGRATIONS.iter().map(|migration| migration.version).max().unwrap_or(0)
+}
+
+/// Gets the version of the schema the database is at, or 0 if no migrations have been applied
+fn get_version(conn: &Connection) -> Result<u32, String> {
+    match conn.query_row("SELECT MAX(version) FROM schema_migrations", [], |row| row.get::<_, Option<u32>>(0)) {
+        Ok(version) => Ok(version.unwrap_or(0)),
+        Err(e) => Err(format!("Error getting database version: {}", e)),
+    }
+}
+
+/// Applies a migration in a transaction, recording it with the time it was applied. The transaction takes the write
+/// lock first, so if two commands start at the same time the second sees the migration has been applied and skips it
+fn apply(conn: &mut Connection, migration: &Migration) -> Result<(), String> {
+    let transaction = match conn.transaction_with_behavior(TransactionBehavior::Immediate) {
+        Ok(transaction) => transaction,
+        Err(e) => return Err(format!("Error starting database migration {}: {}", migration.version, e)),
+    };
+
+    if get_version(&transaction)? >= migration.version {
+        return Ok(());
+    }
+
+    if let Err(e) = (migration.apply)(&transaction) {
+        return Err(format!("Error applying database migration {} ({}): {}", migration.version, migration.name, e));
+    }
+
+    let applied_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    if let Err(e) = transaction.execute(
+        "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?1, ?2, ?3)",
+        rusqlite::params![migration.version, migration.name, applied_at],
+    ) {
+        return Err(format!("Error recording database migration {}: {}", migration.version, e));
+    }
+
+    match transaction.commit() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error committing database migration {}: {}", migration.version, e)),
+    }
+}
+
+/// Upgrades the database to the latest schema, applying the migrations it doesn't have yet. A database that has been
+/// upgraded by a newer version of the CLI is left alone, as this version may not be able to use it
+pub fn migrate(conn: &mut Connection) -> Result<(), String> {
+    if let Err(e) = conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+                  version         INTEGER PRIMARY KEY,
+                  name            TEXT NOT NULL,
+                  applied_at      INTEGER NOT NULL
+                  )",
+        [],
+    ) {
+        return Err(format!("Error creating migrations table: {}", e));
+    }
+
+    let version = get_version(conn)?;
+    if version > get_latest_version() {
+        return Err(format!("rustless_cli.db is at version {}, which is newer than this version of the CLI supports ({}). Upgrade the CLI to use it", version, get_latest_version()));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > version) {
+        apply(conn, migration)?;
+    }
+
+    Ok(())
+}
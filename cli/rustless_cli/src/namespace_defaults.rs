@@ -0,0 +1,71 @@
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::NamespaceDefaults;
+
+use crate::error::CliError;
+use crate::server;
+
+/// Formats a default threshold, or notes that the server default is used
+fn format_threshold(threshold: Option<u64>) -> String {
+    match threshold {
+        Some(threshold) => format!("{} bytes", threshold),
+        None => "server default".to_string(),
+    }
+}
+
+/// Prints the default settings for the apps in a namespace
+fn print_defaults(namespace: &String, defaults: &NamespaceDefaults) {
+    println!("{}", format!("Defaults for namespace '{}':", namespace).blue());
+    println!("  Requests buffered up to:  {}", format_threshold(defaults.buffer_request_threshold));
+    println!("  Responses buffered up to: {}", format_threshold(defaults.buffer_response_threshold));
+
+    match &defaults.egress_allowlist {
+        Some(allowlist) if allowlist.is_empty() => println!("  Egress:                   no destinations"),
+        Some(allowlist) => println!("  Egress:                   only {}", allowlist.join(", ")),
+        None => println!("  Egress:                   any destination"),
+    }
+
+    match defaults.max_replicas {
+        Some(max_replicas) => println!("  Max replicas:             {}", max_replicas),
+        None => println!("  Max replicas:             server default"),
+    }
+
+    println!("Apps in the namespace use these unless the setting is made on the app");
+}
+
+/// Sets the default settings for the apps in a namespace, replacing any that were set before
+pub async fn set_defaults(conn: &Connection, namespace: &String, defaults: &NamespaceDefaults) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+
+    match client.set_namespace_defaults(namespace, defaults).await {
+        Ok(_) => println!("{}", format!("✅ Defaults set for namespace '{}'", namespace).green()),
+        Err(e) => return Err(CliError::Message(format!("Error setting namespace defaults: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Clears the default settings for the apps in a namespace, so they use the server defaults
+pub async fn clear_defaults(conn: &Connection, namespace: &String) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+
+    match client.set_namespace_defaults(namespace, &NamespaceDefaults::default()).await {
+        Ok(_) => println!("{}", format!("✅ Defaults cleared for namespace '{}'", namespace).green()),
+        Err(e) => return Err(CliError::Message(format!("Error clearing namespace defaults: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the default settings for the apps in a namespace
+pub async fn show_defaults(conn: &Connection, namespace: &String) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+
+    match client.namespace_defaults(namespace).await {
+        Ok(defaults) => print_defaults(namespace, &defaults),
+        Err(e) => return Err(CliError::Message(format!("Error getting namespace defaults: {}", e))),
+    }
+
+    Ok(())
+}
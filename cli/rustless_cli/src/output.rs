@@ -0,0 +1,86 @@
+use clap::{Args, ValueEnum};
+
+/// How the results of a command such as list are written out
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// A colored table for reading in the terminal
+    Table,
+
+    /// Comma separated values, for pasting into spreadsheets
+    Csv,
+
+    /// A markdown table, for pasting into issues and docs
+    Markdown,
+}
+
+/// The options for how a command writes out its results
+#[derive(Args)]
+pub struct OutputArgs {
+    /// How to write out the results
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+
+    /// Leave out the header row from csv and markdown output, for piping into other tools
+    #[arg(long)]
+    pub no_header: bool,
+}
+
+impl OutputArgs {
+    /// Gets if the results should be written as a table for reading in the terminal
+    pub fn is_table(&self) -> bool {
+        self.output == OutputFormat::Table
+    }
+}
+
+/// Escapes a value for CSV. Values containing commas, quotes or line breaks are quoted, with quotes doubled
+fn escape_csv(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+/// Escapes a value for a markdown table cell. Pipes would end the cell and line breaks the row, so they are escaped
+fn escape_markdown(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\n', '\r'], "<br>")
+}
+
+/// Formats a row of values as a CSV line
+fn format_csv_row(values: &[String]) -> String {
+    values.iter().map(|value| escape_csv(value)).collect::<Vec<_>>().join(",")
+}
+
+/// Formats a row of values as a markdown table row
+fn format_markdown_row(values: &[String]) -> String {
+    format!("| {} |", values.iter().map(|value| escape_markdown(value)).collect::<Vec<_>>().join(" | "))
+}
+
+/// Writes out rows of results as CSV or markdown, with the header unless it was turned off.
+/// Used for the csv and markdown formats, as each command draws its own table
+pub fn print_rows(args: &OutputArgs, headers: &[&str], rows: &[Vec<String>]) {
+    let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+
+    match args.output {
+        OutputFormat::Csv => {
+            if !args.no_header {
+                println!("{}", format_csv_row(&headers));
+            }
+            for row in rows {
+                println!("{}", format_csv_row(row));
+            }
+        },
+        OutputFormat::Markdown | OutputFormat::Table => {
+            if !args.no_header {
+                println!("{}", format_markdown_row(&headers));
+                println!("|{}", " --- |".repeat(headers.len()));
+            }
+            for row in rows {
+                println!("{}", format_markdown_row(row));
+            }
+        },
+    }
+}
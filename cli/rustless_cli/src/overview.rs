@@ -0,0 +1,98 @@
+use std::time::SystemTime;
+
+use colored::{ColoredString, Colorize};
+use rusqlite::Connection;
+
+use rustless_shared::{FunctionAppOverview, FunctionAppStatus};
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// The columns of the overview table
+const HEADERS: [&str; 8] = ["NAME", "NAMESPACE", "STATUS", "DEPLOYMENT", "UPTIME", "LAST DEPLOY", "PENDING", "ERROR"];
+
+/// Formats a number of seconds in the two largest units, such as 3d4h or 5m12s
+pub fn format_duration(secs: u64) -> String {
+    let (days, hours, minutes, seconds) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m{}s", minutes, seconds),
+        (0, _, _) => format!("{}h{}m", hours, minutes),
+        _ => format!("{}d{}h", days, hours),
+    }
+}
+
+/// Formats how long ago a timestamp from the server was, or - if there isn't one
+fn format_since(now: u64, timestamp: Option<u64>, suffix: &str) -> String {
+    match timestamp {
+        Some(timestamp) => format!("{}{}", format_duration(now.saturating_sub(timestamp)), suffix),
+        None => "-".to_string(),
+    }
+}
+
+/// Colors a status cell, after it has been padded so the colors don't throw out the column widths
+fn color_status(status: &FunctionAppStatus, cell: String) -> ColoredString {
+    match status {
+        FunctionAppStatus::Running => cell.green(),
+        FunctionAppStatus::Error | FunctionAppStatus::NotRegistered => cell.red(),
+        FunctionAppStatus::Cancelled => cell.yellow(),
+        _ => cell.blue(),
+    }
+}
+
+/// Gets the cells for a row of the overview table
+fn get_row(now: u64, overview: &FunctionAppOverview) -> Vec<String> {
+    vec![
+        overview.name.to_string(),
+        overview.namespace.to_string(),
+        cli::get_status_name(&overview.status).to_string(),
+        overview.deployment.map(|number| number.to_string()).unwrap_or_else(|| "-".to_string()),
+        format_since(now, overview.running_since, ""),
+        format_since(now, overview.deployed_at, " ago"),
+        overview.pending_deployment.map(|number| format!("deployment {} awaiting approval", number)).unwrap_or_else(|| "-".to_string()),
+        overview.error.clone().unwrap_or_else(|| "-".to_string()),
+    ]
+}
+
+/// Shows every function app on the server with its status, version, uptime, last deployment, anything waiting to be
+/// started, and why it is in error, one app per line
+pub async fn show_status_overview(conn: &Connection) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+    let overviews = match client.overviews().await {
+        Ok(overviews) => overviews,
+        Err(e) => return Err(CliError::Message(format!("Error getting the status of the function apps: {}", e))),
+    };
+
+    if overviews.is_empty() {
+        println!("{}", "No function apps registered".blue());
+        return Ok(());
+    }
+
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    let rows: Vec<Vec<String>> = overviews.iter().map(|overview| get_row(now, overview)).collect();
+
+    // Size each column to fit the widest cell in it
+    let mut widths: Vec<usize> = HEADERS.iter().map(|header| header.len()).collect();
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = HEADERS.iter().zip(widths.iter()).map(|(header, width)| format!("{:<width$}", header, width = width)).collect();
+    println!("{}", header.join("   ").trim_end().bold());
+
+    for (overview, row) in overviews.iter().zip(rows) {
+        let cells: Vec<String> = row.into_iter().zip(widths.iter()).enumerate().map(|(index, (cell, width))| {
+            let cell = format!("{:<width$}", cell, width = width);
+            match index {
+                2 => color_status(&overview.status, cell).to_string(),
+                _ => cell,
+            }
+        }).collect();
+        println!("{}", cells.join("   ").trim_end());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::time::SystemTime;
+
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_client::RustlessClient;
+use rustless_shared::{FunctionAppStatus, Promotion};
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server::{self, FunctionAppRef};
+use crate::storage;
+
+/// Gets a client for the server in a profile, with the server's address as it is recorded with promotions
+fn get_profile_client(conn: &Connection, profile_name: &String) -> Result<(RustlessClient, String), CliError> {
+    let profiles = match storage::get_profiles(conn) {
+        Ok(profiles) => profiles,
+        Err(e) => return Err(CliError::Message(format!("Error getting profiles: {}", e))),
+    };
+
+    let profile = match profiles.into_iter().find(|profile| &profile.name == profile_name) {
+        Some(profile) => profile,
+        None => return Err(CliError::Message(format!("No profile named '{}' exists. Add it with 'rustless profile add'", profile_name))),
+    };
+
+    let client = server::get_client(&profile.server)?;
+    Ok((client, server::format_server(&profile.server)))
+}
+
+/// Promotes the latest deployment of a function app from the server in one profile to the server in another, such
+/// as from staging to prod. The image is exported and imported as it is rather than built again, so what runs on
+/// the target is exactly what was approved on the source, and both servers record the promotion
+///
+/// The app is created on the target if it doesn't exist, and started or restarted once the deployment is imported
+/// unless it needs approving on the target first
+pub async fn promote(conn: &Connection, name: &String, from: &String, to: &String, no_start: bool) -> Result<(), CliError> {
+    if from == to {
+        return Err(CliError::Usage("The profiles to promote from and to must be different".to_string()));
+    }
+
+    let (source, source_host) = get_profile_client(conn, from)?;
+    let (target, target_host) = get_profile_client(conn, to)?;
+    let app = FunctionAppRef::Name(name.to_string());
+
+    // The app is created on the target in the same namespace it has on the source
+    let info = match source.info(&app).await {
+        Ok(Some(info)) => info,
+        Ok(None) => return Err(CliError::Message(format!("No function app with the name '{}' exists on '{}'", name, from))),
+        Err(e) => return Err(CliError::Message(format!("Error getting function app from '{}': {}", from, e))),
+    };
+
+    let running = match target.status(&app).await {
+        Ok(Some(status)) => matches!(status.status, FunctionAppStatus::Running),
+        Ok(None) => {
+            println!("{}", format!("Creating function app '{}' on '{}'", name, to).blue());
+            if let Err(e) = target.create_app(name, &info.namespace).await {
+                return Err(CliError::Message(format!("Error adding function app to '{}': {}", to, e)));
+            }
+            false
+        },
+        Err(e) => return Err(CliError::Message(format!("Error getting function app status from '{}': {}", to, e))),
+    };
+
+    // The export is streamed from the source straight to the target, so the image is never saved locally
+    let pb = cli::create_progress_bar();
+    pb.set_message(format!("Promoting '{}' from '{}' to '{}'...", name, from, to));
+
+    let export = match source.export_deployment(&app, "latest").await {
+        Ok(Some(export)) => export,
+        Ok(None) => {
+            pb.finish_and_clear();
+            return Err(CliError::Message(format!("No function app with the name '{}' exists on '{}'", name, from)));
+        },
+        Err(e) => {
+            pb.finish_and_clear();
+            return Err(CliError::Message(format!("Error exporting deployment from '{}': {}", from, e)));
+        },
+    };
+    let deployment = export.deployment;
+
+    let imported = target.import_deployment(&app, export, &source_host).await;
+    pb.finish_and_clear();
+
+    let imported = match imported {
+        Ok(Some(imported)) => imported,
+        Ok(None) => return Err(CliError::Message(format!("No function app with the name '{}' exists on '{}'", name, to))),
+        Err(e) => return Err(CliError::Message(format!("Error importing deployment to '{}': {}", to, e))),
+    };
+
+    println!(
+        "{}",
+        format!("✅ Promoted deployment #{} of '{}' from '{}' to '{}' as deployment #{}", deployment, name, from, to, imported.number).green()
+    );
+    println!("Image: {}", imported.image_id);
+
+    // The deployment is on the target, so failing to record it on the source doesn't fail the promotion
+    let promotion = Promotion {
+        host: target_host,
+        deployment: imported.number,
+        image_id: imported.image_id.to_string(),
+        promoted_at: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+    };
+    match source.record_promotion(&app, deployment, &promotion).await {
+        Ok(true) => {},
+        Ok(false) => println!("{}", format!("Deployment #{} is no longer on '{}', so the promotion wasn't recorded there", deployment, from).yellow()),
+        Err(e) => println!("{}", format!("Error recording the promotion on '{}': {}", from, e).yellow()),
+    }
+
+    if !imported.approved {
+        println!("{}", format!("Deployment #{} is waiting for approval on '{}', so the app wasn't started", imported.number, to).yellow());
+        return Ok(());
+    }
+
+    if no_start {
+        return Ok(());
+    }
+
+    // A running app is restarted onto the new image without dropping requests
+    match running {
+        true => match target.restart(&app).await {
+            Ok(Some(restart)) => println!("{}", format!("✅ Function app '{}' restarted on '{}' on port {}", name, to, restart.port).green()),
+            Ok(None) => return Err(CliError::Message(format!("No function app with the name '{}' exists on '{}'", name, to))),
+            Err(e) => return Err(CliError::Message(format!("Error restarting function app on '{}': {}", to, e))),
+        },
+        false => match target.start(&app, true).await {
+            Ok(true) => println!("{}", format!("✅ Function app '{}' started on '{}'", name, to).green()),
+            Ok(false) => return Err(CliError::Message(format!("No function app with the name '{}' exists on '{}'", name, to))),
+            Err(e) => return Err(CliError::Message(format!("Error starting function app on '{}': {}", to, e))),
+        },
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::RedactionRules;
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// Prints the headers and JSON fields redacted from a function app's mirrored and recorded requests
+fn print_redaction(name: &String, rules: &RedactionRules) {
+    println!("{}", format!("Redaction for '{}':", name).blue());
+
+    match rules.headers.is_empty() {
+        true => println!("  Headers:     only the ones that are always redacted"),
+        false => println!("  Headers:     {}, and the ones that are always redacted", rules.headers.join(", ")),
+    }
+
+    match rules.json_fields.is_empty() {
+        true => println!("  JSON fields: none"),
+        false => println!("  JSON fields: {}", rules.json_fields.join(", ")),
+    }
+}
+
+/// Sets the headers and JSON fields redacted from requests to a function app before they are mirrored or recorded,
+/// retrying by name if the cached ID is stale. Empty rules clear them
+pub async fn set_redaction(conn: &Connection, name: &String, rules: &RedactionRules) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.set_redaction(&app, rules).await }).await;
+
+    let cleared = rules.headers.is_empty() && rules.json_fields.is_empty();
+    match result {
+        Ok(true) if cleared => println!("{}", format!("✅ Redaction cleared for '{}'", name).green()),
+        Ok(true) => println!("{}", format!("✅ Redaction set for '{}'", name).green()),
+        Ok(false) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error setting redaction: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the headers and JSON fields redacted from requests to a function app before they are mirrored or recorded
+pub async fn show_redaction(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.redaction(&app).await }).await;
+
+    match result {
+        Ok(Some(rules)) => print_redaction(name, &rules),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting redaction: {}", e))),
+    }
+
+    Ok(())
+}
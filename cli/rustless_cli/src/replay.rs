@@ -0,0 +1,198 @@
+use colored::Colorize;
+use rusqlite::Connection;
+use serde_json::Value;
+
+use rustless_client::RustlessClient;
+use rustless_shared::{RecordedRequest, REDACTED_VALUE};
+
+use crate::error::CliError;
+use crate::server::{self, FunctionAppRef};
+
+/// The most lines of a response body to diff. Longer bodies are only reported as different
+const MAX_DIFF_LINES: usize = 500;
+
+/// A line in a diff between the recorded and replayed response bodies
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Works out the line by line differences between two bodies, using the longest common subsequence of lines
+fn diff_lines<'a>(recorded: &'a str, replayed: &'a str) -> Vec<DiffLine<'a>> {
+    let recorded: Vec<&str> = recorded.lines().collect();
+    let replayed: Vec<&str> = replayed.lines().collect();
+
+    // lengths[i][j] is the length of the longest common subsequence of recorded[i..] and replayed[j..]
+    let mut lengths = vec![vec![0usize; replayed.len() + 1]; recorded.len() + 1];
+    for i in (0..recorded.len()).rev() {
+        for j in (0..replayed.len()).rev() {
+            lengths[i][j] = match recorded[i] == replayed[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    // Walk the table to build the diff
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < recorded.len() && j < replayed.len() {
+        if recorded[i] == replayed[j] {
+            diff.push(DiffLine::Same(recorded[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            diff.push(DiffLine::Removed(recorded[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(replayed[j]));
+            j += 1;
+        }
+    }
+
+    diff.extend(recorded[i..].iter().map(|line| DiffLine::Removed(line)));
+    diff.extend(replayed[j..].iter().map(|line| DiffLine::Added(line)));
+    diff
+}
+
+/// Prints the differences between the recorded and replayed response bodies
+fn print_body_diff(recorded: &[u8], replayed: &[u8]) {
+    // Only text bodies that aren't too long can be diffed line by line
+    let (recorded_text, replayed_text) = match (std::str::from_utf8(recorded), std::str::from_utf8(replayed)) {
+        (Ok(recorded_text), Ok(replayed_text))
+            if recorded_text.lines().count() <= MAX_DIFF_LINES && replayed_text.lines().count() <= MAX_DIFF_LINES => (recorded_text, replayed_text),
+        _ => {
+            println!("    Body differs ({} bytes recorded, {} bytes replayed)", recorded.len(), replayed.len());
+            return;
+        }
+    };
+
+    for line in diff_lines(recorded_text, replayed_text) {
+        match line {
+            DiffLine::Same(line) => println!("      {}", line),
+            DiffLine::Removed(line) => println!("    {}", format!("- {}", line).red()),
+            DiffLine::Added(line) => println!("    {}", format!("+ {}", line).green()),
+        }
+    }
+}
+
+/// Redacts the values in a replayed JSON value that were redacted in the recorded one
+fn redact_matching(recorded: &Value, replayed: &mut Value) {
+    match (recorded, replayed) {
+        (Value::String(value), replayed) if value == REDACTED_VALUE => *replayed = recorded.clone(),
+        (Value::Object(recorded_fields), Value::Object(replayed_fields)) => {
+            for (name, recorded_value) in recorded_fields.iter() {
+                if let Some(replayed_value) = replayed_fields.get_mut(name) {
+                    redact_matching(recorded_value, replayed_value);
+                }
+            }
+        },
+        (Value::Array(recorded_items), Value::Array(replayed_items)) => {
+            for (recorded_item, replayed_item) in recorded_items.iter().zip(replayed_items.iter_mut()) {
+                redact_matching(recorded_item, replayed_item);
+            }
+        },
+        _ => {},
+    }
+}
+
+/// Redacts the fields of a replayed JSON response body that were redacted when the response was recorded, as the
+/// redacted values can't be compared. Both bodies are returned as they are if they aren't JSON
+fn redact_replayed_body(recorded: Vec<u8>, replayed: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+    let redacted = REDACTED_VALUE.as_bytes();
+    if !recorded.windows(redacted.len()).any(|window| window == redacted) {
+        return (recorded, replayed);
+    }
+
+    let (recorded_json, mut replayed_json) = match (serde_json::from_slice::<Value>(&recorded), serde_json::from_slice::<Value>(&replayed)) {
+        (Ok(recorded_json), Ok(replayed_json)) => (recorded_json, replayed_json),
+        _ => return (recorded, replayed),
+    };
+
+    redact_matching(&recorded_json, &mut replayed_json);
+    match (serde_json::to_vec(&recorded_json), serde_json::to_vec(&replayed_json)) {
+        (Ok(recorded), Ok(replayed)) => (recorded, replayed),
+        _ => (recorded, replayed),
+    }
+}
+
+/// Replays a recorded request against the function app, printing if the response matches the recorded one.
+/// This returns true if the response matches
+async fn replay_one(client: &RustlessClient, name: &String, index: usize, request: &RecordedRequest) -> bool {
+    let description = match request.query.is_empty() {
+        true => format!("[{}] {} /{}", index, request.method, request.route),
+        false => format!("[{}] {} /{}?{}", index, request.method, request.route, request.query),
+    };
+
+    // A truncated request body can't be replayed as it was sent
+    if request.body_truncated {
+        println!("{} {}", description.bold(), "skipped, the request body was too long to record in full".yellow());
+        return false;
+    }
+
+    let (status, mut body) = match client.replay_request(name, request).await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("{} {}", description.bold(), format!("❌ {}", e).red());
+            return false;
+        }
+    };
+
+    // Only the start of a long response body is recorded, so only that much of the replayed body is compared
+    let recorded_body = base64::decode(&request.response_body).unwrap_or_default();
+    if request.response_body_truncated {
+        body.truncate(recorded_body.len());
+    }
+
+    let (recorded_body, body) = redact_replayed_body(recorded_body, body);
+
+    if status == request.response_status && body == recorded_body {
+        println!("{} {}", description.bold(), format!("✅ {} matches", status).green());
+        return true;
+    }
+
+    println!("{} {}", description.bold(), "❌ differs".red());
+    if status != request.response_status {
+        println!("    Status: {} recorded, {} replayed", request.response_status, status);
+    }
+    if body != recorded_body {
+        print_body_diff(&recorded_body, &body);
+    }
+
+    false
+}
+
+/// Replays the most recent requests recorded for a function app against the current deployment,
+/// comparing the responses with the recorded ones
+pub async fn replay(conn: &Connection, name: &String, last: usize) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+
+    let requests = match client.recorded_requests(&FunctionAppRef::Name(name.to_string()), last).await {
+        Ok(Some(requests)) => requests,
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting recorded requests: {}", e))),
+    };
+
+    if requests.is_empty() {
+        println!("{}", format!("No requests have been recorded for '{}'. Turn recording on with the 'record' command", name).blue());
+        return Ok(());
+    }
+
+    println!("{}", format!("Replaying {} requests to '{}'", requests.len(), name).blue());
+
+    // Replay the requests one at a time, in the order they were recorded
+    let mut matched = 0;
+    for (index, request) in requests.iter().enumerate() {
+        if replay_one(&client, name, index + 1, request).await {
+            matched += 1;
+        }
+    }
+
+    if matched != requests.len() {
+        return Err(CliError::Message(format!("{} of {} responses differ", requests.len() - matched, requests.len())));
+    }
+
+    println!("{}", format!("✅ All {} responses match", matched).green());
+    Ok(())
+}
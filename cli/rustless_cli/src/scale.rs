@@ -0,0 +1,139 @@
+use chrono::{DateTime, Local, TimeZone};
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::{ReplicasReport, ScaleProfile, ScaleProfilesReport};
+
+use crate::cli;
+use crate::error::CliError;
+use crate::server;
+
+/// Formats when the replicas next change in the local timezone
+fn format_change_time(timestamp: u64) -> String {
+    let change_time: Option<DateTime<Local>> = Local.timestamp_opt(timestamp as i64, 0).single();
+    match change_time {
+        Some(change_time) => change_time.format("%a %d-%m-%Y %H:%M:%S %:z").to_string(),
+        None => format!("{} (Unix time)", timestamp),
+    }
+}
+
+/// Parses a profile given on the command line as SCHEDULE=REPLICAS, such as "0 9 * * 1-5=1". The schedule is
+/// split at the last =, so it can contain any characters
+fn parse_profile(profile: &str) -> Result<ScaleProfile, String> {
+    let (schedule, replicas) = match profile.rsplit_once('=') {
+        Some((schedule, replicas)) => (schedule.trim(), replicas.trim()),
+        None => return Err(format!("Invalid profile '{}'. Profiles are set as SCHEDULE=REPLICAS, such as \"0 9 * * 1-5=1\"", profile)),
+    };
+
+    match replicas.parse::<u32>() {
+        Ok(replicas) => Ok(ScaleProfile {
+            schedule: schedule.to_string(),
+            replicas,
+        }),
+        Err(_) => Err(format!("Invalid replicas '{}' in profile '{}'", replicas, profile)),
+    }
+}
+
+/// Prints the scale profiles for a function app, and the replicas they set now and next
+fn print_scale_profiles(name: &String, report: &ScaleProfilesReport) {
+    if report.profiles.is_empty() {
+        println!("{}", format!("'{}' doesn't have any scale profiles", name).blue());
+        return;
+    }
+
+    println!("{}", format!("Scale profiles for '{}', with schedules in UTC:", name).blue());
+    for profile in report.profiles.iter() {
+        println!("  {}  {} replicas", profile.schedule, profile.replicas);
+    }
+
+    match report.current_replicas {
+        Some(replicas) => println!("Scaled to {} replicas now", replicas),
+        None => println!("No profile has started yet"),
+    }
+
+    if let (Some(change_at), Some(replicas)) = (report.next_change_at, report.next_replicas) {
+        println!("Scales to {} replicas at {}", replicas, format_change_time(change_at));
+    }
+}
+
+/// Sets the scale profiles for a function app, or clears them if the list is empty, retrying by name if the cached
+/// ID is stale
+async fn set_scale_profiles_impl(conn: &Connection, name: &String, profiles: &[ScaleProfile]) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.set_scale_profiles(&app, profiles).await }).await;
+
+    match result {
+        Ok(Some(report)) => {
+            match profiles.is_empty() {
+                true => println!("{}", format!("✅ Cleared the scale profiles for '{}'", name).green()),
+                false => println!("{}", format!("✅ Set the scale profiles for '{}'", name).green()),
+            }
+            print_scale_profiles(name, &report);
+        },
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error setting scale profiles: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Sets the scale profiles for a function app from profiles given as SCHEDULE=REPLICAS, replacing any it has
+pub async fn set_scale_profiles(conn: &Connection, name: &String, profiles: &[String]) -> Result<(), CliError> {
+    let profiles: Result<Vec<ScaleProfile>, String> = profiles.iter().map(|profile| parse_profile(profile)).collect();
+    set_scale_profiles_impl(conn, name, &profiles?).await
+}
+
+/// Clears the scale profiles for a function app, so it is no longer started or stopped on a schedule
+pub async fn clear_scale_profiles(conn: &Connection, name: &String) -> Result<(), CliError> {
+    set_scale_profiles_impl(conn, name, &[]).await
+}
+
+/// Shows the scale profiles for a function app, and the replicas they set now and next
+pub async fn show_scale_profiles(conn: &Connection, name: &String) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.scale_profiles(&app).await }).await;
+
+    match result {
+        Ok(Some(report)) => print_scale_profiles(name, &report),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting scale profiles: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Prints the containers running a function app after it was scaled
+fn print_replicas(name: &String, report: &ReplicasReport) {
+    if report.running.is_empty() {
+        println!("{}", format!("'{}' is stopped. It runs {} replicas when it is started", name, report.replicas).blue());
+        return;
+    }
+
+    println!("{}", format!("'{}' is running {} of {} replicas:", name, report.running.len(), report.replicas).blue());
+    for replica in report.running.iter() {
+        match replica.healthy {
+            true => println!("  port {}  healthy", replica.port),
+            false => println!("  port {}  {}", replica.port, "starting or unhealthy".yellow()),
+        }
+    }
+}
+
+/// Scales a function app to a number of replicas, starting it if it is stopped. 0 stops the app
+pub async fn scale_function_app(conn: &Connection, name: &String, replicas: u32) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.scale(&app, replicas).await }).await;
+
+    match result {
+        Ok(Some(report)) => {
+            match replicas {
+                0 => println!("{}", format!("✅ Scaled '{}' to zero", name).green()),
+                replicas => println!("{}", format!("✅ Scaled '{}' to {} replicas", name, replicas).green()),
+            }
+            print_replicas(name, &report);
+        },
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error scaling function app: {}", e))),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,405 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use colored::Colorize;
+use ed25519_dalek::{Signature, VerifyingKey};
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// The GitHub API endpoint for the latest release of rustless
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/jimbobbennett/rust-less/releases/latest";
+
+/// The hex encoded ed25519 public key that release binaries are signed with. This is pinned into the CLI when it is
+/// built for a release, so a compromised release can't vouch for its own binaries. Builds without it can only check
+/// for updates, not install them
+const RELEASE_SIGNING_KEY: Option<&str> = option_env!("RUSTLESS_RELEASE_SIGNING_KEY");
+
+/// A release from the GitHub releases API
+#[derive(Deserialize)]
+struct Release {
+    // The tag for the release, such as v0.2.0
+    tag_name: String,
+
+    // The files attached to the release
+    assets: Vec<ReleaseAsset>,
+}
+
+/// A file attached to a GitHub release
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    // The file name
+    name: String,
+
+    // The URL to download the file from
+    browser_download_url: String,
+}
+
+/// A part of a pre-release, such as rc or 1 in 1.2.3-rc.1. Numbers come before text, as in semver
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum PreReleasePart {
+    /// A part that is all digits, compared as a number
+    Number(u64),
+
+    /// Any other part, compared as text
+    Text(String),
+}
+
+/// A version of the CLI, ordered as semver orders them, so 1.2.3-rc1 comes before 1.2.3
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Version {
+    // The major, minor, and patch numbers
+    numbers: (u64, u64, u64),
+
+    // Set if this isn't a pre-release, so a release comes after its pre-releases
+    is_release: bool,
+
+    // The dot separated parts of the pre-release, such as rc1
+    pre_release: Vec<PreReleasePart>,
+}
+
+/// Parses a version string such as v0.2.0 or v0.2.0-rc.1 so versions can be compared. Build metadata, such as
+/// +build.5, is ignored
+fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim_start_matches('v');
+    let version = version.split('+').next()?;
+    let (numbers, pre_release) = match version.split_once('-') {
+        Some((numbers, pre_release)) => (numbers, Some(pre_release)),
+        None => (version, None),
+    };
+
+    let mut parts = numbers.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let pre_release = match pre_release {
+        Some(pre_release) => {
+            let mut pre_release_parts = Vec::new();
+            for part in pre_release.split('.') {
+                if part.is_empty() {
+                    return None;
+                }
+
+                pre_release_parts.push(match part.chars().all(|c| c.is_ascii_digit()) {
+                    true => PreReleasePart::Number(part.parse().ok()?),
+                    false => PreReleasePart::Text(part.to_string()),
+                });
+            }
+            pre_release_parts
+        },
+        None => Vec::new(),
+    };
+
+    Some(Version { numbers: (major, minor, patch), is_release: pre_release.is_empty(), pre_release })
+}
+
+/// Gets the name of the release asset containing the CLI for this platform, such as rustless_cli-x86_64-linux
+fn get_asset_name() -> String {
+    format!("rustless_cli-{}-{}{}", env::consts::ARCH, env::consts::OS, env::consts::EXE_SUFFIX)
+}
+
+/// Creates the HTTP client for calling GitHub. GitHub requires a user agent on all requests
+fn create_client() -> Result<Client, String> {
+    match Client::builder().user_agent(format!("rustless_cli/{}", env!("CARGO_PKG_VERSION"))).build() {
+        Ok(client) => Ok(client),
+        Err(e) => Err(format!("Error creating HTTPS client: {}", e)),
+    }
+}
+
+/// Gets the latest release from GitHub
+async fn get_latest_release(client: &Client) -> Result<Release, String> {
+    let res = match client.get(LATEST_RELEASE_URL).send().await {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error checking for updates: {}", e)),
+    };
+
+    if res.status() != 200 {
+        return Err(format!("Release server returned status code: {}", res.status()));
+    }
+
+    match res.json::<Release>().await {
+        Ok(release) => Ok(release),
+        Err(e) => Err(format!("Error parsing release details: {}", e)),
+    }
+}
+
+/// Downloads a file, returning the contents
+async fn download(client: &Client, url: &str) -> Result<Vec<u8>, String> {
+    let res = match client.get(url).send().await {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error downloading {}: {}", url, e)),
+    };
+
+    if res.status() != 200 {
+        return Err(format!("Error downloading {}: status code {}", url, res.status()));
+    }
+
+    match res.bytes().await {
+        Ok(bytes) => Ok(bytes.to_vec()),
+        Err(e) => Err(format!("Error downloading {}: {}", url, e)),
+    }
+}
+
+/// Gets the message that is signed for a release binary - the release tag, the asset name, and the SHA-256 hash of
+/// the binary, separated by spaces. Signing the tag and name as well as the binary stops an older signed binary, or
+/// one for another platform, being passed off as this one
+fn get_signed_message(tag_name: &str, asset_name: &str, binary: &[u8]) -> String {
+    format!("{} {} {}", tag_name, asset_name, hex::encode(Sha256::digest(binary)))
+}
+
+/// Checks the downloaded binary was signed by the release signing key
+///
+/// The signature file holds the hex encoded ed25519 signature of the message from get_signed_message
+fn verify_signature(public_key: &str, tag_name: &str, asset_name: &str, binary: &[u8], signature_file: &[u8]) -> Result<(), String> {
+    let public_key = match hex::decode(public_key.trim()).ok().and_then(|key| <[u8; 32]>::try_from(key).ok()) {
+        Some(public_key) => public_key,
+        None => return Err("The release signing key built into the CLI is not valid".to_string()),
+    };
+
+    let public_key = match VerifyingKey::from_bytes(&public_key) {
+        Ok(public_key) => public_key,
+        Err(e) => return Err(format!("The release signing key built into the CLI is not valid: {}", e)),
+    };
+
+    let signature = String::from_utf8_lossy(signature_file);
+    let signature = match hex::decode(signature.trim()).ok().and_then(|signature| <[u8; 64]>::try_from(signature).ok()) {
+        Some(signature) => Signature::from_bytes(&signature),
+        None => return Err("The published signature is not a valid ed25519 signature".to_string()),
+    };
+
+    let message = get_signed_message(tag_name, asset_name, binary);
+    if public_key.verify_strict(message.as_bytes(), &signature).is_err() {
+        return Err(format!("The signature for {} in release {} does not match the release signing key", asset_name, tag_name));
+    }
+
+    Ok(())
+}
+
+/// Replaces the running executable with the new binary
+///
+/// The new binary is written next to the current one then renamed over it, so the swap is atomic
+/// and a failed download never leaves a half written executable behind.
+fn replace_current_exe(binary: &[u8]) -> Result<PathBuf, String> {
+    let current_exe = match env::current_exe() {
+        Ok(current_exe) => current_exe,
+        Err(e) => return Err(format!("Error finding the current executable: {}", e)),
+    };
+
+    let new_exe = current_exe.with_extension("new");
+    if let Err(e) = fs::write(&new_exe, binary) {
+        return Err(format!("Error writing {}: {}", new_exe.display(), e));
+    }
+
+    // Make sure the new binary can be run
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(e) = fs::set_permissions(&new_exe, fs::Permissions::from_mode(0o755)) {
+            let _ = fs::remove_file(&new_exe);
+            return Err(format!("Error setting permissions on {}: {}", new_exe.display(), e));
+        }
+    }
+
+    // Windows won't let a running executable be replaced, but it can be renamed out of the way
+    #[cfg(windows)]
+    {
+        let old_exe = current_exe.with_extension("old");
+        let _ = fs::remove_file(&old_exe);
+        if let Err(e) = fs::rename(&current_exe, &old_exe) {
+            let _ = fs::remove_file(&new_exe);
+            return Err(format!("Error moving the current executable: {}", e));
+        }
+    }
+
+    if let Err(e) = fs::rename(&new_exe, &current_exe) {
+        let _ = fs::remove_file(&new_exe);
+
+        // Put the current executable back, otherwise the CLI is left missing
+        #[cfg(windows)]
+        {
+            let old_exe = current_exe.with_extension("old");
+            if let Err(restore_error) = fs::rename(&old_exe, &current_exe) {
+                return Err(format!(
+                    "Error replacing {}: {}. The previous version could not be restored from {}: {}",
+                    current_exe.display(),
+                    e,
+                    old_exe.display(),
+                    restore_error
+                ));
+            }
+        }
+
+        return Err(format!("Error replacing {}: {}", current_exe.display(), e));
+    }
+
+    Ok(current_exe)
+}
+
+/// Checks for a new release of the CLI, and if there is one, downloads and installs it
+///
+/// If check_only is set, this only reports if an update is available
+pub async fn update(check_only: bool) -> Result<(), String> {
+    let current_version = env!("CARGO_PKG_VERSION");
+    println!("{}", format!("Current version: {}", current_version).blue());
+
+    let client = create_client()?;
+    let release = get_latest_release(&client).await?;
+
+    // Check if the release is newer than this version
+    let is_newer = match (parse_version(&release.tag_name), parse_version(current_version)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => return Err(format!("Unable to compare release version {} with {}", release.tag_name, current_version)),
+    };
+
+    if !is_newer {
+        println!("{}", "✅ The CLI is up to date".green());
+        return Ok(());
+    }
+
+    println!("{}", format!("A new version is available: {}", release.tag_name).green().bold());
+
+    if check_only {
+        println!("{}", "Run 'rustless_cli self update' to install it".blue());
+        return Ok(());
+    }
+
+    // Without the pinned key there is nothing trusted to check the download against
+    let public_key = match RELEASE_SIGNING_KEY {
+        Some(public_key) => public_key,
+        None => return Err("This build of the CLI has no release signing key, so updates can't be verified. Install the new version manually".to_string()),
+    };
+
+    // Find the binary and signature for this platform
+    let asset_name = get_asset_name();
+    let signature_name = format!("{}.sig", asset_name);
+
+    let binary_asset = release.assets.iter().find(|asset| asset.name == asset_name);
+    let signature_asset = release.assets.iter().find(|asset| asset.name == signature_name);
+
+    let (binary_asset, signature_asset) = match (binary_asset, signature_asset) {
+        (Some(binary_asset), Some(signature_asset)) => (binary_asset, signature_asset),
+        (None, _) => return Err(format!("Release {} does not have a binary for this platform ({})", release.tag_name, asset_name)),
+        (_, None) => return Err(format!("Release {} does not have a signature for {}", release.tag_name, asset_name)),
+    };
+
+    // Download and verify the new binary
+    println!("{}", format!("Downloading {}...", asset_name).blue());
+    let binary = download(&client, &binary_asset.browser_download_url).await?;
+    let signature = download(&client, &signature_asset.browser_download_url).await?;
+
+    verify_signature(public_key, &release.tag_name, &asset_name, &binary, &signature)?;
+    println!("{}", "✅ Signature verified".green());
+
+    // Swap the binary
+    let installed_path = replace_current_exe(&binary)?;
+    println!(
+        "{}",
+        format!("✅ Updated to {} at {}", release.tag_name, installed_path.display()).green()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions_with_and_without_v() {
+        assert_eq!(parse_version("v1.2.3").unwrap().numbers, (1, 2, 3));
+        assert_eq!(parse_version("0.10.0").unwrap().numbers, (0, 10, 0));
+    }
+
+    #[test]
+    fn parses_pre_releases() {
+        let version = parse_version("v1.2.3-rc1").unwrap();
+        assert_eq!(version.numbers, (1, 2, 3));
+        assert!(!version.is_release);
+        assert_eq!(version.pre_release, vec![PreReleasePart::Text("rc1".to_string())]);
+    }
+
+    #[test]
+    fn ignores_build_metadata() {
+        assert_eq!(parse_version("1.2.3+build.5"), parse_version("1.2.3"));
+        assert_eq!(parse_version("1.2.3-rc.1+build.5"), parse_version("1.2.3-rc.1"));
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        assert_eq!(parse_version("v1.2"), None);
+        assert_eq!(parse_version("v1.2.3.4"), None);
+        assert_eq!(parse_version("v1.x.3"), None);
+        assert_eq!(parse_version("v1.2.3-"), None);
+        assert_eq!(parse_version("v1.2.3-rc..1"), None);
+        assert_eq!(parse_version("latest"), None);
+    }
+
+    #[test]
+    fn orders_pre_releases_before_the_release() {
+        assert!(parse_version("v1.2.3-rc1") < parse_version("v1.2.3"));
+        assert!(parse_version("v1.2.3-rc1") > parse_version("v1.2.2"));
+        assert!(parse_version("v1.2.3-alpha") < parse_version("v1.2.3-beta"));
+    }
+
+    #[test]
+    fn orders_pre_release_numbers_numerically() {
+        assert!(parse_version("1.2.3-rc.2") < parse_version("1.2.3-rc.10"));
+        assert!(parse_version("1.2.3-rc") < parse_version("1.2.3-rc.1"));
+        assert!(parse_version("1.2.3-1") < parse_version("1.2.3-rc"));
+    }
+
+    #[test]
+    fn orders_numbers_numerically() {
+        assert!(parse_version("v0.10.0") > parse_version("v0.9.9"));
+        assert!(parse_version("v2.0.0") > parse_version("v1.99.99"));
+    }
+
+    /// Signs a binary the way a release is signed, returning the public key and signature file
+    fn sign(tag_name: &str, asset_name: &str, binary: &[u8]) -> (String, Vec<u8>) {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let signature = signing_key.sign(get_signed_message(tag_name, asset_name, binary).as_bytes());
+        (hex::encode(signing_key.verifying_key().to_bytes()), hex::encode(signature.to_bytes()).into_bytes())
+    }
+
+    #[test]
+    fn accepts_a_signed_binary() {
+        let (public_key, signature) = sign("v1.2.3", "rustless_cli-x86_64-linux", b"binary");
+        assert!(verify_signature(&public_key, "v1.2.3", "rustless_cli-x86_64-linux", b"binary", &signature).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_changed_binary() {
+        let (public_key, signature) = sign("v1.2.3", "rustless_cli-x86_64-linux", b"binary");
+        assert!(verify_signature(&public_key, "v1.2.3", "rustless_cli-x86_64-linux", b"tampered", &signature).is_err());
+    }
+
+    #[test]
+    fn rejects_a_signature_for_another_release_or_platform() {
+        let (public_key, signature) = sign("v1.2.2", "rustless_cli-x86_64-linux", b"binary");
+        assert!(verify_signature(&public_key, "v1.2.3", "rustless_cli-x86_64-linux", b"binary", &signature).is_err());
+
+        let (public_key, signature) = sign("v1.2.3", "rustless_cli-aarch64-macos", b"binary");
+        assert!(verify_signature(&public_key, "v1.2.3", "rustless_cli-x86_64-linux", b"binary", &signature).is_err());
+    }
+
+    #[test]
+    fn rejects_a_signature_from_another_key() {
+        let (_, signature) = sign("v1.2.3", "rustless_cli-x86_64-linux", b"binary");
+        let other_key = hex::encode(ed25519_dalek::SigningKey::from_bytes(&[9; 32]).verifying_key().to_bytes());
+        assert!(verify_signature(&other_key, "v1.2.3", "rustless_cli-x86_64-linux", b"binary", &signature).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_keys_and_signatures() {
+        let (public_key, signature) = sign("v1.2.3", "rustless_cli-x86_64-linux", b"binary");
+        assert!(verify_signature("not hex", "v1.2.3", "rustless_cli-x86_64-linux", b"binary", &signature).is_err());
+        assert!(verify_signature(&public_key, "v1.2.3", "rustless_cli-x86_64-linux", b"binary", b"abcd").is_err());
+    }
+}
@@ -1,410 +1,207 @@
-use colored::Colorize;
-use reqwest::{Client, Error};
-use rusqlite::{Connection, Result};
-use uuid::Uuid;
+use std::net::Ipv6Addr;
+use std::sync::OnceLock;
 
-use rustless_shared::{FunctionApp, FunctionAppStatus, FunctionAppStatusResult, FunctionAppNameRequest};
+use rusqlite::Connection;
 
-use crate::storage;
+use rustless_client::RustlessClient;
 
-/// Builds a HTTPS request client. In debug mode, this ignores invalid certs so it can be run locally
-#[cfg(debug_assertions)]
-fn get_builder() -> Result<Client, Error> {
-    Client::builder().danger_accept_invalid_certs(true).build()
-}
+pub use rustless_client::{format_address, FunctionAppRef, InvokeResponse, UploadResult};
 
-/// Builds a HTTPS request client. In release mode, this does not invalid certs so it can't be run locally
-#[cfg(not(debug_assertions))]
-fn get_builder() -> Result<Client, Error> {
-    Client::builder().build()
-}
+use crate::storage::{self, Server};
 
-/// Test the server to see if it is available
-///
-/// The server will respond on a request to url:port/hello with Hello from rustless!
-/// and a 200 status code if it is a valid server
-pub async fn test_server(hostname: &String, port: u16) -> Result<(), String> {
-    // Create the url from the hostname and port
-    let url = format!("https://{}:{}/hello", hostname, port);
-
-    // Get the request client
-    let builder = get_builder();
-    let client = match builder {
-        Ok(client) => client,
-        Err(e) => return Err(format!("Error creating client: {}", e)),
-    };
+/// The port used for a server if none is given
+pub const DEFAULT_PORT: u16 = 80;
 
-    // Make the request
-    let res = client.get(url).send().await;
-
-    // Check the response
-    match res {
-        Ok(res) => {
-            // If the server is correct, we should get a 200 status code
-            if res.status() != 200 {
-                return Err(format!("Server returned status code: {}", res.status()));
-            }
-
-            // If we got a 200, check the test we get back to see if it matches what is expected
-            // If not, return an error
-            match res.text().await {
-                Ok(text) => {
-                    if text != "Hello from rustless!" {
-                        return Err(format!("Server returned unexpected text: {}", text));
-                    }
-                }
-                Err(e) => {
-                    return Err(format!("Error reading response text: {}", e));
-                }
-            }
-
-            // If everything works, return Ok.
-            Ok(())
-        }
-        Err(err) => Err(format!("Error: {}", err)),
-    }
-}
+/// The API key for the current server, with the server it was stored for, read from the database once
+static API_KEY: OnceLock<Option<(Server, String)>> = OnceLock::new();
 
-/// Registers a function app with the server
-pub async fn register_function_app(conn: &Connection, name: &String) -> Uuid {
-    let result = call_post_function_app(conn, name).await;
+/// Gets the API key to send to a server. Keys are only sent to the server they were stored for with rustless login,
+/// not to the other servers in the profiles. Servers behind the same proxy under different paths are different servers
+fn get_api_key(server: &Server) -> Option<&'static str> {
+    let api_key = API_KEY.get_or_init(|| {
+        let conn = storage::create_connection().ok()?;
+        let server = storage::get_server(&conn).ok()?;
+        let key = storage::get_api_key(&conn).ok()??;
+        Some((server, key))
+    });
 
-    match result {
-        Ok(id) => id,
-        Err(e) => {
-            let error_message = format!("Error adding function app: {}", e).red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
-        }
+    match api_key {
+        Some((key_server, key)) if key_server.hostname == server.hostname && key_server.port == server.port
+            && key_server.base_path == server.base_path => Some(key),
+        _ => None,
     }
 }
 
-/// Calls the server to add a function app
-async fn call_post_function_app(conn: &Connection, name: &String) -> Result<Uuid, String> {
-    // Get the server from the database
-    let server = match storage::get_server(&conn) {
-        Ok(server) => server,
-        Err(_) => {
-            return Err(
-                "No server set. Use the 'set-server' command to set the server.".to_string(),
-            )
-        }
-    };
-
-    let test_result = test_server(&server.hostname, server.port).await;
-    if test_result.is_err() {
-        return Err(format!(
-            "Error testing server: {}. Is the server set correctly",
-            test_result.err().unwrap()
-        ));
-    }
-
-    // Create the url from the hostname and port
-    let url = format!("https://{}:{}/function-apps", server.hostname, server.port);
+/// Formats a server as it is written in a URL, with the path it is served under if it is behind a reverse proxy,
+/// such as example.com:443/rustless
+pub fn format_server(server: &Server) -> String {
+    format!("{}{}", format_address(&server.hostname, server.port), server.base_path)
+}
 
-    let builder = get_builder();
-    let client = match builder {
-        Ok(client) => client,
-        Err(e) => return Err(format!("Error creating HTTPS client: {}", e)),
+/// Gets a server from the hostname given to set-server or profile add, and the port and base path if they were
+/// given. IPv6 addresses can be written in brackets, such as [::1], with the port after them, such as
+/// [::1]:8080, or without brackets and a port, such as ::1. They are returned without the brackets, which are added
+/// back when URLs are built. The port defaults to 80. For servers behind a reverse proxy, the path they are served
+/// under can be written after the hostname, such as example.com/rustless, or given as the base path
+pub fn parse_server(hostname: &str, port: Option<u16>, base_path: &Option<String>) -> Result<Server, String> {
+    let (hostname, hostname_path) = match hostname.trim().split_once('/') {
+        Some((hostname, path)) => (hostname, Some(rustless_client::normalize_base_path(path))),
+        None => (hostname.trim(), None),
     };
 
-    // Build some JSON containing the function app name
-    let json = FunctionAppNameRequest{ 
-        name: name.to_string() 
+    let base_path = match (base_path.as_deref().map(rustless_client::normalize_base_path), hostname_path) {
+        (Some(base_path), Some(hostname_path)) if base_path != hostname_path => {
+            return Err(format!("The server {} has the path {}, but the base path {} was given as well", hostname, hostname_path, base_path));
+        },
+        (Some(base_path), _) | (None, Some(base_path)) => base_path,
+        (None, None) => String::new(),
     };
 
-    // Make the request
-    let res = client.post(url).json(&json).send().await;
-
-    // Check the response
-    match res {
-        Ok(res) => {
-            // If the server is correct, we should get a 200 status code
-            if res.status() != 200 {
-                if res.status() == 409 {
-                    return Err(
-                        format!("A function app already exists that is named '{}'", name)
-                            .to_string(),
-                    );
-                }
-
-                return Err(format!("Server returned status code: {}", res.status()));
-            }
-
-            // We are expecting an ID back if this works
-            match res.text().await {
-                Ok(id) => match Uuid::parse_str(&id) {
-                    Ok(id) => Ok(id),
-                    Err(e) => Err(format!("Error parsing ID: {}", e)),
-                },
-                Err(e) => Err(format!("Error reading response text: {}", e)),
-            }
-        }
-        Err(err) => Err(format!("Error: {}", err)),
+    if base_path.contains(['?', '#', ' ']) || base_path.split('/').skip(1).any(|segment| segment.is_empty() || segment == "..") {
+        return Err(format!("Invalid base path {}: it must be a path such as /rustless", base_path));
     }
-}
 
-/// Uploads the code to the server
-pub async fn post_app_code(conn: &Connection, id: &Uuid, zip_file_buffer: &String) {
-    // Get the server
-    let server = match storage::get_server(&conn) {
-        Ok(server) => server,
-        Err(_) => {
-            let error_message = format!("No server set. Use the 'set-server' command to set the server.").red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
-        }
+    let (address, address_port) = match hostname.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((address, "")) => (address, None),
+            Some((address, suffix)) => match suffix.strip_prefix(':').and_then(|suffix| suffix.parse::<u16>().ok()) {
+                Some(address_port) => (address, Some(address_port)),
+                None => return Err(format!("Invalid server {}: the port after the IPv6 address must be a number, such as [::1]:8080", hostname)),
+            },
+            None => return Err(format!("Invalid server {}: the IPv6 address is missing its closing bracket", hostname)),
+        },
+        None => (hostname, None),
     };
 
-    // Create the url from the hostname and port
-    let url = format!("https://{}:{}/function-apps/{}/code", server.hostname, server.port, id.to_string());
-
-    let builder = get_builder();
-    let client = match builder {
-        Ok(client) => client,
-        Err(e) => {
-            let error_message = format!("Error creating HTTPS client: {}", e).red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
+    // Hostnames can't contain colons, so anything with one must be an IPv6 address
+    if address.contains(':') || hostname.starts_with('[') {
+        if let Err(e) = address.parse::<Ipv6Addr>() {
+            return Err(format!("Invalid IPv6 address {}: {}", address, e));
         }
-    };
+    }
 
-    // Make the request
-    let res = client.post(url).body(zip_file_buffer.to_string()).send().await;
-
-    // Check the response
-    match res {
-        Ok(res) => {
-            // If the server is correct, we should get a 200 status code
-            if res.status() != 200 {
-                let error_message = format!("Server returned status code: {}", res.status()).red().bold();
-                println!("{}", error_message);
-                let error_message = format!("Server returned error: {}", res.text().await.unwrap()).red().bold();
-                println!("{}", error_message);
-                std::process::exit(-1);
-            }
-        }
-        Err(e) => {
-            let error_message = format!("Error: {}", e).red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
-        }
+    let port = match (port, address_port) {
+        (Some(port), Some(address_port)) if port != address_port => {
+            return Err(format!("The server {} has port {}, but port {} was given as well", hostname, address_port, port));
+        },
+        (Some(port), _) | (None, Some(port)) => port,
+        (None, None) => DEFAULT_PORT,
     };
+
+    Ok(Server {
+        hostname: address.to_string(),
+        port,
+        base_path,
+    })
+}
+
+/// Creates a client for a server, under the path it is served under if it is behind a reverse proxy, sending the API
+/// key for the server as a bearer token if one has been stored
+pub fn get_client(server: &Server) -> Result<RustlessClient, String> {
+    let client = RustlessClient::with_api_key(&server.hostname, server.port, get_api_key(server))?;
+    Ok(client.with_base_path(&server.base_path))
 }
 
-/// Get the ID for the function app with the given name
-pub async fn get_id_for_function_app(conn: &Connection, name: &String) -> Uuid {
-    // Get the server
-    let server = match storage::get_server(&conn) {
+/// Creates a client for the current server, set with the set-server command
+pub fn get_server_client(conn: &Connection) -> Result<RustlessClient, String> {
+    let server = match storage::get_server(conn) {
         Ok(server) => server,
-        Err(_) => {
-            println!("{}", format!("No server set. Use the 'set-server' command to set the server.").red().bold());
-            std::process::exit(-1);
-        }
+        Err(_) => return Err("No server set. Use the 'set-server' command to set the server.".to_string()),
     };
 
-    // Create the url from the hostname and port
-    let url = format!("https://{}:{}/function-apps/{}/id", server.hostname, server.port, name);
+    get_client(&server)
+}
 
-    let builder = get_builder();
-    let client = match builder {
-        Ok(client) => client,
-        Err(e) => {
-            println!("{}", format!("Error creating HTTPS client: {}", e).red().bold());
-            std::process::exit(-1);
-        }
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Make the request
-    let res = client.get(url).send().await;
-
-    match res {
-        Ok(res) => {
-            if res.status() == 404 {
-                println!("{}", format!("No function app with the name '{}' exists", name).red().bold());
-                std::process::exit(-1);
-            }
-
-            // If the server is correct, we should get a 200 status code
-            if res.status() != 200 {
-                println!("{}", format!("Server returned status code: {}", res.status()).red().bold());
-                std::process::exit(-1);
-            }
-
-            // We are expecting an ID back if this works
-            let id = match res.text().await {
-                Ok(id) => id,
-                Err(e) => {
-                    println!("{}", format!("Error reading response text: {}", e).red().bold());
-                    std::process::exit(-1);
-                }
-            };
-
-            match Uuid::parse_str(&id) {
-                Ok(id) => id,
-                Err(e) => {
-                    println!("{}", format!("Error parsing ID: {}", e).red().bold());
-                    std::process::exit(-1);
-                }
-            }
-        }
-        Err(e) => {
-            println!("{}", format!("Error: {}", e).red().bold());
-            std::process::exit(-1);
-        }
+    /// Parses a server without a base path, returning its hostname and port
+    fn parse(hostname: &str, port: Option<u16>) -> Result<(String, u16), String> {
+        parse_server(hostname, port, &None).map(|server| (server.hostname, server.port))
     }
-}
 
-/// Gets all the function apps from the server
-pub async fn list_function_apps(conn: &Connection) -> Vec<FunctionApp> {
-    // Get the server
-    let server = match storage::get_server(&conn) {
-        Ok(server) => server,
-        Err(_) => {
-            println!("{}", format!("No server set. Use the 'set-server' command to set the server.").red().bold());
-            std::process::exit(-1);
-        }
-    };
+    #[test]
+    fn parses_bracketed_ipv6_with_a_port() {
+        assert_eq!(parse("[::1]:8080", None), Ok(("::1".to_string(), 8080)));
+        assert_eq!(parse(" [2001:db8::10]:443 ", None), Ok(("2001:db8::10".to_string(), 443)));
+    }
 
-    // Create the url from the hostname and port
-    let url = format!("https://{}:{}/function-apps", server.hostname, server.port);
+    #[test]
+    fn parses_ipv6_without_a_port() {
+        assert_eq!(parse("[::1]", None), Ok(("::1".to_string(), DEFAULT_PORT)));
+        assert_eq!(parse("::1", Some(8080)), Ok(("::1".to_string(), 8080)));
+    }
 
-    let builder = get_builder();
-    let client = match builder {
-        Ok(client) => client,
-        Err(e) => {
-            println!("{}", format!("Error creating HTTPS client: {}", e).red().bold());
-            std::process::exit(-1);
-        }
-    };
+    #[test]
+    fn parses_hostnames() {
+        assert_eq!(parse("example.com", None), Ok(("example.com".to_string(), DEFAULT_PORT)));
+        assert_eq!(parse("127.0.0.1", Some(8080)), Ok(("127.0.0.1".to_string(), 8080)));
+    }
 
-    // Make the request
-    let res = client.get(url).send().await;
-
-    // Check the response
-    match res {
-        Ok(res) => {
-            // If the server is correct, we should get a 200 status code
-            if res.status() != 200 {
-                let error_message = format!("Server returned status code: {}", res.status()).red().bold();
-                println!("{}", error_message);
-                let error_message = format!("Server returned error: {}", res.text().await.unwrap()).red().bold();
-                println!("{}", error_message);
-                std::process::exit(-1);
-            }
-
-            let response_json = res.json::<Vec<FunctionApp>>().await;
-            match response_json {
-                Ok(response_json) => response_json,
-                Err(e) => {
-                    let error_message = format!("Error parsing JSON: {}", e).red().bold();
-                    println!("{}", error_message);
-                    std::process::exit(-1);
-                }
-            }
-        }
-        Err(e) => {
-            let error_message = format!("Error: {}", e).red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
-        }
+    #[test]
+    fn accepts_the_same_port_twice() {
+        assert_eq!(parse("[::1]:8080", Some(8080)), Ok(("::1".to_string(), 8080)));
     }
-}
 
-/// Starts a function app running
-pub async fn start_function_app(conn: &Connection, id: &Uuid) {
-    // Get the server
-    let server = match storage::get_server(&conn) {
-        Ok(server) => server,
-        Err(_) => {
-            println!("{}", format!("No server set. Use the 'set-server' command to set the server.").red().bold());
-            std::process::exit(-1);
-        }
-    };
+    #[test]
+    fn rejects_different_ports() {
+        assert!(parse("[::1]:8080", Some(9090)).unwrap_err().contains("port 9090 was given as well"));
+    }
 
-    // Create the url from the hostname and port
-    let url = format!("https://{}:{}/function-apps/{}/start", server.hostname, server.port, id.to_string());
+    #[test]
+    fn rejects_invalid_ports() {
+        assert!(parse("[::1]:", None).is_err());
+        assert!(parse("[::1]:http", None).is_err());
+        assert!(parse("[::1]:70000", None).is_err());
+        assert!(parse("[::1]8080", None).is_err());
+    }
 
-    let builder = get_builder();
-    let client = match builder {
-        Ok(client) => client,
-        Err(e) => {
-            println!("{}", format!("Error creating HTTPS client: {}", e).red().bold());
-            std::process::exit(-1);
-        }
-    };
+    #[test]
+    fn rejects_invalid_ipv6_addresses() {
+        assert!(parse("[::1", None).unwrap_err().contains("closing bracket"));
+        assert!(parse("[example.com]", None).is_err());
+        assert!(parse("[::g]:8080", None).is_err());
+        assert!(parse("localhost:8080", None).is_err());
+    }
 
-    // Make the request
-    let res = client.post(url).send().await;
-
-    // Check the response
-    match res {
-        Ok(res) => {
-            // If the server is correct, we should get a 200 status code
-            if res.status() != 200 {
-                let error_message = format!("Server returned status code: {}", res.status()).red().bold();
-                println!("{}", error_message);
-                let error_message = format!("Server returned error: {}", res.text().await.unwrap()).red().bold();
-                println!("{}", error_message);
-                std::process::exit(-1);
-            }
-        }
-        Err(e) => {
-            let error_message = format!("Error: {}", e).red().bold();
-            println!("{}", error_message);
-            std::process::exit(-1);
-        }
-    };
-}
+    #[test]
+    fn reads_the_base_path_after_the_hostname() {
+        let server = parse_server("example.com/rustless/", Some(443), &None).unwrap();
+        assert_eq!((server.hostname.as_str(), server.port, server.base_path.as_str()), ("example.com", 443, "/rustless"));
 
-/// Get the status for the function app with the given Id
-pub async fn get_status_for_function_app(conn: &Connection, id: &Uuid) -> FunctionAppStatus {
-    // Get the server
-    let server = match storage::get_server(&conn) {
-        Ok(server) => server,
-        Err(_) => {
-            println!("{}", format!("No server set. Use the 'set-server' command to set the server.").red().bold());
-            std::process::exit(-1);
-        }
-    };
+        let server = parse_server("[::1]:8080/apps/rustless", None, &None).unwrap();
+        assert_eq!((server.hostname.as_str(), server.port, server.base_path.as_str()), ("::1", 8080, "/apps/rustless"));
+    }
 
-    // Create the url from the hostname and port
-    let url = format!("https://{}:{}/function-apps/{}/status", server.hostname, server.port, id);
+    #[test]
+    fn normalizes_the_given_base_path() {
+        let server = parse_server("example.com", None, &Some("rustless/".to_string())).unwrap();
+        assert_eq!(server.base_path, "/rustless");
 
-    let builder = get_builder();
-    let client = match builder {
-        Ok(client) => client,
-        Err(e) => {
-            println!("{}", format!("Error creating HTTPS client: {}", e).red().bold());
-            std::process::exit(-1);
-        }
-    };
+        let server = parse_server("example.com/rustless", None, &Some("/rustless".to_string())).unwrap();
+        assert_eq!(server.base_path, "/rustless");
 
-    // Make the request
-    let res = client.get(url).send().await;
-
-    match res {
-        Ok(res) => {
-            // If the server is correct, we should get a 200 status code
-            if res.status() != 200 {
-                println!("{}", format!("Server returned status code: {}", res.status()).red().bold());
-                std::process::exit(-1);
-            }
-
-            // Get the response JSON
-            let json = res.json::<FunctionAppStatusResult>().await;
-
-            match json {
-                Ok(json) => json.status,
-                Err(e) => {
-                    println!("{}", format!("Error parsing JSON: {}", e).red().bold());
-                    std::process::exit(-1);
-                }
-            }
-        }
-        Err(e) => {
-            println!("{}", format!("Error: {}", e).red().bold());
-            std::process::exit(-1);
-        }
+        let server = parse_server("example.com/", None, &None).unwrap();
+        assert_eq!(server.base_path, "");
+    }
+
+    #[test]
+    fn rejects_different_base_paths() {
+        assert!(parse_server("example.com/rustless", None, &Some("/other".to_string())).unwrap_err().contains("base path /other was given as well"));
+    }
+
+    #[test]
+    fn rejects_invalid_base_paths() {
+        assert!(parse_server("example.com/rustless/../admin", None, &None).is_err());
+        assert!(parse_server("example.com/a//b", None, &None).is_err());
+        assert!(parse_server("example.com/rustless?x=1", None, &None).is_err());
+        assert!(parse_server("example.com", None, &Some("/rust less".to_string())).is_err());
+    }
+
+    #[test]
+    fn formats_parsed_servers_back_to_the_same_address() {
+        let (hostname, port) = parse("[::1]:8080", None).unwrap();
+        assert_eq!(format_address(&hostname, port), "[::1]:8080");
     }
-}
\ No newline at end of file
+}
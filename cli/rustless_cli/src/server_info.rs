@@ -0,0 +1,81 @@
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::QuotaUsage;
+
+use crate::error::CliError;
+use crate::server;
+
+/// Formats an amount of a quota, such as 3 apps or 1.5 GiB
+fn format_amount(quota: &QuotaUsage, amount: u64) -> String {
+    if quota.name == "apps" {
+        return format!("{} apps", amount);
+    }
+
+    let units = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = amount as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} bytes", amount),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
+
+/// Prints how much of a quota is used, colored by how close it is to the limit
+fn print_quota(quota: &QuotaUsage) {
+    let label = format!("  {:<8}", format!("{}:", quota.name));
+
+    if let Some(error) = &quota.error {
+        println!("{}{}", label, format!("unable to measure: {}", error).red());
+        return;
+    }
+
+    let used = format_amount(quota, quota.used);
+    let (hard_limit, soft_limit) = match (quota.hard_limit, quota.soft_limit) {
+        (Some(hard_limit), Some(soft_limit)) => (hard_limit, soft_limit),
+        _ => {
+            println!("{}{} used, no limit", label, used);
+            return;
+        }
+    };
+
+    let line = format!("{}{} used of {} ({}%)", label, used, format_amount(quota, hard_limit), quota.used * 100 / hard_limit.max(1));
+    if quota.used >= hard_limit {
+        println!("{}", format!("{} - limit reached", line).red().bold());
+    } else if quota.used >= soft_limit {
+        println!("{}", format!("{} - over the warning level of {}", line, format_amount(quota, soft_limit)).yellow());
+    } else {
+        println!("{}", line.green());
+    }
+}
+
+/// Shows the current server's version and how much of its quotas are used
+pub async fn show_server_info(conn: &Connection) -> Result<(), CliError> {
+    let client = server::get_server_client(conn)?;
+
+    println!("{}", format!("Server: {}{}", client.address(), client.base_path()).blue());
+    match client.version().await {
+        Ok(version) => {
+            println!("  Version:   {}", version.version);
+            println!("  Features:  {}", version.features.join(", "));
+        },
+        Err(e) => println!("{}", format!("Error getting the server version: {}", e).red()),
+    }
+
+    let report = match client.quotas().await {
+        Ok(report) => report,
+        Err(e) => return Err(CliError::Message(format!("Error getting quotas: {}", e))),
+    };
+
+    println!("{}", format!("Quotas (warnings from {}% of each limit):", report.warn_percent).blue());
+    for quota in report.quotas.iter() {
+        print_quota(quota);
+    }
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
-use colored::Colorize;
 use rusqlite::{Connection, Result, Error};
+use uuid::Uuid;
 
-use crate::server;
+use crate::migrations;
 
 /// The server details to store in the database
 #[derive(Debug)]
@@ -10,22 +10,47 @@ pub struct Server {
     pub hostname: String,
 
     // The server port
-    pub port: u16
+    pub port: u16,
+
+    // The path the server is served under behind a reverse proxy, such as /rustless, or empty if it is at the root
+    pub base_path: String
+}
+
+/// A named server profile, used to run commands against several servers at once
+#[derive(Debug)]
+pub struct Profile {
+    // The profile name
+    pub name: String,
+
+    // The server for the profile
+    pub server: Server
 }
 
-/// Creates a connection to the database
+/// The name used for the current server when it isn't saved as a profile
+const CURRENT_SERVER_PROFILE: &str = "current";
+
+/// Creates a connection to the database, applying the migrations it doesn't have yet
 pub fn create_connection() -> Result<Connection, String> {
     // Open the database file
     let conn_result = Connection::open("rustless_cli.db");
 
     // Check if the open actually worked
-    let conn = match conn_result {
+    let mut conn = match conn_result {
         Ok(conn) => conn,
         Err(_) => {
             return Err("Error connecting to database".to_string());
         }
     };
 
+    migrations::migrate(&mut conn)?;
+
+    // Return the connection
+    Ok(conn)
+}
+
+/// Creates the tables we need if they don't exist, adding any columns that are missing from tables created by older
+/// versions of the CLI. This is the first migration, so it also upgrades databases from before migrations
+pub(crate) fn create_tables(conn: &Connection) -> Result<(), String> {
     // We need a table to store the server details. Create one if it doesn't exist
     match conn.execute(
         "CREATE TABLE IF NOT EXISTS servers (
@@ -41,19 +66,65 @@ pub fn create_connection() -> Result<Connection, String> {
         }
     };
 
-    // Return the connection
-    Ok(conn)
+    // Databases created before logging in was added won't have the API key column, so add it
+    if conn.prepare("SELECT api_key FROM servers LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE servers ADD COLUMN api_key TEXT", []).is_err() {
+        return Err("Error adding API key column".to_string());
+    }
+
+    // Server profiles are stored in their own table, so commands can be run against all of them
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS profiles (
+                  name            TEXT PRIMARY KEY,
+                  hostname        TEXT NOT NULL,
+                  port            INTEGER NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_ ) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // We also cache the IDs of function apps so commands can skip looking them up by name
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS function_app_ids (
+                  name            TEXT PRIMARY KEY,
+                  id              TEXT NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_ ) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    Ok(())
+}
+
+/// Adds the base path column to the servers and profiles tables, for servers behind a reverse proxy that serves them
+/// under a path. Existing servers are at the root, so get an empty path
+pub(crate) fn add_base_path_columns(conn: &Connection) -> Result<(), String> {
+    for table in ["servers", "profiles"] {
+        if let Err(e) = conn.execute(&format!("ALTER TABLE {} ADD COLUMN base_path TEXT NOT NULL DEFAULT ''", table), []) {
+            return Err(format!("Error adding base path column to {}: {}", table, e));
+        }
+    }
+
+    Ok(())
 }
 
 /// Adds a server to the database
 /// 
 /// We only store a single server in the database. This starts by deleting any existing servers
 /// then adds the new one.
-fn add_server(conn: &Connection, hostname: &String, port: u16) -> Result<(), Error> { 
+pub fn add_server(conn: &Connection, hostname: &String, port: u16, base_path: &String) -> Result<(), Error> { 
     // Delete all the entries in the servers table
     let delete_sql = "DELETE FROM servers"; 
     let delete_result = conn.execute(
-        &delete_sql,
+        delete_sql,
         [],
     );
 
@@ -63,11 +134,14 @@ fn add_server(conn: &Connection, hostname: &String, port: u16) -> Result<(), Err
         Err(e) => return Err(e)
     };
 
+    // The cached function app IDs belong to the old server, so clear them
+    clear_function_app_ids(conn)?;
+
     // Insert the new server
-    let sql = format!("INSERT INTO servers (hostname, port) VALUES (?1, {})", port);
+    let sql = format!("INSERT INTO servers (hostname, port, base_path) VALUES (?1, {}, ?2)", port);
     let insert_result = conn.execute(
         &sql,
-        &[&hostname],
+        [hostname, base_path],
     );
 
     // Check if the insert worked
@@ -77,80 +151,128 @@ fn add_server(conn: &Connection, hostname: &String, port: u16) -> Result<(), Err
     }
 }
 
-/// Sets the server
-/// 
-/// This starts by testing the connection to the server, making sure it is valid. If so
-/// the server is stored in the database. There can be only one server, so adding one deletes any
-/// previous entry.
-pub async fn set_server(conn: Connection, hostname: &String, port: u16) -> Result<(), String> {
-    // Write to the console that we are testing the server
-    let message = format !("Testing server: {}:{}...", hostname, port).blue();
-    print!("{}", message);
-
-    // TODO - add a spinner here for long running tests
-
-    // Test the connection to the server
-    let result = server::test_server(hostname, port).await;
-
-    // Check if the test worked. If it did, write the server details to the database
-    match result {
-        Ok(_) => {
-            // Write a message to the console to show it worked
-            println!("✅");
-
-            // Add the server to the database
-            match add_server(&conn, hostname, port) {
-                Ok(_) => {
-                    let ok_message = format!("Server set!").green().bold();
-                    println!("{}", ok_message);
-
-                    Ok(())
-                },
-                Err(e) => Err(format!("Error adding server to storage: {}", e))
-            }
-        },
-        Err(_) => {
-            // If the server is not found, report back to the user
-            println!("❌");
-            let error_message = format!("Server {}:{} not found.\n", hostname, port).red().bold();
-            println!("{}",error_message);
-
-            // If there is a server already set, report this so the user knows which server will be used
-            // If no server is set, also report this back to the user
-            let current_message = match get_server(&conn) {
-                Ok(server) => format!("Current server: {}:{}\n", server.hostname, server.port).bold().blue().to_string(),
-                Err(_) => "No server set".bold().blue().to_string()
-            };
-            println!("{}", current_message);
-
-            // Return an error
-            Err("Server not found".to_string())
-        }
-    }
-}
-
 /// Gets the server from the database
 pub fn get_server(conn: &Connection) -> Result<Server, Error> {
     // Create a statement to select the single server from the database
-    let mut stmt = conn.prepare("SELECT hostname, port FROM servers LIMIT 1")?;
-    let server_iter_result = stmt.query_map([], |row| {
+    let mut stmt = conn.prepare("SELECT hostname, port, base_path FROM servers LIMIT 1")?;
+    let mut server_iter = stmt.query_map([], |row| {
         Ok(Server {
             hostname: row.get(0)?,
             port: row.get(1)?,
+            base_path: row.get(2)?,
         })
-    });
-
-    // Check if the query worked
-    let server_iter = match server_iter_result {
-        Ok(server_iter) => server_iter,
-        Err(e) => return Err(e)
-    };
+    })?;
 
     // Get the first server from the iterator
-    for server in server_iter {
-        return Ok(server?);
+    if let Some(server) = server_iter.next() {
+        return server;
     }
 
     // If there is no server, return an error
     Err(Error::QueryReturnedNoRows)
-}
\ No newline at end of file
+}
+
+/// Sets the API key used for the server, or clears it if the key is None. Returns false if no server is set
+pub fn set_api_key(conn: &Connection, key: Option<&str>) -> Result<bool, Error> {
+    let updated = conn.execute("UPDATE servers SET api_key = ?1", [key])?;
+
+    Ok(updated > 0)
+}
+
+/// Gets the API key used for the server, if one has been set with rustless login
+pub fn get_api_key(conn: &Connection) -> Result<Option<String>, Error> {
+    match conn.query_row("SELECT api_key FROM servers LIMIT 1", [], |row| row.get(0)) {
+        Ok(key) => Ok(key),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets the cached ID for the function app with the given name
+pub fn get_function_app_id(conn: &Connection, name: &String) -> Result<Uuid, Error> {
+    let id: String = conn.query_row("SELECT id FROM function_app_ids WHERE name = ?1", [name], |row| row.get(0))?;
+
+    match Uuid::parse_str(&id) {
+        Ok(id) => Ok(id),
+        Err(_) => Err(Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text)),
+    }
+}
+
+/// Caches the ID for the function app with the given name, replacing any existing entry
+pub fn set_function_app_id(conn: &Connection, name: &String, id: &Uuid) -> Result<(), Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO function_app_ids (name, id) VALUES (?1, ?2)",
+        [name, &id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Removes the cached ID for the function app with the given name
+pub fn remove_function_app_id(conn: &Connection, name: &String) -> Result<(), Error> {
+    conn.execute("DELETE FROM function_app_ids WHERE name = ?1", [name])?;
+
+    Ok(())
+}
+
+/// Removes all the cached function app IDs
+fn clear_function_app_ids(conn: &Connection) -> Result<(), Error> {
+    conn.execute("DELETE FROM function_app_ids", [])?;
+
+    Ok(())
+}
+
+/// Adds a server profile, replacing any existing profile with the same name
+pub fn add_profile(conn: &Connection, name: &String, hostname: &String, port: u16, base_path: &String) -> Result<(), Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO profiles (name, hostname, port, base_path) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![name, hostname, port, base_path],
+    )?;
+
+    Ok(())
+}
+
+/// Removes a server profile. Returns false if there is no profile with the given name
+pub fn remove_profile(conn: &Connection, name: &String) -> Result<bool, Error> {
+    let removed = conn.execute("DELETE FROM profiles WHERE name = ?1", [name])?;
+
+    Ok(removed > 0)
+}
+
+/// Gets all the server profiles
+pub fn get_profiles(conn: &Connection) -> Result<Vec<Profile>, Error> {
+    let mut stmt = conn.prepare("SELECT name, hostname, port, base_path FROM profiles ORDER BY name")?;
+    let profiles = stmt.query_map([], |row| {
+        Ok(Profile {
+            name: row.get(0)?,
+            server: Server {
+                hostname: row.get(1)?,
+                port: row.get(2)?,
+                base_path: row.get(3)?,
+            },
+        })
+    })?;
+
+    profiles.collect()
+}
+
+/// Gets all the servers to run a command against - all the profiles, along with the current server
+/// if it isn't already one of the profiles
+pub fn get_all_servers(conn: &Connection) -> Result<Vec<Profile>, Error> {
+    let mut profiles = get_profiles(conn)?;
+
+    if let Ok(server) = get_server(conn) {
+        let is_profile = profiles.iter()
+            .any(|profile| profile.server.hostname == server.hostname && profile.server.port == server.port
+                && profile.server.base_path == server.base_path);
+
+        if !is_profile {
+            profiles.insert(0, Profile {
+                name: CURRENT_SERVER_PROFILE.to_string(),
+                server,
+            });
+        }
+    }
+
+    Ok(profiles)
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use chrono::Local;
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::AppResourceUsage;
+
+use crate::error::CliError;
+use crate::server;
+
+/// The columns of the top table
+const HEADERS: [&str; 7] = ["NAME", "NAMESPACE", "CONTAINERS", "CPU %", "MEMORY", "MEMORY %", "NET RX / TX"];
+
+/// Apps using more than this percentage of a CPU are highlighted
+const BUSY_CPU_PERCENT: f64 = 80.0;
+
+/// Apps using more than this percentage of their memory limit are highlighted
+const BUSY_MEMORY_PERCENT: f64 = 80.0;
+
+/// Clears the terminal and moves the cursor to the top left, so each refresh draws over the last
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Formats a number of bytes in the largest unit it has at least one of, such as 12.5 MiB
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
+
+/// Gets the memory an app is using as a percentage of its limit
+fn get_memory_percent(usage: &AppResourceUsage) -> f64 {
+    match usage.memory_limit_bytes {
+        0 => 0.0,
+        limit => usage.memory_bytes as f64 / limit as f64 * 100.0,
+    }
+}
+
+/// Gets the cells for a row of the top table
+fn get_row(usage: &AppResourceUsage) -> Vec<String> {
+    vec![
+        usage.name.to_string(),
+        usage.namespace.to_string(),
+        usage.containers.to_string(),
+        format!("{:.1}", usage.cpu_percent),
+        format_bytes(usage.memory_bytes),
+        format!("{:.1}", get_memory_percent(usage)),
+        format!("{} / {}", format_bytes(usage.network_rx_bytes), format_bytes(usage.network_tx_bytes)),
+    ]
+}
+
+/// Prints the resources each app is using, busiest first, highlighting apps using most of a CPU or their memory
+fn print_usage(usage: &mut [AppResourceUsage]) {
+    usage.sort_by(|a, b| b.cpu_percent.total_cmp(&a.cpu_percent));
+    let rows: Vec<Vec<String>> = usage.iter().map(get_row).collect();
+
+    // Size each column to fit the widest cell in it
+    let mut widths: Vec<usize> = HEADERS.iter().map(|header| header.len()).collect();
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = HEADERS.iter().zip(widths.iter()).map(|(header, width)| format!("{:<width$}", header, width = width)).collect();
+    println!("{}", header.join("   ").trim_end().bold());
+
+    for (app, row) in usage.iter().zip(rows) {
+        let cells: Vec<String> = row.into_iter().zip(widths.iter()).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+        let line = cells.join("   ").trim_end().to_string();
+
+        match app.cpu_percent > BUSY_CPU_PERCENT || get_memory_percent(app) > BUSY_MEMORY_PERCENT {
+            true => println!("{}", line.red()),
+            false => println!("{}", line),
+        }
+    }
+}
+
+/// Shows the CPU, memory, and network each running function app is using, or only the given app, refreshing every
+/// interval until stopped with Ctrl+C
+pub async fn show_top(conn: &Connection, app: &Option<String>, interval: u64) -> Result<(), CliError> {
+    loop {
+        let client = server::get_server_client(conn)?;
+        let mut usage = match client.resource_usage(app).await {
+            Ok(Some(usage)) => usage,
+            Ok(None) => match app {
+                Some(app) => return Err(CliError::AppNotFound(app.to_string())),
+                None => return Err(CliError::Message("The server is too old to report resource usage".to_string())),
+            },
+            Err(e) => return Err(CliError::Message(format!("Error getting resource usage: {}", e))),
+        };
+
+        print!("{}", CLEAR_SCREEN);
+        println!("{}", format!("rustless top - {} - every {}s, Ctrl+C to stop", Local::now().format("%H:%M:%S"), interval).blue());
+        println!();
+
+        match (usage.is_empty(), app) {
+            (true, Some(app)) => println!("'{}' isn't running", app),
+            (true, None) => println!("No function apps are running"),
+            (false, _) => print_usage(&mut usage),
+        }
+
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
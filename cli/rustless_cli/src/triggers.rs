@@ -0,0 +1,160 @@
+use chrono::{DateTime, Local, TimeZone};
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::{NextRuns, NextRunsOptions, TimerTrigger, TriggerRun, TriggerRunsOptions};
+
+use crate::cli;
+use crate::error::CliError;
+use crate::output::{self, OutputArgs};
+use crate::server;
+
+/// Formats a run time from the server in the local timezone
+fn format_run_time(timestamp: u64) -> String {
+    let run_time: Option<DateTime<Local>> = Local.timestamp_opt(timestamp as i64, 0).single();
+    match run_time {
+        Some(run_time) => run_time.format("%a %d-%m-%Y %H:%M:%S %:z").to_string(),
+        None => format!("{} (Unix time)", timestamp),
+    }
+}
+
+/// Prints the upcoming runs of a timer trigger
+fn print_next_runs(next_runs: &NextRuns) {
+    match &next_runs.route {
+        Some(route) => println!("{}", format!("Schedule '{}' calls /{}. Upcoming runs in your timezone:", next_runs.schedule, route).blue()),
+        None => println!("{}", format!("Upcoming runs for schedule '{}' in your timezone:", next_runs.schedule).blue()),
+    }
+
+    for run in next_runs.next_runs.iter() {
+        println!("  {}", format_run_time(*run));
+    }
+}
+
+/// Sets or removes the timer trigger for a function app, retrying by name if the cached ID is stale
+async fn set_timer_trigger_impl(conn: &Connection, name: &String, trigger: &Option<TimerTrigger>) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.set_timer_trigger(&app, trigger).await }).await;
+
+    match result {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => Err(CliError::Message(format!("Error setting timer trigger: {}", e))),
+    }
+}
+
+/// Sets the timer trigger for a function app, showing the upcoming runs so the schedule can be checked
+pub async fn set_timer_trigger(conn: &Connection, name: &String, schedule: &String, route: &String) -> Result<(), CliError> {
+    let trigger = Some(TimerTrigger {
+        schedule: schedule.to_string(),
+        route: route.to_string(),
+    });
+
+    set_timer_trigger_impl(conn, name, &trigger).await?;
+    println!("{}", format!("✅ Timer trigger set for '{}'", name).green());
+
+    show_next_runs(conn, name, rustless_shared::default_next_runs_count(), &None).await
+}
+
+/// Removes the timer trigger for a function app
+pub async fn remove_timer_trigger(conn: &Connection, name: &String) -> Result<(), CliError> {
+    set_timer_trigger_impl(conn, name, &None).await?;
+    println!("{}", format!("✅ Timer trigger removed for '{}'", name).green());
+    Ok(())
+}
+
+/// Shows the upcoming runs of the timer trigger for a function app, or of the given schedule so it can be
+/// checked before it is set
+pub async fn show_next_runs(conn: &Connection, name: &String, count: usize, schedule: &Option<String>) -> Result<(), CliError> {
+    let options = &NextRunsOptions {
+        count,
+        schedule: schedule.clone(),
+    };
+
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.timer_next_runs(&app, options).await }).await;
+
+    match result {
+        Ok(Some(next_runs)) => print_next_runs(&next_runs),
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(())
+}
+
+/// Prints a trigger invocation on one line, colored by whether the app handled it successfully
+fn print_trigger_run(run: &TriggerRun) {
+    let source = if run.manual { "manual" } else { "scheduled" };
+    let line = match (run.status_code, &run.error) {
+        (Some(status), _) => format!("{}  {} ({})  status {}  {}ms", format_run_time(run.started_at), run.trigger, source, status, run.duration_ms),
+        (None, Some(e)) => format!("{}  {} ({})  failed: {}", format_run_time(run.started_at), run.trigger, source, e),
+        (None, None) => format!("{}  {} ({})  failed", format_run_time(run.started_at), run.trigger, source),
+    };
+
+    match run.status_code {
+        Some(status) if (200..300).contains(&status) => println!("{}", line.green()),
+        _ => println!("{}", line.red()),
+    }
+}
+
+/// Writes out trigger invocations as CSV or markdown rows
+fn print_trigger_run_rows(output: &OutputArgs, runs: &[TriggerRun]) {
+    let headers = ["Started", "Trigger", "Source", "Status code", "Duration (ms)", "Error"];
+
+    let rows: Vec<Vec<String>> = runs.iter().map(|run| vec![
+        format_run_time(run.started_at),
+        run.trigger.to_string(),
+        if run.manual { "manual" } else { "scheduled" }.to_string(),
+        run.status_code.map(|status| status.to_string()).unwrap_or_default(),
+        run.duration_ms.to_string(),
+        run.error.clone().unwrap_or_default(),
+    ]).collect();
+
+    output::print_rows(output, &headers, &rows);
+}
+
+/// Fires a trigger for a function app now, for testing, and shows how the app responded
+pub async fn run_trigger(conn: &Connection, name: &String, trigger: &str) -> Result<(), CliError> {
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.run_trigger(&app, trigger).await }).await;
+
+    match result {
+        Ok(Some(run)) => {
+            print_trigger_run(&run);
+
+            // The run is recorded whatever the app returned, so fail if the app didn't handle it to help scripts
+            if !matches!(run.status_code, Some(status) if (200..300).contains(&status)) {
+                return Err(CliError::Failed);
+            }
+        },
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error running trigger: {}", e))),
+    }
+
+    Ok(())
+}
+
+/// Shows the most recent trigger invocations for a function app, newest first
+pub async fn show_trigger_runs(conn: &Connection, name: &String, last: usize, trigger: &Option<String>, output: &OutputArgs) -> Result<(), CliError> {
+    let options = &TriggerRunsOptions {
+        last,
+        trigger: trigger.clone(),
+    };
+
+    let client = &server::get_server_client(conn)?;
+    let result = cli::with_app_ref(conn, name, |app| async move { client.trigger_runs(&app, options).await }).await;
+
+    match result {
+        Ok(Some(runs)) if !output.is_table() => print_trigger_run_rows(output, &runs),
+        Ok(Some(runs)) if runs.is_empty() => println!("{}", format!("No triggers have run for '{}'", name).blue()),
+        Ok(Some(runs)) => {
+            for run in runs.iter() {
+                print_trigger_run(run);
+            }
+        },
+        Ok(None) => return Err(CliError::AppNotFound(name.to_string())),
+        Err(e) => return Err(CliError::Message(format!("Error getting trigger history: {}", e))),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use colored::Colorize;
+use rusqlite::Connection;
+
+use rustless_shared::{VersionInfo, API_VERSION};
+
+use crate::server;
+use crate::storage;
+
+/// Gets the version and build details of the CLI
+fn get_version_info() -> VersionInfo {
+    VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_sha: env!("RUSTLESS_GIT_SHA").to_string(),
+        build_date: env!("RUSTLESS_BUILD_DATE").to_string(),
+        features: vec!["self-update".to_string()],
+        api_versions: vec![API_VERSION.to_string()],
+    }
+}
+
+/// Prints the version and build details
+fn print_version_info(version: &VersionInfo) {
+    println!("  Version:      {}", version.version);
+    println!("  Git SHA:      {}", version.git_sha);
+    println!("  Build date:   {}", version.build_date);
+    println!("  Features:     {}", version.features.join(", "));
+    println!("  API versions: {}", version.api_versions.join(", "));
+}
+
+/// Prints the version of the CLI. In verbose mode this also shows the build details, and the
+/// version of the current server along with whether it supports this CLI
+pub async fn print_version(conn: &Connection, verbose: bool) {
+    let version = get_version_info();
+
+    if !verbose {
+        println!("rustless_cli {}", version.version);
+        return;
+    }
+
+    println!("{}", "CLI".bold());
+    print_version_info(&version);
+
+    let server = match storage::get_server(conn) {
+        Ok(server) => server,
+        Err(_) => {
+            println!("{}", "No server set.".blue());
+            return;
+        }
+    };
+
+    println!("{}", format!("Server ({})", server::format_server(&server)).bold());
+    let server_version = match server::get_client(&server) {
+        Ok(client) => client.version().await,
+        Err(e) => Err(e),
+    };
+
+    match server_version {
+        Ok(server_version) => {
+            print_version_info(&server_version);
+
+            if server_version.api_versions.iter().any(|api_version| api_version == API_VERSION) {
+                println!("{}", "✅ The server supports this CLI".green());
+            } else {
+                println!("{}", format!("The server doesn't support API version {} used by this CLI", API_VERSION).red().bold());
+            }
+        },
+        Err(e) => println!("{}", format!("Error getting the server version: {}", e).red()),
+    }
+}
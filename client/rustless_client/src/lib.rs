@@ -0,0 +1,1990 @@
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Client, ClientBuilder, Response};
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use rustless_shared::{AnalyticsOptions, AnalyticsReport, ApiError, AppResourceUsage, AppRestart, AppRouteManifest, AppStop, BufferingReport, BufferingRequest, BuildAccepted, BuildLog, BuildLogOptions, BuildOptions, BulkStartReport, BulkStartRequest, CratesCachePurge, CratesCacheStats, DefaultApp, DependenciesReport, DependenciesRequest, DeployPlan, EffectiveConfig, EgressReport, EgressRequest, ErrorResponse, EventsOptions, HostEvent, LogsOptions, FunctionApp, FunctionAppInfo, FunctionAppOverview, FunctionAppStatusResult, FunctionAppNameRequest, FunctionAppStatus, GrpcReport, GrpcServicesRequest, ImageVerification, ImportedDeployment, MaintenanceRequest, RequestLimitsReport, MirrorConfig, MirrorRequest, NamespaceDefaults, NextRuns, NextRunsOptions, Promotion, QuotasReport, RecordedRequest, RecordedRequestsOptions, RecordingRequest, RedactionRules, ReplicasReport, ResourceUsageOptions, ScaleProfile, ScaleProfilesReport, ScaleProfilesRequest, ScaleRequest, StartOptions, TimerTrigger, TimerTriggerRequest, TriggerRun, TriggerRunsOptions, UnchangedDeployment, VersionInfo, BUILD_FINISHED_EVENT, BUILD_OUTPUT_EVENT, EXPORT_DEPLOYMENT_HEADER, EXPORT_IMAGE_ID_HEADER, LOGS_ERROR_EVENT, REDACTED_VALUE};
+
+// A client for the API of a rustless server, so other Rust tools can script deployments. Each method calls one
+// endpoint and returns the typed response, with the message from the server as the error. Nothing here prints or
+// reads the CLI settings - the CLI creates a client for the server and API key it has stored
+
+/// How often to check the status of a build running on the server
+const BUILD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Headers from a recorded request that only applied to the original connection, so are not replayed
+const REPLAY_SKIPPED_HEADERS: [&str; 5] = ["host", "connection", "content-length", "transfer-encoding", "keep-alive"];
+
+/// Identifies a function app on the server, either by ID or by name
+pub enum FunctionAppRef {
+    Id(Uuid),
+    Name(String),
+}
+
+impl FunctionAppRef {
+    /// Gets the path segment used to address the function app in the server endpoints
+    fn to_path(&self) -> String {
+        match self {
+            FunctionAppRef::Id(id) => id.to_string(),
+            FunctionAppRef::Name(name) => format!("by-name/{}", name),
+        }
+    }
+}
+
+/// What the server did with uploaded code
+pub struct UploadResult {
+    // The number of the deployment waiting for approval, if the server requires deployments to be approved
+    pub pending_deployment: Option<u32>,
+
+    // The number of the latest deployment if the code matched it, so nothing was built
+    pub unchanged_deployment: Option<u32>,
+}
+
+/// The response from a function app to a request sent through the server
+pub struct InvokeResponse {
+    // The status code
+    pub status: u16,
+
+    // The response headers, in the order they were sent
+    pub headers: Vec<(String, String)>,
+
+    // The response body
+    pub body: Vec<u8>,
+}
+
+/// A deployment exported from a server to promote it to another. The export is streamed from the server as it is
+/// sent on with import_deployment, so the image is never held in memory
+pub struct DeploymentExportStream {
+    // The number of the exported deployment
+    pub deployment: u32,
+
+    // The ID of the exported image
+    pub image_id: String,
+
+    // The response the export is streamed from
+    response: Response,
+}
+
+/// A client for the API of a rustless server, with a typed method for each endpoint. Clones share the same
+/// connection pool
+#[derive(Clone)]
+pub struct RustlessClient {
+    // The hostname of the server
+    hostname: String,
+
+    // The port of the server
+    port: u16,
+
+    // The path the server is served under behind a reverse proxy, such as /rustless, or empty if it is at the root
+    base_path: String,
+
+    // The HTTPS client, which sends the API key with every request if there is one
+    client: Client,
+}
+
+/// Creates a builder for a HTTPS request client. In debug mode, this ignores invalid certs so it can be run locally
+#[cfg(debug_assertions)]
+fn get_builder() -> ClientBuilder {
+    Client::builder().danger_accept_invalid_certs(true)
+}
+
+/// Creates a builder for a HTTPS request client. In release mode, this does not invalid certs so it can't be run locally
+#[cfg(not(debug_assertions))]
+fn get_builder() -> ClientBuilder {
+    Client::builder()
+}
+
+/// Gets the error from a failed response. The server sends the code and message of the error, so the message can be
+/// shown as it is. Anything else, such as an error page from a proxy, has an unknown code and the status code in the
+/// message
+async fn get_error(res: Response) -> ErrorResponse {
+    let status = res.status().as_u16();
+    let body = res.text().await.unwrap_or_default();
+
+    match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(error) => error,
+        Err(_) if body.is_empty() => ErrorResponse::new(ApiError::Unknown, &format!("Server returned status code: {}", status)),
+        Err(_) => ErrorResponse::new(ApiError::Unknown, &format!("Server returned status code: {}\nServer returned error: {}", status, body)),
+    }
+}
+
+/// Formats the hostname and port of a server as they are written in a URL. IPv6 addresses are put in brackets, such
+/// as [::1]:8080, as they contain colons themselves
+pub fn format_address(hostname: &str, port: u16) -> String {
+    match hostname.contains(':') && !hostname.starts_with('[') {
+        true => format!("[{}]:{}", hostname, port),
+        false => format!("{}:{}", hostname, port),
+    }
+}
+
+/// Normalizes the path a server is served under behind a reverse proxy, so rustless, /rustless/ and /rustless are all
+/// /rustless. A server at the root has an empty path
+pub fn normalize_base_path(base_path: &str) -> String {
+    match base_path.trim().trim_matches('/') {
+        "" => String::new(),
+        path => format!("/{}", path),
+    }
+}
+
+impl RustlessClient {
+    /// Creates a client for the server with the given hostname and port
+    pub fn new(hostname: &str, port: u16) -> Result<RustlessClient, String> {
+        RustlessClient::with_api_key(hostname, port, None)
+    }
+
+    /// Creates a client for the server with the given hostname and port, sending the API key as a bearer token with
+    /// every request if there is one
+    pub fn with_api_key(hostname: &str, port: u16, api_key: Option<&str>) -> Result<RustlessClient, String> {
+        let mut headers = HeaderMap::new();
+        if let Some(key) = api_key {
+            let mut value = match HeaderValue::from_str(&format!("Bearer {}", key)) {
+                Ok(value) => value,
+                Err(_) => return Err("The API key contains characters that can't be sent in a header".to_string()),
+            };
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let client = match get_builder().default_headers(headers).build() {
+            Ok(client) => client,
+            Err(e) => return Err(format!("Error creating HTTPS client: {}", e)),
+        };
+
+        Ok(RustlessClient {
+            hostname: hostname.to_string(),
+            port,
+            base_path: String::new(),
+            client,
+        })
+    }
+
+    /// Gets the hostname of the server
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// Gets the port of the server
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sets the path the server is served under behind a reverse proxy, such as /rustless for
+    /// https://example.com/rustless/, so every endpoint is called under it
+    pub fn with_base_path(mut self, base_path: &str) -> RustlessClient {
+        self.base_path = normalize_base_path(base_path);
+        self
+    }
+
+    /// Gets the path the server is served under, such as /rustless, or an empty string if it is at the root
+    pub fn base_path(&self) -> &str {
+        &self.base_path
+    }
+
+    /// Gets the hostname and port of the server as they are written in a URL, such as example.com:8080 or [::1]:8080
+    pub fn address(&self) -> String {
+        format_address(&self.hostname, self.port)
+    }
+
+    /// Creates the url for an endpoint on the server from the hostname, port, and the path the server is served under
+    fn url(&self, path: &str) -> String {
+        format!("https://{}{}{}", self.address(), self.base_path, path)
+    }
+
+    /// Test the server to see if it is available
+    ///
+    /// The server will respond on a request to url:port/hello with Hello from rustless!
+    /// and a 200 status code if it is a valid server
+    pub async fn test(&self) -> Result<(), String> {
+        let url = self.url("/hello");
+
+        // Make the request
+        let res = self.client.get(url).send().await;
+
+        // Check the response
+        match res {
+            Ok(res) => {
+                // If the server is correct, we should get a 200 status code
+                if res.status() != 200 {
+                    return Err(format!("Server returned status code: {}", res.status()));
+                }
+
+                // If we got a 200, check the test we get back to see if it matches what is expected
+                // If not, return an error
+                match res.text().await {
+                    Ok(text) => {
+                        if text != "Hello from rustless!" {
+                            return Err(format!("Server returned unexpected text: {}", text));
+                        }
+                    }
+                    Err(e) => {
+                        return Err(format!("Error reading response text: {}", e));
+                    }
+                }
+
+                // If everything works, return Ok.
+                Ok(())
+            }
+            Err(err) => Err(format!("Error: {}", err)),
+        }
+    }
+
+    /// Checks the server accepts an API key, by listing the function apps with it
+    ///
+    /// This returns false if the server rejects the key. Servers that don't have any API keys accept every key
+    pub async fn check_api_key(&self, key: &String) -> Result<bool, String> {
+        let url = self.url("/function-apps");
+
+        // The key replaces the API key the client was created with, if there is one
+        let res = match self.client.get(url).bearer_auth(key).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            401 => Ok(false),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the version and build details of the server
+    pub async fn version(&self) -> Result<VersionInfo, String> {
+        let url = self.url("/version");
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        // Servers from before the version endpoint was added return a 404
+        if res.status() == 404 {
+            return Err("The server is too old to report its version".to_string());
+        }
+
+        // If the server is correct, we should get a 200 status code
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        match res.json::<VersionInfo>().await {
+            Ok(version) => Ok(version),
+            Err(e) => Err(format!("Error parsing JSON: {}", e)),
+        }
+    }
+
+    /// Calls the server to add a function app
+    pub async fn create_app(&self, name: &String, namespace: &String) -> Result<Uuid, String> {
+        let test_result = self.test().await;
+        if test_result.is_err() {
+            return Err(format!(
+                "Error testing server: {}. Is the server set correctly",
+                test_result.err().unwrap()
+            ));
+        }
+
+        let url = self.url("/function-apps");
+
+        // Build some JSON containing the function app name
+        let json = FunctionAppNameRequest{ 
+            name: name.to_string(),
+            namespace: namespace.to_string()
+        };
+
+        // Make the request
+        let res = self.client.post(url).json(&json).send().await;
+
+        // Check the response
+        match res {
+            Ok(res) => {
+                // If the server is correct, we should get a 200 status code
+                if res.status() != 200 {
+                    // Use the message from the server, such as when the name is in use or the server has reached its
+                    // quota of apps
+                    return Err(get_error(res).await.message);
+                }
+
+                // We are expecting an ID back if this works
+                match res.text().await {
+                    Ok(id) => match Uuid::parse_str(&id) {
+                        Ok(id) => Ok(id),
+                        Err(e) => Err(format!("Error parsing ID: {}", e)),
+                    },
+                    Err(e) => Err(format!("Error reading response text: {}", e)),
+                }
+            }
+            Err(err) => Err(format!("Error: {}", err)),
+        }
+    }
+
+    /// Asks the server what deploying code for the function app would do, without making any changes
+    pub async fn deploy_plan(&self, name: &String, namespace: &String) -> Result<DeployPlan, String> {
+        let url = self.url("/function-apps/plan");
+
+        let json = FunctionAppNameRequest {
+            name: name.to_string(),
+            namespace: namespace.to_string(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        // If the server is correct, we should get a 200 status code
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        // Get the response JSON
+        match res.json::<DeployPlan>().await {
+            Ok(json) => Ok(json),
+            Err(e) => Err(format!("Error parsing JSON: {}", e)),
+        }
+    }
+
+    /// Uploads the code to the server, returning the error from the server if the upload or build fails
+    ///
+    /// The server builds the code in the background, so this waits for the build to finish by polling the status of the app.
+    /// If the server requires deployments to be approved, this returns the number of the deployment waiting for approval.
+    /// If the code matches the latest deployment, the server skips the build unless the options force it.
+    /// The zip file is streamed to the server as it is read, so it is never held in memory
+    pub async fn upload_code(&self, app: &FunctionAppRef, zip_file: &Path, options: &BuildOptions) -> Result<UploadResult, String> {
+        let url = self.url(&format!("/function-apps/{}/code", app.to_path()));
+
+        let zip_file = match tokio::fs::File::open(zip_file).await {
+            Ok(zip_file) => zip_file,
+            Err(e) => return Err(format!("Error opening the zip file: {}", e)),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).query(options).header("Content-Type", "application/zip").body(zip_file).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        // If the code matched the latest deployment, nothing was built
+        if res.status() == 200 {
+            return match res.json::<UnchangedDeployment>().await {
+                Ok(unchanged) => Ok(UploadResult {
+                    pending_deployment: if unchanged.approved { None } else { Some(unchanged.number) },
+                    unchanged_deployment: Some(unchanged.number),
+                }),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            };
+        }
+
+        // If the server is correct, we should get a 202 status code as the build is queued. An invalid template is
+        // reported as an error response
+        if res.status() != 202 {
+            return Err(get_error(res).await.message);
+        }
+
+        let accepted = match res.json::<BuildAccepted>().await {
+            Ok(accepted) => accepted,
+            Err(e) => return Err(format!("Error parsing JSON: {}", e)),
+        };
+
+        self.wait_for_build(app, &accepted.build_id).await
+    }
+
+    /// Waits for a build on the server to finish, returning the build output from the server if it fails
+    async fn wait_for_build(&self, app: &FunctionAppRef, build_id: &Uuid) -> Result<UploadResult, String> {
+        loop {
+            let status = match self.status(app).await? {
+                Some(status) => status,
+                None => return Err("The function app was deleted while it was building".to_string()),
+            };
+
+            // Another upload replaces the build, so its result is no longer about this code
+            if status.build_id.as_ref() != Some(build_id) {
+                return Err("The build was replaced by a newer upload of the code".to_string());
+            }
+
+            // A running app keeps running the old code if the build fails or is cancelled, so the build error is
+            // checked as well as the status
+            match (status.status, status.build_error) {
+                (FunctionAppStatus::Building, _) => sleep(BUILD_POLL_INTERVAL).await,
+                (_, Some(e)) => return Err(e),
+                (FunctionAppStatus::Ready, None) | (FunctionAppStatus::Running, None) => return Ok(UploadResult {
+                    pending_deployment: status.pending_deployment,
+                    unchanged_deployment: None,
+                }),
+                (_, None) => return Err("The build failed".to_string()),
+            }
+        }
+    }
+
+    /// Asks the server to cancel the current build for the function app
+    ///
+    /// This returns false if the function app has no build queued or running
+    pub async fn cancel_build(&self, app: &FunctionAppRef) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/builds/current/cancel", app.to_path()));
+
+        // Make the request
+        let res = match self.client.post(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Turns request mirroring on or off for a function app. Mirroring is turned on if there is a config
+    ///
+    /// This returns false if the server doesn't have the function app
+    pub async fn set_mirror(&self, app: &FunctionAppRef, config: &Option<MirrorConfig>) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/mirror", app.to_path()));
+
+        let json = MirrorRequest {
+            enabled: config.is_some(),
+            config: config.clone(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            // The server checks the mirror settings are valid
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Turns recording recent requests on or off for a function app
+    ///
+    /// This returns false if the server doesn't have the function app
+    pub async fn set_recording(&self, app: &FunctionAppRef, enabled: bool, capacity: usize) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/recording", app.to_path()));
+
+        // Make the request
+        let res = match self.client.post(url).json(&RecordingRequest { enabled, capacity }).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the most recent requests recorded for a function app, oldest first
+    ///
+    /// This returns None if the server doesn't have the function app
+    pub async fn recorded_requests(&self, app: &FunctionAppRef, last: usize) -> Result<Option<Vec<RecordedRequest>>, String> {
+        let url = self.url(&format!("/function-apps/{}/recorded-requests", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).query(&RecordedRequestsOptions { last }).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        if res.status() == 404 {
+            return Ok(None);
+        }
+
+        // If the server is correct, we should get a 200 status code
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        match res.json::<Vec<RecordedRequest>>().await {
+            Ok(requests) => Ok(Some(requests)),
+            Err(e) => Err(format!("Error parsing JSON: {}", e)),
+        }
+    }
+
+    /// Sets the header names and JSON field paths redacted from requests to a function app, and their responses,
+    /// before they are mirrored or recorded. Empty rules only redact the headers the server always redacts
+    ///
+    /// This returns false if the server doesn't have the function app
+    pub async fn set_redaction(&self, app: &FunctionAppRef, rules: &RedactionRules) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/redaction", app.to_path()));
+
+        // Make the request
+        let res = match self.client.post(url).json(rules).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            // The server checks the header names and field paths are valid
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the header names and JSON field paths redacted from requests to a function app before they are mirrored
+    /// or recorded
+    ///
+    /// This returns None if the server doesn't have the function app
+    pub async fn redaction(&self, app: &FunctionAppRef) -> Result<Option<RedactionRules>, String> {
+        let url = self.url(&format!("/function-apps/{}/redaction", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<RedactionRules>().await {
+                Ok(rules) => Ok(Some(rules)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Sends a recorded request to the function app through the gateway, returning the status code and body of the response
+    pub async fn replay_request(&self, name: &String, request: &RecordedRequest) -> Result<(u16, Vec<u8>), String> {
+        // Create the url for the app route, keeping the query string
+        let mut url = self.url(&format!("/api/{}/{}", name, request.route));
+        if !request.query.is_empty() {
+            url = format!("{}?{}", url, request.query);
+        }
+
+        let method = match reqwest::Method::from_bytes(request.method.as_bytes()) {
+            Ok(method) => method,
+            Err(e) => return Err(format!("Unsupported method {}: {}", request.method, e)),
+        };
+
+        let body = match base64::decode(&request.body) {
+            Ok(body) => body,
+            Err(e) => return Err(format!("Error decoding recorded body: {}", e)),
+        };
+
+        // Send the recorded headers, apart from the ones that belong to the original connection and the ones that were
+        // redacted when the request was recorded. The replay header stops the server recording the replayed request
+        let mut req = self.client.request(method, url).body(body).header("x-rustless-replay", "true");
+        for (name, value) in request.headers.iter() {
+            if !REPLAY_SKIPPED_HEADERS.contains(&name.to_lowercase().as_str()) && value != REDACTED_VALUE {
+                req = req.header(name.as_str(), value.as_str());
+            }
+        }
+
+        let res = match req.send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        let status = res.status().as_u16();
+        match res.bytes().await {
+            Ok(body) => Ok((status, body.to_vec())),
+            Err(e) => Err(format!("Error reading response: {}", e)),
+        }
+    }
+
+    /// Sends a request to a route of a function app through the server, the same way a client of the app would
+    ///
+    /// The route can include a query string. Error responses from the app, or from the server when the app isn't
+    /// running, are returned like any other response
+    pub async fn invoke(&self, name: &String, route: &str, method: &str, headers: &[(String, String)], body: Vec<u8>) -> Result<InvokeResponse, String> {
+        // Create the url for the app route
+        let url = self.url(&format!("/api/{}/{}", name, route.trim_start_matches('/')));
+
+        let method = match reqwest::Method::from_bytes(method.to_uppercase().as_bytes()) {
+            Ok(method) => method,
+            Err(e) => return Err(format!("Unsupported method {}: {}", method, e)),
+        };
+
+        let mut req = self.client.request(method, url).body(body);
+        for (name, value) in headers.iter() {
+            req = req.header(name.as_str(), value.as_str());
+        }
+
+        let res = match req.send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        let status = res.status().as_u16();
+        let headers = res.headers().iter()
+            .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).to_string()))
+            .collect();
+
+        match res.bytes().await {
+            Ok(body) => Ok(InvokeResponse { status, headers, body: body.to_vec() }),
+            Err(e) => Err(format!("Error reading response: {}", e)),
+        }
+    }
+
+    /// Approves a deployment on the server so the function app can be started, using the key of a user with the approver role
+    ///
+    /// This returns false if the server doesn't have the function app
+    pub async fn approve_deployment(&self, app: &FunctionAppRef, number: u32, key: &String) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/deployments/{}/approve", app.to_path(), number));
+
+        // Make the request
+        let res = match self.client.post(url).bearer_auth(key).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Sets or removes the timer trigger for a function app
+    ///
+    /// This returns false if the function app doesn't exist
+    pub async fn set_timer_trigger(&self, app: &FunctionAppRef, trigger: &Option<TimerTrigger>) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/triggers/timer", app.to_path()));
+
+        let json = TimerTriggerRequest {
+            enabled: trigger.is_some(),
+            trigger: trigger.clone(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            // The server checks the schedule and route are valid
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the upcoming runs of the timer trigger for a function app, or of the given schedule
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn timer_next_runs(&self, app: &FunctionAppRef, options: &NextRunsOptions) -> Result<Option<NextRuns>, String> {
+        let url = self.url(&format!("/function-apps/{}/triggers/timer/next", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).query(options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<NextRuns>().await {
+                Ok(next_runs) => Ok(Some(next_runs)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            // A 404 is either an unknown app, or an app without a timer trigger
+            404 => match get_error(res).await {
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                error => Err(error.message),
+            },
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Fires a trigger for a function app now, for testing, returning the recorded run
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn run_trigger(&self, app: &FunctionAppRef, trigger: &str) -> Result<Option<TriggerRun>, String> {
+        let url = self.url(&format!("/function-apps/{}/triggers/{}/run", app.to_path(), trigger));
+
+        // Make the request
+        let res = match self.client.post(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<TriggerRun>().await {
+                Ok(run) => Ok(Some(run)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            // A 404 is either an unknown app, or an unknown or unset trigger
+            404 => match get_error(res).await {
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                error => Err(error.message),
+            },
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the most recent trigger invocations for a function app
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn trigger_runs(&self, app: &FunctionAppRef, options: &TriggerRunsOptions) -> Result<Option<Vec<TriggerRun>>, String> {
+        let url = self.url(&format!("/function-apps/{}/triggers/runs", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).query(options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<Vec<TriggerRun>>().await {
+                Ok(runs) => Ok(Some(runs)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Restricts the destinations a function app can call through the egress proxy to the allowlist, or lifts the
+    /// restriction if the allowlist is None
+    ///
+    /// This returns false if the function app doesn't exist
+    pub async fn set_egress(&self, app: &FunctionAppRef, allowlist: &Option<Vec<String>>) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/egress", app.to_path()));
+
+        let json = EgressRequest {
+            restricted: allowlist.is_some(),
+            allowlist: allowlist.clone().unwrap_or_default(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            // The server checks the destinations are valid
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the egress allowlist for a function app and the destinations it has called through the egress proxy
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn egress(&self, app: &FunctionAppRef) -> Result<Option<EgressReport>, String> {
+        let url = self.url(&format!("/function-apps/{}/egress", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<EgressReport>().await {
+                Ok(report) => Ok(Some(report)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Sets the gRPC services a function app serves, such as helloworld.Greeter, so the gRPC gateway routes calls to
+    /// them to the app. An empty list stops the gateway routing any calls to the app
+    ///
+    /// This returns false if the function app doesn't exist
+    pub async fn set_grpc_services(&self, app: &FunctionAppRef, services: &[String]) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/grpc", app.to_path()));
+
+        let json = GrpcServicesRequest {
+            services: services.to_vec(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            // The server checks the service names are valid and not served by another app
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the gRPC services a function app serves and the metrics for each method called through the gRPC gateway
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn grpc(&self, app: &FunctionAppRef) -> Result<Option<GrpcReport>, String> {
+        let url = self.url(&format!("/function-apps/{}/grpc", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<GrpcReport>().await {
+                Ok(report) => Ok(Some(report)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Sets the profiles that scale a function app on cron schedules, or clears them if the list is empty. The server
+    /// scales the app to the profile in effect now, and returns the profiles with the replicas they set now and next
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn set_scale_profiles(&self, app: &FunctionAppRef, profiles: &[ScaleProfile]) -> Result<Option<ScaleProfilesReport>, String> {
+        let url = self.url(&format!("/function-apps/{}/scale-profiles", app.to_path()));
+
+        let json = ScaleProfilesRequest {
+            profiles: profiles.to_vec(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<ScaleProfilesReport>().await {
+                Ok(report) => Ok(Some(report)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            // The server checks the schedules are valid and the replicas can be run
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Scales a function app to a number of replicas, starting or stopping containers to match. 0 stops the app
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn scale(&self, app: &FunctionAppRef, replicas: u32) -> Result<Option<ReplicasReport>, String> {
+        let url = self.url(&format!("/function-apps/{}/scale", app.to_path()));
+
+        let json = ScaleRequest { replicas };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<ReplicasReport>().await {
+                Ok(report) => Ok(Some(report)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            // The replicas are over the server's limit, or the app couldn't be started
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the profiles that scale a function app on cron schedules, with the replicas they set now and next
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn scale_profiles(&self, app: &FunctionAppRef) -> Result<Option<ScaleProfilesReport>, String> {
+        let url = self.url(&format!("/function-apps/{}/scale-profiles", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<ScaleProfilesReport>().await {
+                Ok(report) => Ok(Some(report)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Sets how many bytes of request and response bodies the gateway buffers for a function app before streaming
+    /// them. None uses the server default
+    ///
+    /// This returns false if the function app doesn't exist
+    pub async fn set_buffering(&self, app: &FunctionAppRef, request_threshold: Option<u64>, response_threshold: Option<u64>) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/buffering", app.to_path()));
+
+        let json = BufferingRequest {
+            request_threshold,
+            response_threshold,
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            // The server checks the thresholds can be stored
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the buffering thresholds for a function app and how the gateway has buffered and streamed its bodies
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn buffering(&self, app: &FunctionAppRef) -> Result<Option<BufferingReport>, String> {
+        let url = self.url(&format!("/function-apps/{}/buffering", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<BufferingReport>().await {
+                Ok(report) => Ok(Some(report)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the settings a function app uses, with whether each is set on the app or comes from the defaults for its
+    /// namespace or the server
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn effective_config(&self, app: &FunctionAppRef) -> Result<Option<EffectiveConfig>, String> {
+        let url = self.url(&format!("/function-apps/{}/effective-config", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<EffectiveConfig>().await {
+                Ok(config) => Ok(Some(config)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Watches the event stream on the server, calling the handler for each event until the server closes the stream
+    ///
+    /// The stream is sent as server sent events, with each event as JSON in the data lines
+    pub async fn watch_events(&self, options: &EventsOptions, mut handler: impl FnMut(HostEvent)) -> Result<(), String> {
+        let url = self.url("/events");
+
+        // Make the request
+        let mut res = match self.client.get(url).query(options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        if res.status().as_u16() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        // Events can be split across chunks, so keep reading until there is a complete event, which ends with a blank line
+        let mut buffer = String::new();
+        loop {
+            let chunk = match res.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return Ok(()),
+                Err(e) => return Err(format!("Error reading events: {}", e)),
+            };
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+            while let Some(end) = buffer.find("\n\n") {
+                let frame: String = buffer.drain(..end + 2).collect();
+
+                // Comments, such as keepalives, have no data
+                let data: Vec<&str> = frame.lines().filter_map(|line| line.strip_prefix("data: ")).collect();
+                if data.is_empty() {
+                    continue;
+                }
+
+                match serde_json::from_str::<HostEvent>(&data.join("\n")) {
+                    Ok(event) => handler(event),
+                    Err(e) => return Err(format!("Error parsing event: {}", e)),
+                }
+            }
+        }
+    }
+
+    /// Gets the output of the latest build of a function app, or of the given build
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn build_log(&self, app: &FunctionAppRef, build: &Option<Uuid>) -> Result<Option<BuildLog>, String> {
+        let url = self.url(&format!("/function-apps/{}/build-logs", app.to_path()));
+
+        let options = BuildLogOptions {
+            build: *build,
+            follow: false,
+        };
+
+        // Make the request
+        let res = match self.client.get(url).query(&options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<BuildLog>().await {
+                Ok(log) => Ok(Some(log)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            // A 404 is either an unknown app, or a build that has no log
+            404 => match get_error(res).await {
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                error => Err(error.message),
+            },
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Follows the output of the latest build of a function app, or of the given build, calling the handler for each
+    /// line until the build finishes. Returns the result of the build, such as succeeded or failed
+    ///
+    /// The output is sent as server sent events, with a line in each output event and the result in the finished event.
+    /// This returns None if the function app doesn't exist
+    pub async fn follow_build_log(&self, app: &FunctionAppRef, build: &Option<Uuid>, mut handler: impl FnMut(&str)) -> Result<Option<String>, String> {
+        let url = self.url(&format!("/function-apps/{}/build-logs", app.to_path()));
+
+        let options = BuildLogOptions {
+            build: *build,
+            follow: true,
+        };
+
+        // Make the request
+        let mut res = match self.client.get(url).query(&options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => {},
+            404 => return match get_error(res).await {
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                error => Err(error.message),
+            },
+            _ => return Err(get_error(res).await.message),
+        }
+
+        // Events can be split across chunks, so keep reading until there is a complete event, which ends with a blank line
+        let mut buffer = String::new();
+        loop {
+            let chunk = match res.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return Err("The server closed the build log before the build finished".to_string()),
+                Err(e) => return Err(format!("Error reading build log: {}", e)),
+            };
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+            while let Some(end) = buffer.find("\n\n") {
+                let frame: String = buffer.drain(..end + 2).collect();
+
+                // Comments have no event
+                let event = frame.lines().find_map(|line| line.strip_prefix("event: ")).unwrap_or_default();
+                let data: Vec<&str> = frame.lines().filter_map(|line| line.strip_prefix("data: ")).collect();
+
+                match event {
+                    BUILD_OUTPUT_EVENT => handler(&data.join("\n")),
+                    BUILD_FINISHED_EVENT => return Ok(Some(data.join("\n"))),
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    /// Gets the most recent output from the container for a function app as plain text, with when each line was written
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn logs(&self, app: &FunctionAppRef, tail: usize) -> Result<Option<String>, String> {
+        let url = self.url(&format!("/function-apps/{}/logs", app.to_path()));
+
+        let options = LogsOptions {
+            tail,
+            follow: false,
+        };
+
+        // Make the request
+        let res = match self.client.get(url).query(&options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.text().await {
+                Ok(logs) => Ok(Some(logs)),
+                Err(e) => Err(format!("Error reading logs: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Follows the output from the container for a function app, calling the handler with the most recent lines and
+    /// then each line as the app writes it, until the container exits
+    ///
+    /// The output is sent as server sent events, with a line in each event. This returns None if the function app
+    /// doesn't exist
+    pub async fn follow_logs(&self, app: &FunctionAppRef, tail: usize, mut handler: impl FnMut(&str)) -> Result<Option<()>, String> {
+        let url = self.url(&format!("/function-apps/{}/logs", app.to_path()));
+
+        let options = LogsOptions {
+            tail,
+            follow: true,
+        };
+
+        // Make the request
+        let mut res = match self.client.get(url).query(&options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => {},
+            404 => return Ok(None),
+            _ => return Err(get_error(res).await.message),
+        }
+
+        // Events can be split across chunks, so keep reading until there is a complete event, which ends with a blank line
+        let mut buffer = String::new();
+        loop {
+            let chunk = match res.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return Ok(Some(())),
+                Err(e) => return Err(format!("Error reading logs: {}", e)),
+            };
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+            while let Some(end) = buffer.find("\n\n") {
+                let frame: String = buffer.drain(..end + 2).collect();
+
+                // Comments have no data, and lines of output have no event
+                let event = frame.lines().find_map(|line| line.strip_prefix("event: "));
+                let data: Vec<&str> = frame.lines().filter_map(|line| line.strip_prefix("data: ")).collect();
+
+                match event {
+                    Some(LOGS_ERROR_EVENT) => return Err(data.join("\n")),
+                    None if !data.is_empty() => handler(&data.join("\n")),
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    /// Gets the CycloneDX bill of materials for a deployment of a function app. The deployment is a number, or latest
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn deployment_sbom(&self, app: &FunctionAppRef, deployment: &str) -> Result<Option<String>, String> {
+        let url = self.url(&format!("/function-apps/{}/deployments/{}/sbom", app.to_path(), deployment));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.text().await {
+                Ok(sbom) => Ok(Some(sbom)),
+                Err(e) => Err(format!("Error reading response text: {}", e)),
+            },
+            // A 404 is either an unknown app, or a deployment that doesn't exist or has no bill of materials
+            404 => match get_error(res).await {
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                error => Err(error.message),
+            },
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Exports a deployment of a function app so it can be promoted to another server with import_deployment. The
+    /// deployment is a number, or latest. Only the latest deployment can be exported, and it must be approved
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn export_deployment(&self, app: &FunctionAppRef, deployment: &str) -> Result<Option<DeploymentExportStream>, String> {
+        let url = self.url(&format!("/function-apps/{}/deployments/{}/export", app.to_path(), deployment));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => {},
+            // A 404 is either an unknown app, or a deployment that doesn't exist
+            404 => return match get_error(res).await {
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                error => Err(error.message),
+            },
+            _ => return Err(get_error(res).await.message),
+        }
+
+        let header = |name: &str| res.headers().get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string());
+        let deployment = match header(EXPORT_DEPLOYMENT_HEADER).and_then(|value| value.parse::<u32>().ok()) {
+            Some(deployment) => deployment,
+            None => return Err("The server didn't say which deployment it exported".to_string()),
+        };
+        let image_id = match header(EXPORT_IMAGE_ID_HEADER) {
+            Some(image_id) => image_id,
+            None => return Err("The server didn't say which image it exported".to_string()),
+        };
+
+        Ok(Some(DeploymentExportStream { deployment, image_id, response: res }))
+    }
+
+    /// Imports a deployment exported from another server as a new deployment of a function app, streaming the export
+    /// to this server as it arrives. The other server is recorded with the deployment as where it was promoted from
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn import_deployment(&self, app: &FunctionAppRef, export: DeploymentExportStream, from: &str) -> Result<Option<ImportedDeployment>, String> {
+        let url = self.url(&format!("/function-apps/{}/import", app.to_path()));
+        let body = reqwest::Body::wrap_stream(export.response.bytes_stream());
+
+        // Make the request
+        let res = match self.client.post(url).query(&[("from", from)]).body(body).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<ImportedDeployment>().await {
+                Ok(imported) => Ok(Some(imported)),
+                Err(e) => Err(format!("Error parsing imported deployment: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Records that a deployment of a function app was promoted to another server
+    ///
+    /// This returns false if the function app or deployment doesn't exist
+    pub async fn record_promotion(&self, app: &FunctionAppRef, number: u32, promotion: &Promotion) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/deployments/{}/promotions", app.to_path(), number));
+
+        // Make the request
+        let res = match self.client.post(url).json(promotion).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets everything about a function app in one call: its status, settings, limits, recent deployments, triggers,
+    /// and replicas
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn info(&self, app: &FunctionAppRef) -> Result<Option<FunctionAppInfo>, String> {
+        let url = self.url(&format!("/function-apps/{}/info", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<FunctionAppInfo>().await {
+                Ok(info) => Ok(Some(info)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the README from the code for the latest deployment of a function app
+    ///
+    /// This returns None if the function app doesn't exist, and Some(None) if the code didn't have a README
+    pub async fn readme(&self, app: &FunctionAppRef) -> Result<Option<Option<String>>, String> {
+        let url = self.url(&format!("/function-apps/{}/readme", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.text().await {
+                Ok(readme) => Ok(Some(Some(readme))),
+                Err(e) => Err(format!("Error reading response text: {}", e)),
+            },
+            // A 404 is either an unknown app, or an app whose code has no README
+            404 => match get_error(res).await {
+                error if error.code == ApiError::NoReadme => Ok(Some(None)),
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                error => Err(error.message),
+            },
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Checks the image for a function app was signed by the server and hasn't changed since
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn verify_image(&self, app: &FunctionAppRef) -> Result<Option<ImageVerification>, String> {
+        let url = self.url(&format!("/function-apps/{}/signature", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<ImageVerification>().await {
+                Ok(verification) => Ok(Some(verification)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the public key the server signs images with, as PEM
+    pub async fn signing_key(&self) -> Result<String, String> {
+        let url = self.url("/signing-key");
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.text().await {
+                Ok(pem) => Ok(pem),
+                Err(e) => Err(format!("Error reading response text: {}", e)),
+            },
+            404 => Err("The server is too old to sign images".to_string()),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets how the crates.io cache on the server has been used and what it has stored
+    pub async fn crates_cache_stats(&self) -> Result<CratesCacheStats, String> {
+        let url = self.url("/crates-cache");
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<CratesCacheStats>().await {
+                Ok(stats) => Ok(stats),
+                Err(e) => Err(format!("Error parsing response: {}", e)),
+            },
+            404 => Err("The server is too old to cache crates".to_string()),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Removes everything from the crates.io cache on the server
+    pub async fn purge_crates_cache(&self) -> Result<CratesCachePurge, String> {
+        let url = self.url("/crates-cache/purge");
+
+        // Make the request
+        let res = match self.client.post(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<CratesCachePurge>().await {
+                Ok(purged) => Ok(purged),
+                Err(e) => Err(format!("Error parsing response: {}", e)),
+            },
+            404 => Err("The server is too old to cache crates".to_string()),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the limits the server puts on requests, and how many requests it has rejected for breaking them
+    pub async fn request_limits(&self) -> Result<RequestLimitsReport, String> {
+        let url = self.url("/limits");
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<RequestLimitsReport>().await {
+                Ok(report) => Ok(report),
+                Err(e) => Err(format!("Error parsing response: {}", e)),
+            },
+            404 => Err("The server is too old to report its request limits".to_string()),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the quotas on the server and how much of each is used
+    pub async fn quotas(&self) -> Result<QuotasReport, String> {
+        let url = self.url("/quotas");
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<QuotasReport>().await {
+                Ok(report) => Ok(report),
+                Err(e) => Err(format!("Error parsing response: {}", e)),
+            },
+            404 => Err("The server is too old to report its quotas".to_string()),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Stops a running function app, waiting for it to finish the requests in flight
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn stop(&self, app: &FunctionAppRef) -> Result<Option<AppStop>, String> {
+        let url = self.url(&format!("/function-apps/{}/stop", app.to_path()));
+
+        // Make the request
+        let res = match self.client.post(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<AppStop>().await {
+                Ok(stop) => Ok(Some(stop)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Restarts a running function app on a new container without dropping requests. The old container is stopped
+    /// once the new one passes its health check
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn restart(&self, app: &FunctionAppRef) -> Result<Option<AppRestart>, String> {
+        let url = self.url(&format!("/function-apps/{}/restart", app.to_path()));
+
+        // Make the request
+        let res = match self.client.post(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<AppRestart>().await {
+                Ok(restart) => Ok(Some(restart)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            // The app couldn't be moved to a new container, such as when it isn't running or the new container failed
+            // its health check
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Deletes a function app, stopping it if it is running and removing its image and everything stored for it
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn delete(&self, app: &FunctionAppRef) -> Result<Option<()>, String> {
+        let url = self.url(&format!("/function-apps/{}", app.to_path()));
+
+        // Make the request
+        let res = match self.client.delete(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(Some(())),
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the routes handled by a running function app
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn routes(&self, app: &FunctionAppRef) -> Result<Option<AppRouteManifest>, String> {
+        let url = self.url(&format!("/function-apps/{}/routes", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<AppRouteManifest>().await {
+                Ok(manifest) => Ok(Some(manifest)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            // A 404 is either an unknown app, or an app that doesn't serve a route manifest
+            404 => match get_error(res).await {
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                error => Err(error.message),
+            },
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets all the function apps from the server
+    pub async fn list(&self) -> Result<Vec<FunctionApp>, String> {
+        let url = self.url("/function-apps");
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        // If the server is correct, we should get a 200 status code
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        match res.json::<Vec<FunctionApp>>().await {
+            Ok(function_apps) => Ok(function_apps),
+            Err(e) => Err(format!("Error parsing JSON: {}", e)),
+        }
+    }
+
+    /// Gets every function app on the server with its status, version, uptime, and any error, in one call
+    pub async fn overviews(&self) -> Result<Vec<FunctionAppOverview>, String> {
+        let url = self.url("/function-apps/status");
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<Vec<FunctionAppOverview>>().await {
+                Ok(overviews) => Ok(overviews),
+                Err(e) => Err(format!("Error parsing response: {}", e)),
+            },
+            404 => Err("The server is too old to show the status of every app at once".to_string()),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the deployment analytics for every function app on the server over the last few weeks, or only the app
+    /// with the given name or the apps in the given namespace
+    ///
+    /// This returns None if an app is given and it doesn't exist
+    pub async fn analytics(&self, weeks: u32, app: &Option<String>, namespace: &Option<String>) -> Result<Option<AnalyticsReport>, String> {
+        let url = self.url("/analytics");
+
+        let options = AnalyticsOptions {
+            weeks,
+            app: app.clone(),
+            namespace: namespace.clone(),
+        };
+
+        // Make the request
+        let res = match self.client.get(url).query(&options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<AnalyticsReport>().await {
+                Ok(report) => Ok(Some(report)),
+                Err(e) => Err(format!("Error parsing response: {}", e)),
+            },
+            // A 404 is either an unknown app, or a server too old to have analytics
+            404 => match get_error(res).await {
+                error if error.code == ApiError::AppNotFound => Ok(None),
+                _ => Err("The server is too old to show deployment analytics".to_string()),
+            },
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the CPU, memory, and network every running function app on the server is using, or only the app
+    /// with the given name. This takes about a second, as the server samples the containers
+    ///
+    /// This returns None if the function app doesn't exist, or if no app is given and the server is too old to report
+    /// resource usage
+    pub async fn resource_usage(&self, app: &Option<String>) -> Result<Option<Vec<AppResourceUsage>>, String> {
+        let url = self.url("/function-apps/resource-usage");
+
+        let options = ResourceUsageOptions {
+            app: app.clone(),
+        };
+
+        // Make the request
+        let res = match self.client.get(url).query(&options).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<Vec<AppResourceUsage>>().await {
+                Ok(usage) => Ok(Some(usage)),
+                Err(e) => Err(format!("Error parsing response: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Starts a function app running
+    ///
+    /// This returns false if the server doesn't have the function app
+    pub async fn start(&self, app: &FunctionAppRef, with_deps: bool) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/start", app.to_path()));
+
+        // Make the request
+        let res = match self.client.post(url).query(&StartOptions { with_deps }).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            // The app can't be started yet, such as when it is waiting for approval or the server is out of memory
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Starts the given function apps, or every app that is ready if none are given, along with the apps they depend
+    /// on. The server starts each app once the apps it depends on are healthy, and returns what happened to each
+    pub async fn start_apps(&self, names: &[String]) -> Result<BulkStartReport, String> {
+        let url = self.url("/function-apps/start");
+
+        let json = BulkStartRequest {
+            apps: names.to_vec(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<BulkStartReport>().await {
+                Ok(report) => Ok(report),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Sets the function apps a function app depends on, or removes them if the list is empty
+    ///
+    /// This returns false if the server doesn't have the function app
+    pub async fn set_dependencies(&self, app: &FunctionAppRef, depends_on: &[String]) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/dependencies", app.to_path()));
+
+        let json = DependenciesRequest {
+            depends_on: depends_on.to_vec(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            // The server checks the apps exist and don't make a cycle
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the function apps a function app depends on, and the apps that depend on it
+    ///
+    /// This returns None if the function app doesn't exist
+    pub async fn dependencies(&self, app: &FunctionAppRef) -> Result<Option<DependenciesReport>, String> {
+        let url = self.url(&format!("/function-apps/{}/dependencies", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => match res.json::<DependenciesReport>().await {
+                Ok(report) => Ok(Some(report)),
+                Err(e) => Err(format!("Error parsing JSON: {}", e)),
+            },
+            404 => Ok(None),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Turns maintenance mode on or off for a function app
+    ///
+    /// This returns false if the server doesn't have the function app
+    pub async fn set_maintenance(&self, app: &FunctionAppRef, enabled: bool, message: &Option<String>) -> Result<bool, String> {
+        let url = self.url(&format!("/function-apps/{}/maintenance", app.to_path()));
+
+        let json = MaintenanceRequest {
+            enabled,
+            message: message.clone(),
+        };
+
+        // Make the request
+        let res = match self.client.post(url).json(&json).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Gets the name of the default function app on the server, if there is one
+    pub async fn default_app(&self) -> Result<Option<String>, String> {
+        let url = self.url("/default-app");
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        // If the server is correct, we should get a 200 status code
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        match res.json::<DefaultApp>().await {
+            Ok(default_app) => Ok(default_app.name),
+            Err(e) => Err(format!("Error parsing JSON: {}", e)),
+        }
+    }
+
+    /// Sets the default function app on the server, or clears it if the name is None
+    ///
+    /// This returns false if the server doesn't have the function app
+    pub async fn set_default_app(&self, name: Option<String>) -> Result<bool, String> {
+        let url = self.url("/default-app");
+
+        // Make the request
+        let res = match self.client.post(url).json(&DefaultApp { name }).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Get the status for the given function app from the server
+    ///
+    /// This returns None if the server doesn't have the function app
+    pub async fn status(&self, app: &FunctionAppRef) -> Result<Option<FunctionAppStatusResult>, String> {
+        let url = self.url(&format!("/function-apps/{}/status", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        if res.status() == 404 {
+            return Ok(None);
+        }
+
+        // If the server is correct, we should get a 200 status code
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        // Get the response JSON
+        match res.json::<FunctionAppStatusResult>().await {
+            Ok(json) => Ok(Some(json)),
+            Err(e) => Err(format!("Error parsing JSON: {}", e)),
+        }
+    }
+
+    /// Gets the position of the function app in the build queue on the server, along with the estimated wait in seconds
+    ///
+    /// This returns None if the function app is not waiting in the build queue.
+    pub async fn build_queue_position(&self, app: &FunctionAppRef) -> Result<Option<(usize, Option<u64>)>, String> {
+        let url = self.url(&format!("/function-apps/{}/status", app.to_path()));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        // Get the queue position from the status
+        match res.json::<FunctionAppStatusResult>().await {
+            Ok(json) => Ok(json.queue_position.map(|position| (position, json.estimated_wait_secs))),
+            Err(e) => Err(format!("Error parsing JSON: {}", e)),
+        }
+    }
+
+    /// Downloads a backup of the database for a namespace from the server
+    pub async fn backup_namespace(&self, namespace: &String) -> Result<Vec<u8>, String> {
+        let url = self.url(&format!("/namespaces/{}/backup", namespace));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        // If the server is correct, we should get a 200 status code
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        match res.bytes().await {
+            Ok(backup) => Ok(backup.to_vec()),
+            Err(e) => Err(format!("Error reading backup: {}", e)),
+        }
+    }
+
+    /// Gets the default settings for the function apps in a namespace on the server
+    pub async fn namespace_defaults(&self, namespace: &String) -> Result<NamespaceDefaults, String> {
+        let url = self.url(&format!("/namespaces/{}/defaults", namespace));
+
+        // Make the request
+        let res = match self.client.get(url).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        // If the server is correct, we should get a 200 status code
+        if res.status() != 200 {
+            return Err(get_error(res).await.message);
+        }
+
+        match res.json::<NamespaceDefaults>().await {
+            Ok(defaults) => Ok(defaults),
+            Err(e) => Err(format!("Error parsing JSON: {}", e)),
+        }
+    }
+
+    /// Sets the default settings for the function apps in a namespace on the server, replacing any that were set before
+    pub async fn set_namespace_defaults(&self, namespace: &String, defaults: &NamespaceDefaults) -> Result<(), String> {
+        let url = self.url(&format!("/namespaces/{}/defaults", namespace));
+
+        // Make the request
+        let res = match self.client.post(url).json(defaults).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(()),
+            // The server checks the defaults are valid
+            _ => Err(get_error(res).await.message),
+        }
+    }
+
+    /// Uploads a backup of the database for a namespace to the server, replacing the current database
+    pub async fn restore_namespace(&self, namespace: &String, backup: Vec<u8>) -> Result<(), String> {
+        let url = self.url(&format!("/namespaces/{}/restore", namespace));
+
+        // Make the request
+        let res = match self.client.post(url).body(backup).send().await {
+            Ok(res) => res,
+            Err(e) => return Err(format!("Error: {}", e)),
+        };
+
+        match res.status().as_u16() {
+            200 => Ok(()),
+            _ => Err(get_error(res).await.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brackets_ipv6_addresses() {
+        assert_eq!(format_address("::1", 8080), "[::1]:8080");
+        assert_eq!(format_address("2001:db8::10", 443), "[2001:db8::10]:443");
+    }
+
+    #[test]
+    fn leaves_hostnames_and_ipv4_addresses() {
+        assert_eq!(format_address("example.com", 8080), "example.com:8080");
+        assert_eq!(format_address("127.0.0.1", 80), "127.0.0.1:80");
+    }
+
+    #[test]
+    fn doesnt_bracket_twice() {
+        assert_eq!(format_address("[::1]", 8080), "[::1]:8080");
+    }
+
+    #[test]
+    fn normalizes_base_paths_to_a_leading_slash() {
+        assert_eq!(normalize_base_path("rustless"), "/rustless");
+        assert_eq!(normalize_base_path("/rustless/"), "/rustless");
+        assert_eq!(normalize_base_path(" //rustless// "), "/rustless");
+        assert_eq!(normalize_base_path("apps/rustless/"), "/apps/rustless");
+    }
+
+    #[test]
+    fn normalizes_root_base_paths_to_empty() {
+        assert_eq!(normalize_base_path(""), "");
+        assert_eq!(normalize_base_path("/"), "");
+        assert_eq!(normalize_base_path(" // "), "");
+    }
+
+    #[test]
+    fn builds_urls_under_the_base_path() {
+        let client = RustlessClient::new("::1", 8080).unwrap().with_base_path("rustless/");
+        assert_eq!(client.url("/function-apps"), "https://[::1]:8080/rustless/function-apps");
+
+        let client = RustlessClient::new("example.com", 443).unwrap();
+        assert_eq!(client.url("/function-apps"), "https://example.com:443/function-apps");
+    }
+}
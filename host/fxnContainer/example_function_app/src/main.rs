@@ -1,29 +1,21 @@
-use actix_web::{get, App, HttpServer, Responder};
-use clap::Parser;
-
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-   /// The port to start up
-   #[arg(short, long)]
-   port: u16,
-}
+use rustless_app::{logging, web, FunctionApp, Responder};
 
 /// This route is used as a test to ensure the server is running. It will return "Hello!"
-#[get("/hello")]
 async fn greet() -> impl Responder {
-    format!("Hello from the example function app!")
+    "Hello from the example function app!"
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+/// Greets the name in the route
+async fn greet_name(name: web::Path<String>) -> impl Responder {
+    logging::info(&format!("Greeting {}", name));
+    format!("Hello {} from the example function app!", name)
+}
 
-    // Create and start the server
-    HttpServer::new(|| {
-        App::new().service(greet)
-    })
-    .bind(("0.0.0.0", args.port))?
-    .run()
-    .await
-}
\ No newline at end of file
+fn main() -> std::io::Result<()> {
+    // The argument parsing, health and route manifest endpoints, shutdown and logging are handled by rustless_app
+    FunctionApp::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+        .get("/hello", greet)
+        .get("/hello/{name}", greet_name)
+        .on_shutdown(|| logging::info("Goodbye from the example function app!"))
+        .run()
+}
@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// The command line arguments the host starts every function app with
+#[derive(Parser, Debug)]
+#[command(about = "A rustless function app", long_about = None)]
+pub struct Args {
+    /// The port to start up
+    #[arg(short, long, required_unless_present_any = ["print_routes", "write_routes"])]
+    pub port: Option<u16>,
+
+    /// How long to wait for requests in flight to finish when the app is stopped, in seconds.
+    /// The host sets this to finish before its grace period ends, so the app isn't killed
+    #[arg(long, env = "RUSTLESS_SHUTDOWN_TIMEOUT", default_value_t = 30)]
+    pub shutdown_timeout: u64,
+
+    /// Print the route manifest as JSON and exit without starting the app
+    #[arg(long)]
+    pub print_routes: bool,
+
+    /// Write the route manifest as JSON to the given file and exit without starting the app
+    #[arg(long)]
+    pub write_routes: Option<PathBuf>,
+}
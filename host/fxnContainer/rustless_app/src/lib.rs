@@ -0,0 +1,364 @@
+//! Helpers for writing function apps for the rustless host
+//!
+//! A function app is a web server the host starts in a docker container. This crate handles the parts of the
+//! platform contract every app needs, so app authors only write their handlers:
+//!
+//! * Parsing the arguments the host starts the app with, such as the port
+//! * A health endpoint at /__health
+//! * A route manifest generated from the registered routes, served at /__routes. Running the app with
+//!   --print-routes or --write-routes outputs the manifest without starting the app, which the host
+//!   Dockerfile templates use to write routes.json when the app is built
+//! * Graceful shutdown. When the host stops the app with SIGTERM, the app stops accepting requests, reports
+//!   it is stopping from the health endpoint, and waits for requests in flight to finish before running any
+//!   shutdown hooks and exiting. The host sets RUSTLESS_SHUTDOWN_TIMEOUT so the app exits before it is killed
+//! * Structured JSON logging, including a log line for every request
+//!
+//! ```no_run
+//! use rustless_app::{FunctionApp, Responder};
+//!
+//! async fn greet() -> impl Responder {
+//!     "Hello!"
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     FunctionApp::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+//!         .get("/hello", greet)
+//!         .run()
+//! }
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Instant, SystemTime};
+
+use actix_web::dev::Service;
+use actix_web::http::Method;
+use actix_web::{App, FromRequest, Handler, HttpServer};
+use clap::Parser;
+use serde_json::json;
+
+pub mod args;
+pub mod logging;
+pub mod manifest;
+mod shutdown;
+
+pub use actix_web;
+pub use actix_web::{web, HttpRequest, HttpResponse, Responder};
+
+use args::Args;
+use logging::Level;
+use manifest::{Route, RouteManifest};
+
+/// The prefix for the routes every app has for the host to use. Apps can't add routes under this prefix
+pub const PLATFORM_ROUTE_PREFIX: &str = "/__";
+
+/// The route the host calls to check the app is healthy
+pub const HEALTH_ROUTE: &str = "/__health";
+
+/// The route that returns the route manifest for the app, used by the host to list the routes of the app
+pub const ROUTES_ROUTE: &str = "/__routes";
+
+/// The file the route manifest is written to by the host Dockerfile templates
+pub const ROUTES_FILE: &str = "routes.json";
+
+/// Adds a route to the actix app. Stored so it can be added to the app created for each worker
+type ConfigureRoute = Arc<dyn Fn(&mut web::ServiceConfig) + Send + Sync>;
+
+/// A function run when the app shuts down, after the requests in flight have finished
+type ShutdownHook = Box<dyn FnOnce() + Send>;
+
+/// The details of the running app, shared with the platform routes
+struct AppState {
+    // The route manifest for the app
+    manifest: RouteManifest,
+
+    // When the app started, used to report the uptime
+    started: SystemTime,
+
+    // Set when the app has been asked to shut down
+    stopping: AtomicBool,
+
+    // When the app was asked to shut down, used to report how long the shutdown took
+    shutdown_started: OnceLock<Instant>,
+
+    // The number of requests being handled
+    in_flight: AtomicUsize,
+}
+
+/// A function app, built up by adding handlers for each route and then run
+pub struct FunctionApp {
+    // The name of the app
+    name: String,
+
+    // The version of the app
+    version: String,
+
+    // The routes the app handles, for the route manifest
+    routes: Vec<Route>,
+
+    // The functions that add each route to the actix app
+    configure: Vec<ConfigureRoute>,
+
+    // The functions to run when the app shuts down
+    shutdown_hooks: Vec<ShutdownHook>,
+}
+
+impl FunctionApp {
+    /// Creates a function app with the given name and version. Use env!("CARGO_PKG_NAME") and
+    /// env!("CARGO_PKG_VERSION") to use the values from the app's Cargo.toml
+    pub fn new(name: &str, version: &str) -> FunctionApp {
+        FunctionApp {
+            name: name.to_string(),
+            version: version.to_string(),
+            routes: Vec::new(),
+            configure: Vec::new(),
+            shutdown_hooks: Vec::new(),
+        }
+    }
+
+    /// Adds a handler for the given method and path
+    ///
+    /// # Panics
+    ///
+    /// Panics if the path starts with /__, as these routes are used by the host
+    pub fn route<F, A>(mut self, method: Method, path: &str, handler: F) -> FunctionApp
+    where
+        F: Handler<A> + Send + Sync,
+        A: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        assert!(
+            !path.starts_with(PLATFORM_ROUTE_PREFIX),
+            "Routes starting with {} are reserved for the host",
+            PLATFORM_ROUTE_PREFIX
+        );
+
+        self.routes.push(Route {
+            method: method.to_string(),
+            path: path.to_string(),
+        });
+
+        let path = path.to_string();
+        self.configure.push(Arc::new(move |cfg: &mut web::ServiceConfig| {
+            cfg.route(&path, web::method(method.clone()).to(handler.clone()));
+        }));
+
+        self
+    }
+
+    /// Adds a handler for GET requests to the given path
+    pub fn get<F, A>(self, path: &str, handler: F) -> FunctionApp
+    where
+        F: Handler<A> + Send + Sync,
+        A: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.route(Method::GET, path, handler)
+    }
+
+    /// Adds a handler for POST requests to the given path
+    pub fn post<F, A>(self, path: &str, handler: F) -> FunctionApp
+    where
+        F: Handler<A> + Send + Sync,
+        A: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.route(Method::POST, path, handler)
+    }
+
+    /// Adds a handler for PUT requests to the given path
+    pub fn put<F, A>(self, path: &str, handler: F) -> FunctionApp
+    where
+        F: Handler<A> + Send + Sync,
+        A: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.route(Method::PUT, path, handler)
+    }
+
+    /// Adds a handler for DELETE requests to the given path
+    pub fn delete<F, A>(self, path: &str, handler: F) -> FunctionApp
+    where
+        F: Handler<A> + Send + Sync,
+        A: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.route(Method::DELETE, path, handler)
+    }
+
+    /// Adds a function to run when the app shuts down, after the requests in flight have finished.
+    /// Use this to flush any data the app is holding so it isn't lost when the app is restarted
+    pub fn on_shutdown<F>(mut self, hook: F) -> FunctionApp
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shutdown_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Gets the route manifest for the app
+    pub fn manifest(&self) -> RouteManifest {
+        RouteManifest {
+            app: self.name.to_string(),
+            version: self.version.to_string(),
+            routes: self.routes.clone(),
+        }
+    }
+
+    /// Writes the route manifest for the app to the given file as JSON
+    pub fn write_manifest(&self, path: &Path) -> io::Result<()> {
+        let manifest = match serde_json::to_string_pretty(&self.manifest()) {
+            Ok(manifest) => manifest,
+            Err(e) => return Err(io::Error::other(e)),
+        };
+
+        fs::write(path, manifest)
+    }
+
+    /// Parses the arguments from the host and runs the app until it is stopped
+    ///
+    /// This blocks until the app has shut down, so it can be called from a plain main function
+    pub fn run(self) -> io::Result<()> {
+        actix_web::rt::System::new().block_on(self.serve())
+    }
+
+    /// Parses the arguments from the host and serves the app until it is stopped
+    ///
+    /// Use this instead of run if the app already has an async runtime, such as from #[actix_web::main]
+    pub async fn serve(self) -> io::Result<()> {
+        let args = Args::parse();
+
+        // Print or write the manifest so the host or build tools can find the routes without starting the app
+        if args.print_routes {
+            println!("{}", json!(self.manifest()));
+            return Ok(());
+        }
+
+        if let Some(path) = &args.write_routes {
+            return self.write_manifest(path);
+        }
+
+        // The port is required unless the routes are printed or written, so clap has already checked it is set
+        let port = args.port.unwrap_or_default();
+
+        logging::set_app_name(&self.name);
+
+        let state = web::Data::new(AppState {
+            manifest: self.manifest(),
+            started: SystemTime::now(),
+            stopping: AtomicBool::new(false),
+            shutdown_started: OnceLock::new(),
+            in_flight: AtomicUsize::new(0),
+        });
+        let configure = self.configure;
+        let server_state = state.clone();
+
+        logging::log(Level::Info, "Starting function app", json!({ "port": port, "version": self.version }));
+
+        // Create and start the server. Signals are handled below rather than by actix, so the shutdown can be logged
+        let server = HttpServer::new(move || {
+            let mut app = App::new()
+                .app_data(server_state.clone())
+                .route(HEALTH_ROUTE, web::get().to(health))
+                .route(ROUTES_ROUTE, web::get().to(routes));
+
+            for configure_route in configure.iter() {
+                let configure_route = configure_route.clone();
+                app = app.configure(move |cfg| configure_route(cfg));
+            }
+
+            // Log every request with how long it took, counting the requests in flight
+            let request_state = server_state.clone();
+            app.wrap_fn(move |req, srv| {
+                let method = req.method().to_string();
+                let path = req.path().to_string();
+                let start = Instant::now();
+                let response = srv.call(req);
+                let request_state = request_state.clone();
+                request_state.in_flight.fetch_add(1, Ordering::SeqCst);
+
+                async move {
+                    let response = response.await;
+                    request_state.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let response = response?;
+                    logging::log(Level::Info, "Request handled", json!({
+                        "method": method,
+                        "path": path,
+                        "status": response.status().as_u16(),
+                        "duration_ms": start.elapsed().as_millis() as u64,
+                    }));
+                    Ok(response)
+                }
+            })
+        })
+        .disable_signals()
+        .shutdown_timeout(args.shutdown_timeout)
+        .bind(("0.0.0.0", port))?
+        .run();
+
+        // When asked to shut down, stop accepting connections and wait up to the shutdown timeout for the
+        // requests in flight to finish
+        let handle = server.handle();
+        let shutdown_timeout = args.shutdown_timeout;
+        let shutdown_state = state.clone();
+        actix_web::rt::spawn(async move {
+            let state = shutdown_state;
+            if let Err(e) = shutdown::wait_for_signal().await {
+                logging::log(Level::Error, "Error waiting for shutdown signal", json!({ "error": e.to_string() }));
+                return;
+            }
+
+            state.stopping.store(true, Ordering::SeqCst);
+            let _ = state.shutdown_started.set(Instant::now());
+            logging::log(Level::Info, "Shutting down, finishing requests in flight", json!({
+                "in_flight": state.in_flight.load(Ordering::SeqCst),
+                "timeout_secs": shutdown_timeout,
+            }));
+
+            handle.stop(true).await;
+        });
+
+        server.await?;
+
+        // Any requests still in flight were stopped because they didn't finish within the shutdown timeout
+        if let Some(shutdown_started) = state.shutdown_started.get() {
+            logging::log(Level::Info, "Stopped handling requests", json!({
+                "shutdown_ms": shutdown_started.elapsed().as_millis() as u64,
+                "unfinished": state.in_flight.load(Ordering::SeqCst),
+            }));
+        }
+
+        // Run the shutdown hooks now no more requests are being handled
+        for hook in self.shutdown_hooks {
+            hook();
+        }
+
+        logging::info("Function app stopped");
+
+        Ok(())
+    }
+}
+
+/// The health endpoint, returning the app name, version and how long it has been running.
+/// Once the app is shutting down this returns 503 so no more requests are sent to it
+async fn health(state: web::Data<AppState>) -> HttpResponse {
+    let (mut response, status) = match state.stopping.load(Ordering::SeqCst) {
+        true => (HttpResponse::ServiceUnavailable(), "stopping"),
+        false => (HttpResponse::Ok(), "ok"),
+    };
+
+    response.json(json!({
+        "status": status,
+        "app": state.manifest.app,
+        "version": state.manifest.version,
+        "uptime_secs": state.started.elapsed().unwrap_or_default().as_secs(),
+    }))
+}
+
+/// The routes endpoint, returning the route manifest for the app
+async fn routes(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(&state.manifest)
+}
@@ -0,0 +1,66 @@
+use std::sync::OnceLock;
+
+use serde_json::{Map, Value};
+
+/// The name of the app, added to every log line so logs from different apps can be told apart
+static APP_NAME: OnceLock<String> = OnceLock::new();
+
+/// The level of a log line
+#[derive(Clone, Copy, Debug)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    /// Gets the name of the level as it appears in the log
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// Sets the app name added to every log line. Only the first call has any effect
+pub(crate) fn set_app_name(name: &str) {
+    let _ = APP_NAME.set(name.to_string());
+}
+
+/// Writes a structured log line to stdout as a single JSON object
+///
+/// Every line has the timestamp, level, app name and message. Any fields in the fields object are added
+/// alongside them, so the host can collect and filter the logs from all apps the same way.
+pub fn log(level: Level, message: &str, fields: Value) {
+    let mut line = Map::new();
+    line.insert("timestamp".to_string(), Value::String(chrono::Utc::now().to_rfc3339()));
+    line.insert("level".to_string(), Value::String(level.as_str().to_string()));
+    line.insert("app".to_string(), Value::String(APP_NAME.get().cloned().unwrap_or_default()));
+    line.insert("message".to_string(), Value::String(message.to_string()));
+
+    // Add the extra fields, without letting them replace the standard ones
+    if let Value::Object(fields) = fields {
+        for (key, value) in fields {
+            line.entry(key).or_insert(value);
+        }
+    }
+
+    println!("{}", Value::Object(line));
+}
+
+/// Writes an info log line
+pub fn info(message: &str) {
+    log(Level::Info, message, Value::Null);
+}
+
+/// Writes a warning log line
+pub fn warn(message: &str) {
+    log(Level::Warn, message, Value::Null);
+}
+
+/// Writes an error log line
+pub fn error(message: &str) {
+    log(Level::Error, message, Value::Null);
+}
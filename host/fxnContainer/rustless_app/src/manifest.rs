@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// A route handled by a function app
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Route {
+    // The HTTP method, such as GET or POST
+    pub method: String,
+
+    // The path of the route, relative to the app
+    pub path: String,
+}
+
+/// The routes handled by a function app, generated from the routes registered with the app
+///
+/// This is returned by the /__routes endpoint, and the --print-routes and --write-routes arguments
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RouteManifest {
+    // The name of the app
+    pub app: String,
+
+    // The version of the app
+    pub version: String,
+
+    // The routes the app handles, not including the platform routes
+    pub routes: Vec<Route>,
+}
@@ -0,0 +1,21 @@
+use std::io;
+
+/// Waits until the app is asked to shut down, with SIGTERM from docker when the host stops the app,
+/// or Ctrl-C when running locally
+#[cfg(unix)]
+pub async fn wait_for_signal() -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        _ = terminate.recv() => Ok(()),
+        result = tokio::signal::ctrl_c() => result,
+    }
+}
+
+/// Waits until the app is asked to shut down with Ctrl-C
+#[cfg(not(unix))]
+pub async fn wait_for_signal() -> io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use rustless_shared::{AdoptReport, AdoptedApp, SkippedImage};
+
+use crate::docker;
+use crate::storage;
+
+/// Registers the function apps that have a built image with rustless labels but aren't in the database, such as after
+/// the database was restored from a backup. The apps keep the ID and deployment number from their image, and are
+/// ready to start without being deployed again. Apps with containers still running are marked as running by the
+/// reconciler once they are registered
+pub fn adopt() -> Result<AdoptReport, String> {
+    let images = docker::get_app_images()?;
+    let apps = storage::get_all_apps()?;
+
+    let ids: HashSet<_> = apps.iter().map(|app| app.id).collect();
+    let mut report = AdoptReport { adopted: Vec::new(), skipped: Vec::new() };
+
+    for image in images.into_iter().filter(|image| !ids.contains(&image.id)) {
+        if apps.iter().any(|app| app.name == image.name) {
+            report.skipped.push(SkippedImage {
+                name: image.name,
+                reason: "Another app with this name is registered".to_string(),
+            });
+            continue;
+        }
+
+        if let Err(e) = storage::validate_namespace(&image.namespace) {
+            report.skipped.push(SkippedImage { name: image.name, reason: e });
+            continue;
+        }
+
+        let adopted = storage::create_namespace_connection(&image.namespace)
+            .and_then(|mut conn| storage::adopt_function_app(&mut conn, &image.id, &image.name, &image.namespace, image.deployment));
+
+        match adopted {
+            Ok(true) => {
+                println!("Adopted {} in namespace {} from its image", image.name, image.namespace);
+                report.adopted.push(AdoptedApp {
+                    id: image.id,
+                    name: image.name,
+                    namespace: image.namespace,
+                    deployment: image.deployment,
+                });
+            },
+            Ok(false) => report.skipped.push(SkippedImage {
+                name: image.name,
+                reason: "Another app with this name is registered".to_string(),
+            }),
+            Err(e) => report.skipped.push(SkippedImage {
+                name: image.name,
+                reason: format!("Error registering the app: {}", e),
+            }),
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,136 @@
+use std::time::SystemTime;
+
+use rustless_shared::{AnalyticsOptions, AnalyticsReport, AppAnalytics, FunctionApp, FunctionAppStatus, WeekAnalytics};
+
+use crate::database::Connection;
+use crate::storage;
+
+/// The number of seconds in a week
+const WEEK_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// The most weeks the analytics can cover, which is about ten years
+const MAX_WEEKS: u32 = 520;
+
+/// Checks the number of weeks asked for can be covered
+pub fn validate_weeks(weeks: u32) -> Result<(), String> {
+    match weeks {
+        0 => Err("The analytics must cover at least 1 week".to_string()),
+        weeks if weeks > MAX_WEEKS => Err(format!("Invalid weeks {}: the analytics can cover at most {} weeks", weeks, MAX_WEEKS)),
+        _ => Ok(()),
+    }
+}
+
+/// Gets the average of some values, or None if there aren't any
+fn mean(values: &[u64]) -> Option<f64> {
+    match values.len() {
+        0 => None,
+        count => Some(values.iter().sum::<u64>() as f64 / count as f64),
+    }
+}
+
+/// Gets the deployment analytics for a function app over the given number of weeks, starting at the given time
+fn get_app_analytics(conn: &Connection, app: &FunctionApp, since: u64, weeks: u32) -> Result<AppAnalytics, String> {
+    let deployment_times = match storage::get_deployment_times_since(conn, &app.id, since) {
+        Ok(times) => times,
+        Err(e) => return Err(format!("Error getting deployments for function app {}: {}", app.name, e)),
+    };
+
+    let builds = match storage::get_builds_since(conn, &app.id, since) {
+        Ok(builds) => builds,
+        Err(e) => return Err(format!("Error getting builds for function app {}: {}", app.name, e)),
+    };
+
+    let status_changes = match storage::get_status_changes_since(conn, &app.id, since) {
+        Ok(changes) => changes,
+        Err(e) => return Err(format!("Error getting status history for function app {}: {}", app.name, e)),
+    };
+
+    let mut week_analytics: Vec<WeekAnalytics> = (0..weeks as u64).map(|week| WeekAnalytics {
+        starts_at: since + week * WEEK_SECS,
+        deployments: 0,
+        builds: 0,
+        failed_builds: 0,
+        failures: 0,
+    }).collect();
+
+    // Anything that happened as the analytics were gathered goes in the last week
+    let week_index = |time: u64| (time.saturating_sub(since) / WEEK_SECS).min(weeks as u64 - 1) as usize;
+
+    for created_at in deployment_times.iter() {
+        week_analytics[week_index(*created_at)].deployments += 1;
+    }
+
+    let mut build_durations = Vec::new();
+    for (started_at, duration, succeeded) in builds.iter() {
+        let week = &mut week_analytics[week_index(*started_at)];
+        week.builds += 1;
+        match succeeded {
+            true => build_durations.push(*duration),
+            false => week.failed_builds += 1,
+        }
+    }
+
+    // The app has recovered from a crash once it is next running, whether it was started again by hand, by a
+    // restart, or by a new deployment
+    let mut failed_at = None;
+    let mut recovery_times = Vec::new();
+    for (changed_at, status) in status_changes.iter() {
+        if *status == FunctionAppStatus::Error as u8 {
+            week_analytics[week_index(*changed_at)].failures += 1;
+            failed_at = failed_at.or(Some(*changed_at));
+        } else if *status == FunctionAppStatus::Running as u8 {
+            if let Some(failed_at) = failed_at.take() {
+                recovery_times.push(changed_at.saturating_sub(failed_at));
+            }
+        }
+    }
+
+    let build_count = builds.len() as u32;
+    Ok(AppAnalytics {
+        name: app.name.clone(),
+        id: app.id,
+        namespace: app.namespace.clone(),
+        deployments: deployment_times.len() as u32,
+        deploys_per_week: deployment_times.len() as f64 / weeks as f64,
+        builds: build_count,
+        build_success_rate: match build_count {
+            0 => None,
+            _ => Some(build_durations.len() as f64 * 100.0 / build_count as f64),
+        },
+        mean_build_secs: mean(&build_durations),
+        failures: week_analytics.iter().map(|week| week.failures).sum(),
+        mean_time_to_recovery_secs: mean(&recovery_times),
+        weeks: week_analytics,
+    })
+}
+
+/// Gets the deployment analytics for the function apps on the host, or only those picked by the options, over the
+/// weeks ending now. Returns None if an app was asked for by name and there isn't one with that name
+pub fn get_analytics(options: &AnalyticsOptions) -> Result<Option<AnalyticsReport>, String> {
+    validate_weeks(options.weeks)?;
+
+    let apps: Vec<FunctionApp> = storage::get_all_apps()?.into_iter()
+        .filter(|app| options.app.as_ref().is_none_or(|name| &app.name == name))
+        .filter(|app| options.namespace.as_ref().is_none_or(|namespace| &app.namespace == namespace))
+        .collect();
+
+    if options.app.is_some() && apps.is_empty() {
+        return Ok(None);
+    }
+
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    let since = now.saturating_sub(options.weeks as u64 * WEEK_SECS);
+
+    // Each app is read from the database for its namespace, as namespaces can be stored separately
+    let mut app_analytics = Vec::new();
+    for app in apps.iter() {
+        let conn = storage::create_connection_for_app(&app.id)?;
+        app_analytics.push(get_app_analytics(&conn, app, since, options.weeks)?);
+    }
+
+    Ok(Some(AnalyticsReport {
+        weeks: options.weeks,
+        since,
+        apps: app_analytics,
+    }))
+}
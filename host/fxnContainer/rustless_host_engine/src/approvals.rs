@@ -0,0 +1,55 @@
+use actix_web::{HttpRequest, HttpResponse};
+
+use rustless_shared::ApiError;
+
+use crate::errors;
+
+/// The environment variable that turns on approvals. When set to 1 or true, uploaded code
+/// must be approved before the function app can be started
+const REQUIRE_APPROVAL_ENV: &str = "RUSTLESS_REQUIRE_APPROVAL";
+
+/// The environment variable containing the comma separated keys for the users with the approver role
+const APPROVER_KEYS_ENV: &str = "RUSTLESS_APPROVER_KEYS";
+
+/// Gets if uploaded code needs to be approved before the function app can be started
+pub fn is_approval_required() -> bool {
+    match std::env::var(REQUIRE_APPROVAL_ENV) {
+        Ok(value) => value == "1" || value.eq_ignore_ascii_case("true"),
+        Err(_) => false,
+    }
+}
+
+/// Gets the keys for the users with the approver role
+fn get_approver_keys() -> Vec<String> {
+    match std::env::var(APPROVER_KEYS_ENV) {
+        Ok(keys) => keys.split(',')
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Checks the request is from an approver, returning the error response if it isn't
+///
+/// Approvers send their key as a bearer token in the Authorization header
+pub fn check_approver(req: &HttpRequest) -> Result<(), Box<HttpResponse>> {
+    let keys = get_approver_keys();
+    if keys.is_empty() {
+        return Err(Box::new(errors::response(
+            ApiError::NoApprovers,
+            &format!("No approvers are configured. Set {} to allow deployments to be approved", APPROVER_KEYS_ENV),
+        )));
+    }
+
+    let key = req.headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match key {
+        Some(key) if keys.iter().any(|approver_key| approver_key == key) => Ok(()),
+        Some(_) => Err(Box::new(errors::response(ApiError::NotApprover, "The key does not have the approver role"))),
+        None => Err(Box::new(errors::response(ApiError::NoKey, "An approver key is required to approve deployments"))),
+    }
+}
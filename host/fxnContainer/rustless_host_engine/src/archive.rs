@@ -0,0 +1,202 @@
+use tempfile::TempDir;
+
+use crate::function_app_builder;
+use crate::quotas;
+
+/// The environment variable containing the most the uploaded code can take up once it is unzipped, such as 4g
+const MAX_UNZIPPED_SIZE_ENV: &str = "RUSTLESS_MAX_UNZIPPED_SIZE";
+
+/// The most the uploaded code can take up once it is unzipped if the environment variable isn't set
+const DEFAULT_MAX_UNZIPPED_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+/// The manifest every crate has at its root
+const MANIFEST_FILE_NAME: &str = "Cargo.toml";
+
+/// Why an uploaded zip file can't be built
+pub struct InvalidArchive {
+    // What is wrong with the zip file
+    pub message: String,
+
+    // The file in the zip file that is wrong, if the problem is with one file
+    pub file: Option<String>,
+}
+
+impl InvalidArchive {
+    /// Creates the reason a zip file can't be built, when the problem isn't with one file
+    fn new(message: &str) -> InvalidArchive {
+        InvalidArchive { message: message.to_string(), file: None }
+    }
+
+    /// Creates the reason a zip file can't be built because of a file in it
+    fn for_file(message: &str, file: &str) -> InvalidArchive {
+        InvalidArchive { message: message.to_string(), file: Some(file.to_string()) }
+    }
+}
+
+/// Gets the most the uploaded code can take up once it is unzipped, in bytes
+fn get_max_unzipped_size() -> u64 {
+    match std::env::var(MAX_UNZIPPED_SIZE_ENV) {
+        Ok(value) => match quotas::parse_size(&value) {
+            Some(size) if size > 0 => size,
+            _ => {
+                println!("Ignoring invalid {}: {}", MAX_UNZIPPED_SIZE_ENV, value);
+                DEFAULT_MAX_UNZIPPED_SIZE
+            }
+        },
+        Err(_) => DEFAULT_MAX_UNZIPPED_SIZE,
+    }
+}
+
+/// Lists the paths of the files and folders in the uploaded zip file, as they are stored in it. Returns None if
+/// the zip file can't be read, such as if it isn't a zip file or is empty
+fn list_entries(temp_dir: &TempDir) -> Option<Vec<String>> {
+    let archive = function_app_builder::open_zip_file(temp_dir).ok()?;
+    Some(archive.file_names().filter(|entry| !entry.is_empty()).map(str::to_string).collect())
+}
+
+/// Checks a path in the zip file would be unzipped inside the build directory. Absolute paths, including Windows
+/// paths with a drive letter, and paths going up a folder with .. could overwrite files anywhere on the host
+fn check_path(entry: &str) -> Option<InvalidArchive> {
+    let is_absolute = entry.starts_with('/') || entry.starts_with('\\') || entry.chars().nth(1) == Some(':');
+    if is_absolute {
+        return Some(InvalidArchive::for_file(&format!("The zip file has an absolute path: {}", entry), entry));
+    }
+
+    if entry.split(['/', '\\']).any(|component| component == "..") {
+        return Some(InvalidArchive::for_file(&format!("The zip file has a path outside its folder: {}", entry), entry));
+    }
+
+    None
+}
+
+/// Checks the paths in the zip file are one crate: a single folder with a Cargo.toml at its root, and no other
+/// Cargo.toml files
+fn check_crate(entries: &[String]) -> Option<InvalidArchive> {
+    let folder = match entries.first().and_then(|entry| entry.split('/').next()) {
+        Some(folder) => folder,
+        None => return Some(InvalidArchive::new("The zip file is empty")),
+    };
+
+    // Every path must be inside the folder, and files can't be next to it
+    for entry in entries {
+        if !entry.starts_with(&format!("{}/", folder)) {
+            return Some(InvalidArchive::for_file(&format!("Zip file must contain exactly one folder, but {} is outside {}", entry, folder), entry));
+        }
+    }
+
+    let manifest = format!("{}/{}", folder, MANIFEST_FILE_NAME);
+    if !entries.contains(&manifest) {
+        return Some(InvalidArchive::new(&format!("The zip file must contain a crate, but {} doesn't have a {}", folder, MANIFEST_FILE_NAME)));
+    }
+
+    let other_manifest = entries.iter().find(|entry| **entry != manifest && entry.rsplit('/').next() == Some(MANIFEST_FILE_NAME));
+    if let Some(other_manifest) = other_manifest {
+        return Some(InvalidArchive::for_file(
+            &format!("The zip file must contain exactly one crate, but has another {} at {}", MANIFEST_FILE_NAME, other_manifest),
+            other_manifest,
+        ));
+    }
+
+    None
+}
+
+/// Checks the uploaded zip file saved in the temporary directory before it is built, without unzipping it: that it
+/// is one crate, that every path is inside it, and that it isn't too large once unzipped. Returns why the zip file
+/// can't be built, or None if it can
+pub fn validate_archive(temp_dir: &TempDir) -> Result<Option<InvalidArchive>, String> {
+    let entries = match list_entries(temp_dir) {
+        Some(entries) => entries,
+        None => return Ok(Some(InvalidArchive::new("The code isn't a valid zip file"))),
+    };
+
+    // Check every path first, so a path that could escape the build directory is reported whatever else is wrong
+    if let Some(invalid) = entries.iter().find_map(|entry| check_path(entry)) {
+        return Ok(Some(invalid));
+    }
+
+    if let Some(invalid) = check_crate(&entries) {
+        return Ok(Some(invalid));
+    }
+
+    let max_size = get_max_unzipped_size();
+    let size = function_app_builder::get_unzipped_size(temp_dir)?;
+    if size > max_size {
+        return Ok(Some(InvalidArchive::new(&format!(
+            "The code unzips to {} bytes, which is more than the {} byte limit set by {}",
+            size, max_size, MAX_UNZIPPED_SIZE_ENV
+        ))));
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Converts a list of paths to the entries of a zip file
+    fn entries(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn accepts_paths_inside_the_folder() {
+        assert!(check_path("app/src/main.rs").is_none());
+        assert!(check_path("app/..hidden/file").is_none());
+        assert!(check_path("app/file..txt").is_none());
+    }
+
+    #[test]
+    fn rejects_paths_going_up_a_folder() {
+        let invalid = check_path("app/../../etc/passwd").unwrap();
+        assert_eq!(invalid.file.as_deref(), Some("app/../../etc/passwd"));
+        assert!(invalid.message.contains("outside its folder"));
+
+        assert!(check_path("..").is_some());
+        assert!(check_path("app\\..\\..\\evil.exe").is_some());
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        assert!(check_path("/etc/passwd").unwrap().message.contains("absolute path"));
+        assert!(check_path("\\Windows\\System32\\evil.dll").is_some());
+        assert!(check_path("C:\\Windows\\evil.dll").is_some());
+        assert!(check_path("c:/app/Cargo.toml").is_some());
+    }
+
+    #[test]
+    fn accepts_a_single_crate() {
+        assert!(check_crate(&entries(&["app/", "app/Cargo.toml", "app/src/", "app/src/main.rs"])).is_none());
+        assert!(check_crate(&entries(&["app/src/main.rs", "app/Cargo.toml", "app/Cargo.toml.bak"])).is_none());
+    }
+
+    #[test]
+    fn rejects_empty_archives() {
+        assert!(check_crate(&[]).unwrap().message.contains("empty"));
+    }
+
+    #[test]
+    fn rejects_files_outside_the_folder() {
+        let invalid = check_crate(&entries(&["app/Cargo.toml", "README.md"])).unwrap();
+        assert_eq!(invalid.file.as_deref(), Some("README.md"));
+
+        let invalid = check_crate(&entries(&["app/Cargo.toml", "other/Cargo.toml"])).unwrap();
+        assert_eq!(invalid.file.as_deref(), Some("other/Cargo.toml"));
+
+        // A file at the root isn't a folder
+        assert!(check_crate(&entries(&["Cargo.toml", "src/main.rs"])).is_some());
+    }
+
+    #[test]
+    fn rejects_folders_without_a_manifest() {
+        let invalid = check_crate(&entries(&["app/src/main.rs", "app/src/Cargo.toml"])).unwrap();
+        assert!(invalid.message.contains("doesn't have a Cargo.toml"));
+    }
+
+    #[test]
+    fn rejects_nested_manifests() {
+        let invalid = check_crate(&entries(&["app/Cargo.toml", "app/src/main.rs", "app/vendor/dep/Cargo.toml"])).unwrap();
+        assert_eq!(invalid.file.as_deref(), Some("app/vendor/dep/Cargo.toml"));
+        assert!(invalid.message.contains("exactly one crate"));
+    }
+}
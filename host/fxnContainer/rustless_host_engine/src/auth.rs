@@ -0,0 +1,394 @@
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::http::Method;
+use actix_web::{web, Error, HttpRequest};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use rustless_shared::{ApiError, ApiKey};
+
+use crate::errors;
+use crate::key_files::KeyFileProvider;
+use crate::oidc::OidcProvider;
+use crate::storage;
+
+/// The start of every API key, so they are easy to spot, such as in leaked config files
+const API_KEY_PREFIX: &str = "rl_";
+
+/// The routes that stay open without an API key, by method and pattern: the landing page, health checks, web console
+/// pages, and the gateway to the apps. The approve routes are called with an approver key instead of an API key, which
+/// the endpoint checks itself. Every other route is part of the management API, so new routes need an API key unless
+/// they are added here
+const PUBLIC_ROUTES: [(Method, &str); 10] = [
+    (Method::GET, "/"),
+    (Method::GET, "/hello"),
+    (Method::GET, "/healthz"),
+    (Method::GET, "/readyz"),
+    (Method::GET, "/ui"),
+    (Method::GET, "/ui/{path:.*}"),
+    (Method::GET, "/api/{name}/{route:.*}"),
+    (Method::POST, "/api/{name}/{route:.*}"),
+    (Method::POST, "/function-apps/{id}/deployments/{number}/approve"),
+    (Method::POST, "/function-apps/by-name/{name}/deployments/{number}/approve"),
+];
+
+/// The route for the event stream. Browsers can't send headers when they open an event stream, so the web console
+/// sends its API key in a cookie for this route instead
+const EVENTS_PATTERN: &str = "/events";
+
+/// The cookie the web console sends its API key in when it opens the event stream
+const EVENTS_COOKIE: &str = "rustless_api_key";
+
+/// The environment variable that leaves the management API open while no provider has anything to accept, such as
+/// before the first API key is created. The API is closed until then by default
+const OPEN_API_ENV: &str = "RUSTLESS_OPEN_API";
+
+/// The providers that check the tokens sent to the management API, created from the environment once
+static PROVIDERS: OnceLock<Result<Vec<Box<dyn AuthProvider>>, String>> = OnceLock::new();
+
+/// The name of a provider, with what it accepts or why it can't be used
+pub type ProviderCheck = (&'static str, Result<String, String>);
+
+/// What a provider made of a bearer token
+#[derive(Debug)]
+pub enum AuthResult {
+    /// The provider accepted the token, with the name of who it belongs to
+    Accepted(String),
+
+    /// The provider didn't accept the token, with why if it is the kind of token the provider checks, such as an
+    /// expired token from the identity provider
+    Rejected(Option<String>),
+}
+
+/// Checks the bearer tokens sent to the management API. A request is accepted if any provider accepts its token
+pub trait AuthProvider: Send + Sync {
+    /// The name of the provider, shown when the host starts and when the config is checked
+    fn name(&self) -> &'static str;
+
+    /// Gets if the provider has anything to accept. The management API rejects every request until one of the providers
+    /// does, unless it has been left open
+    fn is_enabled(&self) -> Result<bool, String>;
+
+    /// Checks the provider can be used, returning what it accepts. This can be slow, such as fetching the signing
+    /// keys from an identity provider, so it is only run when the host starts or the config is checked
+    fn check(&self) -> Result<String, String>;
+
+    /// Checks a bearer token, returning the error and message if it can't be checked, such as when the database or
+    /// identity provider can't be reached
+    fn authenticate(&self, token: &str) -> Result<AuthResult, (ApiError, String)>;
+}
+
+/// Accepts the API keys created with rustless-hostctl keys create, which are stored in the database as hashes
+struct ApiKeyProvider;
+
+impl AuthProvider for ApiKeyProvider {
+    fn name(&self) -> &'static str {
+        "API keys"
+    }
+
+    fn is_enabled(&self) -> Result<bool, String> {
+        let conn = storage::create_connection()?;
+        storage::has_api_keys(&conn).map_err(|e| format!("Error getting API keys: {}", e))
+    }
+
+    fn check(&self) -> Result<String, String> {
+        let keys = list_keys()?;
+        Ok(format!("{} API keys that haven't been revoked", keys.iter().filter(|key| key.revoked_at.is_none()).count()))
+    }
+
+    fn authenticate(&self, token: &str) -> Result<AuthResult, (ApiError, String)> {
+        let conn = match storage::create_connection() {
+            Ok(conn) => conn,
+            Err(e) => return Err((ApiError::DatabaseError, e)),
+        };
+
+        match storage::get_valid_api_key_name(&conn, &hash_key(token)) {
+            Ok(Some(name)) => Ok(AuthResult::Accepted(name)),
+            Ok(None) if token.starts_with(API_KEY_PREFIX) => Ok(AuthResult::Rejected(Some("The API key is not valid, or has been revoked".to_string()))),
+            Ok(None) => Ok(AuthResult::Rejected(None)),
+            Err(e) => Err((ApiError::DatabaseError, e.to_string())),
+        }
+    }
+}
+
+/// Gets the providers set up in the environment, such as the key file and OpenID Connect provider
+fn get_configured_providers() -> Result<&'static Vec<Box<dyn AuthProvider>>, String> {
+    let providers = PROVIDERS.get_or_init(|| {
+        let mut providers: Vec<Box<dyn AuthProvider>> = Vec::new();
+        if let Some(provider) = KeyFileProvider::from_env() {
+            providers.push(Box::new(provider));
+        }
+
+        if let Some(provider) = OidcProvider::from_env()? {
+            providers.push(Box::new(provider));
+        }
+
+        Ok(providers)
+    });
+
+    providers.as_ref().map_err(|e| e.to_string())
+}
+
+/// Gets the providers that check tokens, starting with the API keys in the database, which are always checked
+fn get_providers() -> Result<Vec<&'static dyn AuthProvider>, String> {
+    let mut providers: Vec<&'static dyn AuthProvider> = vec![&ApiKeyProvider];
+    providers.extend(get_configured_providers()?.iter().map(|provider| provider.as_ref()));
+    Ok(providers)
+}
+
+/// Gets the current time as a Unix timestamp
+fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Hashes an API key for storing and looking up, so the keys can't be read from the database
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Creates an API key with the given name, returning the key. Only the hash is stored, so the key can't be shown again
+pub fn create_key(name: &str) -> Result<String, String> {
+    if name.trim().is_empty() {
+        return Err("The API key name can't be empty".to_string());
+    }
+
+    // Two random UUIDs give 244 random bits
+    let key = format!("{}{}{}", API_KEY_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+    let conn = storage::create_connection()?;
+    match storage::add_api_key(&conn, name, &hash_key(&key), now()) {
+        Ok(_) => Ok(key),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::ConstraintViolation => {
+            Err(format!("An API key named '{}' already exists", name))
+        },
+        Err(e) => Err(format!("Error storing API key: {}", e)),
+    }
+}
+
+/// Revokes the API key with the given name, so it can no longer be used. Returns false if there is no key with that
+/// name, or it was already revoked
+pub fn revoke_key(name: &str) -> Result<bool, String> {
+    let conn = storage::create_connection()?;
+    storage::revoke_api_key(&conn, name, now()).map_err(|e| format!("Error revoking API key: {}", e))
+}
+
+/// Gets all the API keys, including revoked ones
+pub fn list_keys() -> Result<Vec<ApiKey>, String> {
+    let conn = storage::create_connection()?;
+    storage::get_api_keys(&conn).map_err(|e| format!("Error getting API keys: {}", e))
+}
+
+/// Gets if any provider has anything to accept, such as API keys that have been created, so the management API
+/// accepts tokens
+pub fn is_required() -> Result<bool, String> {
+    for provider in get_providers()? {
+        if provider.is_enabled()? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Checks each provider that is set up, returning the name of each with what it accepts, or why it can't be used,
+/// such as an identity provider that can't be reached
+pub fn check_providers() -> Result<Vec<ProviderCheck>, String> {
+    let mut checks = Vec::new();
+    for provider in get_providers()? {
+        if provider.is_enabled()? {
+            checks.push((provider.name(), provider.check()));
+        }
+    }
+
+    Ok(checks)
+}
+
+/// Checks the providers set up in the environment, leaving out the API keys, so the config can be checked without
+/// opening the database
+pub fn check_configured_providers() -> Result<Vec<ProviderCheck>, String> {
+    Ok(get_configured_providers()?.iter().map(|provider| (provider.name(), provider.check())).collect())
+}
+
+/// Gets if the management API is left open while no provider has anything to accept
+pub fn is_open_allowed() -> bool {
+    match std::env::var(OPEN_API_ENV) {
+        Ok(value) if value == "1" || value.eq_ignore_ascii_case("true") => true,
+        Ok(value) if value == "0" || value.eq_ignore_ascii_case("false") || value.is_empty() => false,
+        Ok(value) => {
+            println!("Ignoring invalid {}: {}", OPEN_API_ENV, value);
+            false
+        },
+        Err(_) => false,
+    }
+}
+
+/// Gets the pattern of the route a request is for. This matches the decoded path the router uses, not the raw path,
+/// so percent-encoded characters can't make a request look like it is for a different route
+fn get_route_pattern(req: &HttpRequest) -> Option<String> {
+    req.resource_map().match_pattern(req.match_info().as_str())
+}
+
+/// Gets if a request with the given method, for the route with the given pattern, is for the management API, so needs
+/// an API key. Requests that don't match a route go to the default app, so they stay open
+fn is_protected(method: &Method, pattern: Option<&str>) -> bool {
+    match pattern {
+        Some(pattern) => !PUBLIC_ROUTES.iter().any(|(public_method, public_pattern)| method == public_method && pattern == *public_pattern),
+        None => false,
+    }
+}
+
+/// Checks a bearer token with each provider, returning who it belongs to, or the error and message if no provider
+/// accepts it. While no provider has anything to accept, requests are rejected unless the API has been left open with
+/// RUSTLESS_OPEN_API. This can call the database and identity provider, so it runs on the blocking thread pool
+fn check_token(token: Option<String>) -> Result<Option<String>, (ApiError, String)> {
+    let providers = match get_providers() {
+        Ok(providers) => providers,
+        Err(e) => return Err((ApiError::Internal, e)),
+    };
+
+    let mut enabled = false;
+    for provider in &providers {
+        match provider.is_enabled() {
+            Ok(true) => enabled = true,
+            Ok(false) => {},
+            Err(e) => return Err((ApiError::DatabaseError, e)),
+        }
+    }
+
+    if !enabled {
+        return match is_open_allowed() {
+            true => Ok(None),
+            false => Err((ApiError::NoApiKey, format!("No API keys have been created, so the management API is closed. Create one with rustless-hostctl keys create, or set {} to leave it open", OPEN_API_ENV))),
+        };
+    }
+
+    let token = match token {
+        Some(token) => token,
+        None => return Err((ApiError::NoApiKey, "An API key or token is required. Log in with rustless login".to_string())),
+    };
+
+    // A provider that can't check the token only fails the request if no other provider accepts it
+    let mut reason = None;
+    let mut error = None;
+    for provider in &providers {
+        match provider.authenticate(&token) {
+            Ok(AuthResult::Accepted(name)) => return Ok(Some(format!("{} ({})", name, provider.name()))),
+            Ok(AuthResult::Rejected(rejected)) => reason = reason.or(rejected),
+            Err(e) => error = error.or(Some(e)),
+        }
+    }
+
+    match (reason, error) {
+        (Some(reason), _) => Err((ApiError::InvalidApiKey, reason)),
+        (None, Some(error)) => Err(error),
+        (None, None) => Err((ApiError::InvalidApiKey, "The API key or token is not valid".to_string())),
+    }
+}
+
+/// Middleware that rejects requests to the management API without an API key or token that one of the providers
+/// accepts, sent as a bearer token in the Authorization header, or in a cookie for the event stream. Requests that
+/// change something are logged with who made them
+pub async fn require_api_key(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let pattern = get_route_pattern(req.request());
+    if is_protected(req.method(), pattern.as_deref()) {
+        let token = req.headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+
+        // The cookie is only read for the event stream, so it can't be used to make changes from another site
+        let token = match token {
+            None if req.method() == Method::GET && pattern.as_deref() == Some(EVENTS_PATTERN) => {
+                req.cookie(EVENTS_COOKIE).map(|cookie| cookie.value().trim().to_string())
+            },
+            token => token,
+        };
+
+        let checked = match web::block(move || check_token(token)).await {
+            Ok(checked) => checked,
+            Err(e) => Err((ApiError::Internal, e.to_string())),
+        };
+
+        match checked {
+            Ok(Some(name)) if req.method() != Method::GET => println!("{} {} by {}", req.method(), req.path(), name),
+            Ok(_) => {},
+            Err((error, message)) => return Ok(req.into_response(errors::response(error, &message))),
+        }
+    }
+
+    next.call(req).await.map(|res| res.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test::TestRequest, App, HttpResponse};
+
+    /// Responds with the pattern of the route the request is for, or none if it doesn't match one
+    async fn pattern(req: HttpRequest) -> HttpResponse {
+        HttpResponse::Ok().body(get_route_pattern(&req).unwrap_or_else(|| "none".to_string()))
+    }
+
+    /// Gets the pattern of the route the given path is matched to, in an app with some of the host's routes
+    async fn get_pattern(path: &str) -> String {
+        let app = actix_web::test::init_service(
+            App::new().route("/function-apps", web::get().to(pattern))
+                      .route("/function-apps/{id}", web::get().to(pattern))
+                      .route("/api/{name}/{route:.*}", web::get().to(pattern))
+                      .default_service(web::to(pattern))
+        ).await;
+
+        let body = actix_web::test::call_and_read_body(&app, TestRequest::get().uri(path).to_request()).await;
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn matches_the_decoded_path() {
+        assert_eq!(get_pattern("/function-apps").await, "/function-apps");
+        assert_eq!(get_pattern("/%66unction-apps").await, "/function-apps");
+        assert_eq!(get_pattern("/function%2Dapps").await, "/function-apps");
+        assert_eq!(get_pattern("/function-apps/%61pp").await, "/function-apps/{id}");
+    }
+
+    #[actix_web::test]
+    async fn does_not_match_unknown_paths() {
+        assert_eq!(get_pattern("/function-apps-old").await, "none");
+        assert_eq!(get_pattern("/function-apps%2Fapp").await, "none");
+    }
+
+    #[test]
+    fn protects_the_management_api() {
+        assert!(is_protected(&Method::GET, Some("/function-apps")));
+        assert!(is_protected(&Method::POST, Some("/function-apps/{id}/code")));
+        assert!(is_protected(&Method::GET, Some("/version")));
+        assert!(is_protected(&Method::GET, Some("/signing-key")));
+        assert!(is_protected(&Method::GET, Some("/crates-cache")));
+        assert!(is_protected(&Method::GET, Some(EVENTS_PATTERN)));
+    }
+
+    #[test]
+    fn leaves_public_routes_open() {
+        assert!(!is_protected(&Method::GET, Some("/")));
+        assert!(!is_protected(&Method::GET, Some("/healthz")));
+        assert!(!is_protected(&Method::GET, Some("/ui/{path:.*}")));
+        assert!(!is_protected(&Method::POST, Some("/api/{name}/{route:.*}")));
+        assert!(!is_protected(&Method::POST, Some("/function-apps/by-name/{name}/deployments/{number}/approve")));
+    }
+
+    #[test]
+    fn protects_public_routes_with_other_methods() {
+        assert!(is_protected(&Method::DELETE, Some("/api/{name}/{route:.*}")));
+        assert!(is_protected(&Method::GET, Some("/function-apps/{id}/deployments/{number}/approve")));
+    }
+
+    #[test]
+    fn leaves_the_default_app_open() {
+        assert!(!is_protected(&Method::GET, None));
+        assert!(!is_protected(&Method::POST, None));
+    }
+}
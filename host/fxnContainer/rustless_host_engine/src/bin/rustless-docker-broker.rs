@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use colored::Colorize;
+
+// The docker broker is the only part of rustless that needs access to the docker socket. It runs as a user in the
+// docker group, and the host runs as another user with RUSTLESS_DOCKER_BROKER set to the broker socket, so a
+// compromised host can only do the fixed things the broker allows, such as building and running app images.
+// The broker reads the code for builds from the host's build directory, so it must be able to read it, and it refuses
+// to build, save, or load anything outside it
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The unix socket to listen on. Hosts connect to it if they are started with RUSTLESS_DOCKER_BROKER set to it
+    #[arg(long, default_value = "rustless-docker-broker.sock")]
+    socket: PathBuf,
+
+    /// The directory the host runs builds in, instead of the one in RUSTLESS_BUILD_DIR or the system temporary
+    /// directory. It must be the same directory the host uses
+    #[arg(long)]
+    build_dir: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if let Err(e) = rustless_host::run_docker_broker(&args.socket, args.build_dir) {
+        println!("{}", e.red().bold());
+        std::process::exit(-1);
+    }
+}
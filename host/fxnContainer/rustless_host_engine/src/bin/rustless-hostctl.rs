@@ -0,0 +1,528 @@
+use std::fs::{self, OpenOptions};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand};
+use colored::Colorize;
+use reqwest::blocking::Client;
+use sha2::{Digest, Sha256};
+
+use rustless_host::HostConfig;
+
+// Upgrading a running host
+//
+// The upgrade command replaces the running host with a new version without refusing any requests:
+// 1. Download the new host binary next to the current one and verify its checksum
+// 2. Back up the databases, then run the new binary with --migrate to upgrade them
+// 3. Start the new binary. The host binds its port with SO_REUSEPORT, so both the old and new hosts accept connections
+// 4. Health check the new host by calling /hello until it answers with its own process ID
+// 5. Send SIGTERM to the old host, which stops accepting connections and finishes any in-flight requests
+// 6. Replace the current binary with the new one so future restarts use the new version
+//
+// If anything fails before the old host has stopped, the new host is killed and the old host carries on serving
+// requests. The databases are only restored from the backup if no old host is running, as it keeps writing to them
+// and restoring would lose those writes. Otherwise the backups are kept, and the old host carries on with the
+// migrated databases.
+
+/// The file the running host writes its process ID to
+const PID_FILE: &str = "rustless_host.pid";
+
+/// The log file the new host writes to
+const LOG_FILE: &str = "rustless_host.log";
+
+/// How long to wait for the old host to finish its in-flight requests and exit
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Upgrades the running host to a new version without downtime
+    Upgrade {
+        /// The URL to download the new host binary from
+        url: String,
+
+        /// The expected SHA-256 checksum of the new host binary
+        #[arg(long)]
+        sha256: Option<String>,
+
+        /// The path to the current host binary
+        #[arg(long, default_value = "./rustless_host_engine")]
+        binary: String,
+
+        /// The port the host listens on, instead of the one in the host config
+        #[arg(long)]
+        port: Option<u16>,
+
+        /// How long to wait in seconds for the new host to become healthy
+        #[arg(long, default_value_t = 30)]
+        health_timeout: u64,
+    },
+
+    /// Creates, revokes, and lists the API keys for the management API. Run from the folder the host runs in, so the same config file and database are used
+    #[command(subcommand)]
+    Keys(KeysCommands),
+
+    /// Registers the apps that have an image built by the host but aren't in the database, such as after restoring
+    /// it from a backup, so they can be started without deploying them again. Run from the folder the host runs in, so the same config file and database are used
+    Adopt,
+}
+
+#[derive(Subcommand)]
+enum KeysCommands {
+    /// Creates an API key. Once a key has been created, requests to the management API need one
+    Create {
+        /// The name for the key, such as who or what it is for
+        name: String,
+    },
+
+    /// Revokes an API key, so it can no longer be used
+    Revoke {
+        /// The name the key was created with
+        name: String,
+    },
+
+    /// Lists the API keys, including revoked ones
+    List,
+}
+
+/// Downloads the new host binary and checks it against the expected checksum
+fn download_binary(url: &str, sha256: &Option<String>, new_binary: &Path) -> Result<(), String> {
+    let res = match reqwest::blocking::get(url) {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error downloading {}: {}", url, e)),
+    };
+
+    if !res.status().is_success() {
+        return Err(format!("Error downloading {}: status code {}", url, res.status()));
+    }
+
+    let binary = match res.bytes() {
+        Ok(binary) => binary,
+        Err(e) => return Err(format!("Error downloading {}: {}", url, e)),
+    };
+
+    // Check the checksum if we have one
+    if let Some(expected) = sha256 {
+        let actual = hex::encode(Sha256::digest(&binary));
+        if actual != expected.to_lowercase() {
+            return Err(format!("Checksum mismatch - expected {}, got {}", expected, actual));
+        }
+    }
+
+    if let Err(e) = fs::write(new_binary, &binary) {
+        return Err(format!("Error writing {}: {}", new_binary.display(), e));
+    }
+
+    // Make sure the new binary can be run
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(e) = fs::set_permissions(new_binary, fs::Permissions::from_mode(0o755)) {
+            return Err(format!("Error setting permissions on {}: {}", new_binary.display(), e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets all the database files used by the host
+fn get_database_files() -> Vec<PathBuf> {
+    let mut files = vec![rustless_host::database_file()];
+
+    if let Ok(entries) = fs::read_dir(rustless_host::namespace_database_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map(|e| e == "db").unwrap_or(false) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.into_iter().filter(|file| file.exists()).collect()
+}
+
+/// Backs up the database files before they are migrated, returning the original and backup paths. The old host is
+/// still writing to them, so they are backed up with VACUUM INTO rather than copied
+fn backup_databases() -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut backups = Vec::new();
+
+    for file in get_database_files() {
+        let backup = file.with_extension("db.bak");
+        rustless_host::backup_database(&file, &backup)?;
+        backups.push((file, backup));
+    }
+
+    Ok(backups)
+}
+
+/// Restores the database files from their backups. This must only be done when no host is running, as anything a
+/// host wrote since the backup would be lost
+fn restore_databases(backups: &[(PathBuf, PathBuf)]) {
+    for (file, backup) in backups {
+        if let Err(e) = rustless_host::restore_database(file, backup) {
+            println!("{}", e.red().bold());
+        }
+    }
+}
+
+/// Removes the database backups once the upgrade has succeeded
+fn remove_database_backups(backups: &[(PathBuf, PathBuf)]) {
+    for (_, backup) in backups {
+        let _ = fs::remove_file(backup);
+    }
+}
+
+/// Runs the database migrations using the new host binary
+fn migrate_databases(new_binary: &Path) -> Result<(), String> {
+    let output = Command::new(new_binary).arg("--migrate").output();
+
+    match output {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!(
+            "Error migrating databases: {}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )),
+        Err(e) => Err(format!("Error running {}: {}", new_binary.display(), e)),
+    }
+}
+
+/// Gets the process ID of the running host, if there is one
+fn get_running_host_pid() -> Option<u32> {
+    let pid = fs::read_to_string(PID_FILE).ok()?;
+    let pid = pid.trim().parse().ok()?;
+
+    // Check the process is still alive
+    if is_process_running(pid) {
+        Some(pid)
+    } else {
+        None
+    }
+}
+
+/// Checks if a process is still running
+fn is_process_running(pid: u32) -> bool {
+    match Command::new("kill").arg("-0").arg(pid.to_string()).output() {
+        Ok(output) => output.status.success(),
+        Err(_) => false,
+    }
+}
+
+/// Starts the new host in the background, logging to the host log file
+fn start_new_host(new_binary: &Path) -> Result<Child, String> {
+    let log_file = match OpenOptions::new().create(true).append(true).open(LOG_FILE) {
+        Ok(log_file) => log_file,
+        Err(e) => return Err(format!("Error opening {}: {}", LOG_FILE, e)),
+    };
+
+    let error_log_file = match log_file.try_clone() {
+        Ok(error_log_file) => error_log_file,
+        Err(e) => return Err(format!("Error opening {}: {}", LOG_FILE, e)),
+    };
+
+    let mut command = Command::new(new_binary);
+    command
+        .stdin(Stdio::null())
+        .stdout(log_file)
+        .stderr(error_log_file);
+
+    // Run the host in its own process group so it isn't stopped when this tool's terminal closes
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    match command.spawn() {
+        Ok(child) => Ok(child),
+        Err(e) => Err(format!("Error starting {}: {}", new_binary.display(), e)),
+    }
+}
+
+/// Waits for the new host to answer health checks
+///
+/// As both hosts are listening on the same port, the health check keeps making new connections until
+/// one is answered by the new host, identified by the process ID it returns
+fn wait_for_new_host(child: &mut Child, port: u16, health_timeout: Duration) -> Result<(), String> {
+    // The host uses a self-signed certificate locally, and connections can't be reused
+    // otherwise we would keep talking to the same host
+    let client = Client::builder()
+        .danger_accept_invalid_certs(true)
+        .pool_max_idle_per_host(0)
+        .timeout(Duration::from_secs(2))
+        .build();
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => return Err(format!("Error creating HTTPS client: {}", e)),
+    };
+
+    let url = format!("https://127.0.0.1:{}/hello", port);
+    let expected_pid = child.id().to_string();
+    let start = Instant::now();
+
+    while start.elapsed() < health_timeout {
+        // If the new host has exited, it will never become healthy
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("The new host exited with {}. Check {} for details", status, LOG_FILE));
+        }
+
+        if let Ok(res) = client.get(&url).send() {
+            let pid = res
+                .headers()
+                .get("X-Rustless-Pid")
+                .and_then(|pid| pid.to_str().ok())
+                .map(|pid| pid.to_string());
+
+            if res.status().is_success() && pid.as_deref() == Some(expected_pid.as_str()) {
+                return Ok(());
+            }
+        }
+
+        sleep(Duration::from_millis(250));
+    }
+
+    Err(format!("The new host did not become healthy within {} seconds", health_timeout.as_secs()))
+}
+
+/// Asks the old host to shut down gracefully, and waits for it to finish its in-flight requests
+fn stop_old_host(pid: u32) -> Result<(), String> {
+    if let Err(e) = Command::new("kill").arg("-TERM").arg(pid.to_string()).output() {
+        return Err(format!("Error stopping the old host: {}", e));
+    }
+
+    let start = Instant::now();
+    while is_process_running(pid) {
+        if start.elapsed() > SHUTDOWN_TIMEOUT {
+            return Err(format!("The old host (PID {}) did not stop within {} seconds", pid, SHUTDOWN_TIMEOUT.as_secs()));
+        }
+
+        sleep(Duration::from_millis(250));
+    }
+
+    Ok(())
+}
+
+/// Puts everything back how it was before the upgrade started
+fn roll_back(child: Option<&mut Child>, backups: &[(PathBuf, PathBuf)], old_pid: Option<u32>, new_binary: &Path) {
+    println!("{}", "Rolling back the upgrade...".yellow().bold());
+
+    // Stop the new host
+    if let Some(child) = child {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    let _ = fs::remove_file(new_binary);
+
+    // The old host is still writing to the databases, so they can't be put back without losing what it wrote. It
+    // carries on with the migrated databases, and the backups are kept in case they are needed
+    match old_pid.filter(|pid| is_process_running(*pid)) {
+        Some(old_pid) => {
+            let _ = fs::write(PID_FILE, old_pid.to_string());
+            let files: Vec<String> = backups.iter().map(|(_, backup)| backup.display().to_string()).collect();
+            println!("{}", format!("Rolled back. The old host is still running, so the databases weren't restored. The backups are in {}", files.join(", ")).yellow());
+        },
+        None => {
+            restore_databases(backups);
+            remove_database_backups(backups);
+            println!("{}", "Rolled back. The databases were restored from the backups".yellow());
+        },
+    }
+}
+
+/// Upgrades the running host to a new version
+fn upgrade(url: &str, sha256: &Option<String>, binary: &str, port: u16, health_timeout: Duration) -> Result<(), String> {
+    let binary = PathBuf::from(binary);
+    let new_binary = binary.with_extension("new");
+
+    // Find the running host
+    let old_pid = get_running_host_pid();
+    match old_pid {
+        Some(pid) => println!("{}", format!("Found running host with PID {}", pid).blue()),
+        None => println!("{}", "No running host found, the new host will be started".blue()),
+    }
+
+    // Download the new version
+    println!("{}", format!("Downloading {}...", url).blue());
+    download_binary(url, sha256, &new_binary)?;
+    println!("{}", "✅ New host downloaded".green());
+
+    // Back up and migrate the databases
+    let backups = backup_databases()?;
+    if let Err(e) = migrate_databases(&new_binary) {
+        roll_back(None, &backups, old_pid, &new_binary);
+        return Err(e);
+    }
+    println!("{}", "✅ Databases migrated".green());
+
+    // Start the new host alongside the old one
+    let mut child = match start_new_host(&new_binary) {
+        Ok(child) => child,
+        Err(e) => {
+            roll_back(None, &backups, old_pid, &new_binary);
+            return Err(e);
+        }
+    };
+    println!("{}", format!("Started new host with PID {}", child.id()).blue());
+
+    if let Err(e) = wait_for_new_host(&mut child, port, health_timeout) {
+        roll_back(Some(&mut child), &backups, old_pid, &new_binary);
+        return Err(e);
+    }
+    println!("{}", "✅ New host is healthy".green());
+
+    // Hand over to the new host
+    if let Some(old_pid) = old_pid {
+        if let Err(e) = stop_old_host(old_pid) {
+            roll_back(Some(&mut child), &backups, Some(old_pid), &new_binary);
+            println!("{}", "The old host was asked to stop, so it exits once its in-flight requests finish. Start it again if it does".yellow());
+            return Err(e);
+        }
+        println!("{}", "✅ Old host stopped".green());
+    }
+
+    // Replace the binary so the new version is used from now on
+    if let Err(e) = fs::rename(&new_binary, &binary) {
+        return Err(format!("The new host is running, but replacing {} failed: {}", binary.display(), e));
+    }
+
+    remove_database_backups(&backups);
+
+    Ok(())
+}
+
+/// Formats a Unix timestamp as a UTC date and time
+fn format_timestamp(timestamp: u64) -> String {
+    match chrono::DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(date) => date.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// Runs an API key command against the host database in the current folder
+fn run_keys_command(command: &KeysCommands) -> Result<(), String> {
+    match command {
+        KeysCommands::Create { name } => {
+            let key = rustless_host::create_api_key(name)?;
+            println!("{}", format!("✅ API key '{}' created", name).green().bold());
+            println!("{}", key.bold());
+            println!("{}", "Store the key now, it can't be shown again. Log in with it using rustless login".yellow());
+        }
+        KeysCommands::Revoke { name } => {
+            if !rustless_host::revoke_api_key(name)? {
+                return Err(format!("No API key named '{}' exists, or it has already been revoked", name));
+            }
+
+            println!("{}", format!("✅ API key '{}' revoked", name).green().bold());
+        }
+        KeysCommands::List => {
+            let keys = rustless_host::list_api_keys()?;
+            if keys.is_empty() {
+                println!("{}", "No API keys have been created, so the management API is open".yellow());
+            }
+
+            for key in keys {
+                match key.revoked_at {
+                    Some(revoked_at) => println!("{}  created {}  {}", key.name, format_timestamp(key.created_at), format!("revoked {}", format_timestamp(revoked_at)).red()),
+                    None => println!("{}  created {}", key.name.bold(), format_timestamp(key.created_at)),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Adopts the apps with images that aren't in the host database in the current folder, and prints what was done
+fn adopt() -> Result<(), String> {
+    let report = rustless_host::adopt_orphaned_apps()?;
+    if report.adopted.is_empty() && report.skipped.is_empty() {
+        println!("{}", "Every app with an image is already registered".yellow());
+        return Ok(());
+    }
+
+    for app in report.adopted.iter() {
+        let deployment = match app.deployment {
+            Some(deployment) => format!("deployment #{}", deployment),
+            None => "no deployment".to_string(),
+        };
+        println!("{}", format!("✅ Adopted {} in namespace {} ({})", app.name, app.namespace, deployment).green());
+    }
+
+    for image in report.skipped.iter() {
+        println!("{}", format!("Skipped {}: {}", image.name, image.reason).yellow());
+    }
+
+    if !report.adopted.is_empty() {
+        println!("Start the adopted apps with rustless start, or wait for the host to find any containers still running");
+    }
+
+    Ok(())
+}
+
+/// Gets the port the host listens on from the host config
+fn get_config_port(config: &HostConfig) -> Result<u16, String> {
+    match config.address.parse::<SocketAddr>() {
+        Ok(address) => Ok(address.port()),
+        Err(e) => Err(format!("Invalid address {}: {}", config.address, e)),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    // The host config says where the databases are and which port the host listens on, the same as for the host
+    let config = match rustless_host::load_config(None) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e.red().bold());
+            std::process::exit(-1);
+        }
+    };
+
+    match &cli.command {
+        Commands::Upgrade { url, sha256, binary, port, health_timeout } => {
+            let port = match port {
+                Some(port) => Ok(*port),
+                None => get_config_port(&config),
+            };
+
+            let port = match port {
+                Ok(port) => port,
+                Err(e) => {
+                    println!("{}", e.red().bold());
+                    std::process::exit(-1);
+                }
+            };
+
+            match upgrade(url, sha256, binary, port, Duration::from_secs(*health_timeout)) {
+                Ok(_) => println!("{}", "✅ Host upgraded!".green().bold()),
+                Err(e) => {
+                    println!("{}", e.red().bold());
+                    std::process::exit(-1);
+                }
+            }
+        }
+        Commands::Keys(command) => {
+            if let Err(e) = run_keys_command(command) {
+                println!("{}", e.red().bold());
+                std::process::exit(-1);
+            }
+        }
+        Commands::Adopt => {
+            if let Err(e) = adopt() {
+                println!("{}", e.red().bold());
+                std::process::exit(-1);
+            }
+        }
+    }
+}
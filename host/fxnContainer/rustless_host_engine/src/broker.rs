@@ -0,0 +1,768 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, PipeReader, PipeWriter, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::build_dirs;
+use crate::docker::{self, AppLabels};
+use crate::health;
+use crate::registry;
+
+/// The environment variable containing the socket of the docker broker. When it is set, the host asks the broker to
+/// do everything it does with docker, so the host itself never needs access to the docker socket
+pub const BROKER_SOCKET_ENV: &str = "RUSTLESS_DOCKER_BROKER";
+
+/// The permissions on the broker socket. Only the broker's user and group can connect, so the host runs as a user in
+/// the broker's group instead of in the docker group
+const BROKER_SOCKET_MODE: u32 = 0o660;
+
+/// How often the broker checks if a docker process has exited, or the host has hung up on it
+const PROCESS_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The socket of the docker broker, or None if the host uses docker itself. Read from the environment once
+static BROKER_SOCKET: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// The calls the host can make to the docker broker. Each call does one fixed thing with the images and containers
+/// for apps, so a host that has been compromised can't use docker to do anything else, such as running a privileged
+/// container or mounting the host's files
+#[derive(Serialize, Deserialize)]
+pub enum BrokerRequest {
+    /// Builds the image for an app from the Dockerfile and code in a build directory, streaming the build output
+    Build { context_dir: PathBuf, app: AppLabels, strict: bool, crates_cache: Option<String> },
+
+    /// Runs a container for an app from its image, published on the given port with the options the host always uses
+    Run { app: AppLabels, proxy_url: Option<String>, port: u16 },
+
+    /// Stops containers started for apps and removes them
+    Stop { container_ids: Vec<String>, grace_period: u64 },
+
+    /// Removes the containers for an app that exited by themselves
+    RemoveExitedContainers { app: String },
+
+    /// Removes the image for an app, along with any containers left from it
+    RemoveImage { app: String },
+
+    /// Tags the image for an app to be pushed to the registry
+    TagImage { app: String, image_ref: String },
+
+    /// Pushes an image tagged for the registry, streaming the push output
+    PushImage { image_ref: String },
+
+    /// Gets the details of every app with a built image
+    GetAppImages,
+
+    /// Gets the IDs of the running containers for an app
+    GetContainerIds { app: String },
+
+    /// Gets the names of the apps with a running container
+    GetRunningApps,
+
+    /// Gets every running container started for an app
+    GetAppContainers,
+
+    /// Gets the ID of the built image for an app
+    GetImageId { app: String },
+
+    /// Gets the digest of a local image
+    GetImageDigest { image: String },
+
+    /// Gets the size of the built image for an app
+    GetImageSize { app: String },
+
+    /// Gets the memory used by the running containers for an app
+    GetMemoryUsage { app: String },
+
+    /// Gets the resources used by the running containers for every app, or only the given app
+    GetResourceUsage { app: Option<String> },
+
+    /// Gets the most recent lines of output from the container for an app
+    GetLogs { app: String, tail: usize },
+
+    /// Follows the output from the container for an app, streaming it as server sent events
+    FollowLogs { app: String, tail: usize },
+
+    /// Reads a file from the built image for an app
+    ReadFileFromImage { app: String, path: String },
+
+    /// Saves the built image for an app to a new file in the host's build directory, to promote it to another host
+    SaveImage { app: String, file: PathBuf },
+
+    /// Loads an image promoted from another host from a file in the host's build directory, and tags it for an app
+    LoadImage { app: String, file: PathBuf, image_id: String },
+
+    /// Checks docker can log in to the registry images are pushed to with its stored credentials
+    CheckRegistry { registry: String },
+
+    /// Checks the docker daemon is reachable
+    CheckDocker,
+}
+
+/// A message sent back to the host by the broker. Every call ends with a result, which can follow lines of output
+#[derive(Serialize, Deserialize)]
+enum BrokerReply {
+    /// A line written to stdout by a docker process, or an event from followed logs
+    Stdout(String),
+
+    /// A line written to stderr by a docker process
+    Stderr(String),
+
+    /// The result of the call. For builds and pushes this is the raw exit status of the docker process
+    Done(Result<Value, String>),
+}
+
+/// Gets the socket of the docker broker, or None if the host uses docker itself
+pub fn get_socket() -> Option<&'static PathBuf> {
+    BROKER_SOCKET.get_or_init(|| {
+        std::env::var(BROKER_SOCKET_ENV).ok().filter(|socket| !socket.trim().is_empty()).map(PathBuf::from)
+    }).as_ref()
+}
+
+/// Checks if docker calls are made through the broker
+pub fn is_enabled() -> bool {
+    get_socket().is_some()
+}
+
+/// Connects to the broker and sends it a call
+fn send(request: &BrokerRequest) -> Result<UnixStream, String> {
+    let socket = match get_socket() {
+        Some(socket) => socket,
+        None => return Err("No docker broker is set".to_string()),
+    };
+
+    let mut stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(e) => return Err(format!("Error connecting to the docker broker at {}: {}", socket.display(), e)),
+    };
+
+    let mut line = match serde_json::to_string(request) {
+        Ok(line) => line,
+        Err(e) => return Err(format!("Error calling the docker broker: {}", e)),
+    };
+    line.push('\n');
+
+    match stream.write_all(line.as_bytes()) {
+        Ok(_) => Ok(stream),
+        Err(e) => Err(format!("Error calling the docker broker: {}", e)),
+    }
+}
+
+/// Reads the next message sent back by the broker
+fn read_reply(reader: &mut impl BufRead) -> Result<BrokerReply, String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) => Err("The docker broker closed the connection".to_string()),
+        Ok(_) => serde_json::from_str(&line).map_err(|e| format!("Error reading from the docker broker: {}", e)),
+        Err(e) => Err(format!("Error reading from the docker broker: {}", e)),
+    }
+}
+
+/// Makes a call to the broker and waits for the result
+pub fn call<T: DeserializeOwned>(request: BrokerRequest) -> Result<T, String> {
+    let mut reader = BufReader::new(send(&request)?);
+
+    loop {
+        match read_reply(&mut reader)? {
+            BrokerReply::Done(Ok(value)) => return serde_json::from_value(value).map_err(|e| format!("Error reading from the docker broker: {}", e)),
+            BrokerReply::Done(Err(e)) => return Err(e),
+            BrokerReply::Stdout(_) | BrokerReply::Stderr(_) => {},
+        }
+    }
+}
+
+/// Follows the logs for an app through the broker, returning the events in the same way as docker::follow_logs. The
+/// broker stops following once the receiver is dropped
+pub fn follow_logs(app: &String, tail: usize) -> Result<UnboundedReceiver<String>, String> {
+    let mut reader = BufReader::new(send(&BrokerRequest::FollowLogs { app: app.to_string(), tail })?);
+
+    // The first event is sent as soon as the broker starts following, so errors finding the container are returned
+    let first = match read_reply(&mut reader)? {
+        BrokerReply::Stdout(event) => event,
+        BrokerReply::Done(Err(e)) => return Err(e),
+        BrokerReply::Done(Ok(_)) | BrokerReply::Stderr(_) => return Err("The docker broker stopped following logs".to_string()),
+    };
+
+    let (sender, receiver) = mpsc::unbounded();
+    let _ = sender.unbounded_send(first);
+
+    // Dropping the connection when the client has gone hangs up on the broker
+    thread::spawn(move || {
+        while let Ok(BrokerReply::Stdout(event)) = read_reply(&mut reader) {
+            if sender.unbounded_send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// A docker process run by the broker, such as a build or a push. The output is read from pipes in the same way as
+/// the output of a child process, and hanging up on the broker kills the process
+pub struct BrokerProcess {
+    // What the process writes to stdout
+    pub stdout: Option<PipeReader>,
+
+    // What the process writes to stderr
+    pub stderr: Option<PipeReader>,
+
+    // The connection to the broker
+    stream: UnixStream,
+
+    // The exit status of the process once the broker has sent it, or the error if the call failed
+    status: Arc<Mutex<Option<Result<ExitStatus, String>>>>,
+}
+
+impl BrokerProcess {
+    /// Checks if the process has exited, without waiting for it
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        let status = match self.status.lock() {
+            Ok(status) => status,
+            Err(_) => return Err(io::Error::other("Error reading the status from the docker broker")),
+        };
+
+        match status.as_ref() {
+            Some(Ok(status)) => Ok(Some(*status)),
+            Some(Err(e)) => Err(io::Error::other(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Kills the process by hanging up on the broker
+    pub fn kill(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Writes a line of output to a pipe, ignoring errors as the output is no longer wanted if the pipe has been closed
+fn write_output(pipe: &mut PipeWriter, line: &str) {
+    let _ = pipe.write_all(line.as_bytes());
+}
+
+/// Asks the broker to run a docker process that streams its output, such as a build, returning once it has started
+pub fn spawn(request: BrokerRequest) -> Result<BrokerProcess, String> {
+    let stream = send(&request)?;
+
+    let pipes = io::pipe().and_then(|stdout| Ok((stdout, io::pipe()?)));
+    let ((stdout, mut stdout_writer), (stderr, mut stderr_writer)) = match pipes {
+        Ok(pipes) => pipes,
+        Err(e) => return Err(format!("Error creating pipes for the docker broker: {}", e)),
+    };
+
+    let mut reader = match stream.try_clone() {
+        Ok(stream) => BufReader::new(stream),
+        Err(e) => return Err(format!("Error reading from the docker broker: {}", e)),
+    };
+
+    let status = Arc::new(Mutex::new(None));
+    let process_status = status.clone();
+
+    thread::spawn(move || {
+        let result = loop {
+            match read_reply(&mut reader) {
+                Ok(BrokerReply::Stdout(line)) => write_output(&mut stdout_writer, &line),
+                Ok(BrokerReply::Stderr(line)) => write_output(&mut stderr_writer, &line),
+                Ok(BrokerReply::Done(Ok(value))) => match serde_json::from_value(value) {
+                    Ok(status) => break Ok(ExitStatus::from_raw(status)),
+                    Err(e) => break Err(format!("Error reading from the docker broker: {}", e)),
+                },
+                Ok(BrokerReply::Done(Err(e))) => break Err(e),
+                Err(e) => break Err(e),
+            }
+        };
+
+        // Close the pipes first, so the output has been read to the end by the time the process has exited
+        drop(stdout_writer);
+        drop(stderr_writer);
+
+        if let Ok(mut status) = process_status.lock() {
+            *status = Some(result);
+        }
+    });
+
+    Ok(BrokerProcess { stdout: Some(stdout), stderr: Some(stderr), stream, status })
+}
+
+/// Checks an argument passed to docker isn't empty, and can't be read as an option
+fn check_argument(argument: &str) -> Result<(), String> {
+    match argument.is_empty() || argument.starts_with('-') {
+        true => Err(format!("Invalid argument: {}", argument)),
+        false => Ok(()),
+    }
+}
+
+/// Checks the containers are ones started for apps, so the host can't stop anything else
+fn check_managed_containers(container_ids: &[String]) -> Result<(), String> {
+    let managed = docker::get_managed_container_ids()?;
+
+    // Containers listed with docker ps have short IDs, which are the start of the full IDs
+    for container_id in container_ids {
+        check_argument(container_id)?;
+        if !managed.iter().any(|managed_id| managed_id.starts_with(container_id.as_str())) {
+            return Err(format!("Container {} was not started for an app", container_id));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks an image reference is in the registry the broker pushes to, so the host can't push images anywhere else
+fn check_image_ref(image_ref: &str) -> Result<(), String> {
+    let registry = match registry::get_registry() {
+        Some(registry) => registry,
+        None => return Err("No registry is set for the docker broker".to_string()),
+    };
+
+    check_argument(image_ref)?;
+    match image_ref.starts_with(&format!("{}/", registry)) {
+        true => Ok(()),
+        false => Err(format!("{} is not in the registry {}", image_ref, registry)),
+    }
+}
+
+/// Resolves a path in the given build directory, following any symlinks, so the host can't have the broker build,
+/// save, or load anything outside it. Paths that use .. are refused before they are resolved
+fn resolve_build_path(path: &Path, build_dir: &Path) -> Result<PathBuf, String> {
+    if !path.is_absolute() || path.components().any(|component| component == Component::ParentDir) {
+        return Err(format!("{} must be an absolute path without ..", path.display()));
+    }
+
+    let build_dir = match fs::canonicalize(build_dir) {
+        Ok(build_dir) => build_dir,
+        Err(e) => return Err(format!("Error reading the build directory: {}", e)),
+    };
+
+    let resolved = match fs::canonicalize(path) {
+        Ok(resolved) => resolved,
+        Err(e) => return Err(format!("Error reading {}: {}", path.display(), e)),
+    };
+
+    match resolved.starts_with(&build_dir) && resolved != build_dir {
+        true => Ok(resolved),
+        false => Err(format!("{} is not in the build directory {}", path.display(), build_dir.display())),
+    }
+}
+
+impl BrokerRequest {
+    /// Checks the arguments to the call, before anything is done with docker. Paths are returned resolved, so docker
+    /// is given the paths that were checked rather than ones that go through symlinks
+    fn validate(self) -> Result<BrokerRequest, String> {
+        match self {
+            BrokerRequest::Build { context_dir, app, strict, crates_cache } => {
+                check_argument(&app.name)?;
+                let context_dir = resolve_build_path(&context_dir, &build_dirs::get_build_dir())?;
+                match context_dir.join("Dockerfile").is_file() {
+                    true => Ok(BrokerRequest::Build { context_dir, app, strict, crates_cache }),
+                    false => Err(format!("{} is not a build directory", context_dir.display())),
+                }
+            },
+            BrokerRequest::SaveImage { app, file } => {
+                check_argument(&app)?;
+
+                // The file doesn't exist yet, so its directory is resolved instead. Nothing can be there already, not
+                // even a symlink, as docker would write through it
+                let (parent, name) = match (file.parent(), file.file_name()) {
+                    (Some(parent), Some(name)) => (resolve_build_path(parent, &build_dirs::get_build_dir())?, name),
+                    _ => return Err(format!("{} can't be saved to", file.display())),
+                };
+
+                let file = parent.join(name);
+                match fs::symlink_metadata(&file).is_err() {
+                    true => Ok(BrokerRequest::SaveImage { app, file }),
+                    false => Err(format!("{} can't be saved to", file.display())),
+                }
+            },
+            BrokerRequest::LoadImage { app, file, image_id } => {
+                check_argument(&app)?;
+                check_argument(&image_id)?;
+                let file = resolve_build_path(&file, &build_dirs::get_build_dir())?;
+                match file.is_file() {
+                    true => Ok(BrokerRequest::LoadImage { app, file, image_id }),
+                    false => Err(format!("{} is not a saved image", file.display())),
+                }
+            },
+            request => request.check_arguments().map(|_| request),
+        }
+    }
+
+    /// Checks the arguments to a call that doesn't use any paths
+    fn check_arguments(&self) -> Result<(), String> {
+        match self {
+            BrokerRequest::Run { app, proxy_url, .. } => {
+                check_argument(&app.name)?;
+                match proxy_url {
+                    Some(proxy_url) if !proxy_url.starts_with("http://") => Err(format!("Invalid proxy URL: {}", proxy_url)),
+                    _ => Ok(()),
+                }
+            },
+            BrokerRequest::Stop { container_ids, .. } => check_managed_containers(container_ids),
+            BrokerRequest::TagImage { app, image_ref } => check_argument(app).and_then(|_| check_image_ref(image_ref)),
+            BrokerRequest::PushImage { image_ref } => check_image_ref(image_ref),
+            BrokerRequest::ReadFileFromImage { app, path } => {
+                check_argument(app)?;
+                match path.starts_with('/') {
+                    true => Ok(()),
+                    false => Err(format!("Invalid path: {}", path)),
+                }
+            },
+            BrokerRequest::GetImageDigest { image } => check_argument(image),
+            BrokerRequest::CheckRegistry { registry } => check_argument(registry),
+            BrokerRequest::RemoveExitedContainers { app }
+            | BrokerRequest::RemoveImage { app }
+            | BrokerRequest::GetContainerIds { app }
+            | BrokerRequest::GetImageId { app }
+            | BrokerRequest::GetImageSize { app }
+            | BrokerRequest::GetMemoryUsage { app }
+            | BrokerRequest::GetLogs { app, .. }
+            | BrokerRequest::FollowLogs { app, .. } => check_argument(app),
+            BrokerRequest::GetResourceUsage { app: Some(app) } => check_argument(app),
+            BrokerRequest::Build { .. } | BrokerRequest::SaveImage { .. } | BrokerRequest::LoadImage { .. } => {
+                Err("The call uses paths, so is checked with validate".to_string())
+            },
+            BrokerRequest::GetResourceUsage { app: None }
+            | BrokerRequest::GetAppImages
+            | BrokerRequest::GetRunningApps
+            | BrokerRequest::GetAppContainers
+            | BrokerRequest::CheckDocker => Ok(()),
+        }
+    }
+}
+
+/// Sends a message back to the host. Returns false if the host has hung up
+fn reply(stream: &mut UnixStream, reply: &BrokerReply) -> bool {
+    let mut line = match serde_json::to_string(reply) {
+        Ok(line) => line,
+        Err(_) => return false,
+    };
+    line.push('\n');
+
+    stream.write_all(line.as_bytes()).is_ok()
+}
+
+/// Converts the result of a docker call to send back to the host
+fn to_value<T: Serialize>(result: Result<T, String>) -> Result<Value, String> {
+    result.and_then(|value| serde_json::to_value(value).map_err(|e| format!("Error sending the result: {}", e)))
+}
+
+/// Makes a docker call that has a single result
+fn answer(request: BrokerRequest) -> Result<Value, String> {
+    match request {
+        BrokerRequest::Run { app, proxy_url, port } => to_value(docker::run_function_app_container(&app, &proxy_url, port)),
+        BrokerRequest::Stop { container_ids, grace_period } => to_value(docker::stop_containers(&container_ids, grace_period)),
+        BrokerRequest::RemoveExitedContainers { app } => to_value(Ok(docker::remove_exited_containers(&app))),
+        BrokerRequest::RemoveImage { app } => to_value(docker::remove_function_app_image(&app)),
+        BrokerRequest::TagImage { app, image_ref } => to_value(registry::tag_image(&app, &image_ref)),
+        BrokerRequest::GetAppImages => to_value(docker::get_app_images()),
+        BrokerRequest::GetContainerIds { app } => to_value(docker::get_container_ids(&app)),
+        BrokerRequest::GetRunningApps => to_value(docker::get_running_apps()),
+        BrokerRequest::GetAppContainers => to_value(docker::get_app_containers()),
+        BrokerRequest::GetImageId { app } => to_value(Ok(docker::get_image_id(&app))),
+        BrokerRequest::GetImageDigest { image } => to_value(Ok(docker::get_image_digest(&image))),
+        BrokerRequest::GetImageSize { app } => to_value(docker::get_image_size(&app)),
+        BrokerRequest::GetMemoryUsage { app } => to_value(docker::get_memory_usage(&app)),
+        BrokerRequest::GetResourceUsage { app } => to_value(docker::get_resource_usage(app.as_ref())),
+        BrokerRequest::GetLogs { app, tail } => to_value(docker::get_logs(&app, tail)),
+        BrokerRequest::ReadFileFromImage { app, path } => to_value(docker::read_file_from_image(&app, &path)),
+        BrokerRequest::SaveImage { app, file } => to_value(docker::save_image(&app, &file)),
+        BrokerRequest::LoadImage { app, file, image_id } => to_value(docker::load_image(&app, &file, &image_id)),
+        BrokerRequest::CheckRegistry { registry } => to_value(registry::check_credentials(&registry)),
+        BrokerRequest::CheckDocker => to_value(health::check_docker()),
+        BrokerRequest::Build { .. } | BrokerRequest::PushImage { .. } | BrokerRequest::FollowLogs { .. } => {
+            Err("The call streams its output".to_string())
+        },
+    }
+}
+
+/// Sends each line a docker process writes back to the host on a background thread
+fn forward_output<R: Read + Send + 'static>(output: Option<R>, stream: Arc<Mutex<UnixStream>>, to_reply: fn(String) -> BrokerReply) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let output = match output {
+            Some(output) => output,
+            None => return,
+        };
+
+        let mut reader = BufReader::new(output);
+        let mut line = Vec::new();
+        while matches!(reader.read_until(b'\n', &mut line), Ok(read) if read > 0) {
+            // Keep reading if the host has gone, so the process doesn't block on a full pipe before it is killed
+            if let Ok(mut stream) = stream.lock() {
+                reply(&mut stream, &to_reply(String::from_utf8_lossy(&line).to_string()));
+            }
+            line.clear();
+        }
+    })
+}
+
+/// Streams the output of a docker process back to the host, then sends its exit status. If the host hangs up, such
+/// as when a build is cancelled, the process is killed
+fn run_process(stream: UnixStream, mut child: Child) {
+    let (writer, mut watcher) = match (stream.try_clone(), stream.try_clone()) {
+        (Ok(writer), Ok(watcher)) => (Arc::new(Mutex::new(writer)), watcher),
+        _ => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return;
+        }
+    };
+
+    let std_out = forward_output(child.stdout.take(), writer.clone(), BrokerReply::Stdout);
+    let std_err = forward_output(child.stderr.take(), writer.clone(), BrokerReply::Stderr);
+
+    // The host sends nothing after the call, so the read only returns once it has hung up
+    let hung_up = Arc::new(AtomicBool::new(false));
+    let watcher_hung_up = hung_up.clone();
+    thread::spawn(move || {
+        let _ = watcher.read(&mut [0; 1]);
+        watcher_hung_up.store(true, Ordering::SeqCst);
+    });
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status.into_raw()),
+            Ok(None) => {},
+            Err(e) => break Err(format!("Error waiting for docker: {}", e)),
+        }
+
+        if hung_up.load(Ordering::SeqCst) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return;
+        }
+
+        thread::sleep(PROCESS_POLL_INTERVAL);
+    };
+
+    // Send all the output before the exit status
+    let _ = std_out.join();
+    let _ = std_err.join();
+
+    if let Ok(mut stream) = writer.lock() {
+        reply(&mut stream, &BrokerReply::Done(to_value(status)));
+    };
+}
+
+/// Follows the logs for an app, sending each event back to the host until the container exits or the host hangs up
+fn follow_app_logs(mut stream: UnixStream, app: &String, tail: usize) {
+    let mut events = match docker::follow_logs(app, tail) {
+        Ok(events) => events,
+        Err(e) => {
+            reply(&mut stream, &BrokerReply::Done(Err(e)));
+            return;
+        }
+    };
+
+    while let Some(event) = futures::executor::block_on(events.next()) {
+        if !reply(&mut stream, &BrokerReply::Stdout(event)) {
+            return;
+        }
+    }
+
+    reply(&mut stream, &BrokerReply::Done(Ok(Value::Null)));
+}
+
+/// Answers a call from the host
+fn handle_connection(mut stream: UnixStream) {
+    let mut line = String::new();
+    let read = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader).read_line(&mut line),
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = read {
+        println!("Error reading call from the host: {}", e);
+        return;
+    }
+
+    let request = match serde_json::from_str::<BrokerRequest>(&line) {
+        Ok(request) => request,
+        Err(e) => {
+            reply(&mut stream, &BrokerReply::Done(Err(format!("Invalid call: {}", e))));
+            return;
+        }
+    };
+
+    let request = match request.validate() {
+        Ok(request) => request,
+        Err(e) => {
+            println!("Refusing call from the host: {}", e);
+            reply(&mut stream, &BrokerReply::Done(Err(e)));
+            return;
+        }
+    };
+
+    let spawned = match request {
+        BrokerRequest::Build { context_dir, app, strict, crates_cache } => docker::spawn_build(&context_dir, &app, strict, &crates_cache),
+        BrokerRequest::PushImage { image_ref } => registry::spawn_push(&image_ref),
+        BrokerRequest::FollowLogs { app, tail } => {
+            follow_app_logs(stream, &app, tail);
+            return;
+        },
+        request => {
+            reply(&mut stream, &BrokerReply::Done(answer(request)));
+            return;
+        },
+    };
+
+    match spawned {
+        Ok(child) => run_process(stream, child),
+        Err(e) => {
+            reply(&mut stream, &BrokerReply::Done(Err(e)));
+        },
+    }
+}
+
+/// Runs the docker broker, answering calls from the host on the given socket until it is stopped
+///
+/// The broker is the only process that needs access to the docker socket. It reads the code for builds from the
+/// host's build directory, and saves images being promoted to another host there, so it runs as a user that can
+/// read and write it
+pub fn serve(socket: &Path) -> Result<(), String> {
+    // The broker uses docker itself, even if it was started with the broker socket set
+    BROKER_SOCKET.get_or_init(|| None);
+    if is_enabled() {
+        return Err("The docker broker can't send calls to another broker".to_string());
+    }
+
+    // A socket left behind by a broker that didn't shut down cleanly stops the socket being created again
+    if fs::symlink_metadata(socket).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        let _ = fs::remove_file(socket);
+    }
+
+    let listener = match UnixListener::bind(socket) {
+        Ok(listener) => listener,
+        Err(e) => return Err(format!("Error listening on {}: {}", socket.display(), e)),
+    };
+
+    if let Err(e) = fs::set_permissions(socket, fs::Permissions::from_mode(BROKER_SOCKET_MODE)) {
+        return Err(format!("Error setting the permissions on {}: {}", socket.display(), e));
+    }
+
+    println!("Docker broker listening on {}", socket.display());
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || handle_connection(stream));
+            },
+            Err(e) => println!("Error accepting a connection from the host: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn resolves_paths_in_the_build_directory() {
+        let build_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(build_dir.path().join("build")).unwrap();
+
+        let resolved = resolve_build_path(&build_dir.path().join("build"), build_dir.path()).unwrap();
+
+        assert_eq!(resolved, fs::canonicalize(build_dir.path()).unwrap().join("build"));
+    }
+
+    #[test]
+    fn refuses_the_build_directory_itself() {
+        let build_dir = tempfile::tempdir().unwrap();
+
+        assert!(resolve_build_path(build_dir.path(), build_dir.path()).is_err());
+    }
+
+    #[test]
+    fn refuses_relative_paths() {
+        let build_dir = tempfile::tempdir().unwrap();
+
+        assert!(resolve_build_path(Path::new("build"), build_dir.path()).is_err());
+    }
+
+    #[test]
+    fn refuses_paths_with_parent_directories() {
+        let build_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(build_dir.path().join("build")).unwrap();
+
+        // This would resolve to a directory in the build directory, but .. is refused before it is resolved
+        let path = build_dir.path().join("build").join("..").join("build");
+
+        assert!(resolve_build_path(&path, build_dir.path()).is_err());
+    }
+
+    #[test]
+    fn refuses_paths_outside_the_build_directory() {
+        let build_dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+
+        assert!(resolve_build_path(outside.path(), build_dir.path()).is_err());
+    }
+
+    #[test]
+    fn refuses_symlinks_out_of_the_build_directory() {
+        let build_dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        symlink(outside.path(), build_dir.path().join("build")).unwrap();
+
+        assert!(resolve_build_path(&build_dir.path().join("build"), build_dir.path()).is_err());
+    }
+
+    #[test]
+    fn refuses_saving_over_a_symlink() {
+        let build_dir = tempfile::tempdir_in(build_dirs::get_build_dir()).unwrap();
+        symlink("/tmp/elsewhere", build_dir.path().join("image.tar")).unwrap();
+
+        let request = BrokerRequest::SaveImage { app: "app".to_string(), file: build_dir.path().join("image.tar") };
+
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    fn refuses_loading_through_a_symlink_out_of_the_build_directory() {
+        let build_dir = tempfile::tempdir_in(build_dirs::get_build_dir()).unwrap();
+        symlink("/etc/hostname", build_dir.path().join("image.tar")).unwrap();
+
+        let request = BrokerRequest::LoadImage {
+            app: "app".to_string(),
+            file: build_dir.path().join("image.tar"),
+            image_id: "sha256:1234".to_string(),
+        };
+
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    fn loads_files_in_the_build_directory() {
+        let build_dir = tempfile::tempdir_in(build_dirs::get_build_dir()).unwrap();
+        fs::write(build_dir.path().join("image.tar"), "image").unwrap();
+
+        let request = BrokerRequest::LoadImage {
+            app: "app".to_string(),
+            file: build_dir.path().join("image.tar"),
+            image_id: "sha256:1234".to_string(),
+        };
+
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn checks_the_arguments_of_calls_without_paths() {
+        assert!(BrokerRequest::GetLogs { app: "--help".to_string(), tail: 10 }.validate().is_err());
+        assert!(BrokerRequest::GetLogs { app: "app".to_string(), tail: 10 }.validate().is_ok());
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use tempfile::{Builder, TempDir};
+
+/// The start of the name of each build directory, followed by the ID of the host process that created it so
+/// directories left behind by a host that crashed can be spotted
+const BUILD_DIR_PREFIX: &str = "rustless-build-";
+
+/// The directory builds are run in, set from the host configuration when the host starts
+static BUILD_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory builds are run in, creating it if it doesn't exist. If no directory is given, builds are run
+/// in the system temporary directory
+pub fn set_build_dir(build_dir: &Option<PathBuf>) -> Result<(), String> {
+    let build_dir = match build_dir {
+        Some(build_dir) => build_dir.to_path_buf(),
+        None => std::env::temp_dir(),
+    };
+
+    if let Err(e) = fs::create_dir_all(&build_dir) {
+        return Err(format!("Error creating build directory {}: {}", build_dir.display(), e));
+    }
+
+    println!("Running builds in {}", build_dir.display());
+    let _ = BUILD_DIR.set(build_dir);
+    Ok(())
+}
+
+/// Gets the directory builds are run in
+pub fn get_build_dir() -> PathBuf {
+    match BUILD_DIR.get() {
+        Some(build_dir) => build_dir.to_path_buf(),
+        None => std::env::temp_dir(),
+    }
+}
+
+/// Creates a directory for a build in the build directory, which is deleted when it is dropped
+pub fn create() -> Result<TempDir, String> {
+    let prefix = format!("{}{}-", BUILD_DIR_PREFIX, std::process::id());
+    match Builder::new().prefix(&prefix).tempdir_in(get_build_dir()) {
+        Ok(dir) => {
+            println!("Created build directory at {}", dir.path().display());
+            Ok(dir)
+        },
+        Err(e) => Err(format!("Error creating build directory: {}", e)),
+    }
+}
+
+/// Gets the free space on the disk holding a directory, in bytes, using df so it works on every platform the host
+/// runs on
+fn get_free_space(dir: &Path) -> Result<u64, String> {
+    let output = match Command::new("df").arg("-Pk").arg(dir).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error running df: {}", e)),
+    };
+
+    if !output.status.success() {
+        return Err(format!("Error running df: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    // The second line is the disk, with the available space in kilobytes in the fourth column
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let available = stdout.lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|available| available.parse::<u64>().ok());
+
+    match available {
+        Some(available) => Ok(available * 1024),
+        None => Err(format!("Error reading the free space from df: {}", stdout.trim())),
+    }
+}
+
+/// Checks there is enough free space in the build directory for a number of bytes, returning an error if there isn't.
+/// If the free space can't be read the check is skipped, so builds still run on hosts without df
+pub fn check_free_space(needed: u64) -> Result<(), String> {
+    let build_dir = get_build_dir();
+    let available = match get_free_space(&build_dir) {
+        Ok(available) => available,
+        Err(e) => {
+            println!("Skipping the free space check: {}", e);
+            return Ok(());
+        }
+    };
+
+    if available < needed {
+        return Err(format!(
+            "Not enough free space in the build directory {}. The code needs {} bytes, but only {} bytes are free",
+            build_dir.display(), needed, available
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks if a process is still running
+fn is_process_running(pid: u32) -> bool {
+    match Command::new("kill").arg("-0").arg(pid.to_string()).output() {
+        Ok(output) => output.status.success(),
+        Err(_) => false,
+    }
+}
+
+/// Deletes the build directories left behind by hosts that crashed part way through a build. Directories created by
+/// a host that is still running, such as the old host during an upgrade, are left alone
+pub fn clean_orphaned() {
+    let build_dir = get_build_dir();
+    let entries = match fs::read_dir(&build_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Error reading build directory {}: {}", build_dir.display(), e);
+            return;
+        }
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let pid = match name.strip_prefix(BUILD_DIR_PREFIX).and_then(|rest| rest.split('-').next()) {
+            Some(pid) => pid.parse::<u32>().ok(),
+            None => continue,
+        };
+
+        // The ID of this host can only be on a directory left behind by an old host that had the same ID
+        let orphaned = match pid {
+            Some(pid) => pid == std::process::id() || !is_process_running(pid),
+            None => false,
+        };
+
+        if !orphaned {
+            continue;
+        }
+
+        match fs::remove_dir_all(entry.path()) {
+            Ok(_) => removed += 1,
+            Err(e) => println!("Error removing orphaned build directory {}: {}", entry.path().display(), e),
+        }
+    }
+
+    if removed > 0 {
+        println!("Removed {} orphaned build directories from {}", removed, build_dir.display());
+    }
+}
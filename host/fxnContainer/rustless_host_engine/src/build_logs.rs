@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+use rustless_shared::{BuildLog, BUILD_FINISHED_EVENT, BUILD_OUTPUT_EVENT};
+
+use crate::database::Connection;
+use crate::phases;
+use crate::storage;
+
+/// The most output kept for a build. Compiler errors come at the end, so the start of the output is dropped
+const MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// How many builds of each app have their output kept in the database
+const KEPT_BUILD_LOGS: usize = 10;
+
+/// Put at the start of the output when the start has been dropped to keep it under the limit
+const TRIMMED_MARKER: &str = "[earlier output trimmed]\n";
+
+/// The output of a build that is queued or building
+struct RunningBuild {
+    // The ID of the build
+    build_id: Uuid,
+
+    // When the build was queued, in seconds since the Unix epoch
+    started_at: u64,
+
+    // The output so far
+    output: String,
+
+    // The clients following the output, sent it as server sent events
+    followers: Vec<UnboundedSender<String>>,
+}
+
+/// The builds that are queued or building, by the ID of the function app
+static RUNNING_BUILDS: OnceLock<Mutex<HashMap<Uuid, RunningBuild>>> = OnceLock::new();
+
+/// Gets the builds that are queued or building
+fn get_running_builds() -> &'static Mutex<HashMap<Uuid, RunningBuild>> {
+    RUNNING_BUILDS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Formats a line of build output as a server sent event
+fn get_output_frame(line: &str) -> String {
+    format!("event: {}\ndata: {}\n\n", BUILD_OUTPUT_EVENT, line)
+}
+
+/// Formats the end of a build as a server sent event, with the result as the data
+fn get_finished_frame(result: &str) -> String {
+    format!("event: {}\ndata: {}\n\n", BUILD_FINISHED_EVENT, result)
+}
+
+/// Drops whole lines from the start of the output until it is under the limit
+fn trim_output(output: &mut String) {
+    if output.len() <= MAX_OUTPUT_BYTES {
+        return;
+    }
+
+    let mut start = output.len() - MAX_OUTPUT_BYTES + TRIMMED_MARKER.len();
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+
+    let start = match output[start..].find('\n') {
+        Some(end) => start + end + 1,
+        None => output.len(),
+    };
+
+    output.replace_range(..start, TRIMMED_MARKER);
+}
+
+/// Starts capturing the output of a build of a function app. Output from an earlier build that didn't finish is
+/// forgotten
+pub fn start(id: &Uuid, build_id: &Uuid) {
+    if let Ok(mut builds) = get_running_builds().lock() {
+        builds.insert(*id, RunningBuild {
+            build_id: *build_id,
+            started_at: phases::to_timestamp(SystemTime::now()),
+            output: String::new(),
+            followers: Vec::new(),
+        });
+    }
+}
+
+/// Adds lines of output to the build running for a function app, sending them to the clients following it.
+/// Output is ignored if the app isn't building
+pub fn append(id: &Uuid, text: &str) {
+    if let Ok(mut builds) = get_running_builds().lock() {
+        if let Some(build) = builds.get_mut(id) {
+            for line in text.lines() {
+                let line = line.trim_end_matches('\r');
+                build.output.push_str(line);
+                build.output.push('\n');
+
+                let frame = get_output_frame(line);
+                build.followers.retain(|follower| follower.unbounded_send(frame.clone()).is_ok());
+            }
+
+            trim_output(&mut build.output);
+        }
+    }
+}
+
+/// Checks if the output of the build running for a function app already ends with the last line of an error
+fn ends_with_error(id: &Uuid, error: &str) -> bool {
+    let last_line = error.lines().last().unwrap_or_default().trim();
+    match get_running_builds().lock() {
+        Ok(builds) => builds.get(id).is_some_and(|build| build.output.trim_end().ends_with(last_line)),
+        Err(_) => false,
+    }
+}
+
+/// Finishes capturing the output of the build running for a function app, saving it to the database with the
+/// result and telling the clients following it that it has finished
+///
+/// If the build failed with an error that isn't already at the end of the output, such as a timeout or a failed
+/// push, the error is added to the output. Errors made from the docker build output aren't added again
+pub fn finish(conn: &Connection, id: &Uuid, result: &str, error: Option<&str>) {
+    if let Some(error) = error.map(str::trim).filter(|error| !error.is_empty()) {
+        if !ends_with_error(id, error) {
+            append(id, error);
+        }
+    }
+
+    let log = match get_running(id, &None) {
+        Some(log) => BuildLog {
+            finished_at: Some(phases::to_timestamp(SystemTime::now())),
+            result: Some(result.to_string()),
+            ..log
+        },
+        None => return,
+    };
+
+    // The log is saved before the build stops running, so clients always find it in one place or the other
+    if let Err(e) = storage::add_build_log(conn, id, &log, KEPT_BUILD_LOGS) {
+        println!("Error saving build log: {}", e);
+    }
+
+    if let Ok(mut builds) = get_running_builds().lock() {
+        if builds.get(id).is_some_and(|build| build.build_id == log.build_id) {
+            if let Some(build) = builds.remove(id) {
+                let frame = get_finished_frame(result);
+                for follower in build.followers {
+                    let _ = follower.unbounded_send(frame.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Gets the output so far of the build running for a function app, or None if the app isn't building or the
+/// running build isn't the one asked for
+pub fn get_running(id: &Uuid, build_id: &Option<Uuid>) -> Option<BuildLog> {
+    let builds = get_running_builds().lock().ok()?;
+    let build = builds.get(id).filter(|build| build_id.is_none_or(|build_id| build_id == build.build_id))?;
+
+    Some(BuildLog {
+        build_id: build.build_id,
+        started_at: build.started_at,
+        finished_at: None,
+        result: None,
+        output: build.output.clone(),
+    })
+}
+
+/// Follows the build running for a function app, returning the server sent events for it. The output so far is
+/// sent straight away, then each line as the build writes it, and the stream ends when the build finishes.
+/// Returns None if the app isn't building or the running build isn't the one asked for
+pub fn follow(id: &Uuid, build_id: &Option<Uuid>) -> Option<UnboundedReceiver<String>> {
+    let mut builds = get_running_builds().lock().ok()?;
+    let build = builds.get_mut(id).filter(|build| build_id.is_none_or(|build_id| build_id == build.build_id))?;
+
+    let (sender, receiver) = mpsc::unbounded();
+    let _ = sender.unbounded_send(": connected\n\n".to_string());
+    for line in build.output.lines() {
+        let _ = sender.unbounded_send(get_output_frame(line));
+    }
+
+    build.followers.push(sender);
+    Some(receiver)
+}
+
+/// Replays the log of a finished build as server sent events, in the same way as following a running build
+pub fn replay(log: &BuildLog) -> UnboundedReceiver<String> {
+    let (sender, receiver) = mpsc::unbounded();
+    let _ = sender.unbounded_send(": connected\n\n".to_string());
+    for line in log.output.lines() {
+        let _ = sender.unbounded_send(get_output_frame(line));
+    }
+
+    let _ = sender.unbounded_send(get_finished_frame(log.result.as_deref().unwrap_or_default()));
+    receiver
+}
@@ -0,0 +1,165 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use actix_web::rt::time::sleep;
+use uuid::Uuid;
+
+use crate::phases::DeployPhase;
+
+/// The environment variable containing the maximum number of docker builds that can run at the same time
+pub const MAX_CONCURRENT_BUILDS_ENV: &str = "RUSTLESS_MAX_CONCURRENT_BUILDS";
+
+/// The maximum number of docker builds that can run at the same time if the environment variable isn't set
+const DEFAULT_MAX_CONCURRENT_BUILDS: usize = 1;
+
+/// The maximum number of docker builds that can run at the same time, read from the environment once
+static MAX_CONCURRENT_BUILDS: OnceLock<usize> = OnceLock::new();
+
+/// How often to check if a queued build can start
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The function apps waiting to build, or building, in the order they were queued.
+/// The first get_max_concurrent_builds() entries are the ones currently building.
+static BUILD_QUEUE: Mutex<Vec<Uuid>> = Mutex::new(Vec::new());
+
+/// The function apps with a build that has been asked to cancel. A queued build stops waiting, and
+/// a running build has its docker build killed
+static CANCELLED_BUILDS: Mutex<Vec<Uuid>> = Mutex::new(Vec::new());
+
+/// The error returned when a build is cancelled
+pub const BUILD_CANCELLED: &str = "The build was cancelled";
+
+/// A slot in the build queue. The function app is removed from the queue when this is dropped,
+/// so the queue is always freed up even if the build fails part way through.
+pub struct BuildQueueSlot {
+    id: Uuid,
+
+    // When the build was added to the queue, used for the queue timeout
+    queued_at: SystemTime,
+}
+
+impl Drop for BuildQueueSlot {
+    fn drop(&mut self) {
+        if let Ok(mut queue) = BUILD_QUEUE.lock() {
+            queue.retain(|queued_id| *queued_id != self.id);
+        }
+
+        if let Ok(mut cancelled) = CANCELLED_BUILDS.lock() {
+            cancelled.retain(|cancelled_id| *cancelled_id != self.id);
+        }
+    }
+}
+
+impl BuildQueueSlot {
+    /// Waits until it is the function app's turn to build, giving up if it waits longer than the queue timeout
+    pub async fn wait_for_turn(&self) -> Result<(), String> {
+        // Wait till we are no longer waiting in the queue, giving up if the build is cancelled
+        while get_queue_position(&self.id).is_some() {
+            if is_cancelled(&self.id) {
+                return Err(BUILD_CANCELLED.to_string());
+            }
+
+            if DeployPhase::Queue.has_timed_out(self.queued_at) {
+                return Err(DeployPhase::Queue.timeout_error());
+            }
+
+            sleep(QUEUE_POLL_INTERVAL).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Gets the maximum number of docker builds that can run at the same time
+pub fn get_max_concurrent_builds() -> usize {
+    *MAX_CONCURRENT_BUILDS.get_or_init(|| match std::env::var(MAX_CONCURRENT_BUILDS_ENV) {
+        Ok(value) => match value.trim().parse::<usize>() {
+            Ok(max_builds) if max_builds > 0 => max_builds,
+            _ => {
+                println!("Ignoring invalid {}: {}", MAX_CONCURRENT_BUILDS_ENV, value);
+                DEFAULT_MAX_CONCURRENT_BUILDS
+            }
+        },
+        Err(_) => DEFAULT_MAX_CONCURRENT_BUILDS,
+    })
+}
+
+/// Adds a function app to the end of the build queue. The app counts as queued from now until the slot is dropped,
+/// so the build can be cancelled and the status shows it as building while the code is still being extracted
+pub fn enqueue(id: &Uuid) -> Result<BuildQueueSlot, String> {
+    match BUILD_QUEUE.lock() {
+        Ok(mut queue) => queue.push(*id),
+        Err(e) => return Err(format!("Error adding build to the queue: {}", e)),
+    };
+
+    Ok(BuildQueueSlot {
+        id: *id,
+        queued_at: SystemTime::now(),
+    })
+}
+
+/// Gets the position of the function app in the build queue
+///
+/// This is 1 for the next build to run. If the function app is not waiting in the queue, either
+/// because it is building or has no build queued, this returns None.
+pub fn get_queue_position(id: &Uuid) -> Option<usize> {
+    let queue = BUILD_QUEUE.lock().ok()?;
+    let index = queue.iter().position(|queued_id| queued_id == id)?;
+
+    let max_builds = get_max_concurrent_builds();
+    if index < max_builds {
+        None
+    } else {
+        Some(index - max_builds + 1)
+    }
+}
+
+/// Gets if the function app is building, or waiting in the queue to build
+pub fn is_queued(id: &Uuid) -> bool {
+    match BUILD_QUEUE.lock() {
+        Ok(queue) => queue.contains(id),
+        Err(_) => false,
+    }
+}
+
+/// Asks the build for the function app to stop, whether it is waiting in the queue or building
+///
+/// This returns false if the function app has no build queued.
+pub fn cancel_build(id: &Uuid) -> bool {
+    if !is_queued(id) {
+        return false;
+    }
+
+    match CANCELLED_BUILDS.lock() {
+        Ok(mut cancelled) => {
+            if !cancelled.contains(id) {
+                cancelled.push(*id);
+            }
+            true
+        },
+        Err(_) => false,
+    }
+}
+
+/// Gets if the build for the function app has been asked to cancel
+pub fn is_cancelled(id: &Uuid) -> bool {
+    match CANCELLED_BUILDS.lock() {
+        Ok(cancelled) => cancelled.contains(id),
+        Err(_) => false,
+    }
+}
+
+/// Checks the build queue can still be used. If a build panicked while holding the queue lock,
+/// no more builds can be queued
+pub fn is_healthy() -> bool {
+    !BUILD_QUEUE.is_poisoned() && !CANCELLED_BUILDS.is_poisoned()
+}
+
+/// Estimates how long a queued build will wait, based on the average duration of recent builds
+pub fn estimate_wait(queue_position: usize, average_build_duration: u64) -> u64 {
+    // The builds currently running and the ones ahead in the queue need to finish first,
+    // with up to get_max_concurrent_builds() finishing each round
+    let rounds = queue_position.div_ceil(get_max_concurrent_builds());
+
+    rounds as u64 * average_build_duration
+}
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::broker;
+use crate::build_queue;
+use crate::crates_cache;
+use crate::database;
+use crate::db_pool;
+use crate::docker;
+use crate::egress;
+use crate::grpc;
+use crate::key_files;
+use crate::listeners;
+use crate::oidc;
+use crate::platform;
+use crate::storage;
+use crate::HostConfig;
+
+/// The environment variable containing the path to the config file. The file must exist if this is set
+const CONFIG_FILE_ENV: &str = "RUSTLESS_CONFIG";
+
+/// The config file read from the working directory if it exists and no other file is given
+const DEFAULT_CONFIG_FILE: &str = "rustless_host.toml";
+
+/// The environment variable containing the address to listen on, such as 0.0.0.0:8080
+const ADDRESS_ENV: &str = "RUSTLESS_ADDRESS";
+
+/// The environment variable containing the port to listen on, replacing the port in the address
+const PORT_ENV: &str = "RUSTLESS_PORT";
+
+/// The environment variable containing the PEM file with the private key for HTTPS
+const TLS_KEY_FILE_ENV: &str = "RUSTLESS_TLS_KEY_FILE";
+
+/// The environment variable containing the PEM file with the certificate chain for HTTPS
+const TLS_CERT_FILE_ENV: &str = "RUSTLESS_TLS_CERT_FILE";
+
+/// The environment variable containing the directory builds run in
+const BUILD_DIR_ENV: &str = "RUSTLESS_BUILD_DIR";
+
+/// The environment variable docker reads the address of the docker daemon from
+const DOCKER_HOST_ENV: &str = "DOCKER_HOST";
+
+/// The prefix settings in the env table of the config file must start with
+const ENV_PREFIX: &str = "RUSTLESS_";
+
+/// A setting in the config file that is passed on as an environment variable, so it can be written as a string,
+/// number, or boolean
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum EnvValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl EnvValue {
+    /// Gets the value as it is set in the environment variable
+    fn to_env(&self) -> String {
+        match self {
+            EnvValue::String(value) => value.to_string(),
+            EnvValue::Integer(value) => value.to_string(),
+            EnvValue::Float(value) => value.to_string(),
+            EnvValue::Boolean(value) => value.to_string(),
+        }
+    }
+}
+
+/// The HTTPS settings in the config file
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct TlsSection {
+    // The PEM file containing the private key
+    private_key_file: Option<PathBuf>,
+
+    // The PEM file containing the certificate chain
+    certificate_file: Option<PathBuf>,
+}
+
+/// The addresses the optional listeners run on in the config file. Each one is off unless it has an address
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ListenersSection {
+    // The address the gRPC gateway listens on, set as RUSTLESS_GRPC_GATEWAY
+    grpc_gateway: Option<EnvValue>,
+
+    // The port or address the egress proxy listens on, set as RUSTLESS_EGRESS_PROXY
+    egress_proxy: Option<EnvValue>,
+
+    // The address the crates.io cache listens on, set as RUSTLESS_CRATES_CACHE
+    crates_cache: Option<EnvValue>,
+
+    // Whether listeners on IPv6 addresses, including the main address, only accept IPv6 connections, set as
+    // RUSTLESS_IPV6_ONLY
+    ipv6_only: Option<EnvValue>,
+}
+
+/// The settings for the providers that check tokens sent to the management API in the config file. API keys created
+/// with rustless-hostctl are always accepted
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct AuthSection {
+    // The file of keys that are accepted, set as RUSTLESS_AUTH_KEY_FILE
+    key_file: Option<EnvValue>,
+
+    // The OpenID Connect provider whose tokens are accepted, set as RUSTLESS_OIDC_ISSUER
+    oidc_issuer: Option<EnvValue>,
+
+    // The audience the tokens must be issued for, set as RUSTLESS_OIDC_AUDIENCE
+    oidc_audience: Option<EnvValue>,
+
+    // The comma separated groups that can manage the host, set as RUSTLESS_OIDC_GROUPS
+    oidc_groups: Option<EnvValue>,
+
+    // The claim the groups are read from, set as RUSTLESS_OIDC_GROUPS_CLAIM
+    oidc_groups_claim: Option<EnvValue>,
+}
+
+/// The docker settings in the config file
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct DockerSection {
+    // The address of the docker daemon, set as DOCKER_HOST
+    host: Option<EnvValue>,
+
+    // The docker CLI to run, set as RUSTLESS_DOCKER_PATH
+    path: Option<EnvValue>,
+
+    // The socket of the docker broker, set as RUSTLESS_DOCKER_BROKER
+    broker: Option<EnvValue>,
+
+    // The platform docker runs on, set as RUSTLESS_PLATFORM
+    platform: Option<EnvValue>,
+
+    // The address the host calls running apps on, set as RUSTLESS_APP_HOST
+    app_host: Option<EnvValue>,
+
+    // The prefix for the labels on images and containers, set as RUSTLESS_LABEL_PREFIX
+    label_prefix: Option<EnvValue>,
+
+    // The number of CPUs a build can use, set as RUSTLESS_BUILD_CPUS
+    build_cpus: Option<EnvValue>,
+
+    // The memory a build can use, set as RUSTLESS_BUILD_MEMORY
+    build_memory: Option<EnvValue>,
+
+    // The memory each app container can use, set as RUSTLESS_APP_MEMORY
+    app_memory: Option<EnvValue>,
+
+    // How long apps get to finish requests in flight when they are stopped, set as RUSTLESS_STOP_GRACE_PERIOD
+    stop_grace_period: Option<EnvValue>,
+
+    // How many ports are tried when starting an app, set as RUSTLESS_START_ATTEMPTS
+    start_attempts: Option<EnvValue>,
+}
+
+/// The host config file. Every setting is optional, and environment variables take priority over the file
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    // The address to listen on, such as 0.0.0.0:8080
+    address: Option<String>,
+
+    // The port to listen on, replacing the port in the address
+    port: Option<u16>,
+
+    // The path the host is served under behind a reverse proxy, such as /rustless, set as RUSTLESS_BASE_PATH
+    base_path: Option<EnvValue>,
+
+    // The directory builds run in
+    build_dir: Option<PathBuf>,
+
+    // The main database file, set as RUSTLESS_DB_FILE
+    database_file: Option<EnvValue>,
+
+    // The most connections kept open to each database file, set as RUSTLESS_DB_POOL_SIZE
+    database_pool_size: Option<EnvValue>,
+
+    // Where data is stored, sqlite or postgres, set as RUSTLESS_STORAGE_BACKEND
+    storage_backend: Option<EnvValue>,
+
+    // The connection string for the Postgres database, set as RUSTLESS_DATABASE_URL
+    database_url: Option<EnvValue>,
+
+    // The maximum number of builds that run at the same time, set as RUSTLESS_MAX_CONCURRENT_BUILDS
+    max_concurrent_builds: Option<EnvValue>,
+
+    // The HTTPS settings, in the tls table
+    #[serde(default)]
+    tls: TlsSection,
+
+    // The addresses of the optional listeners, in the listeners table
+    #[serde(default)]
+    listeners: ListenersSection,
+
+    // The settings for checking tokens sent to the management API, in the auth table
+    #[serde(default)]
+    auth: AuthSection,
+
+    // The docker settings, in the docker table
+    #[serde(default)]
+    docker: DockerSection,
+
+    // Any other settings, named by their environment variable, such as RUSTLESS_IDLE_TIMEOUT = 600
+    #[serde(default)]
+    env: HashMap<String, EnvValue>,
+}
+
+impl ConfigFile {
+    /// Gets the settings in the file that are passed on as environment variables, with the variable for each
+    fn env_settings(&self) -> Vec<(String, &EnvValue)> {
+        let settings = [
+            (listeners::BASE_PATH_ENV, &self.base_path),
+            (storage::DB_FILE_ENV, &self.database_file),
+            (db_pool::DB_POOL_SIZE_ENV, &self.database_pool_size),
+            (database::STORAGE_BACKEND_ENV, &self.storage_backend),
+            (database::DATABASE_URL_ENV, &self.database_url),
+            (build_queue::MAX_CONCURRENT_BUILDS_ENV, &self.max_concurrent_builds),
+            (grpc::GRPC_GATEWAY_ENV, &self.listeners.grpc_gateway),
+            (egress::EGRESS_PROXY_ENV, &self.listeners.egress_proxy),
+            (crates_cache::CRATES_CACHE_ENV, &self.listeners.crates_cache),
+            (listeners::IPV6_ONLY_ENV, &self.listeners.ipv6_only),
+            (key_files::AUTH_KEY_FILE_ENV, &self.auth.key_file),
+            (oidc::OIDC_ISSUER_ENV, &self.auth.oidc_issuer),
+            (oidc::OIDC_AUDIENCE_ENV, &self.auth.oidc_audience),
+            (oidc::OIDC_GROUPS_ENV, &self.auth.oidc_groups),
+            (oidc::OIDC_GROUPS_CLAIM_ENV, &self.auth.oidc_groups_claim),
+            (DOCKER_HOST_ENV, &self.docker.host),
+            (platform::DOCKER_PATH_ENV, &self.docker.path),
+            (broker::BROKER_SOCKET_ENV, &self.docker.broker),
+            (platform::PLATFORM_ENV, &self.docker.platform),
+            (platform::APP_HOST_ENV, &self.docker.app_host),
+            (docker::LABEL_PREFIX_ENV, &self.docker.label_prefix),
+            (docker::BUILD_CPUS_ENV, &self.docker.build_cpus),
+            (docker::BUILD_MEMORY_ENV, &self.docker.build_memory),
+            (docker::APP_MEMORY_ENV, &self.docker.app_memory),
+            (docker::STOP_GRACE_PERIOD_ENV, &self.docker.stop_grace_period),
+            (docker::START_ATTEMPTS_ENV, &self.docker.start_attempts),
+        ];
+
+        let mut env_settings: Vec<(String, &EnvValue)> = settings.into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| (name.to_string(), value)))
+            .collect();
+
+        env_settings.extend(self.env.iter().map(|(name, value)| (name.to_string(), value)));
+        env_settings
+    }
+}
+
+/// Reads the config file given, or the one in RUSTLESS_CONFIG, which must exist. Otherwise rustless_host.toml is
+/// read if it is in the working directory. Returns None if there is no config file
+fn read_config_file(path: Option<&Path>) -> Result<Option<ConfigFile>, String> {
+    let path = match (path, std::env::var(CONFIG_FILE_ENV)) {
+        (Some(path), _) => path.to_path_buf(),
+        (None, Ok(value)) if !value.trim().is_empty() => PathBuf::from(value.trim()),
+        _ if Path::new(DEFAULT_CONFIG_FILE).exists() => PathBuf::from(DEFAULT_CONFIG_FILE),
+        _ => return Ok(None),
+    };
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("Error reading config file {}: {}", path.display(), e)),
+    };
+
+    let config_file: ConfigFile = match toml::from_str(&contents) {
+        Ok(config_file) => config_file,
+        Err(e) => return Err(format!("Error in config file {}: {}", path.display(), e)),
+    };
+
+    // Settings in the env table are set as environment variables, so only the host's own can be set
+    if let Some(name) = config_file.env.keys().find(|name| !name.starts_with(ENV_PREFIX) || *name == CONFIG_FILE_ENV) {
+        return Err(format!("Error in config file {}: {} can't be set in the env table", path.display(), name));
+    }
+
+    println!("Using the config file {}", path.display());
+    Ok(Some(config_file))
+}
+
+/// Gets a setting from an environment variable, or the config file if the variable isn't set
+fn get_setting<T>(env: &str, file_value: Option<T>, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    match std::env::var(env) {
+        Ok(value) if !value.trim().is_empty() => match parse(value.trim()) {
+            Some(parsed) => Some(parsed),
+            None => {
+                println!("Ignoring invalid {}: {}", env, value);
+                file_value
+            }
+        },
+        _ => file_value,
+    }
+}
+
+/// Gets the directory builds run in for the docker broker, which doesn't read the host's config file. The directory
+/// given on the command line is used first, then the one in RUSTLESS_BUILD_DIR
+pub fn get_broker_build_dir(build_dir: Option<PathBuf>) -> Option<PathBuf> {
+    build_dir.or_else(|| get_setting(BUILD_DIR_ENV, None, |value| Some(PathBuf::from(value))))
+}
+
+/// Loads the host configuration from the config file and environment variables, which take priority over the file
+///
+/// Settings in the file that the host reads from environment variables, such as the docker and listener settings,
+/// are set as those variables unless they are already set, so they are read in the same way wherever they are used
+pub fn load(path: Option<&Path>) -> Result<HostConfig, String> {
+    let config_file = read_config_file(path)?.unwrap_or_default();
+
+    for (name, value) in config_file.env_settings() {
+        if std::env::var_os(&name).is_none() {
+            std::env::set_var(&name, value.to_env());
+        }
+    }
+
+    let default = HostConfig::default();
+
+    let address = get_setting(ADDRESS_ENV, config_file.address, |value| Some(value.to_string())).unwrap_or(default.address);
+    let address = match get_setting(PORT_ENV, config_file.port, |value| value.parse::<u16>().ok()) {
+        Some(port) => {
+            let mut socket_address = listeners::parse_address(&address)?;
+            socket_address.set_port(port);
+            socket_address.to_string()
+        },
+        None => address,
+    };
+
+    Ok(HostConfig {
+        address,
+        private_key_file: get_setting(TLS_KEY_FILE_ENV, config_file.tls.private_key_file, |value| Some(PathBuf::from(value))).unwrap_or(default.private_key_file),
+        certificate_file: get_setting(TLS_CERT_FILE_ENV, config_file.tls.certificate_file, |value| Some(PathBuf::from(value))).unwrap_or(default.certificate_file),
+        pid_file: default.pid_file,
+        build_dir: get_setting(BUILD_DIR_ENV, config_file.build_dir, |value| Some(PathBuf::from(value))),
+    })
+}
@@ -0,0 +1,222 @@
+use std::fs;
+
+use openssl::asn1::Asn1Time;
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use openssl::x509::X509;
+
+use crate::auth;
+use crate::broker;
+use crate::crates_cache;
+use crate::database::{self, StorageBackend};
+use crate::egress;
+use crate::grpc;
+use crate::health;
+use crate::listeners;
+use crate::migrations;
+use crate::registry;
+use crate::storage;
+use crate::{ConfigCheck, ConfigCheckStatus, HostConfig};
+
+/// How many days before the certificate expires the TLS check starts warning about it
+const CERT_EXPIRY_WARNING_DAYS: i32 = 14;
+
+/// Creates the result of a check
+fn result(name: &str, status: ConfigCheckStatus, message: String) -> ConfigCheck {
+    ConfigCheck { name: name.to_string(), status, message }
+}
+
+/// Checks the address parses, and that the port can be bound. A port held by a running host passes, as the new host
+/// binds alongside it with SO_REUSEPORT when it is upgraded
+fn check_address(config: &HostConfig) -> ConfigCheck {
+    let address = match listeners::parse_address(&config.address) {
+        Ok(address) => address,
+        Err(e) => return result("address", ConfigCheckStatus::Failed, e),
+    };
+
+    // The socket is bound but not listened on, so no connections are taken from a running host
+    match crate::bind_socket(&address) {
+        Ok(_) => result("address", ConfigCheckStatus::Passed, format!("Port {} is available on {}", address.port(), address.ip())),
+        Err(e) => result("address", ConfigCheckStatus::Failed, format!("Can't bind to {}: {}", address, e)),
+    }
+}
+
+/// Checks the private key and certificate chain can be read and loaded, that the key matches the certificate, and
+/// that the certificate hasn't expired
+fn check_tls(config: &HostConfig) -> ConfigCheck {
+    // openssl's errors for missing files are hard to read, so the files are checked for that first
+    for (description, file) in [("private key", &config.private_key_file), ("certificate chain", &config.certificate_file)] {
+        if let Err(e) = fs::File::open(file) {
+            return result("tls", ConfigCheckStatus::Failed, format!("Can't read the {} file {}: {}", description, file.display(), e));
+        }
+    }
+
+    let mut builder = match SslAcceptor::mozilla_intermediate(SslMethod::tls()) {
+        Ok(builder) => builder,
+        Err(e) => return result("tls", ConfigCheckStatus::Failed, format!("Error creating SSL builder: {}", e)),
+    };
+
+    if let Err(e) = builder.set_private_key_file(&config.private_key_file, SslFiletype::PEM) {
+        return result("tls", ConfigCheckStatus::Failed, format!("Can't load the private key file {}: {}", config.private_key_file.display(), e));
+    }
+
+    if let Err(e) = builder.set_certificate_chain_file(&config.certificate_file) {
+        return result("tls", ConfigCheckStatus::Failed, format!("Can't load the certificate chain file {}: {}", config.certificate_file.display(), e));
+    }
+
+    if builder.check_private_key().is_err() {
+        return result("tls", ConfigCheckStatus::Failed, "The private key doesn't match the certificate".to_string());
+    }
+
+    // The first certificate in the chain is the one for the host
+    let certificate = match fs::read(&config.certificate_file).map_err(|e| e.to_string()).and_then(|pem| X509::from_pem(&pem).map_err(|e| e.to_string())) {
+        Ok(certificate) => certificate,
+        Err(e) => return result("tls", ConfigCheckStatus::Failed, format!("Can't read the certificate: {}", e)),
+    };
+
+    let days_left = match Asn1Time::days_from_now(0).and_then(|now| now.diff(certificate.not_after())) {
+        Ok(diff) if diff.days < 0 || diff.secs < 0 => -1,
+        Ok(diff) => diff.days,
+        Err(e) => return result("tls", ConfigCheckStatus::Failed, format!("Can't read when the certificate expires: {}", e)),
+    };
+
+    match days_left {
+        days if days < 0 => result("tls", ConfigCheckStatus::Failed, format!("The certificate expired on {}", certificate.not_after())),
+        days if days < CERT_EXPIRY_WARNING_DAYS => result("tls", ConfigCheckStatus::Warning, format!("The certificate expires on {}", certificate.not_after())),
+        _ => result("tls", ConfigCheckStatus::Passed, format!("The private key matches the certificate, which expires on {}", certificate.not_after())),
+    }
+}
+
+/// Checks the build directory is a directory builds can write to, or notes that it will be created
+fn check_build_dir(config: &HostConfig) -> ConfigCheck {
+    let build_dir = match &config.build_dir {
+        Some(build_dir) => build_dir.to_path_buf(),
+        None => std::env::temp_dir(),
+    };
+
+    if !build_dir.exists() {
+        return result("build_dir", ConfigCheckStatus::Warning, format!("{} doesn't exist, so it will be created when the host starts", build_dir.display()));
+    }
+
+    if !build_dir.is_dir() {
+        return result("build_dir", ConfigCheckStatus::Failed, format!("{} isn't a directory", build_dir.display()));
+    }
+
+    match tempfile::tempfile_in(&build_dir) {
+        Ok(_) => result("build_dir", ConfigCheckStatus::Passed, format!("Builds can write to {}", build_dir.display())),
+        Err(e) => result("build_dir", ConfigCheckStatus::Failed, format!("Builds can't write to {}: {}", build_dir.display(), e)),
+    }
+}
+
+/// Checks the database can be opened and is up to date, without creating or upgrading it
+fn check_database() -> ConfigCheck {
+    let db_name = match database::get_storage_backend() {
+        StorageBackend::Sqlite => storage::get_db_file().display().to_string(),
+        StorageBackend::Postgres => "The Postgres database".to_string(),
+    };
+
+    let conn = match storage::open_existing_database() {
+        Ok(Some(conn)) => conn,
+        Ok(None) => return result("database", ConfigCheckStatus::Passed, format!("{} will be created when the host starts", db_name)),
+        Err(e) => return result("database", ConfigCheckStatus::Failed, format!("Can't open {}: {}", db_name, e)),
+    };
+
+    // A database upgraded by a newer host can't be used, rather than being upgraded when the host starts
+    if let Err(e) = migrations::check(&conn) {
+        return result("database", ConfigCheckStatus::Failed, format!("Can't use {}: {}", db_name, e));
+    }
+
+    match storage::check_schema(&conn) {
+        Ok(_) => result("database", ConfigCheckStatus::Passed, format!("{} is up to date", db_name)),
+        Err(e) => result("database", ConfigCheckStatus::Warning, format!("{} will be upgraded when the host starts: {}", db_name, e)),
+    }
+}
+
+/// Checks docker is reachable, through the broker if one is set
+fn check_docker() -> ConfigCheck {
+    let through = match broker::get_socket() {
+        Some(socket) => format!(" through the broker at {}", socket.display()),
+        None => String::new(),
+    };
+
+    match health::check_docker() {
+        Ok(_) => result("docker", ConfigCheckStatus::Passed, format!("Docker is reachable{}", through)),
+        Err(e) => result("docker", ConfigCheckStatus::Failed, e),
+    }
+}
+
+/// Checks docker can log in to the registry images are pushed to, if one is set
+fn check_registry() -> ConfigCheck {
+    let registry = match registry::get_registry() {
+        Some(registry) => registry,
+        None => return result("registry", ConfigCheckStatus::Passed, "No registry is set, so images are kept in the local docker".to_string()),
+    };
+
+    match registry::check_credentials(&registry) {
+        Ok(None) => result("registry", ConfigCheckStatus::Passed, format!("Docker can log in to push to {}", registry)),
+        Ok(Some(warning)) => result("registry", ConfigCheckStatus::Warning, warning),
+        Err(e) => result("registry", ConfigCheckStatus::Failed, e),
+    }
+}
+
+/// Checks the providers set up to check tokens sent to the management API can be used, such as that the key file can
+/// be read and the identity provider's signing keys can be fetched. The API keys in the database aren't checked, as
+/// that would create the database
+fn check_auth() -> Vec<ConfigCheck> {
+    // Fetching the signing keys blocks, so it runs on its own thread rather than in the runtime the host starts in
+    let checks = match std::thread::spawn(auth::check_configured_providers).join() {
+        Ok(Ok(checks)) => checks,
+        Ok(Err(e)) => return vec![result("auth", ConfigCheckStatus::Failed, e)],
+        Err(_) => return vec![result("auth", ConfigCheckStatus::Failed, "Error checking the auth providers".to_string())],
+    };
+
+    let mut results: Vec<ConfigCheck> = checks.into_iter().map(|(name, check)| match check {
+        Ok(accepts) => result("auth", ConfigCheckStatus::Passed, format!("Accepting {}", accepts)),
+        Err(e) => result("auth", ConfigCheckStatus::Failed, format!("Can't use the {}: {}", name, e)),
+    }).collect();
+
+    if auth::is_open_allowed() {
+        results.push(result("auth", ConfigCheckStatus::Warning, "RUSTLESS_OPEN_API is set, so the management API is open to anyone until an API key is created".to_string()));
+    }
+
+    results
+}
+
+/// Checks the address an optional listener is set to parses and can be bound. The listeners don't share their port
+/// with a running host, so a port in use is a warning, as it may be held by the host being replaced
+fn check_listener(name: &str, address: Option<String>) -> Option<ConfigCheck> {
+    let address = address?;
+    let socket_address = match listeners::parse_address(&address) {
+        Ok(socket_address) => socket_address,
+        Err(e) => return Some(result(name, ConfigCheckStatus::Failed, e)),
+    };
+
+    let bound = listeners::create_socket(&socket_address).and_then(|socket| socket.bind(&socket_address.into()));
+
+    Some(match bound {
+        Ok(_) => result(name, ConfigCheckStatus::Passed, format!("{} is available", socket_address)),
+        Err(e) => result(name, ConfigCheckStatus::Warning, format!("{} is in use, by a running host or another program: {}", socket_address, e)),
+    })
+}
+
+/// Checks the host configuration, returning the result of each check. Nothing is changed, so this can be run
+/// against the config for a production host before it is restarted
+pub fn check(config: &HostConfig) -> Vec<ConfigCheck> {
+    let mut checks = vec![
+        check_address(config),
+        check_tls(config),
+        check_build_dir(config),
+        check_database(),
+        check_docker(),
+        check_registry(),
+    ];
+
+    checks.extend(check_auth());
+    checks.extend([
+        check_listener("grpc_gateway", grpc::get_gateway_address()),
+        check_listener("egress_proxy", egress::get_proxy_address()),
+        check_listener("crates_cache", crates_cache::get_cache_address()),
+    ].into_iter().flatten());
+
+    checks
+}
+
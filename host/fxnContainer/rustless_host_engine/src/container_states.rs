@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::docker;
+
+/// How often the containers docker is running are refreshed
+const REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// How old the refreshed containers can be before they are no longer trusted, such as when docker can't be reached,
+/// and docker is asked directly instead
+const STALE_AFTER: Duration = Duration::from_secs(10);
+
+/// The containers docker was running when they were last refreshed, along with the apps this host has started or
+/// stopped since
+struct ContainerStates {
+    // The names of the apps with a running container, or None if they haven't been refreshed yet
+    running_apps: Option<HashSet<String>>,
+
+    // When docker was asked for the containers that were last refreshed
+    refreshed_at: Instant,
+
+    // Whether the apps started or stopped by this host are running, with when they were started or stopped. These
+    // win over a refresh that started before them, which could have missed the change
+    changes: HashMap<String, (bool, Instant)>,
+}
+
+/// The containers docker is running, refreshed in the background
+static CONTAINER_STATES: OnceLock<Mutex<ContainerStates>> = OnceLock::new();
+
+/// Gets the containers docker is running
+fn get_container_states() -> &'static Mutex<ContainerStates> {
+    CONTAINER_STATES.get_or_init(|| Mutex::new(ContainerStates {
+        running_apps: None,
+        refreshed_at: Instant::now(),
+        changes: HashMap::new(),
+    }))
+}
+
+/// Asks docker for the containers it is running, keeping them for the status endpoints. Changes made before docker
+/// was asked are in the refreshed containers, so they are forgotten
+fn refresh() {
+    let refreshed_at = Instant::now();
+    let running_apps = match docker::get_running_apps() {
+        Ok(running_apps) => running_apps,
+        Err(e) => {
+            println!("Error refreshing container states: {}", e);
+            return;
+        }
+    };
+
+    if let Ok(mut states) = get_container_states().lock() {
+        states.running_apps = Some(running_apps);
+        states.refreshed_at = refreshed_at;
+        states.changes.retain(|_, (_, changed_at)| *changed_at > refreshed_at);
+    }
+}
+
+/// Gets if the container for a function app was running when the containers were last refreshed, or None if they
+/// haven't been refreshed recently enough to trust
+fn get_cached_state(function_app_name: &String) -> Option<bool> {
+    let states = get_container_states().lock().ok()?;
+
+    if let Some((running, _)) = states.changes.get(function_app_name) {
+        return Some(*running);
+    }
+
+    match &states.running_apps {
+        Some(running_apps) if states.refreshed_at.elapsed() < STALE_AFTER => Some(running_apps.contains(function_app_name)),
+        _ => None,
+    }
+}
+
+/// Checks if the container for a function app is running, from the containers refreshed in the background. Docker
+/// is only asked directly if they haven't been refreshed recently, returning an error if docker can't be reached
+pub fn check_running(function_app_name: &String) -> Result<bool, String> {
+    match get_cached_state(function_app_name) {
+        Some(running) => Ok(running),
+        None => docker::check_container_running(function_app_name),
+    }
+}
+
+/// Gets if the container for a function app is running, in the same way as check_running. If docker can't be
+/// reached the app isn't running
+pub fn is_running(function_app_name: &String) -> bool {
+    check_running(function_app_name).unwrap_or(false)
+}
+
+/// Records that this host has started or stopped the container for a function app, so the status is right straight
+/// away rather than after the next refresh
+pub fn set_running(function_app_name: &String, running: bool) {
+    if let Ok(mut states) = get_container_states().lock() {
+        states.changes.insert(function_app_name.to_string(), (running, Instant::now()));
+    }
+}
+
+/// Starts refreshing the containers docker is running on a background thread for the life of the host, so the
+/// status of apps can be served without a call to docker for each request. Every host process refreshes its own
+/// containers, as they are only read
+pub fn start() {
+    thread::spawn(|| loop {
+        refresh();
+        thread::sleep(REFRESH_INTERVAL);
+    });
+}
@@ -0,0 +1,343 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use actix_web::{get, web, App, HttpResponse, HttpServer};
+use reqwest::{Client, StatusCode};
+use uuid::Uuid;
+
+use rustless_shared::{CratesCachePurge, CratesCacheStats};
+
+use crate::listeners;
+use crate::platform;
+
+/// The environment variable containing the address for the crates.io cache to listen on, such as 0.0.0.0:8081.
+/// When this is set, builds download the crates.io index and crates through the host, which keeps them so
+/// repeated builds are faster and still work if crates.io can't be reached
+pub const CRATES_CACHE_ENV: &str = "RUSTLESS_CRATES_CACHE";
+
+/// The environment variable containing the sparse registry URL builds download crates from, such as
+/// http://10.0.0.5:8081/index/. Set this to use an external cache, or if builds can't reach the host's
+/// cache on the address it is guessed to be on
+const CRATES_CACHE_URL_ENV: &str = "RUSTLESS_CRATES_CACHE_URL";
+
+/// The environment variable containing how many seconds a cached index file is used before it is checked
+/// with crates.io for new versions
+const INDEX_TTL_ENV: &str = "RUSTLESS_CRATES_CACHE_INDEX_TTL";
+
+/// How long cached index files are used for if the environment variable isn't set
+const DEFAULT_INDEX_TTL: Duration = Duration::from_secs(300);
+
+/// The folder the cached index files and crates are stored in
+const CACHE_DIR: &str = "crates_cache";
+
+/// The crates.io sparse index
+const UPSTREAM_INDEX: &str = "https://index.crates.io";
+
+/// Where crates.io serves crate downloads from
+const UPSTREAM_DOWNLOADS: &str = "https://static.crates.io/crates";
+
+/// How long to wait for crates.io
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The HTTP client used to call crates.io, shared so connections can be reused
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// How the cache has been used since the host started
+static STATS: OnceLock<Mutex<CratesCacheStats>> = OnceLock::new();
+
+/// Gets the address the cache listens on, or None if the cache is turned off
+pub fn get_cache_address() -> Option<String> {
+    match std::env::var(CRATES_CACHE_ENV) {
+        Ok(address) if !address.trim().is_empty() => Some(address.trim().to_string()),
+        _ => None,
+    }
+}
+
+/// Gets the sparse registry URL builds download crates from, or None if builds use crates.io directly
+///
+/// Build containers reach the host's cache through the docker bridge gateway on Linux, and through
+/// host.docker.internal under Docker Desktop
+pub fn get_build_url() -> Option<String> {
+    if let Ok(url) = std::env::var(CRATES_CACHE_URL_ENV) {
+        if !url.trim().is_empty() {
+            return Some(url.trim().to_string());
+        }
+    }
+
+    let address = get_cache_address()?;
+    let port = address.rsplit_once(':').map(|(_, port)| port).unwrap_or("80");
+    let host = match platform::get_platform().is_docker_desktop() {
+        true => "host.docker.internal",
+        false => platform::DOCKER_BRIDGE_GATEWAY,
+    };
+
+    Some(format!("http://{}:{}/index/", host, port))
+}
+
+/// Gets how long cached index files are used for before they are checked with crates.io
+fn get_index_ttl() -> Duration {
+    match std::env::var(INDEX_TTL_ENV) {
+        Ok(value) => value.parse().map(Duration::from_secs).unwrap_or(DEFAULT_INDEX_TTL),
+        Err(_) => DEFAULT_INDEX_TTL,
+    }
+}
+
+/// Gets the HTTP client used to call crates.io
+fn get_client() -> Result<&'static Client, String> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    let client = match Client::builder().timeout(UPSTREAM_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return Err(format!("Error creating HTTP client: {}", e)),
+    };
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// Updates the cache stats
+fn update_stats(update: impl FnOnce(&mut CratesCacheStats)) {
+    let stats = STATS.get_or_init(|| Mutex::new(CratesCacheStats::default()));
+    if let Ok(mut stats) = stats.lock() {
+        update(&mut stats);
+    }
+}
+
+/// Adds up the number of files and bytes in a folder and the folders inside it
+fn get_folder_usage(path: &Path) -> (u64, u64) {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return (0, 0),
+    };
+
+    let mut usage = (0, 0);
+    for entry in entries.flatten() {
+        let (files, bytes) = match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => get_folder_usage(&entry.path()),
+            Ok(metadata) => (1, metadata.len()),
+            Err(_) => (0, 0),
+        };
+        usage = (usage.0 + files, usage.1 + bytes);
+    }
+
+    usage
+}
+
+/// Gets how the cache has been used since the host started, and what it has stored
+pub fn get_stats() -> CratesCacheStats {
+    let stats = STATS.get_or_init(|| Mutex::new(CratesCacheStats::default()));
+    let mut stats = match stats.lock() {
+        Ok(stats) => stats.clone(),
+        Err(_) => CratesCacheStats::default(),
+    };
+
+    let (cached_files, cached_bytes) = get_folder_usage(Path::new(CACHE_DIR));
+    stats.enabled = get_cache_address().is_some();
+    stats.build_url = get_build_url();
+    stats.cached_files = cached_files;
+    stats.cached_bytes = cached_bytes;
+    stats
+}
+
+/// Removes everything from the cache, so it is downloaded from crates.io again
+pub fn purge() -> Result<CratesCachePurge, String> {
+    let (files_removed, bytes_removed) = get_folder_usage(Path::new(CACHE_DIR));
+
+    if Path::new(CACHE_DIR).exists() {
+        if let Err(e) = fs::remove_dir_all(CACHE_DIR) {
+            return Err(format!("Error purging crates cache: {}", e));
+        }
+    }
+
+    println!("Purged crates cache of {} files, {} bytes", files_removed, bytes_removed);
+    Ok(CratesCachePurge { files_removed, bytes_removed })
+}
+
+/// Checks a path from a request only uses the characters crates.io uses for index paths and crate names and
+/// versions, so it can't be used to read or write outside the cache folder
+fn is_safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && !path.split('/').any(|part| part.is_empty() || part == "." || part == "..")
+        && path.chars().all(|c| c.is_ascii_alphanumeric() || ['-', '_', '.', '+', '/'].contains(&c))
+}
+
+/// Writes a file to the cache. The file is written alongside and then moved into place, so a build reading
+/// the file at the same time never sees part of it
+fn write_cache_file(path: &Path, content: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            return Err(format!("Error creating cache folder: {}", e));
+        }
+    }
+
+    let temp_path = path.with_extension(format!("tmp-{}", Uuid::new_v4()));
+    if let Err(e) = fs::write(&temp_path, content) {
+        return Err(format!("Error writing cache file: {}", e));
+    }
+
+    if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Error writing cache file: {}", e));
+    }
+
+    Ok(())
+}
+
+/// Gets if a cached file was written recently enough to use without checking with crates.io
+fn is_fresh(path: &Path, ttl: Duration) -> bool {
+    let modified = match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return false,
+    };
+
+    SystemTime::now().duration_since(modified).map(|age| age < ttl).unwrap_or(true)
+}
+
+/// Downloads a file from crates.io, returning None if it doesn't exist
+async fn fetch_upstream(url: &str) -> Result<Option<Vec<u8>>, String> {
+    let client = get_client()?;
+
+    let res = match client.get(url).send().await {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error calling crates.io: {}", e)),
+    };
+
+    match res.status() {
+        StatusCode::OK => match res.bytes().await {
+            Ok(bytes) => Ok(Some(bytes.to_vec())),
+            Err(e) => Err(format!("Error reading response from crates.io: {}", e)),
+        },
+        StatusCode::NOT_FOUND | StatusCode::GONE => Ok(None),
+        status => Err(format!("crates.io returned status code: {}", status)),
+    }
+}
+
+/// Serves a file to a build, counting the bytes
+fn serve_file(content: Vec<u8>, content_type: &str) -> HttpResponse {
+    let length = content.len() as u64;
+    update_stats(|stats| stats.bytes_served += length);
+    HttpResponse::Ok().content_type(content_type.to_string()).body(content)
+}
+
+/// The index configuration, which tells cargo to download crates through the cache too
+#[get("/index/config.json")]
+async fn get_index_config() -> HttpResponse {
+    let dl = match get_build_url() {
+        Some(url) => format!("{}/crates", url.trim_end_matches('/').trim_end_matches("/index")),
+        None => UPSTREAM_DOWNLOADS.to_string(),
+    };
+
+    HttpResponse::Ok().json(serde_json::json!({ "dl": dl }))
+}
+
+/// Serves a crates.io index file, from the cache if it was checked recently. If crates.io can't be reached,
+/// the cached file is served however old it is, so builds keep working
+#[get("/index/{path:.*}")]
+async fn get_index_file(path: web::Path<String>) -> HttpResponse {
+    if !is_safe_path(&path) {
+        return HttpResponse::BadRequest().body("Invalid index path");
+    }
+
+    let cache_path = PathBuf::from(CACHE_DIR).join("index").join(path.as_str());
+    if is_fresh(&cache_path, get_index_ttl()) {
+        if let Ok(content) = fs::read(&cache_path) {
+            update_stats(|stats| stats.index_hits += 1);
+            return serve_file(content, "text/plain");
+        }
+    }
+
+    match fetch_upstream(&format!("{}/{}", UPSTREAM_INDEX, path)).await {
+        Ok(Some(content)) => {
+            update_stats(|stats| stats.index_misses += 1);
+            if let Err(e) = write_cache_file(&cache_path, &content) {
+                println!("Crates cache: {}", e);
+            }
+            serve_file(content, "text/plain")
+        },
+        Ok(None) => {
+            // The crate no longer exists, so forget it
+            let _ = fs::remove_file(&cache_path);
+            HttpResponse::NotFound().finish()
+        },
+        Err(e) => {
+            println!("Crates cache: {}", e);
+            update_stats(|stats| stats.upstream_errors += 1);
+
+            match fs::read(&cache_path) {
+                Ok(content) => {
+                    update_stats(|stats| stats.stale_served += 1);
+                    serve_file(content, "text/plain")
+                },
+                Err(_) => HttpResponse::BadGateway().body(e),
+            }
+        }
+    }
+}
+
+/// Serves a crate download. Published crates never change, so once a crate is cached it is always served
+/// from the cache
+#[get("/crates/{name}/{version}/download")]
+async fn download_crate(path: web::Path<(String, String)>) -> HttpResponse {
+    let (name, version) = path.into_inner();
+    if !is_safe_path(&name) || !is_safe_path(&version) || name.contains('/') || version.contains('/') {
+        return HttpResponse::BadRequest().body("Invalid crate");
+    }
+
+    let file_name = format!("{}-{}.crate", name, version);
+    let cache_path = PathBuf::from(CACHE_DIR).join("crates").join(&name).join(&file_name);
+    if let Ok(content) = fs::read(&cache_path) {
+        update_stats(|stats| stats.crate_hits += 1);
+        return serve_file(content, "application/octet-stream");
+    }
+
+    match fetch_upstream(&format!("{}/{}/{}", UPSTREAM_DOWNLOADS, name, file_name)).await {
+        Ok(Some(content)) => {
+            update_stats(|stats| stats.crate_misses += 1);
+            if let Err(e) = write_cache_file(&cache_path, &content) {
+                println!("Crates cache: {}", e);
+            }
+            serve_file(content, "application/octet-stream")
+        },
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            println!("Crates cache: {}", e);
+            update_stats(|stats| stats.upstream_errors += 1);
+            HttpResponse::BadGateway().body(e)
+        }
+    }
+}
+
+/// Starts the crates.io cache if it is turned on. The cache is served over plain HTTP on its own address,
+/// as builds can't check the host's certificate
+pub fn start() -> Result<(), String> {
+    let address = match get_cache_address() {
+        Some(address) => address,
+        None => return Ok(()),
+    };
+
+    let listener = match listeners::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => return Err(format!("Error starting crates cache on {}: {}", address, e)),
+    };
+
+    let server = HttpServer::new(|| {
+        App::new().service(get_index_config)
+                  .service(get_index_file)
+                  .service(download_crate)
+    })
+    .workers(2)
+    .listen(listener);
+
+    let server = match server {
+        Ok(server) => server.run(),
+        Err(e) => return Err(format!("Error starting crates cache on {}: {}", address, e)),
+    };
+
+    println!("Crates cache listening on {}, builds use {}", address, get_build_url().unwrap_or_default());
+    actix_web::rt::spawn(server);
+
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use r2d2::{ManageConnection, Pool, PooledConnection};
+use rusqlite::types::ToSql;
+use rustless_shared::{FunctionApp, FunctionAppStatus};
+use uuid::Uuid;
+
+use crate::database::{Connection, QueryRows, SqlBackend, StorageBackend};
+use crate::postgres_storage::PostgresStorage;
+use crate::storage::{self, Storage};
+
+/// The environment variable containing the most connections kept open to each database file, or to the Postgres database
+pub const DB_POOL_SIZE_ENV: &str = "RUSTLESS_DB_POOL_SIZE";
+
+/// The most connections kept open to each database file if the environment variable isn't set
+const DEFAULT_DB_POOL_SIZE: u32 = 8;
+
+/// The most connections kept open to each database file, read from the environment once
+static DB_POOL_SIZE: OnceLock<u32> = OnceLock::new();
+
+/// How long to wait for a connection when all the connections to a database file are in use
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a connection waits for another connection to finish writing before failing with database is locked.
+/// Without this, pooled connections writing at the same time fail straight away
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to the database taken from its pool. It goes back to the pool when it is dropped
+pub type DbConnection = Connection;
+
+/// The pools shared by the server and the background jobs, created the first time a database is connected to
+static POOLS: OnceLock<Arc<DbPools>> = OnceLock::new();
+
+/// The connection pools for the databases the host uses. The server keeps these in its app data, and the background
+/// jobs share them, so every connection to a database comes from the same pool
+#[derive(Default)]
+pub struct DbPools {
+    // The pool for each database file that has been connected to, by the path to the file. The main database and
+    // each namespace database have their own pool
+    sqlite: Mutex<HashMap<PathBuf, Pool<SqliteConnectionManager>>>,
+
+    // The pool for the Postgres database, once it has been connected to
+    postgres: Mutex<Option<Pool<PostgresConnectionManager>>>,
+}
+
+/// Opens the connections to a database file for its pool, unlocking them if the database is encrypted
+#[derive(Debug)]
+pub struct SqliteConnectionManager {
+    // The database file
+    path: PathBuf,
+}
+
+impl ManageConnection for SqliteConnectionManager {
+    type Connection = rusqlite::Connection;
+    type Error = io::Error;
+
+    fn connect(&self) -> Result<rusqlite::Connection, io::Error> {
+        let conn = storage::open_database(&self.path).map_err(io::Error::other)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(io::Error::other)?;
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut rusqlite::Connection) -> Result<(), io::Error> {
+        conn.query_row("SELECT 1", [], |_| Ok(())).map_err(io::Error::other)
+    }
+
+    fn has_broken(&self, _conn: &mut rusqlite::Connection) -> bool {
+        false
+    }
+}
+
+/// Opens the connections to the Postgres database for its pool
+#[derive(Debug)]
+pub struct PostgresConnectionManager {
+    // The connection string for the database
+    url: String,
+}
+
+impl ManageConnection for PostgresConnectionManager {
+    type Connection = PostgresStorage;
+    type Error = io::Error;
+
+    fn connect(&self) -> Result<PostgresStorage, io::Error> {
+        PostgresStorage::connect(&self.url).map_err(io::Error::other)
+    }
+
+    fn is_valid(&self, conn: &mut PostgresStorage) -> Result<(), io::Error> {
+        conn.query("SELECT 1", &[]).map(|_| ()).map_err(io::Error::other)
+    }
+
+    fn has_broken(&self, conn: &mut PostgresStorage) -> bool {
+        conn.is_closed()
+    }
+}
+
+impl<M> SqlBackend for PooledConnection<M>
+where
+    M: ManageConnection,
+    M::Connection: SqlBackend,
+{
+    fn backend(&self) -> StorageBackend {
+        SqlBackend::backend(&**self)
+    }
+
+    fn execute(&self, sql: &str, params: &[&dyn ToSql]) -> rusqlite::Result<usize> {
+        SqlBackend::execute(&**self, sql, params)
+    }
+
+    fn query(&self, sql: &str, params: &[&dyn ToSql]) -> rusqlite::Result<QueryRows> {
+        SqlBackend::query(&**self, sql, params)
+    }
+
+    fn prepare(&self, sql: &str) -> rusqlite::Result<()> {
+        SqlBackend::prepare(&**self, sql)
+    }
+
+    fn begin(&self) -> rusqlite::Result<()> {
+        SqlBackend::begin(&**self)
+    }
+
+    fn restore(&mut self, backup_file: &Path) -> rusqlite::Result<()> {
+        SqlBackend::restore(&mut **self, backup_file)
+    }
+}
+
+impl<M> Storage for PooledConnection<M>
+where
+    M: ManageConnection,
+    M::Connection: Storage,
+{
+    fn get_all_apps(&self) -> rusqlite::Result<Vec<FunctionApp>> {
+        Storage::get_all_apps(&**self)
+    }
+
+    fn get_status(&self, id: &Uuid) -> rusqlite::Result<FunctionAppStatus> {
+        Storage::get_status(&**self, id)
+    }
+
+    fn set_status(&self, id: &Uuid, status: &FunctionAppStatus) -> rusqlite::Result<bool> {
+        Storage::set_status(&**self, id, status)
+    }
+
+    fn set_running(&self, id: &Uuid, port: u16) -> rusqlite::Result<bool> {
+        Storage::set_running(&**self, id, port)
+    }
+}
+
+/// Gets the most connections kept open to each database file, or to the Postgres database
+pub fn get_pool_size() -> u32 {
+    *DB_POOL_SIZE.get_or_init(|| match std::env::var(DB_POOL_SIZE_ENV) {
+        Ok(value) => match value.trim().parse::<u32>() {
+            Ok(size) if size > 0 => size,
+            _ => {
+                println!("Ignoring invalid {}: {}", DB_POOL_SIZE_ENV, value);
+                DEFAULT_DB_POOL_SIZE
+            }
+        },
+        Err(_) => DEFAULT_DB_POOL_SIZE,
+    })
+}
+
+/// Gets the connection pools shared by the server and the background jobs
+pub fn get_pools() -> Arc<DbPools> {
+    POOLS.get_or_init(|| Arc::new(DbPools::default())).clone()
+}
+
+/// Creates the pool for a database file, running the setup on a connection first, such as to migrate the database.
+/// The first connection is opened directly so a database that can't be opened, such as with the wrong key, fails
+/// straight away rather than after the pool times out
+fn create_pool(path: &Path, setup: fn(&mut Connection) -> Result<(), String>) -> Result<Pool<SqliteConnectionManager>, String> {
+    let manager = SqliteConnectionManager { path: path.to_path_buf() };
+    let mut conn = match manager.connect() {
+        Ok(conn) => Connection::new(conn),
+        Err(e) => return Err(e.to_string()),
+    };
+    setup(&mut conn)?;
+    drop(conn);
+
+    // Connections are only opened as they are needed, so a namespace that is rarely used doesn't hold any open
+    Ok(Pool::builder()
+        .max_size(get_pool_size())
+        .min_idle(Some(0))
+        .connection_timeout(CONNECTION_TIMEOUT)
+        .build_unchecked(manager))
+}
+
+impl DbPools {
+    /// Gets a connection to a database file from its pool, creating the pool the first time the file is connected to.
+    /// The setup is only run when the pool is created, and the pools are locked while it runs so two requests can't
+    /// upgrade the same database at the same time
+    pub fn get_connection(&self, path: &Path, setup: fn(&mut Connection) -> Result<(), String>) -> Result<DbConnection, String> {
+        let pool = {
+            let mut pools = match self.sqlite.lock() {
+                Ok(pools) => pools,
+                Err(e) => return Err(format!("Error getting database pools: {}", e)),
+            };
+
+            match pools.get(path) {
+                Some(pool) => pool.clone(),
+                None => {
+                    let pool = create_pool(path, setup)?;
+                    pools.insert(path.to_path_buf(), pool.clone());
+                    pool
+                }
+            }
+        };
+
+        match pool.get() {
+            Ok(conn) => Ok(Connection::new(conn)),
+            Err(e) => Err(format!("Error getting database connection: {}", e)),
+        }
+    }
+
+    /// Gets a connection to the Postgres database from its pool, creating the pool the first time the database is
+    /// connected to. As with database files, the setup is only run when the pool is created
+    pub fn get_postgres_connection(&self, url: &str, setup: fn(&mut Connection) -> Result<(), String>) -> Result<DbConnection, String> {
+        let pool = {
+            let mut pool = match self.postgres.lock() {
+                Ok(pool) => pool,
+                Err(e) => return Err(format!("Error getting database pool: {}", e)),
+            };
+
+            match &*pool {
+                Some(pool) => pool.clone(),
+                None => {
+                    let manager = PostgresConnectionManager { url: url.to_string() };
+                    let mut conn = match manager.connect() {
+                        Ok(conn) => Connection::new(conn),
+                        Err(e) => return Err(e.to_string()),
+                    };
+                    setup(&mut conn)?;
+                    drop(conn);
+
+                    let created = Pool::builder()
+                        .max_size(get_pool_size())
+                        .min_idle(Some(0))
+                        .connection_timeout(CONNECTION_TIMEOUT)
+                        .build_unchecked(manager);
+                    *pool = Some(created.clone());
+                    created
+                }
+            }
+        };
+
+        match pool.get() {
+            Ok(conn) => Ok(Connection::new(conn)),
+            Err(e) => Err(format!("Error getting database connection: {}", e)),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use uuid::Uuid;
+
+use rustless_shared::{EffectiveConfig, EffectiveSetting, NamespaceDefaults, SettingSource};
+
+use crate::database::Connection;
+use crate::egress;
+use crate::gateway;
+use crate::replicas;
+use crate::storage;
+
+/// Checks the defaults for a namespace are valid before they are saved, returning them with the allowlist tidied up
+pub fn validate_defaults(defaults: &NamespaceDefaults) -> Result<NamespaceDefaults, String> {
+    // SQLite stores integers as i64, so larger thresholds can't be saved
+    let max_threshold = i64::MAX as u64;
+    if defaults.buffer_request_threshold.unwrap_or(0) > max_threshold || defaults.buffer_response_threshold.unwrap_or(0) > max_threshold {
+        return Err(format!("Thresholds must be at most {} bytes", max_threshold));
+    }
+
+    let max_replicas = replicas::get_max_replicas();
+    match defaults.max_replicas {
+        Some(0) => return Err("The most replicas an app can run must be at least 1".to_string()),
+        Some(replicas) if replicas > max_replicas => return Err(format!(
+            "Invalid max replicas {}: the most an app can run on this host is {}",
+            replicas, max_replicas
+        )),
+        _ => {},
+    }
+
+    let egress_allowlist = match &defaults.egress_allowlist {
+        Some(allowlist) => Some(egress::validate_allowlist(allowlist)?),
+        None => None,
+    };
+
+    Ok(NamespaceDefaults { egress_allowlist, ..defaults.clone() })
+}
+
+/// Picks the value an app uses for a setting: the value set on the app, then the default for its namespace, then
+/// the host default
+fn resolve<T>(app: Option<T>, namespace: Option<T>, host: T) -> EffectiveSetting<T> {
+    match (app, namespace) {
+        (Some(value), _) => EffectiveSetting { value, source: SettingSource::App },
+        (None, Some(value)) => EffectiveSetting { value, source: SettingSource::Namespace },
+        (None, None) => EffectiveSetting { value: host, source: SettingSource::Host },
+    }
+}
+
+/// Gets the settings the function app with the given ID uses, from the settings made on it, the defaults for its
+/// namespace, and the host defaults
+pub fn get_effective_config(conn: &Connection, id: &Uuid) -> Result<EffectiveConfig, String> {
+    let (_, namespace) = match storage::get_function_app_name_and_namespace(conn, id) {
+        Ok(name_and_namespace) => name_and_namespace,
+        Err(e) => return Err(format!("Error getting function app namespace: {}", e)),
+    };
+
+    let defaults = match storage::get_namespace_defaults(conn, &namespace) {
+        Ok(defaults) => defaults,
+        Err(e) => return Err(format!("Error getting defaults for namespace {}: {}", namespace, e)),
+    };
+
+    let (request_threshold, response_threshold) = match storage::get_function_app_buffering(conn, id) {
+        Ok(thresholds) => thresholds,
+        Err(e) => return Err(format!("Error getting buffering thresholds: {}", e)),
+    };
+
+    let egress_allowlist = match storage::get_function_app_egress_allowlist(conn, id) {
+        Ok(allowlist) => allowlist,
+        Err(e) => return Err(format!("Error getting egress allowlist: {}", e)),
+    };
+
+    // An app without its own allowlist uses the namespace's, and can call any destination if the namespace doesn't
+    // have one either
+    let egress_allowlist = resolve(egress_allowlist.map(Some), defaults.egress_allowlist.map(Some), None);
+
+    let default_threshold = gateway::get_default_buffer_threshold();
+    Ok(EffectiveConfig {
+        buffer_request_threshold: resolve(request_threshold, defaults.buffer_request_threshold, default_threshold),
+        buffer_response_threshold: resolve(response_threshold, defaults.buffer_response_threshold, default_threshold),
+        egress_allowlist,
+        max_replicas: resolve(None, defaults.max_replicas, replicas::get_max_replicas()),
+        namespace,
+    })
+}
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+
+use rustless_shared::{AppStartResult, BulkStartReport, FunctionAppStatus};
+
+use crate::docker;
+use crate::gateway;
+use crate::replicas;
+use crate::scaling;
+use crate::storage;
+
+/// The environment variable that turns on starting apps again when the host starts. When set to 1 or true, apps
+/// that were running when the host stopped but whose containers have gone, such as after the machine restarted,
+/// are started again in dependency order
+const RESTART_ON_STARTUP_ENV: &str = "RUSTLESS_RESTART_ON_STARTUP";
+
+/// Gets if apps that were running when the host stopped are started again when it starts
+fn is_restart_on_startup() -> bool {
+    match std::env::var(RESTART_ON_STARTUP_ENV) {
+        Ok(value) => value == "1" || value.eq_ignore_ascii_case("true"),
+        Err(_) => false,
+    }
+}
+
+/// Finds a path of dependencies from one app to another, such as a -> b -> c, or None if there isn't one
+fn find_path(from: &String, to: &String, dependencies: &HashMap<String, Vec<String>>, visited: &mut HashSet<String>) -> Option<Vec<String>> {
+    if from == to {
+        return Some(vec![to.to_string()]);
+    }
+
+    if !visited.insert(from.to_string()) {
+        return None;
+    }
+
+    for dependency in dependencies.get(from).into_iter().flatten() {
+        if let Some(mut path) = find_path(dependency, to, dependencies, visited) {
+            path.insert(0, from.to_string());
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+/// Checks the apps a function app depends on exist and don't depend on it, returning them without duplicates
+pub fn validate_dependencies(function_app_name: &String, depends_on: &[String]) -> Result<Vec<String>, String> {
+    let names: HashSet<String> = storage::get_all_apps()?.into_iter().map(|app| app.name).collect();
+
+    let mut validated: Vec<String> = Vec::new();
+    for dependency in depends_on.iter().map(|dependency| dependency.trim().to_string()) {
+        if dependency.is_empty() {
+            return Err("Dependencies can't be empty".to_string());
+        }
+
+        if &dependency == function_app_name {
+            return Err(format!("{} can't depend on itself", function_app_name));
+        }
+
+        if !names.contains(&dependency) {
+            return Err(format!("No function app with name {} found", dependency));
+        }
+
+        if !validated.contains(&dependency) {
+            validated.push(dependency);
+        }
+    }
+
+    // Starting an app in a cycle would wait forever for the others, so cycles are refused
+    let mut dependencies = storage::get_all_dependencies()?;
+    dependencies.insert(function_app_name.to_string(), validated.clone());
+
+    for dependency in validated.iter() {
+        if let Some(path) = find_path(dependency, function_app_name, &dependencies, &mut HashSet::new()) {
+            return Err(format!("The dependencies would make a cycle: {} -> {}", function_app_name, path.join(" -> ")));
+        }
+    }
+
+    Ok(validated)
+}
+
+/// Gets the names of the apps that depend on a function app
+pub fn get_dependents(function_app_name: &String) -> Result<Vec<String>, String> {
+    let mut dependents: Vec<String> = storage::get_all_dependencies()?
+        .into_iter()
+        .filter(|(_, depends_on)| depends_on.contains(function_app_name))
+        .map(|(name, _)| name)
+        .collect();
+
+    dependents.sort();
+    Ok(dependents)
+}
+
+/// Adds an app to the start order after the apps it depends on
+fn add_to_start_order(name: &String, dependencies: &HashMap<String, Vec<String>>, visiting: &mut Vec<String>, order: &mut Vec<String>) -> Result<(), String> {
+    if order.contains(name) {
+        return Ok(());
+    }
+
+    if visiting.contains(name) {
+        visiting.push(name.to_string());
+        return Err(format!("The dependencies make a cycle: {}", visiting.join(" -> ")));
+    }
+
+    visiting.push(name.to_string());
+    for dependency in dependencies.get(name).into_iter().flatten() {
+        add_to_start_order(dependency, dependencies, visiting, order)?;
+    }
+    visiting.pop();
+
+    order.push(name.to_string());
+    Ok(())
+}
+
+/// Gets the order to start the given apps and the apps they depend on, so each app starts after its dependencies
+fn get_start_order(names: &[String], dependencies: &HashMap<String, Vec<String>>) -> Result<Vec<String>, String> {
+    let mut order = Vec::new();
+    for name in names {
+        add_to_start_order(name, dependencies, &mut Vec::new(), &mut order)?;
+    }
+
+    Ok(order)
+}
+
+/// Starts a function app if it isn't running, returning the port it runs on and whether it was already running
+///
+/// The app is started in the same way as through the API, so the same checks apply
+fn start_app(function_app_name: &String) -> Result<(u16, bool), String> {
+    // An app that was deleted can still be named as a dependency, so it gets the same error as an unknown app
+    let not_found = |_| format!("No function app with name {} found", function_app_name);
+    let conn = storage::create_connection_for_app_name(function_app_name).map_err(not_found)?;
+    let id = storage::get_function_id_from_name(&conn, function_app_name).map_err(|e| not_found(e.to_string()))?;
+
+    if let Some(port) = storage::get_function_app_port(&conn, &id).map_err(|e| e.to_string())? {
+        return Ok((port, true));
+    }
+
+    let res = crate::start_function_app_impl(&conn, id);
+    if !res.status().is_success() {
+        return Err(scaling::get_response_error(res));
+    }
+
+    match storage::get_function_app_port(&conn, &id).map_err(|e| e.to_string())? {
+        Some(port) => Ok((port, false)),
+        None => Err("The app isn't running after it was started".to_string()),
+    }
+}
+
+/// Starts a function app if it isn't running, then waits for it to pass its health check if apps depend on it.
+/// Returns whether it was already running
+async fn start_and_wait(function_app_name: &String, wait: bool) -> Result<bool, String> {
+    let name = function_app_name.to_string();
+    let (port, already_running) = match actix_web::rt::task::spawn_blocking(move || start_app(&name)).await {
+        Ok(started) => started?,
+        Err(e) => return Err(format!("Error starting {}: {}", function_app_name, e)),
+    };
+
+    if wait {
+        gateway::wait_until_healthy(port).await?;
+    }
+
+    Ok(already_running)
+}
+
+/// Starts the apps a function app depends on that aren't running, each after the apps it depends on pass their
+/// health check, then waits for them all to pass theirs so the app can be started
+pub async fn start_dependencies(function_app_name: &String) -> Result<(), String> {
+    let dependencies = storage::get_all_dependencies()?;
+    let order = get_start_order(std::slice::from_ref(function_app_name), &dependencies)?;
+
+    for dependency in order.iter().filter(|name| *name != function_app_name) {
+        match start_and_wait(dependency, true).await {
+            Ok(true) => {},
+            Ok(false) => println!("Started {} as {} depends on it", dependency, function_app_name),
+            Err(e) => return Err(format!("{} depends on {}, which couldn't be started: {}", function_app_name, dependency, e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts the given function apps, or every app that is ready to start if none are given, along with the apps they
+/// depend on. Each app is started once the apps it depends on have passed their health check, and apps whose
+/// dependencies couldn't be started are skipped
+pub async fn start_apps(names: &[String]) -> Result<BulkStartReport, String> {
+    let apps = storage::get_all_apps()?;
+
+    let names: Vec<String> = match names.is_empty() {
+        true => apps.iter()
+            .filter(|app| app.status as u8 == FunctionAppStatus::Ready as u8)
+            .map(|app| app.name.to_string())
+            .collect(),
+        false => names.to_vec(),
+    };
+
+    if let Some(name) = names.iter().find(|name| !apps.iter().any(|app| &app.name == *name)) {
+        return Err(format!("No function app with name {} found", name));
+    }
+
+    let dependencies = storage::get_all_dependencies()?;
+    let order = get_start_order(&names, &dependencies)?;
+
+    // Only apps that others are waiting on need to pass their health check before the next app starts
+    let needed: HashSet<&String> = order.iter().flat_map(|name| dependencies.get(name).into_iter().flatten()).collect();
+
+    let mut failed: HashSet<String> = HashSet::new();
+    let mut report = BulkStartReport { apps: Vec::new() };
+
+    for name in order.iter() {
+        let failed_dependency = dependencies.get(name).into_iter().flatten().find(|dependency| failed.contains(*dependency));
+
+        let result = match failed_dependency {
+            Some(dependency) => Err(format!("{}, which it depends on, couldn't be started", dependency)),
+            None => start_and_wait(name, needed.contains(name)).await,
+        };
+
+        report.apps.push(match result {
+            Ok(already_running) => AppStartResult { name: name.to_string(), already_running, error: None },
+            Err(e) => {
+                println!("Error starting {}: {}", name, e);
+                failed.insert(name.to_string());
+                AppStartResult { name: name.to_string(), already_running: false, error: Some(e) }
+            },
+        });
+    }
+
+    Ok(report)
+}
+
+/// Finds the apps that were running when the host stopped but whose containers have gone, marking them as ready so
+/// they aren't recorded as crashed while they are started again
+fn take_stopped_apps() -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+
+    for app in storage::get_all_apps()?.into_iter().filter(|app| app.status as u8 == FunctionAppStatus::Running as u8) {
+        // If docker can't be reached nothing is known about the containers, so the app is left alone
+        if docker::check_container_running(&app.name)? {
+            continue;
+        }
+
+        let conn = storage::create_connection_for_app(&app.id)?;
+        storage::set_function_app_status(&conn, &app.id, &FunctionAppStatus::Ready).map_err(|e| e.to_string())?;
+        replicas::clear(&conn, &app.id)?;
+
+        println!("{} was running when the host stopped, so it will be started again", app.name);
+        names.push(app.name);
+    }
+
+    Ok(names)
+}
+
+/// Starts the apps that were running when the host stopped again, in dependency order, if this is turned on with
+/// RUSTLESS_RESTART_ON_STARTUP. This runs in the background so the host can take requests while the apps start
+pub fn restart_on_startup() {
+    if !is_restart_on_startup() {
+        return;
+    }
+
+    let names = match take_stopped_apps() {
+        Ok(names) if names.is_empty() => return,
+        Ok(names) => names,
+        Err(e) => {
+            println!("Error finding the apps to start again: {}", e);
+            return;
+        }
+    };
+
+    actix_web::rt::spawn(async move {
+        match start_apps(&names).await {
+            Ok(report) => {
+                let started = report.apps.iter().filter(|app| app.error.is_none()).count();
+                println!("Started {} of {} apps that were running when the host stopped, with their dependencies", started, report.apps.len());
+            },
+            Err(e) => println!("Error starting the apps that were running when the host stopped: {}", e),
+        }
+    });
+}
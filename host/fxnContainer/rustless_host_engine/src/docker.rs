@@ -1,42 +1,349 @@
-use std::{process::Command};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Child, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
+use actix_rt::Arbiter;
+use bollard::container::{Config, CreateContainerOptions, ListContainersOptions, LogsOptions, MemoryStatsStats, RemoveContainerOptions, StartContainerOptions, Stats, StatsOptions};
+use bollard::image::ListImagesOptions;
+use bollard::models::{HostConfig, PortBinding};
+use bollard::{Docker, API_DEFAULT_VERSION};
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use futures::StreamExt;
 use portpicker::pick_unused_port;
-use rust_embed::RustEmbed;
+use tokio::runtime::Runtime;
+use serde::{Deserialize, Serialize};
 use tempfile::TempDir;
+use uuid::Uuid;
 
-/// Files from the Container folder
-#[derive(RustEmbed)]
-#[folder = "container/"]
-struct ContainerFolder;
+use rustless_shared::LOGS_ERROR_EVENT;
 
-/// Gets if a docker container is running
-pub fn is_container_running(function_app_name: &String) -> bool {
-    let tag = get_container_tag(function_app_name);
+use crate::broker::{self, BrokerProcess, BrokerRequest};
+use crate::build_logs;
+use crate::build_queue;
+use crate::crates_cache;
+use crate::database::Connection;
+use crate::egress;
+use crate::phases::DeployPhase;
+use crate::platform;
+use crate::quotas;
+use crate::storage;
 
-    let output = Command::new("docker")
-        .arg("ps")
-        .output();
-    
-    let output = match output {
-        Ok(output) => output,
-        Err(_) => return false
+/// The name of the buildx builder used to build function apps
+const BUILDER_NAME: &str = "rustless-builder";
+
+/// The environment variable containing the number of CPUs a build can use, such as 1.5
+pub const BUILD_CPUS_ENV: &str = "RUSTLESS_BUILD_CPUS";
+
+/// The environment variable containing the memory a build can use, such as 2g
+pub const BUILD_MEMORY_ENV: &str = "RUSTLESS_BUILD_MEMORY";
+
+/// The relative CPU weight given to builds. Containers default to 1024, so when the CPU is busy
+/// running apps get four times the CPU time of a build
+const BUILD_CPU_SHARES: u32 = 256;
+
+/// The period CPU quotas are measured over, in microseconds. This is the docker default
+const CPU_PERIOD: u32 = 100000;
+
+/// What buildx prints in its plain progress output when it starts exporting the built image, which is when
+/// compiling has finished
+const EXPORT_STARTED_MARKER: &str = " exporting to ";
+
+/// How often a running build checks if it has been cancelled or timed out
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The environment variable containing how long apps get to finish requests in flight when they are stopped, in seconds
+pub const STOP_GRACE_PERIOD_ENV: &str = "RUSTLESS_STOP_GRACE_PERIOD";
+
+/// How long apps get to finish requests in flight when they are stopped if the environment variable isn't set
+const DEFAULT_STOP_GRACE_PERIOD_SECS: u64 = 30;
+
+/// Apps are told to finish shutting down this many seconds before the grace period ends, so they can exit
+/// before docker kills them
+const SHUTDOWN_MARGIN_SECS: u64 = 2;
+
+/// The environment variable apps built with rustless_app read their shutdown timeout from
+const SHUTDOWN_TIMEOUT_ENV: &str = "RUSTLESS_SHUTDOWN_TIMEOUT";
+
+/// The environment variable containing the memory each app container can use, such as 256m. Apps can use all the
+/// memory of the machine if it isn't set
+pub const APP_MEMORY_ENV: &str = "RUSTLESS_APP_MEMORY";
+
+/// The exit code of a container docker killed because it didn't stop within the grace period, 128 + SIGKILL
+const KILLED_EXIT_CODE: i32 = 137;
+
+/// The exit code of a container sent SIGTERM that exited because of it, 128 + SIGTERM
+const TERMINATED_EXIT_CODE: i32 = 143;
+
+/// The exit code of a container that crashed reading or writing memory it doesn't own, 128 + SIGSEGV
+const SEGFAULT_EXIT_CODE: i32 = 139;
+
+/// The exit code of a container that aborted, such as a Rust app built to abort on panic, 128 + SIGABRT
+const ABORTED_EXIT_CODE: i32 = 134;
+
+/// The exit code of a Rust app whose main thread panicked
+const PANIC_EXIT_CODE: i32 = 101;
+
+/// The error docker gives when the port an app is published on is in use
+const PORT_ALLOCATED_ERROR: &str = "port is already allocated";
+
+/// The environment variable containing how many ports are tried when starting an app before giving up
+pub const START_ATTEMPTS_ENV: &str = "RUSTLESS_START_ATTEMPTS";
+
+/// How many ports are tried when starting an app if the environment variable isn't set
+const DEFAULT_START_ATTEMPTS: u32 = 3;
+
+/// The environment variable containing the prefix for the labels put on the images and containers for apps, such as
+/// com.example.rustless. Changing it means containers started with the old prefix are no longer found
+pub const LABEL_PREFIX_ENV: &str = "RUSTLESS_LABEL_PREFIX";
+
+/// The prefix for the labels if the environment variable isn't set
+const DEFAULT_LABEL_PREFIX: &str = "rustless";
+
+/// The label holding the app name, so containers can be traced back to their app
+const APP_LABEL: &str = "app";
+
+/// The label holding the app ID
+const APP_ID_LABEL: &str = "app-id";
+
+/// The label holding the namespace the app belongs to
+const NAMESPACE_LABEL: &str = "namespace";
+
+/// The label holding the number of the deployment the image was built for
+const DEPLOYMENT_LABEL: &str = "deployment";
+
+/// The label marking images and containers as made by the host, so external tools can find them all
+const MANAGED_BY_LABEL: &str = "managed-by";
+
+/// The value of the managed by label
+const MANAGED_BY: &str = "rustless";
+
+/// The permissions on images saved to promote them to another host. A host using the docker broker runs in the
+/// broker's group, so it can read images the broker saved
+const SAVED_IMAGE_MODE: u32 = 0o640;
+
+/// The port apps listen on in their container
+const APP_PORT: &str = "8080/tcp";
+
+/// How long calls to the docker API wait for docker before giving up, in seconds
+const DOCKER_API_TIMEOUT_SECS: u64 = 120;
+
+/// How often a comment is sent to clients following the logs of an app, so a client that has gone is noticed even
+/// when the app is quiet
+const LOGS_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How many threads the docker API client uses
+const DOCKER_RUNTIME_THREADS: usize = 2;
+
+/// The prefix for the labels, read from the environment once
+static LABEL_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// Whether the builder has been set up. Only success is kept, so if docker wasn't ready the next build tries again.
+/// It is locked while the builder is checked, so two builds don't both try to create it
+static BUILDER_READY: Mutex<bool> = Mutex::new(false);
+
+/// The docker API client, kept once it has connected so connections to docker can be reused
+static DOCKER: OnceLock<Docker> = OnceLock::new();
+
+/// The runtime docker API calls run on. Calls are made from server workers, the blocking pool, and background
+/// threads, so they run here and the caller waits for the result
+static DOCKER_RUNTIME: OnceLock<Result<Runtime, String>> = OnceLock::new();
+
+/// Gets the docker API client, connecting to the docker socket for the platform the first time it is used
+fn get_docker() -> Result<Docker, String> {
+    if let Some(docker) = DOCKER.get() {
+        return Ok(docker.clone());
+    }
+
+    // DOCKER_HOST is honoured if it is set, otherwise the socket found for the platform is used
+    let docker = match platform::get_docker_host() {
+        Some(docker_host) => Docker::connect_with_unix(&docker_host, DOCKER_API_TIMEOUT_SECS, API_DEFAULT_VERSION),
+        None => Docker::connect_with_defaults(),
     };
 
-    let output = String::from_utf8(output.stdout);
-    let output = match output {
-        Ok(output) => output,
-        Err(_) => return false
+    match docker {
+        Ok(docker) => Ok(DOCKER.get_or_init(|| docker).clone()),
+        Err(e) => Err(format!("Error connecting to docker: {}", e)),
+    }
+}
+
+/// Makes a call to the docker API on the docker runtime, waiting for the result
+///
+/// Waiting blocks the thread, so this must be called from the blocking thread pool, such as with web::block, or a
+/// background thread. Calls made from a server worker are refused rather than holding up every request it handles
+fn call_docker<T, F>(call: impl FnOnce(Docker) -> F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: Future<Output = Result<T, String>> + Send + 'static,
+{
+    // Server workers each run an arbiter, which the blocking thread pool and background threads don't have
+    if Arbiter::try_current().is_some() {
+        return Err("Docker calls must be made from the blocking thread pool, not a server worker".to_string());
+    }
+
+    let docker = get_docker()?;
+    let runtime = get_docker_runtime()?;
+
+    match futures::executor::block_on(runtime.spawn(call(docker))) {
+        Ok(result) => result,
+        Err(e) => Err(format!("Error calling docker: {}", e)),
+    }
+}
+
+/// Gets the runtime docker API calls run on, starting it the first time it is used
+fn get_docker_runtime() -> Result<&'static Runtime, String> {
+    let runtime = DOCKER_RUNTIME.get_or_init(|| {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(DOCKER_RUNTIME_THREADS)
+            .thread_name("rustless-docker")
+            .enable_all()
+            .build();
+
+        runtime.map_err(|e| format!("Error starting the docker client: {}", e))
+    });
+
+    match runtime {
+        Ok(runtime) => Ok(runtime),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Gets the prefix for the labels put on the images and containers for apps
+fn get_label_prefix() -> &'static str {
+    LABEL_PREFIX.get_or_init(|| {
+        let value = match std::env::var(LABEL_PREFIX_ENV) {
+            Ok(value) => value,
+            Err(_) => return DEFAULT_LABEL_PREFIX.to_string(),
+        };
+
+        // Docker recommends label keys are lower case letters, numbers, dots, and dashes, starting and ending with a
+        // letter or number
+        let valid = value.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-')
+            && value.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && value.ends_with(|c: char| c.is_ascii_alphanumeric());
+        if !valid {
+            println!("Ignoring invalid {}: {}", LABEL_PREFIX_ENV, value);
+            return DEFAULT_LABEL_PREFIX.to_string();
+        }
+
+        value
+    })
+}
+
+/// Gets the full key for a label, such as rustless.app
+fn get_label_key(label: &str) -> String {
+    format!("{}.{}", get_label_prefix(), label)
+}
+
+/// Gets the filter that finds the containers for a function app by its label
+fn get_app_filter(function_app_name: &String) -> String {
+    format!("{}={}", get_label_key(APP_LABEL), function_app_name)
+}
+
+/// The details of a function app put on its image and containers as labels, so they can be identified by the host
+/// and external tools without relying on the image name
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AppLabels {
+    // The app ID
+    pub id: Uuid,
+
+    // The app name
+    pub name: String,
+
+    // The namespace the app belongs to
+    pub namespace: String,
+
+    // The number of the deployment the image is for, or None if the app hasn't been deployed
+    pub deployment: Option<u32>,
+}
+
+impl AppLabels {
+    /// Gets the labels, keyed by their full keys
+    fn to_labels(&self) -> HashMap<String, String> {
+        let mut labels = HashMap::from([
+            (get_label_key(APP_LABEL), self.name.to_string()),
+            (get_label_key(APP_ID_LABEL), self.id.to_string()),
+            (get_label_key(NAMESPACE_LABEL), self.namespace.to_string()),
+            (get_label_key(MANAGED_BY_LABEL), MANAGED_BY.to_string()),
+        ]);
+
+        if let Some(deployment) = self.deployment {
+            labels.insert(get_label_key(DEPLOYMENT_LABEL), deployment.to_string());
+        }
+
+        labels
+    }
+}
+
+/// Gets the labels for a function app, with its latest deployment
+pub fn get_app_labels(conn: &Connection, id: &Uuid) -> Result<AppLabels, String> {
+    let (name, namespace) = storage::get_function_app_name_and_namespace(conn, id).map_err(|e| e.to_string())?;
+    let deployment = storage::get_latest_deployment(conn, id).map_err(|e| e.to_string())?;
+
+    Ok(AppLabels { id: *id, name, namespace, deployment })
+}
+
+/// Reads the details of a function app back from the labels on an image, or None if the labels are missing or invalid
+fn parse_app_labels(labels: &HashMap<String, String>) -> Option<AppLabels> {
+    let id = Uuid::parse_str(labels.get(&get_label_key(APP_ID_LABEL))?).ok()?;
+    let name = labels.get(&get_label_key(APP_LABEL))?.to_string();
+    let namespace = labels.get(&get_label_key(NAMESPACE_LABEL))?.to_string();
+    let deployment = labels.get(&get_label_key(DEPLOYMENT_LABEL)).and_then(|deployment| deployment.parse::<u32>().ok());
+
+    Some(AppLabels { id, name, namespace, deployment })
+}
+
+/// Gets the details of every function app with a built image, read from the labels on the image. Only the images
+/// tagged to run an app are included, not copies pushed to a registry, and images built before labels were added are
+/// skipped as there is nothing to read the app from
+pub fn get_app_images() -> Result<Vec<AppLabels>, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetAppImages);
+    }
+
+    let options = ListImagesOptions {
+        filters: HashMap::from([("label".to_string(), vec![format!("{}={}", get_label_key(MANAGED_BY_LABEL), MANAGED_BY)])]),
+        ..Default::default()
     };
 
-    let output = output.split("\n");
+    let images = call_docker(|docker| async move {
+        docker.list_images(Some(options)).await.map_err(|e| format!("Error listing images: {}", e))
+    })?;
+
+    let mut apps: HashMap<Uuid, AppLabels> = HashMap::new();
+    for image in images {
+        let app = match parse_app_labels(&image.labels) {
+            Some(app) => app,
+            None => continue,
+        };
 
-    for line in output {
-        if line.contains(&tag) {
-            return true;
+        let tag = get_container_tag(&app.name);
+        if !image.repo_tags.iter().any(|repo_tag| repo_tag.split(':').next() == Some(tag.as_str())) {
+            continue;
         }
+
+        // Keep the newest image if the app was tagged more than once
+        if apps.get(&app.id).is_some_and(|existing| existing.deployment >= app.deployment) {
+            continue;
+        }
+
+        apps.insert(app.id, app);
     }
 
-    false
+    Ok(apps.into_values().collect())
+}
+
+/// Checks if a docker container is running, returning an error if docker can't be reached
+///
+/// The containers are found through the docker API by the app label, so there is no docker ps output to parse
+pub fn check_container_running(function_app_name: &String) -> Result<bool, String> {
+    Ok(!get_container_ids(function_app_name)?.is_empty())
 }
 
 /// Gets the next free port
@@ -48,72 +355,1141 @@ fn get_next_free_port() -> Result<u16, String> {
     }
 }
 
-/// Starts a docker container
-pub fn start_function_app(function_app_name: &String) -> Result<u16, String> {
+/// Gets how many ports are tried when starting an app before giving up. At least one is always tried
+fn get_start_attempts() -> u32 {
+    match std::env::var(START_ATTEMPTS_ENV) {
+        Ok(value) => value.parse().unwrap_or(DEFAULT_START_ATTEMPTS).max(1),
+        Err(_) => DEFAULT_START_ATTEMPTS,
+    }
+}
+
+/// A container started for a function app
+pub struct StartedContainer {
+    // The port the app is published on
+    pub port: u16,
+
+    // The container ID
+    pub container_id: String,
+
+    // How many ports were tried before the container started
+    pub port_attempts: u32,
+}
+
+/// Starts a docker container, returning the port it is published on and the container ID. If a proxy URL is
+/// given, the app's outbound HTTP is sent through the egress proxy
+///
+/// The port is picked before docker publishes the app on it, so something else can take it in between, and Docker
+/// Desktop can still be holding a port that looks free on the machine. If the port is already allocated the app is
+/// started again on another port, up to the number of attempts set by RUSTLESS_START_ATTEMPTS
+pub fn start_function_app(app: &AppLabels, proxy_url: &Option<String>) -> Result<StartedContainer, String> {
+    let function_app_name = &app.name;
+    let attempts = get_start_attempts();
+    let mut attempt = 1;
+    loop {
+        // get the next free port
+        let port = get_next_free_port()?;
+
+        match run_function_app_container(app, proxy_url, port) {
+            Ok(container_id) => return Ok(StartedContainer { port, container_id, port_attempts: attempt }),
+            Err(e) if e.contains(PORT_ALLOCATED_ERROR) && attempt < attempts => {
+                println!("Port {} is already allocated, starting {} on another port (attempt {} of {})", port, function_app_name, attempt + 1, attempts);
+                attempt += 1;
+            },
+            Err(e) if e.contains(PORT_ALLOCATED_ERROR) => {
+                println!("Error starting {}: {}", function_app_name, e);
+                return match attempts {
+                    1 => Err("the port picked was taken before the app could start on it".to_string()),
+                    attempts => Err(format!("every port tried was taken before the app could start on it, after trying {} ports", attempts)),
+                };
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Gets the settings for the container for a function app, published on the given port
+fn get_container_config(app: &AppLabels, proxy_url: &Option<String>, port: u16) -> Config<String> {
+    // Docker stops the container with SIGTERM, then kills it if it hasn't exited within the grace period. The app
+    // is told to finish shutting down a little before then
+    let grace_period = get_stop_grace_period();
+    let mut env = vec![format!("{}={}", SHUTDOWN_TIMEOUT_ENV, grace_period.saturating_sub(SHUTDOWN_MARGIN_SECS).max(1))];
+    let mut extra_hosts = Vec::new();
+
+    // Point the standard proxy variables at the egress proxy on the host. Clients differ on which case they read
+    if let Some(proxy_url) = proxy_url {
+        if platform::needs_host_gateway() {
+            extra_hosts.push(format!("{}:host-gateway", egress::CONTAINER_HOST));
+        }
+        for variable in ["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy"] {
+            env.push(format!("{}={}", variable, proxy_url));
+        }
+        env.push("NO_PROXY=localhost,127.0.0.1".to_string());
+        env.push("no_proxy=localhost,127.0.0.1".to_string());
+    }
+
+    let port_binding = PortBinding {
+        host_ip: platform::get_publish_address(),
+        host_port: Some(port.to_string()),
+    };
+
+    Config {
+        image: Some(get_container_tag(&app.name)),
+        env: Some(env),
+        labels: Some(app.to_labels()),
+        exposed_ports: Some(HashMap::from([(APP_PORT.to_string(), HashMap::new())])),
+        stop_timeout: Some(grace_period as i64),
+        host_config: Some(HostConfig {
+            port_bindings: Some(HashMap::from([(APP_PORT.to_string(), Some(vec![port_binding]))])),
+            extra_hosts: Some(extra_hosts),
+            memory: get_app_memory_limit().map(|limit| limit as i64),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Gets the memory each app container can use in bytes, or None if apps aren't limited
+pub fn get_app_memory_limit() -> Option<u64> {
+    let value = std::env::var(APP_MEMORY_ENV).ok()?;
+    match quotas::parse_size(&value) {
+        Some(limit) if limit > 0 => Some(limit),
+        _ => {
+            println!("Ignoring invalid {}: {}", APP_MEMORY_ENV, value);
+            None
+        }
+    }
+}
+
+/// Runs the container for a function app, publishing it on the given port, and returns the container ID
+///
+/// If the container is created but can't be started, such as when the port was taken, it is removed so it isn't
+/// left behind
+pub fn run_function_app_container(app: &AppLabels, proxy_url: &Option<String>, port: u16) -> Result<String, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::Run { app: app.clone(), proxy_url: proxy_url.clone(), port });
+    }
+
+    let config = get_container_config(app, proxy_url, port);
+
+    call_docker(|docker| async move {
+        let container_id = match docker.create_container(None::<CreateContainerOptions<String>>, config).await {
+            Ok(created) => created.id,
+            Err(e) => return Err(format!("Error starting container: {}", e)),
+        };
+
+        if let Err(e) = docker.start_container(&container_id, None::<StartContainerOptions<String>>).await {
+            let remove = RemoveContainerOptions { force: true, ..Default::default() };
+            let _ = docker.remove_container(&container_id, Some(remove)).await;
+            return Err(format!("Error starting container: {}", e));
+        }
+
+        Ok(container_id)
+    })
+}
+
+/// Reads a file from the built image for a function app, without starting the app
+pub fn read_file_from_image(function_app_name: &String, path: &str) -> Result<String, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::ReadFileFromImage { app: function_app_name.to_string(), path: path.to_string() });
+    }
+
     let tag = get_container_tag(function_app_name);
 
-    // get the next free port
-    let port = get_next_free_port();
-    let port = match port {
-        Ok(port) => port,
-        Err(e) => return Err(e)
+    let output = match platform::docker_command().args(["run", "--rm", "--entrypoint", "cat", &tag, path]).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error reading {} from image: {}", path, e)),
     };
 
-    // Start the container running
-    let output = Command::new("docker")
-        .arg("run")
-        .arg("-d")
-        .arg("-p")
-        .arg(format!("{}:8080/tcp", port))
-        .arg(tag)
+    if !output.status.success() {
+        return Err(format!("Error reading {} from image: {}", path, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    match String::from_utf8(output.stdout) {
+        Ok(contents) => Ok(contents),
+        Err(e) => Err(format!("Error reading {} from image: {}", path, e)),
+    }
+}
+
+/// Gets the ID of the built image for a function app, such as sha256:..., or None if the image doesn't exist
+pub fn get_image_id(function_app_name: &String) -> Option<String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetImageId { app: function_app_name.to_string() }).ok().flatten();
+    }
+
+    let tag = get_container_tag(function_app_name);
+
+    let output = platform::docker_command().args(["image", "inspect", "--format", "{{.Id}}", &tag]).output().ok()?;
+    let image_id = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    match output.status.success() && !image_id.is_empty() {
+        true => Some(image_id),
+        false => None,
+    }
+}
+
+/// Saves the built image for a function app to a file with docker save, so it can be promoted to another host.
+/// The image is saved by its ID rather than its tag, so loading it doesn't replace the image for any app
+pub fn save_image(function_app_name: &String, file: &Path) -> Result<(), String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::SaveImage { app: function_app_name.to_string(), file: file.to_path_buf() });
+    }
+
+    let image_id = match get_image_id(function_app_name) {
+        Some(image_id) => image_id,
+        None => return Err(format!("No image found for {}", function_app_name)),
+    };
+
+    let output = match platform::docker_command().arg("save").arg("-o").arg(file).arg(&image_id).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error saving image: {}", e)),
+    };
+
+    if !output.status.success() {
+        return Err(format!("Error saving image: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    match fs::set_permissions(file, fs::Permissions::from_mode(SAVED_IMAGE_MODE)) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error setting the permissions on the saved image: {}", e)),
+    }
+}
+
+/// Loads an image saved with docker save from a file, and tags it as the image for a function app. The image must
+/// have the given ID, so the image that runs is the one that was exported
+pub fn load_image(function_app_name: &String, file: &Path, image_id: &str) -> Result<(), String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::LoadImage {
+            app: function_app_name.to_string(),
+            file: file.to_path_buf(),
+            image_id: image_id.to_string(),
+        });
+    }
+
+    let output = match platform::docker_command().arg("load").arg("-q").arg("-i").arg(file).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error loading image: {}", e)),
+    };
+
+    if !output.status.success() {
+        return Err(format!("Error loading image: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    // Tagging by ID fails if the file held a different image
+    let tag = get_container_tag(function_app_name);
+    let output = match platform::docker_command().args(["tag", image_id, &tag]).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error tagging image: {}", e)),
+    };
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(format!("The image loaded isn't {}: {}", image_id, String::from_utf8_lossy(&output.stderr).trim())),
+    }
+}
+
+/// Gets the digest of a local image, such as debian@sha256:..., or None if the image has no digest
+pub fn get_image_digest(image: &str) -> Option<String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetImageDigest { image: image.to_string() }).ok().flatten();
+    }
+
+    let output = platform::docker_command()
+        .args(["image", "inspect", "--format", "{{if .RepoDigests}}{{index .RepoDigests 0}}{{end}}", image])
+        .output()
+        .ok()?;
+
+    let digest = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    match output.status.success() && !digest.is_empty() {
+        true => Some(digest),
+        false => None,
+    }
+}
+
+/// Gets the size of the built image for a function app in bytes, or 0 if it hasn't been built
+pub fn get_image_size(function_app_name: &String) -> Result<u64, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetImageSize { app: function_app_name.to_string() });
+    }
+
+    let tag = get_container_tag(function_app_name);
+
+    let output = match platform::docker_command().args(["image", "inspect", "--format", "{{.Size}}", &tag]).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error inspecting image: {}", e)),
+    };
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return match error.contains("No such image") {
+            true => Ok(0),
+            false => Err(format!("Error inspecting image: {}", error.trim())),
+        };
+    }
+
+    match String::from_utf8_lossy(&output.stdout).trim().parse() {
+        Ok(size) => Ok(size),
+        Err(e) => Err(format!("Error reading image size: {}", e)),
+    }
+}
+
+/// Parses a size shown by docker stats, such as 12.5MiB, into bytes
+fn parse_docker_size(size: &str) -> Option<u64> {
+    let number_end = size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(number_end);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier: u64 = match unit {
+        "B" => 1,
+        "kB" => 1000,
+        "KiB" => 1024,
+        "MB" => 1000 * 1000,
+        "MiB" => 1024 * 1024,
+        "GB" => 1000 * 1000 * 1000,
+        "GiB" => 1024 * 1024 * 1024,
+        "TB" => 1000 * 1000 * 1000 * 1000,
+        "TiB" => 1024 * 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Gets the memory used by the running containers for a function app in bytes, or 0 if it isn't running
+pub fn get_memory_usage(function_app_name: &String) -> Result<u64, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetMemoryUsage { app: function_app_name.to_string() });
+    }
+
+    let container_ids = get_container_ids(function_app_name)?;
+    if container_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let output = match platform::docker_command().args(["stats", "--no-stream", "--format", "{{.MemUsage}}"]).args(&container_ids).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error getting container stats: {}", e)),
+    };
+
+    if !output.status.success() {
+        return Err(format!("Error getting container stats: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    // Each line is the usage and the limit, such as 12.5MiB / 7.6GiB
+    let mut total = 0;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let usage = line.split('/').next().unwrap_or_default().trim();
+        match parse_docker_size(usage) {
+            Some(bytes) => total += bytes,
+            None => return Err(format!("Error reading memory usage: {}", line)),
+        }
+    }
+
+    Ok(total)
+}
+
+/// The resources used by the running containers for a function app, summed over its containers
+#[derive(Default, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    // The number of running containers
+    pub containers: u32,
+
+    // The CPU used, as a percentage of one CPU
+    pub cpu_percent: f64,
+
+    // The memory used in bytes, not counting the page cache
+    pub memory_bytes: u64,
+
+    // The memory the containers can use in bytes
+    pub memory_limit_bytes: u64,
+
+    // The bytes received over the network
+    pub network_rx_bytes: u64,
+
+    // The bytes sent over the network
+    pub network_tx_bytes: u64,
+}
+
+impl ResourceUsage {
+    /// Adds the stats for a container to the usage, working them out the same way as docker stats
+    fn add(&mut self, stats: &Stats) {
+        self.containers += 1;
+
+        // The CPU used between the sample docker took before this one and this one
+        let cpu_delta = stats.cpu_stats.cpu_usage.total_usage.saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+        let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0).saturating_sub(stats.precpu_stats.system_cpu_usage.unwrap_or(0));
+        let cpus = stats.cpu_stats.online_cpus.unwrap_or(1);
+        if cpu_delta > 0 && system_delta > 0 {
+            self.cpu_percent += cpu_delta as f64 / system_delta as f64 * cpus as f64 * 100.0;
+        }
+
+        // The page cache can be reclaimed if memory runs low, so it isn't counted
+        let cache = match stats.memory_stats.stats {
+            Some(MemoryStatsStats::V1(stats)) => stats.total_inactive_file,
+            Some(MemoryStatsStats::V2(stats)) => stats.inactive_file,
+            None => 0,
+        };
+        self.memory_bytes += stats.memory_stats.usage.unwrap_or(0).saturating_sub(cache);
+        self.memory_limit_bytes += stats.memory_stats.limit.unwrap_or(0);
+
+        for network in stats.networks.iter().flat_map(|networks| networks.values()) {
+            self.network_rx_bytes += network.rx_bytes;
+            self.network_tx_bytes += network.tx_bytes;
+        }
+    }
+}
+
+/// Gets the resources used by the running containers for every function app, or only the given app, keyed by app
+/// name. Apps that aren't running aren't included
+///
+/// Containers are found by the label added when they are started. Docker samples a container for about a second to
+/// work out the CPU it is using, so all the containers are sampled at the same time
+pub fn get_resource_usage(function_app_name: Option<&String>) -> Result<HashMap<String, ResourceUsage>, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetResourceUsage { app: function_app_name.cloned() });
+    }
+
+    let app_label = get_label_key(APP_LABEL);
+    let label = match function_app_name {
+        Some(function_app_name) => get_app_filter(function_app_name),
+        None => app_label.to_string(),
+    };
+
+    let options = ListContainersOptions {
+        filters: HashMap::from([("label".to_string(), vec![label])]),
+        ..Default::default()
+    };
+
+    call_docker(|docker| async move {
+        let containers = match docker.list_containers(Some(options)).await {
+            Ok(containers) => containers,
+            Err(e) => return Err(format!("Error listing containers: {}", e)),
+        };
+
+        let samples = containers.into_iter()
+            .filter_map(|container| Some((container.id?, container.labels?.remove(&app_label)?)))
+            .map(|(container_id, app)| {
+                let docker = docker.clone();
+                async move {
+                    let options = StatsOptions { stream: false, one_shot: false };
+                    (app, docker.stats(&container_id, Some(options)).next().await)
+                }
+            });
+
+        let mut usage: HashMap<String, ResourceUsage> = HashMap::new();
+        for (app, stats) in futures::future::join_all(samples).await {
+            // Containers that stopped after they were listed have no stats, and are left out
+            if let Some(Ok(stats)) = stats {
+                usage.entry(app).or_default().add(&stats);
+            }
+        }
+
+        Ok(usage)
+    })
+}
+
+/// Gets how long apps get to finish requests in flight when they are stopped, in seconds
+pub fn get_stop_grace_period() -> u64 {
+    match std::env::var(STOP_GRACE_PERIOD_ENV) {
+        Ok(value) => value.parse().unwrap_or(DEFAULT_STOP_GRACE_PERIOD_SECS),
+        Err(_) => DEFAULT_STOP_GRACE_PERIOD_SECS,
+    }
+}
+
+/// Gets the IDs of the running containers for a function app, found by the app label
+pub fn get_container_ids(function_app_name: &String) -> Result<Vec<String>, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetContainerIds { app: function_app_name.to_string() });
+    }
+
+    let options = ListContainersOptions {
+        filters: HashMap::from([("label".to_string(), vec![get_app_filter(function_app_name)])]),
+        ..Default::default()
+    };
+
+    call_docker(|docker| async move {
+        match docker.list_containers(Some(options)).await {
+            Ok(containers) => Ok(containers.into_iter().filter_map(|container| container.id).collect()),
+            Err(e) => Err(format!("Error listing containers: {}", e)),
+        }
+    })
+}
+
+/// Gets the names of the function apps with a running container, from the app label, with one call to docker
+pub fn get_running_apps() -> Result<HashSet<String>, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetRunningApps);
+    }
+
+    let app_label = get_label_key(APP_LABEL);
+    let options = ListContainersOptions {
+        filters: HashMap::from([("label".to_string(), vec![app_label.to_string()])]),
+        ..Default::default()
+    };
+
+    call_docker(|docker| async move {
+        match docker.list_containers(Some(options)).await {
+            Ok(containers) => Ok(containers.into_iter()
+                .filter_map(|container| container.labels?.remove(&app_label))
+                .collect()),
+            Err(e) => Err(format!("Error listing containers: {}", e)),
+        }
+    })
+}
+
+/// A running container started for a function app
+#[derive(Serialize, Deserialize)]
+pub struct AppContainer {
+    // The container ID
+    pub id: String,
+
+    // The name of the function app the container was started for
+    pub app: String,
+
+    // The port the app is published on, if docker reports one
+    pub port: Option<u16>,
+}
+
+/// Gets every running container started for a function app, whether or not the app still exists
+///
+/// Containers are found by the label added when they are started, so containers started before the label was
+/// added aren't included
+pub fn get_app_containers() -> Result<Vec<AppContainer>, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetAppContainers);
+    }
+
+    let app_label = get_label_key(APP_LABEL);
+    let format = format!("{{{{.ID}}}}\t{{{{.Label \"{}\"}}}}\t{{{{.Ports}}}}", app_label);
+    let output = match platform::docker_command().args(["ps", "--filter", &format!("label={}", app_label), "--format", &format]).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error listing containers: {}", e))
+    };
+
+    if !output.status.success() {
+        return Err(format!("Error listing containers: {}", String::from_utf8_lossy(&output.stderr)));
+    }
+
+    let containers = String::from_utf8_lossy(&output.stdout).lines().filter_map(|line| {
+        let mut fields = line.trim().split('\t');
+        let id = fields.next()?.to_string();
+        let app = fields.next()?.to_string();
+        if id.is_empty() || app.is_empty() {
+            return None;
+        }
+
+        Some(AppContainer {
+            id,
+            app,
+            port: parse_published_port(fields.next().unwrap_or_default()),
+        })
+    }).collect();
+
+    Ok(containers)
+}
+
+/// Gets the IDs of every container started for a function app, running or not, found by the managed-by label. The
+/// docker broker uses this to check it is only asked to stop containers for apps
+pub fn get_managed_container_ids() -> Result<Vec<String>, String> {
+    let options = ListContainersOptions {
+        all: true,
+        filters: HashMap::from([("label".to_string(), vec![format!("{}={}", get_label_key(MANAGED_BY_LABEL), MANAGED_BY)])]),
+        ..Default::default()
+    };
+
+    call_docker(|docker| async move {
+        match docker.list_containers(Some(options)).await {
+            Ok(containers) => Ok(containers.into_iter().filter_map(|container| container.id).collect()),
+            Err(e) => Err(format!("Error listing containers: {}", e)),
+        }
+    })
+}
+
+/// Gets the port an app is published on from the ports docker lists for its container, such as
+/// 0.0.0.0:32768->8080/tcp, [::]:32768->8080/tcp
+fn parse_published_port(ports: &str) -> Option<u16> {
+    ports.split(", ")
+         .filter_map(|mapping| mapping.strip_suffix("->8080/tcp"))
+         .find_map(|address| address.rsplit(':').next()?.parse().ok())
+}
+
+/// Gets the exit code of a stopped container
+fn get_container_exit_code(container_id: &str) -> Result<i32, String> {
+    let output = match platform::docker_command().args(["inspect", "-f", "{{.State.ExitCode}}", container_id]).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error inspecting container: {}", e))
+    };
+
+    match String::from_utf8_lossy(&output.stdout).trim().parse() {
+        Ok(exit_code) => Ok(exit_code),
+        Err(_) => Err(format!("Error inspecting container: {}", String::from_utf8_lossy(&output.stderr))),
+    }
+}
+
+/// Gets the most recent lines of output from the container for a function app, each starting with when it was
+/// written. If the app isn't running, the output is from the last container that exited and hasn't been removed
+pub fn get_logs(function_app_name: &String, tail: usize) -> Result<String, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::GetLogs { app: function_app_name.to_string(), tail });
+    }
+
+    // Docker lists the most recently created containers first
+    let output = match platform::docker_command().args(["ps", "-aq", "--filter", &format!("label={}", get_app_filter(function_app_name))]).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error listing containers: {}", e)),
+    };
+
+    let container_id = match String::from_utf8_lossy(&output.stdout).lines().next() {
+        Some(container_id) => container_id.trim().to_string(),
+        None => return Ok(String::new()),
+    };
+
+    let output = match platform::docker_command().args(["logs", "--timestamps", "--tail", &tail.to_string(), &container_id]).output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error getting logs: {}", e)),
+    };
+
+    if !output.status.success() {
+        return Err(format!("Error getting logs: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    // Docker returns what the app wrote to stdout and stderr separately, so merge them back in order using the timestamps
+    let std_out = String::from_utf8_lossy(&output.stdout);
+    let std_err = String::from_utf8_lossy(&output.stderr);
+    let mut lines: Vec<&str> = std_out.lines().chain(std_err.lines()).collect();
+    lines.sort();
+
+    let skip = lines.len().saturating_sub(tail);
+    Ok(lines[skip..].join("\n"))
+}
+
+/// Gets the ID of the most recently created container for a function app, whether or not it is still running
+fn get_latest_container_id(function_app_name: &String) -> Result<Option<String>, String> {
+    let options = ListContainersOptions {
+        all: true,
+        filters: HashMap::from([("label".to_string(), vec![get_app_filter(function_app_name)])]),
+        ..Default::default()
+    };
+
+    // Docker lists the most recently created containers first
+    call_docker(|docker| async move {
+        match docker.list_containers(Some(options)).await {
+            Ok(containers) => Ok(containers.into_iter().find_map(|container| container.id)),
+            Err(e) => Err(format!("Error listing containers: {}", e)),
+        }
+    })
+}
+
+/// Follows the output from the container for a function app, returning it as server sent events. The most recent
+/// lines are sent straight away, then each line as the app writes it, and the stream ends when the container
+/// exits. If the app has no container the stream ends straight away
+///
+/// Docker sends nothing while the app is quiet, so a comment is sent every so often to find out when the client has
+/// gone and stop following
+pub fn follow_logs(function_app_name: &String, tail: usize) -> Result<UnboundedReceiver<String>, String> {
+    if broker::is_enabled() {
+        return broker::follow_logs(function_app_name, tail);
+    }
+
+    let (sender, receiver) = mpsc::unbounded();
+    let _ = sender.unbounded_send(": connected\n\n".to_string());
+
+    let container_id = match get_latest_container_id(function_app_name)? {
+        Some(container_id) => container_id,
+        None => return Ok(receiver),
+    };
+
+    let docker = get_docker()?;
+    let options = LogsOptions::<String> {
+        follow: true,
+        stdout: true,
+        stderr: true,
+        timestamps: true,
+        tail: tail.to_string(),
+        ..Default::default()
+    };
+
+    get_docker_runtime()?.spawn(async move {
+        let mut logs = docker.logs(&container_id, Some(options));
+        let mut keepalive = tokio::time::interval(LOGS_KEEPALIVE_INTERVAL);
+
+        loop {
+            let frames = tokio::select! {
+                output = logs.next() => match output {
+                    Some(Ok(output)) => String::from_utf8_lossy(output.as_ref()).lines().map(|line| format!("data: {}\n\n", line)).collect(),
+                    Some(Err(e)) => {
+                        let _ = sender.unbounded_send(format!("event: {}\ndata: Error getting logs: {}\n\n", LOGS_ERROR_EVENT, e));
+                        break;
+                    },
+                    None => break,
+                },
+                _ = keepalive.tick() => vec![": keepalive\n\n".to_string()],
+            };
+
+            if frames.into_iter().any(|frame| sender.unbounded_send(frame).is_err()) {
+                break;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// How a container exited, from docker inspect
+#[derive(Serialize, Deserialize)]
+pub struct ContainerExit {
+    // The exit code of the container
+    pub exit_code: i32,
+
+    // Whether the kernel killed the container for going over its memory limit
+    pub oom_killed: bool,
+
+    // The memory the container could use in bytes, or 0 if it wasn't limited
+    pub memory_limit_bytes: u64,
+}
+
+/// Gets how a container that has stopped exited
+fn get_container_exit(container_id: &str) -> Result<ContainerExit, String> {
+    let output = match platform::docker_command()
+        .args(["inspect", "-f", "{{.State.ExitCode}} {{.State.OOMKilled}} {{.HostConfig.Memory}}", container_id])
+        .output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error inspecting container: {}", e))
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.split_whitespace();
+
+    let exit_code = match fields.next().map(str::parse) {
+        Some(Ok(exit_code)) => exit_code,
+        _ => return Err(format!("Error inspecting container: {}", String::from_utf8_lossy(&output.stderr))),
+    };
+
+    Ok(ContainerExit {
+        exit_code,
+        oom_killed: fields.next() == Some("true"),
+        memory_limit_bytes: fields.next().and_then(|memory| memory.parse().ok()).unwrap_or(0),
+    })
+}
+
+/// Formats a memory limit the way it is usually set, such as 256Mi or 1.5Gi
+fn format_memory_limit(bytes: u64) -> String {
+    let units = ["", "Ki", "Mi", "Gi", "Ti"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match size.fract() == 0.0 {
+        true => format!("{}{}", size, units[unit]),
+        false => format!("{:.1}{}", size, units[unit]),
+    }
+}
+
+/// Gets a human readable reason for a container exiting without being stopped, such as killed: out of memory,
+/// limit 256Mi. The exit is None if the container was removed before it could be checked
+pub fn get_exit_reason(exit: Option<&ContainerExit>) -> String {
+    let exit = match exit {
+        Some(exit) => exit,
+        None => return "the container was removed before its exit could be checked".to_string(),
+    };
+
+    if exit.oom_killed {
+        return match exit.memory_limit_bytes {
+            0 => "killed: out of memory".to_string(),
+            limit => format!("killed: out of memory, limit {}", format_memory_limit(limit)),
+        };
+    }
+
+    match exit.exit_code {
+        0 => "exited with code 0 without being stopped".to_string(),
+        PANIC_EXIT_CODE => format!("exited with code {}: the app panicked", PANIC_EXIT_CODE),
+        ABORTED_EXIT_CODE => "killed: aborted (SIGABRT)".to_string(),
+        KILLED_EXIT_CODE => "killed (SIGKILL)".to_string(),
+        SEGFAULT_EXIT_CODE => "killed: segmentation fault (SIGSEGV)".to_string(),
+        TERMINATED_EXIT_CODE => "terminated (SIGTERM)".to_string(),
+        code if code > 128 => format!("killed by signal {}", code - 128),
+        code => format!("exited with code {}", code),
+    }
+}
+
+/// Removes the containers for a function app that have exited by themselves, returning how the most recent exited,
+/// or None if there are none
+pub fn remove_exited_containers(function_app_name: &String) -> Option<ContainerExit> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::RemoveExitedContainers { app: function_app_name.to_string() }).ok().flatten();
+    }
+
+    // Docker lists the most recently created containers first
+    let output = platform::docker_command()
+        .args(["ps", "-aq", "--filter", &format!("label={}", get_app_filter(function_app_name)), "--filter", "status=exited"])
+        .output()
+        .ok()?;
+
+    let container_ids: Vec<String> = String::from_utf8_lossy(&output.stdout).lines().map(|line| line.trim().to_string()).filter(|id| !id.is_empty()).collect();
+    let exit = container_ids.first().and_then(|container_id| get_container_exit(container_id).ok());
+
+    for container_id in container_ids.iter() {
+        let _ = platform::docker_command().args(["rm", container_id]).output();
+    }
+
+    exit
+}
+
+/// Stops the running containers for a function app, returning the exit code, or None if the app wasn't running
+///
+/// Each container is sent SIGTERM so the app can finish the requests in flight, and is killed if it hasn't exited
+/// within the grace period. If any container was killed, the exit code is KILLED_EXIT_CODE. The stopped
+/// containers are removed.
+pub fn stop_function_app(function_app_name: &String, grace_period: u64) -> Result<Option<i32>, String> {
+    let container_ids = get_container_ids(function_app_name)?;
+    if container_ids.is_empty() {
+        return Ok(None);
+    }
+
+    stop_containers(&container_ids, grace_period).map(Some)
+}
+
+/// Stops the given containers and removes them, returning the exit code in the same way as stop_function_app
+///
+/// This is used to stop the old containers for an app once a restart has moved requests to a new one
+pub fn stop_containers(container_ids: &[String], grace_period: u64) -> Result<i32, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::Stop { container_ids: container_ids.to_vec(), grace_period });
+    }
+
+    let output = platform::docker_command()
+        .args(["stop", "-t", &grace_period.to_string()])
+        .args(container_ids)
         .output();
-    
-    // Check for any errors
-    let output = match output {
+
+    match output {
+        Ok(output) if output.status.success() => {},
+        Ok(output) => return Err(format!("Error stopping container: {}", String::from_utf8_lossy(&output.stderr))),
+        Err(e) => return Err(format!("Error stopping container: {}", e)),
+    };
+
+    // Report a killed container over one that exited cleanly, otherwise the first non zero exit code
+    let mut exit_code = 0;
+    for container_id in container_ids.iter() {
+        let container_exit_code = get_container_exit_code(container_id)?;
+        if container_exit_code == KILLED_EXIT_CODE || exit_code == 0 {
+            exit_code = container_exit_code;
+        }
+
+        // The container has stopped, so remove it. Failing to remove it doesn't affect the stop
+        let _ = platform::docker_command().args(["rm", container_id]).output();
+    }
+
+    Ok(exit_code)
+}
+
+/// Removes the built image for a function app, along with any containers left from it such as ones that exited.
+/// Returns false if the app was never built, so there was no image to remove
+pub fn remove_function_app_image(function_app_name: &String) -> Result<bool, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::RemoveImage { app: function_app_name.to_string() });
+    }
+
+    let tag = get_container_tag(function_app_name);
+
+    // An image can't be removed while containers made from it exist, even if they have exited
+    let output = match platform::docker_command().args(["ps", "-aq", "--filter", &format!("label={}", get_app_filter(function_app_name))]).output() {
         Ok(output) => output,
-        Err(e) => return Err(format!("Error starting container: {}", e))
+        Err(e) => return Err(format!("Error listing containers: {}", e)),
     };
 
-    let output = String::from_utf8(output.stdout);
-    let output = match output {
+    let container_ids: Vec<String> = String::from_utf8_lossy(&output.stdout).lines().map(|line| line.trim().to_string()).filter(|id| !id.is_empty()).collect();
+    if !container_ids.is_empty() {
+        match platform::docker_command().args(["rm", "-f"]).args(&container_ids).output() {
+            Ok(output) if output.status.success() => {},
+            Ok(output) => return Err(format!("Error removing container: {}", String::from_utf8_lossy(&output.stderr).trim())),
+            Err(e) => return Err(format!("Error removing container: {}", e)),
+        }
+    }
+
+    let output = match platform::docker_command().args(["image", "rm", &tag]).output() {
         Ok(output) => output,
-        Err(e) => return Err(format!("Error starting container: {}", e))
+        Err(e) => return Err(format!("Error removing image: {}", e)),
     };
 
-    if output.contains("Error") {
-        return Err(format!("Error starting container: {}", output));
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return match error.contains("No such image") {
+            true => Ok(false),
+            false => Err(format!("Error removing image: {}", error.trim())),
+        };
     }
 
-    // Return the port
-    Ok(port)
+    Ok(true)
+}
+
+/// Checks if an exit code means the container was killed because it didn't stop within the grace period
+pub fn was_killed(exit_code: i32) -> bool {
+    exit_code == KILLED_EXIT_CODE
 }
 
 /// Creates a docker container tag from a function app name
-fn get_container_tag(function_app_name: &String) -> String {
+pub fn get_container_tag(function_app_name: &str) -> String {
     format!("{}-container", function_app_name.replace(" ", "-").to_lowercase())
 }
 
+/// Gets the driver options that limit the resources the build container can use
+fn get_build_limit_options() -> Result<Vec<String>, String> {
+    // Always run builds at a lower priority than apps
+    let mut options = vec![format!("cpu-shares={}", BUILD_CPU_SHARES)];
+
+    if let Ok(cpus) = std::env::var(BUILD_CPUS_ENV) {
+        let cpus: f64 = match cpus.parse() {
+            Ok(cpus) if cpus > 0.0 => cpus,
+            _ => return Err(format!("Invalid value for {}: {}", BUILD_CPUS_ENV, cpus)),
+        };
+
+        options.push(format!("cpu-period={}", CPU_PERIOD));
+        options.push(format!("cpu-quota={}", (cpus * CPU_PERIOD as f64) as u64));
+    }
+
+    if let Ok(memory) = std::env::var(BUILD_MEMORY_ENV) {
+        options.push(format!("memory={}", memory));
+    }
+
+    Ok(options)
+}
+
+/// Makes sure the buildx builder used for function app builds exists
+///
+/// Builds run in their own builder container so the CPU and memory they use can be limited.
+/// The builder is kept between runs to keep its build cache, so to change the limits remove it
+/// with docker buildx rm rustless-builder and it will be recreated on the next build.
+fn ensure_builder() -> Result<(), String> {
+    let mut ready = match BUILDER_READY.lock() {
+        Ok(ready) => ready,
+        Err(e) => return Err(format!("Error checking builder: {}", e)),
+    };
+
+    if *ready {
+        return Ok(());
+    }
+
+    // Check if the builder already exists
+    let inspect = platform::docker_command()
+        .args(["buildx", "inspect", BUILDER_NAME])
+        .output();
+
+    if let Ok(output) = inspect {
+        if output.status.success() {
+            *ready = true;
+            return Ok(());
+        }
+    }
+
+    let options = get_build_limit_options()?;
+    println!("Creating builder {} with options {}", BUILDER_NAME, options.join(","));
+
+    let output = platform::docker_command()
+        .args(["buildx", "create", "--name", BUILDER_NAME, "--driver", "docker-container"])
+        .arg("--driver-opt")
+        .arg(options.join(","))
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            *ready = true;
+            Ok(())
+        },
+        Ok(output) => Err(format!("Error creating builder: {}", String::from_utf8_lossy(&output.stderr))),
+        Err(e) => Err(format!("Error creating builder: {}", e)),
+    }
+}
+
+/// A docker process whose output is streamed, such as a build or a push. The process is run by the host, or by the
+/// docker broker if one is set
+pub enum DockerProcess {
+    /// A docker process run by the host
+    Local(Child),
+
+    /// A docker process run by the broker
+    Broker(BrokerProcess),
+}
+
+impl DockerProcess {
+    /// Takes what the process writes to stdout
+    pub fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+        match self {
+            DockerProcess::Local(child) => child.stdout.take().map(|stdout| Box::new(stdout) as Box<dyn Read + Send>),
+            DockerProcess::Broker(process) => process.stdout.take().map(|stdout| Box::new(stdout) as Box<dyn Read + Send>),
+        }
+    }
+
+    /// Takes what the process writes to stderr
+    pub fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+        match self {
+            DockerProcess::Local(child) => child.stderr.take().map(|stderr| Box::new(stderr) as Box<dyn Read + Send>),
+            DockerProcess::Broker(process) => process.stderr.take().map(|stderr| Box::new(stderr) as Box<dyn Read + Send>),
+        }
+    }
+
+    /// Checks if the process has exited, without waiting for it
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        match self {
+            DockerProcess::Local(child) => child.try_wait(),
+            DockerProcess::Broker(process) => process.try_wait(),
+        }
+    }
+
+    /// Kills the process and waits for it to exit
+    pub fn kill(&mut self) {
+        match self {
+            DockerProcess::Local(child) => {
+                let _ = child.kill();
+                let _ = child.wait();
+            },
+            DockerProcess::Broker(process) => process.kill(),
+        }
+    }
+}
+
+/// Reads all of a child process output stream on a background thread, so the child never blocks on a full pipe
+pub fn read_in_background<R: Read + Send + 'static>(stream: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Reads all of the buildx progress output on a background thread, noting when the image starts being exported.
+/// Each line is added to the build log as it arrives, so clients can follow the build
+fn read_build_progress<R: Read + Send + 'static>(stream: Option<R>, id: Uuid, export_started: Arc<Mutex<Option<SystemTime>>>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(stream) = stream {
+            let mut reader = BufReader::new(stream);
+            let mut line = Vec::new();
+            while matches!(reader.read_until(b'\n', &mut line), Ok(read) if read > 0) {
+                // Progress lines start with the step number, such as #12 exporting to docker image format
+                if line.starts_with(b"#") && String::from_utf8_lossy(&line).contains(EXPORT_STARTED_MARKER) {
+                    if let Ok(mut export_started) = export_started.lock() {
+                        export_started.get_or_insert_with(SystemTime::now);
+                    }
+                }
+
+                build_logs::append(&id, &String::from_utf8_lossy(&line));
+                buffer.append(&mut line);
+            }
+        }
+        buffer
+    })
+}
+
+/// Waits for a build process to finish, killing it if the build for the function app is cancelled, or if compiling
+/// or exporting the image takes longer than its timeout. Returns the output and when the export started
+fn wait_for_build(mut child: DockerProcess, id: &Uuid) -> Result<(Output, Option<SystemTime>), String> {
+    let compile_started = SystemTime::now();
+    let export_started = Arc::new(Mutex::new(None));
+
+    let std_out = read_in_background(child.take_stdout());
+    let std_err = read_build_progress(child.take_stderr(), *id, export_started.clone());
+
+    let get_export_started = || export_started.lock().ok().and_then(|export_started| *export_started);
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {},
+            Err(e) => return Err(format!("Error waiting for build: {}", e)),
+        }
+
+        // Killing the buildx client stops the build in the builder too
+        if build_queue::is_cancelled(id) {
+            println!("Build for {} cancelled", id);
+            child.kill();
+            return Err(build_queue::BUILD_CANCELLED.to_string());
+        }
+
+        // Compiling and exporting have separate timeouts, as exporting a large image can be slow on a busy disk
+        let timed_out = match get_export_started() {
+            Some(started) => Some(DeployPhase::Export).filter(|phase| phase.has_timed_out(started)),
+            None => Some(DeployPhase::Compile).filter(|phase| phase.has_timed_out(compile_started)),
+        };
+
+        if let Some(phase) = timed_out {
+            println!("Build for {} timed out in the {} phase", id, phase.name());
+            child.kill();
+            return Err(phase.timeout_error());
+        }
+
+        thread::sleep(CANCEL_POLL_INTERVAL);
+    };
+
+    let output = Output {
+        status,
+        stdout: std_out.join().unwrap_or_default(),
+        stderr: std_err.join().unwrap_or_default(),
+    };
+
+    Ok((output, get_export_started()))
+}
+
+/// Starts building the image for a function app from the Dockerfile and code in a build directory, with the given
+/// crates cache URL passed to the build. The build output is piped
+pub fn spawn_build(context_dir: &Path, app: &AppLabels, strict: bool, crates_cache: &Option<String>) -> Result<Child, String> {
+    // Build the correct docker tag
+    let tag = get_container_tag(&app.name);
+
+    // Make sure the resource limited builder is available
+    ensure_builder()?;
+
+    // Build the Dockerfile and tag it with the name of the function app, loading the image into docker
+    // so it can be run. Plain progress output is used so the full compiler output is returned if the build fails.
+    // The image is labelled with the app details, and containers started from it are labelled the same way
+    let dockerfile_command = format!(
+        "docker buildx build --builder {} --load --progress=plain --build-arg STRICT={} -t {} .",
+        BUILDER_NAME, strict, tag
+    );
+    println!("Running command: {}", dockerfile_command);
+    // Docker is run directly rather than through a shell so cancelling the build kills the docker process
+    let child = platform::docker_command()
+        .args(["buildx", "build", "--builder", BUILDER_NAME, "--load", "--progress=plain"])
+        .arg("--build-arg")
+        .arg(format!("STRICT={}", strict))
+        .arg("--build-arg")
+        .arg(format!("CRATES_CACHE={}", crates_cache.as_deref().unwrap_or_default()))
+        .args(app.to_labels().iter().flat_map(|(key, value)| ["--label".to_string(), format!("{}={}", key, value)]))
+        .arg("-t")
+        .arg(tag)
+        .arg(".")
+        .current_dir(context_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    match child {
+        Ok(child) => Ok(child),
+        Err(e) => Err(format!("Error building Dockerfile: {}", e)),
+    }
+}
+
 /// Builds a function app container.
 /// 
 /// This takes the source code that is uploaded, and builds a container
-/// with docker that installs Rust, and then compiles the code that is sent
-pub fn build_function_app_container(temp_dir: &TempDir, function_app_name: &String) -> Result<(), String> {
+/// with docker using the Dockerfile rendered from the app's template, which installs Rust
+/// and then compiles the code that is sent.
+/// In strict mode the build fails on any compiler or clippy warnings.
+/// If the build is cancelled part way through, this returns build_queue::BUILD_CANCELLED.
+/// Returns when compiling finished and the image started being exported, if buildx reported it
+///
+/// The build runs through the buildx CLI rather than the docker API, as templates use BuildKit features such as
+/// RUN --network that the API's classic builder doesn't support, and builds run in the resource limited builder.
+/// If a docker broker is set, the broker runs the build and streams the output back
+pub fn build_function_app_container(temp_dir: &TempDir, app: &AppLabels, dockerfile_content: &str, strict: bool) -> Result<Option<SystemTime>, String> {
+    let id = &app.id;
+
     // Create a Dockerfile in the temporary folder
     let dockerfile_path = temp_dir.path().join("Dockerfile");
 
-    // Get the Dockerfile content from the embedded folder
-    let dockerfile_source = match ContainerFolder::get("Dockerfile") {
-        Some(dockerfile_source) => dockerfile_source,
-        None => return Err("Error getting Dockerfile from container folder".to_string())
-    };
-
-    // Get the Dockerfile content as a string
-    let dockerfile_content = std::str::from_utf8(dockerfile_source.data.as_ref());
-    let dockerfile_content = match dockerfile_content {
-        Ok(content) => content,
-        Err(e) => return Err(format!("Error converting Dockerfile to string: {}", e))
-    };
-
     // Write the Dockerfile to the temporary folder
     let dockerfile_result = std::fs::write(dockerfile_path, dockerfile_content);
     match dockerfile_result {
@@ -123,20 +1499,24 @@ pub fn build_function_app_container(temp_dir: &TempDir, function_app_name: &Stri
 
     println!("Dockerfile created in {}", temp_dir.path().display());
 
-    // Build the correct docker tag
-    let tag = get_container_tag(function_app_name);
+    let crates_cache = crates_cache::get_build_url();
+    let dockerfile_command_result = match broker::is_enabled() {
+        true => broker::spawn(BrokerRequest::Build {
+            context_dir: temp_dir.path().to_path_buf(),
+            app: app.clone(),
+            strict,
+            crates_cache,
+        }).map(DockerProcess::Broker),
+        false => spawn_build(temp_dir.path(), app, strict, &crates_cache).map(DockerProcess::Local),
+    };
 
-    // Build the Dockerfile and tag it with the name of the function app
-    let dockerfile_command = format!("docker build -t {} .", tag);
-    println!("Running command: {}", dockerfile_command);
-    let dockerfile_command_result = Command::new("sh")
-        .arg("-c")
-        .arg(dockerfile_command)
-        .current_dir(temp_dir.path())
-        .output();
+    let dockerfile_command_result = match dockerfile_command_result {
+        Ok(child) => wait_for_build(child, id),
+        Err(e) => Err(e),
+    };
 
-    match dockerfile_command_result {
-        Ok(output) => {
+    let export_started = match dockerfile_command_result {
+        Ok((output, export_started)) => {
             let std_out = String::from_utf8(output.stdout);
             let std_out = match std_out {
                 Ok(std_out) => std_out,
@@ -150,9 +1530,11 @@ pub fn build_function_app_container(temp_dir: &TempDir, function_app_name: &Stri
             } else {
                 return Err(format!("Error building Dockerfile: {}", String::from_utf8_lossy(&output.stderr)))
             }
+
+            export_started
         },
-        Err(e) => return Err(format!("Error building Dockerfile: {}", e))
+        Err(e) => return Err(e)
     };
 
-    Ok(())
+    Ok(export_started)
 }
@@ -0,0 +1,604 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use uuid::Uuid;
+
+use rustless_shared::EgressDestination;
+
+use crate::database::Connection;
+use crate::defaults;
+use crate::listeners;
+use crate::platform;
+use crate::storage;
+
+/// The environment variable containing the port for the egress proxy to listen on, such as 3128, or the address,
+/// such as 172.17.0.1:3128. A port on its own listens on the docker bridge, or on localhost under Docker Desktop, so
+/// only containers can reach the proxy. When this is set, function apps are started with HTTP_PROXY and HTTPS_PROXY
+/// set so their outbound HTTP goes through the proxy, which enforces the allowlist for each app and records the
+/// destinations called.
+///
+/// The proxy only sees calls made by clients that use these variables. To stop apps calling out directly,
+/// block outbound traffic from the docker network except to the proxy
+pub const EGRESS_PROXY_ENV: &str = "RUSTLESS_EGRESS_PROXY";
+
+/// The host name containers use to reach the host, mapped to the docker bridge gateway when the container starts
+pub const CONTAINER_HOST: &str = "host.docker.internal";
+
+/// How long to wait when connecting to a destination
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for an app to send the request headers
+const HEADER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest request headers the proxy will read
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// The destinations called by a function app, keyed by host and port
+type AppDestinations = HashMap<(String, u16), EgressDestination>;
+
+/// The destinations called by each function app through the proxy.
+/// These are only kept in memory, so are lost when the host restarts
+static DESTINATIONS: OnceLock<Mutex<HashMap<Uuid, AppDestinations>>> = OnceLock::new();
+
+/// Gets the destinations called by all the function apps
+fn get_all_destinations() -> &'static Mutex<HashMap<Uuid, AppDestinations>> {
+    DESTINATIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Gets the address the egress proxy listens on, or None if the proxy is turned off. A port on its own listens on
+/// the address on the host that containers reach it on
+pub fn get_proxy_address() -> Option<String> {
+    let address = match std::env::var(EGRESS_PROXY_ENV) {
+        Ok(address) if !address.trim().is_empty() => address.trim().to_string(),
+        _ => return None,
+    };
+
+    match address.parse::<u16>() {
+        Ok(port) => Some(format!("{}:{}", platform::get_container_facing_host(), port)),
+        Err(_) => Some(address),
+    }
+}
+
+/// Gets if the host is running the egress proxy
+pub fn is_enabled() -> bool {
+    get_proxy_address().is_some()
+}
+
+/// Gets the secret a function app's containers give the egress proxy, issuing a random one the first time. Replicas
+/// and restarted containers get the same secret, so containers that are already running keep working
+fn get_or_issue_secret(conn: &Connection, id: &Uuid) -> Result<String, String> {
+    // Two random UUIDs give 244 random bits. If another container is started at the same time, its secret is kept
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    if let Err(e) = storage::issue_function_app_proxy_secret(conn, id, &secret) {
+        return Err(format!("Error issuing egress proxy secret: {}", e));
+    }
+
+    match storage::get_function_app_proxy_secret(conn, id) {
+        Ok(Some(secret)) => Ok(secret),
+        Ok(None) => Err("Error issuing egress proxy secret: the function app was not found".to_string()),
+        Err(e) => Err(format!("Error getting egress proxy secret: {}", e)),
+    }
+}
+
+/// Gets the proxy URL a function app uses to call out through the egress proxy, or None if the proxy is turned off.
+/// The app ID is the proxy user name, so the proxy knows which allowlist to apply, and the password is the secret
+/// issued to the app, so one app can't use another's allowlist by knowing its ID
+pub fn get_container_proxy_url(conn: &Connection, id: &Uuid) -> Result<Option<String>, String> {
+    let address = match get_proxy_address() {
+        Some(address) => address,
+        None => return Ok(None),
+    };
+
+    let port = address.rsplit_once(':').map(|(_, port)| port).unwrap_or("3128").to_string();
+    let secret = get_or_issue_secret(conn, id)?;
+    Ok(Some(format!("http://{}:{}@{}:{}", id, secret, CONTAINER_HOST, port)))
+}
+
+/// Splits a host and optional port, such as example.com:443, lower casing the host. IPv6 addresses must be in brackets
+fn split_host_port(value: &str) -> Result<(String, Option<u16>), String> {
+    let (host, port) = match value.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(':') && (!host.starts_with('[') || host.ends_with(']')) => match port.parse::<u16>() {
+            Ok(port) => (host, Some(port)),
+            Err(_) => return Err(format!("Invalid port in '{}'", value)),
+        },
+        _ => (value, None),
+    };
+
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+    if host.is_empty() {
+        return Err(format!("Missing host in '{}'", value));
+    }
+
+    Ok((host, port))
+}
+
+/// Checks the destinations in an allowlist are valid, returning them tidied up.
+/// Destinations are a host name or IP address, optionally starting with *. to allow any subdomain, and
+/// optionally ending with a port
+pub fn validate_allowlist(allowlist: &[String]) -> Result<Vec<String>, String> {
+    let mut validated = Vec::new();
+
+    for destination in allowlist {
+        let destination = destination.trim();
+        let (host, port) = split_host_port(destination)?;
+        let name = host.strip_prefix("*.").unwrap_or(&host);
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == ':') {
+            return Err(format!(
+                "Invalid destination '{}': use a host name such as api.example.com or *.example.com, optionally with a port",
+                destination
+            ));
+        }
+
+        match port {
+            Some(port) if host.contains(':') => validated.push(format!("[{}]:{}", host, port)),
+            Some(port) => validated.push(format!("{}:{}", host, port)),
+            None => validated.push(host),
+        }
+    }
+
+    Ok(validated)
+}
+
+/// Gets if a destination is in the allowlist. Every destination is allowed if there is no allowlist
+fn is_allowed(allowlist: &Option<Vec<String>>, host: &str, port: u16) -> bool {
+    let allowlist = match allowlist {
+        Some(allowlist) => allowlist,
+        None => return true,
+    };
+
+    allowlist.iter().any(|destination| {
+        let (allowed_host, allowed_port) = match split_host_port(destination) {
+            Ok(destination) => destination,
+            Err(_) => return false,
+        };
+
+        let host_matches = match allowed_host.strip_prefix("*.") {
+            Some(domain) => host.ends_with(&format!(".{}", domain)),
+            None => host == allowed_host,
+        };
+
+        host_matches && allowed_port.is_none_or(|allowed_port| allowed_port == port)
+    })
+}
+
+/// Records a call from a function app to a destination
+fn record_call(id: &Uuid, host: &str, port: u16, allowed: bool) {
+    if let Ok(mut all_destinations) = get_all_destinations().lock() {
+        let destination = all_destinations
+            .entry(*id)
+            .or_default()
+            .entry((host.to_string(), port))
+            .or_insert_with(|| EgressDestination {
+                host: host.to_string(),
+                port,
+                allowed: 0,
+                denied: 0,
+                bytes_sent: 0,
+                bytes_received: 0,
+                last_seen: 0,
+            });
+
+        if allowed {
+            destination.allowed += 1;
+        } else {
+            destination.denied += 1;
+        }
+
+        destination.last_seen = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    }
+}
+
+/// Adds the bytes sent and received over a connection once it has closed
+fn record_bytes(id: &Uuid, host: &str, port: u16, bytes_sent: u64, bytes_received: u64) {
+    if let Ok(mut all_destinations) = get_all_destinations().lock() {
+        if let Some(destination) = all_destinations.get_mut(id).and_then(|destinations| destinations.get_mut(&(host.to_string(), port))) {
+            destination.bytes_sent += bytes_sent;
+            destination.bytes_received += bytes_received;
+        }
+    }
+}
+
+/// Gets the destinations a function app has called through the proxy, most recent first
+pub fn get_destinations(id: &Uuid) -> Vec<EgressDestination> {
+    let mut destinations: Vec<EgressDestination> = match get_all_destinations().lock() {
+        Ok(all_destinations) => match all_destinations.get(id) {
+            Some(destinations) => destinations.values().cloned().collect(),
+            None => Vec::new(),
+        },
+        Err(_) => Vec::new(),
+    };
+
+    destinations.sort_by_key(|destination| std::cmp::Reverse(destination.last_seen));
+    destinations
+}
+
+/// The request an app sent to the proxy
+struct ProxyRequest {
+    // The request method. CONNECT opens a tunnel, used for HTTPS
+    method: String,
+
+    // The host being called
+    host: String,
+
+    // The port being called
+    port: u16,
+
+    // The path for plain HTTP requests, or None for a tunnel
+    path: Option<String>,
+
+    // The HTTP version from the request line
+    version: String,
+
+    // The request headers, without the proxy headers
+    headers: Vec<String>,
+
+    // The function app that sent the request and its secret, from the proxy credentials
+    credentials: Option<(Uuid, String)>,
+}
+
+/// Reads the request line and headers sent to the proxy
+fn read_request(reader: &mut BufReader<TcpStream>) -> Result<ProxyRequest, String> {
+    let mut lines = Vec::new();
+    let mut size = 0;
+
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err("Connection closed before the request was sent".to_string()),
+            Ok(read) => size += read,
+            Err(e) => return Err(format!("Error reading request: {}", e)),
+        }
+
+        if size > MAX_HEADER_BYTES {
+            return Err("Request headers are too large".to_string());
+        }
+
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            break;
+        }
+
+        lines.push(line);
+    }
+
+    let request_line: Vec<&str> = lines.first().map(|line| line.split_whitespace().collect()).unwrap_or_default();
+    let (method, target, version) = match request_line.as_slice() {
+        [method, target, version] => (method.to_string(), target.to_string(), version.to_string()),
+        _ => return Err("Invalid request line".to_string()),
+    };
+
+    // Tunnels are to host:port, plain HTTP requests use the full URL
+    let (authority, path, default_port) = if method.eq_ignore_ascii_case("CONNECT") {
+        (target.as_str(), None, 443)
+    } else {
+        match target.strip_prefix("http://") {
+            Some(rest) => match rest.find('/') {
+                Some(index) => (&rest[..index], Some(rest[index..].to_string()), 80),
+                None => (rest, Some("/".to_string()), 80),
+            },
+            None => return Err(format!("Only http URLs and CONNECT tunnels can be proxied, not '{}'", target)),
+        }
+    };
+
+    let (host, port) = split_host_port(authority)?;
+
+    let mut headers = Vec::new();
+    let mut credentials = None;
+    for line in lines.iter().skip(1) {
+        let name = line.split(':').next().unwrap_or_default().trim().to_lowercase();
+        match name.as_str() {
+            "proxy-authorization" => credentials = parse_proxy_authorization(line),
+            "proxy-connection" | "connection" | "keep-alive" => {},
+            _ => headers.push(line.to_string()),
+        }
+    }
+
+    Ok(ProxyRequest {
+        method,
+        host,
+        port: port.unwrap_or(default_port),
+        path,
+        version,
+        headers,
+        credentials,
+    })
+}
+
+/// Gets the function app ID and secret from the user name and password in a basic Proxy-Authorization header
+fn parse_proxy_authorization(header: &str) -> Option<(Uuid, String)> {
+    let value = header.split_once(':')?.1.trim();
+    let credentials = value.strip_prefix("Basic ").or_else(|| value.strip_prefix("basic "))?;
+    let credentials = base64::decode(credentials.trim()).ok()?;
+    let credentials = String::from_utf8(credentials).ok()?;
+    let (user, secret) = credentials.split_once(':')?;
+    Some((Uuid::parse_str(user).ok()?, secret.to_string()))
+}
+
+/// Compares a secret with the one issued, taking the same time however much of it matches so it can't be guessed a
+/// character at a time
+fn secrets_match(secret: &str, issued: &str) -> bool {
+    secret.len() == issued.len() && secret.bytes().zip(issued.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Gets the allowlist for a function app if the secret is the one issued to it, or None if it isn't. Apps without
+/// their own allowlist use the default for their namespace
+fn get_allowlist(id: &Uuid, secret: &str) -> Option<Option<Vec<String>>> {
+    let conn = storage::create_connection_for_app(id).ok()?;
+    match storage::get_function_app_proxy_secret(&conn, id) {
+        Ok(Some(issued)) if secrets_match(secret, &issued) => {},
+        _ => return None,
+    }
+
+    defaults::get_effective_config(&conn, id).map(|effective| effective.egress_allowlist.value).ok()
+}
+
+/// Sends an error response to the app and closes the connection
+fn send_error(stream: &mut TcpStream, status: &str, message: &str, extra_headers: &str) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+        status,
+        message.len(),
+        extra_headers,
+        message
+    );
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Connects to a destination, trying each address it resolves to
+fn connect(host: &str, port: u16) -> Result<TcpStream, String> {
+    let addresses = match (host, port).to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(e) => return Err(format!("Error resolving {}: {}", host, e)),
+    };
+
+    let mut last_error = format!("No addresses found for {}", host);
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = format!("Error connecting to {}:{}: {}", host, port, e),
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Copies data both ways between the app and the destination until either side closes, returning the bytes
+/// sent to and received from the destination
+fn pipe(client: TcpStream, upstream: TcpStream) -> (u64, u64) {
+    let (mut client_read, mut upstream_write) = match (client.try_clone(), upstream.try_clone()) {
+        (Ok(client_read), Ok(upstream_write)) => (client_read, upstream_write),
+        _ => return (0, 0),
+    };
+
+    let sender = thread::spawn(move || {
+        let sent = io::copy(&mut client_read, &mut upstream_write).unwrap_or_default();
+        let _ = upstream_write.shutdown(Shutdown::Write);
+        sent
+    });
+
+    let (mut upstream_read, mut client_write) = (upstream, client);
+    let received = io::copy(&mut upstream_read, &mut client_write).unwrap_or_default();
+    let _ = client_write.shutdown(Shutdown::Both);
+
+    (sender.join().unwrap_or_default(), received)
+}
+
+/// Handles a connection from a function app, checking the destination is allowed before passing the request on
+fn handle_connection(stream: TcpStream) -> Result<(), String> {
+    let _ = stream.set_read_timeout(Some(HEADER_TIMEOUT));
+    let mut client = match stream.try_clone() {
+        Ok(client) => client,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut reader = BufReader::new(stream);
+
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(e) => {
+            send_error(&mut client, "400 Bad Request", &e, "");
+            return Err(e);
+        }
+    };
+
+    // The app is identified by its proxy credentials, which are set when it is started
+    let challenge = "Proxy-Authenticate: Basic realm=\"rustless\"\r\n";
+    let (id, secret) = match &request.credentials {
+        Some(credentials) => credentials,
+        None => {
+            send_error(&mut client, "407 Proxy Authentication Required", "Unknown function app", challenge);
+            return Err(format!("Request to {} from an unknown function app", request.host));
+        }
+    };
+
+    let id = *id;
+    let allowlist = match get_allowlist(&id, secret) {
+        Some(allowlist) => allowlist,
+        None => {
+            send_error(&mut client, "407 Proxy Authentication Required", "Unknown function app", challenge);
+            return Err(format!("Request to {} with invalid credentials for function app {}", request.host, id));
+        }
+    };
+
+    if !is_allowed(&allowlist, &request.host, request.port) {
+        record_call(&id, &request.host, request.port, false);
+        send_error(
+            &mut client,
+            "403 Forbidden",
+            &format!("{}:{} is not in the egress allowlist for this function app", request.host, request.port),
+            "",
+        );
+        return Err(format!("Denied request from {} to {}:{}", id, request.host, request.port));
+    }
+
+    let mut upstream = match connect(&request.host, request.port) {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            send_error(&mut client, "502 Bad Gateway", &e, "");
+            return Err(e);
+        }
+    };
+
+    record_call(&id, &request.host, request.port, true);
+
+    // Tunnels are confirmed to the app, plain HTTP requests are passed on without the proxy headers. Connections
+    // are closed after each request so every request is checked against the allowlist
+    let forwarded = match &request.path {
+        None => client.write_all(format!("{} 200 Connection Established\r\n\r\n", request.version).as_bytes()),
+        Some(path) => {
+            let mut head = format!("{} {} {}\r\n", request.method, path, request.version);
+            for header in request.headers.iter() {
+                head.push_str(header);
+                head.push_str("\r\n");
+            }
+            head.push_str("Connection: close\r\n\r\n");
+            upstream.write_all(head.as_bytes())
+        },
+    };
+
+    // Anything the app sent after the headers has already been read into the buffer, so send it on first
+    let buffered = reader.buffer().to_vec();
+    let forwarded = forwarded.and_then(|_| upstream.write_all(&buffered));
+    if let Err(e) = forwarded {
+        return Err(format!("Error forwarding request to {}: {}", request.host, e));
+    }
+
+    let client = reader.into_inner();
+    let _ = client.set_read_timeout(None);
+    let (sent, received) = pipe(client, upstream);
+    record_bytes(&id, &request.host, request.port, sent + buffered.len() as u64, received);
+
+    Ok(())
+}
+
+/// Starts the egress proxy on a background thread if it is turned on, handling each connection on its own thread
+pub fn start_proxy() -> Result<(), String> {
+    let address = match get_proxy_address() {
+        Some(address) => address,
+        None => return Ok(()),
+    };
+
+    let listener = match listeners::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => return Err(format!("Error starting egress proxy on {}: {}", address, e)),
+    };
+
+    println!("Egress proxy listening on {}", address);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    thread::spawn(move || {
+                        if let Err(e) = handle_connection(stream) {
+                            println!("Egress proxy: {}", e);
+                        }
+                    });
+                },
+                Err(e) => println!("Egress proxy: error accepting connection: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets an allowlist from a list of destinations
+    fn allowlist(destinations: &[&str]) -> Option<Vec<String>> {
+        Some(destinations.iter().map(|destination| destination.to_string()).collect())
+    }
+
+    #[test]
+    fn splits_hosts_and_ports() {
+        assert_eq!(split_host_port("Example.com:443").unwrap(), ("example.com".to_string(), Some(443)));
+        assert_eq!(split_host_port("example.com").unwrap(), ("example.com".to_string(), None));
+        assert_eq!(split_host_port("[::1]:8080").unwrap(), ("::1".to_string(), Some(8080)));
+        assert_eq!(split_host_port("[::1]").unwrap(), ("::1".to_string(), None));
+    }
+
+    #[test]
+    fn refuses_invalid_hosts_and_ports() {
+        assert!(split_host_port("example.com:https").is_err());
+        assert!(split_host_port("example.com:70000").is_err());
+        assert!(split_host_port(":443").is_err());
+    }
+
+    #[test]
+    fn allows_everything_without_an_allowlist() {
+        assert!(is_allowed(&None, "example.com", 443));
+    }
+
+    #[test]
+    fn allows_only_the_destinations_in_the_allowlist() {
+        let allowlist = allowlist(&["api.example.com"]);
+
+        assert!(is_allowed(&allowlist, "api.example.com", 443));
+        assert!(!is_allowed(&allowlist, "example.com", 443));
+        assert!(!is_allowed(&allowlist, "api.example.com.evil.com", 443));
+        assert!(!is_allowed(&allowlist, "evilapi.example.com", 443));
+    }
+
+    #[test]
+    fn allows_subdomains_of_wildcards() {
+        let allowlist = allowlist(&["*.example.com"]);
+
+        assert!(is_allowed(&allowlist, "api.example.com", 443));
+        assert!(is_allowed(&allowlist, "a.b.example.com", 443));
+        assert!(!is_allowed(&allowlist, "example.com", 443));
+        assert!(!is_allowed(&allowlist, "evilexample.com", 443));
+    }
+
+    #[test]
+    fn allows_only_the_port_in_the_allowlist() {
+        let allowlist = allowlist(&["api.example.com:443"]);
+
+        assert!(is_allowed(&allowlist, "api.example.com", 443));
+        assert!(!is_allowed(&allowlist, "api.example.com", 22));
+    }
+
+    #[test]
+    fn tidies_up_allowlists() {
+        let validated = validate_allowlist(&[" API.example.com:443 ".to_string(), "[::1]:80".to_string()]).unwrap();
+
+        assert_eq!(validated, vec!["api.example.com:443".to_string(), "[::1]:80".to_string()]);
+    }
+
+    #[test]
+    fn refuses_invalid_allowlists() {
+        assert!(validate_allowlist(&["https://example.com".to_string()]).is_err());
+        assert!(validate_allowlist(&["example.com/path".to_string()]).is_err());
+        assert!(validate_allowlist(&["*.".to_string()]).is_err());
+    }
+
+    #[test]
+    fn reads_the_app_and_secret_from_proxy_credentials() {
+        let id = Uuid::new_v4();
+        let header = format!("Proxy-Authorization: Basic {}", base64::encode(format!("{}:secret", id)));
+
+        assert_eq!(parse_proxy_authorization(&header), Some((id, "secret".to_string())));
+    }
+
+    #[test]
+    fn refuses_proxy_credentials_without_a_secret() {
+        let header = format!("Proxy-Authorization: Basic {}", base64::encode(Uuid::new_v4().to_string()));
+
+        assert_eq!(parse_proxy_authorization(&header), None);
+    }
+
+    #[test]
+    fn matches_only_the_issued_secret() {
+        assert!(secrets_match("secret", "secret"));
+        assert!(!secrets_match("secreT", "secret"));
+        assert!(!secrets_match("secret", "secrets"));
+        assert!(!secrets_match("", "secret"));
+    }
+}
@@ -0,0 +1,18 @@
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+
+use rustless_shared::{ApiError, ErrorResponse};
+
+/// Builds the response for a failed request, with the status code for the error and the error as JSON, so clients can
+/// tell errors apart by their code
+pub fn response(error: ApiError, message: &str) -> HttpResponse {
+    let status = StatusCode::from_u16(error.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status).json(ErrorResponse::new(error, message))
+}
+
+/// Builds the response for a request that failed because of a file, such as a file in an uploaded zip file, with the
+/// file named in the error so clients can point to it
+pub fn file_response(error: ApiError, message: &str, file: &str) -> HttpResponse {
+    let status = StatusCode::from_u16(error.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status).json(ErrorResponse::for_file(error, message, file))
+}
@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+use rustless_shared::{EventsOptions, FunctionAppStatus, HostEvent, CONTAINER_CRASHED_EVENT, DEPLOY_PROGRESS_EVENT, QUOTA_WARNING_EVENT, STATUS_CHANGED_EVENT};
+
+use crate::container_states;
+use crate::database::Connection;
+use crate::docker;
+use crate::leases;
+use crate::replicas;
+use crate::storage;
+
+/// How often a comment is sent to subscribers so proxies keep the connection open, and closed connections are noticed
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often running apps are checked to see if their containers have exited
+const CRASH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The lease that makes sure only one host process checks for crashed apps
+const CRASH_CHECK_LEASE: &str = "crash-checker";
+
+/// A client listening to the event stream
+struct Subscriber {
+    // The events the client wants
+    options: EventsOptions,
+
+    // Where to send the events, formatted as server sent events
+    sender: UnboundedSender<String>,
+}
+
+/// The clients listening to the event stream
+static SUBSCRIBERS: OnceLock<Mutex<Vec<Subscriber>>> = OnceLock::new();
+
+/// Gets the clients listening to the event stream
+fn get_subscribers() -> &'static Mutex<Vec<Subscriber>> {
+    SUBSCRIBERS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Checks if an event matches the filters a client asked for
+fn is_match(options: &EventsOptions, event: &HostEvent) -> bool {
+    options.app.as_ref().is_none_or(|app| *app == event.app)
+        && options.namespace.as_ref().is_none_or(|namespace| *namespace == event.namespace)
+}
+
+/// Sends a frame to every subscriber the filter allows, forgetting any that have disconnected
+fn send_to_subscribers(frame: &str, filter: impl Fn(&EventsOptions) -> bool) {
+    if let Ok(mut subscribers) = get_subscribers().lock() {
+        subscribers.retain(|subscriber| !filter(&subscriber.options) || subscriber.sender.unbounded_send(frame.to_string()).is_ok());
+    }
+}
+
+/// Adds a client to the event stream, returning the server sent events for it
+pub fn subscribe(options: EventsOptions) -> UnboundedReceiver<String> {
+    let (sender, receiver) = mpsc::unbounded();
+
+    // Send a comment straight away, so the client knows it is connected before the first event
+    let _ = sender.unbounded_send(": connected\n\n".to_string());
+
+    if let Ok(mut subscribers) = get_subscribers().lock() {
+        subscribers.push(Subscriber { options, sender });
+    }
+
+    receiver
+}
+
+/// Sends an event to the clients listening to the event stream
+fn publish(event: HostEvent) {
+    let data = match serde_json::to_string(&event) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("Error serializing event: {}", e);
+            return;
+        }
+    };
+
+    let frame = format!("event: {}\ndata: {}\n\n", event.event, data);
+    send_to_subscribers(&frame, |options| is_match(options, &event));
+}
+
+/// Creates an event for a function app, with the name and namespace looked up from the database
+fn new_event(conn: &Connection, id: &Uuid, event: &str) -> Option<HostEvent> {
+    let (app, namespace) = match storage::get_function_app_name_and_namespace(conn, id) {
+        Ok(details) => details,
+        Err(e) => {
+            println!("Error getting function app for {} event: {}", event, e);
+            return None;
+        }
+    };
+
+    Some(HostEvent {
+        event: event.to_string(),
+        id: *id,
+        app,
+        namespace,
+        timestamp: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+        status: None,
+        stage: None,
+        deployment: None,
+        exit_code: None,
+        message: None,
+    })
+}
+
+/// Sends an event for a function app changing status
+pub fn publish_status_changed(conn: &Connection, id: &Uuid, status: &FunctionAppStatus) {
+    if let Some(mut event) = new_event(conn, id, STATUS_CHANGED_EVENT) {
+        event.status = Some(*status);
+        publish(event);
+    }
+}
+
+/// Sends an event for a deployment reaching a stage of the build, with the deployment number once it is known
+pub fn publish_deploy_progress(conn: &Connection, id: &Uuid, stage: &str, deployment: Option<u32>, message: Option<String>) {
+    if let Some(mut event) = new_event(conn, id, DEPLOY_PROGRESS_EVENT) {
+        event.stage = Some(stage.to_string());
+        event.deployment = deployment;
+        event.message = message;
+        publish(event);
+    }
+}
+
+/// Sends an event warning that adding, building, or starting a function app took a quota over its soft limit
+pub fn publish_quota_warning(conn: &Connection, id: &Uuid, message: &str) {
+    if let Some(mut event) = new_event(conn, id, QUOTA_WARNING_EVENT) {
+        event.message = Some(message.to_string());
+        publish(event);
+    }
+}
+
+/// Sends an event for the container of a running function app exiting without being stopped, with why it exited
+fn publish_container_crashed(conn: &Connection, id: &Uuid, exit_code: Option<i32>, reason: &str) {
+    if let Some(mut event) = new_event(conn, id, CONTAINER_CRASHED_EVENT) {
+        event.exit_code = exit_code;
+        event.message = Some(reason.to_string());
+        publish(event);
+    }
+}
+
+/// Checks the apps marked as running still have a running container, recording any that have crashed with why they
+/// exited, such as being killed for running out of memory
+///
+/// An app is only treated as crashed if its container is missing on two checks in a row, so an app that is
+/// part way through being stopped isn't reported
+fn check_for_crashes(missing: &mut HashSet<Uuid>) {
+    let apps = match storage::get_all_apps() {
+        Ok(apps) => apps,
+        Err(e) => {
+            println!("Error checking for crashed apps: {}", e);
+            return;
+        }
+    };
+
+    let mut still_missing = HashSet::new();
+
+    for app in apps.iter().filter(|app| app.status as u8 == FunctionAppStatus::Running as u8) {
+        // If docker can't be reached the app may still be running, so it isn't treated as crashed
+        match container_states::check_running(&app.name) {
+            Ok(false) => {},
+            _ => continue,
+        }
+
+        if !missing.contains(&app.id) {
+            still_missing.insert(app.id);
+            continue;
+        }
+
+        let mut conn = match storage::create_connection_for_app(&app.id) {
+            Ok(conn) => conn,
+            Err(e) => {
+                println!("Error recording crash for {}: {}", app.name, e);
+                continue;
+            }
+        };
+
+        let exit = docker::remove_exited_containers(&app.name);
+        let exit_code = exit.as_ref().map(|exit| exit.exit_code);
+        let reason = docker::get_exit_reason(exit.as_ref());
+        let crashed_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        match storage::complete_crash(&mut conn, &app.id, crashed_at, exit_code, &reason) {
+            Ok(true) => {
+                // Every container for the app has exited, including any extra replicas
+                if let Err(e) = replicas::clear(&conn, &app.id) {
+                    println!("Error clearing the replicas of {}: {}", app.name, e);
+                }
+                println!("Container for {} exited: {}", app.name, reason);
+                publish_container_crashed(&conn, &app.id, exit_code, &reason);
+            },
+            Ok(false) => {},
+            Err(e) => println!("Error recording crash for {}: {}", app.name, e),
+        }
+    }
+
+    *missing = still_missing;
+}
+
+/// Starts sending keepalives to the event stream and watching for crashed apps, on background threads for the
+/// life of the host. Every host process sends keepalives to its own subscribers, but only the one holding the
+/// lease checks for crashes
+pub fn start() {
+    thread::spawn(|| loop {
+        thread::sleep(KEEPALIVE_INTERVAL);
+        send_to_subscribers(": keepalive\n\n", |_| true);
+    });
+
+    thread::spawn(|| {
+        let mut missing = HashSet::new();
+
+        loop {
+            thread::sleep(CRASH_CHECK_INTERVAL);
+
+            // Another host process is checking, so forget what this one saw in case it takes over later
+            if !leases::is_leader(CRASH_CHECK_LEASE) {
+                missing.clear();
+                continue;
+            }
+
+            check_for_crashes(&mut missing);
+        }
+    });
+}
@@ -0,0 +1,180 @@
+use actix_web::web;
+
+#[cfg(feature = "fault-injection")]
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "fault-injection")]
+use std::time::Duration;
+
+#[cfg(feature = "fault-injection")]
+use actix_web::{delete, get, post, web::Json, HttpResponse};
+
+#[cfg(feature = "fault-injection")]
+use rustless_shared::{ApiError, InjectedFaults, InjectedFaultsReport};
+
+#[cfg(feature = "fault-injection")]
+use crate::errors;
+
+/// The error builds fail with when a build failure is injected
+#[cfg(feature = "fault-injection")]
+const INJECTED_BUILD_FAILURE: &str = "Injected build failure";
+
+/// The faults being injected, and how often they have been hit since they were set
+#[cfg(feature = "fault-injection")]
+#[derive(Default)]
+struct FaultState {
+    // The faults being injected
+    faults: InjectedFaults,
+
+    // The number of status writes since the faults were set, used to drop an exact percentage of them
+    status_writes: u64,
+
+    // The number of builds failed
+    failed_builds: u64,
+
+    // The number of requests delayed
+    delayed_requests: u64,
+
+    // The number of status writes dropped
+    dropped_status_writes: u64,
+}
+
+/// The faults being injected. Nothing is injected until they are set through the faults endpoint
+#[cfg(feature = "fault-injection")]
+static FAULTS: OnceLock<Mutex<FaultState>> = OnceLock::new();
+
+/// Runs the given function with the fault state
+#[cfg(feature = "fault-injection")]
+fn with_faults<T: Default>(f: impl FnOnce(&mut FaultState) -> T) -> T {
+    let faults = FAULTS.get_or_init(|| Mutex::new(FaultState::default()));
+    match faults.lock() {
+        Ok(mut faults) => f(&mut faults),
+        Err(_) => T::default(),
+    }
+}
+
+/// Gets the faults being injected and how often they have been hit
+#[cfg(feature = "fault-injection")]
+fn get_report() -> InjectedFaultsReport {
+    let faults = FAULTS.get_or_init(|| Mutex::new(FaultState::default()));
+    let (faults, failed_builds, delayed_requests, dropped_status_writes) = match faults.lock() {
+        Ok(state) => (state.faults.clone(), state.failed_builds, state.delayed_requests, state.dropped_status_writes),
+        Err(_) => (InjectedFaults::default(), 0, 0, 0),
+    };
+
+    InjectedFaultsReport {
+        faults,
+        failed_builds,
+        delayed_requests,
+        dropped_status_writes,
+    }
+}
+
+/// Gets the faults being injected, and how many times each has been hit since they were set
+#[cfg(feature = "fault-injection")]
+#[get("/faults")]
+async fn get_faults() -> HttpResponse {
+    HttpResponse::Ok().json(get_report())
+}
+
+/// Sets the faults to inject, replacing any already set and resetting the counts
+#[cfg(feature = "fault-injection")]
+#[post("/faults")]
+async fn set_faults(body: Json<InjectedFaults>) -> HttpResponse {
+    let faults = body.into_inner();
+    if faults.drop_status_writes_percent > 100 {
+        return errors::response(ApiError::InvalidFaults, "drop_status_writes_percent must be between 0 and 100");
+    }
+
+    println!("Injecting faults: fail next build {}, proxy delay {}ms, dropping {}% of status writes",
+             faults.fail_next_build, faults.proxy_delay_ms, faults.drop_status_writes_percent);
+
+    with_faults(|state| *state = FaultState { faults, ..FaultState::default() });
+    HttpResponse::Ok().json(get_report())
+}
+
+/// Stops injecting faults and resets the counts
+#[cfg(feature = "fault-injection")]
+#[delete("/faults")]
+async fn clear_faults() -> HttpResponse {
+    with_faults(|state| *state = FaultState::default());
+    HttpResponse::Ok().json(get_report())
+}
+
+/// Adds the fault injection endpoints. The endpoints only exist when the host is built with the fault-injection
+/// feature, which is for integration tests and must never be turned on in production
+#[cfg(feature = "fault-injection")]
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_faults)
+       .service(set_faults)
+       .service(clear_faults);
+}
+
+/// Adds the fault injection endpoints. The host was built without the fault-injection feature, so there are none
+#[cfg(not(feature = "fault-injection"))]
+pub fn configure(_cfg: &mut web::ServiceConfig) {
+}
+
+/// Gets the error to fail a build with if a build failure has been injected. The failure only applies to the next
+/// build, so it is cleared once taken
+#[cfg(feature = "fault-injection")]
+pub fn take_build_failure() -> Option<String> {
+    with_faults(|state| {
+        if !state.faults.fail_next_build {
+            return None;
+        }
+
+        state.faults.fail_next_build = false;
+        state.failed_builds += 1;
+        Some(INJECTED_BUILD_FAILURE.to_string())
+    })
+}
+
+/// Gets the error to fail a build with if a build failure has been injected. Without the fault-injection feature
+/// this is always None
+#[cfg(not(feature = "fault-injection"))]
+pub fn take_build_failure() -> Option<String> {
+    None
+}
+
+/// Waits before the gateway forwards a request to an app, if a proxy delay has been injected
+#[cfg(feature = "fault-injection")]
+pub async fn delay_proxy() {
+    let delay_ms = with_faults(|state| {
+        if state.faults.proxy_delay_ms > 0 {
+            state.delayed_requests += 1;
+        }
+        state.faults.proxy_delay_ms
+    });
+
+    if delay_ms > 0 {
+        actix_web::rt::time::sleep(Duration::from_millis(delay_ms)).await;
+    }
+}
+
+/// Waits before the gateway forwards a request to an app. Without the fault-injection feature there is no delay
+#[cfg(not(feature = "fault-injection"))]
+pub async fn delay_proxy() {
+}
+
+/// Gets if a status write should be dropped. Writes are dropped evenly rather than at random, so a test sees the
+/// exact percentage set: at 50% every second write is dropped
+#[cfg(feature = "fault-injection")]
+pub fn should_drop_status_write() -> bool {
+    with_faults(|state| {
+        let percent = state.faults.drop_status_writes_percent as u64;
+        let before = state.status_writes * percent / 100;
+        state.status_writes += 1;
+
+        let drop = state.status_writes * percent / 100 > before;
+        if drop {
+            state.dropped_status_writes += 1;
+        }
+        drop
+    })
+}
+
+/// Gets if a status write should be dropped. Without the fault-injection feature writes are never dropped
+#[cfg(not(feature = "fault-injection"))]
+pub fn should_drop_status_write() -> bool {
+    false
+}
@@ -1,42 +1,163 @@
-use std::{process::Command, io::Write};
+use std::io::{self, Read, Write};
 use std::fs::{self, File};
 use std::path::Path;
+use std::time::SystemTime;
 
-use rusqlite::Connection;
+use sha2::{Digest, Sha256};
 use tempfile::TempDir;
 use uuid::Uuid;
+use zip::read::ZipFile;
+use zip::ZipArchive;
 
 use rustless_shared::FunctionAppStatus;
 
-use crate::docker;
+use crate::build_dirs;
+use crate::container_states;
+use crate::database::Connection;
+use crate::phases::DeployPhase;
 use crate::storage;
 
-/// Creates a zip file from the binary data and unzips it in the temporary directory
-pub fn unzip_file_in_temp_dir(temp_dir: &TempDir, zip_file_data: &Vec<u8>) -> Result<(), String> {
-    // Create a zip file in the temporary directory
-    let zip_file_path = temp_dir.path().join("code.zip");
-    let zip_file = File::create(&zip_file_path);
-    let mut zip_file = match zip_file {
+/// The size of the pieces files are unzipped in, so the extract timeout is checked while large files are written
+const UNZIP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The name of the zip file uploaded code is saved to in the temporary directory the build runs in
+pub const ZIP_FILE_NAME: &str = "code.zip";
+
+/// Gets the hash of uploaded code along with what it is built with, so uploads of the same code can be spotted
+///
+/// The rendered Dockerfile covers the template, toolchain, and base image, so changing any of them changes the hash.
+/// The zip file is read from the temporary directory it was saved to, a piece at a time
+pub fn get_content_hash(temp_dir: &TempDir, dockerfile: &str, strict: bool) -> Result<String, String> {
+    let mut zip_file = match File::open(temp_dir.path().join(ZIP_FILE_NAME)) {
         Ok(file) => file,
-        Err(e) => return Err(format!("Error creating zip file: {}", e))
+        Err(e) => return Err(format!("Error opening zip file: {}", e)),
     };
 
-    // Write the zip file to the temporary directory
-    if zip_file.write_all(&zip_file_data).is_err() {
-        return Err("Error writing zip file".to_string());
+    let mut hasher = Sha256::new();
+    if let Err(e) = io::copy(&mut zip_file, &mut hasher) {
+        return Err(format!("Error reading zip file: {}", e));
     }
 
-    // Unzip the file
-    let unzip_result = Command::new("unzip")
-        .arg("code.zip")
-        .current_dir(temp_dir.path())
-        .output();
+    hasher.update(dockerfile.as_bytes());
+    hasher.update([strict as u8]);
+    Ok(hex::encode(hasher.finalize()))
+}
 
-    match unzip_result {
-        Ok(_) => {},
-        Err(e) => return Err(format!("Error unzipping file: {}", e))
+/// Opens the uploaded zip file saved in the temporary directory
+pub fn open_zip_file(temp_dir: &TempDir) -> Result<ZipArchive<File>, String> {
+    let zip_file = match File::open(temp_dir.path().join(ZIP_FILE_NAME)) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Error opening zip file: {}", e)),
+    };
+
+    match ZipArchive::new(zip_file) {
+        Ok(archive) => Ok(archive),
+        Err(e) => Err(format!("Error reading zip file, it may not be a valid zip file: {}", e)),
+    }
+}
+
+/// Gets the size of the files in a zip file once they are unzipped, in bytes, from the sizes stored in it
+pub fn get_unzipped_size(temp_dir: &TempDir) -> Result<u64, String> {
+    let mut archive = open_zip_file(temp_dir)?;
+
+    let mut size: u64 = 0;
+    for index in 0..archive.len() {
+        match archive.by_index_raw(index) {
+            Ok(entry) => size = size.saturating_add(entry.size()),
+            Err(e) => return Err(format!("Error reading the size of the zip file: {}", e)),
+        }
+    }
+
+    Ok(size)
+}
+
+/// Writes a file from the zip file to the given path a piece at a time, giving up if the extract timeout passes.
+/// Files can't be written larger than the size stored for them in the zip file
+fn unzip_entry(entry: &mut ZipFile, path: &Path, started: SystemTime) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            return Err(format!("Error creating directory {}: {}", parent.display(), e));
+        }
+    }
+
+    let mut file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Error creating file {}: {}", path.display(), e)),
     };
 
+    let size = entry.size();
+    let mut written: u64 = 0;
+    let mut buffer = vec![0; UNZIP_CHUNK_SIZE];
+    loop {
+        let read = match entry.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) => return Err(format!("Error unzipping {}: {}", entry.name(), e)),
+        };
+
+        written += read as u64;
+        if written > size {
+            return Err(format!("Error unzipping {}: it is larger than the zip file says", entry.name()));
+        }
+
+        if let Err(e) = file.write_all(&buffer[..read]) {
+            return Err(format!("Error writing file {}: {}", path.display(), e));
+        }
+
+        if DeployPhase::Extract.has_timed_out(started) {
+            return Err(DeployPhase::Extract.timeout_error());
+        }
+    }
+
+    // Keep executable scripts executable, as unzip would
+    #[cfg(unix)]
+    if let Some(mode) = entry.unix_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777));
+    }
+
+    Ok(())
+}
+
+/// Unzips the uploaded code saved in the temporary directory, giving up if this takes longer than the extract
+/// timeout. The build directory is checked for enough free space for the unzipped code first
+pub fn unzip_file_in_temp_dir(temp_dir: &TempDir) -> Result<(), String> {
+    let zip_file_path = temp_dir.path().join(ZIP_FILE_NAME);
+
+    build_dirs::check_free_space(get_unzipped_size(temp_dir)?)?;
+
+    // Unzip the file. A zip that expands to a huge number of files could hold up the deployment, so stop if it
+    // takes too long
+    let started = SystemTime::now();
+    let mut archive = open_zip_file(temp_dir)?;
+    for index in 0..archive.len() {
+        let mut entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("Error unzipping file: {}", e)),
+        };
+
+        // The paths were checked when the code was uploaded, but never write outside the temporary directory
+        let path = match entry.enclosed_name() {
+            Some(path) => temp_dir.path().join(path),
+            None => return Err(format!("Error unzipping file: {} is outside the zip file's folder", entry.name())),
+        };
+
+        if entry.is_dir() {
+            if let Err(e) = fs::create_dir_all(&path) {
+                return Err(format!("Error creating directory {}: {}", path.display(), e));
+            }
+        } else {
+            unzip_entry(&mut entry, &path, started)?;
+        }
+
+        if DeployPhase::Extract.has_timed_out(started) {
+            return Err(DeployPhase::Extract.timeout_error());
+        }
+    }
+
+    // Close the zip file so it can be deleted on Windows
+    drop(archive);
+
     // Delete the zip file
     let remove_result = fs::remove_file(&zip_file_path);
     match remove_result {
@@ -86,7 +207,7 @@ pub fn unzip_file_in_temp_dir(temp_dir: &TempDir, zip_file_data: &Vec<u8>) -> Re
 /// Gets if the function app is running under docker
 pub fn get_function_app_status(conn: &Connection, id: &Uuid) -> Result<FunctionAppStatus, String> {
     // Get the function app name to prove we have an app registered with this ID
-    let function_app_name = storage::get_function_app_name(&conn, &id);
+    let function_app_name = storage::get_function_app_name(conn, id);
     let function_app_name = match function_app_name {
         Ok(n) => n,
         Err(e) => {
@@ -95,7 +216,7 @@ pub fn get_function_app_status(conn: &Connection, id: &Uuid) -> Result<FunctionA
     };
 
     // Check if the function app is running under docker
-    let is_running = docker::is_container_running(&function_app_name);
+    let is_running = container_states::is_running(&function_app_name);
 
     // Update the status in the database
     if is_running {
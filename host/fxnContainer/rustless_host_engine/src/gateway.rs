@@ -0,0 +1,709 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use futures::channel::mpsc;
+use futures::{future, stream, SinkExt, Stream, StreamExt};
+use reqwest::{Client, Method};
+use uuid::Uuid;
+
+use rustless_shared::{ApiError, AppRouteManifest, BufferMetrics, MirrorConfig, RedactionRules};
+
+use crate::database::Connection;
+use crate::db_pool::DbPools;
+use crate::defaults;
+use crate::errors;
+use crate::faults;
+use crate::idle;
+use crate::mirror;
+use crate::pages;
+use crate::platform;
+use crate::recorder;
+use crate::replicas;
+use crate::storage;
+
+/// How long to wait for a function app to respond before giving up
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Headers that only apply to a single connection, so are not passed between the client and the app
+const HOP_BY_HOP_HEADERS: [&str; 5] = ["host", "connection", "content-length", "transfer-encoding", "keep-alive"];
+
+/// The route apps built with rustless_app serve their route manifest on
+const ROUTES_ROUTE: &str = "/__routes";
+
+/// The route called to check a new container is serving requests before traffic is moved to it
+pub const HEALTH_CHECK_ROUTE: &str = "/hello";
+
+/// The environment variable containing how long a new container has to pass its health check, in seconds
+const HEALTH_CHECK_TIMEOUT_ENV: &str = "RUSTLESS_HEALTH_CHECK_TIMEOUT";
+
+/// How long a new container has to pass its health check if the environment variable isn't set
+const DEFAULT_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the health check is tried while waiting for a new container to start
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The HTTP client used to call function apps, shared so connections can be reused
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Gets the HTTP client used to call function apps
+fn get_client() -> Result<&'static Client, String> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    let client = match Client::builder().timeout(UPSTREAM_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return Err(format!("Error creating HTTP client: {}", e)),
+    };
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// The environment variable containing how many bytes of a body the gateway buffers before streaming it,
+/// for apps that don't set their own thresholds
+const BUFFER_THRESHOLD_ENV: &str = "RUSTLESS_BUFFER_THRESHOLD";
+
+/// How many bytes of a body are buffered before it is streamed if the environment variable isn't set
+const DEFAULT_BUFFER_THRESHOLD: u64 = 1024 * 1024;
+
+/// How many chunks of a streamed request body can be waiting to be sent to the app
+const STREAM_CHANNEL_SIZE: usize = 16;
+
+/// How bodies have been buffered and streamed for each function app since the host started
+static BUFFER_METRICS: OnceLock<Mutex<HashMap<Uuid, BufferMetrics>>> = OnceLock::new();
+
+/// Gets how many bytes of a body are buffered before it is streamed, for apps that don't set their own thresholds
+pub fn get_default_buffer_threshold() -> u64 {
+    match std::env::var(BUFFER_THRESHOLD_ENV) {
+        Ok(value) => value.parse().unwrap_or(DEFAULT_BUFFER_THRESHOLD),
+        Err(_) => DEFAULT_BUFFER_THRESHOLD,
+    }
+}
+
+/// The environment variable that turns off the headers the gateway adds to app responses, for production
+const RESPONSE_HEADERS_ENV: &str = "RUSTLESS_GATEWAY_HEADERS";
+
+/// The response header with the name of the app that served the request
+pub const APP_HEADER: &str = "x-rustless-app";
+
+/// The response header with the deployment of the app that served the request
+pub const VERSION_HEADER: &str = "x-rustless-version";
+
+/// The response header saying if the request was the first served by the app since it started
+pub const COLD_START_HEADER: &str = "x-rustless-cold-start";
+
+/// The request header listing the addresses of the clients a request has come through, ending with the client that
+/// called the gateway
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// The ports of the containers of each function app that have served a request, so the first request to each
+/// container after it starts can be reported as a cold start
+static WARM_APPS: OnceLock<Mutex<HashMap<Uuid, HashSet<u16>>>> = OnceLock::new();
+
+/// Gets if the gateway adds the app, version, and cold start headers to app responses. They are on unless
+/// RUSTLESS_GATEWAY_HEADERS is set to off
+pub fn are_response_headers_enabled() -> bool {
+    match std::env::var(RESPONSE_HEADERS_ENV) {
+        Ok(value) => !matches!(value.to_lowercase().as_str(), "off" | "false" | "0"),
+        Err(_) => true,
+    }
+}
+
+/// Forgets that a function app has served requests, so the next request is reported as a cold start. Called
+/// when the app is started
+pub fn reset_cold_start(id: &Uuid) {
+    let warm_apps = WARM_APPS.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Ok(mut warm_apps) = warm_apps.lock() {
+        warm_apps.remove(id);
+    }
+}
+
+/// Checks if a request is the first served by the container of a function app on the given port since it started,
+/// marking the container as warm. A container on a new port has been restarted or added, so is cold
+fn check_cold_start(id: &Uuid, port: u16) -> bool {
+    let warm_apps = WARM_APPS.get_or_init(|| Mutex::new(HashMap::new()));
+    match warm_apps.lock() {
+        Ok(mut warm_apps) => warm_apps.entry(*id).or_default().insert(port),
+        Err(_) => false,
+    }
+}
+
+/// Adds the headers that say which app and deployment served a request, and if it was a cold start, so callers
+/// and monitors can tell which version they reached during a rollout. Any headers with the same names set by the
+/// app are replaced
+pub fn add_deployment_headers(response: &mut AppResponse, id: &Uuid, name: &str, port: u16, version: Option<u32>) {
+    let cold_start = check_cold_start(id, port);
+    let version = version.map(|version| version.to_string()).unwrap_or_else(|| "unknown".to_string());
+
+    response.headers.retain(|(header, _)| ![APP_HEADER, VERSION_HEADER, COLD_START_HEADER].contains(&header.to_lowercase().as_str()));
+    response.headers.push((APP_HEADER.to_string(), name.as_bytes().to_vec()));
+    response.headers.push((VERSION_HEADER.to_string(), version.into_bytes()));
+    response.headers.push((COLD_START_HEADER.to_string(), cold_start.to_string().into_bytes()));
+}
+
+/// Whether a body is from a request or a response
+#[derive(Clone, Copy)]
+enum BodyKind {
+    /// A request body, sent to the app
+    Request,
+
+    /// A response body, returned from the app
+    Response,
+}
+
+/// Updates the buffer metrics for a function app
+fn update_metrics(id: &Uuid, update: impl FnOnce(&mut BufferMetrics)) {
+    let metrics = BUFFER_METRICS.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Ok(mut metrics) = metrics.lock() {
+        update(metrics.entry(*id).or_default());
+    }
+}
+
+/// Gets how bodies have been buffered and streamed for a function app since the host started
+pub fn get_buffer_metrics(id: &Uuid) -> BufferMetrics {
+    let metrics = BUFFER_METRICS.get_or_init(|| Mutex::new(HashMap::new()));
+    match metrics.lock() {
+        Ok(metrics) => metrics.get(id).cloned().unwrap_or_default(),
+        Err(_) => BufferMetrics::default(),
+    }
+}
+
+/// Counts a body that was streamed rather than buffered
+fn count_streamed(id: &Uuid, kind: BodyKind) {
+    update_metrics(id, |metrics| match kind {
+        BodyKind::Request => metrics.requests_streamed += 1,
+        BodyKind::Response => metrics.responses_streamed += 1,
+    });
+}
+
+/// A buffered body, counted in the in flight bytes for the app until it is dropped
+pub struct BufferGuard {
+    // The function app the body is for
+    id: Uuid,
+
+    // The size of the body
+    bytes: u64,
+}
+
+impl BufferGuard {
+    /// Counts a buffered body for a function app
+    fn new(id: &Uuid, kind: BodyKind, bytes: u64) -> BufferGuard {
+        update_metrics(id, |metrics| {
+            match kind {
+                BodyKind::Request => metrics.requests_buffered += 1,
+                BodyKind::Response => metrics.responses_buffered += 1,
+            }
+            metrics.bytes_buffered += bytes;
+            metrics.largest_buffer = metrics.largest_buffer.max(bytes);
+            metrics.buffered_in_flight += bytes;
+            metrics.peak_buffered_in_flight = metrics.peak_buffered_in_flight.max(metrics.buffered_in_flight);
+        });
+
+        BufferGuard { id: *id, bytes }
+    }
+}
+
+impl Drop for BufferGuard {
+    fn drop(&mut self) {
+        let bytes = self.bytes;
+        update_metrics(&self.id, |metrics| metrics.buffered_in_flight = metrics.buffered_in_flight.saturating_sub(bytes));
+    }
+}
+
+/// The start of a body, read until it ended or went over the buffer threshold
+enum BodyStart<S> {
+    /// The whole body, which was within the threshold
+    Complete(web::Bytes),
+
+    /// The chunks read before the threshold was reached, and the stream with the rest of the body
+    Partial(Vec<web::Bytes>, S),
+}
+
+/// Reads a body until it ends or goes over the threshold
+async fn read_body_start<S, E>(mut stream: S, threshold: u64) -> Result<BodyStart<S>, E>
+where
+    S: Stream<Item = Result<web::Bytes, E>> + Unpin,
+{
+    let mut chunks = Vec::new();
+    let mut length: u64 = 0;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        length += chunk.len() as u64;
+        chunks.push(chunk);
+
+        if length > threshold {
+            return Ok(BodyStart::Partial(chunks, stream));
+        }
+    }
+
+    Ok(BodyStart::Complete(web::Bytes::from(chunks.concat())))
+}
+
+/// The body of a request to a function app
+pub enum RequestBody {
+    /// The whole body, sent to the app with a content length
+    Buffered(web::Bytes, BufferGuard),
+
+    /// The chunks read before the threshold was reached, and the rest of the body, streamed to the app
+    Streamed(Vec<web::Bytes>, web::Payload),
+}
+
+impl RequestBody {
+    /// Gets the body, or the start of it read before the rest was streamed, and whether the rest is being streamed
+    pub fn start(&self) -> (web::Bytes, bool) {
+        match self {
+            RequestBody::Buffered(body, _) => (body.clone(), false),
+            RequestBody::Streamed(chunks, _) => (web::Bytes::from(chunks.concat()), true),
+        }
+    }
+}
+
+/// Reads the body of a request to a function app, buffering it if it is within the threshold
+///
+/// Bodies with a content length over the threshold are streamed straight away. Bodies without one are
+/// buffered until they go over the threshold, then the rest is streamed. At least the given number of bytes are read
+/// before the body is streamed either way, so a copy of the start of it can be kept.
+pub async fn read_request_body(id: &Uuid, req: &HttpRequest, payload: web::Payload, threshold: u64, copy_bytes: u64) -> Result<RequestBody, String> {
+    let content_length = req.headers()
+        .get("content-length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    let threshold = match content_length {
+        Some(content_length) if content_length > threshold => 0,
+        _ => threshold,
+    };
+
+    match read_body_start(payload, threshold.max(copy_bytes)).await {
+        Ok(BodyStart::Complete(body)) => {
+            let guard = BufferGuard::new(id, BodyKind::Request, body.len() as u64);
+            Ok(RequestBody::Buffered(body, guard))
+        },
+        Ok(BodyStart::Partial(chunks, payload)) => {
+            count_streamed(id, BodyKind::Request);
+            Ok(RequestBody::Streamed(chunks, payload))
+        },
+        Err(e) => Err(format!("Error reading request body: {}", e)),
+    }
+}
+
+/// What routing a request to an app needs from its database, read before the request is routed so the connection
+/// isn't held while the app is started or the request is forwarded
+#[derive(Default)]
+struct RouteSettings {
+    // The maintenance message, if the app is in maintenance mode
+    maintenance: Option<String>,
+
+    // The port to send the request to, or none if the app isn't running
+    port: Option<u16>,
+
+    // Where to send a copy of the request, if mirroring is on
+    mirror_config: Option<MirrorConfig>,
+
+    // How many requests to keep, if recording is on
+    record_capacity: Option<usize>,
+
+    // What to redact from mirrored and recorded requests
+    redaction_rules: RedactionRules,
+
+    // How much of the request body to buffer before streaming it
+    request_threshold: u64,
+
+    // How much of the response body to buffer before streaming it
+    response_threshold: u64,
+}
+
+/// The body of a response from a function app
+pub enum ResponseBody {
+    /// The whole body, returned with a content length
+    Buffered(web::Bytes, BufferGuard),
+
+    /// The body, streamed to the caller as it arrives from the app
+    Streamed(Pin<Box<dyn Stream<Item = Result<web::Bytes, reqwest::Error>>>>),
+}
+
+/// The response from a function app
+pub struct AppResponse {
+    // The HTTP status code
+    pub status: u16,
+
+    // The response headers, without the hop by hop headers
+    pub headers: Vec<(String, Vec<u8>)>,
+
+    // The response body
+    pub body: ResponseBody,
+}
+
+impl AppResponse {
+    /// Converts the app response into the response for the caller
+    pub fn into_http_response(self) -> HttpResponse {
+        let mut response = HttpResponse::build(
+            actix_web::http::StatusCode::from_u16(self.status).unwrap_or(actix_web::http::StatusCode::BAD_GATEWAY)
+        );
+        for (name, value) in self.headers.iter() {
+            response.insert_header((name.as_str(), value.as_slice()));
+        }
+
+        match self.body {
+            ResponseBody::Buffered(body, _guard) => response.body(body),
+            ResponseBody::Streamed(stream) => response.streaming(stream),
+        }
+    }
+}
+
+/// Gets the X-Forwarded-For header to send to an app: the addresses the request came through, if any, followed by
+/// the client that called the gateway. IPv4 clients on a dual-stack listener are written as plain IPv4 addresses,
+/// and IPv6 addresses are written without brackets or a port
+fn get_forwarded_for(req: &HttpRequest) -> Option<String> {
+    let mut addresses: Vec<String> = req.headers()
+        .get_all(FORWARDED_FOR_HEADER)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if let Some(peer) = req.peer_addr() {
+        addresses.push(peer.ip().to_canonical().to_string());
+    }
+
+    match addresses.is_empty() {
+        true => None,
+        false => Some(addresses.join(", ")),
+    }
+}
+
+/// Forwards a request to the function app running on the given port, returning the response from the app
+///
+/// Response bodies within the threshold are buffered, larger ones are streamed to the caller.
+/// This returns an error if the app can't be reached, so the caller can show a bad gateway page.
+pub async fn forward_request(id: &Uuid, req: &HttpRequest, body: RequestBody, port: u16, route: &str, response_threshold: u64) -> Result<AppResponse, String> {
+    let client = get_client()?;
+
+    // Build the URL for the app, keeping the query string
+    let mut url = format!("{}/{}", platform::get_app_url(port), route);
+    if !req.query_string().is_empty() {
+        url = format!("{}?{}", url, req.query_string());
+    }
+
+    let method = match Method::from_bytes(req.method().as_str().as_bytes()) {
+        Ok(method) => method,
+        Err(e) => return Err(format!("Unsupported method: {}", e)),
+    };
+
+    // Copy the request headers across to the app
+    let mut upstream_req = client.request(method, url);
+    for (name, value) in req.headers() {
+        if !HOP_BY_HOP_HEADERS.contains(&name.as_str()) && name.as_str() != FORWARDED_FOR_HEADER {
+            upstream_req = upstream_req.header(name.as_str(), value.as_bytes());
+        }
+    }
+
+    if let Some(forwarded_for) = get_forwarded_for(req) {
+        upstream_req = upstream_req.header(FORWARDED_FOR_HEADER, forwarded_for);
+    }
+
+    // Buffered bodies are sent with a content length. Streamed bodies are sent chunked, passed through a channel
+    // as the request payload can't be sent between threads
+    let upstream_res = match body {
+        RequestBody::Buffered(body, _guard) => upstream_req.body(body).send().await,
+        RequestBody::Streamed(chunks, mut payload) => {
+            let (mut sender, receiver) = mpsc::channel::<Result<web::Bytes, io::Error>>(STREAM_CHANNEL_SIZE);
+            let pump = async move {
+                for chunk in chunks {
+                    if sender.send(Ok(chunk)).await.is_err() {
+                        return;
+                    }
+                }
+
+                while let Some(chunk) = payload.next().await {
+                    let chunk = chunk.map_err(|e| io::Error::other(e.to_string()));
+                    if sender.send(chunk).await.is_err() {
+                        return;
+                    }
+                }
+            };
+
+            let (upstream_res, _) = future::join(upstream_req.body(reqwest::Body::wrap_stream(receiver)).send(), pump).await;
+            upstream_res
+        },
+    };
+
+    let upstream_res = match upstream_res {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error calling function app: {}", e)),
+    };
+
+    // Copy the status and headers from the app response
+    let status = upstream_res.status().as_u16();
+    let headers = upstream_res.headers()
+        .iter()
+        .filter(|(name, _)| !HOP_BY_HOP_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+        .collect();
+
+    // Responses known to be over the threshold are streamed straight away
+    let threshold = match upstream_res.content_length() {
+        Some(content_length) if content_length > response_threshold => 0,
+        _ => response_threshold,
+    };
+
+    let body = match read_body_start(Box::pin(upstream_res.bytes_stream()), threshold).await {
+        Ok(BodyStart::Complete(body)) => {
+            let guard = BufferGuard::new(id, BodyKind::Response, body.len() as u64);
+            ResponseBody::Buffered(body, guard)
+        },
+        Ok(BodyStart::Partial(chunks, rest)) => {
+            count_streamed(id, BodyKind::Response);
+            ResponseBody::Streamed(Box::pin(stream::iter(chunks.into_iter().map(Ok)).chain(rest)))
+        },
+        Err(e) => return Err(format!("Error reading function app response: {}", e)),
+    };
+
+    Ok(AppResponse { status, headers, body })
+}
+
+/// Gets the route manifest from the function app running on the given port
+///
+/// Apps built with rustless_app serve their routes at /__routes. This returns None for apps that don't.
+pub async fn get_app_routes(port: u16) -> Result<Option<AppRouteManifest>, String> {
+    let client = get_client()?;
+
+    let res = match client.get(format!("{}{}", platform::get_app_url(port), ROUTES_ROUTE)).send().await {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error calling function app: {}", e)),
+    };
+
+    if res.status() == 404 {
+        return Ok(None);
+    }
+
+    if !res.status().is_success() {
+        return Err(format!("Function app returned status code {} for its routes", res.status()));
+    }
+
+    let body = match res.bytes().await {
+        Ok(body) => body,
+        Err(e) => return Err(format!("Error reading function app routes: {}", e)),
+    };
+
+    match serde_json::from_slice::<AppRouteManifest>(&body) {
+        Ok(manifest) => Ok(Some(manifest)),
+        Err(e) => Err(format!("Error parsing function app routes: {}", e)),
+    }
+}
+
+/// Gets how long a new container has to pass its health check
+fn get_health_check_timeout() -> Duration {
+    match std::env::var(HEALTH_CHECK_TIMEOUT_ENV) {
+        Ok(value) => match value.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+            _ => {
+                println!("Ignoring invalid {}: {}", HEALTH_CHECK_TIMEOUT_ENV, value);
+                DEFAULT_HEALTH_CHECK_TIMEOUT
+            }
+        },
+        Err(_) => DEFAULT_HEALTH_CHECK_TIMEOUT,
+    }
+}
+
+/// Waits for the app on the given port to answer its health check, returning how long it took
+///
+/// The container takes a moment to start listening after docker starts it, so connection errors and failed
+/// responses are retried until the health check timeout
+pub async fn wait_until_healthy(port: u16) -> Result<Duration, String> {
+    let client = get_client()?;
+    let url = format!("{}{}", platform::get_app_url(port), HEALTH_CHECK_ROUTE);
+    let timeout = get_health_check_timeout();
+    let start = Instant::now();
+
+    loop {
+        let last_error = match client.get(&url).timeout(HEALTH_CHECK_INTERVAL * 4).send().await {
+            Ok(res) if res.status().is_success() => return Ok(start.elapsed()),
+            Ok(res) => format!("{} returned status code {}", HEALTH_CHECK_ROUTE, res.status()),
+            Err(e) => format!("Error calling {}: {}", HEALTH_CHECK_ROUTE, e),
+        };
+
+        if start.elapsed() >= timeout {
+            return Err(format!("The new container did not pass its health check within {} seconds. {}", timeout.as_secs(), last_error));
+        }
+
+        actix_web::rt::time::sleep(HEALTH_CHECK_INTERVAL).await;
+    }
+}
+
+/// Sends a request to the given route on a function app, showing an error page if the app can't handle it. This is
+/// the api/{appname}/{approute} routing proxy, also used for the default app. Apps that are idle are started, and the
+/// request is mirrored, recorded, and shared between replicas as the app is set up to
+pub async fn route_to_app(pools: &web::Data<DbPools>, req: &HttpRequest, name: &String, route: &str, payload: web::Payload) -> HttpResponse {
+    // Unknown apps get the 404 page
+    let (conn, id) = match crate::resolve_function_app_name(pools, name).await {
+        Ok(resolved) => resolved,
+        Err(res) if res.status() == 404 => return crate::not_found(req),
+        Err(res) => return *res,
+    };
+
+    // Read everything routing needs on the blocking thread pool, giving the connection back to the pool before the
+    // app is started or the request is forwarded, as either can take as long as the upstream timeout
+    let is_replay = req.headers().contains_key(recorder::REPLAY_HEADER);
+    let settings_name = name.to_string();
+    let settings = match web::block(move || get_route_settings(&conn, &id, &settings_name, is_replay)).await {
+        Ok(Ok(settings)) => settings,
+        Ok(Err(e)) => return errors::response(ApiError::Internal, &e),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    // Apps in maintenance mode get the maintenance page, even though they are still running
+    if let Some(message) = settings.maintenance {
+        return pages::app_error_response(&id, name, pages::AppErrorPage::Maintenance(message));
+    }
+
+    // If the app isn't running there is nothing to route to, unless it was stopped for being idle and can be
+    // started for this request
+    let port = match settings.port {
+        Some(port) => port,
+        None => match idle::cold_start(id, name).await {
+            Ok(Some(port)) => port,
+            Ok(None) => return pages::app_error_response(&id, name, pages::AppErrorPage::Unavailable),
+            Err(e) => {
+                println!("Error starting idle app {}: {}", name, e);
+                return pages::app_error_response(&id, name, pages::AppErrorPage::Unavailable);
+            }
+        },
+    };
+
+    let RouteSettings { mirror_config, record_capacity, redaction_rules, request_threshold, response_threshold, .. } = settings;
+
+    // Only the requests in the sample are mirrored. Mirroring and recording keep the start of the request body, so
+    // that much is read before the rest is streamed
+    let mirror_config = mirror_config.filter(|config| mirror::is_sampled(config.sample_rate));
+    let mirror_bytes = mirror_config.as_ref().map(|config| config.max_body_bytes as u64).unwrap_or(0);
+    let record_bytes = match record_capacity {
+        Some(_) => recorder::MAX_BODY_BYTES as u64,
+        None => 0,
+    };
+
+    let body = match read_request_body(&id, req, payload, request_threshold, mirror_bytes.max(record_bytes)).await {
+        Ok(body) => body,
+        Err(e) => return errors::response(ApiError::BadRequest, &e),
+    };
+
+    // Send a copy of the request to the mirror sink if it is in the sample. This happens in the background
+    // so it doesn't affect the response
+    if let Some(config) = &mirror_config {
+        mirror::mirror_request(config, &redaction_rules, name, req, &body, route);
+    }
+
+    // Start recording the request if recording is on, so it can be replayed later
+    let record = record_capacity.map(|capacity| recorder::record_request(&id, capacity, &redaction_rules, req, &body, route));
+
+    faults::delay_proxy().await;
+
+    let mut response = match forward_request(&id, req, body, port, route, response_threshold).await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("Error routing request to {}: {}", name, e);
+            replicas::mark_unhealthy(port);
+            return pages::app_error_response(&id, name, pages::AppErrorPage::BadGateway);
+        }
+    };
+
+    // Record the response with the request. Streamed responses are recorded once they have been sent
+    if let Some(record) = record {
+        response = record.finish(response);
+    }
+
+    // Say which deployment served the request, unless the headers have been turned off. The connection was given
+    // back before the request was forwarded, so this takes a new one
+    if are_response_headers_enabled() {
+        let pools = pools.clone();
+        let version = web::block(move || get_latest_deployment(&pools, &id)).await.map_err(|e| e.to_string());
+        let version = match version {
+            Ok(Ok(version)) => version,
+            Ok(Err(e)) | Err(e) => {
+                println!("Error getting deployment for {}: {}", name, e);
+                None
+            }
+        };
+        add_deployment_headers(&mut response, &id, name, port, version);
+    }
+
+    response.into_http_response()
+}
+
+/// Reads what routing a request to an app needs from its database, counting the request as activity so the app
+/// isn't stopped for being idle. Apps scaled out share requests between their healthy replicas in turn, so the port
+/// is the replica this request goes to. Requests that are themselves replays aren't recorded
+fn get_route_settings(conn: &Connection, id: &Uuid, name: &str, is_replay: bool) -> Result<RouteSettings, String> {
+    let maintenance = storage::get_function_app_maintenance(conn, id).map_err(|e| e.to_string())?;
+    if maintenance.is_some() {
+        return Ok(RouteSettings { maintenance, ..Default::default() });
+    }
+
+    idle::record_request(conn, id);
+
+    let port = match storage::get_function_app_port(conn, id) {
+        Ok(Some(port)) => Some(replicas::pick_port(conn, id, port)),
+        Ok(None) => None,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let mirror_config = match storage::get_function_app_mirror(conn, id) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Error getting mirror config for {}: {}", name, e);
+            None
+        }
+    };
+
+    // Replaying doesn't push out the requests being replayed
+    let record_capacity = match storage::get_function_app_recording(conn, id) {
+        Ok(Some(_)) if is_replay => None,
+        Ok(capacity) => capacity,
+        Err(e) => {
+            println!("Error getting recording setting for {}: {}", name, e);
+            None
+        }
+    };
+
+    // Mirrored and recorded requests are redacted first. If the rules can't be read, the request isn't mirrored or
+    // recorded, rather than keeping something that should have been redacted
+    let redaction_rules = match mirror_config.is_some() || record_capacity.is_some() {
+        true => storage::get_function_app_redaction(conn, id).map(Option::unwrap_or_default),
+        false => Ok(RedactionRules::default()),
+    };
+    let (mirror_config, record_capacity, redaction_rules) = match redaction_rules {
+        Ok(rules) => (mirror_config, record_capacity, rules),
+        Err(e) => {
+            println!("Error getting redaction rules for {}: {}", name, e);
+            (None, None, RedactionRules::default())
+        }
+    };
+
+    // The headers the mirror config redacts are redacted from recorded requests too, so they aren't kept in memory
+    let redaction_rules = match &mirror_config {
+        Some(config) => mirror::add_redacted_headers(config, &redaction_rules),
+        None => redaction_rules,
+    };
+
+    // Work out how much of the bodies to buffer before streaming them. Mirroring and recording only keep the start
+    // of the bodies, so don't change this
+    let (request_threshold, response_threshold) = match defaults::get_effective_config(conn, id) {
+        Ok(effective) => (effective.buffer_request_threshold.value, effective.buffer_response_threshold.value),
+        Err(e) => return Err(e),
+    };
+
+    Ok(RouteSettings { maintenance, port, mirror_config, record_capacity, redaction_rules, request_threshold, response_threshold })
+}
+
+/// Gets the latest deployment of an app, connecting to the database that holds it
+fn get_latest_deployment(pools: &DbPools, id: &Uuid) -> Result<Option<u32>, String> {
+    let conn = storage::connect_for_app(pools, id)?;
+    storage::get_latest_deployment(&conn, id).map_err(|e| e.to_string())
+}
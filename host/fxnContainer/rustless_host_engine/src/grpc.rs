@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+use hyper::body::{Bytes, HttpBody, SizeHint};
+use hyper::client::HttpConnector;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Request, Response, Server, StatusCode};
+use uuid::Uuid;
+
+use rustless_shared::GrpcMethodMetrics;
+
+use crate::platform;
+use crate::listeners;
+use crate::storage;
+
+/// The environment variable containing the address for the gRPC gateway to listen on, such as 0.0.0.0:8082.
+/// The gateway only runs when this is set
+///
+/// gRPC needs HTTP/2 with trailers, which the main gateway can't send, so gRPC calls go through their own listener.
+/// Calls are plain HTTP/2 (h2c), so put a TLS terminating load balancer in front of the gateway to call it over TLS
+pub const GRPC_GATEWAY_ENV: &str = "RUSTLESS_GRPC_GATEWAY";
+
+/// How many threads the gateway uses to proxy calls
+const GATEWAY_THREADS: usize = 2;
+
+/// The gRPC status for a call that succeeded
+const GRPC_OK: u32 = 0;
+
+/// The gRPC status for a call the client cancelled, such as by closing the stream before the response ended
+const GRPC_CANCELLED: u32 = 1;
+
+/// The gRPC status for a call whose response ended without a status
+const GRPC_UNKNOWN: u32 = 2;
+
+/// The gRPC status for a call to a service no app serves
+const GRPC_UNIMPLEMENTED: u32 = 12;
+
+/// The gRPC status for a call the gateway failed to handle
+const GRPC_INTERNAL: u32 = 13;
+
+/// The gRPC status for a call to an app that isn't running, is in maintenance mode, or can't be reached
+const GRPC_UNAVAILABLE: u32 = 14;
+
+/// The header and trailer gRPC sends the status of a call in
+const GRPC_STATUS_HEADER: &str = "grpc-status";
+
+/// The header and trailer gRPC sends the error message for a call in
+const GRPC_MESSAGE_HEADER: &str = "grpc-message";
+
+/// The calls made to each method of each app since the host started, keyed by app ID then method
+static METHODS: OnceLock<Mutex<HashMap<Uuid, HashMap<String, GrpcMethodMetrics>>>> = OnceLock::new();
+
+/// The HTTP/2 client used to call apps, shared so connections can be reused
+static CLIENT: OnceLock<Client<HttpConnector, MeteredBody>> = OnceLock::new();
+
+/// Gets the metrics for all the apps
+fn get_all_methods() -> &'static Mutex<HashMap<Uuid, HashMap<String, GrpcMethodMetrics>>> {
+    METHODS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Gets the address the gateway listens on, or None if the gateway is turned off
+pub fn get_gateway_address() -> Option<String> {
+    match std::env::var(GRPC_GATEWAY_ENV) {
+        Ok(address) if !address.trim().is_empty() => Some(address.trim().to_string()),
+        _ => None,
+    }
+}
+
+/// Gets if the gRPC gateway is turned on
+pub fn is_enabled() -> bool {
+    get_gateway_address().is_some()
+}
+
+/// Checks the services are fully qualified gRPC service names, such as helloworld.Greeter, returning them without
+/// duplicates
+pub fn validate_services(services: &[String]) -> Result<Vec<String>, String> {
+    let mut validated: Vec<String> = Vec::new();
+
+    for service in services {
+        let service = service.trim();
+        let valid = !service.is_empty()
+            && service.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+
+        if !valid {
+            return Err(format!("Invalid service '{}': use the fully qualified service name, such as helloworld.Greeter", service));
+        }
+
+        if !validated.iter().any(|existing| existing == service) {
+            validated.push(service.to_string());
+        }
+    }
+
+    Ok(validated)
+}
+
+/// Gets the calls made to the methods of a function app since the host started, sorted by method
+pub fn get_method_metrics(id: &Uuid) -> Vec<GrpcMethodMetrics> {
+    let mut methods: Vec<GrpcMethodMetrics> = match get_all_methods().lock() {
+        Ok(all_methods) => match all_methods.get(id) {
+            Some(methods) => methods.values().cloned().collect(),
+            None => Vec::new(),
+        },
+        Err(_) => Vec::new(),
+    };
+
+    methods.sort_by(|a, b| a.method.cmp(&b.method));
+    methods
+}
+
+/// Forgets the calls made to a function app, such as when it is deleted
+pub fn clear(id: &Uuid) {
+    if let Ok(mut all_methods) = get_all_methods().lock() {
+        all_methods.remove(id);
+    }
+}
+
+/// Updates the metrics for a method of a function app
+fn update_method(id: &Uuid, method: &str, update: impl FnOnce(&mut GrpcMethodMetrics)) {
+    if let Ok(mut all_methods) = get_all_methods().lock() {
+        let metrics = all_methods
+            .entry(*id)
+            .or_default()
+            .entry(method.to_string())
+            .or_insert_with(|| GrpcMethodMetrics {
+                method: method.to_string(),
+                ..Default::default()
+            });
+
+        update(metrics);
+    }
+}
+
+/// A call through the gateway, recorded in the method metrics when it starts and when it ends
+struct GrpcCall {
+    // The ID of the app the call was routed to
+    id: Uuid,
+
+    // The full method name, such as /helloworld.Greeter/SayHello
+    method: String,
+
+    // When the call arrived
+    started: Instant,
+
+    // The status the app sent in the response headers, for calls that fail without sending a response body
+    header_status: OnceLock<u32>,
+
+    // Whether the call has ended, so it is only recorded once
+    finished: AtomicBool,
+}
+
+impl GrpcCall {
+    /// Starts recording a call to a method of a function app
+    fn start(id: Uuid, method: String) -> GrpcCall {
+        update_method(&id, &method, |metrics| {
+            metrics.calls += 1;
+            metrics.in_flight += 1;
+            metrics.last_called = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        });
+
+        GrpcCall {
+            id,
+            method,
+            started: Instant::now(),
+            header_status: OnceLock::new(),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    /// Records bytes received from the client or sent back to it
+    fn add_bytes(&self, bytes: usize, sent: bool) {
+        update_method(&self.id, &self.method, |metrics| match sent {
+            true => metrics.bytes_sent += bytes as u64,
+            false => metrics.bytes_received += bytes as u64,
+        });
+    }
+
+    /// Records the end of the call with the given status. The status from the response headers is used if the
+    /// response had no trailers
+    fn finish(&self, status: Option<u32>) {
+        if self.finished.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let status = status.or(self.header_status.get().copied()).unwrap_or(GRPC_UNKNOWN);
+        let duration_ms = self.started.elapsed().as_millis() as u64;
+
+        update_method(&self.id, &self.method, |metrics| {
+            metrics.in_flight = metrics.in_flight.saturating_sub(1);
+            *metrics.status_codes.entry(status).or_default() += 1;
+            if status != GRPC_OK {
+                metrics.errors += 1;
+            }
+            metrics.total_duration_ms += duration_ms;
+            metrics.max_duration_ms = metrics.max_duration_ms.max(duration_ms);
+        });
+    }
+}
+
+/// A request or response body passed through the gateway, counting its bytes for the method metrics. The call
+/// ends when the response trailers arrive, or when the response is dropped before it ends
+struct MeteredBody {
+    // The body being passed through
+    inner: Body,
+
+    // The call the body is part of, or None for responses made by the gateway
+    call: Option<Arc<GrpcCall>>,
+
+    // Whether this is the response, which is sent to the client, rather than the request
+    is_response: bool,
+}
+
+impl MeteredBody {
+    /// Creates a body for a response made by the gateway, which isn't recorded
+    fn empty() -> MeteredBody {
+        MeteredBody {
+            inner: Body::empty(),
+            call: None,
+            is_response: true,
+        }
+    }
+}
+
+impl HttpBody for MeteredBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let result = Pin::new(&mut self.inner).poll_data(cx);
+
+        if let (Poll::Ready(Some(Ok(data))), Some(call)) = (&result, &self.call) {
+            call.add_bytes(data.len(), self.is_response);
+        }
+
+        result
+    }
+
+    fn poll_trailers(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let result = Pin::new(&mut self.inner).poll_trailers(cx);
+
+        if self.is_response {
+            if let (Poll::Ready(Ok(trailers)), Some(call)) = (&result, &self.call) {
+                call.finish(trailers.as_ref().and_then(get_grpc_status));
+            }
+        }
+
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for MeteredBody {
+    fn drop(&mut self) {
+        // Responses that end in their headers are dropped without being read, otherwise the client went away
+        if let (true, Some(call)) = (self.is_response, &self.call) {
+            call.finish(call.header_status.get().copied().or(Some(GRPC_CANCELLED)));
+        }
+    }
+}
+
+/// Gets the gRPC status from response headers or trailers
+fn get_grpc_status(headers: &HeaderMap) -> Option<u32> {
+    headers.get(GRPC_STATUS_HEADER)?.to_str().ok()?.parse().ok()
+}
+
+/// Gets the service from the path of a gRPC call, such as helloworld.Greeter from /helloworld.Greeter/SayHello
+fn get_service(path: &str) -> Option<&str> {
+    match path.strip_prefix('/')?.split_once('/') {
+        Some((service, method)) if !service.is_empty() && !method.is_empty() && !method.contains('/') => Some(service),
+        _ => None,
+    }
+}
+
+/// Creates a response for a call the gateway can't route, with the status in the headers as gRPC expects for a
+/// call that fails before any messages are sent
+fn grpc_error(status: u32, message: &str) -> Response<MeteredBody> {
+    let mut response = Response::new(MeteredBody::empty());
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    headers.insert(GRPC_STATUS_HEADER, HeaderValue::from(status));
+
+    // The message must be printable ASCII, with percent signs escaped
+    let message: String = message.chars().map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '%' { c } else { '?' }).collect();
+    if let Ok(message) = HeaderValue::from_str(&message) {
+        headers.insert(GRPC_MESSAGE_HEADER, message);
+    }
+
+    response
+}
+
+/// Gets the client used to call apps. Apps are called with HTTP/2 without TLS, as gRPC servers expect
+fn get_client() -> &'static Client<HttpConnector, MeteredBody> {
+    CLIENT.get_or_init(|| Client::builder().http2_only(true).build_http())
+}
+
+/// Where a call to a gRPC service is sent
+struct GrpcTarget {
+    // The ID of the app that serves the service
+    id: Uuid,
+
+    // The port the app is running on, or None if it isn't running
+    port: Option<u16>,
+
+    // The maintenance message if the app is in maintenance mode
+    maintenance: Option<String>,
+}
+
+/// Finds the app that serves a gRPC service, or None if no app serves it
+fn find_target(service: &str) -> Result<Option<GrpcTarget>, String> {
+    let (conn, id) = match storage::find_app_for_grpc_service(service)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    Ok(Some(GrpcTarget {
+        id,
+        port: storage::get_function_app_port(&conn, &id).map_err(|e| e.to_string())?,
+        maintenance: storage::get_function_app_maintenance(&conn, &id).map_err(|e| e.to_string())?,
+    }))
+}
+
+/// Routes a gRPC call to the app that serves its service
+///
+/// Calls are streamed both ways, so client, server, and bidirectional streaming calls all work. Calls that can't
+/// be routed get a gRPC error rather than an HTTP error, so clients report them properly
+async fn handle_call(req: Request<Body>) -> Result<Response<MeteredBody>, Infallible> {
+    let is_grpc = req.headers().get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(|content_type| content_type.starts_with("application/grpc"))
+        .unwrap_or(false);
+
+    if !is_grpc {
+        let mut response = Response::new(MeteredBody::empty());
+        *response.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        return Ok(response);
+    }
+
+    let method = req.uri().path().to_string();
+    let service = match get_service(&method) {
+        Some(service) => service.to_string(),
+        None => return Ok(grpc_error(GRPC_UNIMPLEMENTED, &format!("{} is not a gRPC method", method))),
+    };
+
+    // Looking up the app uses the database, so keep it off the threads handling calls
+    let lookup_service = service.clone();
+    let target = match tokio::task::spawn_blocking(move || find_target(&lookup_service)).await {
+        Ok(Ok(Some(target))) => target,
+        Ok(Ok(None)) => return Ok(grpc_error(GRPC_UNIMPLEMENTED, &format!("No function app serves {}", service))),
+        Ok(Err(e)) => return Ok(grpc_error(GRPC_INTERNAL, &e)),
+        Err(e) => return Ok(grpc_error(GRPC_INTERNAL, &e.to_string())),
+    };
+
+    if let Some(message) = target.maintenance {
+        let message = if message.is_empty() { "The function app is in maintenance mode".to_string() } else { message };
+        return Ok(grpc_error(GRPC_UNAVAILABLE, &message));
+    }
+
+    let port = match target.port {
+        Some(port) => port,
+        None => return Ok(grpc_error(GRPC_UNAVAILABLE, "The function app is not running")),
+    };
+
+    let call = Arc::new(GrpcCall::start(target.id, method));
+
+    // Send the call to the app, keeping the path, headers, and body as they are
+    let (mut parts, body) = req.into_parts();
+    let path = parts.uri.path_and_query().map(|path| path.as_str()).unwrap_or("/");
+    parts.uri = match format!("{}{}", platform::get_app_url(port), path).parse() {
+        Ok(uri) => uri,
+        Err(e) => {
+            call.finish(Some(GRPC_INTERNAL));
+            return Ok(grpc_error(GRPC_INTERNAL, &format!("Error creating the URL for the function app: {}", e)));
+        }
+    };
+    parts.headers.remove(header::HOST);
+
+    let body = MeteredBody {
+        inner: body,
+        call: Some(call.clone()),
+        is_response: false,
+    };
+
+    match get_client().request(Request::from_parts(parts, body)).await {
+        Ok(response) => {
+            let (parts, body) = response.into_parts();
+            if let Some(status) = get_grpc_status(&parts.headers) {
+                let _ = call.header_status.set(status);
+            }
+
+            Ok(Response::from_parts(parts, MeteredBody {
+                inner: body,
+                call: Some(call),
+                is_response: true,
+            }))
+        },
+        Err(e) => {
+            println!("gRPC gateway: error calling {}: {}", call.method, e);
+            call.finish(Some(GRPC_UNAVAILABLE));
+            Ok(grpc_error(GRPC_UNAVAILABLE, "The function app is not responding"))
+        }
+    }
+}
+
+/// Starts the gRPC gateway if it is turned on. The gateway runs on its own threads, as it is built on hyper
+/// rather than actix-web so it can send trailers
+pub fn start() -> Result<(), String> {
+    let address = match get_gateway_address() {
+        Some(address) => address,
+        None => return Ok(()),
+    };
+
+    // Bind here so a bad address stops the host starting, rather than only being logged
+    let listener = match listeners::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => return Err(format!("Error starting gRPC gateway on {}: {}", address, e)),
+    };
+
+    if let Err(e) = listener.set_nonblocking(true) {
+        return Err(format!("Error starting gRPC gateway on {}: {}", address, e));
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(GATEWAY_THREADS)
+        .thread_name("rustless-grpc")
+        .enable_all()
+        .build();
+
+    let runtime = match runtime {
+        Ok(runtime) => runtime,
+        Err(e) => return Err(format!("Error starting gRPC gateway: {}", e)),
+    };
+
+    println!("gRPC gateway listening on {}", address);
+
+    thread::spawn(move || {
+        runtime.block_on(async move {
+            let server = match Server::from_tcp(listener) {
+                Ok(server) => server,
+                Err(e) => {
+                    println!("gRPC gateway: {}", e);
+                    return;
+                }
+            };
+
+            let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle_call)) });
+            if let Err(e) = server.http2_only(true).serve(make_service).await {
+                println!("gRPC gateway: {}", e);
+            }
+        });
+    });
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use actix_web::{HttpResponse, web};
+use serde::{Deserialize, Serialize};
+
+use crate::broker::{self, BrokerRequest};
+use crate::build_queue;
+use crate::platform;
+use crate::storage;
+
+/// The options for the health endpoints, sent as query parameters
+#[derive(Deserialize)]
+pub struct HealthOptions {
+    // Return the result of each check as JSON instead of a plain ok or unavailable
+    #[serde(default)]
+    pub verbose: bool,
+}
+
+/// The result of a single health check
+#[derive(Serialize)]
+struct HealthCheck {
+    // The name of the check
+    name: &'static str,
+
+    // Whether the check passed
+    healthy: bool,
+
+    // Why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The result of all the health checks, returned in verbose mode
+#[derive(Serialize)]
+struct HealthReport {
+    // ok if all the checks passed, otherwise unavailable
+    status: &'static str,
+
+    // The result of each check
+    checks: Vec<HealthCheck>,
+}
+
+/// Runs a health check, converting the result into a HealthCheck
+fn run_check(name: &'static str, check: impl FnOnce() -> Result<(), String>) -> HealthCheck {
+    match check() {
+        Ok(_) => HealthCheck { name, healthy: true, error: None },
+        Err(e) => HealthCheck { name, healthy: false, error: Some(e) },
+    }
+}
+
+/// Checks the database can be opened and queried
+fn check_database() -> Result<(), String> {
+    let conn = storage::create_connection()?;
+    match conn.query_row("SELECT 1", [], |row| row.get::<_, i32>(0)) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error querying database: {}", e)),
+    }
+}
+
+/// Checks the database has been upgraded to the latest schema. The database is opened without the pool, as opening
+/// it through the pool would apply the migrations
+fn check_migrations() -> Result<(), String> {
+    match storage::open_existing_database()? {
+        Some(conn) => storage::check_schema(&conn),
+        None => Err("The database hasn't been created".to_string()),
+    }
+}
+
+/// Checks the docker daemon is reachable, as it is needed to build and run apps. If a docker broker is set, this
+/// also checks the broker is reachable
+pub fn check_docker() -> Result<(), String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::CheckDocker);
+    }
+
+    match platform::docker_command().args(["info", "--format", "{{.ServerVersion}}"]).output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!("Docker is not reachable: {}", String::from_utf8_lossy(&output.stderr).trim())),
+        Err(e) => Err(format!("Error running docker: {}", e)),
+    }
+}
+
+/// Checks builds can still be queued
+fn check_build_workers() -> Result<(), String> {
+    match build_queue::is_healthy() {
+        true => Ok(()),
+        false => Err("The build queue is unusable after a build failed unexpectedly".to_string()),
+    }
+}
+
+/// Builds the response for a set of health checks. This is a 200 if all the checks passed, otherwise a 503
+fn to_response(checks: Vec<HealthCheck>, verbose: bool) -> HttpResponse {
+    let healthy = checks.iter().all(|check| check.healthy);
+    let status = if healthy { "ok" } else { "unavailable" };
+
+    let mut response = match healthy {
+        true => HttpResponse::Ok(),
+        false => HttpResponse::ServiceUnavailable(),
+    };
+
+    match verbose {
+        true => response.json(HealthReport { status, checks }),
+        false => response.body(status),
+    }
+}
+
+/// Checks the host is alive - the process is running and the database can be used
+pub fn liveness(verbose: bool) -> HttpResponse {
+    let checks = vec![
+        run_check("database", check_database),
+    ];
+
+    to_response(checks, verbose)
+}
+
+/// Checks the host is ready to take traffic - docker is reachable, the database is up to date, and builds can run
+pub async fn readiness(verbose: bool) -> HttpResponse {
+    // Running docker info can take a while if the daemon is slow, so it is run off the async workers
+    let docker = match web::block(|| run_check("docker", check_docker)).await {
+        Ok(check) => check,
+        Err(e) => HealthCheck { name: "docker", healthy: false, error: Some(format!("Error checking docker: {}", e)) },
+    };
+
+    let checks = vec![
+        run_check("database", check_database),
+        run_check("migrations", check_migrations),
+        docker,
+        run_check("build_workers", check_build_workers),
+    ];
+
+    to_response(checks, verbose)
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use uuid::Uuid;
+
+use rustless_shared::{FunctionApp, FunctionAppStatus};
+
+use crate::database::Connection;
+use crate::gateway;
+use crate::leases;
+use crate::scaling;
+use crate::storage;
+
+/// The environment variable containing how many seconds an app can go without a request before it is stopped.
+/// Apps are never stopped for being idle if this isn't set or is 0
+const IDLE_TIMEOUT_ENV: &str = "RUSTLESS_IDLE_TIMEOUT";
+
+/// How often running apps are checked for being idle
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the last request to an app is written to the database. Requests in between are only counted in
+/// memory, so busy apps don't write on every request
+const ACTIVITY_WRITE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The lease that makes sure only one host process stops idle apps
+const IDLE_LEASE: &str = "idle-stopper";
+
+/// How long an app can go without a request before it is stopped, read from the environment once
+static IDLE_TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+
+/// When the last request to each app was written to the database
+static ACTIVITY_WRITES: OnceLock<Mutex<HashMap<Uuid, Instant>>> = OnceLock::new();
+
+/// The lock for each app that is being started by a request, so requests that arrive together only start it once
+static COLD_STARTS: OnceLock<Mutex<HashMap<Uuid, Arc<Mutex<()>>>>> = OnceLock::new();
+
+/// Gets how long an app can go without a request before it is stopped, or None if idle apps are left running
+pub fn get_idle_timeout() -> Option<Duration> {
+    *IDLE_TIMEOUT.get_or_init(|| {
+        let value = std::env::var(IDLE_TIMEOUT_ENV).ok()?;
+        match value.trim().parse::<u64>() {
+            Ok(0) => None,
+            Ok(seconds) => Some(Duration::from_secs(seconds)),
+            Err(_) => {
+                println!("Ignoring invalid {}: {}", IDLE_TIMEOUT_ENV, value);
+                None
+            }
+        }
+    })
+}
+
+/// Gets the current time as a Unix timestamp
+fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Records that the gateway is routing a request to an app, so it isn't stopped for being idle. The time is stored
+/// in the database so every host process sharing it sees the request, but at most every few seconds for each app
+pub fn record_request(conn: &Connection, id: &Uuid) {
+    if get_idle_timeout().is_none() {
+        return;
+    }
+
+    if let Ok(mut writes) = ACTIVITY_WRITES.get_or_init(|| Mutex::new(HashMap::new())).lock() {
+        if writes.get(id).is_some_and(|written_at| written_at.elapsed() < ACTIVITY_WRITE_INTERVAL) {
+            return;
+        }
+        writes.insert(*id, Instant::now());
+    }
+
+    if let Err(e) = storage::set_function_app_last_request(conn, id, now()) {
+        println!("Error recording request to {}: {}", id, e);
+    }
+}
+
+/// Gets the lock used to start an app for a request
+fn get_cold_start_lock(id: &Uuid) -> Arc<Mutex<()>> {
+    let mut locks = match COLD_STARTS.get_or_init(|| Mutex::new(HashMap::new())).lock() {
+        Ok(locks) => locks,
+        Err(e) => e.into_inner(),
+    };
+
+    locks.entry(*id).or_insert_with(|| Arc::new(Mutex::new(()))).clone()
+}
+
+/// Starts an app that was stopped for being idle, returning the port it is running on. This returns None if the app
+/// isn't running and wasn't stopped for being idle, such as if it was stopped by hand
+fn start_idle_app(id: Uuid) -> Result<Option<u16>, String> {
+    let lock = get_cold_start_lock(&id);
+    let _guard = match lock.lock() {
+        Ok(guard) => guard,
+        Err(e) => e.into_inner(),
+    };
+
+    let conn = storage::create_connection_for_app(&id)?;
+
+    // Another request may have started the app while this one waited
+    if let Some(port) = storage::get_function_app_port(&conn, &id).map_err(|e| e.to_string())? {
+        return Ok(Some(port));
+    }
+
+    if !storage::is_function_app_idle_stopped(&conn, &id).map_err(|e| e.to_string())? {
+        return Ok(None);
+    }
+
+    let res = crate::start_function_app_impl(&conn, id);
+    if !res.status().is_success() {
+        return Err(scaling::get_response_error(res));
+    }
+
+    storage::get_function_app_port(&conn, &id).map_err(|e| e.to_string())
+}
+
+/// Starts an app that was stopped for being idle so a request can be routed to it, waiting for it to pass its
+/// health check. This returns None if the app wasn't stopped for being idle, so it should stay stopped
+pub async fn cold_start(id: Uuid, name: &str) -> Result<Option<u16>, String> {
+    let started = Instant::now();
+    let port = match actix_web::rt::task::spawn_blocking(move || start_idle_app(id)).await {
+        Ok(Ok(Some(port))) => port,
+        Ok(Ok(None)) => return Ok(None),
+        Ok(Err(e)) => return Err(e),
+        Err(e) => return Err(format!("Error starting {}: {}", name, e)),
+    };
+
+    gateway::wait_until_healthy(port).await?;
+    println!("Started {} for a request in {}ms, as it was stopped for being idle", name, started.elapsed().as_millis());
+    Ok(Some(port))
+}
+
+/// Stops an app if it hasn't had a request for longer than the idle timeout, marking it so the next request starts
+/// it again. Apps that haven't had a request since they started are idle from when they started
+fn stop_if_idle(app: &FunctionApp, timeout: Duration) -> Result<(), String> {
+    let mut conn = storage::create_connection_for_app(&app.id)?;
+
+    let last_request = storage::get_function_app_last_request(&conn, &app.id).map_err(|e| e.to_string())?;
+    let started_at = storage::get_last_status_event(&conn, &app.id).map_err(|e| e.to_string())?.map(|(changed_at, _, _)| changed_at);
+    // Apps marked as running without being started through the host, such as by the reconciler, are idle from when
+    // they are first checked
+    let active_at = match last_request.max(started_at) {
+        Some(active_at) => active_at,
+        None => return storage::set_function_app_last_request(&conn, &app.id, now()).map_err(|e| e.to_string()),
+    };
+
+    let idle_for = now().saturating_sub(active_at);
+    if idle_for < timeout.as_secs() {
+        return Ok(());
+    }
+
+    let res = crate::stop_function_app_impl(&mut conn, app.id);
+    if !res.status().is_success() {
+        return Err(scaling::get_response_error(res));
+    }
+
+    storage::set_function_app_idle_stopped(&conn, &app.id, true).map_err(|e| e.to_string())?;
+    println!("Stopped {} as it hasn't had a request for {} seconds. The next request will start it", app.name, idle_for);
+    Ok(())
+}
+
+/// Stops every running app that has been idle for longer than the timeout
+fn stop_idle_apps(timeout: Duration) {
+    let apps = match storage::get_all_apps() {
+        Ok(apps) => apps,
+        Err(e) => {
+            println!("Error checking for idle apps: {}", e);
+            return;
+        }
+    };
+
+    for app in apps.iter().filter(|app| matches!(app.status, FunctionAppStatus::Running)) {
+        if let Err(e) = stop_if_idle(app, timeout) {
+            println!("Error stopping idle app {}: {}", app.name, e);
+        }
+    }
+}
+
+/// Starts stopping apps that go without a request for longer than the idle timeout, on a background thread for the
+/// life of the host. Nothing is started if there is no idle timeout. If more than one host process shares the
+/// database, only the one holding the lease stops idle apps
+pub fn start() {
+    let timeout = match get_idle_timeout() {
+        Some(timeout) => timeout,
+        None => return,
+    };
+
+    println!("Stopping apps that go {} seconds without a request", timeout.as_secs());
+    thread::spawn(move || loop {
+        thread::sleep(IDLE_CHECK_INTERVAL);
+
+        if leases::is_leader(IDLE_LEASE) {
+            stop_idle_apps(timeout);
+        }
+    });
+}
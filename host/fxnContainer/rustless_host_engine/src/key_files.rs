@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+use rustless_shared::ApiError;
+
+use crate::auth::{AuthProvider, AuthResult};
+
+/// The environment variable containing the path to a file of keys for the management API, kept outside the database
+/// so they can be managed by config management or mounted from a secret store
+pub const AUTH_KEY_FILE_ENV: &str = "RUSTLESS_AUTH_KEY_FILE";
+
+/// The start of a key in the key file that is stored as the hex SHA-256 hash of the key rather than the key itself
+const SHA256_PREFIX: &str = "sha256:";
+
+/// A key read from the key file
+#[derive(Debug)]
+struct FileKey {
+    // The name of the key, shown as who made a request
+    name: String,
+
+    // The key, or the hex SHA-256 hash of the key if hashed is true
+    key: String,
+
+    // Whether the key is stored as its hash
+    hashed: bool,
+}
+
+/// The keys read from the key file, with when the file was last changed so it is only read again once it changes
+type LoadedKeys = (SystemTime, Vec<FileKey>);
+
+/// Accepts the keys listed in a key file. Each line has the name of a key and the key, separated by whitespace, such
+/// as `ci rl_1234`. Keys can be written as `sha256:` followed by the hex SHA-256 hash of the key, so the file doesn't
+/// hold the keys themselves. Blank lines and lines starting with # are ignored
+///
+/// The file is read again when it changes, so keys can be added and removed without restarting the host
+pub struct KeyFileProvider {
+    // The key file
+    path: PathBuf,
+
+    // The keys last read from the file
+    keys: Mutex<Option<LoadedKeys>>,
+}
+
+impl KeyFileProvider {
+    /// Creates the provider for the key file set in RUSTLESS_AUTH_KEY_FILE, or None if it isn't set
+    pub fn from_env() -> Option<KeyFileProvider> {
+        match std::env::var(AUTH_KEY_FILE_ENV) {
+            Ok(value) if !value.trim().is_empty() => Some(KeyFileProvider { path: PathBuf::from(value.trim()), keys: Mutex::new(None) }),
+            _ => None,
+        }
+    }
+
+    /// Reads the keys from the file, returning an error for a line that isn't a name and a key
+    fn read_keys(&self) -> Result<Vec<FileKey>, String> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Error reading key file {}: {}", self.path.display(), e)),
+        };
+
+        let mut keys = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let (name, key) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(name), Some(key), None) => (name, key),
+                _ => return Err(format!("Error in key file {} on line {}: expected a name and a key", self.path.display(), number + 1)),
+            };
+
+            let (key, hashed) = match key.strip_prefix(SHA256_PREFIX) {
+                Some(hash) => (hash.to_ascii_lowercase(), true),
+                None => (key.to_string(), false),
+            };
+            keys.push(FileKey { name: name.to_string(), key, hashed });
+        }
+
+        Ok(keys)
+    }
+
+    /// Finds the name of the key in the file that matches the given key, reading the file again if it has changed
+    fn find_key(&self, key: &str) -> Result<Option<String>, String> {
+        let modified = match fs::metadata(&self.path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(e) => return Err(format!("Error reading key file {}: {}", self.path.display(), e)),
+        };
+
+        let mut loaded = match self.keys.lock() {
+            Ok(loaded) => loaded,
+            Err(e) => return Err(format!("Error reading key file: {}", e)),
+        };
+
+        if !matches!(&*loaded, Some((loaded_at, _)) if *loaded_at == modified) {
+            *loaded = Some((modified, self.read_keys()?));
+        }
+
+        let hash = hex::encode(Sha256::digest(key.as_bytes()));
+        let keys = loaded.as_ref().map(|(_, keys)| keys.as_slice()).unwrap_or_default();
+        Ok(keys.iter()
+            .find(|file_key| if file_key.hashed { file_key.key == hash } else { file_key.key == key })
+            .map(|file_key| file_key.name.to_string()))
+    }
+}
+
+impl AuthProvider for KeyFileProvider {
+    fn name(&self) -> &'static str {
+        "key file"
+    }
+
+    fn is_enabled(&self) -> Result<bool, String> {
+        Ok(true)
+    }
+
+    fn check(&self) -> Result<String, String> {
+        let keys = self.read_keys()?;
+        Ok(format!("{} keys in {}", keys.len(), self.path.display()))
+    }
+
+    fn authenticate(&self, token: &str) -> Result<AuthResult, (ApiError, String)> {
+        match self.find_key(token) {
+            Ok(Some(name)) => Ok(AuthResult::Accepted(name)),
+            Ok(None) => Ok(AuthResult::Rejected(None)),
+            Err(e) => Err((ApiError::Internal, e)),
+        }
+    }
+}
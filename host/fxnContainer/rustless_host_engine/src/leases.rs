@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use uuid::Uuid;
+
+use rustless_shared::LeasesReport;
+
+use crate::storage;
+
+/// The environment variable containing where leases are stored. Only sqlite is supported for now
+const LEASE_BACKEND_ENV: &str = "RUSTLESS_LEASE_BACKEND";
+
+/// The environment variable containing how many seconds a lease lasts before another process can take it
+const LEASE_TTL_ENV: &str = "RUSTLESS_LEASE_TTL";
+
+/// How long a lease lasts if the environment variable isn't set
+const DEFAULT_LEASE_TTL: Duration = Duration::from_secs(15);
+
+/// Where leases are stored, which decides which host processes can share background jobs
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LeaseBackend {
+    /// SQLite - leases are stored in the host database, so are shared by processes using the same database, including
+    /// hosts on other machines when the postgres storage backend is used
+    Sqlite,
+}
+
+impl LeaseBackend {
+    /// Gets the name of the backend, as set in the environment variable
+    pub fn name(&self) -> &'static str {
+        match self {
+            LeaseBackend::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// Where leases are stored, read from the environment once
+static BACKEND: OnceLock<LeaseBackend> = OnceLock::new();
+
+/// The ID this process holds leases with, made once when the host starts
+static HOLDER: OnceLock<String> = OnceLock::new();
+
+/// The leases this process holds, with when they expire
+static HELD: OnceLock<Mutex<HashMap<String, u64>>> = OnceLock::new();
+
+/// Gets where leases are stored
+pub fn get_backend() -> LeaseBackend {
+    *BACKEND.get_or_init(|| {
+        match std::env::var(LEASE_BACKEND_ENV) {
+            Ok(value) => match value.to_lowercase().as_str() {
+                "sqlite" => LeaseBackend::Sqlite,
+                "redis" | "etcd" => {
+                    println!("{} leases aren't supported yet, using sqlite", value);
+                    LeaseBackend::Sqlite
+                },
+                _ => {
+                    println!("Ignoring unknown {}: {}", LEASE_BACKEND_ENV, value);
+                    LeaseBackend::Sqlite
+                }
+            },
+            Err(_) => LeaseBackend::Sqlite,
+        }
+    })
+}
+
+/// Gets how long a lease lasts before another process can take it, unless it is renewed
+fn get_lease_ttl() -> Duration {
+    match std::env::var(LEASE_TTL_ENV) {
+        Ok(value) => match value.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+            _ => {
+                println!("Ignoring invalid {}: {}", LEASE_TTL_ENV, value);
+                DEFAULT_LEASE_TTL
+            }
+        },
+        Err(_) => DEFAULT_LEASE_TTL,
+    }
+}
+
+/// Gets the ID this process holds leases with. The process ID is included so it is easy to find the process,
+/// and a random part so it is unique across machines
+pub fn get_holder() -> &'static str {
+    HOLDER.get_or_init(|| {
+        let random = Uuid::new_v4().simple().to_string();
+        format!("{}-{}", std::process::id(), &random[..8])
+    })
+}
+
+/// Gets the current time as a Unix timestamp
+fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Takes or renews a lease in the backend, returning true if this process holds it
+fn try_acquire(name: &str, now: u64, expires_at: u64) -> Result<bool, String> {
+    match get_backend() {
+        LeaseBackend::Sqlite => {
+            let conn = storage::create_connection()?;
+            storage::try_acquire_lease(&conn, name, get_holder(), now, expires_at).map_err(|e| e.to_string())
+        },
+    }
+}
+
+/// Gives up a lease in the backend
+fn release(name: &str) -> Result<(), String> {
+    match get_backend() {
+        LeaseBackend::Sqlite => {
+            let conn = storage::create_connection()?;
+            storage::release_lease(&conn, name, get_holder()).map_err(|e| e.to_string())
+        },
+    }
+}
+
+/// Gets if this process should run the given background job, taking or renewing the lease on it. Call this each
+/// time the job runs, so the lease is renewed while the process is alive and taken over by another if it dies.
+///
+/// Leases are renewed once half their time is used, rather than on every call. If the backend can't be reached,
+/// the job doesn't run, as it may be running somewhere else
+pub fn is_leader(name: &str) -> bool {
+    let now = now();
+    let held = HELD.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut held = match held.lock() {
+        Ok(held) => held,
+        Err(_) => return false,
+    };
+
+    let ttl = get_lease_ttl().as_secs();
+    if let Some(expires_at) = held.get(name) {
+        if *expires_at > now + ttl / 2 {
+            return true;
+        }
+    }
+
+    let expires_at = now + ttl;
+    match try_acquire(name, now, expires_at) {
+        Ok(true) => {
+            if held.insert(name.to_string(), expires_at).is_none() {
+                println!("Took the lease on {}, so it runs in this host process", name);
+            }
+            true
+        },
+        Ok(false) => {
+            if held.remove(name).is_some() {
+                println!("Lost the lease on {} to another host process", name);
+            }
+            false
+        },
+        Err(e) => {
+            println!("Error getting the lease on {}: {}", name, e);
+            held.remove(name);
+            false
+        }
+    }
+}
+
+/// Gives up all the leases this process holds, so other processes can take over the background jobs straight away
+/// rather than waiting for the leases to expire
+pub fn release_all() {
+    let held = HELD.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut held = match held.lock() {
+        Ok(held) => held,
+        Err(_) => return,
+    };
+
+    for (name, _) in held.drain() {
+        if let Err(e) = release(&name) {
+            println!("Error releasing the lease on {}: {}", name, e);
+        }
+    }
+}
+
+/// Gets the leases on background jobs and who holds them
+pub fn get_report() -> Result<LeasesReport, String> {
+    let mut leases = match get_backend() {
+        LeaseBackend::Sqlite => {
+            let conn = storage::create_connection()?;
+            storage::get_leases(&conn).map_err(|e| e.to_string())?
+        },
+    };
+
+    for lease in leases.iter_mut() {
+        lease.held_by_this_host = lease.holder == get_holder();
+    }
+
+    Ok(LeasesReport {
+        backend: get_backend().name().to_string(),
+        holder: get_holder().to_string(),
+        leases,
+    })
+}
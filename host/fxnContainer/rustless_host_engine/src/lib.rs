@@ -0,0 +1,3857 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use actix_web::body::MessageBody;
+use actix_web::http::Method;
+use actix_web::middleware::from_fn;
+use actix_web::{delete, get, post, route, App, HttpRequest, HttpServer, Responder, HttpResponse, web, web::Json};
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use rusqlite::Error;
+use socket2::Socket;
+use tempfile::{tempdir, TempDir};
+use uuid::Uuid;
+
+use rustless_shared::{default_next_runs_count, AdoptReport, AnalyticsOptions, ApiError, ApiKey, AppLimits, AppResourceUsage, AppRestart, AppRouteManifest, AppStop, BufferingReport, BufferingRequest, BuildAccepted, BulkStartRequest, BuildLogOptions, BuildOptions, CratesCacheStats, DefaultApp, DependenciesReport, DependenciesRequest, DeployAction, DeployPhases, DeployPlan, DeploymentExport, EventsOptions, LogsOptions, FunctionApp, FunctionAppInfo, FunctionAppOverview, FunctionAppStatus, FunctionAppStatusResult, FunctionAppNameRequest, EgressReport, EgressRequest, GrpcReport, GrpcServicesRequest, ImportOptions, ImportedDeployment, MaintenanceRequest, MirrorRequest, NamespaceDefaults, NextRuns, NextRunsOptions, Promotion, RecordedRequestsOptions, RecordingRequest, RedactionRules, ReplicasReport, RequestLimitsReport, ResourceSamplesOptions, ResourceUsageOptions, ScaleProfilesRequest, ScaleRequest, SettingSource, StartOptions, TimerTriggerRequest, TriggerRunsOptions, UnchangedDeployment, VersionInfo, API_VERSION, BUILD_CANCELLED, BUILD_FAILED, BUILD_SUCCEEDED, EXPORT_DEPLOYMENT_HEADER, EXPORT_IMAGE_ID_HEADER, TIMER_TRIGGER};
+
+use database::{Connection, StorageBackend};
+use db_pool::{DbConnection, DbPools};
+
+mod adopt;
+mod analytics;
+mod archive;
+mod approvals;
+mod auth;
+mod broker;
+mod build_dirs;
+mod build_logs;
+mod build_queue;
+mod config;
+mod config_check;
+mod container_states;
+mod crates_cache;
+mod database;
+mod db_pool;
+mod defaults;
+mod dependencies;
+mod docker;
+mod egress;
+mod errors;
+mod events;
+mod faults;
+mod function_app_builder;
+mod gateway;
+mod grpc;
+mod health;
+mod idle;
+mod key_files;
+mod leases;
+mod limits;
+mod listeners;
+mod migrations;
+mod mirror;
+mod oidc;
+mod pages;
+mod phases;
+mod platform;
+mod postgres_storage;
+mod promotion;
+mod quotas;
+mod readme;
+mod reconciler;
+mod recorder;
+mod redaction;
+mod registry;
+mod replicas;
+mod resource_history;
+mod sbom;
+mod scaling;
+mod signing;
+mod storage;
+mod templates;
+mod triggers;
+mod ui;
+mod uploads;
+
+// Interface
+// ✅ GET / - landing page listing the running apps. Unknown routes return a 404 page
+// ✅ GET/POST default-app - gets or sets the app that receives requests to / and unknown routes instead of the landing and 404 pages
+// ✅ GET ui - web console showing the apps, their status, logs, and deployments, updated live from the event stream
+// ✅ GET hello - test that the server is running
+// ✅ API keys - every endpoint except the landing page, hello, healthz, readyz, the web console pages, and the api/{name} gateway needs an API key created with rustless-hostctl keys create, sent as a bearer token in the Authorization header, and returns 401 without it. Routes are matched on the decoded path, so percent-encoded paths need a key too. The web console sends its key to the events stream in a cookie, as browsers can't send headers to event streams. Until a key is created they reject every request, unless RUSTLESS_OPEN_API is set to leave them open, which is warned about when the host starts. Only a hash of each key is stored. Keys are revoked with rustless-hostctl keys revoke. Approving deployments takes an approver key instead
+// ✅ Auth providers - as well as API keys, the management API accepts the keys in the file set with RUSTLESS_AUTH_KEY_FILE, one name and key per line with the key optionally written as sha256:<hex hash>, and the tokens from an OpenID Connect provider set with RUSTLESS_OIDC_ISSUER and RUSTLESS_OIDC_AUDIENCE, limited to the groups in RUSTLESS_OIDC_GROUPS if it is set. Once any provider is set up the management API needs a token one of them accepts, and requests that change something are logged with who made them
+// ✅ GET version - the host version, git SHA, build date, compiled in features, and supported API versions
+// ✅ GET healthz - liveness check for load balancers. 200 if the process and database are working, otherwise 503. Add ?verbose=true for JSON
+// ✅ GET readyz - readiness check for load balancers. 200 if docker is reachable, the database is up to date, and builds can run, otherwise 503
+// ✅ GET limits - the limits on request headers, request line length, and slow clients, with how many requests have been rejected for breaking them. Set with the RUSTLESS_MAX_HEADERS, RUSTLESS_MAX_HEADER_BYTES, RUSTLESS_MAX_REQUEST_LINE_BYTES, RUSTLESS_HEADER_TIMEOUT, RUSTLESS_BODY_IDLE_TIMEOUT, RUSTLESS_KEEP_ALIVE, and RUSTLESS_TLS_HANDSHAKE_TIMEOUT environment variables
+// ✅ GET quotas - the quotas on apps, disk used by app images, and memory used by running apps, with how much of each is used. Hard limits are set with RUSTLESS_MAX_APPS, RUSTLESS_MAX_DISK, and RUSTLESS_MAX_MEMORY, and adding, building, or starting an app past one returns 507. Past RUSTLESS_QUOTA_WARN_PERCENT of a limit (default 80) a quota_warning event is sent instead
+// ✅ GET leases - the leases that make sure background jobs, such as the timer scheduler and crash checker, run in only one host process when several share the database. Leases last RUSTLESS_LEASE_TTL seconds (default 15) unless renewed, and are stored in the backend set by RUSTLESS_LEASE_BACKEND (only sqlite for now)
+// ✅ GET crates-cache - how the crates.io cache has been used and what it has stored. The cache runs when RUSTLESS_CRATES_CACHE is set to the address to listen on, and builds download crates through it, or through the external cache in RUSTLESS_CRATES_CACHE_URL
+// ✅ POST crates-cache/purge - removes everything from the crates.io cache
+// ✅ GET/POST/DELETE faults - gets, sets, or clears the faults being injected: failing the next build, delaying requests through the gateway, and dropping a percentage of status writes. Only available when the host is built with the fault-injection feature, for integration tests
+// ✅ GET events?app={name}&namespace={namespace} - server sent event stream of app lifecycle events as JSON: status changes, deploy progress, and container crashes. Filter by app or namespace
+// ✅ GET templates - lists the Dockerfile templates apps can be built with. Templates are embedded, and can be overridden or added as {name}.Dockerfile files in RUSTLESS_TEMPLATES_DIR
+// ✅ GET api/{appname}/ - lists the routes the app handles as JSON, from the route manifest apps built with rustless_app serve at /__routes. Apps that handle / themselves, or don't serve a manifest, get the request instead
+// ✅ GET/POST api/{appname}/{approute} - route request to function app. Error pages are shown if the app is not running or not responding. Responses say which app and deployment served them, and if it was the first request since the app started, in the X-Rustless-App, X-Rustless-Version, and X-Rustless-Cold-Start headers. Set RUSTLESS_GATEWAY_HEADERS to off to leave them out. Apps get the address of the caller at the end of the X-Forwarded-For header, with IPv4 callers on a dual-stack listener given as IPv4 addresses. When RUSTLESS_IDLE_TIMEOUT is set, apps that go that many seconds without a request are stopped, and the next request starts the app again and waits for it to pass its health check. Apps stopped by hand stay stopped
+// ✅ GET function-apps - list all apps
+// ✅ GET function-apps/status - every app with its stored status, latest deployment, when it was started, why it is in error, and any deployment waiting for approval
+// ✅ GET function-apps/resource-usage?app={name} - the CPU, memory, and network each running app is using, from docker stats, or only the given app. Docker samples the containers for about a second, so this takes a second to return
+// ✅ GET function-apps/{appname}/id - Get the ID for the app
+// ✅ GET analytics?weeks={n}&app={name}&namespace={namespace} - deployment analytics for each app over the last n weeks (default 4): deployments and deploys per week, builds with the success rate and mean time of the builds that succeeded, and crashes with the mean time to recovery, from the crash to the app next running, with the figures for each week. Filter by app or namespace
+// ✅ POST function-apps - adds a new function app to the server. This is a multi-stage process. This stage returns a unique ID for the function app
+// ✅ POST function-apps/plan - works out what deploying code for an app would do, without making any changes. Used for dry runs
+// ✅ POST function-apps/adopt - registers the apps that have an image with rustless labels but aren't in the database, such as after restoring it from a backup, keeping their IDs and deployment numbers so they can be started without deploying again. Also run with rustless-hostctl adopt
+// ✅ POST function-apps/{id}/code?strict=&template=&toolchain=&base_image=&force= - uploads the code for the function app for the given ID (registered with a post to api/function-apps), and this queues the build and registration of the docker container using the given template, returning 202 with the build ID. The build runs in the background, so poll the status until the app is no longer building - it is then ready, in error with the build output, or cancelled. The code is the zip file sent as application/zip or application/octet-stream, or the zip file encoded as base64 for older CLIs, and is written to disk as it arrives, decoding base64 a chunk at a time. Uploads with a zip file over RUSTLESS_MAX_CODE_SIZE (1GiB by default) are rejected with 413 as soon as the limit is passed. Before anything changes, the zip file is checked to be a single folder with one Cargo.toml at its root and no absolute or .. paths, that unzips to no more than RUSTLESS_MAX_UNZIPPED_SIZE (4GiB by default), and is rejected with 422 naming the file that is wrong if it isn't. Builds run in the build directory set with --build-dir, or the system temporary directory, and fail if there isn't enough free space there to unzip the code. If the code and options match the latest deployment, the build is skipped and the deployment returned with 200, unless force is set. Extracting the code, waiting in the queue, compiling, and exporting the image each have a timeout in seconds set with RUSTLESS_EXTRACT_TIMEOUT, RUSTLESS_QUEUE_TIMEOUT, RUSTLESS_COMPILE_TIMEOUT, and RUSTLESS_EXPORT_TIMEOUT, failing the build if one is hit. When RUSTLESS_IMAGE_REGISTRY is set, the built image is pushed there before the app is ready, with push progress in the build log and deploy events. Pushes that fail with transient registry errors are retried, and a push that makes no progress for RUSTLESS_PUSH_TIMEOUT seconds is killed
+// ✅ GET function-apps/{id}/status - gets the status of the function app, Not found, registered, building, ready, running, error, cancelled, along with the latest build ID and why it failed, and why its container last exited without being stopped, such as killed: out of memory, limit 256Mi. Whether the app is running comes from the containers docker is running, refreshed in the background every 3 seconds, rather than asking docker on each request
+// ✅ GET function-apps/{id}/info - everything about the app in one response: its status and why it is in error, the names of the settings made on it (values are left out as they can hold credentials), its buffering, memory, and replica limits, the last 5 deployments, its timer trigger and when it next fires, its scale profiles, and the containers running it
+// ✅ POST function-apps/start - starts the given apps, or every app that is ready if none are given, along with the apps they depend on. Each app is started once the apps it depends on pass their /hello health check, and apps whose dependencies couldn't be started are skipped. Returns what happened to each app in the order they were started
+// ✅ POST function-apps/{id}/start?with_deps={true|false} - starts the function app if it is ready or error. If the port picked for the app is taken before docker can publish it, another port is tried, up to RUSTLESS_START_ATTEMPTS times (default 3), and the status records how many were tried. Set with_deps to start the apps it depends on first, waiting for each to pass its /hello health check
+// ✅ POST function-apps/{id}/dependencies - sets the apps the function app depends on, so they are started before it by bulk starts, starts with with_deps, and restarts when the host starts. Apps that don't exist and cycles are refused
+// ✅ GET function-apps/{id}/dependencies - gets the apps the function app depends on, and the apps that depend on it
+// ✅ POST function-apps/{id}/maintenance - turns maintenance mode on or off. The gateway shows a maintenance page while it is on, but the app keeps running
+// ✅ POST function-apps/{id}/builds/current/cancel - cancels the build for the function app, whether it is queued or building. The docker build is killed, the uploaded code deleted, and the app marked as cancelled
+// ✅ POST function-apps/{id}/mirror - turns mirroring a sample of requests to an external URL or file on or off, for debugging and replay. Files are relative paths in RUSTLESS_MIRROR_DIR (default mirrors), and absolute paths or paths using .. are rejected
+// ✅ POST function-apps/{id}/triggers/timer - sets or removes a timer trigger that POSTs to a route on the app on a cron schedule, evaluated in UTC. The schedule is validated when it is set
+// ✅ GET function-apps/{id}/triggers/timer/next?count={n}&schedule={cron} - previews the upcoming runs of the timer trigger, or of the given schedule to check it before setting it
+// ✅ POST function-apps/{id}/triggers/{trigger}/run - fires a trigger now, for testing. The app must be running. Only the timer trigger is supported
+// ✅ GET function-apps/{id}/triggers/runs?last={n}&trigger={trigger} - gets the most recent trigger invocations, with when they ran, how long they took, and the response code
+// ✅ POST function-apps/{id}/egress - restricts the destinations the app can call through the egress proxy to an allowlist, or lifts the restriction. Apps without their own allowlist use the default for their namespace. The proxy runs when RUSTLESS_EGRESS_PROXY is set to the port to listen on, which listens on the docker bridge, or to a full address. Each app's containers authenticate with the proxy using a random secret issued to the app
+// ✅ GET function-apps/{id}/egress - gets the egress allowlist and the destinations the app has called through the proxy since the host started, with connection and byte counts
+// ✅ POST function-apps/{id}/grpc - sets the gRPC services the app serves, such as helloworld.Greeter. The gRPC gateway routes calls to each service to the app that serves it, streaming them both ways over HTTP/2. The gateway runs when RUSTLESS_GRPC_GATEWAY is set to the address to listen on, and takes plain HTTP/2 calls
+// ✅ GET function-apps/{id}/grpc - gets the gRPC services the app serves and, for each method called since the host started, the calls in progress, status codes, durations, and bytes sent and received
+// ✅ POST function-apps/{id}/scale-profiles - sets or clears the profiles that scale the app on cron schedules, evaluated in UTC. When a profile starts the app is scaled to its replicas, in the same way as the scale endpoint
+// ✅ POST function-apps/{id}/scale - scales the app to a number of replicas, up to the most set for its namespace or RUSTLESS_MAX_REPLICAS (default 10). Stopped apps are started, extra containers are started or stopped to match, and 0 stops the app. The gateway shares requests between the replicas in turn, skipping any that fail their /hello health check, which is run every 5 seconds. Starting the app again runs the same number of replicas
+// ✅ GET function-apps/{id}/scale - gets the replicas the app runs, and the port of each running container with whether it is healthy
+// ✅ GET function-apps/{id}/scale-profiles - gets the scale profiles for the app, the replicas they set now, and when they next change
+// ✅ POST function-apps/{id}/buffering - sets how many bytes of request and response bodies the gateway buffers before streaming them. Bodies within the threshold are sent with a content length. Unset thresholds use the default for the namespace, or RUSTLESS_BUFFER_THRESHOLD (default 1MiB)
+// ✅ GET function-apps/{id}/buffering - gets the buffering thresholds and how many bodies have been buffered and streamed since the host started, with buffer memory use
+// ✅ GET function-apps/{id}/effective-config - gets the settings the app uses, with whether each is set on the app, or comes from the defaults for its namespace or the host
+// ✅ POST function-apps/{id}/recording - turns recording recent requests and responses on or off. Requests are kept in memory in a ring buffer of up to 1000 requests, with bodies truncated to 64KB, and redacted with the app's redaction rules and the headers its mirror config redacts
+// ✅ GET function-apps/{id}/recorded-requests?last={n} - gets the most recent recorded requests and responses, used to replay them
+// ✅ POST function-apps/{id}/redaction - sets the header names and JSON field paths, such as user.password or cards.*.number, redacted from requests and responses before they are mirrored or recorded. Authorization, Cookie, and other credential headers are always redacted. Empty rules only redact those
+// ✅ GET function-apps/{id}/redaction - gets the header names and JSON field paths redacted from the function app's mirrored and recorded requests
+// ✅ GET signing-key - the public key for the host signing key, to check image signatures with cosign verify-blob
+// ✅ GET function-apps/{id}/signature - checks the image for the app was signed by this host when it was built and hasn't changed since. Apps are checked before they start, as set by RUSTLESS_IMAGE_VERIFICATION (off, warn, or enforce)
+// ✅ GET function-apps/{id}/deployments - lists the deployments of the app, most recent first, with when they were built, approved, and signed, and when each phase of the build finished
+// ✅ GET function-apps/{id}/logs?tail={n}&follow={true|false} - gets the most recent output of the app's container as plain text, with timestamps. Defaults to 200 lines. Set follow to stream the output as server sent events, with the most recent lines and then each line as the app writes it until the container exits
+// ✅ GET function-apps/{id}/resource-samples?last={n} - gets the most recent samples of the CPU, memory, and network the app used, newest first. Running apps are sampled every 30 seconds and samples are kept for a day. Defaults to 60 samples
+// ✅ GET function-apps/{id}/build-logs?build={build_id}&follow={true|false} - gets the output of the latest build, or the given one, with when it started and finished and whether it succeeded, failed, or was cancelled. The docker build and image push output is captured as the build runs, and the last 10 builds of each app are kept. Set follow to stream the log as server sent events, with an output event for each line and a finished event with the result
+// ✅ GET function-apps/{id}/deployments/{n}/sbom - gets the CycloneDX bill of materials generated from the Cargo.lock and base image when the deployment was built. Use latest for the most recent deployment
+// ✅ GET function-apps/{id}/deployments/{n}/export - exports an approved deployment to promote it to another host, as the details of the deployment on one line of JSON followed by its image saved with docker save. Only the latest deployment's image is kept, so older deployments get 410
+// ✅ POST function-apps/{id}/import?from={host} - imports a deployment exported from another host as a new deployment, loading the image with the same ID rather than building it. The deployment records the host and deployment it was promoted from, and needs approving if RUSTLESS_REQUIRE_APPROVAL is set
+// ✅ POST function-apps/{id}/deployments/{n}/promotions - records that a deployment was promoted to another host, so the deployments list shows where it went
+// ✅ GET function-apps/{id}/readme - gets the README.md, README.txt, or README next to the Cargo.toml in the code for the latest deployment, so callers can find out how to call the app. Returns 404 if the code didn't have one
+// ✅ POST function-apps/{id}/deployments/{n}/approve - approves a deployment so the app can be started. Only needed when RUSTLESS_REQUIRE_APPROVAL is set, and must be called with an approver key
+// ✅ GET function-apps/{id}/routes - lists the routes the app handles, from the route manifest served by apps built with rustless_app. The app must be running
+// ✅ POST function-apps/{id}/stop - stops the function app if it is started. The app is sent SIGTERM and given RUSTLESS_STOP_GRACE_PERIOD seconds (default 30) to finish requests in flight before it is killed. Clean and forced stops are recorded in the status history
+// ✅ POST function-apps/{id}/restart - restarts the function app without dropping requests. A new container is started on a fresh port, and once it passes its /hello health check (within RUSTLESS_HEALTH_CHECK_TIMEOUT seconds, default 30) requests go to it and the old container is stopped with the grace period. Apps that are scaled out get new replicas started alongside it, and the old ones are stopped with the old container
+// ✅ DELETE function-apps/{id} - deletes the function app, stopping it if it is running and removing its image, saved error pages, and everything stored for it. Apps that are building must have the build cancelled first
+// ✅ function-apps/by-name/{name}/... - the status, info, routes, start, dependencies, stop, restart, delete, maintenance, timer trigger, trigger run and history, egress, grpc, scale, scale profiles, buffering, effective config, mirror, recording, redaction, build cancel, approve, deployments, logs, build logs, resource samples, sbom, export, import, promotions, readme, signature, and code endpoints addressed by app name instead of ID
+// ✅ Reconcile the stored status with docker every 10 seconds - apps with a running container are marked as running on its port, and containers left running for apps that no longer exist are stopped. Apps marked as running whose container exited are recorded as crashed every 5 seconds, with why they exited. Set RUSTLESS_APP_MEMORY, such as 256m, to limit the memory each app container can use. When RUSTLESS_RESTART_ON_STARTUP is set, apps that were running when the host stopped but whose containers have gone are started again when it starts, in dependency order
+// ✅ Label app images and containers with the app name, ID, namespace, and deployment, and managed-by=rustless, so the host and external tools can find them by label rather than image name. The labels are prefixed with RUSTLESS_LABEL_PREFIX (default rustless), such as rustless.app-id
+// ✅ Configuration - read from the TOML file given with --config, the file in RUSTLESS_CONFIG, or rustless_host.toml in the working directory, with environment variables taking priority. The address, port, TLS files, and build directory are set with RUSTLESS_ADDRESS, RUSTLESS_PORT, RUSTLESS_TLS_KEY_FILE, RUSTLESS_TLS_CERT_FILE, and RUSTLESS_BUILD_DIR, the database file with RUSTLESS_DB_FILE (default rustless_host.db, with the namespaces folder next to it), how many connections are kept open to each database file with RUSTLESS_DB_POOL_SIZE (default 8), and how many builds run at once with RUSTLESS_MAX_CONCURRENT_BUILDS (default 1). IPv6 addresses go in brackets, such as [::]:8080, and listen for IPv4 and IPv6 clients unless RUSTLESS_IPV6_ONLY is set, for the main address and the other listeners. Behind a reverse proxy that serves the host under a path, such as https://example.com/rustless/, and strips it from requests, RUSTLESS_BASE_PATH sets the path so links the host generates, such as the web console redirect, include it. The file can also set the listeners, docker settings, and any other RUSTLESS_ environment variable in its env table. Run with --check-config to check the config without starting the host, for the port, TLS files, build directory, database, docker, registry login, and listeners
+// ✅ GET namespaces/{namespace}/backup - backs up the database for a namespace when namespaces are stored separately
+// ✅ POST namespaces/{namespace}/restore - restores the database for a namespace from a backup
+// ✅ GET/POST namespaces/{namespace}/defaults - gets or sets the default settings for the apps in a namespace: buffering thresholds, egress allowlist, and the most replicas an app can be scaled to, which can't be more than the host allows. Apps use the defaults for the settings that aren't made on them, and settings that aren't set for the namespace use the host defaults
+// ✅ Storage backend - data is stored in SQLite by default, or in the Postgres database set with RUSTLESS_DATABASE_URL when RUSTLESS_STORAGE_BACKEND is postgres, so hosts on several machines can share their apps, deployments, API keys, and leases. The tables are created when the host first connects. Namespaces can't be stored separately with Postgres. The connection is encrypted as set by sslmode in the connection string, from disable to verify-full as with libpq, and sslrootcert can be set to the certificate authority that signed the server's certificate. Images are still built and run on each host
+//
+// ✅ Schema migrations - the databases record which version of the schema they are at in the schema_migrations table, and the migrations they don't have yet are applied in order when the host first connects, each in a transaction. Older databases are upgraded by the first migration, and a database upgraded by a newer host is refused rather than used. --check-config reports a database that needs upgrading
+//
+// ❌ Check status before adding code
+// ❌ Check status before updating code, and stop the app if it is running
+
+/// The file the process ID is written to, so rustless-hostctl can find the running host
+const PID_FILE: &str = "rustless_host.pid";
+
+/// The number of recent deployments returned with the details of a function app
+const INFO_DEPLOYMENTS: usize = 5;
+
+/// How to run the host when it is embedded with serve. The default is how the rustless_host_engine binary runs
+/// without a config file, and load_config reads it from the config file and environment variables
+///
+/// The database, built code, and error pages are stored relative to the working directory, the same as when the
+/// host is run from the binary, unless the config file or environment variables say otherwise
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct HostConfig {
+    // The address to listen on, such as 0.0.0.0:8080
+    pub address: String,
+
+    // The PEM file containing the private key for HTTPS
+    pub private_key_file: PathBuf,
+
+    // The PEM file containing the certificate chain for HTTPS
+    pub certificate_file: PathBuf,
+
+    // The file to write the process ID to so rustless-hostctl can find the host, or None to not write one.
+    // Tests running several hosts in one process should turn this off
+    pub pid_file: Option<PathBuf>,
+
+    // The directory builds unzip and compile code in, or None to use the system temporary directory. This is often
+    // a small tmpfs, so hosts building large apps should use a directory on a bigger disk
+    pub build_dir: Option<PathBuf>,
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        HostConfig {
+            address: "0.0.0.0:8080".to_string(),
+            private_key_file: PathBuf::from("key.pem"),
+            certificate_file: PathBuf::from("cert.pem"),
+            pid_file: Some(PathBuf::from(PID_FILE)),
+            build_dir: None,
+        }
+    }
+}
+
+/// Whether a check of the host configuration passed, from check_config
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum ConfigCheckStatus {
+    /// Passed - the setting works
+    Passed,
+
+    /// Warning - the host will start, but something may need attention, such as a certificate that expires soon
+    Warning,
+
+    /// Failed - the host won't start, or part of it won't work
+    Failed,
+}
+
+/// The result of checking part of the host configuration, from check_config
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct ConfigCheck {
+    // What was checked, such as tls or docker
+    pub name: String,
+
+    // Whether the check passed
+    pub status: ConfigCheckStatus,
+
+    // What the check found
+    pub message: String,
+}
+
+/// This route is used as a test to ensure the server is running. It will return "Hello!"
+///
+/// The process ID is returned in a header so rustless-hostctl can tell which host answered during an upgrade
+#[get("/hello")]
+async fn greet() -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header(("X-Rustless-Pid", std::process::id().to_string()))
+        .body("Hello from rustless!")
+}
+
+/// Gets the version and build details of the host
+fn get_version_info() -> VersionInfo {
+    // Apps are always built with Rust. The storage backend depends on the features the host was built with,
+    // the platform on where it is running, and the build network policy on how the host is configured
+    let storage = if cfg!(feature = "sqlcipher") { "storage:sqlcipher" } else { "storage:sqlite" };
+    let platform = format!("platform:{}", platform::get_platform().name());
+    let build_network = format!("build-network:{}", templates::get_build_network().name());
+    let mut features = vec!["runtime:rust".to_string(), storage.to_string(), platform, build_network];
+
+    // Hosts that can inject faults say so, so they are easy to spot if one is ever deployed by mistake
+    if cfg!(feature = "fault-injection") {
+        features.push("fault-injection".to_string());
+    }
+
+    VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_sha: env!("RUSTLESS_GIT_SHA").to_string(),
+        build_date: env!("RUSTLESS_BUILD_DATE").to_string(),
+        features,
+        api_versions: vec![API_VERSION.to_string()],
+    }
+}
+
+/// Streams app lifecycle events as server sent events, so dashboards don't need to poll
+///
+/// Each event is sent with its type as the event name and a HostEvent as JSON for the data. Comments are sent
+/// to keep the connection open while there are no events
+#[get("/events")]
+async fn get_events(options: web::Query<EventsOptions>) -> HttpResponse {
+    let receiver = events::subscribe(options.into_inner());
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(receiver.map(|frame| Ok::<_, actix_web::Error>(web::Bytes::from(frame))))
+}
+
+/// Gets the version of the host, so support can tell which version is running
+#[get("/version")]
+async fn get_version() -> HttpResponse {
+    HttpResponse::Ok().json(get_version_info())
+}
+
+/// Lists the Dockerfile templates function apps can be built with
+#[get("/templates")]
+async fn list_templates() -> HttpResponse {
+    HttpResponse::Ok().json(templates::list_templates())
+}
+
+/// Liveness check for load balancers and uptime monitors, checking the process and database are working
+#[get("/healthz")]
+async fn healthz(options: web::Query<health::HealthOptions>) -> HttpResponse {
+    health::liveness(options.verbose)
+}
+
+/// Gets how the crates.io cache has been used since the host started and what it has stored
+#[get("/crates-cache")]
+async fn get_crates_cache_stats() -> HttpResponse {
+    let stats: CratesCacheStats = crates_cache::get_stats();
+    HttpResponse::Ok().json(stats)
+}
+
+/// Removes everything from the crates.io cache, so builds download it from crates.io again
+#[post("/crates-cache/purge")]
+async fn purge_crates_cache() -> HttpResponse {
+    run_blocking(purge_crates_cache_impl).await
+}
+
+/// Removes everything from the crates.io cache
+fn purge_crates_cache_impl() -> HttpResponse {
+    match crates_cache::purge() {
+        Ok(purged) => HttpResponse::Ok().json(purged),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Gets the limits put on requests and how many requests have been rejected for breaking them
+#[get("/limits")]
+async fn get_request_limits() -> HttpResponse {
+    HttpResponse::Ok().json(RequestLimitsReport {
+        limits: limits::get_limits().clone(),
+        rejected: limits::get_rejected_requests(),
+    })
+}
+
+/// Gets the quotas on apps, disk, and memory, with how much of each is used
+#[get("/quotas")]
+async fn get_quotas() -> HttpResponse {
+    match web::block(quotas::get_report).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Gets the leases on background jobs, showing which host process runs each of them
+#[get("/leases")]
+async fn get_leases() -> HttpResponse {
+    run_blocking(get_leases_impl).await
+}
+
+/// Gets the leases held on the background jobs
+fn get_leases_impl() -> HttpResponse {
+    match leases::get_report() {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Readiness check for load balancers, checking the host can build and run apps
+#[get("/readyz")]
+async fn readyz(options: web::Query<health::HealthOptions>) -> HttpResponse {
+    health::readiness(options.verbose).await
+}
+
+/// Parses a function app ID from the request path and connects to the database that holds the app
+async fn resolve_function_app_id(pools: &web::Data<DbPools>, info: &str) -> Result<(DbConnection, Uuid), Box<HttpResponse>> {
+    let id = Uuid::parse_str(info);
+    let id = match id {
+        Ok(id) => id,
+        Err(e) => {
+            println!("Error parsing ID: {}", e);
+            return Err(Box::new(errors::response(ApiError::InvalidId, &e.to_string())))
+        }
+    };
+
+    let conn = connect_function_app(pools, id).await?;
+    Ok((conn, id))
+}
+
+/// Connects to the database that holds a function app. This can mean opening every namespace database, so it runs
+/// on the blocking thread pool rather than holding up a server worker
+///
+/// Handlers that wait on something slow, such as an upload, give their connection back to the pool while they wait
+/// and connect again with this afterwards, so slow clients can't use up the connections
+async fn connect_function_app(pools: &web::Data<DbPools>, id: Uuid) -> Result<DbConnection, Box<HttpResponse>> {
+    let pools = pools.clone();
+    match web::block(move || storage::connect_for_app(&pools, &id)).await {
+        Ok(Ok(conn)) => Ok(conn),
+        Ok(Err(e)) => Err(Box::new(errors::response(ApiError::AppNotFound, &e))),
+        Err(e) => Err(Box::new(errors::response(ApiError::Internal, &e.to_string()))),
+    }
+}
+
+/// Looks up a function app by name and connects to the database that holds the app
+async fn resolve_function_app_name(pools: &web::Data<DbPools>, name: &String) -> Result<(DbConnection, Uuid), Box<HttpResponse>> {
+    // Connect to the database that holds this app, on the blocking thread pool as with connect_function_app
+    let lookup_name = name.to_string();
+    let pools = pools.clone();
+    let found = web::block(move || {
+        let conn = storage::connect_for_app_name(&pools, &lookup_name)?;
+        let id = storage::get_function_id_from_name(&conn, &lookup_name);
+        Ok::<_, String>((conn, id))
+    }).await;
+
+    let (conn, id) = match found {
+        Ok(Ok(found)) => found,
+        Ok(Err(_)) => return Err(Box::new(errors::response(ApiError::AppNotFound, &format!("No function app with name {} found", name)))),
+        Err(e) => return Err(Box::new(errors::response(ApiError::Internal, &e.to_string()))),
+    };
+
+    match id {
+        Ok(id) => Ok((conn, id)),
+        Err(Error::QueryReturnedNoRows) => Err(Box::new(errors::response(ApiError::AppNotFound, &format!("No function app with name {} found", name)))),
+        Err(e) => Err(Box::new(errors::response(ApiError::Internal, &e.to_string()))),
+    }
+}
+
+/// Runs a handler's database and docker calls on the blocking thread pool, so they don't hold up the server worker.
+/// Responses can't be sent between threads, so the status, headers, and body are sent back to build the response
+/// again. Streamed responses can't be sent back this way, so handlers that stream don't use this
+async fn run_blocking<F>(handler: F) -> HttpResponse
+where
+    F: FnOnce() -> HttpResponse + Send + 'static,
+{
+    let parts = web::block(move || {
+        let (response, body) = handler().into_parts();
+        match body.try_into_bytes() {
+            Ok(body) => Ok((response.status(), response.headers().clone(), body)),
+            Err(_) => Err("A streamed response can't be sent from the blocking thread pool".to_string()),
+        }
+    }).await;
+
+    match parts {
+        Ok(Ok((status, headers, body))) => {
+            let mut response = HttpResponse::with_body(status, body).map_into_boxed_body();
+            *response.headers_mut() = headers;
+            response
+        },
+        Ok(Err(e)) => errors::response(ApiError::Internal, &e),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{id}/status")]
+async fn get_function_app_status(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_status_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/status")]
+async fn get_function_app_status_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_status_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Gets the status of the function app with the given ID
+fn get_function_app_status_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match get_function_app_status_result(conn, id) {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => {
+            println!("Error getting function app status: {}", e);
+            errors::response(ApiError::Internal, &e)
+        }
+    }
+}
+
+/// Gets the status of the function app with the given ID, with why it is in error and the progress of any build
+fn get_function_app_status_result(conn: &Connection, id: Uuid) -> Result<FunctionAppStatusResult, String> {
+    let status = function_app_builder::get_function_app_status(conn, &id)?;
+
+    // A queued build owns the status until it finishes, and an app that isn't running keeps the result of its latest
+    // build, so callers polling for the result of a build see it building then ready, in error, or cancelled
+    let (build_id, build_error) = storage::get_function_app_build(conn, &id).unwrap_or((None, None));
+    let building = build_queue::is_queued(&id);
+    let status = match (status, storage::get_function_app_stored_status(conn, &id)) {
+        _ if building => FunctionAppStatus::Building,
+        (FunctionAppStatus::Ready, Ok(FunctionAppStatus::Cancelled)) => FunctionAppStatus::Cancelled,
+        (FunctionAppStatus::Ready, Ok(FunctionAppStatus::Error)) if build_error.is_some() => FunctionAppStatus::Error,
+        (status, _) => status,
+    };
+
+    // A build stores its own status when it finishes
+    if !building {
+        let _ = storage::set_function_app_status(conn, &id, &status);
+    }
+
+    // If the app is waiting to be built, get the queue position and estimate how long it will wait
+    let queue_position = build_queue::get_queue_position(&id);
+    let estimated_wait_secs = match (queue_position, storage::get_average_build_duration(conn)) {
+        (Some(position), Ok(Some(average_build_duration))) => Some(build_queue::estimate_wait(position, average_build_duration)),
+        _ => None,
+    };
+
+    Ok(FunctionAppStatusResult {
+        id,
+        status,
+        queue_position,
+        estimated_wait_secs,
+        pending_deployment: storage::get_pending_deployment(conn, &id).unwrap_or(None),
+        last_stop: storage::get_last_stop(conn, &id).unwrap_or(None),
+        last_exit: storage::get_last_exit(conn, &id).unwrap_or(None),
+        last_start: match status {
+            FunctionAppStatus::Running => storage::get_last_start(conn, &id).unwrap_or(None),
+            _ => None,
+        },
+        build_id,
+        build_error,
+    })
+}
+
+#[get("/function-apps/{id}/info")]
+async fn get_function_app_info(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_info_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/info")]
+async fn get_function_app_info_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_info_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Gets the names of the settings made on a function app. Only the names are returned, as the values can hold
+/// credentials, such as a mirror URL with a token in it
+fn get_function_app_config(conn: &Connection, id: &Uuid) -> Result<Vec<String>, rusqlite::Error> {
+    let mut config = Vec::new();
+
+    if storage::get_function_app_maintenance(conn, id)?.is_some() {
+        config.push("maintenance_message".to_string());
+    }
+
+    if storage::get_function_app_mirror(conn, id)?.is_some() {
+        config.push("mirror_sink".to_string());
+    }
+
+    if storage::get_function_app_recording(conn, id)?.is_some() {
+        config.push("record_capacity".to_string());
+    }
+
+    if storage::get_function_app_redaction(conn, id)?.is_some() {
+        config.push("redaction_rules".to_string());
+    }
+
+    if storage::get_function_app_egress_allowlist(conn, id)?.is_some() {
+        config.push("egress_allowlist".to_string());
+    }
+
+    if !storage::get_function_app_grpc_services(conn, id)?.is_empty() {
+        config.push("grpc_services".to_string());
+    }
+
+    if !storage::get_function_app_dependencies(conn, id)?.is_empty() {
+        config.push("depends_on".to_string());
+    }
+
+    let (request_threshold, response_threshold) = storage::get_function_app_buffering(conn, id)?;
+    if request_threshold.is_some() {
+        config.push("buffer_request_threshold".to_string());
+    }
+    if response_threshold.is_some() {
+        config.push("buffer_response_threshold".to_string());
+    }
+
+    Ok(config)
+}
+
+/// Gets everything about the function app with the given ID in one response: its status, the settings made on it,
+/// its limits, recent deployments, triggers, and replicas
+fn get_function_app_info_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    let (name, namespace) = match storage::get_function_app_name_and_namespace(conn, &id) {
+        Ok(name_and_namespace) => name_and_namespace,
+        Err(e) => return errors::response(ApiError::BadRequest, &format!("Cannot get function app name from ID: {}", e)),
+    };
+
+    let status = match get_function_app_status_result(conn, id) {
+        Ok(status) => status,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    let config = match get_function_app_config(conn, &id) {
+        Ok(config) => config,
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let limits = match defaults::get_effective_config(conn, &id) {
+        Ok(effective) => AppLimits {
+            request_buffer_threshold: effective.buffer_request_threshold.value,
+            response_buffer_threshold: effective.buffer_response_threshold.value,
+            memory_limit_bytes: docker::get_app_memory_limit(),
+            max_replicas: effective.max_replicas.value,
+        },
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    let deployments = match storage::get_deployments(conn, &id) {
+        Ok(deployments) => deployments.into_iter().take(INFO_DEPLOYMENTS).collect(),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let timer_trigger = match storage::get_function_app_timer_trigger(conn, &id) {
+        Ok(timer_trigger) => timer_trigger,
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+    let next_timer_run = timer_trigger.as_ref()
+        .and_then(|trigger| triggers::get_next_runs(&trigger.schedule, 1).ok())
+        .and_then(|next_runs| next_runs.first().copied());
+
+    let scale_profiles = match storage::get_function_app_scale_profiles(conn, &id) {
+        Ok(profiles) => scaling::get_report(profiles),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let replicas = match storage::get_function_app_replicas(conn, &id) {
+        Ok(replicas) => replicas,
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+    let running = match replicas::get_running(conn, &id) {
+        Ok(running) => running,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    HttpResponse::Ok().json(FunctionAppInfo {
+        name,
+        namespace,
+        status,
+        config,
+        limits,
+        deployments,
+        timer_trigger,
+        next_timer_run,
+        scale_profiles,
+        replicas: ReplicasReport { replicas, running },
+    })
+}
+
+#[post("/function-apps/start")]
+async fn start_function_apps(pools: web::Data<DbPools>, body: Json<BulkStartRequest>) -> HttpResponse {
+    for name in body.apps.iter() {
+        if let Err(res) = resolve_function_app_name(&pools, name).await {
+            return *res;
+        }
+    }
+
+    match dependencies::start_apps(&body.apps).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+#[post("/function-apps/{id}/start")]
+async fn start_function_app(pools: web::Data<DbPools>, info: web::Path<String>, options: web::Query<StartOptions>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => start_function_app_with_options_impl(&pools, conn, id, &options).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/start")]
+async fn start_function_app_by_name(pools: web::Data<DbPools>, name: web::Path<String>, options: web::Query<StartOptions>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => start_function_app_with_options_impl(&pools, conn, id, &options).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/dependencies")]
+async fn set_function_app_dependencies(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<DependenciesRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_dependencies_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/dependencies")]
+async fn set_function_app_dependencies_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<DependenciesRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_dependencies_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/dependencies")]
+async fn get_function_app_dependencies(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_dependencies_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/dependencies")]
+async fn get_function_app_dependencies_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_dependencies_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/stop")]
+async fn stop_function_app(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((mut conn, id)) => run_blocking(move || stop_function_app_impl(&mut conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/restart")]
+async fn restart_function_app(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => restart_function_app_impl(&pools, conn, id).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/restart")]
+async fn restart_function_app_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => restart_function_app_impl(&pools, conn, id).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/stop")]
+async fn stop_function_app_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((mut conn, id)) => run_blocking(move || stop_function_app_impl(&mut conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[delete("/function-apps/{id}")]
+async fn delete_function_app(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((mut conn, id)) => run_blocking(move || delete_function_app_impl(&mut conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[delete("/function-apps/by-name/{name}")]
+async fn delete_function_app_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((mut conn, id)) => run_blocking(move || delete_function_app_impl(&mut conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/routes")]
+async fn get_function_app_routes(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => get_function_app_routes_impl(conn, id).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/routes")]
+async fn get_function_app_routes_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => get_function_app_routes_impl(conn, id).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/maintenance")]
+async fn set_function_app_maintenance(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<MaintenanceRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_maintenance_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/maintenance")]
+async fn set_function_app_maintenance_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<MaintenanceRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_maintenance_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/builds/current/cancel")]
+async fn cancel_function_app_build(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((_, id)) => run_blocking(move || cancel_function_app_build_impl(id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/builds/current/cancel")]
+async fn cancel_function_app_build_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((_, id)) => run_blocking(move || cancel_function_app_build_impl(id)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Cancels the current build for the function app with the given ID
+///
+/// The upload request for the build returns once the build has stopped
+fn cancel_function_app_build_impl(id: Uuid) -> HttpResponse {
+    if build_queue::cancel_build(&id) {
+        println!("Cancelling build for {}", id);
+        HttpResponse::Ok().body("")
+    } else {
+        errors::response(ApiError::NoBuild, "The function app is not building")
+    }
+}
+
+#[get("/function-apps/{id}/deployments")]
+async fn get_function_app_deployments(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_deployments_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/deployments")]
+async fn get_function_app_deployments_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_deployments_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Gets the deployments of the function app with the given ID, most recent first
+fn get_function_app_deployments_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match storage::get_deployments(conn, &id) {
+        Ok(deployments) => HttpResponse::Ok().json(deployments),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{id}/logs")]
+async fn get_function_app_logs(pools: web::Data<DbPools>, info: web::Path<String>, options: web::Query<LogsOptions>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => get_function_app_logs_impl(conn, id, &options).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/logs")]
+async fn get_function_app_logs_by_name(pools: web::Data<DbPools>, name: web::Path<String>, options: web::Query<LogsOptions>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => get_function_app_logs_impl(conn, id, &options).await,
+        Err(res) => *res,
+    }
+}
+
+/// Gets the most recent output from the container for the function app with the given ID, as plain text
+///
+/// When following, the output is streamed as server sent events instead, with the most recent lines and then each
+/// line as the app writes it until the container exits
+async fn get_function_app_logs_impl(conn: DbConnection, id: Uuid, options: &LogsOptions) -> HttpResponse {
+    // The connection goes back to the pool before docker is called, as the logs can be followed for as long as the
+    // app runs
+    let function_app_name = web::block(move || storage::get_function_app_name(&conn, &id).map_err(|e| e.to_string())).await.map_err(|e| e.to_string());
+    let function_app_name = match function_app_name {
+        Ok(Ok(name)) => name,
+        Ok(Err(e)) | Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    let tail = options.tail;
+
+    // Docker is run on a blocking thread so the server can keep handling requests
+    if options.follow {
+        return match web::block(move || docker::follow_logs(&function_app_name, tail)).await {
+            Ok(Ok(receiver)) => stream_events(receiver),
+            Ok(Err(e)) => errors::response(ApiError::Internal, &e),
+            Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+        };
+    }
+
+    match web::block(move || docker::get_logs(&function_app_name, tail)).await {
+        Ok(Ok(logs)) => HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(logs),
+        Ok(Err(e)) => errors::response(ApiError::Internal, &e),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{id}/resource-samples")]
+async fn get_function_app_resource_samples(pools: web::Data<DbPools>, info: web::Path<String>, options: web::Query<ResourceSamplesOptions>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_resource_samples_impl(&conn, id, &options)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/resource-samples")]
+async fn get_function_app_resource_samples_by_name(pools: web::Data<DbPools>, name: web::Path<String>, options: web::Query<ResourceSamplesOptions>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_resource_samples_impl(&conn, id, &options)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Gets the most recent samples of the resources the function app with the given ID was using, newest first
+fn get_function_app_resource_samples_impl(conn: &Connection, id: Uuid, options: &ResourceSamplesOptions) -> HttpResponse {
+    match storage::get_resource_samples(conn, &id, options.last) {
+        Ok(samples) => HttpResponse::Ok().json(samples),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{id}/build-logs")]
+async fn get_function_app_build_log(pools: web::Data<DbPools>, info: web::Path<String>, options: web::Query<BuildLogOptions>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => get_function_app_build_log_impl(conn, id, &options).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/build-logs")]
+async fn get_function_app_build_log_by_name(pools: web::Data<DbPools>, name: web::Path<String>, options: web::Query<BuildLogOptions>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => get_function_app_build_log_impl(conn, id, &options).await,
+        Err(res) => *res,
+    }
+}
+
+/// Streams server sent events, such as a build log or the output of an app, to the client
+fn stream_events(receiver: UnboundedReceiver<String>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(receiver.map(|frame| Ok::<_, actix_web::Error>(web::Bytes::from(frame))))
+}
+
+/// Gets the output of the latest build of the function app with the given ID, or of the given build, as JSON
+///
+/// When following, the log is streamed as server sent events instead. A running build sends its output so far and
+/// then each line as it is written until it finishes, and a finished build sends its saved output straight away
+async fn get_function_app_build_log_impl(conn: DbConnection, id: Uuid, options: &BuildLogOptions) -> HttpResponse {
+    // The running build is checked first, as its log isn't saved until it finishes
+    if options.follow {
+        if let Some(receiver) = build_logs::follow(&id, &options.build) {
+            return stream_events(receiver);
+        }
+    } else if let Some(log) = build_logs::get_running(&id, &options.build) {
+        return HttpResponse::Ok().json(log);
+    }
+
+    let build = options.build;
+    let log = web::block(move || storage::get_build_log(&conn, &id, &build).map_err(|e| e.to_string())).await.map_err(|e| e.to_string());
+    let log = match log {
+        Ok(Ok(Some(log))) => log,
+        Ok(Ok(None)) => return errors::response(ApiError::BuildLogNotFound, "No log was found for the build"),
+        Ok(Err(e)) | Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    match options.follow {
+        true => stream_events(build_logs::replay(&log)),
+        false => HttpResponse::Ok().json(log),
+    }
+}
+
+/// Serves the web console. The console is a single page app, so paths that aren't assets get the page itself
+#[get("/ui/{path:.*}")]
+async fn get_ui(path: web::Path<String>) -> HttpResponse {
+    match ui::get_asset(&path) {
+        Some((content, content_type)) => HttpResponse::Ok().content_type(content_type).body(content),
+        None => errors::response(ApiError::NotFound, "The web console is missing from this build"),
+    }
+}
+
+/// Redirects to the web console, so its assets are loaded relative to /ui/. Behind a reverse proxy the redirect
+/// includes the path the host is served under, as the proxy strips it from the request
+#[get("/ui")]
+async fn redirect_to_ui() -> HttpResponse {
+    HttpResponse::PermanentRedirect().insert_header(("Location", format!("{}/ui/", listeners::get_base_path()))).finish()
+}
+
+#[get("/signing-key")]
+async fn get_signing_key() -> HttpResponse {
+    run_blocking(get_signing_key_impl).await
+}
+
+/// Gets the public key images are signed with
+fn get_signing_key_impl() -> HttpResponse {
+    match signing::get_public_key_pem() {
+        Ok(pem) => HttpResponse::Ok().content_type("application/x-pem-file").body(pem),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+#[get("/function-apps/{id}/signature")]
+async fn verify_function_app_signature(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || verify_function_app_signature_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/signature")]
+async fn verify_function_app_signature_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || verify_function_app_signature_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Checks the image for the function app with the given ID was signed by this host and hasn't changed since
+fn verify_function_app_signature_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match storage::get_function_app_name(conn, &id) {
+        Ok(function_app_name) => HttpResponse::Ok().json(signing::verify_function_app_image(conn, &id, &function_app_name)),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{id}/deployments/{number}/sbom")]
+async fn get_deployment_sbom(pools: web::Data<DbPools>, info: web::Path<(String, String)>) -> HttpResponse {
+    let (info, number) = info.into_inner();
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_deployment_sbom_impl(&conn, id, &number)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/deployments/{number}/sbom")]
+async fn get_deployment_sbom_by_name(pools: web::Data<DbPools>, info: web::Path<(String, String)>) -> HttpResponse {
+    let (name, number) = info.into_inner();
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_deployment_sbom_impl(&conn, id, &number)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Gets the number of a deployment of the function app with the given ID from a path, which is a number, or latest
+/// for the most recent deployment
+fn resolve_deployment_number(conn: &Connection, id: &Uuid, number: &str) -> Result<u32, Box<HttpResponse>> {
+    parse_deployment_number(conn, id, number).map_err(|(error, message)| Box::new(errors::response(error, &message)))
+}
+
+/// Gets the number of a deployment given as a number or latest, returning the error and message if it isn't valid
+fn parse_deployment_number(conn: &Connection, id: &Uuid, number: &str) -> Result<u32, (ApiError, String)> {
+    match number {
+        "latest" => match storage::get_latest_deployment(conn, id) {
+            Ok(Some(number)) => Ok(number),
+            Ok(None) => Err((ApiError::DeploymentNotFound, "The function app has not been deployed".to_string())),
+            Err(e) => Err((ApiError::Internal, e.to_string())),
+        },
+        number => match number.parse::<u32>() {
+            Ok(number) => Ok(number),
+            Err(_) => Err((
+                ApiError::InvalidDeployment,
+                format!("Invalid deployment '{}': use a deployment number or latest", number),
+            )),
+        },
+    }
+}
+
+/// Gets the CycloneDX bill of materials generated when a deployment of the function app with the given ID was built.
+/// The deployment is a number, or latest for the most recent deployment
+fn get_deployment_sbom_impl(conn: &Connection, id: Uuid, number: &str) -> HttpResponse {
+    let number = match resolve_deployment_number(conn, &id, number) {
+        Ok(number) => number,
+        Err(res) => return *res,
+    };
+
+    match storage::get_deployment_sbom(conn, &id, number) {
+        Ok(Some(Some(sbom))) => HttpResponse::Ok().content_type("application/vnd.cyclonedx+json").body(sbom),
+        Ok(Some(None)) => errors::response(
+            ApiError::NoSbom,
+            &format!("No bill of materials was generated for deployment {}", number),
+        ),
+        Ok(None) => errors::response(
+            ApiError::DeploymentNotFound,
+            &format!("Deployment {} does not exist", number),
+        ),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{id}/deployments/{number}/export")]
+async fn export_deployment(pools: web::Data<DbPools>, info: web::Path<(String, String)>) -> HttpResponse {
+    let (info, number) = info.into_inner();
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => export_deployment_impl(conn, id, &number).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/deployments/{number}/export")]
+async fn export_deployment_by_name(pools: web::Data<DbPools>, info: web::Path<(String, String)>) -> HttpResponse {
+    let (name, number) = info.into_inner();
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => export_deployment_impl(conn, id, &number).await,
+        Err(res) => *res,
+    }
+}
+
+/// Exports a deployment of the function app with the given ID so it can be promoted to another host, as the details
+/// of the deployment on one line of JSON followed by its image saved with docker save. The deployment is a number,
+/// or latest for the most recent deployment
+///
+/// Only the image for the latest deployment is kept, so only it can be exported. It must be approved, and its image
+/// must be the one signed when it was built, so what is promoted is exactly what was approved
+async fn export_deployment_impl(conn: DbConnection, id: Uuid, number: &str) -> HttpResponse {
+    // The checks call the database and docker, so they run on the blocking thread pool, and the connection goes back
+    // to the pool before the image is saved
+    let number = number.to_string();
+    let (function_app_name, number, image_id, stored) = match web::block(move || check_export(&conn, id, &number)).await {
+        Ok(Ok(checked)) => checked,
+        Ok(Err((error, message))) => return errors::response(error, &message),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let temp_dir = match build_dirs::create() {
+        Ok(dir) => dir,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    // Saving the image can take a while for large apps, so it runs on the blocking thread pool
+    let name = function_app_name.clone();
+    let file = match actix_web::rt::task::spawn_blocking(move || promotion::save_image(&temp_dir, &name)).await {
+        Ok(Ok(file)) => file,
+        Ok(Err(e)) => return errors::response(ApiError::Internal, &e),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let export = DeploymentExport {
+        app: function_app_name,
+        app_id: id,
+        deployment: number,
+        image_id,
+        content_hash: stored.content_hash,
+        sbom: stored.sbom,
+        readme: stored.readme,
+    };
+
+    match promotion::stream_export(&export, file) {
+        Ok(receiver) => {
+            println!("Exporting deployment {} of {}", number, export.app);
+            HttpResponse::Ok()
+                .content_type(promotion::EXPORT_CONTENT_TYPE)
+                .insert_header((EXPORT_DEPLOYMENT_HEADER, number.to_string()))
+                .insert_header((EXPORT_IMAGE_ID_HEADER, export.image_id.as_str()))
+                .streaming(receiver)
+        },
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Checks a deployment of the function app with the given ID can be exported, returning the app name, deployment
+/// number, image ID, and what was stored for the deployment
+fn check_export(conn: &Connection, id: Uuid, number: &str) -> Result<(String, u32, String, storage::StoredExport), (ApiError, String)> {
+    let number = parse_deployment_number(conn, &id, number)?;
+
+    let function_app_name = match storage::get_function_app_name(conn, &id) {
+        Ok(function_app_name) => function_app_name,
+        Err(e) => return Err((ApiError::Internal, e.to_string())),
+    };
+
+    let stored = match storage::get_deployment_export(conn, &id, number) {
+        Ok(Some(stored)) => stored,
+        Ok(None) => return Err((ApiError::DeploymentNotFound, format!("Deployment {} does not exist", number))),
+        Err(e) => return Err((ApiError::Internal, e.to_string())),
+    };
+
+    match storage::get_latest_deployment(conn, &id) {
+        Ok(Some(latest)) if latest == number => {},
+        Ok(_) => return Err((
+            ApiError::ImageGone,
+            format!("Only the image for the latest deployment is kept, so deployment {} can't be exported", number),
+        )),
+        Err(e) => return Err((ApiError::Internal, e.to_string())),
+    }
+
+    if !stored.approved {
+        return Err((
+            ApiError::ApprovalRequired,
+            format!("Deployment {} must be approved before it can be promoted", number),
+        ));
+    }
+
+    let image_id = match docker::get_image_id(&function_app_name) {
+        Some(image_id) => image_id,
+        None => return Err((ApiError::ImageGone, format!("The image for deployment {} is no longer there", number))),
+    };
+
+    if stored.image_digest.as_ref().is_some_and(|image_digest| *image_digest != image_id) {
+        return Err((
+            ApiError::SignatureInvalid,
+            format!("The image for deployment {} has changed since it was built", number),
+        ));
+    }
+
+    Ok((function_app_name, number, image_id, stored))
+}
+
+#[post("/function-apps/{id}/import")]
+async fn import_deployment(pools: web::Data<DbPools>, info: web::Path<String>, options: web::Query<ImportOptions>, payload: web::Payload) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => import_deployment_impl(&pools, conn, id, &options, payload).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/import")]
+async fn import_deployment_by_name(pools: web::Data<DbPools>, name: web::Path<String>, options: web::Query<ImportOptions>, payload: web::Payload) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => import_deployment_impl(&pools, conn, id, &options, payload).await,
+        Err(res) => *res,
+    }
+}
+
+/// Imports a deployment exported from another host as a new deployment of the function app with the given ID,
+/// without building it. The image is loaded with the same ID it had on the other host, and the deployment records
+/// where it was promoted from
+///
+/// The deployment needs approving in the same way as a build if RUSTLESS_REQUIRE_APPROVAL is set
+async fn import_deployment_impl(pools: &web::Data<DbPools>, conn: DbConnection, id: Uuid, options: &ImportOptions, payload: web::Payload) -> HttpResponse {
+    let function_app_name = match storage::get_function_app_name(&conn, &id) {
+        Ok(function_app_name) => function_app_name,
+        Err(e) => return errors::response(ApiError::BadRequest, &format!("Cannot get function app name from ID: {}", e)),
+    };
+
+    // Loading the image would replace the one a build in progress is about to record
+    if build_queue::is_queued(&id) {
+        return errors::response(
+            ApiError::BuildInProgress,
+            &format!("A build is in progress for {}, so a deployment can't be imported until it finishes", function_app_name),
+        );
+    }
+
+    // Check there is disk for another image before anything changes
+    match quotas::check_quota(quotas::Quota::Disk) {
+        Ok(Some(warning)) => events::publish_quota_warning(&conn, &id, &warning),
+        Ok(None) => {},
+        Err(e) => return quota_exceeded_response(&e),
+    }
+
+    let temp_dir = match build_dirs::create() {
+        Ok(dir) => dir,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    // Receiving and loading the image can take minutes, so the connection goes back to the pool until it is done
+    drop(conn);
+
+    let export = match promotion::read_export(&temp_dir, payload).await {
+        Ok(export) => export,
+        Err(res) => return *res,
+    };
+
+    // Loading the image can take a while for large apps, so it runs on the blocking thread pool
+    let name = function_app_name.clone();
+    let image_id = export.image_id.clone();
+    let loaded = actix_web::rt::task::spawn_blocking(move || {
+        docker::load_image(&name, &temp_dir.path().join(promotion::EXPORT_FILE_NAME), &image_id)
+    }).await;
+
+    match loaded {
+        Ok(Ok(_)) => {},
+        Ok(Err(e)) => return errors::response(ApiError::InvalidExport, &e),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    }
+
+    let conn = match connect_function_app(pools, id).await {
+        Ok(conn) => conn,
+        Err(res) => return *res,
+    };
+
+    let imported_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    let promoted_from = Promotion {
+        host: options.from.clone().unwrap_or_else(|| "unknown".to_string()),
+        deployment: export.deployment,
+        image_id: export.image_id.clone(),
+        promoted_at: imported_at,
+    };
+    let stored = storage::StoredExport {
+        approved: !approvals::is_approval_required(),
+        image_digest: None,
+        content_hash: export.content_hash,
+        sbom: export.sbom,
+        readme: export.readme,
+    };
+
+    let number = match storage::add_imported_deployment(&conn, &id, imported_at, &stored, &promoted_from) {
+        Ok(number) => number,
+        Err(e) => return errors::response(ApiError::Internal, &format!("Error adding deployment: {}", e)),
+    };
+
+    // Sign the image on this host too, so it can be checked before it is started
+    if let Err(e) = signing::sign_deployment(&conn, &id, &function_app_name, number) {
+        println!("Error signing image for {}: {}", function_app_name, e);
+    }
+
+    if let Err(e) = storage::set_function_app_status(&conn, &id, &FunctionAppStatus::Ready) {
+        return errors::response(ApiError::Internal, &format!("Error updating status: {}", e));
+    }
+
+    println!("Imported deployment {} of {} from {} as deployment {}", export.deployment, function_app_name, promoted_from.host, number);
+    let progress = if stored.approved { "deployed" } else { "awaiting_approval" };
+    events::publish_deploy_progress(&conn, &id, progress, Some(number), None);
+
+    HttpResponse::Ok().json(ImportedDeployment {
+        number,
+        approved: stored.approved,
+        image_id: export.image_id,
+    })
+}
+
+#[post("/function-apps/{id}/deployments/{number}/promotions")]
+async fn add_deployment_promotion(pools: web::Data<DbPools>, info: web::Path<(String, u32)>, body: Json<Promotion>) -> HttpResponse {
+    let (info, number) = info.into_inner();
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || add_deployment_promotion_impl(conn, id, number, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/deployments/{number}/promotions")]
+async fn add_deployment_promotion_by_name(pools: web::Data<DbPools>, info: web::Path<(String, u32)>, body: Json<Promotion>) -> HttpResponse {
+    let (name, number) = info.into_inner();
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || add_deployment_promotion_impl(conn, id, number, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Records that a deployment of the function app with the given ID was promoted to another host
+fn add_deployment_promotion_impl(mut conn: DbConnection, id: Uuid, number: u32, promotion: &Promotion) -> HttpResponse {
+    match storage::add_deployment_promotion(&mut conn, &id, number, promotion) {
+        Ok(true) => {
+            println!("Deployment {} of {} was promoted to {} as deployment {}", number, id, promotion.host, promotion.deployment);
+            HttpResponse::Ok().body("")
+        },
+        Ok(false) => errors::response(ApiError::DeploymentNotFound, &format!("Deployment {} does not exist", number)),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{id}/readme")]
+async fn get_function_app_readme(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_readme_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/readme")]
+async fn get_function_app_readme_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_readme_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Gets the README from the code for the latest deployment of the function app with the given ID
+fn get_function_app_readme_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match storage::get_latest_readme(conn, &id) {
+        Ok(Some(readme)) => HttpResponse::Ok().content_type("text/markdown; charset=utf-8").body(readme),
+        Ok(None) => errors::response(
+            ApiError::NoReadme,
+            "The code for the latest deployment doesn't have a README",
+        ),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[post("/function-apps/{id}/deployments/{number}/approve")]
+async fn approve_deployment(pools: web::Data<DbPools>, req: HttpRequest, info: web::Path<(String, u32)>) -> HttpResponse {
+    if let Err(res) = approvals::check_approver(&req) {
+        return *res;
+    }
+
+    let (info, number) = info.into_inner();
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || approve_deployment_impl(&conn, id, number)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/deployments/{number}/approve")]
+async fn approve_deployment_by_name(pools: web::Data<DbPools>, req: HttpRequest, info: web::Path<(String, u32)>) -> HttpResponse {
+    if let Err(res) = approvals::check_approver(&req) {
+        return *res;
+    }
+
+    let (name, number) = info.into_inner();
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || approve_deployment_impl(&conn, id, number)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Approves a deployment for the function app with the given ID so it can be started
+fn approve_deployment_impl(conn: &Connection, id: Uuid, number: u32) -> HttpResponse {
+    match storage::get_pending_deployment(conn, &id) {
+        Ok(Some(pending)) if pending == number => {},
+        Ok(_) => return errors::response(
+            ApiError::NotPending,
+            &format!("Deployment {} is not waiting for approval", number),
+        ),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let approved_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    match storage::approve_deployment(conn, &id, number, approved_at) {
+        Ok(_) => {
+            println!("Deployment {} approved for {}", number, id);
+            events::publish_deploy_progress(conn, &id, "approved", Some(number), None);
+            HttpResponse::Ok().body("")
+        },
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[post("/function-apps/{id}/triggers/timer")]
+async fn set_function_app_timer_trigger(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<TimerTriggerRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_timer_trigger_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/triggers/timer")]
+async fn set_function_app_timer_trigger_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<TimerTriggerRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_timer_trigger_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/triggers/timer/next")]
+async fn get_function_app_timer_next_runs(pools: web::Data<DbPools>, info: web::Path<String>, options: web::Query<NextRunsOptions>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_timer_next_runs_impl(&conn, id, &options)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/triggers/timer/next")]
+async fn get_function_app_timer_next_runs_by_name(pools: web::Data<DbPools>, name: web::Path<String>, options: web::Query<NextRunsOptions>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_timer_next_runs_impl(&conn, id, &options)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/triggers/{trigger}/run")]
+async fn run_function_app_trigger(pools: web::Data<DbPools>, info: web::Path<(String, String)>) -> HttpResponse {
+    let (id, trigger) = info.into_inner();
+    match resolve_function_app_id(&pools, &id).await {
+        Ok((conn, id)) => run_function_app_trigger_impl(conn, id, &trigger).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/triggers/{trigger}/run")]
+async fn run_function_app_trigger_by_name(pools: web::Data<DbPools>, info: web::Path<(String, String)>) -> HttpResponse {
+    let (name, trigger) = info.into_inner();
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_function_app_trigger_impl(conn, id, &trigger).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/triggers/runs")]
+async fn get_function_app_trigger_runs(pools: web::Data<DbPools>, info: web::Path<String>, options: web::Query<TriggerRunsOptions>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_trigger_runs_impl(&conn, id, &options)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/triggers/runs")]
+async fn get_function_app_trigger_runs_by_name(pools: web::Data<DbPools>, name: web::Path<String>, options: web::Query<TriggerRunsOptions>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_trigger_runs_impl(&conn, id, &options)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/egress")]
+async fn set_function_app_egress(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<EgressRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_egress_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/egress")]
+async fn set_function_app_egress_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<EgressRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_egress_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/egress")]
+async fn get_function_app_egress(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_egress_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/grpc")]
+async fn set_function_app_grpc(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<GrpcServicesRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_grpc_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/grpc")]
+async fn set_function_app_grpc_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<GrpcServicesRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_grpc_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/grpc")]
+async fn get_function_app_grpc(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_grpc_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/grpc")]
+async fn get_function_app_grpc_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_grpc_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/scale")]
+async fn scale_function_app(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<ScaleRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((mut conn, id)) => run_blocking(move || scale_function_app_impl(&mut conn, id, body.replicas)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/scale")]
+async fn scale_function_app_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<ScaleRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((mut conn, id)) => run_blocking(move || scale_function_app_impl(&mut conn, id, body.replicas)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/scale")]
+async fn get_function_app_replicas(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_replicas_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/scale")]
+async fn get_function_app_replicas_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_replicas_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/scale-profiles")]
+async fn set_function_app_scale_profiles(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<ScaleProfilesRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_scale_profiles_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/scale-profiles")]
+async fn set_function_app_scale_profiles_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<ScaleProfilesRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_scale_profiles_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/scale-profiles")]
+async fn get_function_app_scale_profiles(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_scale_profiles_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/scale-profiles")]
+async fn get_function_app_scale_profiles_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_scale_profiles_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/egress")]
+async fn get_function_app_egress_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_egress_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/buffering")]
+async fn set_function_app_buffering(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<BufferingRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_buffering_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/buffering")]
+async fn set_function_app_buffering_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<BufferingRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_buffering_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/buffering")]
+async fn get_function_app_buffering(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_buffering_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/buffering")]
+async fn get_function_app_buffering_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_buffering_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/effective-config")]
+async fn get_function_app_effective_config(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_effective_config_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/effective-config")]
+async fn get_function_app_effective_config_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_effective_config_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/{id}/mirror")]
+async fn set_function_app_mirror(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<MirrorRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_mirror_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/mirror")]
+async fn set_function_app_mirror_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<MirrorRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_mirror_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Sets or removes the timer trigger for the function app with the given ID
+///
+/// The schedule is checked before it is saved, and the upcoming runs are returned so the caller can check
+/// the trigger will fire when they expect
+fn set_function_app_timer_trigger_impl(conn: &Connection, id: Uuid, request: &TimerTriggerRequest) -> HttpResponse {
+    let trigger = match (request.enabled, &request.trigger) {
+        (true, Some(trigger)) => match triggers::validate_trigger(trigger) {
+            Ok(trigger) => Some(trigger),
+            Err(e) => return errors::response(ApiError::InvalidTrigger, &e),
+        },
+        (true, None) => return errors::response(ApiError::InvalidTrigger, "A schedule and route are required to turn on the timer trigger"),
+        (false, _) => None,
+    };
+
+    if let Err(e) = storage::set_function_app_timer_trigger(conn, &id, &trigger) {
+        return errors::response(ApiError::Internal, &e.to_string());
+    }
+
+    match trigger {
+        Some(trigger) => match triggers::get_next_runs(&trigger.schedule, default_next_runs_count()) {
+            Ok(next_runs) => HttpResponse::Ok().json(NextRuns { schedule: trigger.schedule, route: Some(trigger.route), next_runs }),
+            Err(e) => errors::response(ApiError::Internal, &e),
+        },
+        None => HttpResponse::Ok().body(""),
+    }
+}
+
+/// Gets the upcoming runs of the timer trigger for the function app with the given ID, or of the schedule
+/// in the options so it can be checked before it is set
+fn get_function_app_timer_next_runs_impl(conn: &Connection, id: Uuid, options: &NextRunsOptions) -> HttpResponse {
+    let (schedule, route) = match &options.schedule {
+        Some(schedule) => (schedule.to_string(), None),
+        None => match storage::get_function_app_timer_trigger(conn, &id) {
+            Ok(Some(trigger)) => (trigger.schedule, Some(trigger.route)),
+            Ok(None) => return errors::response(ApiError::NoTimerTrigger, "The function app does not have a timer trigger"),
+            Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+        },
+    };
+
+    match triggers::get_next_runs(&schedule, options.count) {
+        Ok(next_runs) => HttpResponse::Ok().json(NextRuns { schedule, route, next_runs }),
+        Err(e) => errors::response(ApiError::InvalidSchedule, &e),
+    }
+}
+
+/// Fires a trigger for the function app with the given ID now rather than waiting for it to be due, recording
+/// it in the trigger history as a manual run. The run is returned even if the app returned an error status code
+async fn run_function_app_trigger_impl(conn: DbConnection, id: Uuid, trigger_name: &str) -> HttpResponse {
+    if trigger_name != TIMER_TRIGGER {
+        return errors::response(
+            ApiError::UnknownTrigger,
+            &format!("Unknown trigger '{}'. Function apps support the {} trigger", trigger_name, TIMER_TRIGGER),
+        );
+    }
+
+    // The trigger is fired with a blocking client, the same as the scheduler, so run it off the async runtime
+    let run = web::block(move || {
+        let trigger = match storage::get_function_app_timer_trigger(&conn, &id) {
+            Ok(Some(trigger)) => trigger,
+            Ok(None) => return Err((ApiError::NoTimerTrigger, "The function app does not have a timer trigger".to_string())),
+            Err(e) => return Err((ApiError::Internal, e.to_string())),
+        };
+
+        // Only running apps can be triggered
+        let port = match storage::get_function_app_port(&conn, &id) {
+            Ok(Some(port)) => port,
+            Ok(None) => return Err((ApiError::NotRunning, "The function app is not running".to_string())),
+            Err(e) => return Err((ApiError::Internal, e.to_string())),
+        };
+
+        Ok(triggers::invoke_timer_trigger(&conn, &id, port, &trigger, true))
+    }).await;
+
+    match run {
+        Ok(Ok(run)) => HttpResponse::Ok().json(run),
+        Ok(Err((error, message))) => errors::response(error, &message),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Gets the most recent trigger invocations for the function app with the given ID
+fn get_function_app_trigger_runs_impl(conn: &Connection, id: Uuid, options: &TriggerRunsOptions) -> HttpResponse {
+    match storage::get_trigger_runs(conn, &id, &options.trigger, options.last) {
+        Ok(runs) => HttpResponse::Ok().json(runs),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Restricts the destinations the function app with the given ID can call through the egress proxy, or lifts the
+/// restriction. The allowlist is checked for each new connection, so this applies to a running app straight away
+fn set_function_app_egress_impl(conn: &Connection, id: Uuid, request: &EgressRequest) -> HttpResponse {
+    let allowlist = match request.restricted {
+        true => match egress::validate_allowlist(&request.allowlist) {
+            Ok(allowlist) => Some(allowlist),
+            Err(e) => return errors::response(ApiError::InvalidAllowlist, &e),
+        },
+        false => None,
+    };
+
+    match storage::set_function_app_egress_allowlist(conn, &id, &allowlist) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Gets the egress allowlist for the function app with the given ID, or the default for its namespace if it doesn't
+/// have one, and the destinations it has called
+fn get_function_app_egress_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match defaults::get_effective_config(conn, &id) {
+        Ok(effective) => HttpResponse::Ok().json(EgressReport {
+            proxy_enabled: egress::is_enabled(),
+            allowlist: effective.egress_allowlist.value,
+            destinations: egress::get_destinations(&id),
+        }),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Sets the gRPC services the function app with the given ID serves. Each service can only be served by one app,
+/// as the gRPC gateway routes calls by service
+fn set_function_app_grpc_impl(conn: &Connection, id: Uuid, request: &GrpcServicesRequest) -> HttpResponse {
+    let services = match grpc::validate_services(&request.services) {
+        Ok(services) => services,
+        Err(e) => return errors::response(ApiError::InvalidServices, &e),
+    };
+
+    for service in services.iter() {
+        match storage::find_app_for_grpc_service(service) {
+            Ok(Some((_, other_id))) if other_id != id => return errors::response(
+                ApiError::ServiceInUse,
+                &format!("The service {} is already served by another function app", service),
+            ),
+            Ok(_) => {},
+            Err(e) => return errors::response(ApiError::Internal, &e),
+        }
+    }
+
+    match storage::set_function_app_grpc_services(conn, &id, &services) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Gets the gRPC services the function app with the given ID serves, and the calls made to its methods
+fn get_function_app_grpc_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match storage::get_function_app_grpc_services(conn, &id) {
+        Ok(services) => HttpResponse::Ok().json(GrpcReport {
+            gateway_enabled: grpc::is_enabled(),
+            services,
+            methods: grpc::get_method_metrics(&id),
+        }),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Scales the function app with the given ID to a number of replicas, returning the replicas it is running
+///
+/// Stopped apps are started with the replicas, and running apps have extra containers started or stopped to match.
+/// Scaling to 0 stops the app, keeping the replicas it had so it runs the same number when it is started again
+fn scale_function_app_impl(conn: &mut Connection, id: Uuid, replicas: u32) -> HttpResponse {
+    let max_replicas = match defaults::get_effective_config(conn, &id) {
+        Ok(effective) => effective.max_replicas.value,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+    if replicas > max_replicas {
+        return errors::response(
+            ApiError::InvalidReplicas,
+            &format!("Cannot scale to {} replicas, the most an app can run is {}", replicas, max_replicas),
+        );
+    }
+
+    let function_app_name = match storage::get_function_app_name(conn, &id) {
+        Ok(n) => n,
+        Err(e) => {
+            return errors::response(ApiError::BadRequest, &format!("Cannot get function app name from ID: {}", e));
+        }
+    };
+
+    let running = match storage::get_function_app_port(conn, &id) {
+        Ok(port) => port.is_some(),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    if replicas == 0 {
+        if running {
+            let res = stop_function_app_impl(conn, id);
+            if !res.status().is_success() {
+                return res;
+            }
+        }
+
+        return get_function_app_replicas_impl(conn, id);
+    }
+
+    if let Err(e) = storage::set_function_app_replicas(conn, &id, replicas) {
+        return errors::response(ApiError::Internal, &e.to_string());
+    }
+
+    if running {
+        if let Err(e) = replicas::scale(conn, &id, &function_app_name, replicas) {
+            println!("Error scaling function app {}: {}", function_app_name, e);
+            return errors::response(ApiError::Internal, &format!("Error scaling function app: {}", e));
+        }
+    } else {
+        let res = start_function_app_impl(conn, id);
+        if !res.status().is_success() {
+            return res;
+        }
+    }
+
+    get_function_app_replicas_impl(conn, id)
+}
+
+/// Gets the replicas the function app with the given ID runs, and the containers running it
+fn get_function_app_replicas_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    let replicas = match storage::get_function_app_replicas(conn, &id) {
+        Ok(replicas) => replicas,
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    match replicas::get_running(conn, &id) {
+        Ok(running) => HttpResponse::Ok().json(ReplicasReport { replicas, running }),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Sets the scale profiles for the function app with the given ID, or clears them if the list is empty. The app is
+/// scaled to the profile in effect now, rather than waiting for the next profile to start
+fn set_function_app_scale_profiles_impl(conn: &Connection, id: Uuid, request: &ScaleProfilesRequest) -> HttpResponse {
+    let max_replicas = match defaults::get_effective_config(conn, &id) {
+        Ok(effective) => effective.max_replicas.value,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    let profiles = match scaling::validate_profiles(&request.profiles, max_replicas) {
+        Ok(profiles) => profiles,
+        Err(e) => return errors::response(ApiError::InvalidScaleProfiles, &e),
+    };
+
+    if let Err(e) = storage::set_function_app_scale_profiles(conn, &id, &profiles) {
+        return errors::response(ApiError::Internal, &e.to_string());
+    }
+
+    let report = scaling::get_report(profiles);
+    if let Some(replicas) = report.current_replicas {
+        match storage::get_function_app_name(conn, &id) {
+            Ok(name) => scaling::scale_in_background(id, name, replicas),
+            Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+        }
+    }
+
+    HttpResponse::Ok().json(report)
+}
+
+/// Gets the scale profiles for the function app with the given ID, and the replicas they set now and next
+fn get_function_app_scale_profiles_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match storage::get_function_app_scale_profiles(conn, &id) {
+        Ok(profiles) => HttpResponse::Ok().json(scaling::get_report(profiles)),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Sets when the gateway buffers or streams bodies for the function app with the given ID. This applies to the
+/// next request, so a running app doesn't need restarting
+fn set_function_app_buffering_impl(conn: &Connection, id: Uuid, request: &BufferingRequest) -> HttpResponse {
+    // SQLite stores integers as i64, so larger thresholds can't be saved
+    let max_threshold = i64::MAX as u64;
+    if request.request_threshold.unwrap_or(0) > max_threshold || request.response_threshold.unwrap_or(0) > max_threshold {
+        return errors::response(ApiError::InvalidThreshold, &format!("Thresholds must be at most {} bytes", max_threshold));
+    }
+
+    match storage::set_function_app_buffering(conn, &id, request.request_threshold, request.response_threshold) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Gets the buffering thresholds for the function app with the given ID, and how its bodies have been buffered
+fn get_function_app_buffering_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match defaults::get_effective_config(conn, &id) {
+        Ok(effective) => HttpResponse::Ok().json(BufferingReport {
+            request_threshold: effective.buffer_request_threshold.value,
+            response_threshold: effective.buffer_response_threshold.value,
+            request_threshold_default: effective.buffer_request_threshold.source != SettingSource::App,
+            response_threshold_default: effective.buffer_response_threshold.source != SettingSource::App,
+            metrics: gateway::get_buffer_metrics(&id),
+        }),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Gets the settings the function app with the given ID uses, with whether each is set on the app or comes from
+/// the defaults for its namespace or the host
+fn get_function_app_effective_config_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match defaults::get_effective_config(conn, &id) {
+        Ok(effective) => HttpResponse::Ok().json(effective),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Turns request mirroring on or off for the function app with the given ID
+fn set_function_app_mirror_impl(conn: &Connection, id: Uuid, request: &MirrorRequest) -> HttpResponse {
+    let config = match (request.enabled, &request.config) {
+        (true, Some(config)) => {
+            if let Err(e) = mirror::validate_config(config) {
+                return errors::response(ApiError::InvalidMirror, &e);
+            }
+            Some(config.clone())
+        },
+        (true, None) => return errors::response(ApiError::InvalidMirror, "A mirror config is required to mirror requests"),
+        (false, _) => None,
+    };
+
+    match storage::set_function_app_mirror(conn, &id, &config) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[post("/function-apps/{id}/recording")]
+async fn set_function_app_recording(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<RecordingRequest>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_recording_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/recording")]
+async fn set_function_app_recording_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<RecordingRequest>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_recording_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Turns recording recent requests on or off for the function app with the given ID.
+/// Turning recording off removes the requests recorded so far
+fn set_function_app_recording_impl(conn: &Connection, id: Uuid, request: &RecordingRequest) -> HttpResponse {
+    let capacity = match request.enabled {
+        true => match recorder::validate_capacity(request.capacity) {
+            Ok(_) => Some(request.capacity),
+            Err(e) => return errors::response(ApiError::InvalidCapacity, &e),
+        },
+        false => None,
+    };
+
+    if capacity.is_none() {
+        recorder::clear(&id);
+    }
+
+    match storage::set_function_app_recording(conn, &id, capacity) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[post("/function-apps/{id}/redaction")]
+async fn set_function_app_redaction(pools: web::Data<DbPools>, info: web::Path<String>, body: Json<RedactionRules>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_redaction_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[post("/function-apps/by-name/{name}/redaction")]
+async fn set_function_app_redaction_by_name(pools: web::Data<DbPools>, name: web::Path<String>, body: Json<RedactionRules>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || set_function_app_redaction_impl(&conn, id, &body)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/{id}/redaction")]
+async fn get_function_app_redaction(pools: web::Data<DbPools>, info: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_redaction_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/redaction")]
+async fn get_function_app_redaction_by_name(pools: web::Data<DbPools>, name: web::Path<String>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => run_blocking(move || get_function_app_redaction_impl(&conn, id)).await,
+        Err(res) => *res,
+    }
+}
+
+/// Sets what is redacted from requests to the function app with the given ID, and their responses, before they are
+/// mirrored or recorded. Empty rules only redact the headers that are always redacted
+fn set_function_app_redaction_impl(conn: &Connection, id: Uuid, rules: &RedactionRules) -> HttpResponse {
+    if let Err(e) = redaction::validate_rules(rules) {
+        return errors::response(ApiError::InvalidRedaction, &e);
+    }
+
+    let rules = match rules.headers.is_empty() && rules.json_fields.is_empty() {
+        true => None,
+        false => Some(rules.clone()),
+    };
+
+    match storage::set_function_app_redaction(conn, &id, &rules) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Gets what is redacted from requests to the function app with the given ID before they are mirrored or recorded
+fn get_function_app_redaction_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    match storage::get_function_app_redaction(conn, &id) {
+        Ok(rules) => HttpResponse::Ok().json(rules.unwrap_or_default()),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{id}/recorded-requests")]
+async fn get_recorded_requests(pools: web::Data<DbPools>, info: web::Path<String>, options: web::Query<RecordedRequestsOptions>) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((_, id)) => HttpResponse::Ok().json(recorder::get_recent_requests(&id, options.last)),
+        Err(res) => *res,
+    }
+}
+
+#[get("/function-apps/by-name/{name}/recorded-requests")]
+async fn get_recorded_requests_by_name(pools: web::Data<DbPools>, name: web::Path<String>, options: web::Query<RecordedRequestsOptions>) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((_, id)) => HttpResponse::Ok().json(recorder::get_recent_requests(&id, options.last)),
+        Err(res) => *res,
+    }
+}
+
+/// Stops the function app with the given ID, giving it the grace period to finish the requests in flight
+///
+/// The app is marked as ready first so no new requests are routed to it while it shuts down. The stop is
+/// recorded in the status history as clean if the app exited by itself, or forced if it had to be killed. An app
+/// stopped for being idle is no longer started by the next request, so it stays stopped
+fn stop_function_app_impl(conn: &mut Connection, id: Uuid) -> HttpResponse {
+    let function_app_name = match storage::get_function_app_name(conn, &id) {
+        Ok(n) => n,
+        Err(e) => {
+            return errors::response(ApiError::BadRequest, &format!("Cannot get function app name from ID: {}", e));
+        }
+    };
+
+    if let Err(e) = storage::set_function_app_idle_stopped(conn, &id, false) {
+        return errors::response(ApiError::Internal, &e.to_string());
+    }
+
+    // Stop routing requests to the app before it starts shutting down
+    if let Err(e) = storage::set_function_app_status(conn, &id, &FunctionAppStatus::Ready) {
+        return errors::response(ApiError::Internal, &e.to_string());
+    }
+
+    let grace_period = docker::get_stop_grace_period();
+    let stop_start = SystemTime::now();
+
+    let exit_code = match docker::stop_function_app(&function_app_name, grace_period) {
+        Ok(Some(exit_code)) => exit_code,
+        Ok(None) => return errors::response(ApiError::NotRunning, "The function app is not running"),
+        Err(e) => {
+            println!("Error stopping function app {}: {}", function_app_name, e);
+            return errors::response(ApiError::Internal, &format!("Error stopping function app: {}", e));
+        }
+    };
+    container_states::set_running(&function_app_name, false);
+
+    // Every container for the app was stopped, including any extra replicas
+    if let Err(e) = replicas::clear(conn, &id) {
+        println!("Error clearing the replicas of function app {}: {}", function_app_name, e);
+    }
+
+    let stop = AppStop {
+        stopped_at: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+        clean: !docker::was_killed(exit_code),
+        exit_code,
+        duration_secs: stop_start.elapsed().unwrap_or_default().as_secs(),
+        grace_period_secs: grace_period,
+    };
+
+    if !stop.clean {
+        println!("Function app {} did not stop within {} seconds and was killed", function_app_name, grace_period);
+    }
+
+    match storage::complete_stop(conn, &id, &stop) {
+        Ok(_) => HttpResponse::Ok().json(stop),
+        Err(e) => errors::response(ApiError::Internal, &format!("Error recording stop: {}", e)),
+    }
+}
+
+/// Restarts the function app with the given ID on a new container without dropping requests
+///
+/// The new container is started on a fresh port and requests keep going to the old one until the new one passes
+/// its health check. The stored port is then switched over, and the old container is given the grace period to
+/// finish the requests in flight before it is stopped. If the new container never becomes healthy it is removed
+/// and the old one keeps running
+async fn restart_function_app_impl(pools: &web::Data<DbPools>, conn: DbConnection, id: Uuid) -> HttpResponse {
+    // The checks and starting the new container call the database and docker, so they run on the blocking thread
+    // pool. The connection goes back to the pool while the new container is health checked
+    let restarting = match web::block(move || start_restart(&conn, id)).await {
+        Ok(Ok(restarting)) => restarting,
+        Ok(Err((error, message))) => return errors::response(error, &message),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+    let RestartStarted { function_app_name, old_container_ids, started } = restarting;
+
+    let port = started.port;
+    let health_check = match gateway::wait_until_healthy(port).await {
+        Ok(duration) => duration,
+        Err(e) => {
+            println!("New container for function app {} failed its health check: {}", function_app_name, e);
+            let new_container_ids = vec![started.container_id];
+            let _ = web::block(move || docker::stop_containers(&new_container_ids, 0)).await;
+            return errors::response(
+                ApiError::HealthCheckFailed,
+                &format!("{}. The old container is still running", e),
+            );
+        }
+    };
+
+    // Send requests to the new container, with a new connection as the health check can take a while
+    let conn = match connect_function_app(pools, id).await {
+        Ok(conn) => conn,
+        Err(res) => return *res,
+    };
+    let name = function_app_name.clone();
+    let port_attempts = started.port_attempts;
+    let restarted_at = match web::block(move || finish_restart(&conn, id, &name, port, port_attempts)).await {
+        Ok(Ok(restarted_at)) => restarted_at,
+        Ok(Err((error, message))) => return errors::response(error, &message),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    // Stop the old containers off the worker thread, as they can take the whole grace period to finish
+    let grace_period = docker::get_stop_grace_period();
+    let stop_start = SystemTime::now();
+    let stopped = web::block(move || docker::stop_containers(&old_container_ids, grace_period)).await.map_err(|e| e.to_string());
+    let exit_code = match stopped {
+        Ok(Ok(exit_code)) => exit_code,
+        Ok(Err(e)) | Err(e) => {
+            println!("Error stopping the old container for function app {}: {}", function_app_name, e);
+            return errors::response(ApiError::Internal, &format!("Error stopping the old container: {}", e));
+        }
+    };
+
+    let restart = AppRestart {
+        restarted_at,
+        port,
+        health_check_ms: health_check.as_millis() as u64,
+        port_attempts: started.port_attempts,
+        old_container: AppStop {
+            stopped_at: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+            clean: !docker::was_killed(exit_code),
+            exit_code,
+            duration_secs: stop_start.elapsed().unwrap_or_default().as_secs(),
+            grace_period_secs: grace_period,
+        },
+    };
+
+    println!("Restarted function app {} on port {}", function_app_name, port);
+    HttpResponse::Ok().json(restart)
+}
+
+/// A restart that has started the new container, which is health checked before requests are sent to it
+struct RestartStarted {
+    // The name of the app
+    function_app_name: String,
+
+    // The containers to stop once the new container is serving requests
+    old_container_ids: Vec<String>,
+
+    // The new container
+    started: docker::StartedContainer,
+}
+
+/// Checks the function app with the given ID can be restarted, then starts its new container alongside the old ones
+fn start_restart(conn: &Connection, id: Uuid) -> Result<RestartStarted, (ApiError, String)> {
+    let function_app_name = match storage::get_function_app_name(conn, &id) {
+        Ok(n) => n,
+        Err(e) => return Err((ApiError::BadRequest, format!("Cannot get function app name from ID: {}", e))),
+    };
+
+    // Code waiting for approval can't be started on the new container
+    match storage::get_pending_deployment(conn, &id) {
+        Ok(Some(number)) => return Err((
+            ApiError::ApprovalRequired,
+            format!("Cannot restart function app, deployment {} is waiting for approval", number),
+        )),
+        Ok(None) => {},
+        Err(e) => return Err((ApiError::Internal, e.to_string())),
+    };
+
+    match function_app_builder::get_function_app_status(conn, &id) {
+        Ok(FunctionAppStatus::Running) => {},
+        Ok(_) => return Err((ApiError::NotRunning, "The function app is not running".to_string())),
+        Err(e) => {
+            println!("Error getting function app status: {}", e);
+            return Err((ApiError::Internal, e.to_string()))
+        }
+    };
+
+    // Both containers run for a while, so check there is memory for another running app
+    match quotas::check_quota(quotas::Quota::Memory) {
+        Ok(Some(warning)) => events::publish_quota_warning(conn, &id, &warning),
+        Ok(None) => {},
+        Err(e) => {
+            println!("{}", e);
+            return Err((ApiError::QuotaExceeded, e));
+        }
+    }
+
+    // Check the image was signed by this host, if signatures are checked
+    if let Err(e) = signing::check_before_start(conn, &id, &function_app_name) {
+        return Err((
+            ApiError::SignatureInvalid,
+            format!("Cannot restart function app, its image signature is not valid: {}", e),
+        ));
+    }
+
+    // Get the old containers before the new one is started, as they are found by the app label they share
+    let old_container_ids = match docker::get_container_ids(&function_app_name) {
+        Ok(ids) => ids,
+        Err(e) => return Err((ApiError::Internal, format!("Error getting the running containers: {}", e))),
+    };
+
+    let labels = match docker::get_app_labels(conn, &id) {
+        Ok(labels) => labels,
+        Err(e) => return Err((ApiError::Internal, format!("Error getting function app details: {}", e))),
+    };
+
+    let proxy_url = match egress::get_container_proxy_url(conn, &id) {
+        Ok(proxy_url) => proxy_url,
+        Err(e) => return Err((ApiError::Internal, e)),
+    };
+
+    match docker::start_function_app(&labels, &proxy_url) {
+        Ok(started) => Ok(RestartStarted { function_app_name, old_container_ids, started }),
+        Err(e) => Err((ApiError::Internal, format!("Error starting function app: {}", e))),
+    }
+}
+
+/// Sends requests to the new container of a restarted app once it has passed its health check, starting new
+/// replicas alongside it, and returns when it was restarted
+fn finish_restart(conn: &Connection, id: Uuid, function_app_name: &String, port: u16, port_attempts: u32) -> Result<u64, (ApiError, String)> {
+    // The first request to the new container is a cold start
+    if let Err(e) = storage::set_function_app_running(conn, &id, port) {
+        return Err((ApiError::Internal, format!("Error updating function app status: {}", e)));
+    }
+    gateway::reset_cold_start(&id);
+
+    // The old replicas are stopped with the old container, so start new ones alongside the new container
+    let new_replicas = storage::get_function_app_replicas(conn, &id).unwrap_or(1).saturating_sub(1);
+    let started_replicas = replicas::clear(conn, &id).and_then(|_| replicas::start_replicas(conn, &id, function_app_name, new_replicas));
+    if let Err(e) = started_replicas {
+        println!("Error starting the replicas of function app {}: {}", function_app_name, e);
+    }
+
+    let restarted_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    let _ = storage::add_start_history(conn, &id, restarted_at, storage::RESTARTED_EVENT, port_attempts);
+    Ok(restarted_at)
+}
+
+/// Deletes the function app with the given ID, stopping it if it is running
+///
+/// The container is given the grace period to finish the requests in flight, then the image, the saved error
+/// pages, and everything stored for the app are removed. Apps that are building or waiting to build can't be
+/// deleted, as the build would recreate the image once it finishes
+fn delete_function_app_impl(conn: &mut Connection, id: Uuid) -> HttpResponse {
+    let function_app_name = match storage::get_function_app_name(conn, &id) {
+        Ok(n) => n,
+        Err(e) => {
+            return errors::response(ApiError::BadRequest, &format!("Cannot get function app name from ID: {}", e));
+        }
+    };
+
+    if build_queue::is_queued(&id) {
+        return errors::response(
+            ApiError::BuildInProgress,
+            "The function app is building. Cancel the build before deleting the app"
+        );
+    }
+
+    if let Err(e) = docker::stop_function_app(&function_app_name, docker::get_stop_grace_period()) {
+        println!("Error stopping function app {}: {}", function_app_name, e);
+        return errors::response(ApiError::Internal, &format!("Error stopping function app: {}", e));
+    }
+    container_states::set_running(&function_app_name, false);
+
+    if let Err(e) = docker::remove_function_app_image(&function_app_name) {
+        println!("Error removing the image for function app {}: {}", function_app_name, e);
+        return errors::response(ApiError::Internal, &e);
+    }
+
+    if let Err(e) = pages::remove_app_error_pages(&id) {
+        return errors::response(ApiError::Internal, &e);
+    }
+
+    // Send the event while the app is still stored, as the event is made from the stored app
+    events::publish_status_changed(conn, &id, &FunctionAppStatus::NotRegistered);
+
+    if let Err(e) = storage::delete_function_app(conn, &id) {
+        return errors::response(ApiError::Internal, &format!("Error deleting function app: {}", e));
+    }
+
+    recorder::clear(&id);
+    grpc::clear(&id);
+    gateway::reset_cold_start(&id);
+
+    // Requests to the root shouldn't be routed to an app that no longer exists
+    if get_default_app_name().as_ref() == Some(&function_app_name) {
+        let cleared = storage::create_connection().and_then(|conn| storage::set_default_app(&conn, None).map_err(|e| e.to_string()));
+        if let Err(e) = cleared {
+            println!("Error clearing the default app: {}", e);
+        }
+    }
+
+    println!("Deleted function app {}", function_app_name);
+    HttpResponse::Ok().body("")
+}
+
+/// Gets the routes handled by the function app with the given ID, asking the running app for its route manifest
+async fn get_function_app_routes_impl(conn: DbConnection, id: Uuid) -> HttpResponse {
+    // Only running apps can be asked for their routes. The connection goes back to the pool before the app is called
+    let port = web::block(move || storage::get_function_app_port(&conn, &id).map_err(|e| e.to_string())).await.map_err(|e| e.to_string());
+    let port = match port {
+        Ok(Ok(Some(port))) => port,
+        Ok(Ok(None)) => return errors::response(ApiError::NotRunning, "The function app is not running"),
+        Ok(Err(e)) | Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    match gateway::get_app_routes(port).await {
+        Ok(Some(manifest)) => HttpResponse::Ok().json(manifest),
+        Ok(None) => errors::response(
+            ApiError::NoRouteManifest,
+            "The function app does not serve a route manifest. Build it with rustless_app to list its routes"
+        ),
+        Err(e) => errors::response(ApiError::BadGateway, &e),
+    }
+}
+
+/// Turns maintenance mode on or off for the function app with the given ID
+fn set_function_app_maintenance_impl(conn: &Connection, id: Uuid, request: &MaintenanceRequest) -> HttpResponse {
+    match storage::set_function_app_maintenance(conn, &id, request.enabled, &request.message) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Starts the function app with the given ID, starting the apps it depends on first if asked to
+async fn start_function_app_with_options_impl(pools: &web::Data<DbPools>, conn: DbConnection, id: Uuid, options: &StartOptions) -> HttpResponse {
+    // The dependencies can take a while to start, so the connection goes back to the pool while they do
+    let conn = match options.with_deps {
+        true => {
+            let function_app_name = web::block(move || storage::get_function_app_name(&conn, &id)).await.map_err(|e| e.to_string());
+            let function_app_name = match function_app_name {
+                Ok(Ok(n)) => n,
+                Ok(Err(e)) => return errors::response(ApiError::Internal, &e.to_string()),
+                Err(e) => return errors::response(ApiError::Internal, &e),
+            };
+
+            if let Err(e) = dependencies::start_dependencies(&function_app_name).await {
+                return errors::response(ApiError::DependencyNotStarted, &e);
+            }
+
+            match connect_function_app(pools, id).await {
+                Ok(conn) => conn,
+                Err(res) => return *res,
+            }
+        },
+        false => conn,
+    };
+
+    run_blocking(move || start_function_app_impl(&conn, id)).await
+}
+
+/// Sets the apps the function app with the given ID depends on
+fn set_function_app_dependencies_impl(conn: &Connection, id: Uuid, request: &DependenciesRequest) -> HttpResponse {
+    let function_app_name = match storage::get_function_app_name(conn, &id) {
+        Ok(n) => n,
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let depends_on = match dependencies::validate_dependencies(&function_app_name, &request.depends_on) {
+        Ok(depends_on) => depends_on,
+        Err(e) => return errors::response(ApiError::InvalidDependencies, &e),
+    };
+
+    match storage::set_function_app_dependencies(conn, &id, &depends_on) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Gets the apps the function app with the given ID depends on, and the apps that depend on it
+fn get_function_app_dependencies_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+    let function_app_name = match storage::get_function_app_name(conn, &id) {
+        Ok(n) => n,
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let depends_on = match storage::get_function_app_dependencies(conn, &id) {
+        Ok(depends_on) => depends_on,
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    match dependencies::get_dependents(&function_app_name) {
+        Ok(required_by) => HttpResponse::Ok().json(DependenciesReport { depends_on, required_by }),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Starts the function app with the given ID
+fn start_function_app_impl(conn: &Connection, id: Uuid) -> HttpResponse {
+
+    // Code waiting for approval can't be started
+    match storage::get_pending_deployment(conn, &id) {
+        Ok(Some(number)) => return errors::response(
+            ApiError::ApprovalRequired,
+            &format!("Cannot start function app, deployment {} is waiting for approval", number),
+        ),
+        Ok(None) => {},
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let status = function_app_builder::get_function_app_status(conn, &id);
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            println!("Error getting function app status: {}", e);
+            return errors::response(ApiError::Internal, &e.to_string())
+        }
+    };
+
+    let _ = storage::set_function_app_status(conn, &id, &status);
+
+    match status {
+        FunctionAppStatus::Ready => {
+            // Get the function app name to prove we have an app registered with this ID
+            let function_app_name = storage::get_function_app_name(conn, &id);
+            let function_app_name = match function_app_name {
+                Ok(n) => n,
+                Err(e) => {
+                    return errors::response(ApiError::BadRequest, &format!("Cannot get function app name from ID: {}", e));
+                }
+            };
+
+            // Check there is memory for another running app
+            match quotas::check_quota(quotas::Quota::Memory) {
+                Ok(Some(warning)) => events::publish_quota_warning(conn, &id, &warning),
+                Ok(None) => {},
+                Err(e) => return quota_exceeded_response(&e),
+            }
+
+            // Check the image was signed by this host, if signatures are checked
+            if let Err(e) = signing::check_before_start(conn, &id, &function_app_name) {
+                return errors::response(
+                    ApiError::SignatureInvalid,
+                    &format!("Cannot start function app, its image signature is not valid: {}", e),
+                );
+            }
+
+            let labels = match docker::get_app_labels(conn, &id) {
+                Ok(labels) => labels,
+                Err(e) => return errors::response(ApiError::Internal, &format!("Error getting function app details: {}", e)),
+            };
+
+            let proxy_url = match egress::get_container_proxy_url(conn, &id) {
+                Ok(proxy_url) => proxy_url,
+                Err(e) => return errors::response(ApiError::Internal, &e),
+            };
+
+            // Start the function app
+            let start_result = docker::start_function_app(&labels, &proxy_url);
+            let started = match start_result {
+                Ok(started) => started,
+                Err(e) => {
+                    return errors::response(ApiError::Internal, &format!("Error starting function app: {}", e));
+                }
+            };
+            container_states::set_running(&function_app_name, true);
+
+            // The first request to the new container is a cold start
+            gateway::reset_cold_start(&id);
+
+            // Update the status and port in the database
+            match storage::set_function_app_running(conn, &id, started.port){
+                Ok(_) => {
+                    let started_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+                    let _ = storage::add_start_history(conn, &id, started_at, storage::STARTED_EVENT, started.port_attempts);
+
+                    // Start the extra replicas the app is scaled to. The app is running if any of them fail
+                    let extra_replicas = storage::get_function_app_replicas(conn, &id).unwrap_or(1).saturating_sub(1);
+                    if let Err(e) = replicas::start_replicas(conn, &id, &function_app_name, extra_replicas) {
+                        println!("Error starting the replicas of function app {}: {}", function_app_name, e);
+                    }
+
+                    HttpResponse::Ok().body("Function app is already running")
+                },
+                Err(e) => errors::response(ApiError::Internal, &format!("Error updating function app status: {}", e))
+            }            
+        },
+        FunctionAppStatus::Running => HttpResponse::Ok().body("Function app is already running"),
+        FunctionAppStatus::Building => errors::response(ApiError::CannotStart, "Cannot start function app, it is currently building"),
+        FunctionAppStatus::Error => errors::response(ApiError::CannotStart, "Cannot start function app, it is in an error state"),
+        FunctionAppStatus::Cancelled => errors::response(ApiError::CannotStart, "Cannot start function app, its last build was cancelled"),
+        FunctionAppStatus::Registered => errors::response(ApiError::CannotStart, "Cannot start function app, it doesn't have any code yet"),
+        FunctionAppStatus::NotRegistered => errors::response(ApiError::AppNotFound, "Cannot start function app, it doesn't exist"),
+    }
+}
+
+#[get("/function-apps")]
+async fn list_function_apps() -> HttpResponse {
+    run_blocking(list_function_apps_impl).await
+}
+
+/// Gets all the registered function apps
+fn list_function_apps_impl() -> HttpResponse {
+    let result = storage::get_all_apps();
+
+    match result {
+        Ok(apps) => {
+            HttpResponse::Ok().json(apps)
+        },
+        Err(e) => errors::response(ApiError::Internal, &e.to_string())
+    }
+}
+
+/// Gets the overview of a function app shown when every app is listed with its status
+fn get_function_app_overview(app: FunctionApp) -> Result<FunctionAppOverview, String> {
+    let conn = storage::create_connection_for_app(&app.id)?;
+
+    let deployments = storage::get_deployments(&conn, &app.id).map_err(|e| e.to_string())?;
+    let last_event = storage::get_last_status_event(&conn, &app.id).map_err(|e| e.to_string())?;
+    let pending_deployment = storage::get_pending_deployment(&conn, &app.id).map_err(|e| e.to_string())?;
+
+    // The status history only says when the app was started if that was the last thing that happened to it
+    let running_since = match (&app.status, &last_event) {
+        (FunctionAppStatus::Running, Some((changed_at, event, _))) if event == storage::STARTED_EVENT || event == storage::RESTARTED_EVENT => Some(*changed_at),
+        _ => None,
+    };
+
+    // Apps are in error because their container crashed or their code didn't build
+    let error = match (&app.status, &last_event) {
+        (FunctionAppStatus::Error, Some((_, event, _))) if event == storage::CRASHED_EVENT => match storage::get_last_exit(&conn, &app.id) {
+            Ok(Some(exit)) => Some(format!("Crashed: {}", exit.reason)),
+            _ => Some("Crashed".to_string()),
+        },
+        (FunctionAppStatus::Error, _) => match storage::get_last_build_succeeded(&conn, &app.id) {
+            Ok(Some(false)) => Some("The last build failed".to_string()),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    Ok(FunctionAppOverview {
+        name: app.name,
+        id: app.id,
+        namespace: app.namespace,
+        status: app.status,
+        deployment: deployments.first().map(|deployment| deployment.number),
+        deployed_at: deployments.first().map(|deployment| deployment.created_at),
+        running_since,
+        error,
+        pending_deployment,
+    })
+}
+
+/// Gets every function app with its status, version, uptime, last deployment, and any error, in one call
+///
+/// This uses the stored status rather than checking docker for each app, so it stays fast with many apps
+#[get("/function-apps/status")]
+async fn get_function_apps_status() -> HttpResponse {
+    run_blocking(get_function_apps_status_impl).await
+}
+
+/// Gets the status of all the registered function apps
+fn get_function_apps_status_impl() -> HttpResponse {
+    let apps = match storage::get_all_apps() {
+        Ok(apps) => apps,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    let mut overviews = Vec::new();
+    for app in apps {
+        match get_function_app_overview(app) {
+            Ok(overview) => overviews.push(overview),
+            Err(e) => return errors::response(ApiError::Internal, &e),
+        }
+    }
+
+    HttpResponse::Ok().json(overviews)
+}
+
+/// Gets the resources every running function app is using, or only the app with the given name
+///
+/// Apps whose containers were started before they were labelled with the app name aren't included until they are
+/// restarted
+#[get("/function-apps/resource-usage")]
+async fn get_function_apps_resource_usage(options: web::Query<ResourceUsageOptions>) -> HttpResponse {
+    let apps = match storage::get_all_apps() {
+        Ok(apps) => apps,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    let apps: Vec<FunctionApp> = match &options.app {
+        Some(name) => apps.into_iter().filter(|app| &app.name == name).collect(),
+        None => apps,
+    };
+
+    if let (Some(name), true) = (&options.app, apps.is_empty()) {
+        return errors::response(ApiError::AppNotFound, &format!("No function app with name {} found", name));
+    }
+
+    // Docker takes a second to sample the containers, so wait for it off the server worker
+    let app_name = options.app.clone();
+    let usage = match web::block(move || docker::get_resource_usage(app_name.as_ref())).await {
+        Ok(Ok(usage)) => usage,
+        Ok(Err(e)) => return errors::response(ApiError::Internal, &e),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let apps: Vec<AppResourceUsage> = apps.into_iter().filter_map(|app| {
+        let usage = usage.get(&app.name)?;
+        Some(AppResourceUsage {
+            name: app.name,
+            id: app.id,
+            namespace: app.namespace,
+            containers: usage.containers,
+            cpu_percent: usage.cpu_percent,
+            memory_bytes: usage.memory_bytes,
+            memory_limit_bytes: usage.memory_limit_bytes,
+            network_rx_bytes: usage.network_rx_bytes,
+            network_tx_bytes: usage.network_tx_bytes,
+        })
+    }).collect();
+
+    HttpResponse::Ok().json(apps)
+}
+
+/// Gets the deployment analytics for every function app, or the apps picked by the options, over the last few weeks
+///
+/// These are worked out from the deployments, builds, and status history the host already records
+#[get("/analytics")]
+async fn get_analytics(options: web::Query<AnalyticsOptions>) -> HttpResponse {
+    if let Err(e) = analytics::validate_weeks(options.weeks) {
+        return errors::response(ApiError::BadRequest, &e);
+    }
+
+    let options = options.into_inner();
+    let app = options.app.clone();
+    match web::block(move || analytics::get_analytics(&options)).await {
+        Ok(Ok(Some(report))) => HttpResponse::Ok().json(report),
+        Ok(Ok(None)) => errors::response(ApiError::AppNotFound, &format!("No function app with name {} found", app.unwrap_or_default())),
+        Ok(Err(e)) => errors::response(ApiError::Internal, &e),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// The landing page for the server, listing the running function apps
+///
+/// If a default app is set, the request is sent to that app instead
+#[get("/")]
+async fn landing_page(pools: web::Data<DbPools>, req: HttpRequest, body: web::Payload) -> HttpResponse {
+    if let Some(name) = find_default_app_name(&pools).await {
+        return gateway::route_to_app(&pools, &req, &name, "", body).await;
+    }
+
+    let apps = match web::block(storage::get_all_apps).await {
+        Ok(Ok(apps)) => apps,
+        Ok(Err(e)) => return errors::response(ApiError::Internal, &e),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    match pages::render_landing_page(&apps) {
+        Ok(page) => HttpResponse::Ok().content_type("text/html; charset=utf-8").body(page),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Handles any route that isn't handled by another service
+///
+/// If a default app is set, the request is sent to that app with the full path, otherwise a 404 page is shown
+async fn fallback(pools: web::Data<DbPools>, req: HttpRequest, body: web::Payload) -> HttpResponse {
+    match find_default_app_name(&pools).await {
+        Some(name) => {
+            let route = req.path().trim_start_matches('/').to_string();
+            gateway::route_to_app(&pools, &req, &name, &route, body).await
+        },
+        None => not_found(&req),
+    }
+}
+
+/// Gets the name of the default app, if one is set, on the blocking thread pool
+async fn find_default_app_name(pools: &web::Data<DbPools>) -> Option<String> {
+    let pools = pools.clone();
+    web::block(move || storage::connect(&pools).ok().and_then(|conn| get_default_app_from(&conn))).await.ok().flatten()
+}
+
+/// Gets the name of the default app, if one is set
+fn get_default_app_name() -> Option<String> {
+    let conn = storage::create_connection().ok()?;
+    get_default_app_from(&conn)
+}
+
+/// Gets the name of the default app from the main database, if one is set
+fn get_default_app_from(conn: &Connection) -> Option<String> {
+    match storage::get_default_app(conn) {
+        Ok(name) => name,
+        Err(e) => {
+            println!("Error getting default app: {}", e);
+            None
+        }
+    }
+}
+
+/// Returns a friendly 404 page
+fn not_found(req: &HttpRequest) -> HttpResponse {
+    match pages::render_not_found_page(req.path()) {
+        Ok(page) => HttpResponse::NotFound().content_type("text/html; charset=utf-8").body(page),
+        Err(e) => errors::response(ApiError::NotFound, &e),
+    }
+}
+
+/// Routes a request to the function app with the given name
+///
+/// A GET to the root of the app lists the routes it handles, unless the app handles the root itself
+#[route("/api/{name}/{route:.*}", method = "GET", method = "POST")]
+async fn route_to_function_app(pools: web::Data<DbPools>, req: HttpRequest, path: web::Path<(String, String)>, body: web::Payload) -> HttpResponse {
+    let (name, route) = path.into_inner();
+
+    if route.is_empty() && req.method() == Method::GET {
+        if let Some(manifest) = get_listed_app_routes(&pools, &name).await {
+            return HttpResponse::Ok().json(manifest);
+        }
+    }
+
+    gateway::route_to_app(&pools, &req, &name, &route, body).await
+}
+
+/// Gets the routes to list for a GET to the root of a function app, or None if the request should go to the app
+///
+/// Apps that handle the root themselves or don't serve a route manifest get the request as normal, as do apps
+/// that aren't running or are in maintenance mode, so they show the usual error pages
+async fn get_listed_app_routes(pools: &web::Data<DbPools>, name: &String) -> Option<AppRouteManifest> {
+    let (conn, id) = resolve_function_app_name(pools, name).await.ok()?;
+
+    // The connection goes back to the pool before the app is called
+    let port = web::block(move || {
+        match storage::get_function_app_maintenance(&conn, &id) {
+            Ok(None) => storage::get_function_app_port(&conn, &id).ok().flatten(),
+            _ => None,
+        }
+    }).await.ok()??;
+
+    let manifest = match gateway::get_app_routes(port).await {
+        Ok(manifest) => manifest?,
+        Err(e) => {
+            println!("Error getting routes for {}: {}", name, e);
+            return None;
+        }
+    };
+
+    let handles_root = manifest.routes.iter().any(|route| route.path == "/" && route.method.eq_ignore_ascii_case("GET"));
+    if handles_root {
+        return None;
+    }
+
+    Some(manifest)
+}
+
+/// Gets the default app that receives requests that don't match any other route
+#[get("/default-app")]
+async fn get_default_app() -> HttpResponse {
+    run_blocking(get_default_app_impl).await
+}
+
+/// Gets the default app
+fn get_default_app_impl() -> HttpResponse {
+    let conn = match storage::create_connection() {
+        Ok(conn) => conn,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    match storage::get_default_app(&conn) {
+        Ok(name) => HttpResponse::Ok().json(DefaultApp { name }),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Sets or clears the default app that receives requests that don't match any other route
+#[post("/default-app")]
+async fn set_default_app(pools: web::Data<DbPools>, body: Json<DefaultApp>) -> HttpResponse {
+    // Make sure the app exists before making it the default
+    if let Some(name) = &body.name {
+        if let Err(res) = resolve_function_app_name(&pools, name).await {
+            return *res;
+        }
+    }
+
+    let conn = match storage::create_connection() {
+        Ok(conn) => conn,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    match storage::set_default_app(&conn, body.name.as_deref()) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+#[get("/function-apps/{name}/id")]
+async fn get_function_app_id(name: web::Path<String>) -> HttpResponse {
+    run_blocking(move || get_function_app_id_impl(name)).await
+}
+
+/// Gets the ID of the function app with the given name
+fn get_function_app_id_impl(name: web::Path<String>) -> HttpResponse {
+    let name = name.to_string();
+
+    // Connect to the database that holds this app
+    let conn = match storage::create_connection_for_app_name(&name) {
+        Ok(conn) => conn,
+        Err(_) => return errors::response(ApiError::AppNotFound, &format!("No function app with name {} found", name)),
+    };
+
+    let result = storage::get_function_id_from_name(&conn, &name);
+
+    match result {
+        Ok(id) => HttpResponse::Ok().body(id.to_string()),
+        Err(Error::QueryReturnedNoRows) => errors::response(ApiError::AppNotFound, &format!("No function app with name {} found", name)),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string())
+    }
+}
+
+/// Rejects an operation because a quota has been reached. 507 is used as the host doesn't have the room for it
+fn quota_exceeded_response(e: &str) -> HttpResponse {
+    println!("{}", e);
+    errors::response(ApiError::QuotaExceeded, e)
+}
+
+/// Create a new function app in the server
+/// 
+/// This registers a new function app by name in the database and returns the new ID
+/// The name MUST be unique
+#[post("/function-apps")]
+async fn create_function_app(body: Json<FunctionAppNameRequest>) -> HttpResponse {
+    run_blocking(move || create_function_app_impl(body)).await
+}
+
+/// Registers a new function app
+fn create_function_app_impl(body: Json<FunctionAppNameRequest>) -> HttpResponse {
+    // Check the namespace is valid
+    if let Err(e) = storage::validate_namespace(&body.namespace) {
+        return errors::response(ApiError::BadRequest, &e);
+    }
+
+    // Connect to the database for the namespace
+    let mut conn = match storage::create_namespace_connection(&body.namespace) {
+        Ok(conn) => conn,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    // Check there is room for another app
+    let quota_warning = match quotas::check_quota(quotas::Quota::Apps) {
+        Ok(warning) => warning,
+        Err(e) => return quota_exceeded_response(&e),
+    };
+
+    // Register the function app in the database, as long as the name is not in use in any namespace
+    let res = storage::register_function_app(&mut conn, &body.name, &body.namespace);
+    match res {
+        Ok(Some(id)) => {
+            if let Some(warning) = quota_warning {
+                events::publish_quota_warning(&conn, &id, &warning);
+            }
+            HttpResponse::Ok().body(id.to_string())
+        },
+        Ok(None) => errors::response(
+            ApiError::NameInUse,
+            &format!("A function app already exists that is named '{}'", body.name),
+        ),
+        Err(e) => errors::response(ApiError::Internal, &e),
+    }
+}
+
+/// Plans deploying code for a function app without making any changes
+///
+/// This reports if the app would be created or updated, along with anything that would cause the deployment to fail
+#[post("/function-apps/plan")]
+async fn plan_function_app(pools: web::Data<DbPools>, body: Json<FunctionAppNameRequest>) -> HttpResponse {
+    let (conn, id) = match resolve_function_app_name(&pools, &body.name).await {
+        Ok(resolved) => resolved,
+        Err(res) if res.status() == 404 => {
+            // The app doesn't exist, so it would be created in the given namespace
+            let mut errors = Vec::new();
+            if let Err(e) = storage::validate_namespace(&body.namespace) {
+                errors.push(e);
+            }
+
+            return HttpResponse::Ok().json(DeployPlan {
+                action: DeployAction::Create,
+                id: None,
+                status: None,
+                errors,
+                warnings: Vec::new(),
+            });
+        },
+        Err(res) => return *res,
+    };
+
+    let status = match storage::get_function_app_stored_status(&conn, &id) {
+        Ok(status) => status,
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    let mut warnings = Vec::new();
+    if build_queue::is_queued(&id) {
+        warnings.push("A build is already in progress for this app, so the new build would wait for it to finish".to_string());
+    }
+    if let Ok(Some(_)) = storage::get_function_app_maintenance(&conn, &id) {
+        warnings.push("The app is in maintenance mode, so requests would get the maintenance page after the deployment".to_string());
+    }
+
+    HttpResponse::Ok().json(DeployPlan {
+        action: DeployAction::Update,
+        id: Some(id),
+        status: Some(status),
+        errors: Vec::new(),
+        warnings,
+    })
+}
+
+/// Registers the function apps that have a built image with rustless labels but aren't in the database, such as
+/// after the database was restored, so they can be started without being deployed again
+#[post("/function-apps/adopt")]
+async fn adopt_function_apps() -> HttpResponse {
+    match web::block(adopt::adopt).await {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        Ok(Err(e)) => errors::response(ApiError::Internal, &e),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Handles code upload for the function app
+/// 
+/// The body is the zip file with all the code for the function app, sent as application/zip or
+/// application/octet-stream, or the zip file encoded as base64 by older CLIs
+#[post("/function-apps/{id}/code")]
+async fn post_function_app_code(pools: web::Data<DbPools>, req: HttpRequest, info: web::Path<String>, options: web::Query<BuildOptions>, payload: web::Payload) -> HttpResponse {
+    match resolve_function_app_id(&pools, &info).await {
+        Ok((conn, id)) => post_function_app_code_impl(&pools, conn, id, &options, &req, payload).await,
+        Err(res) => *res,
+    }
+}
+
+/// Handles code upload for the function app with the given name
+#[post("/function-apps/by-name/{name}/code")]
+async fn post_function_app_code_by_name(pools: web::Data<DbPools>, req: HttpRequest, name: web::Path<String>, options: web::Query<BuildOptions>, payload: web::Payload) -> HttpResponse {
+    match resolve_function_app_name(&pools, &name).await {
+        Ok((conn, id)) => post_function_app_code_impl(&pools, conn, id, &options, &req, payload).await,
+        Err(res) => *res,
+    }
+}
+
+/// Fails a build, recording why so callers polling the status of the app can show it
+fn fail_build(conn: &Connection, id: &Uuid, e: &str) {
+    let _ = storage::set_function_app_build_error(conn, id, e);
+    let _ = storage::set_function_app_status(conn, id, &FunctionAppStatus::Error);
+    build_logs::finish(conn, id, BUILD_FAILED, Some(e));
+    events::publish_deploy_progress(conn, id, "failed", None, Some(e.to_string()));
+    println!("{}", e);
+}
+
+/// Queues a build of the uploaded code for the function app with the given ID
+///
+/// The upload is checked and the build queued before this returns 202 with the build ID. The build runs in the
+/// background, so large apps don't time out the upload, and callers poll the status of the app for the result. The
+/// code is saved to a build directory as it arrives, and the build runs in that directory
+async fn post_function_app_code_impl(pools: &web::Data<DbPools>, conn: DbConnection, id: Uuid, options: &BuildOptions, req: &HttpRequest, payload: web::Payload) -> HttpResponse {
+    let received_at = SystemTime::now();
+
+    // Get the function app name to prove we have an app registered with this ID
+    let function_app_name = storage::get_function_app_name(&conn, &id);
+    let function_app_name = match function_app_name {
+        Ok(n) => n,
+        Err(e) => {
+            return errors::response(ApiError::BadRequest, &format!("Cannot get function app name from ID: {}", e));
+        }
+    };
+
+    // Check there is disk for another image before anything changes
+    match quotas::check_quota(quotas::Quota::Disk) {
+        Ok(Some(warning)) => events::publish_quota_warning(&conn, &id, &warning),
+        Ok(None) => {},
+        Err(e) => return quota_exceeded_response(&e),
+    }
+
+    // Render the Dockerfile from the template before anything changes, so a bad template or variable
+    // is reported straight away instead of failing part way through the build
+    let dockerfile = match templates::render_dockerfile(options) {
+        Ok(dockerfile) => dockerfile,
+        Err(e) => {
+            return errors::response(ApiError::InvalidTemplate, &e);
+        }
+    };
+
+    let temp_dir = match build_dirs::create() {
+        Ok(dir) => dir,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+
+    // Save the uploaded zip file to the build directory. This can take minutes on a slow network, so the connection
+    // goes back to the pool until it is saved
+    drop(conn);
+    let saved = uploads::save_code(&temp_dir, req, payload).await;
+    let conn = match connect_function_app(pools, id).await {
+        Ok(conn) => conn,
+        Err(res) => return *res,
+    };
+
+    if let Err(res) = saved {
+        let _ = storage::set_function_app_status(&conn, &id, &FunctionAppStatus::Error);
+        return *res;
+    }
+
+    // Check the zip file is one crate that can be unzipped safely before anything changes, so a bad upload is
+    // rejected straight away rather than failing the build
+    let temp_dir = match web::block(move || (archive::validate_archive(&temp_dir), temp_dir)).await {
+        Ok((Ok(None), temp_dir)) => temp_dir,
+        Ok((Ok(Some(invalid)), _)) => return match invalid.file {
+            Some(file) => errors::file_response(ApiError::InvalidArchive, &invalid.message, &file),
+            None => errors::response(ApiError::InvalidArchive, &invalid.message),
+        },
+        Ok((Err(e), _)) => return errors::response(ApiError::Internal, &e),
+        Err(e) => return errors::response(ApiError::Internal, &e.to_string()),
+    };
+
+    // If the same code was deployed last with the same options, there is nothing to build
+    let content_hash = match function_app_builder::get_content_hash(&temp_dir, &dockerfile, options.strict) {
+        Ok(content_hash) => content_hash,
+        Err(e) => return errors::response(ApiError::Internal, &e),
+    };
+    if !options.force {
+        match get_unchanged_deployment(&conn, &id, &function_app_name, &content_hash) {
+            Ok(Some(unchanged)) => {
+                println!("Code for {} matches deployment {}, skipping the build", function_app_name, unchanged.number);
+                events::publish_deploy_progress(&conn, &id, "unchanged", Some(unchanged.number), None);
+                return HttpResponse::Ok().json(unchanged);
+            },
+            Ok(None) => {},
+            Err(e) => return errors::response(ApiError::Internal, &e),
+        }
+    }
+
+    // Record the new build, clearing the error from the last one
+    let build_id = Uuid::new_v4();
+    if let Err(e) = storage::set_function_app_build(&conn, &id, &build_id) {
+        println!("Error recording build: {}", e);
+        return errors::response(ApiError::Internal, &e.to_string());
+    }
+
+    let status_update = storage::set_function_app_status(&conn, &id, &FunctionAppStatus::Building);
+    match status_update {
+        Ok(_) => (),
+        Err(e) => {
+            let _ = storage::set_function_app_status(&conn, &id, &FunctionAppStatus::Error);
+            println!("Error updating status: {}", e);
+            return errors::response(ApiError::Internal, &e.to_string())
+        }
+    }
+
+    // Add the build to the queue now, so the status shows it as building straight away and it can be cancelled
+    let slot = match build_queue::enqueue(&id) {
+        Ok(slot) => slot,
+        Err(e) => {
+            fail_build(&conn, &id, &e);
+            return errors::response(ApiError::Internal, &e);
+        }
+    };
+    build_logs::start(&id, &build_id);
+    events::publish_deploy_progress(&conn, &id, "queued", None, None);
+    println!("Queued build {} for {}", build_id, function_app_name);
+
+    let build = QueuedBuild {
+        id,
+        function_app_name,
+        dockerfile,
+        strict: options.strict,
+        temp_dir,
+        content_hash,
+        received_at,
+        slot,
+    };
+    actix_web::rt::spawn(run_build(conn, build));
+
+    HttpResponse::Accepted().json(BuildAccepted { build_id })
+}
+
+/// A build of uploaded code, queued to run in the background
+struct QueuedBuild {
+    // The ID of the function app being built
+    id: Uuid,
+
+    // The name of the function app, used to name the image
+    function_app_name: String,
+
+    // The Dockerfile rendered from the template
+    dockerfile: String,
+
+    // Whether to treat compiler warnings as errors
+    strict: bool,
+
+    // The build directory the uploaded zip file with the code was saved to, and the build runs in
+    temp_dir: TempDir,
+
+    // The SHA-256 hash of the code and build options, stored with the deployment
+    content_hash: String,
+
+    // When the code was uploaded
+    received_at: SystemTime,
+
+    // The place in the build queue, which is freed when the build finishes
+    slot: build_queue::BuildQueueSlot,
+}
+
+/// Runs a queued build, recording the result in the status of the function app
+///
+/// Extracting the code, waiting in the queue, compiling, exporting, and pushing the image each have their own
+/// timeout, and when each phase finished is stored with the deployment
+async fn run_build(conn: DbConnection, build: QueuedBuild) {
+    let QueuedBuild { id, function_app_name, dockerfile, strict, temp_dir, content_hash, received_at, slot } = build;
+
+    // Unzip the uploaded code in the build directory
+    match function_app_builder::unzip_file_in_temp_dir(&temp_dir) {
+        Ok(_) => (),
+        Err(e) if phases::is_timeout_error(&e) => return fail_build(&conn, &id, &e),
+        Err(e) => return fail_build(&conn, &id, &format!("Could not unzip code: {}", e)),
+    }
+
+    let extracted_at = SystemTime::now();
+
+    // Wait for our turn in the build queue. The slot is released when the build finishes. Any number of builds can be
+    // queued, so the connection goes back to the pool while waiting
+    drop(conn);
+    let waited = slot.wait_for_turn().await;
+    let mut conn = match web::block(move || storage::create_connection_for_app(&id)).await.map_err(|e| e.to_string()).and_then(|conn| conn) {
+        Ok(conn) => conn,
+        Err(e) => {
+            println!("Error connecting to the database to build {}: {}", function_app_name, e);
+            return;
+        }
+    };
+
+    match waited {
+        Ok(_) => (),
+        Err(e) if e == build_queue::BUILD_CANCELLED => return cancel_build_cleanup(&conn, &id, temp_dir),
+        Err(e) => return fail_build(&conn, &id, &e),
+    };
+
+    // Build the Docker container for the function app, recording how long it takes. The build can take minutes,
+    // so it runs on the blocking thread pool rather than holding up a server worker
+    events::publish_deploy_progress(&conn, &id, "building", None, None);
+    let build_start = SystemTime::now();
+    let (temp_dir, result) = match faults::take_build_failure() {
+        Some(e) => (temp_dir, Err(e)),
+        None => {
+            // The image is labelled with the deployment the build will be stored as
+            let mut labels = match docker::get_app_labels(&conn, &id) {
+                Ok(labels) => labels,
+                Err(e) => return fail_build(&conn, &id, &format!("Error getting function app details: {}", e)),
+            };
+            labels.deployment = Some(labels.deployment.unwrap_or_default() + 1);
+
+            let content = dockerfile.clone();
+            let built = actix_web::rt::task::spawn_blocking(move || {
+                let result = docker::build_function_app_container(&temp_dir, &labels, &content, strict);
+                (temp_dir, result)
+            }).await;
+
+            match built {
+                Ok(built) => built,
+                Err(e) => return fail_build(&conn, &id, &format!("Error running the build: {}", e)),
+            }
+        }
+    };
+
+    let built_at = SystemTime::now();
+    let started_at = phases::to_timestamp(build_start);
+    let duration = build_start.elapsed().unwrap_or_default().as_secs();
+
+    // Push the image to the registry if one is set. The app stays building until the push finishes, and a push
+    // that fails or stops making progress fails the build
+    let result = match result {
+        Ok(export_started) if registry::get_registry().is_some() => {
+            events::publish_deploy_progress(&conn, &id, "pushing", None, None);
+            let name = function_app_name.clone();
+            let hash = content_hash.clone();
+            let pushed = actix_web::rt::task::spawn_blocking(move || registry::push_image(&id, &name, &hash)).await;
+
+            match pushed {
+                Ok(pushed) => pushed.map(|_| export_started),
+                Err(e) => Err(format!("Error running the push: {}", e)),
+            }
+        },
+        result => result,
+    };
+
+    // Record the build and set the status to ready or error based on the result
+    let status_update = storage::complete_build(&mut conn, &id, started_at, duration, result.is_ok());
+
+    let export_started = match result {
+        Ok(export_started) => export_started,
+        Err(e) if e == build_queue::BUILD_CANCELLED => return cancel_build_cleanup(&conn, &id, temp_dir),
+        Err(e) => return fail_build(&conn, &id, &e),
+    };
+
+    // Keep any custom error pages from the app so the gateway can show them
+    if let Err(e) = pages::save_app_error_pages(&temp_dir.path().join("code"), &id) {
+        println!("Error saving error pages: {}", e);
+    }
+
+    if let Err(e) = status_update {
+        return fail_build(&conn, &id, &format!("Error updating status: {}", e));
+    }
+
+    // Record the deployment. If approval is required, the new code can't be started until the deployment is approved
+    let approval_required = approvals::is_approval_required();
+    let deploy_phases = DeployPhases {
+        received_at: Some(phases::to_timestamp(received_at)),
+        extracted_at: Some(phases::to_timestamp(extracted_at)),
+        build_started_at: Some(started_at),
+        export_started_at: export_started.map(phases::to_timestamp),
+        built_at: Some(phases::to_timestamp(built_at)),
+    };
+    let number = match storage::add_deployment(&conn, &id, started_at, !approval_required, &deploy_phases, &content_hash) {
+        Ok(number) => number,
+        Err(e) => return fail_build(&conn, &id, &format!("Error adding deployment: {}", e)),
+    };
+    build_logs::finish(&conn, &id, BUILD_SUCCEEDED, None);
+
+    // Sign the image so it can be checked before it is started
+    if let Err(e) = signing::sign_deployment(&conn, &id, &function_app_name, number) {
+        println!("Error signing image for {}: {}", function_app_name, e);
+    }
+
+    // Store the bill of materials with the deployment. The app has built, so a failure here doesn't fail the deployment
+    match sbom::generate_sbom(&function_app_name, &temp_dir.path().join("code"), &dockerfile) {
+        Ok(sbom) => {
+            if let Err(e) = storage::set_deployment_sbom(&conn, &id, number, &sbom) {
+                println!("Error saving bill of materials: {}", e);
+            }
+        },
+        Err(e) => println!("Error generating bill of materials for {}: {}", function_app_name, e),
+    }
+
+    // Store the README from the code, so callers can find out how to call the app
+    match readme::read_readme(&temp_dir.path().join("code")) {
+        Ok(Some(readme)) => {
+            if let Err(e) = storage::set_deployment_readme(&conn, &id, number, &readme) {
+                println!("Error saving README: {}", e);
+            }
+        },
+        Ok(None) => {},
+        Err(e) => println!("Error reading README for {}: {}", function_app_name, e),
+    }
+
+    if approval_required {
+        events::publish_deploy_progress(&conn, &id, "awaiting_approval", Some(number), None);
+        return;
+    }
+
+    events::publish_deploy_progress(&conn, &id, "deployed", Some(number), None);
+}
+
+/// Gets the latest deployment of a function app if it was built from code with the given content hash, so the
+/// upload doesn't need building
+///
+/// The deployment is only reused if its image is still there and nothing has happened to the app since, such as a
+/// failed or cancelled build or another build waiting in the queue
+fn get_unchanged_deployment(conn: &Connection, id: &Uuid, function_app_name: &String, content_hash: &str) -> Result<Option<UnchangedDeployment>, String> {
+    let (number, approved, latest_hash) = match storage::get_latest_deployment_content_hash(conn, id) {
+        Ok(Some(latest)) => latest,
+        Ok(None) => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    if latest_hash != content_hash || build_queue::is_queued(id) {
+        return Ok(None);
+    }
+
+    match storage::get_function_app_stored_status(conn, id) {
+        Ok(FunctionAppStatus::Ready) | Ok(FunctionAppStatus::Running) => {},
+        Ok(_) => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    }
+
+    if docker::get_image_id(function_app_name).is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some(UnchangedDeployment {
+        number,
+        approved,
+        content_hash: latest_hash,
+    }))
+}
+
+/// Cleans up after a cancelled build, deleting the uploaded code and marking the app as cancelled
+fn cancel_build_cleanup(conn: &Connection, id: &Uuid, temp_dir: TempDir) {
+    if let Err(e) = temp_dir.close() {
+        println!("Error removing temporary directory: {}", e);
+    }
+
+    let _ = storage::set_function_app_build_error(conn, id, build_queue::BUILD_CANCELLED);
+    let _ = storage::set_function_app_status(conn, id, &FunctionAppStatus::Cancelled);
+    build_logs::finish(conn, id, BUILD_CANCELLED, None);
+    events::publish_deploy_progress(conn, id, "cancelled", None, None);
+}
+
+/// Backs up the database for a namespace, returning the SQLite database file
+///
+/// This is only available when each namespace is stored in its own database
+#[get("/namespaces/{namespace}/backup")]
+async fn backup_namespace(namespace: web::Path<String>) -> HttpResponse {
+    run_blocking(move || backup_namespace_impl(namespace)).await
+}
+
+/// Backs up the database for a namespace
+fn backup_namespace_impl(namespace: web::Path<String>) -> HttpResponse {
+    // Back up to a temporary folder
+    let temp_dir = match tempdir() {
+        Ok(dir) => dir,
+        Err(e) => return errors::response(ApiError::Internal, &format!("Error creating temporary directory: {}", e)),
+    };
+    let backup_file = temp_dir.path().join("backup.db");
+
+    if let Err(e) = storage::backup_namespace(&namespace, &backup_file) {
+        println!("Error backing up namespace: {}", e);
+        return errors::response(ApiError::BadRequest, &e);
+    }
+
+    // Return the backup file
+    match std::fs::read(&backup_file) {
+        Ok(backup) => HttpResponse::Ok().content_type("application/vnd.sqlite3").body(backup),
+        Err(e) => errors::response(ApiError::Internal, &format!("Error reading backup: {}", e)),
+    }
+}
+
+/// Restores the database for a namespace from a backup, replacing the current database
+///
+/// The body is the SQLite database file returned from the backup endpoint
+#[post("/namespaces/{namespace}/restore")]
+async fn restore_namespace(namespace: web::Path<String>, body: web::Bytes) -> HttpResponse {
+    run_blocking(move || restore_namespace_impl(namespace, body)).await
+}
+
+/// Restores the database for a namespace from a backup
+fn restore_namespace_impl(namespace: web::Path<String>, body: web::Bytes) -> HttpResponse {
+    // Write the backup to a temporary folder so it can be checked before it is restored
+    let temp_dir = match tempdir() {
+        Ok(dir) => dir,
+        Err(e) => return errors::response(ApiError::Internal, &format!("Error creating temporary directory: {}", e)),
+    };
+    let backup_file = temp_dir.path().join("backup.db");
+
+    if let Err(e) = std::fs::write(&backup_file, &body) {
+        return errors::response(ApiError::Internal, &format!("Error writing backup: {}", e));
+    }
+
+    match storage::restore_namespace(&namespace, &backup_file) {
+        Ok(_) => HttpResponse::Ok().body(""),
+        Err(e) => {
+            println!("Error restoring namespace: {}", e);
+            errors::response(ApiError::BadRequest, &e)
+        }
+    }
+}
+
+/// Gets the default settings for the apps in a namespace. A namespace without defaults returns them all unset
+#[get("/namespaces/{namespace}/defaults")]
+async fn get_namespace_defaults(namespace: web::Path<String>) -> HttpResponse {
+    if let Err(e) = storage::validate_namespace(&namespace) {
+        return errors::response(ApiError::BadRequest, &e);
+    }
+
+    let namespace = namespace.into_inner();
+    let defaults = web::block(move || {
+        let conn = storage::create_namespace_connection(&namespace)?;
+        storage::get_namespace_defaults(&conn, &namespace).map_err(|e| e.to_string())
+    }).await;
+
+    match defaults {
+        Ok(Ok(defaults)) => HttpResponse::Ok().json(defaults),
+        Ok(Err(e)) => errors::response(ApiError::Internal, &e),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Sets the default settings for the apps in a namespace, replacing any that were set before. Apps use the defaults
+/// for the settings that aren't made on them from their next request or scale, so running apps don't need restarting
+#[post("/namespaces/{namespace}/defaults")]
+async fn set_namespace_defaults(namespace: web::Path<String>, body: Json<NamespaceDefaults>) -> HttpResponse {
+    if let Err(e) = storage::validate_namespace(&namespace) {
+        return errors::response(ApiError::BadRequest, &e);
+    }
+
+    let defaults = match defaults::validate_defaults(&body) {
+        Ok(defaults) => defaults,
+        Err(e) => return errors::response(ApiError::InvalidDefaults, &e),
+    };
+
+    let namespace = namespace.into_inner();
+    let saved = web::block(move || {
+        let conn = storage::create_namespace_connection(&namespace)?;
+        storage::set_namespace_defaults(&conn, &namespace, &defaults).map_err(|e| e.to_string())
+    }).await;
+
+    match saved {
+        Ok(Ok(_)) => HttpResponse::Ok().body(""),
+        Ok(Err(e)) => errors::response(ApiError::Internal, &e),
+        Err(e) => errors::response(ApiError::Internal, &e.to_string()),
+    }
+}
+
+/// Creates the listener for the server
+///
+/// SO_REUSEPORT is set so a new version of the host can bind to the same port while the old one is still
+/// running, allowing rustless-hostctl to upgrade the host without refusing any connections
+fn create_listener(address: &str) -> io::Result<TcpListener> {
+    let address = match listeners::parse_address(address) {
+        Ok(address) => address,
+        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+    };
+
+    let socket = bind_socket(&address)?;
+    socket.listen(1024)?;
+
+    Ok(socket.into())
+}
+
+/// Creates a socket bound to the address the server listens on, with the options that let a new version of the
+/// host bind alongside the old one. IPv6 addresses are dual-stack unless RUSTLESS_IPV6_ONLY is set
+fn bind_socket(address: &SocketAddr) -> io::Result<Socket> {
+    let socket = listeners::create_socket(address)?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&(*address).into())?;
+
+    Ok(socket)
+}
+
+/// Loads the host configuration from the given config file, or the one in RUSTLESS_CONFIG, or rustless_host.toml in
+/// the working directory if it exists. Environment variables, such as RUSTLESS_ADDRESS, RUSTLESS_PORT, and
+/// RUSTLESS_DB_FILE, take priority over the file, and the defaults are used for anything not set in either
+///
+/// Call this before anything else, as settings in the file are set as environment variables for the rest of the host
+pub fn load_config(path: Option<&Path>) -> Result<HostConfig, String> {
+    config::load(path)
+}
+
+/// Checks the host configuration without starting the host or changing anything: the port can be bound, the TLS
+/// files load and the certificate hasn't expired, builds can write to the build directory, the database is up to
+/// date, docker is reachable, docker can log in to the registry, and the optional listeners can be bound
+///
+/// Call load_config first, as the checks read the settings it sets as environment variables
+pub fn check_config(config: &HostConfig) -> Vec<ConfigCheck> {
+    config_check::check(config)
+}
+
+/// Gets the path to the main database file, set with RUSTLESS_DB_FILE or database_file in the config file
+pub fn database_file() -> PathBuf {
+    storage::get_db_file()
+}
+
+/// Gets the folder the namespace database files are in when namespaces are stored separately, next to the main
+/// database file
+pub fn namespace_database_dir() -> PathBuf {
+    storage::get_namespace_db_dir()
+}
+
+/// Backs up a database file with VACUUM INTO, so the copy is consistent even while a host is writing to it
+pub fn backup_database(file: &Path, backup_file: &Path) -> Result<(), String> {
+    storage::backup_database_file(file, backup_file)
+}
+
+/// Restores a database file from a backup made with backup_database, using SQLite's backup API
+pub fn restore_database(file: &Path, backup_file: &Path) -> Result<(), String> {
+    storage::restore_database_file(file, backup_file)
+}
+
+/// Creates or upgrades the database tables, without starting the server
+pub fn migrate() -> Result<(), String> {
+    storage::create_connection().map(|_| ())
+}
+
+/// Creates an API key with the given name for the management API, returning the key. Once a key has been created,
+/// requests to the management API need one
+pub fn create_api_key(name: &str) -> Result<String, String> {
+    auth::create_key(name)
+}
+
+/// Revokes the API key with the given name. Returns false if there is no key with that name, or it was already revoked
+pub fn revoke_api_key(name: &str) -> Result<bool, String> {
+    auth::revoke_key(name)
+}
+
+/// Gets all the API keys for the management API, including revoked ones
+pub fn list_api_keys() -> Result<Vec<ApiKey>, String> {
+    auth::list_keys()
+}
+
+/// Registers the function apps that have a built image with rustless labels but aren't in the database, such as
+/// after the database was restored from a backup
+pub fn adopt_orphaned_apps() -> Result<AdoptReport, String> {
+    adopt::adopt()
+}
+
+/// Runs the docker broker until it is stopped, answering calls from hosts on the given unix socket. Hosts started
+/// with RUSTLESS_DOCKER_BROKER set to the socket send everything they do with docker to the broker, so only the
+/// broker needs access to the docker socket. The broker only builds, saves, and loads files in the build directory,
+/// which must be the one the host runs builds in
+pub fn run_docker_broker(socket: &Path, build_dir: Option<PathBuf>) -> Result<(), String> {
+    build_dirs::set_build_dir(&config::get_broker_build_dir(build_dir))?;
+    broker::serve(socket)
+}
+
+/// Runs the host until it is stopped, such as by SIGTERM or Ctrl+C, migrating the database first
+///
+/// This lets the host run inside another binary or a test, and must be called from an actix runtime, such as in a
+/// function marked with #[actix_web::main] or #[actix_web::test]. The background jobs, such as the timer scheduler
+/// and crash checker, are shared by the whole process, so only one host should be served from a process at a time
+pub async fn serve(config: HostConfig) -> Result<(), String> {
+    // Create the connection. This also creates or upgrades the database tables
+    migrate()?;
+
+    if database::get_storage_backend() == StorageBackend::Postgres {
+        println!("Storing data in the Postgres database set in {}, shared with any other hosts that use it", database::DATABASE_URL_ENV);
+    }
+
+    // Warn if the management API is open, as anyone who can reach the host can deploy and start containers. Otherwise
+    // show what is accepted, and warn about providers that can't be used, such as an identity provider that is down
+    if !auth::is_required()? {
+        match auth::is_open_allowed() {
+            true => {
+                println!("**************************************************************************************************");
+                println!("WARNING: the management API is open, as no API keys have been created and RUSTLESS_OPEN_API is set.");
+                println!("Anyone who can reach the host can deploy and run containers on it. Create a key with");
+                println!("rustless-hostctl keys create to close it");
+                println!("**************************************************************************************************");
+            },
+            false => println!("No API keys have been created, so the management API rejects every request. Create one with rustless-hostctl keys create"),
+        }
+    }
+
+    let checks = match web::block(auth::check_providers).await {
+        Ok(checks) => checks?,
+        Err(e) => return Err(format!("Error checking the auth providers: {}", e)),
+    };
+    for (name, check) in checks {
+        match check {
+            Ok(accepts) => println!("Authenticating the management API with {}: {}", name, accepts),
+            Err(e) => println!("Authenticating the management API with {} may fail: {}", name, e),
+        }
+    }
+
+    // Set up HTTPS
+    let mut builder = match SslAcceptor::mozilla_intermediate(SslMethod::tls()) {
+        Ok(builder) => builder,
+        Err(e) => return Err(format!("Error creating SSL builder: {}", e)),
+    };
+
+    if builder.set_private_key_file(&config.private_key_file, SslFiletype::PEM).is_err() {
+        return Err(format!("Error setting private key file {}", config.private_key_file.display()));
+    }
+
+    if builder.set_certificate_chain_file(&config.certificate_file).is_err() {
+        return Err(format!("Error setting certificate chain file {}", config.certificate_file.display()));
+    }
+
+    // Bind to the port
+    let listener = match create_listener(&config.address) {
+        Ok(listener) => listener,
+        Err(e) => return Err(format!("Error binding to port: {}", e)),
+    };
+    if let Ok(address) = listener.local_addr() {
+        println!("Listening on {}", listeners::describe(&address));
+    }
+
+    // Behind a reverse proxy the host is reached under a path, which generated links include
+    let base_path = listeners::get_base_path();
+    if !base_path.is_empty() {
+        println!("Served under {} by a reverse proxy", base_path);
+    }
+
+    // Docker is called differently under Docker Desktop, so show which platform was detected
+    println!("Running on {}", platform::get_platform().name());
+
+    // The host only needs access to the docker socket if it isn't using a broker
+    if let Some(socket) = broker::get_socket() {
+        println!("Using the docker broker at {}", socket.display());
+    }
+
+    // Set where builds run, removing anything left behind by builds on a host that crashed
+    build_dirs::set_build_dir(&config.build_dir)?;
+    build_dirs::clean_orphaned();
+
+    // Start firing timer triggers
+    triggers::start_scheduler();
+
+    // Start watching for crashed apps and keeping event stream connections open
+    events::start();
+
+    // Start refreshing the containers docker is running, so app status is served without asking docker each time
+    container_states::start();
+
+    // Start keeping the stored status of apps in line with the containers docker is running
+    reconciler::start();
+
+    // Start the apps that were running when the host stopped again, if this is turned on
+    dependencies::restart_on_startup();
+
+    // Start stopping apps that go without requests, if there is an idle timeout
+    idle::start();
+
+    // Start health checking the replicas of apps that are scaled out, so requests only go to healthy ones
+    replicas::start();
+
+    // Start sampling the resources running apps use, so there is a history to look back on when one crashes
+    resource_history::start();
+
+    // Start the egress proxy if it is turned on, so apps can only call the destinations they are allowed to
+    egress::start_proxy()?;
+
+    // Start the crates.io cache if it is turned on, so builds download crates through the host
+    crates_cache::start()?;
+
+    // Start the gRPC gateway if it is turned on, so gRPC calls are routed to the apps that serve them
+    grpc::start()?;
+
+    // Record the process ID so the host can be found for upgrades
+    if let Some(pid_file) = &config.pid_file {
+        if let Err(e) = std::fs::write(pid_file, std::process::id().to_string()) {
+            println!("Error writing PID file: {}", e);
+        }
+    }
+
+    // Create and start the server. Slow clients are disconnected by the timeouts here, and oversized
+    // requests are rejected by the limits middleware
+    let request_limits = limits::get_limits();
+
+    // The handlers get their database connections from the same pools as the background jobs
+    let pools = web::Data::from(db_pool::get_pools());
+    HttpServer::new(move || {
+        App::new().app_data(pools.clone())
+                  .wrap(from_fn(auth::require_api_key))
+                  .wrap(from_fn(limits::enforce_limits))
+                  .configure(faults::configure)
+                  .service(greet)
+                  .service(get_request_limits)
+                  .service(get_leases)
+                  .service(get_quotas)
+                  .service(get_crates_cache_stats)
+                  .service(purge_crates_cache)
+                  .service(get_version)
+                  .service(healthz)
+                  .service(get_events)
+                  .service(redirect_to_ui)
+                  .service(get_ui)
+                  .service(get_function_app_deployments)
+                  .service(get_function_app_deployments_by_name)
+                  .service(get_function_app_logs)
+                  .service(get_function_app_logs_by_name)
+                  .service(readyz)
+                  .service(list_templates)
+                  .service(create_function_app)
+                  .service(plan_function_app)
+                  .service(adopt_function_apps)
+                  .service(post_function_app_code)
+                  .service(list_function_apps)
+                  .service(get_function_apps_status)
+                  .service(get_function_apps_resource_usage)
+                  .service(get_analytics)
+                  .service(get_function_app_id)
+                  .service(start_function_apps)
+                  .service(start_function_app)
+                  .service(get_function_app_status)
+                  .service(get_function_app_status_by_name)
+                  .service(get_function_app_routes)
+                  .service(stop_function_app)
+                  .service(stop_function_app_by_name)
+                  .service(restart_function_app)
+                  .service(restart_function_app_by_name)
+                  .service(delete_function_app)
+                  .service(delete_function_app_by_name)
+                  .service(get_function_app_routes_by_name)
+                  .service(start_function_app_by_name)
+                  .service(set_function_app_dependencies)
+                  .service(set_function_app_dependencies_by_name)
+                  .service(get_function_app_dependencies)
+                  .service(get_function_app_dependencies_by_name)
+                  .service(set_function_app_maintenance)
+                  .service(set_function_app_maintenance_by_name)
+                  .service(cancel_function_app_build)
+                  .service(cancel_function_app_build_by_name)
+                  .service(get_signing_key)
+                  .service(verify_function_app_signature)
+                  .service(verify_function_app_signature_by_name)
+                  .service(get_deployment_sbom)
+                  .service(get_deployment_sbom_by_name)
+                  .service(export_deployment)
+                  .service(export_deployment_by_name)
+                  .service(import_deployment)
+                  .service(import_deployment_by_name)
+                  .service(add_deployment_promotion)
+                  .service(add_deployment_promotion_by_name)
+                  .service(get_function_app_readme)
+                  .service(get_function_app_readme_by_name)
+                  .service(approve_deployment)
+                  .service(set_function_app_mirror)
+                  .service(set_function_app_timer_trigger)
+                  .service(set_function_app_timer_trigger_by_name)
+                  .service(get_function_app_timer_next_runs)
+                  .service(get_function_app_timer_next_runs_by_name)
+                  .service(set_function_app_egress)
+                  .service(set_function_app_egress_by_name)
+                  .service(get_function_app_egress)
+                  .service(get_function_app_egress_by_name)
+                  .service(set_function_app_grpc)
+                  .service(set_function_app_grpc_by_name)
+                  .service(get_function_app_grpc)
+                  .service(get_function_app_grpc_by_name)
+                  .service(get_function_app_info)
+                  .service(get_function_app_info_by_name)
+                  .service(scale_function_app)
+                  .service(scale_function_app_by_name)
+                  .service(get_function_app_replicas)
+                  .service(get_function_app_replicas_by_name)
+                  .service(set_function_app_scale_profiles)
+                  .service(set_function_app_scale_profiles_by_name)
+                  .service(get_function_app_scale_profiles)
+                  .service(get_function_app_scale_profiles_by_name)
+                  .service(set_function_app_buffering)
+                  .service(set_function_app_buffering_by_name)
+                  .service(get_function_app_buffering)
+                  .service(get_function_app_buffering_by_name)
+                  .service(get_function_app_effective_config)
+                  .service(get_function_app_effective_config_by_name)
+                  .service(run_function_app_trigger)
+                  .service(run_function_app_trigger_by_name)
+                  .service(get_function_app_resource_samples)
+                  .service(get_function_app_resource_samples_by_name)
+                  .service(get_function_app_build_log)
+                  .service(get_function_app_build_log_by_name)
+                  .service(get_function_app_trigger_runs)
+                  .service(get_function_app_trigger_runs_by_name)
+                  .service(set_function_app_mirror_by_name)
+                  .service(set_function_app_recording)
+                  .service(set_function_app_recording_by_name)
+                  .service(get_recorded_requests)
+                  .service(get_recorded_requests_by_name)
+                  .service(set_function_app_redaction)
+                  .service(set_function_app_redaction_by_name)
+                  .service(get_function_app_redaction)
+                  .service(get_function_app_redaction_by_name)
+                  .service(approve_deployment_by_name)
+                  .service(post_function_app_code_by_name)
+                  .service(backup_namespace)
+                  .service(restore_namespace)
+                  .service(get_namespace_defaults)
+                  .service(set_namespace_defaults)
+                  .service(landing_page)
+                  .service(route_to_function_app)
+                  .service(get_default_app)
+                  .service(set_default_app)
+                  .default_service(web::to(fallback))
+    })
+    .client_request_timeout(Duration::from_secs(request_limits.header_timeout_secs))
+    .keep_alive(Duration::from_secs(request_limits.keep_alive_secs))
+    .tls_handshake_timeout(Duration::from_secs(request_limits.tls_handshake_timeout_secs))
+    .listen_openssl(listener, builder)
+    .map_err(|e| format!("Error starting server: {}", e))?
+    .run()
+    .await
+    .map_err(|e| format!("Error running server: {}", e))?;
+
+    // Let other host processes take over the background jobs straight away
+    leases::release_all();
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,157 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use futures::{stream, Stream, StreamExt};
+
+use rustless_shared::{RejectedRequests, RequestLimits};
+
+/// The environment variable containing the most headers a request can have. The HTTP server never accepts more
+/// than 96, whatever this is set to
+const MAX_HEADERS_ENV: &str = "RUSTLESS_MAX_HEADERS";
+
+/// The environment variable containing the most bytes the headers of a request can take up
+const MAX_HEADER_BYTES_ENV: &str = "RUSTLESS_MAX_HEADER_BYTES";
+
+/// The environment variable containing the most bytes the request line can take up
+const MAX_REQUEST_LINE_BYTES_ENV: &str = "RUSTLESS_MAX_REQUEST_LINE_BYTES";
+
+/// The environment variable containing how many seconds a client has to send the request line and headers
+const HEADER_TIMEOUT_ENV: &str = "RUSTLESS_HEADER_TIMEOUT";
+
+/// The environment variable containing how many seconds a client can go without sending any of the request body
+const BODY_IDLE_TIMEOUT_ENV: &str = "RUSTLESS_BODY_IDLE_TIMEOUT";
+
+/// The environment variable containing how many seconds an idle keep-alive connection is kept open
+const KEEP_ALIVE_ENV: &str = "RUSTLESS_KEEP_ALIVE";
+
+/// The environment variable containing how many seconds a client has to complete the TLS handshake
+const TLS_HANDSHAKE_TIMEOUT_ENV: &str = "RUSTLESS_TLS_HANDSHAKE_TIMEOUT";
+
+/// The limits used if the environment variables aren't set
+const DEFAULT_MAX_HEADERS: usize = 64;
+const DEFAULT_MAX_HEADER_BYTES: usize = 16 * 1024;
+const DEFAULT_MAX_REQUEST_LINE_BYTES: usize = 8 * 1024;
+const DEFAULT_HEADER_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_BODY_IDLE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_KEEP_ALIVE_SECS: u64 = 15;
+const DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS: u64 = 3;
+
+/// The limits put on requests, read from the environment once
+static LIMITS: OnceLock<RequestLimits> = OnceLock::new();
+
+/// The requests rejected for breaking the limits since the host started
+static REJECTED: OnceLock<Mutex<RejectedRequests>> = OnceLock::new();
+
+/// Reads a limit from an environment variable, using the default if it isn't set or isn't a number above 0
+fn read_limit<T: std::str::FromStr + PartialOrd + Default>(env_var: &str, default: T) -> T {
+    match std::env::var(env_var) {
+        Ok(value) => match value.parse::<T>() {
+            Ok(limit) if limit > T::default() => limit,
+            _ => {
+                println!("Ignoring invalid {}: {}", env_var, value);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+/// Gets the limits put on requests
+pub fn get_limits() -> &'static RequestLimits {
+    LIMITS.get_or_init(|| RequestLimits {
+        max_headers: read_limit(MAX_HEADERS_ENV, DEFAULT_MAX_HEADERS),
+        max_header_bytes: read_limit(MAX_HEADER_BYTES_ENV, DEFAULT_MAX_HEADER_BYTES),
+        max_request_line_bytes: read_limit(MAX_REQUEST_LINE_BYTES_ENV, DEFAULT_MAX_REQUEST_LINE_BYTES),
+        header_timeout_secs: read_limit(HEADER_TIMEOUT_ENV, DEFAULT_HEADER_TIMEOUT_SECS),
+        body_idle_timeout_secs: read_limit(BODY_IDLE_TIMEOUT_ENV, DEFAULT_BODY_IDLE_TIMEOUT_SECS),
+        keep_alive_secs: read_limit(KEEP_ALIVE_ENV, DEFAULT_KEEP_ALIVE_SECS),
+        tls_handshake_timeout_secs: read_limit(TLS_HANDSHAKE_TIMEOUT_ENV, DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS),
+    })
+}
+
+/// Records a rejected request
+fn record_rejection(update: impl FnOnce(&mut RejectedRequests)) {
+    let rejected = REJECTED.get_or_init(|| Mutex::new(RejectedRequests::default()));
+    if let Ok(mut rejected) = rejected.lock() {
+        update(&mut rejected);
+    }
+}
+
+/// Gets the requests rejected for breaking the limits since the host started
+pub fn get_rejected_requests() -> RejectedRequests {
+    let rejected = REJECTED.get_or_init(|| Mutex::new(RejectedRequests::default()));
+    match rejected.lock() {
+        Ok(rejected) => rejected.clone(),
+        Err(_) => RejectedRequests::default(),
+    }
+}
+
+/// Checks the request line and headers against the limits, returning the response to reject the request with
+/// if they are broken
+fn check_head(req: &ServiceRequest, limits: &RequestLimits) -> Option<HttpResponse> {
+    let target_length = req.uri().path_and_query().map(|target| target.as_str().len()).unwrap_or(0);
+    if req.method().as_str().len() + 1 + target_length > limits.max_request_line_bytes {
+        record_rejection(|rejected| rejected.request_line_too_long += 1);
+        return Some(HttpResponse::build(StatusCode::URI_TOO_LONG).body("The request line is too long"));
+    }
+
+    if req.headers().len() > limits.max_headers {
+        record_rejection(|rejected| rejected.too_many_headers += 1);
+        return Some(HttpResponse::build(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE).body("The request has too many headers"));
+    }
+
+    let header_bytes: usize = req.headers().iter().map(|(name, value)| name.as_str().len() + value.len()).sum();
+    if header_bytes > limits.max_header_bytes {
+        record_rejection(|rejected| rejected.headers_too_large += 1);
+        return Some(HttpResponse::build(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE).body("The request headers are too large"));
+    }
+
+    None
+}
+
+/// Wraps a request body so reading it fails if the client stops sending it, rather than holding the
+/// connection open for ever
+fn with_idle_timeout(payload: Payload, idle_timeout: Duration) -> Payload {
+    let body = stream::unfold((payload, false), move |(mut payload, timed_out)| async move {
+        if timed_out {
+            return None;
+        }
+
+        match actix_web::rt::time::timeout(idle_timeout, payload.next()).await {
+            Ok(Some(chunk)) => Some((chunk, (payload, false))),
+            Ok(None) => None,
+            Err(_) => {
+                record_rejection(|rejected| rejected.slow_body += 1);
+                let error = io::Error::new(io::ErrorKind::TimedOut, "The client stopped sending the request body");
+                Some((Err(PayloadError::Io(error)), (payload, true)))
+            }
+        }
+    });
+
+    let body: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> = Box::pin(body);
+    Payload::from(body)
+}
+
+/// Middleware that rejects requests that break the limits, for both the management API and the gateway as
+/// they share a listener. Slow clients sending headers are disconnected by the server's own timeouts
+pub async fn enforce_limits(mut req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limits = get_limits();
+
+    if let Some(response) = check_head(&req, limits) {
+        return Ok(req.into_response(response));
+    }
+
+    let payload = req.take_payload();
+    req.set_payload(with_idle_timeout(payload, Duration::from_secs(limits.body_idle_timeout_secs)));
+
+    next.call(req).await.map(|res| res.map_into_boxed_body())
+}
@@ -0,0 +1,96 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// The environment variable that makes listeners on an IPv6 address, such as [::]:8080, only accept IPv6
+/// connections. By default they are dual-stack, accepting IPv4 connections too, whatever the OS default is
+pub const IPV6_ONLY_ENV: &str = "RUSTLESS_IPV6_ONLY";
+
+/// The environment variable containing the path the host is served under when it runs behind a reverse proxy, such
+/// as /rustless for https://example.com/rustless/. The proxy strips it from requests, but links the host generates
+/// need it
+pub const BASE_PATH_ENV: &str = "RUSTLESS_BASE_PATH";
+
+/// How many connections can wait to be accepted by a listener
+const BACKLOG: i32 = 1024;
+
+/// Gets if listeners on an IPv6 address only accept IPv6 connections
+pub fn is_ipv6_only() -> bool {
+    match std::env::var(IPV6_ONLY_ENV) {
+        Ok(value) => value == "1" || value.eq_ignore_ascii_case("true"),
+        Err(_) => false,
+    }
+}
+
+/// Gets the path the host is served under, such as /rustless, without a trailing slash. This is empty if the host
+/// is served at the root
+pub fn get_base_path() -> String {
+    let value = match std::env::var(BASE_PATH_ENV) {
+        Ok(value) => value,
+        Err(_) => return String::new(),
+    };
+
+    let path = value.trim().trim_matches('/');
+    if path.is_empty() {
+        return String::new();
+    }
+
+    // The path is put into links as it is, so it can't have a query, fragment, or empty or .. segments
+    if path.contains(['?', '#', ' ']) || path.split('/').any(|segment| segment.is_empty() || segment == "..") {
+        println!("Ignoring invalid {}: {}", BASE_PATH_ENV, value);
+        return String::new();
+    }
+
+    format!("/{}", path)
+}
+
+/// Parses the address a listener is set to, such as 0.0.0.0:8080 or [::]:8080. IPv6 addresses must be in brackets
+/// so the port can be told apart from the address
+pub fn parse_address(address: &str) -> Result<SocketAddr, String> {
+    match address.trim().parse::<SocketAddr>() {
+        Ok(address) => Ok(address),
+        Err(_) if address.matches(':').count() > 1 && !address.trim().starts_with('[') => Err(format!(
+            "Invalid address {}: IPv6 addresses must be in brackets, such as [::]:8080",
+            address
+        )),
+        Err(e) => Err(format!("Invalid address {}: {}", address, e)),
+    }
+}
+
+/// Creates a TCP socket for the address. Sockets for IPv6 addresses are dual-stack unless RUSTLESS_IPV6_ONLY is set,
+/// so [::]:8080 serves IPv4 and IPv6 clients on one listener
+pub fn create_socket(address: &SocketAddr) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(*address), Type::STREAM, Some(Protocol::TCP))?;
+    if address.is_ipv6() {
+        socket.set_only_v6(is_ipv6_only())?;
+    }
+
+    Ok(socket)
+}
+
+/// Describes which clients a listener on the address accepts, for the startup logs
+pub fn describe(address: &SocketAddr) -> String {
+    match (address.is_ipv6(), is_ipv6_only()) {
+        (true, true) => format!("{} (IPv6 only)", address),
+        (true, false) => format!("{} (IPv4 and IPv6)", address),
+        (false, _) => format!("{} (IPv4 only)", address),
+    }
+}
+
+/// Binds and listens on the address an optional listener, such as the gRPC gateway, is set to
+pub fn bind(address: &str) -> Result<TcpListener, String> {
+    let socket_address = parse_address(address)?;
+
+    let bound = create_socket(&socket_address).and_then(|socket| {
+        socket.set_reuse_address(true)?;
+        socket.bind(&socket_address.into())?;
+        socket.listen(BACKLOG)?;
+        Ok(socket)
+    });
+
+    match bound {
+        Ok(socket) => Ok(socket.into()),
+        Err(e) => Err(format!("Can't listen on {}: {}", socket_address, e)),
+    }
+}
@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+use std::time::SystemTime;
+
+use rusqlite::Error;
+
+use crate::database::{Connection, StorageBackend};
+use crate::storage;
+
+/// A change to the database schema. Each migration is applied once to each database, in order of version, and
+/// recorded in the schema_migrations table so it isn't applied again
+///
+/// To change the schema, add a migration to the end of MIGRATIONS with the next version rather than changing the
+/// existing ones, which have already been applied to existing databases
+struct Migration {
+    // The version the database is at once the migration has been applied
+    version: u32,
+
+    // What the migration does, recorded when it is applied
+    name: &'static str,
+
+    // Applies the migration. It runs in a transaction, so if it fails the database is left as it was
+    apply: fn(&Connection) -> Result<(), String>,
+}
+
+/// The migrations, in order of version. The first creates the tables, and upgrades databases created before
+/// migrations were recorded, as it only adds what is missing
+const MIGRATIONS: [Migration; 4] = [
+    Migration { version: 1, name: "create tables", apply: storage::create_tables },
+    Migration { version: 2, name: "namespace defaults", apply: storage::create_namespace_defaults_table },
+    Migration { version: 3, name: "redaction rules", apply: storage::add_redaction_rules_column },
+    Migration { version: 4, name: "egress proxy secrets", apply: storage::add_proxy_secret_column },
+];
+
+/// Gets the version of the schema this host upgrades databases to
+pub fn get_latest_version() -> u32 {
+    MIGRATIONS.iter().map(|migration| migration.version).max().unwrap_or(0)
+}
+
+/// Gets the version of the schema the database is at, or 0 if no migrations have been applied
+pub fn get_version(conn: &Connection) -> Result<u32, String> {
+    match conn.query_row("SELECT MAX(version) FROM schema_migrations", [], |row| row.get::<_, Option<u32>>(0)) {
+        Ok(version) => Ok(version.unwrap_or(0)),
+        Err(e) => Err(format!("Error getting database version: {}", e)),
+    }
+}
+
+/// Applies a migration if the database doesn't have it yet, recording it with the time it was applied. This must be
+/// called in a transaction. Returns false if it had already been applied, such as by another host sharing the
+/// database that started at the same time
+fn apply_if_needed(conn: &Connection, migration: &Migration) -> Result<bool, String> {
+    // Postgres doesn't lock anything when the transaction starts, so lock the migrations so hosts sharing the
+    // database apply them one at a time. SQLite takes the write lock when the transaction starts
+    if conn.backend() == StorageBackend::Postgres {
+        if let Err(e) = conn.execute("LOCK TABLE schema_migrations IN ACCESS EXCLUSIVE MODE", []) {
+            return Err(format!("Error locking migrations table: {}", e));
+        }
+    }
+
+    if get_version(conn)? >= migration.version {
+        return Ok(false);
+    }
+
+    (migration.apply)(conn)?;
+
+    let applied_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    match conn.execute(
+        "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?1, ?2, ?3)",
+        rusqlite::params![migration.version, migration.name, applied_at],
+    ) {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("Error recording migration: {}", e)),
+    }
+}
+
+/// Applies a migration in a transaction, so if it fails the database is left as it was. Returns false if it had
+/// already been applied
+fn apply(conn: &mut Connection, migration: &Migration) -> Result<bool, String> {
+    if let Err(e) = conn.begin() {
+        return Err(format!("Error starting database migration {}: {}", migration.version, e));
+    }
+
+    match apply_if_needed(conn, migration) {
+        Ok(applied) => match conn.execute("COMMIT", []) {
+            Ok(_) => Ok(applied),
+            Err(e) => Err(format!("Error committing database migration {}: {}", migration.version, e)),
+        },
+        Err(e) => {
+            let _ = conn.execute("ROLLBACK", []);
+            Err(format!("Error applying database migration {} ({}): {}", migration.version, migration.name, e))
+        }
+    }
+}
+
+/// Upgrades a database to the latest schema, applying the migrations it doesn't have yet. A database that has been
+/// upgraded by a newer version of the host is left alone, as this host may not be able to use it
+pub fn migrate(conn: &mut Connection) -> Result<(), String> {
+    if let Err(e) = conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+                  version     INTEGER PRIMARY KEY,
+                  name        TEXT NOT NULL,
+                  applied_at  INTEGER NOT NULL
+                  )",
+        [],
+    ) {
+        return Err(format!("Error creating migrations table: {}", e));
+    }
+
+    let version = get_version(conn)?;
+    if version > get_latest_version() {
+        return Err(format!("The database is at version {}, which is newer than this host supports ({}). Upgrade the host to use it", version, get_latest_version()));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > version) {
+        if apply(conn, migration)? {
+            println!("Applied database migration {}: {}", migration.version, migration.name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the database has had all the migrations applied, without applying them. Returns why the database needs
+/// upgrading if it doesn't, or an error if it has been upgraded by a newer version of the host
+pub fn check(conn: &Connection) -> Result<Option<String>, String> {
+    // A database from before migrations doesn't have the table, so is at version 0
+    let version = match conn.query_row("SELECT MAX(version) FROM schema_migrations", [], |row| row.get::<_, Option<u32>>(0)) {
+        Ok(version) => version.unwrap_or(0),
+        Err(Error::SqliteFailure(_, _)) => 0,
+        Err(e) => return Err(format!("Error getting database version: {}", e)),
+    };
+
+    match version.cmp(&get_latest_version()) {
+        Ordering::Equal => Ok(None),
+        Ordering::Less => Ok(Some(format!("Database is at version {}, and the latest is version {}", version, get_latest_version()))),
+        Ordering::Greater => Err(format!("Database is at version {}, which is newer than this host supports ({})", version, get_latest_version())),
+    }
+}
@@ -0,0 +1,192 @@
+use std::borrow::Cow;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use actix_web::HttpRequest;
+use reqwest::Client;
+use serde::Serialize;
+use uuid::Uuid;
+
+use rustless_shared::{MirrorConfig, RedactionRules};
+
+use crate::gateway::RequestBody;
+use crate::redaction;
+
+/// How long to wait for the mirror sink to accept a request before giving up
+const SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The environment variable containing the folder mirror files are written to. File sinks are paths in this folder
+const MIRROR_DIR_ENV: &str = "RUSTLESS_MIRROR_DIR";
+
+/// The folder mirror files are written to if the environment variable isn't set
+const DEFAULT_MIRROR_DIR: &str = "mirrors";
+
+/// The HTTP client used to send mirrored requests, shared so connections can be reused
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// A copy of a request to a function app, sent to the mirror sink
+#[derive(Serialize)]
+struct MirroredRequest {
+    // The name of the function app the request was for
+    app: String,
+
+    // When the request was received, in seconds since the Unix epoch
+    timestamp: u64,
+
+    // The HTTP method
+    method: String,
+
+    // The route in the app, without the api/{name} prefix
+    route: String,
+
+    // The query string, without the leading ?
+    query: String,
+
+    // The request headers, with sensitive values redacted
+    headers: Vec<(String, String)>,
+
+    // The request body, with the redacted JSON fields replaced and truncated to the configured maximum size
+    body: String,
+
+    // Whether the body was truncated
+    body_truncated: bool,
+}
+
+/// Gets the folder mirror files are written to
+fn get_mirror_dir() -> PathBuf {
+    match std::env::var(MIRROR_DIR_ENV) {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(DEFAULT_MIRROR_DIR),
+    }
+}
+
+/// Gets if a sink is a URL to post mirrored requests to, rather than a file
+fn is_url_sink(sink: &str) -> bool {
+    sink.starts_with("http://") || sink.starts_with("https://")
+}
+
+/// Gets the file in the mirror folder for a file sink. The sink must be a relative path that stays in the folder,
+/// so a mirror config can't write to any other file on the host
+fn get_sink_file(sink: &str) -> Result<PathBuf, String> {
+    // Windows drive letters and backslashes are checked for on every platform, as the config can come from anywhere
+    let path = Path::new(sink);
+    let has_drive = sink.len() >= 2 && sink.as_bytes()[0].is_ascii_alphabetic() && sink.as_bytes()[1] == b':';
+    if path.is_absolute() || sink.starts_with('/') || sink.starts_with('\\') || has_drive {
+        return Err(format!("The mirror file {} must be a path in the mirror folder, not an absolute path", sink));
+    }
+
+    let outside = sink.split(['/', '\\']).any(|part| part == "..")
+        || path.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if outside {
+        return Err(format!("The mirror file {} can't use .. to leave the mirror folder", sink));
+    }
+
+    Ok(get_mirror_dir().join(path))
+}
+
+/// Checks that a mirror config is valid before it is saved
+pub fn validate_config(config: &MirrorConfig) -> Result<(), String> {
+    if config.sink.trim().is_empty() {
+        return Err("A sink URL or file path is required to mirror requests".to_string());
+    }
+
+    if !is_url_sink(&config.sink) {
+        get_sink_file(&config.sink)?;
+    }
+
+    if !(0.0..=1.0).contains(&config.sample_rate) {
+        return Err("The sample rate must be between 0 and 1".to_string());
+    }
+
+    Ok(())
+}
+
+/// Decides if this request is in the sample to mirror
+pub fn is_sampled(sample_rate: f64) -> bool {
+    // v4 UUIDs are random, so one is used as the random number rather than adding a dependency
+    let random = Uuid::new_v4().as_u128() as f64 / u128::MAX as f64;
+    random < sample_rate
+}
+
+/// Sends a mirrored request to the sink, either posting it to a URL or appending it to a file
+async fn send_to_sink(sink: String, record: MirroredRequest) -> Result<(), String> {
+    let json = match serde_json::to_string(&record) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("Error serializing mirrored request: {}", e)),
+    };
+
+    if is_url_sink(&sink) {
+        let client = CLIENT.get_or_init(|| Client::builder().timeout(SINK_TIMEOUT).build().unwrap_or_default());
+
+        return match client.post(&sink).header("Content-Type", "application/json").body(json).send().await {
+            Ok(res) if res.status().is_success() => Ok(()),
+            Ok(res) => Err(format!("Mirror sink returned status code: {}", res.status())),
+            Err(e) => Err(format!("Error sending to mirror sink: {}", e)),
+        };
+    }
+
+    // Configs saved before file sinks were kept to the mirror folder are checked again here
+    let file = get_sink_file(&sink)?;
+
+    // Files are written on a blocking thread so the async workers aren't held up by disk IO
+    let result = actix_web::rt::task::spawn_blocking(move || {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&file)?;
+        writeln!(file, "{}", json)
+    }).await;
+
+    match result {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("Error writing to mirror file: {}", e)),
+        Err(e) => Err(format!("Error writing to mirror file: {}", e)),
+    }
+}
+
+/// Adds the headers a mirror config redacts to the app's redaction rules
+pub fn add_redacted_headers(config: &MirrorConfig, rules: &RedactionRules) -> RedactionRules {
+    RedactionRules {
+        headers: rules.headers.iter().chain(config.redact_headers.iter()).cloned().collect(),
+        json_fields: rules.json_fields.clone(),
+    }
+}
+
+/// Mirrors a request in the sample to the sink in the config, redacting it with the given rules first. The rules
+/// should include the headers the config redacts, from add_redacted_headers
+///
+/// The request is copied then sent in the background, so mirroring never slows down or changes the
+/// response to the caller. Errors sending to the sink are only logged.
+pub fn mirror_request(config: &MirrorConfig, rules: &RedactionRules, app: &str, req: &HttpRequest, body: &RequestBody, route: &str) {
+    // The body is redacted before it is truncated, as truncated JSON can't be read. Bodies cut short when the rest
+    // was streamed can't be redacted, so they are left out when the rules redact JSON fields
+    let (body, cut_short) = body.start();
+    let redacted_body = match cut_short && !rules.json_fields.is_empty() {
+        true => Cow::Borrowed(&[][..]),
+        false => redaction::redact_body(&body, rules),
+    };
+    let body_truncated = cut_short || redacted_body.len() > config.max_body_bytes;
+    let body = &redacted_body[..redacted_body.len().min(config.max_body_bytes)];
+
+    let record = MirroredRequest {
+        app: app.to_string(),
+        timestamp: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+        method: req.method().to_string(),
+        route: route.to_string(),
+        query: req.query_string().to_string(),
+        headers: redaction::redact_headers(req.headers().iter().map(|(name, value)| (name.as_str(), value.as_bytes())), rules),
+        body: String::from_utf8_lossy(body).to_string(),
+        body_truncated,
+    };
+
+    let sink = config.sink.to_string();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = send_to_sink(sink, record).await {
+            println!("Error mirroring request: {}", e);
+        }
+    });
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+use rustless_shared::ApiError;
+
+use crate::auth::{AuthProvider, AuthResult};
+
+/// The environment variable containing the issuer URL of the OpenID Connect provider whose tokens are accepted, such
+/// as https://login.example.com. Its signing keys are found from the discovery document at the issuer
+pub const OIDC_ISSUER_ENV: &str = "RUSTLESS_OIDC_ISSUER";
+
+/// The environment variable containing the audience tokens must be issued for, usually the client ID of the host in
+/// the identity provider. It must be set with the issuer, so tokens issued for other apps aren't accepted
+pub const OIDC_AUDIENCE_ENV: &str = "RUSTLESS_OIDC_AUDIENCE";
+
+/// The environment variable containing the comma separated groups that can use the management API. If it is set,
+/// tokens must have one of the groups in their groups claim, otherwise any token from the issuer is accepted
+pub const OIDC_GROUPS_ENV: &str = "RUSTLESS_OIDC_GROUPS";
+
+/// The environment variable containing the claim the groups are read from, if the provider doesn't use groups
+pub const OIDC_GROUPS_CLAIM_ENV: &str = "RUSTLESS_OIDC_GROUPS_CLAIM";
+
+/// The claim the groups are read from if the environment variable isn't set
+const DEFAULT_GROUPS_CLAIM: &str = "groups";
+
+/// The path of the discovery document under the issuer URL
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+
+/// How long to wait for the identity provider when fetching the discovery document or signing keys
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the signing keys are kept before they are fetched again
+const SIGNING_KEYS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// How soon the signing keys can be fetched again for a token signed with a key that isn't in them, such as after
+/// the provider rotates its keys. This stops tokens with made up key IDs sending a request to the provider each time
+const SIGNING_KEYS_MIN_AGE: Duration = Duration::from_secs(60);
+
+/// The algorithms tokens can be signed with. Only public key algorithms are allowed, as the keys are public and a
+/// token signed with HMAC using one as the secret would otherwise be accepted
+const ALLOWED_ALGORITHMS: [Algorithm; 8] = [
+    Algorithm::RS256, Algorithm::RS384, Algorithm::RS512,
+    Algorithm::PS256, Algorithm::PS384, Algorithm::PS512,
+    Algorithm::ES256, Algorithm::ES384,
+];
+
+/// The signing keys fetched from the identity provider, with when they were fetched
+type SigningKeys = (Instant, JwkSet);
+
+/// Accepts bearer tokens issued by an OpenID Connect provider, so access to the management API can be managed in an
+/// organization's existing identity provider. Tokens must be signed by the issuer, issued for the audience, and not
+/// expired, and must have one of the allowed groups if any are set
+pub struct OidcProvider {
+    // The issuer URL, which must match the iss claim
+    issuer: String,
+
+    // The audience, which must be in the aud claim
+    audience: String,
+
+    // The groups that can use the management API. Empty if any token from the issuer is accepted
+    groups: Vec<String>,
+
+    // The claim the groups are read from
+    groups_claim: String,
+
+    // The signing keys last fetched from the identity provider
+    signing_keys: Mutex<Option<SigningKeys>>,
+}
+
+impl OidcProvider {
+    /// Creates the provider for the issuer set in RUSTLESS_OIDC_ISSUER, or None if it isn't set. It is an error to set
+    /// the issuer without the audience
+    pub fn from_env() -> Result<Option<OidcProvider>, String> {
+        let issuer = match std::env::var(OIDC_ISSUER_ENV) {
+            Ok(value) if !value.trim().is_empty() => value.trim().trim_end_matches('/').to_string(),
+            _ => return Ok(None),
+        };
+
+        let audience = match std::env::var(OIDC_AUDIENCE_ENV) {
+            Ok(value) if !value.trim().is_empty() => value.trim().to_string(),
+            _ => return Err(format!("{} is set, so {} must be set to the audience tokens are issued for", OIDC_ISSUER_ENV, OIDC_AUDIENCE_ENV)),
+        };
+
+        let groups = match std::env::var(OIDC_GROUPS_ENV) {
+            Ok(value) => value.split(',')
+                .map(|group| group.trim().to_string())
+                .filter(|group| !group.is_empty())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        let groups_claim = match std::env::var(OIDC_GROUPS_CLAIM_ENV) {
+            Ok(value) if !value.trim().is_empty() => value.trim().to_string(),
+            _ => DEFAULT_GROUPS_CLAIM.to_string(),
+        };
+
+        Ok(Some(OidcProvider { issuer, audience, groups, groups_claim, signing_keys: Mutex::new(None) }))
+    }
+
+    /// Fetches a JSON document from the identity provider. The client blocks, so this can't be called from the server
+    /// workers, and it is created for each fetch as the keys are rarely fetched
+    fn fetch_json(&self, url: &str) -> Result<Value, String> {
+        let client = match Client::builder().timeout(FETCH_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => return Err(format!("Error creating client for the OpenID Connect provider: {}", e)),
+        };
+
+        let response = match client.get(url).send() {
+            Ok(response) => response,
+            Err(e) => return Err(format!("Error fetching {}: {}", url, e)),
+        };
+
+        if !response.status().is_success() {
+            return Err(format!("Error fetching {}: {}", url, response.status()));
+        }
+
+        match response.text().map_err(|e| e.to_string()).and_then(|body| serde_json::from_str(&body).map_err(|e| e.to_string())) {
+            Ok(json) => Ok(json),
+            Err(e) => Err(format!("Error reading {}: {}", url, e)),
+        }
+    }
+
+    /// Fetches the signing keys from the identity provider, finding where they are from the discovery document
+    fn fetch_signing_keys(&self) -> Result<JwkSet, String> {
+        let discovery = self.fetch_json(&format!("{}{}", self.issuer, DISCOVERY_PATH))?;
+        let jwks_uri = match discovery.get("jwks_uri").and_then(Value::as_str) {
+            Some(jwks_uri) => jwks_uri.to_string(),
+            None => return Err(format!("The discovery document for {} doesn't have a jwks_uri", self.issuer)),
+        };
+
+        match serde_json::from_value(self.fetch_json(&jwks_uri)?) {
+            Ok(keys) => Ok(keys),
+            Err(e) => Err(format!("Error reading the signing keys from {}: {}", jwks_uri, e)),
+        }
+    }
+
+    /// Gets the key a token was signed with, by its key ID. The keys are fetched again if they are old, or the key
+    /// isn't in them and they haven't just been fetched. A token without a key ID can only be checked if the provider
+    /// has a single key
+    fn get_decoding_key(&self, kid: Option<&str>) -> Result<Option<DecodingKey>, String> {
+        let mut signing_keys = match self.signing_keys.lock() {
+            Ok(signing_keys) => signing_keys,
+            Err(e) => return Err(format!("Error getting signing keys: {}", e)),
+        };
+
+        let find = |keys: &JwkSet| match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        };
+
+        let refresh = match &*signing_keys {
+            Some((fetched_at, keys)) => fetched_at.elapsed() > SIGNING_KEYS_MAX_AGE || (find(keys).is_none() && fetched_at.elapsed() > SIGNING_KEYS_MIN_AGE),
+            None => true,
+        };
+
+        if refresh {
+            *signing_keys = Some((Instant::now(), self.fetch_signing_keys()?));
+        }
+
+        let jwk = match signing_keys.as_ref().and_then(|(_, keys)| find(keys)) {
+            Some(jwk) => jwk,
+            None => return Ok(None),
+        };
+
+        match DecodingKey::from_jwk(&jwk) {
+            Ok(key) => Ok(Some(key)),
+            Err(e) => Err(format!("Error reading signing key from {}: {}", self.issuer, e)),
+        }
+    }
+
+    /// Gets the groups in a token's claims. Providers send them as a list, or as a single space separated string
+    fn get_groups(&self, claims: &HashMap<String, Value>) -> Vec<String> {
+        match claims.get(&self.groups_claim) {
+            Some(Value::Array(groups)) => groups.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            Some(Value::String(groups)) => groups.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl AuthProvider for OidcProvider {
+    fn name(&self) -> &'static str {
+        "OpenID Connect provider"
+    }
+
+    fn is_enabled(&self) -> Result<bool, String> {
+        Ok(true)
+    }
+
+    fn check(&self) -> Result<String, String> {
+        let keys = self.fetch_signing_keys()?;
+        let groups = match self.groups.is_empty() {
+            true => "any group".to_string(),
+            false => format!("the groups {}", self.groups.join(", ")),
+        };
+
+        Ok(format!("tokens from {} for {} in {}, signed with one of {} keys", self.issuer, self.audience, groups, keys.keys.len()))
+    }
+
+    fn authenticate(&self, token: &str) -> Result<AuthResult, (ApiError, String)> {
+        // Anything that isn't a JWT, such as an API key, is left for the other providers
+        let header = match decode_header(token) {
+            Ok(header) => header,
+            Err(_) => return Ok(AuthResult::Rejected(None)),
+        };
+
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Ok(AuthResult::Rejected(Some(format!("Tokens signed with {:?} aren't accepted", header.alg))));
+        }
+
+        let key = match self.get_decoding_key(header.kid.as_deref()) {
+            Ok(Some(key)) => key,
+            Ok(None) => return Ok(AuthResult::Rejected(Some(format!("The token wasn't signed by {}", self.issuer)))),
+            Err(e) => return Err((ApiError::BadGateway, e)),
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+
+        let claims = match decode::<HashMap<String, Value>>(token, &key, &validation) {
+            Ok(token_data) => token_data.claims,
+            Err(e) => return Ok(AuthResult::Rejected(Some(format!("The token is not valid: {}", e)))),
+        };
+
+        if !self.groups.is_empty() && !self.get_groups(&claims).iter().any(|group| self.groups.contains(group)) {
+            return Ok(AuthResult::Rejected(Some(format!("The token isn't in any of the groups that can manage the host: {}", self.groups.join(", ")))));
+        }
+
+        // Requests are logged with the user's email if the provider sends it, which is easier to read than the subject
+        let name = claims.get("email").or_else(|| claims.get("sub")).and_then(Value::as_str).unwrap_or("unknown");
+        Ok(AuthResult::Accepted(name.to_string()))
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use rust_embed::RustEmbed;
+
+use uuid::Uuid;
+
+use rustless_shared::{FunctionApp, FunctionAppStatus};
+
+/// The default pages from the pages folder
+#[derive(RustEmbed)]
+#[folder = "pages/"]
+struct PagesFolder;
+
+/// The environment variable containing the path to a HTML template to use for the landing page.
+/// The {{apps}} placeholder in the template is replaced with the list of running apps
+const LANDING_PAGE_ENV: &str = "RUSTLESS_LANDING_PAGE";
+
+/// The environment variable containing the path to a HTML template to use for the 404 page.
+/// The {{path}} placeholder in the template is replaced with the path that was requested
+const NOT_FOUND_PAGE_ENV: &str = "RUSTLESS_NOT_FOUND_PAGE";
+
+/// The folder the custom error pages for each app are stored in
+const APP_PAGES_DIR: &str = "app_pages";
+
+/// The folder in an app's code that holds its custom error pages
+const APP_PAGES_SOURCE_DIR: &str = "error_pages";
+
+/// The custom error pages an app can provide
+const APP_PAGE_FILES: [&str; 3] = ["502.html", "503.html", "maintenance.html"];
+
+/// The error pages shown by the gateway on behalf of an app
+pub enum AppErrorPage {
+    /// The app is running but isn't responding
+    BadGateway,
+
+    /// The app isn't running
+    Unavailable,
+
+    /// The app has been put into maintenance mode, with a message to show
+    Maintenance(String),
+}
+
+impl AppErrorPage {
+    /// Gets the file name for the page
+    fn file_name(&self) -> &'static str {
+        match self {
+            AppErrorPage::BadGateway => "502.html",
+            AppErrorPage::Unavailable => "503.html",
+            AppErrorPage::Maintenance(_) => "maintenance.html",
+        }
+    }
+
+    /// Gets the status code the page is served with
+    fn status(&self) -> StatusCode {
+        match self {
+            AppErrorPage::BadGateway => StatusCode::BAD_GATEWAY,
+            AppErrorPage::Unavailable | AppErrorPage::Maintenance(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// Loads a page template, either from the file set in the environment variable, or the embedded default.
+/// Templates are loaded on every request so they can be changed without restarting the host
+fn load_template(env_var: &str, default_page: &str) -> Result<String, String> {
+    if let Ok(path) = std::env::var(env_var) {
+        return match fs::read_to_string(&path) {
+            Ok(template) => Ok(template),
+            Err(e) => Err(format!("Error reading page template {}: {}", path, e)),
+        };
+    }
+
+    load_embedded_page(default_page)
+}
+
+/// Loads one of the default pages embedded in the host
+fn load_embedded_page(page_name: &str) -> Result<String, String> {
+    let page = match PagesFolder::get(page_name) {
+        Some(page) => page,
+        None => return Err(format!("Error getting {} from pages folder", page_name)),
+    };
+
+    match std::str::from_utf8(page.data.as_ref()) {
+        Ok(template) => Ok(template.to_string()),
+        Err(e) => Err(format!("Error converting {} to string: {}", page_name, e)),
+    }
+}
+
+/// Escapes text so it can be safely added to a HTML page
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Renders the landing page, listing the running function apps
+pub fn render_landing_page(apps: &[FunctionApp]) -> Result<String, String> {
+    let template = load_template(LANDING_PAGE_ENV, "landing.html")?;
+
+    // Only running apps are listed, as these are the ones end users can reach
+    let items: Vec<String> = apps.iter()
+        .filter(|app| matches!(app.status, FunctionAppStatus::Running))
+        .map(|app| format!("<li>{}</li>", escape_html(&app.name)))
+        .collect();
+
+    let apps_html = if items.is_empty() {
+        "<p>No function apps are running.</p>".to_string()
+    } else {
+        format!("<ul>\n{}\n</ul>", items.join("\n"))
+    };
+
+    Ok(template.replace("{{apps}}", &apps_html))
+}
+
+/// Renders the page shown when an unknown app or route is requested
+pub fn render_not_found_page(path: &str) -> Result<String, String> {
+    let template = load_template(NOT_FOUND_PAGE_ENV, "404.html")?;
+
+    Ok(template.replace("{{path}}", &escape_html(path)))
+}
+
+/// Gets the folder holding the custom error pages for an app
+fn get_app_pages_dir(id: &Uuid) -> PathBuf {
+    Path::new(APP_PAGES_DIR).join(id.to_string())
+}
+
+/// Saves the custom error pages from an app's code, replacing any pages from a previous deployment
+///
+/// Apps provide custom pages by adding 502.html, 503.html, or maintenance.html to an error_pages folder
+/// next to their Cargo.toml.
+pub fn save_app_error_pages(code_dir: &Path, id: &Uuid) -> Result<(), String> {
+    let pages_dir = get_app_pages_dir(id);
+    if pages_dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&pages_dir) {
+            return Err(format!("Error removing old error pages: {}", e));
+        }
+    }
+
+    let source_dir = code_dir.join(APP_PAGES_SOURCE_DIR);
+    for page in APP_PAGE_FILES {
+        let source = source_dir.join(page);
+        if !source.is_file() {
+            continue;
+        }
+
+        if let Err(e) = fs::create_dir_all(&pages_dir) {
+            return Err(format!("Error creating {}: {}", pages_dir.display(), e));
+        }
+
+        if let Err(e) = fs::copy(&source, pages_dir.join(page)) {
+            return Err(format!("Error copying error page {}: {}", page, e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the custom error pages saved for an app, such as when the app is deleted
+pub fn remove_app_error_pages(id: &Uuid) -> Result<(), String> {
+    let pages_dir = get_app_pages_dir(id);
+    if !pages_dir.exists() {
+        return Ok(());
+    }
+
+    match fs::remove_dir_all(&pages_dir) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error removing error pages: {}", e)),
+    }
+}
+
+/// Renders an error page for an app, using the app's custom page if it has one
+fn render_app_error_page(id: &Uuid, app_name: &str, page: AppErrorPage) -> String {
+    let template = match fs::read_to_string(get_app_pages_dir(id).join(page.file_name())) {
+        Ok(template) => Ok(template),
+        Err(_) => load_embedded_page(page.file_name()),
+    };
+
+    let message = match &page {
+        AppErrorPage::Maintenance(message) => escape_html(message),
+        _ => String::new(),
+    };
+
+    match template {
+        Ok(template) => template.replace("{{app}}", &escape_html(app_name)).replace("{{message}}", &message),
+        Err(e) => e,
+    }
+}
+
+/// Creates the response for an error page for an app, using the app's custom page if it has one
+pub fn app_error_response(id: &Uuid, app_name: &str, page: AppErrorPage) -> HttpResponse {
+    HttpResponse::build(page.status())
+        .content_type("text/html; charset=utf-8")
+        .body(render_app_error_page(id, app_name, page))
+}
@@ -0,0 +1,102 @@
+use std::time::{Duration, SystemTime};
+
+/// The phases of a deployment, each with its own timeout so a long wait in one isn't blamed on another
+#[derive(Clone, Copy)]
+pub enum DeployPhase {
+    /// Decoding and unzipping the uploaded code
+    Extract,
+
+    /// Waiting in the build queue for other builds to finish
+    Queue,
+
+    /// Compiling the code in the docker build
+    Compile,
+
+    /// Exporting the built image from the builder into docker
+    Export,
+
+    /// Pushing the built image to the registry. This times out when the push stops making progress, rather than
+    /// after the push has taken a set time, as large images can take a while to push
+    Push,
+}
+
+impl DeployPhase {
+    /// Gets the name of the phase, as used in errors
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeployPhase::Extract => "extract",
+            DeployPhase::Queue => "queue",
+            DeployPhase::Compile => "compile",
+            DeployPhase::Export => "export",
+            DeployPhase::Push => "push",
+        }
+    }
+
+    /// Gets the environment variable containing the timeout for the phase in seconds. 0 turns the timeout off
+    fn timeout_env(&self) -> &'static str {
+        match self {
+            DeployPhase::Extract => "RUSTLESS_EXTRACT_TIMEOUT",
+            DeployPhase::Queue => "RUSTLESS_QUEUE_TIMEOUT",
+            DeployPhase::Compile => "RUSTLESS_COMPILE_TIMEOUT",
+            DeployPhase::Export => "RUSTLESS_EXPORT_TIMEOUT",
+            DeployPhase::Push => "RUSTLESS_PUSH_TIMEOUT",
+        }
+    }
+
+    /// Gets the timeout for the phase in seconds if the environment variable isn't set
+    fn default_timeout_secs(&self) -> u64 {
+        match self {
+            DeployPhase::Extract => 120,
+            DeployPhase::Queue => 60 * 60,
+            DeployPhase::Compile => 30 * 60,
+            DeployPhase::Export => 10 * 60,
+            DeployPhase::Push => 5 * 60,
+        }
+    }
+
+    /// Gets how long the phase can take before the deployment fails, or None if it can take as long as it needs
+    pub fn get_timeout(&self) -> Option<Duration> {
+        let secs = match std::env::var(self.timeout_env()) {
+            Ok(value) => match value.parse::<u64>() {
+                Ok(secs) => secs,
+                Err(_) => {
+                    println!("Ignoring invalid {}: {}", self.timeout_env(), value);
+                    self.default_timeout_secs()
+                }
+            },
+            Err(_) => self.default_timeout_secs(),
+        };
+
+        match secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Gets if the phase has run for longer than its timeout
+    pub fn has_timed_out(&self, started: SystemTime) -> bool {
+        match self.get_timeout() {
+            Some(timeout) => started.elapsed().unwrap_or_default() > timeout,
+            None => false,
+        }
+    }
+
+    /// Gets the error returned when the phase times out
+    pub fn timeout_error(&self) -> String {
+        let timeout = self.get_timeout().unwrap_or_default().as_secs();
+        format!("{} '{}' timed out after {}s. Set {} to change the timeout", TIMED_OUT_PREFIX, self.name(), timeout, self.timeout_env())
+    }
+}
+
+/// The start of the error returned when a phase times out, so timeouts can be told apart from other failures
+const TIMED_OUT_PREFIX: &str = "Deployment phase";
+
+/// Gets if an error is from a phase timing out
+pub fn is_timeout_error(error: &str) -> bool {
+    error.starts_with(TIMED_OUT_PREFIX)
+}
+
+/// Gets a time in seconds since the Unix epoch, for recording when a phase happened
+pub fn to_timestamp(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
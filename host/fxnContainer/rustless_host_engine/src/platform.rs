@@ -0,0 +1,178 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// The environment variable that overrides the detected platform: linux, macos, or wsl2
+pub const PLATFORM_ENV: &str = "RUSTLESS_PLATFORM";
+
+/// The environment variable containing the docker CLI to run, if it isn't docker on the path
+pub const DOCKER_PATH_ENV: &str = "RUSTLESS_DOCKER_PATH";
+
+/// The environment variable containing the address the host calls running apps on, if it isn't 127.0.0.1
+pub const APP_HOST_ENV: &str = "RUSTLESS_APP_HOST";
+
+/// The address the host calls running apps on if the environment variable isn't set
+const DEFAULT_APP_HOST: &str = "127.0.0.1";
+
+/// The docker socket on Linux, and where Docker Desktop links its socket to when allowed to
+const DEFAULT_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// The kernel release file, which contains microsoft when running under WSL2
+const OS_RELEASE_FILE: &str = "/proc/sys/kernel/osrelease";
+
+/// The platform the host is running on, which decides how docker is called and how app ports are published
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Platform {
+    /// Linux - docker runs natively and published ports are on the host
+    Linux,
+
+    /// macOS - docker runs in the Docker Desktop VM, and its socket may be in the user's home folder
+    MacOs,
+
+    /// WSL2 - docker runs in the Docker Desktop VM, with ports forwarded to localhost
+    Wsl2,
+}
+
+impl Platform {
+    /// Gets the name of the platform, as set in the environment variable
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Linux => "linux",
+            Platform::MacOs => "macos",
+            Platform::Wsl2 => "wsl2",
+        }
+    }
+
+    /// Gets if docker runs in the Docker Desktop VM rather than natively
+    pub fn is_docker_desktop(&self) -> bool {
+        *self != Platform::Linux
+    }
+}
+
+/// The platform the host is running on, detected once
+static PLATFORM: OnceLock<Platform> = OnceLock::new();
+
+/// The docker socket to use if docker isn't at the default location, found once
+static DOCKER_HOST: OnceLock<Option<String>> = OnceLock::new();
+
+/// Detects the platform from the OS the host was built for, and the kernel for WSL2
+fn detect_platform() -> Platform {
+    if let Ok(value) = std::env::var(PLATFORM_ENV) {
+        match value.to_lowercase().as_str() {
+            "linux" => return Platform::Linux,
+            "macos" => return Platform::MacOs,
+            "wsl2" => return Platform::Wsl2,
+            _ => println!("Ignoring unknown {}: {}", PLATFORM_ENV, value),
+        }
+    }
+
+    if cfg!(target_os = "macos") {
+        return Platform::MacOs;
+    }
+
+    match std::fs::read_to_string(OS_RELEASE_FILE) {
+        Ok(release) if release.to_lowercase().contains("microsoft") => Platform::Wsl2,
+        _ => Platform::Linux,
+    }
+}
+
+/// Gets the platform the host is running on
+pub fn get_platform() -> Platform {
+    *PLATFORM.get_or_init(detect_platform)
+}
+
+/// Gets the sockets Docker Desktop may be listening on, in the order they are checked
+fn get_docker_socket_candidates(platform: Platform) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::from(DEFAULT_DOCKER_SOCKET)];
+
+    // Newer versions of Docker Desktop on macOS only create the socket in the user's home folder
+    // unless the default socket is allowed in the advanced settings
+    if platform == Platform::MacOs {
+        if let Ok(home) = std::env::var("HOME") {
+            let home = Path::new(&home);
+            candidates.push(home.join(".docker/run/docker.sock"));
+            candidates.push(home.join("Library/Containers/com.docker.docker/Data/docker.raw.sock"));
+        }
+    }
+
+    candidates
+}
+
+/// Finds the docker socket to use, or None to leave docker to use its default
+///
+/// Docker's own DOCKER_HOST setting and docker contexts always win. Otherwise, if the default socket doesn't
+/// exist, the Docker Desktop sockets are checked.
+fn find_docker_host() -> Option<String> {
+    if std::env::var("DOCKER_HOST").is_ok() {
+        return None;
+    }
+
+    let platform = get_platform();
+    if !platform.is_docker_desktop() || Path::new(DEFAULT_DOCKER_SOCKET).exists() {
+        return None;
+    }
+
+    let socket = get_docker_socket_candidates(platform).into_iter().find(|socket| socket.exists())?;
+    println!("Using docker socket {}", socket.display());
+    Some(format!("unix://{}", socket.display()))
+}
+
+/// Gets the docker socket to connect to, such as unix:///var/run/docker.sock, or None to use docker's default
+pub fn get_docker_host() -> Option<String> {
+    DOCKER_HOST.get_or_init(find_docker_host).clone()
+}
+
+/// Creates a command that runs the docker CLI, connected to the docker socket for the platform
+pub fn docker_command() -> Command {
+    let docker = std::env::var(DOCKER_PATH_ENV).unwrap_or("docker".to_string());
+    let mut command = Command::new(docker);
+
+    if let Some(docker_host) = get_docker_host() {
+        command.env("DOCKER_HOST", docker_host);
+    }
+
+    command
+}
+
+/// Gets the address ports are published on for apps, or None to publish on all addresses
+///
+/// Docker Desktop forwards published ports from its VM to the machine. Ports are bound to localhost
+/// there, as binding to all addresses can clash with ports the OS has reserved, and the host only calls
+/// apps on localhost
+pub fn get_publish_address() -> Option<String> {
+    match get_platform().is_docker_desktop() {
+        true => Some("127.0.0.1".to_string()),
+        false => None,
+    }
+}
+
+/// The docker bridge gateway, which is how containers on Linux reach the host
+pub const DOCKER_BRIDGE_GATEWAY: &str = "172.17.0.1";
+
+/// Gets the address on the host that containers can reach: the docker bridge gateway on Linux, or localhost under
+/// Docker Desktop, which forwards host.docker.internal to it
+pub fn get_container_facing_host() -> &'static str {
+    match get_platform().is_docker_desktop() {
+        true => "127.0.0.1",
+        false => DOCKER_BRIDGE_GATEWAY,
+    }
+}
+
+/// Gets if containers need host.docker.internal adding to reach the host. Docker Desktop always provides it
+pub fn needs_host_gateway() -> bool {
+    !get_platform().is_docker_desktop()
+}
+
+/// Gets the address the host calls running apps on
+pub fn get_app_host() -> String {
+    std::env::var(APP_HOST_ENV).unwrap_or(DEFAULT_APP_HOST.to_string())
+}
+
+/// Gets the base URL for the function app running on the given port. IPv6 app hosts are put in brackets
+pub fn get_app_url(port: u16) -> String {
+    let host = get_app_host();
+    match host.contains(':') && !host.starts_with('[') {
+        true => format!("http://[{}]:{}", host, port),
+        false => format!("http://{}:{}", host, port),
+    }
+}
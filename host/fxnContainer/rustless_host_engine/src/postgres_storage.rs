@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use bytes::BytesMut;
+use native_tls::{Certificate, TlsConnector};
+use postgres::error::SqlState;
+use postgres::types::{to_sql_checked, IsNull, ToSql as PgToSql, Type};
+use postgres::{Client, NoTls, Statement};
+use postgres_native_tls::MakeTlsConnector;
+use rusqlite::types::{ToSql, Value};
+use rusqlite::{ffi, Error, Result};
+use rustless_shared::{FunctionApp, FunctionAppStatus};
+use uuid::Uuid;
+
+use crate::database::{self, QueryRows, Rows, SqlBackend, StorageBackend};
+use crate::storage::{self, Storage};
+
+/// How the connection to Postgres is encrypted, set with sslmode in the connection string. The modes are the same
+/// as libpq's, so connection strings used with other Postgres tools work here too
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SslMode {
+    /// The connection isn't encrypted
+    Disable,
+
+    /// The connection is encrypted if the server supports it, without checking the server's certificate. This is
+    /// the default, and allow is treated the same
+    Prefer,
+
+    /// The connection must be encrypted, but the server's certificate isn't checked unless sslrootcert is set
+    Require,
+
+    /// The connection must be encrypted, and the server's certificate must be signed by a trusted certificate
+    /// authority, or the one in sslrootcert
+    VerifyCa,
+
+    /// The same as verify-ca, and the server's certificate must also be for the host being connected to
+    VerifyFull,
+}
+
+/// How to encrypt the connection to Postgres, read from the connection string
+#[derive(Debug, PartialEq)]
+struct TlsOptions {
+    // How the connection is encrypted
+    mode: SslMode,
+
+    // The file holding the certificate authority that signed the server's certificate, set with sslrootcert
+    root_cert: Option<String>,
+}
+
+/// A statement to run on the thread that owns a Postgres connection
+enum Command {
+    /// Runs a statement, replying with how many rows it changed
+    Execute(String, Vec<Value>),
+
+    /// Runs a query, replying with all the rows
+    Query(String, Vec<Value>),
+
+    /// Checks a statement is valid without running it
+    Prepare(String),
+}
+
+/// The reply to a command from the thread that owns a Postgres connection
+enum Reply {
+    /// How many rows a statement changed
+    Changed(usize),
+
+    /// The rows returned by a query
+    Rows(QueryRows),
+
+    /// The statement is valid
+    Prepared,
+}
+
+/// A command sent to the thread that owns a Postgres connection, with where to send the reply
+struct Request {
+    // The command to run
+    command: Command,
+
+    // Where to send the result
+    reply: Sender<Result<Reply>>,
+}
+
+/// A parameter passed to Postgres. Parameters are SQLite values, so they are converted to the type Postgres expects
+/// for each parameter, such as an integer to a boolean
+#[derive(Debug)]
+struct PgValue(Value);
+
+/// Gets if a Postgres type holds text
+fn is_text(ty: &Type) -> bool {
+    *ty == Type::TEXT || *ty == Type::VARCHAR || *ty == Type::BPCHAR || *ty == Type::NAME || *ty == Type::UNKNOWN
+}
+
+impl PgToSql for PgValue {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> std::result::Result<IsNull, Box<dyn StdError + Sync + Send>> {
+        match &self.0 {
+            Value::Null => Ok(IsNull::Yes),
+            Value::Integer(value) if *ty == Type::INT8 => PgToSql::to_sql(value, ty, out),
+            Value::Integer(value) if *ty == Type::INT4 => PgToSql::to_sql(&i32::try_from(*value)?, ty, out),
+            Value::Integer(value) if *ty == Type::INT2 => PgToSql::to_sql(&i16::try_from(*value)?, ty, out),
+            Value::Integer(value) if *ty == Type::BOOL => PgToSql::to_sql(&(*value != 0), ty, out),
+            Value::Integer(value) if *ty == Type::FLOAT8 => PgToSql::to_sql(&(*value as f64), ty, out),
+            Value::Integer(value) if is_text(ty) => PgToSql::to_sql(&value.to_string(), ty, out),
+            Value::Real(value) if *ty == Type::FLOAT8 => PgToSql::to_sql(value, ty, out),
+            Value::Real(value) if *ty == Type::FLOAT4 => PgToSql::to_sql(&(*value as f32), ty, out),
+            Value::Text(value) if is_text(ty) => PgToSql::to_sql(value, ty, out),
+            Value::Text(value) if *ty == Type::INT8 => PgToSql::to_sql(&value.parse::<i64>()?, ty, out),
+            Value::Text(value) if *ty == Type::INT4 => PgToSql::to_sql(&value.parse::<i32>()?, ty, out),
+            Value::Blob(value) if *ty == Type::BYTEA => PgToSql::to_sql(value, ty, out),
+            value => Err(format!("Can't convert {} to {}", value.data_type(), ty).into()),
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+/// Converts a Postgres error to the SQLite error the storage functions expect, so they handle errors the same way
+/// whichever backend is used. Constraint violations, such as adding a name that is already in use, keep their code
+fn to_error(e: postgres::Error) -> Error {
+    let code = match e.code() {
+        Some(code) if *code == SqlState::UNIQUE_VIOLATION => ffi::SQLITE_CONSTRAINT_UNIQUE,
+        Some(code) if code.code().starts_with("23") => ffi::SQLITE_CONSTRAINT,
+        _ => ffi::SQLITE_ERROR,
+    };
+
+    let message = match e.as_db_error() {
+        Some(db_error) => db_error.message().to_string(),
+        None => e.to_string(),
+    };
+
+    Error::SqliteFailure(ffi::Error::new(code), Some(message))
+}
+
+/// Converts SQL written for SQLite to Postgres, numbering the ? parameters as $1, $2, and so on. ?1 style parameters
+/// keep their numbers, and anything in quotes is left alone
+fn to_postgres_sql(sql: &str) -> String {
+    let mut converted = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut in_quotes = false;
+    let mut next_param = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_quotes = !in_quotes;
+                converted.push(c);
+            },
+            '?' if !in_quotes => {
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    number.push(digit);
+                }
+
+                if number.is_empty() {
+                    number = next_param.to_string();
+                    next_param += 1;
+                }
+
+                converted.push('$');
+                converted.push_str(&number);
+            },
+            _ => converted.push(c),
+        }
+    }
+
+    converted
+}
+
+/// Gets the sslmode for the given value in a connection string
+fn parse_ssl_mode(value: &str) -> Result<SslMode, String> {
+    match value {
+        "disable" => Ok(SslMode::Disable),
+        "allow" | "prefer" => Ok(SslMode::Prefer),
+        "require" => Ok(SslMode::Require),
+        "verify-ca" => Ok(SslMode::VerifyCa),
+        "verify-full" => Ok(SslMode::VerifyFull),
+        _ => Err(format!("Unknown sslmode in {}: {}", database::DATABASE_URL_ENV, value)),
+    }
+}
+
+/// Splits the options in a key=value connection string, such as host=db.example.com sslmode=require, returning the
+/// key, the value, and the text of each option. Values can be quoted, such as password='a b', and \ escapes the next
+/// character
+fn split_options(conninfo: &str) -> Vec<(String, String, &str)> {
+    let mut options = Vec::new();
+    let mut chars = conninfo.char_indices().peekable();
+
+    loop {
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let start = match chars.peek() {
+            Some((idx, _)) => *idx,
+            None => break,
+        };
+
+        let mut key = String::new();
+        while let Some((_, c)) = chars.next_if(|(_, c)| *c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        chars.next_if(|(_, c)| *c == '=');
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+        let quoted = chars.next_if(|(_, c)| *c == '\'').is_some();
+        while let Some((_, c)) = chars.next_if(|(_, c)| quoted || !c.is_whitespace()) {
+            match c {
+                '\'' if quoted => break,
+                '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                c => value.push(c),
+            }
+        }
+
+        let end = chars.peek().map(|(idx, _)| *idx).unwrap_or(conninfo.len());
+        options.push((key, value, &conninfo[start..end]));
+    }
+
+    options
+}
+
+/// Reads sslmode and sslrootcert from a connection string, either a postgres:// URL or key=value options. Returns
+/// the connection string to pass to the Postgres client along with how to encrypt the connection. The client only
+/// knows disable, prefer, and require, so verify-ca and verify-full are passed to it as require and the server's
+/// certificate is checked by the TLS connector
+fn parse_tls_options(url: &str) -> Result<(String, TlsOptions), String> {
+    let is_url = url.starts_with("postgres://") || url.starts_with("postgresql://");
+    let (base, options) = if is_url {
+        match url.split_once('?') {
+            Some((base, query)) => {
+                let options = query.split('&')
+                    .filter(|option| !option.is_empty())
+                    .map(|option| match option.split_once('=') {
+                        Some((key, value)) => (key.to_string(), value.to_string(), option),
+                        None => (option.to_string(), String::new(), option),
+                    })
+                    .collect();
+                (base, options)
+            },
+            None => (url, Vec::new()),
+        }
+    } else {
+        ("", split_options(url))
+    };
+
+    let mut mode = SslMode::Prefer;
+    let mut root_cert = None;
+    let mut kept = Vec::new();
+    for (key, value, text) in options {
+        match key.as_str() {
+            "sslmode" => mode = parse_ssl_mode(&value)?,
+            "sslrootcert" => root_cert = Some(value),
+            _ => kept.push(text),
+        }
+    }
+
+    let client_mode = match mode {
+        SslMode::Disable => "sslmode=disable",
+        SslMode::Prefer => "sslmode=prefer",
+        _ => "sslmode=require",
+    };
+    kept.push(client_mode);
+
+    let url = match is_url {
+        true => format!("{}?{}", base, kept.join("&")),
+        false => kept.join(" "),
+    };
+
+    Ok((url, TlsOptions { mode, root_cert }))
+}
+
+/// Creates the TLS connector for the connection to Postgres. As with libpq, prefer and require don't check the
+/// server's certificate unless a root certificate is given, so use verify-full to be sure of the server
+fn create_tls_connector(options: &TlsOptions) -> Result<MakeTlsConnector, String> {
+    let mut builder = TlsConnector::builder();
+
+    if let Some(root_cert) = &options.root_cert {
+        let pem = match std::fs::read(root_cert) {
+            Ok(pem) => pem,
+            Err(e) => return Err(format!("Error reading sslrootcert {}: {}", root_cert, e)),
+        };
+
+        match Certificate::from_pem(&pem) {
+            Ok(cert) => builder.add_root_certificate(cert),
+            Err(e) => return Err(format!("Error reading sslrootcert {}: {}", root_cert, e)),
+        };
+    }
+
+    match options.mode {
+        SslMode::Prefer | SslMode::Require if options.root_cert.is_none() => {
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        },
+        SslMode::Prefer | SslMode::Require | SslMode::VerifyCa => {
+            builder.danger_accept_invalid_hostnames(true);
+        },
+        SslMode::Disable | SslMode::VerifyFull => {},
+    }
+
+    match builder.build() {
+        Ok(connector) => Ok(MakeTlsConnector::new(connector)),
+        Err(e) => Err(format!("Error creating the TLS connector for Postgres: {}", e)),
+    }
+}
+
+/// Reads a column of a Postgres row as the SQLite value the storage functions expect
+fn read_value(row: &postgres::Row, idx: usize) -> Result<Value> {
+    let ty = row.columns()[idx].type_();
+    let value = if *ty == Type::INT8 {
+        row.try_get::<_, Option<i64>>(idx).map(|value| value.map(Value::Integer))
+    } else if *ty == Type::INT4 {
+        row.try_get::<_, Option<i32>>(idx).map(|value| value.map(|value| Value::Integer(value.into())))
+    } else if *ty == Type::INT2 {
+        row.try_get::<_, Option<i16>>(idx).map(|value| value.map(|value| Value::Integer(value.into())))
+    } else if *ty == Type::BOOL {
+        row.try_get::<_, Option<bool>>(idx).map(|value| value.map(|value| Value::Integer(value.into())))
+    } else if *ty == Type::FLOAT8 {
+        row.try_get::<_, Option<f64>>(idx).map(|value| value.map(Value::Real))
+    } else if *ty == Type::FLOAT4 {
+        row.try_get::<_, Option<f32>>(idx).map(|value| value.map(|value| Value::Real(value.into())))
+    } else if *ty == Type::BYTEA {
+        row.try_get::<_, Option<Vec<u8>>>(idx).map(|value| value.map(Value::Blob))
+    } else if is_text(ty) {
+        row.try_get::<_, Option<String>>(idx).map(|value| value.map(Value::Text))
+    } else {
+        return Err(Error::InvalidColumnType(idx, row.columns()[idx].name().to_string(), rusqlite::types::Type::Null));
+    };
+
+    match value {
+        Ok(value) => Ok(value.unwrap_or(Value::Null)),
+        Err(e) => Err(to_error(e)),
+    }
+}
+
+/// Runs commands on a Postgres connection. Statements are prepared once and kept for the life of the connection
+struct Worker {
+    // The connection to the database
+    client: Client,
+
+    // The statements that have been prepared, by their SQLite SQL
+    statements: HashMap<String, Statement>,
+}
+
+impl Worker {
+    /// Gets the prepared statement for the given SQL, preparing it the first time it is used
+    fn get_statement(&mut self, sql: &str) -> Result<Statement> {
+        if let Some(statement) = self.statements.get(sql) {
+            return Ok(statement.clone());
+        }
+
+        let statement = self.client.prepare(&to_postgres_sql(sql)).map_err(to_error)?;
+        self.statements.insert(sql.to_string(), statement.clone());
+        Ok(statement)
+    }
+
+    /// Runs a command
+    fn run(&mut self, command: Command) -> Result<Reply> {
+        match command {
+            Command::Execute(sql, params) => {
+                let statement = self.get_statement(&sql)?;
+                let params: Vec<PgValue> = params.into_iter().map(PgValue).collect();
+                let params: Vec<&(dyn PgToSql + Sync)> = params.iter().map(|param| param as &(dyn PgToSql + Sync)).collect();
+                match self.client.execute(&statement, &params) {
+                    Ok(changed) => Ok(Reply::Changed(changed as usize)),
+                    Err(e) => Err(to_error(e)),
+                }
+            },
+            Command::Query(sql, params) => {
+                let statement = self.get_statement(&sql)?;
+                let params: Vec<PgValue> = params.into_iter().map(PgValue).collect();
+                let params: Vec<&(dyn PgToSql + Sync)> = params.iter().map(|param| param as &(dyn PgToSql + Sync)).collect();
+                let rows = self.client.query(&statement, &params).map_err(to_error)?;
+
+                let columns = statement.columns().iter().map(|column| column.name().to_string()).collect();
+                let rows = rows.iter()
+                    .map(|row| (0..row.len()).map(|idx| read_value(row, idx)).collect::<Result<Vec<Value>>>())
+                    .collect::<Result<Vec<Vec<Value>>>>()?;
+
+                Ok(Reply::Rows(QueryRows { columns, rows }))
+            },
+            Command::Prepare(sql) => self.get_statement(&sql).map(|_| Reply::Prepared),
+        }
+    }
+}
+
+/// A connection to a Postgres database. The Postgres client runs its own async runtime, which panics if it is
+/// used from the server workers, so each connection is owned by its own thread and statements are sent to it
+pub struct PostgresStorage {
+    // Where to send commands for the connection
+    requests: Sender<Request>,
+
+    // Set once the connection to the database has been lost, so the pool can replace it
+    closed: Arc<AtomicBool>,
+}
+
+impl PostgresStorage {
+    /// Connects to the Postgres database with the given connection string. The connection is encrypted as set by
+    /// sslmode in the connection string
+    pub fn connect(url: &str) -> Result<PostgresStorage, String> {
+        let (url, tls_options) = parse_tls_options(url)?;
+        let connector = match tls_options.mode {
+            SslMode::Disable => None,
+            _ => Some(create_tls_connector(&tls_options)?),
+        };
+
+        let (requests, receiver) = mpsc::channel::<Request>();
+        let (connected, connect_result) = mpsc::channel();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let worker_closed = closed.clone();
+        let spawned = thread::Builder::new().name("postgres".to_string()).spawn(move || {
+            let client = match connector {
+                Some(connector) => Client::connect(&url, connector),
+                None => Client::connect(&url, NoTls),
+            };
+            let client = match client {
+                Ok(client) => client,
+                Err(e) => {
+                    let _ = connected.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            let _ = connected.send(Ok(()));
+
+            // The connection is closed when the last sender is dropped, which ends the loop
+            let mut worker = Worker { client, statements: HashMap::new() };
+            for request in receiver {
+                let result = worker.run(request.command);
+                worker_closed.store(worker.client.is_closed(), Ordering::Relaxed);
+                let _ = request.reply.send(result);
+            }
+        });
+
+        if let Err(e) = spawned {
+            return Err(format!("Error starting Postgres connection thread: {}", e));
+        }
+
+        match connect_result.recv() {
+            Ok(Ok(())) => Ok(PostgresStorage { requests, closed }),
+            Ok(Err(e)) => Err(format!("Error connecting to Postgres: {}", e)),
+            Err(_) => Err("Error connecting to Postgres: the connection thread stopped".to_string()),
+        }
+    }
+
+    /// Gets if the connection to the database has been lost
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Sends a command to the thread that owns the connection and waits for the reply
+    fn send(&self, command: Command) -> Result<Reply> {
+        let (reply, result) = mpsc::channel();
+        let closed = || Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_IOERR), Some("The connection to Postgres was closed".to_string()));
+
+        if self.requests.send(Request { command, reply }).is_err() {
+            return Err(closed());
+        }
+
+        match result.recv() {
+            Ok(result) => result,
+            Err(_) => Err(closed()),
+        }
+    }
+}
+
+/// Converts the parameters for a statement to values that can be sent to the thread that owns the connection
+fn to_values(params: &[&dyn ToSql]) -> Result<Vec<Value>> {
+    params.iter().map(|param| database::to_value(*param)).collect()
+}
+
+impl SqlBackend for PostgresStorage {
+    fn backend(&self) -> StorageBackend {
+        StorageBackend::Postgres
+    }
+
+    fn execute(&self, sql: &str, params: &[&dyn ToSql]) -> Result<usize> {
+        match self.send(Command::Execute(sql.to_string(), to_values(params)?))? {
+            Reply::Changed(changed) => Ok(changed),
+            _ => Ok(0),
+        }
+    }
+
+    fn query(&self, sql: &str, params: &[&dyn ToSql]) -> Result<QueryRows> {
+        match self.send(Command::Query(sql.to_string(), to_values(params)?))? {
+            Reply::Rows(rows) => Ok(rows),
+            _ => Ok(QueryRows::default()),
+        }
+    }
+
+    fn prepare(&self, sql: &str) -> Result<()> {
+        self.send(Command::Prepare(sql.to_string())).map(|_| ())
+    }
+
+    fn begin(&self) -> Result<()> {
+        // Serializable transactions fail rather than let another writer change the rows they read, the same as the
+        // write lock taken by SQLite
+        self.execute("BEGIN ISOLATION LEVEL SERIALIZABLE", &[]).map(|_| ())
+    }
+
+    fn restore(&mut self, _backup_file: &Path) -> Result<()> {
+        Err(Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), Some("Postgres databases can't be restored from a database file".to_string())))
+    }
+}
+
+impl Storage for PostgresStorage {
+    fn get_all_apps(&self) -> Result<Vec<FunctionApp>> {
+        let mut rows = Rows::new(self.query("SELECT name, id, status, created_at, namespace FROM function_apps", &[])?);
+
+        let mut response = Vec::new();
+        while let Some(row) = rows.next()? {
+            response.push(FunctionApp {
+                name: row.get(0)?,
+                id: storage::to_id(&row.get::<_, String>(1)?)?,
+                status: storage::to_status(row.get(2)?)?,
+                created_at: row.get(3)?,
+                namespace: row.get(4)?,
+            });
+        }
+
+        Ok(response)
+    }
+
+    fn get_status(&self, id: &Uuid) -> Result<FunctionAppStatus> {
+        let mut rows = Rows::new(self.query("SELECT status FROM function_apps WHERE id = $1", &[&id.to_string()])?);
+        match rows.next()? {
+            Some(row) => storage::to_status(row.get(0)?),
+            None => Err(Error::QueryReturnedNoRows),
+        }
+    }
+
+    fn set_status(&self, id: &Uuid, status: &FunctionAppStatus) -> Result<bool> {
+        let changed = self.execute(
+            "UPDATE function_apps SET status = $1 WHERE id = $2 AND status <> $1",
+            &[&(*status as u8), &id.to_string()],
+        )?;
+
+        Ok(changed > 0)
+    }
+
+    fn set_running(&self, id: &Uuid, port: u16) -> Result<bool> {
+        // The old status is read in the same statement as the update, locking the row, so when two hosts start the
+        // same app only one of them sees it start
+        let mut rows = Rows::new(self.query(
+            "UPDATE function_apps SET status = $1, port = $2, idle_stopped = 0
+             FROM (SELECT id, status FROM function_apps WHERE id = $3 FOR UPDATE) AS old
+             WHERE function_apps.id = old.id
+             RETURNING old.status",
+            &[&(FunctionAppStatus::Running as u8), &port, &id.to_string()],
+        )?);
+
+        match rows.next()? {
+            Some(row) => Ok(row.get::<_, i64>(0)? != FunctionAppStatus::Running as i64),
+            None => Err(Error::QueryReturnedNoRows),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_parameters_in_order() {
+        assert_eq!(to_postgres_sql("SELECT * FROM function_apps WHERE name = ? AND namespace = ?"), "SELECT * FROM function_apps WHERE name = $1 AND namespace = $2");
+    }
+
+    #[test]
+    fn keeps_numbered_parameters() {
+        assert_eq!(to_postgres_sql("UPDATE function_apps SET status = ?1 WHERE id = ?2 AND status != ?1"), "UPDATE function_apps SET status = $1 WHERE id = $2 AND status != $1");
+    }
+
+    #[test]
+    fn leaves_quoted_question_marks() {
+        assert_eq!(to_postgres_sql("SELECT 'why?' FROM settings WHERE name = ?"), "SELECT 'why?' FROM settings WHERE name = $1");
+        assert_eq!(to_postgres_sql("SELECT 'it''s ?' WHERE a = ?"), "SELECT 'it''s ?' WHERE a = $1");
+    }
+
+    #[test]
+    fn leaves_postgres_parameters() {
+        assert_eq!(to_postgres_sql("SELECT status FROM function_apps WHERE id = $1"), "SELECT status FROM function_apps WHERE id = $1");
+    }
+
+    #[test]
+    fn splits_quoted_options() {
+        let options = split_options("host=localhost  port = 5432 password='a b\\'c' dbname=rustless");
+        let options: Vec<(&str, &str)> = options.iter().map(|(key, value, _)| (key.as_str(), value.as_str())).collect();
+        assert_eq!(options, vec![("host", "localhost"), ("port", "5432"), ("password", "a b'c"), ("dbname", "rustless")]);
+    }
+
+    #[test]
+    fn defaults_to_prefer() {
+        let (url, options) = parse_tls_options("postgres://rustless@db.example.com/rustless").unwrap();
+        assert_eq!(url, "postgres://rustless@db.example.com/rustless?sslmode=prefer");
+        assert_eq!(options, TlsOptions { mode: SslMode::Prefer, root_cert: None });
+    }
+
+    #[test]
+    fn passes_verify_modes_to_the_client_as_require() {
+        let (url, options) = parse_tls_options("postgresql://rustless@db.example.com/rustless?connect_timeout=5&sslmode=verify-full&sslrootcert=/certs/ca.pem").unwrap();
+        assert_eq!(url, "postgresql://rustless@db.example.com/rustless?connect_timeout=5&sslmode=require");
+        assert_eq!(options, TlsOptions { mode: SslMode::VerifyFull, root_cert: Some("/certs/ca.pem".to_string()) });
+    }
+
+    #[test]
+    fn reads_key_value_connection_strings() {
+        let (url, options) = parse_tls_options("host=db.example.com password='a b' sslmode=verify-ca sslrootcert='/certs/my ca.pem'").unwrap();
+        assert_eq!(url, "host=db.example.com password='a b' sslmode=require");
+        assert_eq!(options, TlsOptions { mode: SslMode::VerifyCa, root_cert: Some("/certs/my ca.pem".to_string()) });
+    }
+
+    #[test]
+    fn treats_allow_as_prefer() {
+        let (url, options) = parse_tls_options("host=db.example.com sslmode=allow").unwrap();
+        assert_eq!(url, "host=db.example.com sslmode=prefer");
+        assert_eq!(options.mode, SslMode::Prefer);
+    }
+
+    #[test]
+    fn rejects_unknown_sslmode() {
+        assert!(parse_tls_options("postgres://db.example.com/rustless?sslmode=always").is_err());
+    }
+}
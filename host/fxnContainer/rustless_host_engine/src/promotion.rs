@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use actix_web::{web, HttpResponse};
+use futures::channel::mpsc::{self, Receiver};
+use futures::{SinkExt, StreamExt};
+use tempfile::TempDir;
+
+use rustless_shared::{ApiError, DeploymentExport};
+
+use crate::docker;
+use crate::errors;
+
+/// The name of the file in the build directory the image is saved to when it is exported, or loaded from when it is
+/// imported
+pub const EXPORT_FILE_NAME: &str = "image.tar";
+
+/// The content type of an exported deployment
+pub const EXPORT_CONTENT_TYPE: &str = "application/vnd.rustless.deployment";
+
+/// The largest line of JSON at the start of an export. The bill of materials and README are in it, so it can be
+/// larger than most requests, but anything over this isn't an export
+const MAX_EXPORT_HEADER_SIZE: usize = 16 * 1024 * 1024;
+
+/// The size of each chunk of the image read from disk as it is sent
+const EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// How many chunks of the image can be waiting to be sent before reading from disk waits for the client
+const EXPORT_CHANNEL_SIZE: usize = 16;
+
+/// Saves the image for a function app to the build directory and opens it to be sent. The file stays readable
+/// after the build directory is deleted, so the directory can be dropped once this returns
+pub fn save_image(temp_dir: &TempDir, function_app_name: &String) -> Result<File, String> {
+    let file = temp_dir.path().join(EXPORT_FILE_NAME);
+    docker::save_image(function_app_name, &file)?;
+
+    match File::open(&file) {
+        Ok(file) => Ok(file),
+        Err(e) => Err(format!("Error opening the saved image: {}", e)),
+    }
+}
+
+/// Streams an export of a deployment, as the details of the deployment on one line of JSON followed by the saved
+/// image. The image is read from disk on its own thread a chunk at a time, so it is never held in memory
+pub fn stream_export(export: &DeploymentExport, mut file: File) -> Result<Receiver<Result<web::Bytes, io::Error>>, String> {
+    let mut header = match serde_json::to_vec(export) {
+        Ok(header) => header,
+        Err(e) => return Err(format!("Error serializing the export: {}", e)),
+    };
+    header.push(b'\n');
+
+    let (mut sender, receiver) = mpsc::channel::<Result<web::Bytes, io::Error>>(EXPORT_CHANNEL_SIZE);
+    std::thread::spawn(move || {
+        if futures::executor::block_on(sender.send(Ok(web::Bytes::from(header)))).is_err() {
+            return;
+        }
+
+        let mut buffer = vec![0u8; EXPORT_CHUNK_SIZE];
+        loop {
+            let chunk = match file.read(&mut buffer) {
+                Ok(0) => return,
+                Ok(read) => Ok(web::Bytes::copy_from_slice(&buffer[..read])),
+                Err(e) => Err(e),
+            };
+
+            let failed = chunk.is_err();
+            if futures::executor::block_on(sender.send(chunk)).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Gets the response for an import that isn't a valid export
+fn invalid_export_response(message: &str) -> Box<HttpResponse> {
+    Box::new(errors::response(ApiError::InvalidExport, message))
+}
+
+/// Writes a piece of the imported image to disk, returning the error response if it can't be written
+fn write_image(image_file: &mut File, data: &[u8]) -> Result<(), Box<HttpResponse>> {
+    match image_file.write_all(data) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Box::new(errors::response(ApiError::Internal, &format!("Error writing image file: {}", e)))),
+    }
+}
+
+/// Reads an exported deployment sent to import, returning the details of the deployment and saving the image to the
+/// build directory the import runs in, or the error response if it isn't a valid export
+///
+/// The image is written to disk as it arrives, so the whole export is never held in memory
+pub async fn read_export(temp_dir: &TempDir, mut payload: web::Payload) -> Result<DeploymentExport, Box<HttpResponse>> {
+    let image_file_path = temp_dir.path().join(EXPORT_FILE_NAME);
+    let mut image_file = match File::create(&image_file_path) {
+        Ok(file) => file,
+        Err(e) => return Err(Box::new(errors::response(ApiError::Internal, &format!("Error creating image file: {}", e)))),
+    };
+
+    let mut header = Vec::new();
+    let mut export: Option<DeploymentExport> = None;
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => return Err(Box::new(errors::response(ApiError::BadRequest, &format!("Error reading export: {}", e)))),
+        };
+
+        if export.is_some() {
+            write_image(&mut image_file, &chunk)?;
+            continue;
+        }
+
+        // The details of the deployment are on the first line, and the image starts straight after it
+        let newline = match chunk.iter().position(|byte| *byte == b'\n') {
+            Some(newline) => newline,
+            None => {
+                header.extend_from_slice(&chunk);
+                if header.len() > MAX_EXPORT_HEADER_SIZE {
+                    return Err(invalid_export_response("The export doesn't start with the details of the deployment"));
+                }
+                continue;
+            }
+        };
+
+        header.extend_from_slice(&chunk[..newline]);
+        export = match serde_json::from_slice(&header) {
+            Ok(export) => Some(export),
+            Err(e) => return Err(invalid_export_response(&format!("Error reading the details of the deployment: {}", e))),
+        };
+        write_image(&mut image_file, &chunk[newline + 1..])?;
+    }
+
+    match export {
+        Some(export) => Ok(export),
+        None => Err(invalid_export_response("The export doesn't start with the details of the deployment")),
+    }
+}
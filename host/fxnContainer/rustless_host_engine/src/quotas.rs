@@ -0,0 +1,228 @@
+use rustless_shared::{FunctionAppStatus, QuotaUsage, QuotasReport};
+
+use crate::docker;
+use crate::storage;
+
+/// The environment variable containing the most apps the host can have
+const MAX_APPS_ENV: &str = "RUSTLESS_MAX_APPS";
+
+/// The environment variable containing the most disk the images for apps can use, such as 20g
+const MAX_DISK_ENV: &str = "RUSTLESS_MAX_DISK";
+
+/// The environment variable containing the most memory running apps can use, such as 4g
+const MAX_MEMORY_ENV: &str = "RUSTLESS_MAX_MEMORY";
+
+/// The environment variable containing the percentage of each limit that sends warnings
+const WARN_PERCENT_ENV: &str = "RUSTLESS_QUOTA_WARN_PERCENT";
+
+/// The percentage of each limit that sends warnings if the environment variable isn't set
+const DEFAULT_WARN_PERCENT: u8 = 80;
+
+/// A quota on the resources apps use. Each quota has a hard limit that rejects adding, building, or starting apps,
+/// and a soft limit below it that sends warnings so the hard limit doesn't come as a surprise
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Quota {
+    /// The number of apps on the host, checked when an app is added
+    Apps,
+
+    /// The disk used by the images for apps, checked when an app is built
+    Disk,
+
+    /// The memory used by running apps, checked when an app is started
+    Memory,
+}
+
+impl Quota {
+    /// Gets the name of the quota
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quota::Apps => "apps",
+            Quota::Disk => "disk",
+            Quota::Memory => "memory",
+        }
+    }
+
+    /// Gets the environment variable the hard limit is set with
+    fn limit_env(&self) -> &'static str {
+        match self {
+            Quota::Apps => MAX_APPS_ENV,
+            Quota::Disk => MAX_DISK_ENV,
+            Quota::Memory => MAX_MEMORY_ENV,
+        }
+    }
+
+    /// Gets the hard limit, or None if there isn't one. Disk and memory can be given with a k, m, g, or t suffix
+    pub fn get_hard_limit(&self) -> Option<u64> {
+        let value = std::env::var(self.limit_env()).ok()?;
+        let limit = match self {
+            Quota::Apps => value.trim().parse().ok(),
+            Quota::Disk | Quota::Memory => parse_size(&value),
+        };
+
+        if limit.is_none() {
+            println!("Ignoring invalid {}: {}", self.limit_env(), value);
+        }
+        limit
+    }
+
+    /// Measures how much of the quota is used
+    pub fn get_usage(&self) -> Result<u64, String> {
+        let apps = storage::get_all_apps()?;
+
+        match self {
+            Quota::Apps => Ok(apps.len() as u64),
+            Quota::Disk => {
+                let mut total = 0;
+                for app in apps.iter() {
+                    total += docker::get_image_size(&app.name)?;
+                }
+                Ok(total)
+            },
+            Quota::Memory => {
+                let mut total = 0;
+                for app in apps.iter().filter(|app| app.status as u8 == FunctionAppStatus::Running as u8) {
+                    total += docker::get_memory_usage(&app.name)?;
+                }
+                Ok(total)
+            },
+        }
+    }
+
+    /// Gets if the usage leaves no room for the operation the quota is checked for. A new app adds one to the
+    /// count, but the size of a new image or the memory a new app will use isn't known until it is built or
+    /// running, so those are checked against what is already used
+    fn is_exceeded(&self, used: u64, hard_limit: u64) -> bool {
+        match self {
+            Quota::Apps => used + 1 > hard_limit,
+            Quota::Disk | Quota::Memory => used >= hard_limit,
+        }
+    }
+
+    /// Formats an amount of the quota, such as 3 apps or 1.5 GiB
+    fn format_amount(&self, amount: u64) -> String {
+        match self {
+            Quota::Apps => format!("{} apps", amount),
+            Quota::Disk | Quota::Memory => format_size(amount),
+        }
+    }
+}
+
+/// Gets the percentage of each hard limit that sends warnings
+pub fn get_warn_percent() -> u8 {
+    match std::env::var(WARN_PERCENT_ENV) {
+        Ok(value) => match value.parse::<u8>() {
+            Ok(percent) if percent <= 100 => percent,
+            _ => {
+                println!("Ignoring invalid {}: {}", WARN_PERCENT_ENV, value);
+                DEFAULT_WARN_PERCENT
+            }
+        },
+        Err(_) => DEFAULT_WARN_PERCENT,
+    }
+}
+
+/// Gets the soft limit for a hard limit, which is the warning percentage of it
+fn get_soft_limit(hard_limit: u64) -> u64 {
+    hard_limit * get_warn_percent() as u64 / 100
+}
+
+/// Parses a size such as 512m or 20g into bytes. Suffixes are binary, so 1k is 1024 bytes
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim().to_lowercase();
+    let value = value.strip_suffix('b').unwrap_or(&value);
+
+    let (number, multiplier) = match value.chars().last()? {
+        'k' => (&value[..value.len() - 1], 1024),
+        'm' => (&value[..value.len() - 1], 1024 * 1024),
+        'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        't' => (&value[..value.len() - 1], 1024 * 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+
+    number.trim().parse::<u64>().ok().map(|number| number * multiplier)
+}
+
+/// Formats a number of bytes in the largest unit that keeps it above 1, such as 1.5 GiB
+fn format_size(bytes: u64) -> String {
+    let units = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} bytes", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
+
+/// Checks there is room in a quota before an app is added, built, or started. Returns an error if the hard limit
+/// has been reached, or a warning to send if the usage is over the soft limit.
+///
+/// If the usage can't be measured, such as when docker can't be reached, the operation is allowed, as it will
+/// fail on its own if docker is down
+pub fn check_quota(quota: Quota) -> Result<Option<String>, String> {
+    let hard_limit = match quota.get_hard_limit() {
+        Some(limit) => limit,
+        None => return Ok(None),
+    };
+
+    let used = match quota.get_usage() {
+        Ok(used) => used,
+        Err(e) => {
+            println!("Error measuring {} quota usage: {}", quota.name(), e);
+            return Ok(None);
+        }
+    };
+
+    if quota.is_exceeded(used, hard_limit) {
+        return Err(format!(
+            "The {} quota has been reached: {} used of {}. Free some up or raise {}",
+            quota.name(), quota.format_amount(used), quota.format_amount(hard_limit), quota.limit_env()
+        ));
+    }
+
+    // Warn about the usage once the operation has happened, so adding the app that reaches the soft limit warns
+    let used = match quota {
+        Quota::Apps => used + 1,
+        Quota::Disk | Quota::Memory => used,
+    };
+
+    match used >= get_soft_limit(hard_limit) {
+        true => {
+            let warning = format!(
+                "The {} quota is {}% used: {} of {}",
+                quota.name(), used * 100 / hard_limit.max(1), quota.format_amount(used), quota.format_amount(hard_limit)
+            );
+            println!("{}", warning);
+            Ok(Some(warning))
+        },
+        false => Ok(None),
+    }
+}
+
+/// Gets the quotas on the host and how much of each is used
+pub fn get_report() -> QuotasReport {
+    let quotas = [Quota::Apps, Quota::Disk, Quota::Memory].iter().map(|quota| {
+        let (used, error) = match quota.get_usage() {
+            Ok(used) => (used, None),
+            Err(e) => (0, Some(e)),
+        };
+
+        let hard_limit = quota.get_hard_limit();
+        QuotaUsage {
+            name: quota.name().to_string(),
+            used,
+            soft_limit: hard_limit.map(get_soft_limit),
+            hard_limit,
+            error,
+        }
+    }).collect();
+
+    QuotasReport {
+        warn_percent: get_warn_percent(),
+        quotas,
+    }
+}
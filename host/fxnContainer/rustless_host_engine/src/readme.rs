@@ -0,0 +1,43 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The names a README can have next to the Cargo.toml, in the order they are looked for. Names are matched
+/// ignoring case, so readme.md is found as well as README.md
+const README_FILES: [&str; 3] = ["README.md", "README.txt", "README"];
+
+/// The largest README that is stored. Larger READMEs are cut off at this size
+const MAX_README_SIZE: usize = 256 * 1024;
+
+/// Finds the README in the code for an app, matching the name ignoring case
+fn find_readme_file(code_dir: &Path) -> Option<PathBuf> {
+    let entries: Vec<PathBuf> = fs::read_dir(code_dir).ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+
+    README_FILES.iter().find_map(|readme_file| {
+        entries.iter()
+            .find(|path| path.file_name().is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(readme_file)))
+            .cloned()
+    })
+}
+
+/// Reads the README from the code for an app, or None if the code doesn't have one
+///
+/// Apps describe how to call them by adding a README.md, README.txt, or README next to their Cargo.toml. READMEs
+/// over 256KiB are cut off, and anything that isn't UTF-8 is replaced
+pub fn read_readme(code_dir: &Path) -> Result<Option<String>, String> {
+    let readme_file = match find_readme_file(code_dir) {
+        Some(readme_file) => readme_file,
+        None => return Ok(None),
+    };
+
+    let mut readme = match fs::read(&readme_file) {
+        Ok(readme) => readme,
+        Err(e) => return Err(format!("Error reading {}: {}", readme_file.display(), e)),
+    };
+
+    readme.truncate(MAX_README_SIZE);
+    Ok(Some(String::from_utf8_lossy(&readme).to_string()))
+}
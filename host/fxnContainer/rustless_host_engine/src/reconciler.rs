@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use std::time::Duration;
+
+use rustless_shared::{FunctionApp, FunctionAppStatus};
+
+use crate::build_queue;
+use crate::docker;
+use crate::leases;
+use crate::storage;
+
+/// How often the stored status of every app is checked against the containers docker is running
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The lease that makes sure only one host process reconciles the stored status with docker
+const RECONCILE_LEASE: &str = "status-reconciler";
+
+/// Gets if the stored status of an app can be changed by the reconciler. Running apps are already right, and the
+/// build sets the status of apps that are building when it finishes
+fn is_reconcilable(app: &FunctionApp) -> bool {
+    !matches!(app.status, FunctionAppStatus::Running | FunctionAppStatus::Building) && !build_queue::is_queued(&app.id)
+}
+
+/// Marks an app as running on the port its container is published on, as docker is running it
+fn mark_running(app: &FunctionApp, port: u16) {
+    let conn = match storage::create_connection_for_app(&app.id) {
+        Ok(conn) => conn,
+        Err(e) => {
+            println!("Error reconciling the status of {}: {}", app.name, e);
+            return;
+        }
+    };
+
+    // Check again in case the app was started or stopped since the apps were read
+    match storage::get_function_app_stored_status(&conn, &app.id) {
+        Ok(FunctionAppStatus::Running) | Ok(FunctionAppStatus::Building) => return,
+        Ok(_) => {},
+        Err(e) => {
+            println!("Error reconciling the status of {}: {}", app.name, e);
+            return;
+        }
+    }
+
+    match storage::set_function_app_running(&conn, &app.id, port) {
+        Ok(_) => println!("{} is running in docker on port {}, so it is marked as running", app.name, port),
+        Err(e) => println!("Error reconciling the status of {}: {}", app.name, e),
+    }
+}
+
+/// Stops a container left running for a function app that no longer exists
+fn stop_orphan(container: &docker::AppContainer) {
+    match docker::stop_containers(std::slice::from_ref(&container.id), docker::get_stop_grace_period()) {
+        Ok(_) => println!("Stopped container {} for {}, as there is no function app with that name", container.id, container.app),
+        Err(e) => println!("Error stopping container {} for {}: {}", container.id, container.app, e),
+    }
+}
+
+/// Checks the containers docker is running against the stored status of every app, marking apps with a running
+/// container as running and stopping containers for apps that don't exist
+///
+/// A container is only acted on if it is out of line on two checks in a row, so an app part way through being
+/// started or deleted isn't touched
+fn reconcile(suspects: &mut HashSet<String>) {
+    // If docker can't be reached nothing is known about the containers, so nothing is changed
+    let containers = match docker::get_app_containers() {
+        Ok(containers) => containers,
+        Err(e) => {
+            println!("Error reconciling app status: {}", e);
+            return;
+        }
+    };
+
+    let apps = match storage::get_all_apps() {
+        Ok(apps) => apps,
+        Err(e) => {
+            println!("Error reconciling app status: {}", e);
+            return;
+        }
+    };
+    let apps: HashMap<&String, &FunctionApp> = apps.iter().map(|app| (&app.name, app)).collect();
+
+    let mut still_suspect = HashSet::new();
+
+    for container in containers.iter() {
+        let app = apps.get(&container.app);
+
+        // Containers for running apps, or apps that are building, are where they should be
+        if let Some(app) = app {
+            if !is_reconcilable(app) {
+                continue;
+            }
+        }
+
+        if !suspects.contains(&container.id) {
+            still_suspect.insert(container.id.to_string());
+            continue;
+        }
+
+        match (app, container.port) {
+            (Some(app), Some(port)) => mark_running(app, port),
+            (Some(app), None) => println!("{} is running in docker but isn't published on a port, so can't be marked as running", app.name),
+            (None, _) => stop_orphan(container),
+        }
+    }
+
+    *suspects = still_suspect;
+}
+
+/// Starts reconciling the stored status of apps with docker, on a background thread for the life of the host, so a
+/// lost status write or a container left behind doesn't leave the database wrong. Apps marked as running whose
+/// container has gone are found by the crash checker. If more than one host process shares the database, only the
+/// one holding the lease reconciles
+pub fn start() {
+    thread::spawn(|| {
+        let mut suspects = HashSet::new();
+
+        loop {
+            thread::sleep(RECONCILE_INTERVAL);
+
+            // Another host process is reconciling, so forget what this one saw in case it takes over later
+            if !leases::is_leader(RECONCILE_LEASE) {
+                suspects.clear();
+                continue;
+            }
+
+            reconcile(&mut suspects);
+        }
+    });
+}
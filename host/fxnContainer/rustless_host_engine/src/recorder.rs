@@ -0,0 +1,244 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use actix_web::HttpRequest;
+use futures::StreamExt;
+use uuid::Uuid;
+
+use rustless_shared::{RecordedRequest, RedactionRules};
+
+use crate::gateway::{AppResponse, RequestBody, ResponseBody};
+use crate::redaction;
+
+/// The header the CLI adds to replayed requests
+pub const REPLAY_HEADER: &str = "x-rustless-replay";
+
+/// The most requests that can be recorded for a function app, as they are all kept in memory
+const MAX_CAPACITY: usize = 1000;
+
+/// The most bytes of each request and response body that are recorded. Longer bodies are truncated
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// The recent requests for each function app that has recording turned on, oldest first.
+/// These are only kept in memory, so are lost when the host restarts
+static RECORDINGS: OnceLock<Mutex<HashMap<Uuid, VecDeque<RecordedRequest>>>> = OnceLock::new();
+
+/// Gets the recorded requests for all the function apps
+fn get_recordings() -> &'static Mutex<HashMap<Uuid, VecDeque<RecordedRequest>>> {
+    RECORDINGS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Checks a recording capacity is valid before it is saved
+pub fn validate_capacity(capacity: usize) -> Result<(), String> {
+    if capacity == 0 {
+        return Err("The capacity must be at least 1".to_string());
+    }
+
+    if capacity > MAX_CAPACITY {
+        return Err(format!("The capacity can be at most {}", MAX_CAPACITY));
+    }
+
+    Ok(())
+}
+
+/// Redacts a body, then truncates it to the most bytes that are recorded, returning the base64 encoded body and
+/// whether it was truncated. The body is redacted first, as truncated JSON can't be read. Bodies that were already cut
+/// short can't be redacted, so they are left out when the rules redact JSON fields, rather than keeping something that
+/// should have been redacted
+fn encode_body(body: &[u8], cut_short: bool, rules: &RedactionRules) -> (String, bool) {
+    if cut_short && !rules.json_fields.is_empty() {
+        return (String::new(), true);
+    }
+
+    let redacted = redaction::redact_body(body, rules);
+    let truncated = cut_short || redacted.len() > MAX_BODY_BYTES;
+    (base64::encode(&redacted[..redacted.len().min(MAX_BODY_BYTES)]), truncated)
+}
+
+/// Keeps a recorded request, dropping the oldest requests to stay within the capacity
+fn keep(id: &Uuid, capacity: usize, record: RecordedRequest) {
+    if let Ok(mut recordings) = get_recordings().lock() {
+        let requests = recordings.entry(*id).or_default();
+        requests.push_back(record);
+
+        while requests.len() > capacity {
+            requests.pop_front();
+        }
+    }
+}
+
+/// A recorded request, waiting for the response from the app
+pub struct PendingRecord {
+    // The function app the request was to
+    id: Uuid,
+
+    // How many requests to keep for the app
+    capacity: usize,
+
+    // What to redact from the response
+    rules: RedactionRules,
+
+    // The request, with an empty response
+    record: RecordedRequest,
+}
+
+/// Starts recording a request to a function app. The headers and body are redacted with the given rules before they
+/// are kept, and long bodies are truncated. Only the start of streamed bodies is read, so the rest is recorded as
+/// truncated
+pub fn record_request(id: &Uuid, capacity: usize, rules: &RedactionRules, req: &HttpRequest, body: &RequestBody, route: &str) -> PendingRecord {
+    // Capacities saved before they were limited are held to the maximum
+    let capacity = capacity.min(MAX_CAPACITY);
+
+    let (body, body_streamed) = body.start();
+    let (body, body_truncated) = encode_body(&body, body_streamed, rules);
+    let record = RecordedRequest {
+        timestamp: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+        method: req.method().to_string(),
+        route: route.to_string(),
+        query: req.query_string().to_string(),
+        headers: redaction::redact_headers(req.headers().iter().map(|(name, value)| (name.as_str(), value.as_bytes())), rules),
+        body,
+        body_truncated,
+        response_status: 0,
+        response_headers: Vec::new(),
+        response_body: String::new(),
+        response_body_truncated: false,
+    };
+
+    PendingRecord { id: *id, capacity, rules: rules.clone(), record }
+}
+
+impl PendingRecord {
+    /// Records the response to the request. Buffered responses are recorded straight away. Streamed responses are
+    /// copied as they are sent to the caller, up to the most bytes that are recorded, and recorded once the stream
+    /// ends or the caller goes away, so recording never holds a streamed response back
+    pub fn finish(mut self, mut response: AppResponse) -> AppResponse {
+        self.record.response_status = response.status;
+        self.record.response_headers = redaction::redact_headers(response.headers.iter().map(|(name, value)| (name.as_str(), value.as_slice())), &self.rules);
+
+        response.body = match response.body {
+            ResponseBody::Buffered(body, guard) => {
+                (self.record.response_body, self.record.response_body_truncated) = encode_body(&body, false, &self.rules);
+                keep(&self.id, self.capacity, self.record);
+                ResponseBody::Buffered(body, guard)
+            },
+            ResponseBody::Streamed(stream) => {
+                let mut copy = ResponseCopy { pending: Some(self), body: Vec::new(), cut_short: false };
+                ResponseBody::Streamed(Box::pin(stream.map(move |chunk| {
+                    if let Ok(chunk) = &chunk {
+                        copy.push(chunk);
+                    }
+                    chunk
+                })))
+            },
+        };
+
+        response
+    }
+}
+
+/// The start of a streamed response, copied as it is sent so it can be recorded. The request is kept when the copy
+/// is dropped with the stream
+struct ResponseCopy {
+    // The request the response is for
+    pending: Option<PendingRecord>,
+
+    // The start of the response body
+    body: Vec<u8>,
+
+    // Whether there was more of the body than was copied
+    cut_short: bool,
+}
+
+impl ResponseCopy {
+    /// Copies a chunk of the body, up to the most bytes that are recorded
+    fn push(&mut self, chunk: &[u8]) {
+        let space = MAX_BODY_BYTES - self.body.len();
+        self.cut_short |= chunk.len() > space;
+        self.body.extend_from_slice(&chunk[..chunk.len().min(space)]);
+    }
+}
+
+impl Drop for ResponseCopy {
+    fn drop(&mut self) {
+        if let Some(mut pending) = self.pending.take() {
+            (pending.record.response_body, pending.record.response_body_truncated) = encode_body(&self.body, self.cut_short, &pending.rules);
+            keep(&pending.id, pending.capacity, pending.record);
+        }
+    }
+}
+
+/// Gets up to the given number of the most recent requests recorded for a function app, oldest first
+pub fn get_recent_requests(id: &Uuid, last: usize) -> Vec<RecordedRequest> {
+    match get_recordings().lock() {
+        Ok(recordings) => match recordings.get(id) {
+            Some(requests) => requests.iter().skip(requests.len().saturating_sub(last)).cloned().collect(),
+            None => Vec::new(),
+        },
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Removes all the requests recorded for a function app
+pub fn clear(id: &Uuid) {
+    if let Ok(mut recordings) = get_recordings().lock() {
+        recordings.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets redaction rules that redact a JSON field
+    fn json_rules() -> RedactionRules {
+        RedactionRules { headers: Vec::new(), json_fields: vec!["password".to_string()] }
+    }
+
+    #[test]
+    fn redacts_whole_bodies() {
+        let (body, truncated) = encode_body(br#"{"password":"secret"}"#, false, &json_rules());
+
+        assert!(!truncated);
+        assert!(!String::from_utf8(base64::decode(body).unwrap()).unwrap().contains("secret"));
+    }
+
+    #[test]
+    fn truncates_long_bodies() {
+        let (body, truncated) = encode_body(&vec![b'a'; MAX_BODY_BYTES + 1], false, &RedactionRules::default());
+
+        assert!(truncated);
+        assert_eq!(base64::decode(body).unwrap().len(), MAX_BODY_BYTES);
+    }
+
+    #[test]
+    fn keeps_bodies_cut_short_without_json_fields_to_redact() {
+        let (body, truncated) = encode_body(b"partial", true, &RedactionRules::default());
+
+        assert!(truncated);
+        assert_eq!(base64::decode(body).unwrap(), b"partial");
+    }
+
+    #[test]
+    fn leaves_out_bodies_cut_short_with_json_fields_to_redact() {
+        let (body, truncated) = encode_body(br#"{"password":"sec"#, true, &json_rules());
+
+        assert!(truncated);
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn copies_the_start_of_streamed_responses() {
+        let mut copy = ResponseCopy { pending: None, body: Vec::new(), cut_short: false };
+        copy.push(&vec![b'a'; MAX_BODY_BYTES - 1]);
+        assert!(!copy.cut_short);
+
+        copy.push(b"bc");
+        assert!(copy.cut_short);
+        assert_eq!(copy.body.len(), MAX_BODY_BYTES);
+
+        copy.push(b"d");
+        assert_eq!(copy.body.len(), MAX_BODY_BYTES);
+    }
+}
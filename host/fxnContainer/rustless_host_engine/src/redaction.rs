@@ -0,0 +1,190 @@
+use std::borrow::Cow;
+
+use actix_web::http::header::HeaderName;
+use serde_json::Value;
+
+use rustless_shared::{RedactionRules, REDACTED_VALUE};
+
+/// Headers that can contain credentials or personal data, so are always redacted
+const ALWAYS_REDACTED_HEADERS: [&str; 6] = ["authorization", "proxy-authorization", "cookie", "set-cookie", "x-api-key", "x-auth-token"];
+
+/// The most JSON fields an app can redact, as every mirrored or recorded body is searched for each of them
+const MAX_JSON_FIELDS: usize = 100;
+
+/// The field in a JSON path that matches any field or array item
+const WILDCARD_FIELD: &str = "*";
+
+/// Checks redaction rules are valid before they are saved
+pub fn validate_rules(rules: &RedactionRules) -> Result<(), String> {
+    if let Some(header) = rules.headers.iter().find(|header| HeaderName::from_bytes(header.as_bytes()).is_err()) {
+        return Err(format!("Invalid header name: {}", header));
+    }
+
+    if rules.json_fields.len() > MAX_JSON_FIELDS {
+        return Err(format!("At most {} JSON fields can be redacted", MAX_JSON_FIELDS));
+    }
+
+    if let Some(path) = rules.json_fields.iter().find(|path| path.split('.').any(|field| field.is_empty())) {
+        return Err(format!("Invalid JSON field path {}: fields are separated by a single ., such as user.password", path));
+    }
+
+    Ok(())
+}
+
+/// Copies headers, replacing the values of any that are always redacted or are redacted by the rules
+pub fn redact_headers<'a>(headers: impl Iterator<Item = (&'a str, &'a [u8])>, rules: &RedactionRules) -> Vec<(String, String)> {
+    headers
+        .map(|(name, value)| {
+            let redacted = ALWAYS_REDACTED_HEADERS.iter().any(|header| header.eq_ignore_ascii_case(name))
+                || rules.headers.iter().any(|header| header.eq_ignore_ascii_case(name));
+
+            let value = match redacted {
+                true => REDACTED_VALUE.to_string(),
+                false => String::from_utf8_lossy(value).to_string(),
+            };
+
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// Replaces the values at a path of fields in a JSON value, returning if anything was replaced
+fn redact_path(value: &mut Value, path: &[&str]) -> bool {
+    let (field, rest) = match path.split_first() {
+        Some(split) => split,
+        None => {
+            *value = Value::String(REDACTED_VALUE.to_string());
+            return true;
+        }
+    };
+
+    // Every match is redacted, so a * redacts the field in all the items, not just the first
+    let mut redacted = false;
+    match value {
+        Value::Object(fields) => {
+            for (_, value) in fields.iter_mut().filter(|(name, _)| *field == WILDCARD_FIELD || name == field) {
+                redacted |= redact_path(value, rest);
+            }
+        },
+        Value::Array(items) if *field == WILDCARD_FIELD => {
+            for item in items.iter_mut() {
+                redacted |= redact_path(item, rest);
+            }
+        },
+        Value::Array(items) => {
+            if let Some(item) = field.parse::<usize>().ok().and_then(|index| items.get_mut(index)) {
+                redacted = redact_path(item, rest);
+            }
+        },
+        _ => {},
+    }
+
+    redacted
+}
+
+/// Redacts the fields at the JSON paths in the rules from a body. Bodies that aren't JSON, or don't have any of the
+/// fields, are returned as they are
+pub fn redact_body<'a>(body: &'a [u8], rules: &RedactionRules) -> Cow<'a, [u8]> {
+    if rules.json_fields.is_empty() || body.is_empty() {
+        return Cow::Borrowed(body);
+    }
+
+    let mut json: Value = match serde_json::from_slice(body) {
+        Ok(json) => json,
+        Err(_) => return Cow::Borrowed(body),
+    };
+
+    let mut redacted = false;
+    for path in rules.json_fields.iter() {
+        let fields: Vec<&str> = path.split('.').collect();
+        redacted |= redact_path(&mut json, &fields);
+    }
+
+    match redacted {
+        true => match serde_json::to_vec(&json) {
+            Ok(body) => Cow::Owned(body),
+            Err(_) => Cow::Owned(REDACTED_VALUE.as_bytes().to_vec()),
+        },
+        false => Cow::Borrowed(body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets redaction rules for the given headers and JSON fields
+    fn rules(headers: &[&str], json_fields: &[&str]) -> RedactionRules {
+        RedactionRules {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            json_fields: json_fields.iter().map(|field| field.to_string()).collect(),
+        }
+    }
+
+    /// Redacts a JSON body, returning it parsed
+    fn redact_json(body: Value, rules: &RedactionRules) -> Value {
+        serde_json::from_slice(&redact_body(&serde_json::to_vec(&body).unwrap(), rules)).unwrap()
+    }
+
+    #[test]
+    fn always_redacts_credential_headers() {
+        let headers = [("Authorization", b"Bearer secret".as_slice()), ("Cookie", b"session=secret"), ("Accept", b"*/*")];
+        let redacted = redact_headers(headers.into_iter(), &RedactionRules::default());
+
+        assert_eq!(redacted, vec![
+            ("Authorization".to_string(), REDACTED_VALUE.to_string()),
+            ("Cookie".to_string(), REDACTED_VALUE.to_string()),
+            ("Accept".to_string(), "*/*".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn redacts_the_headers_in_the_rules_in_any_case() {
+        let headers = [("X-Customer-Email", b"someone@example.com".as_slice())];
+        let redacted = redact_headers(headers.into_iter(), &rules(&["x-customer-email"], &[]));
+
+        assert_eq!(redacted, vec![("X-Customer-Email".to_string(), REDACTED_VALUE.to_string())]);
+    }
+
+    #[test]
+    fn redacts_nested_json_fields() {
+        let body = serde_json::json!({ "user": { "name": "someone", "password": "secret" } });
+        let redacted = redact_json(body, &rules(&[], &["user.password"]));
+
+        assert_eq!(redacted, serde_json::json!({ "user": { "name": "someone", "password": REDACTED_VALUE } }));
+    }
+
+    #[test]
+    fn redacts_every_match_of_a_wildcard() {
+        let body = serde_json::json!({ "cards": [{ "number": "1111" }, { "number": "2222" }] });
+        let redacted = redact_json(body, &rules(&[], &["cards.*.number"]));
+
+        assert_eq!(redacted, serde_json::json!({ "cards": [{ "number": REDACTED_VALUE }, { "number": REDACTED_VALUE }] }));
+    }
+
+    #[test]
+    fn redacts_array_items_by_index() {
+        let body = serde_json::json!({ "tokens": ["first", "second"] });
+        let redacted = redact_json(body, &rules(&[], &["tokens.1"]));
+
+        assert_eq!(redacted, serde_json::json!({ "tokens": ["first", REDACTED_VALUE] }));
+    }
+
+    #[test]
+    fn leaves_bodies_without_the_fields_as_they_are() {
+        let body = br#"{ "name": "someone" }"#;
+
+        assert_eq!(redact_body(body, &rules(&[], &["password"])), Cow::Borrowed(body.as_slice()));
+        assert_eq!(redact_body(b"not json", &rules(&[], &["password"])), Cow::Borrowed(b"not json".as_slice()));
+    }
+
+    #[test]
+    fn refuses_invalid_rules() {
+        assert!(validate_rules(&rules(&["bad header"], &[])).is_err());
+        assert!(validate_rules(&rules(&[], &["user..password"])).is_err());
+        assert!(validate_rules(&rules(&["x-email"], &["user.password"])).is_ok());
+
+        let too_many: Vec<String> = (0..=MAX_JSON_FIELDS).map(|index| format!("field{}", index)).collect();
+        assert!(validate_rules(&RedactionRules { headers: Vec::new(), json_fields: too_many }).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use uuid::Uuid;
+
+use crate::broker::{self, BrokerRequest};
+use crate::build_logs;
+use crate::build_queue;
+use crate::docker::{self, DockerProcess};
+use crate::events;
+use crate::phases::DeployPhase;
+use crate::platform;
+use crate::storage;
+
+/// The environment variable containing the registry built images are pushed to, such as
+/// registry.example.com/rustless. Images are only kept in the local docker if this isn't set
+const REGISTRY_ENV: &str = "RUSTLESS_IMAGE_REGISTRY";
+
+/// How many times a push is tried before the build fails, if it keeps failing with errors that may go away
+const PUSH_ATTEMPTS: u32 = 3;
+
+/// How long to wait before trying a failed push again. This is multiplied by the number of attempts so far
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// How often a running push checks if it has been cancelled or stopped making progress
+const PUSH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many characters of the content hash are used to tag pushed images
+const TAG_LENGTH: usize = 12;
+
+/// Parts of push errors that mean the registry or the network had a problem that may go away, so the push is
+/// tried again. Other errors, such as being denied access, fail the build straight away
+const TRANSIENT_ERRORS: [&str; 13] = [
+    "timeout",
+    "timed out",
+    "connection reset",
+    "connection refused",
+    "broken pipe",
+    "unexpected eof",
+    "tls handshake",
+    "no such host",
+    "toomanyrequests",
+    "500 internal server error",
+    "502 bad gateway",
+    "503 service unavailable",
+    "504 gateway timeout",
+];
+
+/// Gets the registry built images are pushed to, or None if images aren't pushed
+pub fn get_registry() -> Option<String> {
+    match std::env::var(REGISTRY_ENV) {
+        Ok(registry) if !registry.trim().is_empty() => Some(registry.trim().trim_end_matches('/').to_string()),
+        _ => None,
+    }
+}
+
+/// Gets the reference an image is pushed to, tagged with the start of the hash of the code it was built from
+fn get_image_ref(registry: &str, function_app_name: &str, content_hash: &str) -> String {
+    let tag: String = content_hash.chars().take(TAG_LENGTH).collect();
+    format!("{}/{}:{}", registry, docker::get_container_tag(function_app_name), tag)
+}
+
+/// Gets if a push error may go away if the push is tried again
+fn is_transient_error(error: &str) -> bool {
+    let error = error.to_lowercase();
+    TRANSIENT_ERRORS.iter().any(|transient| error.contains(transient))
+}
+
+/// The layers of an image seen in the push output, used to report how far the push has got
+#[derive(Default)]
+struct PushProgress {
+    // The IDs of the layers the push has started on
+    layers: HashSet<String>,
+
+    // The IDs of the layers that are in the registry, either pushed or already there
+    done: HashSet<String>,
+}
+
+impl PushProgress {
+    /// Updates the progress from a line of push output, such as 5f70bf18a086: Pushed. Returns true if a layer
+    /// finished
+    fn update(&mut self, line: &str) -> bool {
+        let (layer, status) = match line.split_once(": ") {
+            Some((layer, status)) if !layer.is_empty() && layer.chars().all(|c| c.is_ascii_hexdigit()) => (layer, status),
+            _ => return false,
+        };
+
+        self.layers.insert(layer.to_string());
+
+        let finished = status.starts_with("Pushed") || status.starts_with("Layer already exists") || status.starts_with("Mounted from");
+        finished && self.done.insert(layer.to_string())
+    }
+
+    /// Gets the progress as shown in deploy progress events
+    fn message(&self) -> String {
+        format!("Pushed {} of {} layers", self.done.len(), self.layers.len())
+    }
+}
+
+/// Reads the push output line by line on a background thread, logging each line and adding it to the build log,
+/// and noting when the push last made progress. The output is read as it is written, so the push never blocks on a
+/// full pipe
+fn read_push_progress<R: Read + Send + 'static>(stream: Option<R>, id: Uuid, last_progress: Arc<Mutex<SystemTime>>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let stream = match stream {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        // Progress events need a connection to find the namespace of the app. The push still runs without one
+        let conn = storage::create_connection_for_app(&id).ok();
+        let mut progress = PushProgress::default();
+
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            println!("Push output: {}", line);
+            build_logs::append(&id, &line);
+
+            if let Ok(mut last_progress) = last_progress.lock() {
+                *last_progress = SystemTime::now();
+            }
+
+            if progress.update(&line) {
+                if let Some(conn) = &conn {
+                    events::publish_deploy_progress(conn, &id, "pushing", None, Some(progress.message()));
+                }
+            }
+        }
+    })
+}
+
+/// Tags the built image for a function app with the reference it is pushed to
+pub fn tag_image(function_app_name: &String, image_ref: &str) -> Result<(), String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::TagImage { app: function_app_name.to_string(), image_ref: image_ref.to_string() });
+    }
+
+    let output = platform::docker_command()
+        .args(["tag", &docker::get_container_tag(function_app_name), image_ref])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!("Error tagging image: {}", String::from_utf8_lossy(&output.stderr).trim())),
+        Err(e) => Err(format!("Error tagging image: {}", e)),
+    }
+}
+
+/// Checks docker can log in to a registry with the credentials it has stored, so pushes won't be refused. Returns a
+/// warning rather than an error if no credentials are stored, as registries that don't need them still work
+pub fn check_credentials(registry: &str) -> Result<Option<String>, String> {
+    if broker::is_enabled() {
+        return broker::call(BrokerRequest::CheckRegistry { registry: registry.to_string() });
+    }
+
+    // docker login takes the registry host, without the path images are pushed under
+    let host = registry.split('/').next().unwrap_or(registry);
+
+    // With no credentials passed, docker login checks the stored ones. Without a terminal it can't ask for any
+    let output = platform::docker_command()
+        .args(["login", host])
+        .stdin(Stdio::null())
+        .output();
+
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Error running docker login: {}", e)),
+    };
+
+    let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    match output.status.success() {
+        true => Ok(None),
+        false if error.contains("non TTY") => Ok(Some(format!("No credentials are stored for {}, so pushes only work if it doesn't need them", host))),
+        false => Err(format!("docker can't log in to {}: {}", host, error)),
+    }
+}
+
+/// Starts pushing an image to the registry, with the push output piped
+pub fn spawn_push(image_ref: &str) -> Result<Child, String> {
+    let child = platform::docker_command()
+        .args(["push", image_ref])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    match child {
+        Ok(child) => Ok(child),
+        Err(e) => Err(format!("Error pushing image: {}", e)),
+    }
+}
+
+/// Pushes an image to the registry once, killing the push if the build is cancelled or the push stops making
+/// progress for longer than the push timeout. If a docker broker is set, the broker runs the push
+fn push_once(id: &Uuid, image_ref: &str) -> Result<(), String> {
+    let child = match broker::is_enabled() {
+        true => broker::spawn(BrokerRequest::PushImage { image_ref: image_ref.to_string() }).map(DockerProcess::Broker),
+        false => spawn_push(image_ref).map(DockerProcess::Local),
+    };
+
+    let mut child = child?;
+
+    let last_progress = Arc::new(Mutex::new(SystemTime::now()));
+    let std_out = read_push_progress(child.take_stdout(), *id, last_progress.clone());
+    let std_err = docker::read_in_background(child.take_stderr());
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {},
+            Err(e) => return Err(format!("Error waiting for push: {}", e)),
+        }
+
+        if build_queue::is_cancelled(id) {
+            println!("Push for {} cancelled", id);
+            child.kill();
+            return Err(build_queue::BUILD_CANCELLED.to_string());
+        }
+
+        let stalled = match last_progress.lock() {
+            Ok(last_progress) => DeployPhase::Push.has_timed_out(*last_progress),
+            Err(_) => false,
+        };
+
+        if stalled {
+            println!("Push for {} stopped making progress", id);
+            child.kill();
+            return Err(DeployPhase::Push.timeout_error());
+        }
+
+        thread::sleep(PUSH_POLL_INTERVAL);
+    };
+
+    let _ = std_out.join();
+    let std_err = std_err.join().unwrap_or_default();
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("Error pushing image: {}", String::from_utf8_lossy(&std_err).trim())),
+    }
+}
+
+/// Pushes the built image for a function app to the registry, if one is set, returning the reference it was pushed
+/// to. The image is tagged with the start of the hash of the code it was built from
+///
+/// The push output is logged as it arrives, and deploy progress events are sent as each layer is pushed. Pushes
+/// that fail with errors that may go away are tried again, and layers already pushed aren't sent again.
+/// If the build is cancelled part way through, this returns build_queue::BUILD_CANCELLED
+pub fn push_image(id: &Uuid, function_app_name: &String, content_hash: &str) -> Result<Option<String>, String> {
+    let registry = match get_registry() {
+        Some(registry) => registry,
+        None => return Ok(None),
+    };
+
+    let image_ref = get_image_ref(&registry, function_app_name, content_hash);
+
+    tag_image(function_app_name, &image_ref)?;
+
+    println!("Pushing {} to {}", function_app_name, image_ref);
+    build_logs::append(id, &format!("Pushing to {}", image_ref));
+
+    let mut attempt = 1;
+    loop {
+        match push_once(id, &image_ref) {
+            Ok(_) => {
+                println!("Pushed {} to {}", function_app_name, image_ref);
+                return Ok(Some(image_ref));
+            },
+            Err(e) if attempt < PUSH_ATTEMPTS && is_transient_error(&e) => {
+                let retry = format!("Push failed, trying again (attempt {} of {}): {}", attempt + 1, PUSH_ATTEMPTS, e);
+                println!("{}: {}", function_app_name, retry);
+                build_logs::append(id, &retry);
+                thread::sleep(RETRY_DELAY * attempt);
+
+                if build_queue::is_cancelled(id) {
+                    return Err(build_queue::BUILD_CANCELLED.to_string());
+                }
+
+                attempt += 1;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use reqwest::blocking::Client;
+use uuid::Uuid;
+
+use rustless_shared::{FunctionAppStatus, Replica};
+
+use crate::database::Connection;
+use crate::docker;
+use crate::egress;
+use crate::gateway;
+use crate::platform;
+use crate::storage;
+
+/// The environment variable containing the most replicas an app can be scaled to
+const MAX_REPLICAS_ENV: &str = "RUSTLESS_MAX_REPLICAS";
+
+/// The most replicas an app can be scaled to if the environment variable isn't set
+const DEFAULT_MAX_REPLICAS: u32 = 10;
+
+/// How often the containers of apps with more than one replica are health checked
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a container has to answer its health check before it is marked as unhealthy
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether the container on each port passed its last health check. Containers that haven't been checked yet,
+/// such as replicas that are still starting, aren't in the map
+static HEALTH: OnceLock<Mutex<HashMap<u16, bool>>> = OnceLock::new();
+
+/// How many requests have been routed to each app, used to pick the next replica in turn
+static NEXT_REPLICA: OnceLock<Mutex<HashMap<Uuid, usize>>> = OnceLock::new();
+
+/// Gets the most replicas an app can be scaled to
+pub fn get_max_replicas() -> u32 {
+    match std::env::var(MAX_REPLICAS_ENV) {
+        Ok(value) => match value.trim().parse::<u32>() {
+            Ok(max_replicas) if max_replicas > 0 => max_replicas,
+            _ => {
+                println!("Ignoring invalid {}: {}", MAX_REPLICAS_ENV, value);
+                DEFAULT_MAX_REPLICAS
+            }
+        },
+        Err(_) => DEFAULT_MAX_REPLICAS,
+    }
+}
+
+/// Gets the current time as a Unix timestamp
+fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Gets whether the container on a port passed its last health check, or None if it hasn't been checked
+fn get_health(port: u16) -> Option<bool> {
+    let health = HEALTH.get_or_init(|| Mutex::new(HashMap::new()));
+    match health.lock() {
+        Ok(health) => health.get(&port).copied(),
+        Err(_) => None,
+    }
+}
+
+/// Records whether the container on a port passed its health check
+fn set_health(port: u16, healthy: bool) {
+    let health = HEALTH.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Ok(mut health) = health.lock() {
+        health.insert(port, healthy);
+    }
+}
+
+/// Forgets the health of the container on a port, as it has been stopped and the port can be reused
+fn forget_health(port: u16) {
+    let health = HEALTH.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Ok(mut health) = health.lock() {
+        health.remove(&port);
+    }
+}
+
+/// Marks the container on a port as unhealthy after a request to it failed, so no more requests are routed to it
+/// until it passes its next health check
+pub fn mark_unhealthy(port: u16) {
+    set_health(port, false);
+}
+
+/// Gets the containers running an app, starting with the one on its port. The container on the app's port is
+/// healthy unless it failed its last health check, as apps with one replica aren't checked. Extra replicas are only
+/// healthy once they have passed a health check, so requests aren't routed to them while they start
+fn get_containers(conn: &Connection, id: &Uuid, port: u16) -> Result<Vec<Replica>, String> {
+    let replicas = storage::get_replicas(conn, id).map_err(|e| e.to_string())?;
+
+    let mut containers = vec![Replica { port, healthy: get_health(port) != Some(false) }];
+    containers.extend(replicas.into_iter().map(|(_, port)| Replica { port, healthy: get_health(port) == Some(true) }));
+    Ok(containers)
+}
+
+/// Gets the containers running an app, or an empty list if the app is stopped
+pub fn get_running(conn: &Connection, id: &Uuid) -> Result<Vec<Replica>, String> {
+    match storage::get_function_app_port(conn, id).map_err(|e| e.to_string())? {
+        Some(port) => get_containers(conn, id, port),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Picks the port to route a request to an app on, taking each healthy replica in turn. If no replica is healthy
+/// the request goes to the app's port, as it would if the app had one replica
+pub fn pick_port(conn: &Connection, id: &Uuid, port: u16) -> u16 {
+    let containers = match get_containers(conn, id, port) {
+        Ok(containers) => containers,
+        Err(e) => {
+            println!("Error getting the replicas of {}: {}", id, e);
+            return port;
+        }
+    };
+
+    let healthy: Vec<u16> = containers.iter().filter(|container| container.healthy).map(|container| container.port).collect();
+    if healthy.len() < 2 {
+        return healthy.first().copied().unwrap_or(port);
+    }
+
+    let mut next_replica = match NEXT_REPLICA.get_or_init(|| Mutex::new(HashMap::new())).lock() {
+        Ok(next_replica) => next_replica,
+        Err(e) => e.into_inner(),
+    };
+
+    let next = next_replica.entry(*id).or_insert(0);
+    let picked = healthy[*next % healthy.len()];
+    *next = next.wrapping_add(1);
+    picked
+}
+
+/// Starts extra replicas of an app, on top of the container on its port. Requests are routed to each one once it
+/// passes a health check
+pub fn start_replicas(conn: &Connection, id: &Uuid, name: &String, count: u32) -> Result<(), String> {
+    let labels = docker::get_app_labels(conn, id)?;
+    for _ in 0..count {
+        let started = docker::start_function_app(&labels, &egress::get_container_proxy_url(conn, id)?)?;
+
+        // The port may have been used by a container that has since stopped
+        forget_health(started.port);
+
+        if let Err(e) = storage::add_replica(conn, id, &started.container_id, started.port, now()) {
+            let _ = docker::stop_containers(std::slice::from_ref(&started.container_id), 0);
+            return Err(format!("Error storing replica: {}", e));
+        }
+
+        println!("Started a replica of {} on port {}", name, started.port);
+    }
+
+    Ok(())
+}
+
+/// Stops the newest extra replicas of an app. Requests stop being routed to them before they are stopped, and
+/// they are given the grace period to finish the requests in flight
+fn stop_replicas(conn: &Connection, id: &Uuid, name: &String, count: u32) -> Result<(), String> {
+    let replicas = storage::get_replicas(conn, id).map_err(|e| e.to_string())?;
+
+    let mut container_ids = Vec::new();
+    for (container_id, port) in replicas.into_iter().rev().take(count as usize) {
+        storage::remove_replica(conn, id, &container_id).map_err(|e| e.to_string())?;
+        forget_health(port);
+        container_ids.push(container_id);
+    }
+
+    if container_ids.is_empty() {
+        return Ok(());
+    }
+
+    docker::stop_containers(&container_ids, docker::get_stop_grace_period())?;
+    println!("Stopped {} replicas of {}", container_ids.len(), name);
+    Ok(())
+}
+
+/// Starts or stops extra replicas of a running app so it runs on the given number of containers, including the one
+/// on its port
+pub fn scale(conn: &Connection, id: &Uuid, name: &String, replicas: u32) -> Result<(), String> {
+    let running = storage::get_replicas(conn, id).map_err(|e| e.to_string())?.len() as u32;
+    let wanted = replicas.saturating_sub(1);
+
+    if wanted > running {
+        start_replicas(conn, id, name, wanted - running)
+    } else {
+        stop_replicas(conn, id, name, running - wanted)
+    }
+}
+
+/// Forgets the extra replicas of an app once every container for it has been stopped, such as when it is stopped
+/// or restarted
+pub fn clear(conn: &Connection, id: &Uuid) -> Result<(), String> {
+    let replicas = storage::get_replicas(conn, id).map_err(|e| e.to_string())?;
+    for (_, port) in replicas {
+        forget_health(port);
+    }
+
+    storage::clear_replicas(conn, id).map_err(|e| e.to_string())
+}
+
+/// Checks if the container on a port answers its health check
+fn check_health(client: &Client, port: u16) -> bool {
+    match client.get(format!("{}{}", platform::get_app_url(port), gateway::HEALTH_CHECK_ROUTE)).send() {
+        Ok(res) => res.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+/// Health checks every container of the running apps that have more than one replica
+fn check_replicas(client: &Client) {
+    let apps = match storage::get_all_apps() {
+        Ok(apps) => apps,
+        Err(e) => {
+            println!("Error checking replicas: {}", e);
+            return;
+        }
+    };
+
+    for app in apps.iter().filter(|app| matches!(app.status, FunctionAppStatus::Running)) {
+        let containers = storage::create_connection_for_app(&app.id).and_then(|conn| get_running(&conn, &app.id));
+        let containers = match containers {
+            Ok(containers) => containers,
+            Err(e) => {
+                println!("Error checking the replicas of {}: {}", app.name, e);
+                continue;
+            }
+        };
+
+        if containers.len() < 2 {
+            continue;
+        }
+
+        for container in containers {
+            let healthy = check_health(client, container.port);
+            if healthy != container.healthy {
+                println!("Replica of {} on port {} is now {}", app.name, container.port, if healthy { "healthy" } else { "unhealthy" });
+            }
+            set_health(container.port, healthy);
+        }
+    }
+}
+
+/// Starts health checking the replicas of apps scaled out to more than one container, on a background thread for
+/// the life of the host. Each host process routes requests itself, so every process checks the replicas rather
+/// than only the one holding a lease
+pub fn start() {
+    // The blocking client can't be created on the async runtime the host is started from, so it is created on the
+    // thread that uses it
+    thread::spawn(|| {
+        let client = match Client::builder().timeout(HEALTH_CHECK_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                println!("Error creating HTTP client for replica health checks: {}", e);
+                return;
+            }
+        };
+
+        loop {
+            thread::sleep(HEALTH_CHECK_INTERVAL);
+            check_replicas(&client);
+        }
+    });
+}
@@ -0,0 +1,84 @@
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use rustless_shared::ResourceSample;
+
+use crate::docker;
+use crate::leases;
+use crate::phases;
+use crate::storage;
+
+/// How often the resources used by running apps are sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long samples are kept for
+const SAMPLE_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The lease that makes sure only one host process samples the resources used by apps
+const SAMPLE_LEASE: &str = "resource-sampler";
+
+/// Samples the resources used by every running app, recording them in the database that holds each app and
+/// removing samples past the retention period
+fn take_samples() {
+    // If docker can't be reached there is nothing to sample, so try again next time
+    let usage = match docker::get_resource_usage(None) {
+        Ok(usage) => usage,
+        Err(e) => {
+            println!("Error sampling resource usage: {}", e);
+            return;
+        }
+    };
+
+    if usage.is_empty() {
+        return;
+    }
+
+    let apps = match storage::get_all_apps() {
+        Ok(apps) => apps,
+        Err(e) => {
+            println!("Error sampling resource usage: {}", e);
+            return;
+        }
+    };
+
+    let now = SystemTime::now();
+    let sampled_at = phases::to_timestamp(now);
+    let keep_since = phases::to_timestamp(now - SAMPLE_RETENTION);
+
+    for app in apps {
+        let usage = match usage.get(&app.name) {
+            Some(usage) => usage,
+            None => continue,
+        };
+
+        let sample = ResourceSample {
+            sampled_at,
+            containers: usage.containers,
+            cpu_percent: usage.cpu_percent,
+            memory_bytes: usage.memory_bytes,
+            memory_limit_bytes: usage.memory_limit_bytes,
+            network_rx_bytes: usage.network_rx_bytes,
+            network_tx_bytes: usage.network_tx_bytes,
+        };
+
+        let result = storage::create_connection_for_app(&app.id)
+            .and_then(|conn| storage::add_resource_sample(&conn, &app.id, &sample, keep_since).map_err(|e| e.to_string()));
+
+        if let Err(e) = result {
+            println!("Error recording resource usage for {}: {}", app.name, e);
+        }
+    }
+}
+
+/// Starts sampling the resources used by running apps on a background thread for the life of the host, so there
+/// is a history to look back on when an app runs out of memory or crashes. Only the host process holding the lease
+/// takes samples
+pub fn start() {
+    thread::spawn(|| loop {
+        thread::sleep(SAMPLE_INTERVAL);
+
+        if leases::is_leader(SAMPLE_LEASE) {
+            take_samples();
+        }
+    });
+}
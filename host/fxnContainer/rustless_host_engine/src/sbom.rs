@@ -0,0 +1,189 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::docker;
+
+/// The CycloneDX version of the bills of materials the host generates
+const CYCLONEDX_SPEC_VERSION: &str = "1.5";
+
+/// Where the Dockerfile templates build the app code in the image. Cargo.lock is written here by the build
+const IMAGE_CARGO_LOCK: &str = "/code/Cargo.lock";
+
+/// The source of packages from crates.io in Cargo.lock
+const CRATES_IO_SOURCE: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+/// A package from Cargo.lock
+struct LockedPackage {
+    // The crate name
+    name: String,
+
+    // The crate version
+    version: String,
+
+    // Where the crate came from, such as crates.io or a git repo. Path dependencies, including the app itself, have no source
+    source: Option<String>,
+
+    // The SHA-256 checksum of the crate, for registry crates
+    checksum: Option<String>,
+}
+
+/// Parses the packages from a Cargo.lock file
+fn parse_cargo_lock(contents: &str) -> Vec<LockedPackage> {
+    let mut packages = Vec::new();
+    let mut current: Option<LockedPackage> = None;
+
+    for line in contents.lines().map(|line| line.trim()) {
+        if line == "[[package]]" {
+            packages.extend(current.take());
+            current = Some(LockedPackage { name: String::new(), version: String::new(), source: None, checksum: None });
+            continue;
+        }
+
+        // Each package is a list of key = "value" lines. Other sections, such as the dependency lists, are skipped
+        let (package, (key, value)) = match (current.as_mut(), line.split_once(" = ")) {
+            (Some(package), Some(pair)) => (package, pair),
+            _ => continue,
+        };
+
+        let value = value.trim_matches('"').to_string();
+        match key {
+            "name" => package.name = value,
+            "version" => package.version = value,
+            "source" => package.source = Some(value),
+            "checksum" => package.checksum = Some(value),
+            _ => {},
+        }
+    }
+
+    packages.extend(current);
+    packages.retain(|package| !package.name.is_empty());
+    packages
+}
+
+/// Creates the CycloneDX component for a crate
+fn package_component(package: &LockedPackage) -> Value {
+    let mut component = json!({
+        "type": "library",
+        "bom-ref": format!("{}@{}", package.name, package.version),
+        "name": package.name,
+        "version": package.version,
+    });
+
+    match package.source.as_deref() {
+        Some(CRATES_IO_SOURCE) => component["purl"] = json!(format!("pkg:cargo/{}@{}", package.name, package.version)),
+        Some(source) => component["externalReferences"] = json!([{ "type": "vcs", "url": source }]),
+        None => {},
+    }
+
+    if let Some(checksum) = &package.checksum {
+        component["hashes"] = json!([{ "alg": "SHA-256", "content": checksum }]);
+    }
+
+    component
+}
+
+/// Gets the images the Dockerfile builds from, skipping earlier stages of multi-stage builds
+fn get_base_images(dockerfile: &str) -> Vec<String> {
+    let mut stages: Vec<String> = Vec::new();
+    let mut images = Vec::new();
+
+    for line in dockerfile.lines().map(|line| line.trim()) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 2 || !parts[0].eq_ignore_ascii_case("FROM") {
+            continue;
+        }
+
+        // Skip options such as --platform
+        let image = match parts.iter().skip(1).find(|part| !part.starts_with("--")) {
+            Some(image) => image.to_string(),
+            None => continue,
+        };
+
+        if let Some(index) = parts.iter().position(|part| part.eq_ignore_ascii_case("AS")) {
+            if let Some(stage) = parts.get(index + 1) {
+                stages.push(stage.to_lowercase());
+            }
+        }
+
+        if !stages.contains(&image.to_lowercase()) && !images.contains(&image) {
+            images.push(image);
+        }
+    }
+
+    images
+}
+
+/// Creates the CycloneDX component for a base image, including its digest if docker has it
+fn image_component(image: &str) -> Value {
+    // The tag is after the last colon, as long as that colon isn't part of a registry port
+    let (name, tag) = match image.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name, tag),
+        _ => (image, "latest"),
+    };
+
+    let mut component = json!({
+        "type": "container",
+        "bom-ref": format!("{}:{}", name, tag),
+        "name": name,
+        "version": tag,
+        "purl": format!("pkg:docker/{}@{}", name, tag),
+    });
+
+    if let Some(digest) = docker::get_image_digest(image) {
+        if let Some((_, hash)) = digest.split_once("@sha256:") {
+            component["hashes"] = json!([{ "alg": "SHA-256", "content": hash }]);
+        }
+    }
+
+    component
+}
+
+/// Generates a CycloneDX bill of materials in JSON for a function app that has just been built, listing the crates
+/// from Cargo.lock and the base images from the Dockerfile
+///
+/// The Cargo.lock written by the build is read from the image, so the versions match what was built. If the image
+/// can't be read, the Cargo.lock uploaded with the code is used instead
+pub fn generate_sbom(function_app_name: &String, code_dir: &Path, dockerfile: &str) -> Result<String, String> {
+    let cargo_lock = match docker::read_file_from_image(function_app_name, IMAGE_CARGO_LOCK) {
+        Ok(cargo_lock) => cargo_lock,
+        Err(image_error) => match fs::read_to_string(code_dir.join("Cargo.lock")) {
+            Ok(cargo_lock) => cargo_lock,
+            Err(_) => return Err(image_error),
+        },
+    };
+
+    let mut components: Vec<Value> = parse_cargo_lock(&cargo_lock).iter().map(package_component).collect();
+    components.extend(get_base_images(dockerfile).iter().map(|image| image_component(image)));
+
+    let sbom = json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": CYCLONEDX_SPEC_VERSION,
+        "serialNumber": format!("urn:uuid:{}", Uuid::new_v4()),
+        "version": 1,
+        "metadata": {
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            "component": {
+                "type": "application",
+                "bom-ref": function_app_name,
+                "name": function_app_name,
+            },
+        },
+        "components": components,
+    });
+
+    match serde_json::to_string_pretty(&sbom) {
+        Ok(sbom) => Ok(sbom),
+        Err(e) => Err(format!("Error creating bill of materials: {}", e)),
+    }
+}
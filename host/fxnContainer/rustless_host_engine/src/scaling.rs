@@ -0,0 +1,122 @@
+use std::thread;
+
+use actix_web::body::MessageBody;
+use actix_web::HttpResponse;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use rustless_shared::{ErrorResponse, ScaleProfile, ScaleProfilesReport};
+
+use crate::storage;
+use crate::triggers;
+
+/// Checks scale profiles are valid before they are saved, returning them with the schedules tidied up. Profiles can't
+/// scale the app to more than the given replicas, the most it can run
+pub fn validate_profiles(profiles: &[ScaleProfile], max_replicas: u32) -> Result<Vec<ScaleProfile>, String> {
+    profiles.iter().map(|profile| {
+        triggers::parse_schedule(&profile.schedule)?;
+
+        if profile.replicas > max_replicas {
+            return Err(format!(
+                "Invalid replicas {} for schedule '{}': the most an app can run is {}",
+                profile.replicas, profile.schedule, max_replicas
+            ));
+        }
+
+        Ok(ScaleProfile {
+            schedule: profile.schedule.trim().to_string(),
+            replicas: profile.replicas,
+        })
+    }).collect()
+}
+
+/// Gets the replicas set by the profile that started most recently before the given time, or None if no profile
+/// has started. If profiles start at the same time, the later one in the list wins
+fn get_replicas_at(profiles: &[ScaleProfile], at: &DateTime<Utc>) -> Option<u32> {
+    profiles.iter()
+        .filter_map(|profile| Some((triggers::parse_schedule(&profile.schedule).ok()?.after(at).next_back()?, profile.replicas)))
+        .max_by_key(|(started_at, _)| *started_at)
+        .map(|(_, replicas)| replicas)
+}
+
+/// Gets the replicas set by a profile that started since the last check, or None if no profile started. If
+/// profiles start at the same time, the later one in the list wins
+fn get_due_replicas(profiles: &[ScaleProfile], last_check: &DateTime<Utc>, now: &DateTime<Utc>) -> Option<u32> {
+    profiles.iter()
+        .filter_map(|profile| Some((triggers::parse_schedule(&profile.schedule).ok()?.after(last_check).next()?, profile.replicas)))
+        .filter(|(started_at, _)| started_at <= now)
+        .max_by_key(|(started_at, _)| *started_at)
+        .map(|(_, replicas)| replicas)
+}
+
+/// Gets the scale profiles for a function app, with the replicas they set now and when they next change
+pub fn get_report(profiles: Vec<ScaleProfile>) -> ScaleProfilesReport {
+    let now = Utc::now();
+
+    // Reversed so the later profile in the list wins if profiles start at the same time
+    let next_change = profiles.iter()
+        .rev()
+        .filter_map(|profile| Some((triggers::parse_schedule(&profile.schedule).ok()?.after(&now).next()?, profile.replicas)))
+        .min_by_key(|(starts_at, _)| *starts_at);
+
+    ScaleProfilesReport {
+        current_replicas: get_replicas_at(&profiles, &now),
+        next_change_at: next_change.map(|(starts_at, _)| starts_at.timestamp() as u64),
+        next_replicas: next_change.map(|(_, replicas)| replicas),
+        profiles,
+    }
+}
+
+/// Gets the error message from a failed start or stop, so it can be logged
+pub fn get_response_error(res: HttpResponse) -> String {
+    let status = res.status();
+    match res.into_body().try_into_bytes() {
+        Ok(body) => match serde_json::from_slice::<ErrorResponse>(&body) {
+            Ok(error) => error.message,
+            Err(_) => String::from_utf8_lossy(&body).to_string(),
+        },
+        Err(_) => format!("Status code {}", status),
+    }
+}
+
+/// Scales a function app to the given replicas, starting it if it should run, or stopping it if it should be scaled
+/// to zero. The app is scaled in the same way as through the API, so the same checks apply
+///
+/// This runs on its own thread, as stopping an app waits for the requests in flight to finish
+pub fn scale_in_background(id: Uuid, name: String, replicas: u32) {
+    thread::spawn(move || {
+        let mut conn = match storage::create_connection_for_app(&id) {
+            Ok(conn) => conn,
+            Err(e) => {
+                println!("Error scaling {} to {} replicas: {}", name, replicas, e);
+                return;
+            }
+        };
+
+        let res = crate::scale_function_app_impl(&mut conn, id, replicas);
+        match res.status().is_success() {
+            true => println!("Scaled {} to {} replicas", name, replicas),
+            false => println!("Error scaling {} to {} replicas: {}", name, replicas, get_response_error(res)),
+        }
+    });
+}
+
+/// Checks the scale profiles for every function app, scaling any app whose profile started since the last check
+///
+/// Apps are only scaled when a profile starts, so an app started or stopped by hand stays that way until the next
+/// profile starts
+pub fn check_scale_profiles(last_check: &DateTime<Utc>, now: &DateTime<Utc>) {
+    let all_profiles = match storage::get_all_scale_profiles() {
+        Ok(all_profiles) => all_profiles,
+        Err(e) => {
+            println!("Error getting scale profiles: {}", e);
+            return;
+        }
+    };
+
+    for (id, name, profiles) in all_profiles {
+        if let Some(replicas) = get_due_replicas(&profiles, last_check, now) {
+            scale_in_background(id, name, replicas);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::{Signer, Verifier};
+use serde_json::json;
+use uuid::Uuid;
+
+use rustless_shared::ImageVerification;
+
+use crate::database::Connection;
+use crate::docker;
+use crate::storage;
+
+/// The environment variable containing the path to the PEM file holding the host signing key.
+/// The key is an ECDSA P-256 key, the same as cosign uses, and is created if the file doesn't exist
+const SIGNING_KEY_FILE_ENV: &str = "RUSTLESS_SIGNING_KEY_FILE";
+
+/// The signing key file used if the environment variable isn't set
+const DEFAULT_SIGNING_KEY_FILE: &str = "signing_key.pem";
+
+/// The environment variable setting how signatures are checked when apps start: off, warn, or enforce.
+/// With warn, apps with missing or invalid signatures are started and a warning logged. With enforce, they aren't started
+const VERIFICATION_ENV: &str = "RUSTLESS_IMAGE_VERIFICATION";
+
+/// The type cosign uses for container image signatures in the simple signing payload
+const COSIGN_SIGNATURE_TYPE: &str = "cosign container image signature";
+
+/// How signatures are checked when apps start
+#[derive(Clone, Copy, PartialEq)]
+pub enum Enforcement {
+    /// Off - signatures aren't checked
+    Off,
+
+    /// Warn - apps with missing or invalid signatures are started, with a warning
+    Warn,
+
+    /// Enforce - apps with missing or invalid signatures aren't started
+    Enforce,
+}
+
+impl Enforcement {
+    /// Gets the name of the enforcement level, as set in the environment variable
+    pub fn name(&self) -> &'static str {
+        match self {
+            Enforcement::Off => "off",
+            Enforcement::Warn => "warn",
+            Enforcement::Enforce => "enforce",
+        }
+    }
+}
+
+/// The host signing key, loaded or created once
+static SIGNING_KEY: OnceLock<Result<PKey<Private>, String>> = OnceLock::new();
+
+/// Gets how signatures are checked when apps start. Signatures are checked with a warning by default
+pub fn get_enforcement() -> Enforcement {
+    match std::env::var(VERIFICATION_ENV) {
+        Ok(value) if value.eq_ignore_ascii_case("off") => Enforcement::Off,
+        Ok(value) if value.eq_ignore_ascii_case("enforce") => Enforcement::Enforce,
+        _ => Enforcement::Warn,
+    }
+}
+
+/// Loads the host signing key, creating it if it doesn't exist
+fn load_or_create_key(path: &Path) -> Result<PKey<Private>, String> {
+    if path.exists() {
+        let pem = match fs::read(path) {
+            Ok(pem) => pem,
+            Err(e) => return Err(format!("Error reading signing key {}: {}", path.display(), e)),
+        };
+
+        return match PKey::private_key_from_pem(&pem) {
+            Ok(key) => Ok(key),
+            Err(e) => Err(format!("Error loading signing key {}: {}", path.display(), e)),
+        };
+    }
+
+    let key = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+        .and_then(|group| EcKey::generate(&group))
+        .and_then(PKey::from_ec_key);
+    let key = match key {
+        Ok(key) => key,
+        Err(e) => return Err(format!("Error creating signing key: {}", e)),
+    };
+
+    let pem = match key.private_key_to_pem_pkcs8() {
+        Ok(pem) => pem,
+        Err(e) => return Err(format!("Error creating signing key: {}", e)),
+    };
+
+    // The key is created only readable by the host, and never replaces a key that appeared since it was checked for
+    let mut file = match create_key_file(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Error creating signing key {}: {}", path.display(), e)),
+    };
+
+    if let Err(e) = file.write_all(&pem) {
+        let _ = fs::remove_file(path);
+        return Err(format!("Error writing signing key {}: {}", path.display(), e));
+    }
+
+    println!("Created signing key {}", path.display());
+    Ok(key)
+}
+
+/// Creates a new file for the signing key that only the host can read, failing if the file already exists or the
+/// permissions can't be set
+fn create_key_file(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+
+        // Some file systems ignore the mode, so the permissions are checked rather than trusted
+        let file = options.open(path)?;
+        let mode = file.metadata()?.permissions().mode();
+        if mode & 0o077 != 0 {
+            let _ = fs::remove_file(path);
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("the permissions couldn't be set to 600, the file has {:o}", mode & 0o777)));
+        }
+
+        Ok(file)
+    }
+
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
+/// Gets the host signing key
+fn get_signing_key() -> Result<PKey<Private>, String> {
+    SIGNING_KEY.get_or_init(|| {
+        let path = std::env::var(SIGNING_KEY_FILE_ENV).unwrap_or(DEFAULT_SIGNING_KEY_FILE.to_string());
+        load_or_create_key(Path::new(&path))
+    }).clone()
+}
+
+/// Gets the public key signatures can be checked with, as PEM. This can be used with cosign verify-blob
+pub fn get_public_key_pem() -> Result<String, String> {
+    let key = get_signing_key()?;
+
+    match key.public_key_to_pem() {
+        Ok(pem) => Ok(String::from_utf8_lossy(&pem).to_string()),
+        Err(e) => Err(format!("Error getting public key: {}", e)),
+    }
+}
+
+/// Signs the image built for a deployment of a function app, storing the signature with the deployment
+///
+/// The signature is over a cosign simple signing payload for the image ID, so it can be checked with
+/// cosign verify-blob using the host public key
+pub fn sign_deployment(conn: &Connection, id: &Uuid, function_app_name: &String, number: u32) -> Result<(), String> {
+    let image_digest = match docker::get_image_id(function_app_name) {
+        Some(image_digest) => image_digest,
+        None => return Err(format!("No image found for {}", function_app_name)),
+    };
+
+    let payload = json!({
+        "critical": {
+            "identity": { "docker-reference": docker::get_container_tag(function_app_name) },
+            "image": { "docker-manifest-digest": image_digest },
+            "type": COSIGN_SIGNATURE_TYPE,
+        },
+        "optional": {
+            "app": function_app_name,
+            "deployment": number,
+        },
+    }).to_string();
+
+    let key = get_signing_key()?;
+    let signature = Signer::new(MessageDigest::sha256(), &key).and_then(|mut signer| signer.sign_oneshot_to_vec(payload.as_bytes()));
+    let signature = match signature {
+        Ok(signature) => base64::encode(signature),
+        Err(e) => return Err(format!("Error signing image: {}", e)),
+    };
+
+    let signed_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    match storage::set_deployment_signature(conn, id, number, &image_digest, &payload, &signature, signed_at) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error saving signature: {}", e)),
+    }
+}
+
+/// Checks the signature for a payload was made with the host signing key
+fn verify_signature(payload: &str, signature: &str) -> Result<bool, String> {
+    let key = get_signing_key()?;
+
+    let signature = match base64::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return Ok(false),
+    };
+
+    match Verifier::new(MessageDigest::sha256(), &key).and_then(|mut verifier| verifier.verify_oneshot(&signature, payload.as_bytes())) {
+        Ok(valid) => Ok(valid),
+        Err(e) => Err(format!("Error checking signature: {}", e)),
+    }
+}
+
+/// Checks the image a function app runs from was signed by this host and hasn't been replaced since
+pub fn verify_function_app_image(conn: &Connection, id: &Uuid, function_app_name: &String) -> ImageVerification {
+    let mut verification = ImageVerification {
+        enforcement: get_enforcement().name().to_string(),
+        image_digest: docker::get_image_id(function_app_name),
+        deployment: None,
+        signed_at: None,
+        verified: false,
+        message: None,
+    };
+
+    let signature = match storage::get_latest_deployment_signature(conn, id) {
+        Ok(Some(signature)) => signature,
+        Ok(None) => {
+            verification.message = Some("The image is not signed".to_string());
+            return verification;
+        },
+        Err(e) => {
+            verification.message = Some(format!("Error getting signature: {}", e));
+            return verification;
+        }
+    };
+
+    verification.deployment = Some(signature.number);
+    verification.signed_at = Some(signature.signed_at);
+
+    // The payload is checked as well as the stored digest, as the payload is what was signed
+    let payload_digest = serde_json::from_str::<serde_json::Value>(&signature.payload)
+        .ok()
+        .and_then(|payload| payload["critical"]["image"]["docker-manifest-digest"].as_str().map(|digest| digest.to_string()));
+
+    verification.message = match (verify_signature(&signature.payload, &signature.signature), &verification.image_digest) {
+        (Err(e), _) => Some(e),
+        (Ok(false), _) => Some("The signature was not made with the host signing key".to_string()),
+        (Ok(true), None) => Some("The image does not exist".to_string()),
+        (Ok(true), Some(image_digest)) if payload_digest.as_ref() != Some(image_digest) || signature.image_digest != *image_digest => Some(format!(
+            "The image has changed since deployment {} was signed",
+            signature.number
+        )),
+        (Ok(true), Some(_)) => None,
+    };
+
+    verification.verified = verification.message.is_none();
+    verification
+}
+
+/// Checks the image for a function app can be started, based on the enforcement level. This returns an error
+/// with the reason if the app must not be started
+pub fn check_before_start(conn: &Connection, id: &Uuid, function_app_name: &String) -> Result<(), String> {
+    let enforcement = get_enforcement();
+    if enforcement == Enforcement::Off {
+        return Ok(());
+    }
+
+    let verification = verify_function_app_image(conn, id, function_app_name);
+    let message = match verification.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+
+    match enforcement {
+        Enforcement::Enforce => Err(message),
+        _ => {
+            println!("Warning: starting {} without a valid signature: {}", function_app_name, message);
+            Ok(())
+        }
+    }
+}
@@ -1,108 +1,424 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::time::SystemTime;
 
-use rusqlite::{Connection, Result, Error};
+use rusqlite::{Result, Error, ErrorCode, OpenFlags};
 use uuid::Uuid;
-use rustless_shared::{FunctionApp, FunctionAppStatus};
+use rustless_shared::{ApiKey, AppExit, AppStart, AppStop, BuildLog, DeployPhases, Deployment, FunctionApp, FunctionAppStatus, Lease, MirrorConfig, NamespaceDefaults, Promotion, RedactionRules, ResourceSample, ScaleProfile, TimerTrigger, TriggerRun, BUILD_FAILED, DEFAULT_NAMESPACE};
 
-/// The function app details to store in the database
-#[derive(Debug)]
-struct SqliteFunctionApp {
-    // The app name
-    pub name: String,
+use crate::database::{self, Connection, SqlBackend, StorageBackend};
+use crate::db_pool::{self, DbConnection, DbPools};
+use crate::events;
+use crate::faults;
+use crate::migrations;
+use crate::postgres_storage::PostgresStorage;
 
-    // The app ID
-    pub id: String,
+/// The function app records the host reads and writes most, such as checking and changing the status of each app as
+/// requests come in. Each backend implements these with its own SQL, rather than having SQL written for SQLite
+/// translated, and the other storage functions go through database::SqlBackend
+pub trait Storage: SqlBackend {
+    /// Gets all the function apps registered in this database
+    fn get_all_apps(&self) -> Result<Vec<FunctionApp>>;
 
-    // The status of the app
-    pub status: u8,
+    /// Gets the status of a function app as stored in the database
+    fn get_status(&self, id: &Uuid) -> Result<FunctionAppStatus>;
 
-    // The date/time the app was created
-    pub created_at: u64,
+    /// Sets the status of a function app, returning if it changed
+    fn set_status(&self, id: &Uuid, status: &FunctionAppStatus) -> Result<bool>;
 
-    // The port the container is running on, if it is running
-    pub port: u16
+    /// Sets a function app as running on the given port, clearing any stop for being idle. Returns if the app wasn't
+    /// already running
+    fn set_running(&self, id: &Uuid, port: u16) -> Result<bool>;
 }
 
-const DB_FILE: &str = "rustless_host.db";
+impl Storage for rusqlite::Connection {
+    fn get_all_apps(&self) -> Result<Vec<FunctionApp>> {
+        let mut stmt = rusqlite::Connection::prepare(self, "SELECT name, id, status, created_at, namespace FROM function_apps")?;
+        let mut rows = stmt.query([])?;
 
-/// Create the database connection assuming it already exists. Only call this if create_connection() has already been called once
-/// create_connection() will be called at the start of the server, so this should be ok. It will panic if the database does not exist
-pub fn create_connection_fast() -> Connection {
-    let conn = Connection::open(DB_FILE);
-    match conn {
-        Ok(conn) => conn,
-        Err(e) => panic!("Error opening database: {}", e),
+        let mut response = Vec::new();
+        while let Some(row) = rows.next()? {
+            response.push(FunctionApp {
+                name: row.get(0)?,
+                id: to_id(&row.get::<_, String>(1)?)?,
+                status: to_status(row.get(2)?)?,
+                created_at: row.get(3)?,
+                namespace: row.get(4)?,
+            });
+        }
+
+        Ok(response)
+    }
+
+    fn get_status(&self, id: &Uuid) -> Result<FunctionAppStatus> {
+        let status = rusqlite::Connection::query_row(self, "SELECT status FROM function_apps WHERE id = ?1", [id.to_string()], |row| row.get(0))?;
+        to_status(status)
+    }
+
+    fn set_status(&self, id: &Uuid, status: &FunctionAppStatus) -> Result<bool> {
+        let changed = rusqlite::Connection::execute(
+            self,
+            "UPDATE function_apps SET status = ?1 WHERE id = ?2 AND status != ?1",
+            rusqlite::params![*status as u8, id.to_string()],
+        )?;
+
+        Ok(changed > 0)
+    }
+
+    fn set_running(&self, id: &Uuid, port: u16) -> Result<bool> {
+        let was_running = self.get_status(id)? as u8 == FunctionAppStatus::Running as u8;
+        rusqlite::Connection::execute(
+            self,
+            "UPDATE function_apps SET status = ?1, port = ?2, idle_stopped = 0 WHERE id = ?3",
+            rusqlite::params![FunctionAppStatus::Running as u8, port, id.to_string()],
+        )?;
+
+        Ok(!was_running)
     }
 }
 
-/// Gets all the registered function apps
-pub fn get_all_apps() -> Result<Vec<FunctionApp>, String> {
-    let conn = create_connection_fast();
+/// Converts a function app ID stored in the database to a UUID
+pub(crate) fn to_id(id: &str) -> Result<Uuid> {
+    match Uuid::parse_str(id) {
+        Ok(id) => Ok(id),
+        Err(e) => Err(Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e))),
+    }
+}
 
-    // Prepare the SQL statement
-    let stmt = conn.prepare("SELECT name, id, status, created_at, port FROM function_apps");
-    let mut stmt = match stmt {
-        Ok(stmt) => stmt,
+/// Converts a function app status stored in the database to the enum
+pub(crate) fn to_status(status: i64) -> Result<FunctionAppStatus> {
+    match status {
+        0 => Ok(FunctionAppStatus::NotRegistered),
+        1 => Ok(FunctionAppStatus::Registered),
+        2 => Ok(FunctionAppStatus::Building),
+        3 => Ok(FunctionAppStatus::Ready),
+        4 => Ok(FunctionAppStatus::Running),
+        5 => Ok(FunctionAppStatus::Error),
+        6 => Ok(FunctionAppStatus::Cancelled),
+        _ => Err(Error::IntegralValueOutOfRange(0, status)),
+    }
+}
+
+/// The environment variable containing the path to the database file
+pub const DB_FILE_ENV: &str = "RUSTLESS_DB_FILE";
+
+/// The database file if the environment variable isn't set, relative to the working directory
+const DEFAULT_DB_FILE: &str = "rustless_host.db";
+
+/// The setting holding the name of the default app
+const DEFAULT_APP_SETTING: &str = "default_app";
+
+/// The folder that holds the database files for each namespace when namespace isolation is enabled. It is next to
+/// the main database file
+const NAMESPACE_DB_DIR: &str = "namespaces";
+
+/// Set this environment variable to 1 or true to store each namespace in its own database file
+///
+/// This limits the impact of a corrupt database to a single namespace, allows namespaces to be backed up
+/// and restored separately, and means removing a tenant is as simple as deleting their file.
+/// Apps already stored in the main database are not moved when this is turned on.
+const NAMESPACE_ISOLATION_ENV: &str = "RUSTLESS_ISOLATE_NAMESPACES";
+
+/// The environment variable containing the key used to encrypt the database
+const DB_KEY_ENV: &str = "RUSTLESS_DB_KEY";
+
+/// The environment variable containing the path to a file holding the key used to encrypt the database.
+/// This allows the key to be mounted from a secret store or KMS rather than set directly in the environment
+const DB_KEY_FILE_ENV: &str = "RUSTLESS_DB_KEY_FILE";
+
+/// The database key, loaded once from the environment
+static DB_KEY: OnceLock<Result<Option<String>, String>> = OnceLock::new();
+
+/// Held while registering a function app so two registrations can't check the same name at the same time.
+/// Names are unique across all namespaces, which can be in different database files, so a transaction
+/// on a single database isn't enough on its own
+static REGISTRATION_LOCK: Mutex<()> = Mutex::new(());
+
+/// Gets the key to encrypt the database with, or None if the database is not encrypted
+fn get_database_key() -> Result<Option<String>, String> {
+    DB_KEY.get_or_init(|| {
+        // A key set directly takes priority over a key file
+        if let Ok(key) = std::env::var(DB_KEY_ENV) {
+            return Ok(Some(key));
+        }
+
+        match std::env::var(DB_KEY_FILE_ENV) {
+            Ok(key_file) => match fs::read_to_string(&key_file) {
+                Ok(key) => Ok(Some(key.trim().to_string())),
+                Err(e) => Err(format!("Error reading database key file {}: {}", key_file, e)),
+            },
+            Err(_) => Ok(None),
+        }
+    }).clone()
+}
+
+/// Unlocks an encrypted database with the given key
+#[cfg(feature = "sqlcipher")]
+fn apply_database_key(conn: &rusqlite::Connection, key: &str) -> Result<(), String> {
+    if let Err(e) = conn.pragma_update(None, "key", key) {
+        return Err(format!("Error setting database key: {}", e));
+    }
+
+    // SQLCipher doesn't check the key until the database is read, so read something to check it now
+    match conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(())) {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Unable to read the database. Is the database key correct?".to_string()),
+    }
+}
+
+/// Unlocks an encrypted database with the given key
+///
+/// Without SQLCipher a key would be silently ignored and the database left unencrypted, so this is an error
+#[cfg(not(feature = "sqlcipher"))]
+fn apply_database_key(_conn: &rusqlite::Connection, _key: &str) -> Result<(), String> {
+    Err(format!("{} or {} is set, but the host was built without the sqlcipher feature so cannot encrypt the database", DB_KEY_ENV, DB_KEY_FILE_ENV))
+}
+
+/// Opens a database file, unlocking it if the database is encrypted
+pub fn open_database(path: impl AsRef<Path>) -> Result<rusqlite::Connection, String> {
+    let conn = match rusqlite::Connection::open(path) {
+        Ok(conn) => conn,
         Err(e) => return Err(e.to_string()),
     };
 
-    // Run the query
-    let function_apps = stmt.query_map([], |row| {
-        Ok(SqliteFunctionApp {
-            name: row.get(0)?,
-            id: row.get(1)?,
-            status: row.get(2)?,
-            created_at: row.get(3)?,
-            port: row.get(4)?
-        })
-    });
+    if let Some(key) = get_database_key()? {
+        apply_database_key(&conn, &key)?;
+    }
 
-    let function_apps = match function_apps {
-        Ok(function_apps) => function_apps,
+    Ok(conn)
+}
+
+/// Opens the main database read only, without creating or upgrading it, so it can be checked before the host starts.
+/// Returns None if the database file doesn't exist yet. With the postgres backend, this connects to the database
+/// without using the pool, which would create the tables
+pub fn open_existing_database() -> Result<Option<Connection>, String> {
+    if database::get_storage_backend() == StorageBackend::Postgres {
+        return PostgresStorage::connect(&database::get_database_url()?).map(|conn| Some(Connection::new(conn)));
+    }
+
+    let db_file = get_db_file();
+    if !db_file.exists() {
+        return Ok(None);
+    }
+
+    let conn = match rusqlite::Connection::open_with_flags(&db_file, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Ok(conn) => conn,
         Err(e) => return Err(e.to_string()),
     };
 
-    // Build a vector for the response
-    let mut response = Vec::new();
+    if let Some(key) = get_database_key()? {
+        apply_database_key(&conn, &key)?;
+    }
 
-    // Add all the items to the vector, mapping the status to the enum
-    for function_app in function_apps {
-        let function_app = match function_app {
-            Ok(function_app) => function_app,
-            Err(e) => return Err(e.to_string()),
-        };
+    Ok(Some(Connection::new(conn)))
+}
 
-        let id = Uuid::parse_str(&function_app.id);
-        let id = match id {
-            Ok(id) => id,
-            Err(e) => return Err(e.to_string()),
+/// Gets the path to the main database file
+pub fn get_db_file() -> PathBuf {
+    match std::env::var(DB_FILE_ENV) {
+        Ok(value) if !value.trim().is_empty() => PathBuf::from(value.trim()),
+        _ => PathBuf::from(DEFAULT_DB_FILE),
+    }
+}
+
+/// Gets the folder that holds the database files for each namespace
+pub fn get_namespace_db_dir() -> PathBuf {
+    get_db_file().with_file_name(NAMESPACE_DB_DIR)
+}
+
+/// Gets if each namespace should be stored in its own database file. Namespaces can't be stored separately with the
+/// postgres backend, as they share the database with every host that uses it
+pub fn is_namespace_isolation_enabled() -> bool {
+    if database::get_storage_backend() == StorageBackend::Postgres {
+        return false;
+    }
+
+    match std::env::var(NAMESPACE_ISOLATION_ENV) {
+        Ok(value) => value == "1" || value.eq_ignore_ascii_case("true"),
+        Err(_) => false,
+    }
+}
+
+/// Checks that a namespace name is valid. As namespaces can be used as file names, only letters,
+/// numbers, dashes and underscores are allowed
+pub fn validate_namespace(namespace: &str) -> Result<(), String> {
+    if namespace.is_empty() || namespace.len() > 64 {
+        return Err("Namespace must be between 1 and 64 characters".to_string());
+    }
+
+    if !namespace.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("Namespace can only contain letters, numbers, dashes and underscores".to_string());
+    }
+
+    Ok(())
+}
+
+/// Gets the path to the database file for a namespace when namespace isolation is enabled
+pub fn get_namespace_db_file(namespace: &str) -> PathBuf {
+    get_namespace_db_dir().join(format!("{}.db", namespace))
+}
+
+/// Gets a connection to the database that holds the given namespace
+///
+/// If namespace isolation is enabled, this is the namespace's own database file, which is created if needed.
+/// Otherwise all namespaces share the main database.
+pub fn create_namespace_connection(namespace: &str) -> Result<DbConnection, String> {
+    connect_namespace(&db_pool::get_pools(), namespace)
+}
+
+/// Gets a connection to the database that holds the given namespace from the given pools
+pub fn connect_namespace(pools: &DbPools, namespace: &str) -> Result<DbConnection, String> {
+    if !is_namespace_isolation_enabled() {
+        return connect(pools);
+    }
+
+    validate_namespace(namespace)?;
+
+    if let Err(e) = fs::create_dir_all(get_namespace_db_dir()) {
+        return Err(format!("Error creating namespace database folder: {}", e));
+    }
+
+    // The migrations are applied the first time the namespace is connected to
+    match pools.get_connection(&get_namespace_db_file(namespace), migrations::migrate) {
+        Ok(conn) => Ok(conn),
+        Err(e) => Err(format!("Error opening database for namespace {}: {}", namespace, e)),
+    }
+}
+
+/// Gets all the namespaces that have apps
+pub fn get_namespaces() -> Result<Vec<String>, String> {
+    list_namespaces(&db_pool::get_pools())
+}
+
+/// Gets all the namespaces that have apps, connecting to the main database from the given pools if needed
+fn list_namespaces(pools: &DbPools) -> Result<Vec<String>, String> {
+    // With isolation, each namespace has a database file
+    if is_namespace_isolation_enabled() {
+        let entries = match fs::read_dir(get_namespace_db_dir()) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(Vec::new()),
         };
-        
-        response.push(FunctionApp {
-            name: function_app.name,
-            id: id,
-            status: match function_app.status {
-                0 => FunctionAppStatus::NotRegistered,
-                1 => FunctionAppStatus::Registered,
-                2 => FunctionAppStatus::Building,
-                3 => FunctionAppStatus::Ready,
-                4 => FunctionAppStatus::Running,
-                5 => FunctionAppStatus::Error,
-                _ => panic!("Unknown status"),
-            },
-            created_at: function_app.created_at
-        });
+
+        let mut namespaces = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map(|e| e == "db").unwrap_or(false) {
+                if let Some(namespace) = path.file_stem() {
+                    namespaces.push(namespace.to_string_lossy().to_string());
+                }
+            }
+        }
+
+        namespaces.sort();
+        return Ok(namespaces);
+    }
+
+    // Without isolation, get the namespaces from the main database
+    let conn = connect(pools)?;
+    let stmt = conn.prepare("SELECT DISTINCT namespace FROM function_apps ORDER BY namespace");
+    let mut stmt = match stmt {
+        Ok(stmt) => stmt,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let namespaces = stmt.query_map([], |row| row.get(0));
+    match namespaces {
+        Ok(namespaces) => namespaces.collect::<Result<Vec<String>>>().map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Gets a connection to every database that holds function apps. The main database is always included, as apps
+/// registered before namespace isolation was turned on stay in it
+fn create_all_connections() -> Result<Vec<DbConnection>, String> {
+    connect_all(&db_pool::get_pools())
+}
+
+/// Gets a connection to every database that holds function apps from the given pools
+fn connect_all(pools: &DbPools) -> Result<Vec<DbConnection>, String> {
+    let mut connections = vec![connect(pools)?];
+    if !is_namespace_isolation_enabled() {
+        return Ok(connections);
+    }
+
+    for namespace in list_namespaces(pools)? {
+        connections.push(connect_namespace(pools, &namespace)?);
+    }
+
+    Ok(connections)
+}
+
+/// Gets a connection to the database that holds the function app with the given ID
+pub fn create_connection_for_app(id: &Uuid) -> Result<DbConnection, String> {
+    connect_for_app(&db_pool::get_pools(), id)
+}
+
+/// Gets a connection to the database that holds the function app with the given ID from the given pools
+pub fn connect_for_app(pools: &DbPools, id: &Uuid) -> Result<DbConnection, String> {
+    for conn in connect_all(pools)? {
+        if get_function_app_name(&conn, id).is_ok() {
+            return Ok(conn);
+        }
+    }
+
+    Err(format!("No function app with ID {} found", id))
+}
+
+/// Gets a connection to the database that holds the function app with the given name
+pub fn create_connection_for_app_name(name: &String) -> Result<DbConnection, String> {
+    connect_for_app_name(&db_pool::get_pools(), name)
+}
+
+/// Gets a connection to the database that holds the function app with the given name from the given pools
+pub fn connect_for_app_name(pools: &DbPools, name: &String) -> Result<DbConnection, String> {
+    for conn in connect_all(pools)? {
+        if get_function_id_from_name(&conn, name).is_ok() {
+            return Ok(conn);
+        }
+    }
+
+    Err(format!("No function app with name {} found", name))
+}
+
+/// Checks if the given function app name is in use in any namespace. Names are unique across
+/// all namespaces as they are used to name the docker containers
+pub fn is_name_in_use_in_any_namespace(name: &str) -> Result<bool, String> {
+    for conn in create_all_connections()? {
+        if is_name_in_use(&conn, name).map_err(|e| e.to_string())? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Gets all the registered function apps across all namespaces
+pub fn get_all_apps() -> Result<Vec<FunctionApp>, String> {
+    let mut response = Vec::new();
+
+    for conn in create_all_connections()? {
+        response.append(&mut get_apps(&conn)?);
     }
 
     Ok(response)
 }
 
+/// Gets all the registered function apps in the given database
+fn get_apps(conn: &Connection) -> Result<Vec<FunctionApp>, String> {
+    match conn.storage().get_all_apps() {
+        Ok(function_apps) => Ok(function_apps),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 /// Checks if the given function app name is already in use
 pub fn is_name_in_use(conn: &Connection, name: &str) -> Result<bool, Error> {
     let mut stmt = conn
         .prepare("SELECT COUNT(*) FROM function_apps WHERE name = ?")?;
     
-    let mut rows = stmt.query(&[name])?;
+    let mut rows = stmt.query([name])?;
     match rows.next()? {
         Some(row) => {
             let count: i64 = row.get(0)?;
@@ -131,6 +447,11 @@ pub fn get_function_id_from_name(conn: &Connection, name: &String) -> Result<Uui
     }
 }
 
+/// Gets the status of the function app stored in the database
+pub fn get_function_app_stored_status(conn: &Connection, id: &Uuid) -> Result<FunctionAppStatus, Error> {
+    conn.storage().get_status(id)
+}
+
 /// Gets the function app name from the ID
 pub fn get_function_app_name(conn: &Connection, id: &Uuid) -> Result<String, Error> {
     let mut stmt = conn
@@ -146,8 +467,17 @@ pub fn get_function_app_name(conn: &Connection, id: &Uuid) -> Result<String, Err
     }
 }
 
+/// Gets the name and namespace of a function app from the ID
+pub fn get_function_app_name_and_namespace(conn: &Connection, id: &Uuid) -> Result<(String, String)> {
+    conn.query_row(
+        "SELECT name, namespace FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
 /// Adds a new function app to the database and returns the ID
-pub fn add_new_function_app(conn: &Connection, name: &str) -> Result<Uuid> {
+pub fn add_new_function_app(conn: &Connection, name: &str, namespace: &str) -> Result<Uuid> {
     // Generate the ID
     let id = Uuid::new_v4();
 
@@ -157,72 +487,2444 @@ pub fn add_new_function_app(conn: &Connection, name: &str) -> Result<Uuid> {
     let time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .expect("Time went backwards")
-        .as_secs() as u64;
+        .as_secs();
 
     // Insert the new row
     match conn.execute(
-        format!("INSERT INTO function_apps (name, id, status, created_at, port) VALUES (?1, ?2, {}, {}, 0)", status, time).as_str(),
-        &[name, &id.to_string()],
+        format!("INSERT INTO function_apps (name, id, status, created_at, port, namespace) VALUES (?1, ?2, {}, {}, 0, ?3)", status, time).as_str(),
+        [name, &id.to_string(), namespace],
     ) {
-        Ok(_) => Ok(id),
+        Ok(_) => {
+            events::publish_status_changed(conn, &id, &FunctionAppStatus::Registered);
+            Ok(id)
+        },
         Err(e) => Err(e),
     }
 }
 
-/// Sets the status of the given app to building
+/// Registers a new function app, making sure the name is not in use in any namespace
+///
+/// The check and insert are run in a transaction so a concurrent registration of the same name
+/// is caught. Returns None if the name is already in use.
+pub fn register_function_app(conn: &mut Connection, name: &str, namespace: &str) -> Result<Option<Uuid>, String> {
+    let _lock = match REGISTRATION_LOCK.lock() {
+        Ok(lock) => lock,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    // Check the other namespaces first
+    if is_name_in_use_in_any_namespace(name)? {
+        return Ok(None);
+    }
+
+    // Check and insert in a transaction so no other process can insert the name in between
+    let res = with_transaction(conn, |tx| {
+        if is_name_in_use(tx, name)? {
+            return Ok(None);
+        }
+
+        add_new_function_app(tx, name, namespace).map(Some)
+    });
+
+    match res {
+        Ok(id) => Ok(id),
+        Err(Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Registers a function app that already has a built image, keeping the ID and deployment number from the image so
+/// the app can be started without building it again. The app is ready, and the deployment is approved as it was built
+/// before. Returns false if the name is already in use
+pub fn adopt_function_app(conn: &mut Connection, id: &Uuid, name: &str, namespace: &str, deployment: Option<u32>) -> Result<bool, String> {
+    let _lock = match REGISTRATION_LOCK.lock() {
+        Ok(lock) => lock,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    if is_name_in_use_in_any_namespace(name)? {
+        return Ok(false);
+    }
+
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+
+    let res = with_transaction(conn, |tx| {
+        if is_name_in_use(tx, name)? {
+            return Ok(false);
+        }
+
+        tx.execute(
+            "INSERT INTO function_apps (name, id, status, created_at, port, namespace) VALUES (?1, ?2, ?3, ?4, 0, ?5)",
+            rusqlite::params![name, id.to_string(), FunctionAppStatus::Ready as u8, time, namespace],
+        )?;
+
+        if let Some(number) = deployment {
+            tx.execute(
+                "INSERT INTO deployments (function_app_id, number, created_at, approved, approved_at) VALUES (?1, ?2, ?3, 1, ?3)",
+                rusqlite::params![id.to_string(), number, time],
+            )?;
+        }
+
+        Ok(true)
+    });
+
+    match res {
+        Ok(true) => {
+            events::publish_status_changed(conn, id, &FunctionAppStatus::Ready);
+            Ok(true)
+        },
+        Ok(false) => Ok(false),
+        Err(Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => Ok(false),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Runs a set of storage updates in a transaction
+///
+/// The transaction is committed if the closure succeeds, and rolled back if it returns an error, so a
+/// failure part way through a multi-step update doesn't leave inconsistent rows behind. The write lock
+/// is taken at the start so other writers can't change rows between reading and updating them.
+pub fn with_transaction<T, F>(conn: &mut Connection, f: F) -> Result<T>
+where
+    F: FnOnce(&Connection) -> Result<T>,
+{
+    conn.begin()?;
+
+    match f(conn) {
+        Ok(result) => {
+            conn.execute("COMMIT", [])?;
+            Ok(result)
+        },
+        Err(e) => {
+            // The connection goes back to the pool, so it mustn't be left in the transaction
+            let _ = conn.execute("ROLLBACK", []);
+            Err(e)
+        }
+    }
+}
+
+/// Deletes a function app along with its builds, deployments, status history, and trigger runs. Everything is
+/// deleted in one transaction so an app is never left half deleted
+pub fn delete_function_app(conn: &mut Connection, id: &Uuid) -> Result<()> {
+    with_transaction(conn, |tx| {
+        for table in ["builds", "build_logs", "deployments", "replicas", "resource_samples", "status_history", "trigger_runs"] {
+            tx.execute(&format!("DELETE FROM {} WHERE function_app_id = ?", table), [id.to_string()])?;
+        }
+
+        tx.execute("DELETE FROM function_apps WHERE id = ?", [id.to_string()])?;
+        Ok(())
+    })
+}
+
+/// Sets the status of the given app
+///
+/// If the status changed, an event is sent to the event stream
 pub fn set_function_app_status(conn: &Connection, id: &Uuid, status: &FunctionAppStatus) -> Result<()> {
-    let status = (*status) as u8;
+    // Tests can drop status writes to check the reconciler recovers from them
+    if faults::should_drop_status_write() {
+        return Ok(());
+    }
 
-    match conn.execute(
-        format!("UPDATE function_apps SET status = {} WHERE id = ?", status).as_str(),
-        &[&id.to_string()],
-    ) {
-        Ok(_) => Ok(()),
+    match conn.storage().set_status(id, status) {
+        Ok(true) => {
+            events::publish_status_changed(conn, id, status);
+            Ok(())
+        },
+        Ok(false) => Ok(()),
         Err(e) => Err(e),
     }
 }
 
-/// Sets a function app as running
+/// Sets a function app as running, clearing any stop for being idle
 pub fn set_function_app_running(conn: &Connection, id: &Uuid, port: u16) -> Result<()> {
-    match conn.execute(
-        "UPDATE function_apps SET status = 4, port = ? WHERE id = ?",
-        &[&port.to_string(), &id.to_string()],
-    ) {
-        Ok(_) => Ok(()),
+    if faults::should_drop_status_write() {
+        return Ok(());
+    }
+
+    match conn.storage().set_running(id, port) {
+        Ok(true) => {
+            events::publish_status_changed(conn, id, &FunctionAppStatus::Running);
+            Ok(())
+        },
+        Ok(false) => Ok(()),
         Err(e) => Err(e),
     }
 }
 
-/// Creates a connection to the database
-pub fn create_connection() -> Result<Connection, String> {
-    // Open the database file
-    let conn_result = Connection::open(DB_FILE);
+/// Gets the port a function app is running on, or None if it is not running
+pub fn get_function_app_port(conn: &Connection, id: &Uuid) -> Result<Option<u16>> {
+    let (status, port): (u8, u16) = conn.query_row(
+        "SELECT status, port FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
 
-    // Check if the open actually worked
-    let conn = match conn_result {
-        Ok(conn) => conn,
-        Err(_) => {
-            return Err("Error connecting to database".to_string());
-        }
+    if status == FunctionAppStatus::Running as u8 {
+        Ok(Some(port))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Sets if a function app was stopped for being idle, so the next request to it starts it again. Starting the app
+/// clears this
+pub fn set_function_app_idle_stopped(conn: &Connection, id: &Uuid, idle_stopped: bool) -> Result<()> {
+    conn.execute(
+        "UPDATE function_apps SET idle_stopped = ?1 WHERE id = ?2",
+        rusqlite::params![idle_stopped, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets if a function app was stopped for being idle and hasn't been started or stopped by hand since
+pub fn is_function_app_idle_stopped(conn: &Connection, id: &Uuid) -> Result<bool> {
+    conn.query_row(
+        "SELECT idle_stopped FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Records when the gateway last routed a request to a function app, as a Unix timestamp
+pub fn set_function_app_last_request(conn: &Connection, id: &Uuid, last_request_at: u64) -> Result<()> {
+    conn.execute(
+        "UPDATE function_apps SET last_request_at = ?1 WHERE id = ?2",
+        rusqlite::params![last_request_at, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets when the gateway last routed a request to a function app, or None if it never has
+pub fn get_function_app_last_request(conn: &Connection, id: &Uuid) -> Result<Option<u64>> {
+    conn.query_row(
+        "SELECT last_request_at FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Sets how many replicas of a function app are run when it is started
+pub fn set_function_app_replicas(conn: &Connection, id: &Uuid, replicas: u32) -> Result<()> {
+    conn.execute(
+        "UPDATE function_apps SET replicas = ?1 WHERE id = ?2",
+        rusqlite::params![replicas, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets how many replicas of a function app are run when it is started
+pub fn get_function_app_replicas(conn: &Connection, id: &Uuid) -> Result<u32> {
+    conn.query_row(
+        "SELECT replicas FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Adds a container started as an extra replica of a function app, alongside the container on the app's port
+pub fn add_replica(conn: &Connection, id: &Uuid, container_id: &str, port: u16, started_at: u64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO replicas (function_app_id, container_id, port, started_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![id.to_string(), container_id, port, started_at],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the containers started as extra replicas of a function app, as their container IDs and ports, oldest first
+pub fn get_replicas(conn: &Connection, id: &Uuid) -> Result<Vec<(String, u16)>> {
+    let mut stmt = conn.prepare("SELECT container_id, port FROM replicas WHERE function_app_id = ? ORDER BY started_at, rowid")?;
+    let rows = stmt.query_map([id.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Removes a container from the extra replicas of a function app, so requests are no longer routed to it
+pub fn remove_replica(conn: &Connection, id: &Uuid, container_id: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM replicas WHERE function_app_id = ?1 AND container_id = ?2",
+        rusqlite::params![id.to_string(), container_id],
+    )?;
+
+    Ok(())
+}
+
+/// Removes all the extra replicas of a function app, once their containers have been stopped
+pub fn clear_replicas(conn: &Connection, id: &Uuid) -> Result<()> {
+    conn.execute("DELETE FROM replicas WHERE function_app_id = ?", [id.to_string()])?;
+    Ok(())
+}
+
+/// Turns maintenance mode on or off for a function app, with an optional message to show on the maintenance page
+pub fn set_function_app_maintenance(conn: &Connection, id: &Uuid, enabled: bool, message: &Option<String>) -> Result<()> {
+    // An empty message still turns maintenance mode on
+    let message = match enabled {
+        true => Some(message.clone().unwrap_or_default()),
+        false => None,
     };
 
-    // We need a table to store the function app details. Create it if it doesn't exist
     match conn.execute(
-        "CREATE TABLE IF NOT EXISTS function_apps (
-                  id          TEXT PRIMARY KEY,
-                  name        TEXT NOT NULL UNIQUE,
-                  status      INTEGER NOT NULL,
-                  created_at  INTEGER NOT NULL,
-                  port        INTEGER NOT NULL
-                  )",
-        [],
+        "UPDATE function_apps SET maintenance_message = ?1 WHERE id = ?2",
+        rusqlite::params![message, id.to_string()],
     ) {
-        Ok(_) => {},
-        Err(_) => {
-            return Err("Error creating table".to_string());
-        }
+        Ok(_) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets the maintenance message for a function app, or None if it is not in maintenance mode
+pub fn get_function_app_maintenance(conn: &Connection, id: &Uuid) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT maintenance_message FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Sets how requests to a function app are mirrored, or stops mirroring if the config is None
+pub fn set_function_app_mirror(conn: &Connection, id: &Uuid, config: &Option<MirrorConfig>) -> Result<()> {
+    // The config is stored as JSON so new options don't need new columns
+    let config = match config {
+        Some(config) => match serde_json::to_string(config) {
+            Ok(config) => Some(config),
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        },
+        None => None,
     };
 
-    // Return the connection
-    Ok(conn)
-}
\ No newline at end of file
+    conn.execute(
+        "UPDATE function_apps SET mirror_config = ?1 WHERE id = ?2",
+        rusqlite::params![config, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets how requests to a function app are mirrored, or None if they are not mirrored
+pub fn get_function_app_mirror(conn: &Connection, id: &Uuid) -> Result<Option<MirrorConfig>> {
+    let config: Option<String> = conn.query_row(
+        "SELECT mirror_config FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    match config {
+        Some(config) => match serde_json::from_str(&config) {
+            Ok(config) => Ok(Some(config)),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Sets what is redacted from requests to a function app before they are mirrored or recorded, or only redacts the
+/// headers that are always redacted if the rules are None
+pub fn set_function_app_redaction(conn: &Connection, id: &Uuid, rules: &Option<RedactionRules>) -> Result<()> {
+    let rules = match rules {
+        Some(rules) => match serde_json::to_string(rules) {
+            Ok(rules) => Some(rules),
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        },
+        None => None,
+    };
+
+    conn.execute(
+        "UPDATE function_apps SET redaction_rules = ?1 WHERE id = ?2",
+        rusqlite::params![rules, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets what is redacted from requests to a function app before they are mirrored or recorded, or None if only the
+/// headers that are always redacted are
+pub fn get_function_app_redaction(conn: &Connection, id: &Uuid) -> Result<Option<RedactionRules>> {
+    let rules: Option<String> = conn.query_row(
+        "SELECT redaction_rules FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    match rules {
+        Some(rules) => match serde_json::from_str(&rules) {
+            Ok(rules) => Ok(Some(rules)),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Sets the timer trigger for a function app, or turns the timer trigger off if the trigger is None
+pub fn set_function_app_timer_trigger(conn: &Connection, id: &Uuid, trigger: &Option<TimerTrigger>) -> Result<()> {
+    let trigger = match trigger {
+        Some(trigger) => match serde_json::to_string(trigger) {
+            Ok(trigger) => Some(trigger),
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        },
+        None => None,
+    };
+
+    conn.execute(
+        "UPDATE function_apps SET timer_trigger = ?1 WHERE id = ?2",
+        rusqlite::params![trigger, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Parses a timer trigger stored as JSON
+fn parse_timer_trigger(trigger: Option<String>) -> Result<Option<TimerTrigger>> {
+    match trigger {
+        Some(trigger) => match serde_json::from_str(&trigger) {
+            Ok(trigger) => Ok(Some(trigger)),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Gets the timer trigger for a function app, or None if it doesn't have one
+pub fn get_function_app_timer_trigger(conn: &Connection, id: &Uuid) -> Result<Option<TimerTrigger>> {
+    let trigger: Option<String> = conn.query_row(
+        "SELECT timer_trigger FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    parse_timer_trigger(trigger)
+}
+
+/// Gets the IDs and names of all the function apps with a timer trigger across all namespaces, along with their triggers
+pub fn get_all_timer_triggers() -> Result<Vec<(Uuid, String, TimerTrigger)>, String> {
+    let mut triggers = Vec::new();
+
+    for conn in create_all_connections()? {
+        let mut stmt = match conn.prepare("SELECT id, name, timer_trigger FROM function_apps WHERE timer_trigger IS NOT NULL") {
+            Ok(stmt) => stmt,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let rows = stmt.query_map([], |row| {
+            let id: String = row.get(0)?;
+            Ok((id, row.get::<_, String>(1)?, parse_timer_trigger(row.get(2)?)?))
+        });
+
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        for row in rows {
+            match row {
+                Ok((id, name, Some(trigger))) => match Uuid::parse_str(&id) {
+                    Ok(id) => triggers.push((id, name, trigger)),
+                    Err(e) => return Err(e.to_string()),
+                },
+                Ok((_, _, None)) => {},
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    Ok(triggers)
+}
+
+/// Sets the scale profiles for a function app. An empty list removes them
+pub fn set_function_app_scale_profiles(conn: &Connection, id: &Uuid, profiles: &[ScaleProfile]) -> Result<()> {
+    let profiles = match profiles.is_empty() {
+        true => None,
+        false => match serde_json::to_string(profiles) {
+            Ok(profiles) => Some(profiles),
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        },
+    };
+
+    conn.execute(
+        "UPDATE function_apps SET scale_profiles = ?1 WHERE id = ?2",
+        rusqlite::params![profiles, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Parses scale profiles stored as JSON
+fn parse_scale_profiles(profiles: Option<String>) -> Result<Vec<ScaleProfile>> {
+    match profiles {
+        Some(profiles) => match serde_json::from_str(&profiles) {
+            Ok(profiles) => Ok(profiles),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Gets the scale profiles for a function app
+pub fn get_function_app_scale_profiles(conn: &Connection, id: &Uuid) -> Result<Vec<ScaleProfile>> {
+    let profiles: Option<String> = conn.query_row(
+        "SELECT scale_profiles FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    parse_scale_profiles(profiles)
+}
+
+/// Gets the IDs and names of all the function apps with scale profiles across all namespaces, along with their profiles
+pub fn get_all_scale_profiles() -> Result<Vec<(Uuid, String, Vec<ScaleProfile>)>, String> {
+    let mut all_profiles = Vec::new();
+
+    for conn in create_all_connections()? {
+        let mut stmt = match conn.prepare("SELECT id, name, scale_profiles FROM function_apps WHERE scale_profiles IS NOT NULL") {
+            Ok(stmt) => stmt,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let rows = stmt.query_map([], |row| {
+            let id: String = row.get(0)?;
+            Ok((id, row.get::<_, String>(1)?, parse_scale_profiles(row.get(2)?)?))
+        });
+
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        for row in rows {
+            match row {
+                Ok((id, name, profiles)) => match Uuid::parse_str(&id) {
+                    Ok(id) => all_profiles.push((id, name, profiles)),
+                    Err(e) => return Err(e.to_string()),
+                },
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    Ok(all_profiles)
+}
+
+/// Sets the destinations a function app can call through the egress proxy, or lets it call any destination if the
+/// allowlist is None
+pub fn set_function_app_egress_allowlist(conn: &Connection, id: &Uuid, allowlist: &Option<Vec<String>>) -> Result<()> {
+    let allowlist = match allowlist {
+        Some(allowlist) => match serde_json::to_string(allowlist) {
+            Ok(allowlist) => Some(allowlist),
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        },
+        None => None,
+    };
+
+    conn.execute(
+        "UPDATE function_apps SET egress_allowlist = ?1 WHERE id = ?2",
+        rusqlite::params![allowlist, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Sets the secret a function app's containers give the egress proxy, unless one has already been issued
+pub fn issue_function_app_proxy_secret(conn: &Connection, id: &Uuid, secret: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE function_apps SET proxy_secret = ?1 WHERE id = ?2 AND proxy_secret IS NULL",
+        rusqlite::params![secret, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the secret a function app's containers give the egress proxy, or None if one hasn't been issued
+pub fn get_function_app_proxy_secret(conn: &Connection, id: &Uuid) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT proxy_secret FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Gets the destinations a function app can call through the egress proxy, or None if it can call any destination
+pub fn get_function_app_egress_allowlist(conn: &Connection, id: &Uuid) -> Result<Option<Vec<String>>> {
+    let allowlist: Option<String> = conn.query_row(
+        "SELECT egress_allowlist FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    match allowlist {
+        Some(allowlist) => match serde_json::from_str(&allowlist) {
+            Ok(allowlist) => Ok(Some(allowlist)),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Sets how many recent requests to record for a function app, or stops recording if the capacity is None
+pub fn set_function_app_recording(conn: &Connection, id: &Uuid, capacity: Option<usize>) -> Result<()> {
+    conn.execute(
+        "UPDATE function_apps SET record_capacity = ?1 WHERE id = ?2",
+        rusqlite::params![capacity, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets how many recent requests to record for a function app, or None if requests are not recorded
+pub fn get_function_app_recording(conn: &Connection, id: &Uuid) -> Result<Option<usize>> {
+    conn.query_row(
+        "SELECT record_capacity FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Sets how many bytes of request and response bodies the gateway buffers for a function app before streaming them.
+/// None uses the host default
+pub fn set_function_app_buffering(conn: &Connection, id: &Uuid, request_threshold: Option<u64>, response_threshold: Option<u64>) -> Result<()> {
+    conn.execute(
+        "UPDATE function_apps SET buffer_request_threshold = ?1, buffer_response_threshold = ?2 WHERE id = ?3",
+        rusqlite::params![request_threshold, response_threshold, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets how many bytes of request and response bodies the gateway buffers for a function app before streaming them,
+/// with None for the host default
+pub fn get_function_app_buffering(conn: &Connection, id: &Uuid) -> Result<(Option<u64>, Option<u64>)> {
+    conn.query_row(
+        "SELECT buffer_request_threshold, buffer_response_threshold FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+/// Sets the gRPC services a function app serves through the gRPC gateway. An empty list stops routing calls to it
+pub fn set_function_app_grpc_services(conn: &Connection, id: &Uuid, services: &[String]) -> Result<()> {
+    let services = match services.is_empty() {
+        true => None,
+        false => match serde_json::to_string(services) {
+            Ok(services) => Some(services),
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        },
+    };
+
+    conn.execute(
+        "UPDATE function_apps SET grpc_services = ?1 WHERE id = ?2",
+        rusqlite::params![services, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the gRPC services a function app serves through the gRPC gateway
+pub fn get_function_app_grpc_services(conn: &Connection, id: &Uuid) -> Result<Vec<String>> {
+    let services: Option<String> = conn.query_row(
+        "SELECT grpc_services FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    match services {
+        Some(services) => match serde_json::from_str(&services) {
+            Ok(services) => Ok(services),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Sets the names of the function apps a function app depends on. An empty list removes the dependencies
+pub fn set_function_app_dependencies(conn: &Connection, id: &Uuid, depends_on: &[String]) -> Result<()> {
+    let depends_on = match depends_on.is_empty() {
+        true => None,
+        false => match serde_json::to_string(depends_on) {
+            Ok(depends_on) => Some(depends_on),
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        },
+    };
+
+    conn.execute(
+        "UPDATE function_apps SET depends_on = ?1 WHERE id = ?2",
+        rusqlite::params![depends_on, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the names of the function apps a function app depends on
+pub fn get_function_app_dependencies(conn: &Connection, id: &Uuid) -> Result<Vec<String>> {
+    let depends_on: Option<String> = conn.query_row(
+        "SELECT depends_on FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    match depends_on {
+        Some(depends_on) => match serde_json::from_str(&depends_on) {
+            Ok(depends_on) => Ok(depends_on),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Gets the names of the apps each function app depends on in every namespace, keyed by app name. Apps without
+/// dependencies aren't included
+pub fn get_all_dependencies() -> Result<HashMap<String, Vec<String>>, String> {
+    let mut dependencies = HashMap::new();
+
+    for conn in create_all_connections()? {
+        let mut stmt = conn.prepare("SELECT name, depends_on FROM function_apps WHERE depends_on IS NOT NULL").map_err(|e| e.to_string())?;
+        let apps = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))).map_err(|e| e.to_string())?;
+
+        for app in apps {
+            let (name, depends_on) = app.map_err(|e| e.to_string())?;
+            let depends_on: Vec<String> = serde_json::from_str(&depends_on).map_err(|e| e.to_string())?;
+            dependencies.insert(name, depends_on);
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// Records a new build of a function app, clearing the error from any earlier build
+pub fn set_function_app_build(conn: &Connection, id: &Uuid, build_id: &Uuid) -> Result<()> {
+    conn.execute(
+        "UPDATE function_apps SET build_id = ?1, build_error = NULL WHERE id = ?2",
+        rusqlite::params![build_id.to_string(), id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Records why the latest build of a function app failed or was cancelled, such as the compiler output
+pub fn set_function_app_build_error(conn: &Connection, id: &Uuid, error: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE function_apps SET build_error = ?1 WHERE id = ?2",
+        rusqlite::params![error, id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the ID of the latest build of a function app and why it failed, if it did
+pub fn get_function_app_build(conn: &Connection, id: &Uuid) -> Result<(Option<Uuid>, Option<String>)> {
+    let (build_id, build_error): (Option<String>, Option<String>) = conn.query_row(
+        "SELECT build_id, build_error FROM function_apps WHERE id = ?",
+        [id.to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let build_id = match build_id {
+        Some(build_id) => match Uuid::parse_str(&build_id) {
+            Ok(build_id) => Some(build_id),
+            Err(e) => return Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => None,
+    };
+
+    Ok((build_id, build_error))
+}
+
+/// Finds the function app that serves the given gRPC service in any namespace, returning a connection to the
+/// database that holds it and its ID, or None if no app serves the service
+pub fn find_app_for_grpc_service(service: &str) -> Result<Option<(DbConnection, Uuid)>, String> {
+    for conn in create_all_connections()? {
+        let mut found = None;
+        {
+            let mut stmt = conn.prepare("SELECT id, grpc_services FROM function_apps WHERE grpc_services IS NOT NULL").map_err(|e| e.to_string())?;
+            let apps = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))).map_err(|e| e.to_string())?;
+
+            for app in apps {
+                let (id, services) = app.map_err(|e| e.to_string())?;
+                let services: Vec<String> = serde_json::from_str(&services).unwrap_or_default();
+                if services.iter().any(|s| s == service) {
+                    found = Some(Uuid::parse_str(&id).map_err(|e| e.to_string())?);
+                    break;
+                }
+            }
+        }
+
+        if let Some(id) = found {
+            return Ok(Some((conn, id)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Gets a server wide setting, or None if it is not set
+fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
+    match conn.query_row("SELECT value FROM settings WHERE key = ?", [key], |row| row.get(0)) {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Sets a server wide setting, removing it if the value is None
+fn set_setting(conn: &Connection, key: &str, value: Option<&str>) -> Result<()> {
+    match value {
+        Some(value) => conn.execute("INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = excluded.value", [key, value])?,
+        None => conn.execute("DELETE FROM settings WHERE key = ?", [key])?,
+    };
+
+    Ok(())
+}
+
+/// Gets the name of the default app that receives requests that don't match any other route
+pub fn get_default_app(conn: &Connection) -> Result<Option<String>> {
+    get_setting(conn, DEFAULT_APP_SETTING)
+}
+
+/// Sets the name of the default app, or clears it if the name is None
+pub fn set_default_app(conn: &Connection, name: Option<&str>) -> Result<()> {
+    set_setting(conn, DEFAULT_APP_SETTING, name)
+}
+
+/// Gets the default settings for the apps in a namespace. A namespace without defaults has none of them set
+pub fn get_namespace_defaults(conn: &Connection, namespace: &str) -> Result<NamespaceDefaults> {
+    let defaults = conn.query_row(
+        "SELECT buffer_request_threshold, buffer_response_threshold, egress_allowlist, max_replicas FROM namespace_defaults WHERE namespace = ?",
+        [namespace],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, Option<String>>(2)?, row.get(3)?)),
+    );
+
+    let (buffer_request_threshold, buffer_response_threshold, egress_allowlist, max_replicas) = match defaults {
+        Ok(defaults) => defaults,
+        Err(Error::QueryReturnedNoRows) => return Ok(NamespaceDefaults::default()),
+        Err(e) => return Err(e),
+    };
+
+    let egress_allowlist = match egress_allowlist {
+        Some(allowlist) => match serde_json::from_str(&allowlist) {
+            Ok(allowlist) => Some(allowlist),
+            Err(e) => return Err(Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => None,
+    };
+
+    Ok(NamespaceDefaults { buffer_request_threshold, buffer_response_threshold, egress_allowlist, max_replicas })
+}
+
+/// Sets the default settings for the apps in a namespace, replacing any that were set before
+pub fn set_namespace_defaults(conn: &Connection, namespace: &str, defaults: &NamespaceDefaults) -> Result<()> {
+    let egress_allowlist = match &defaults.egress_allowlist {
+        Some(allowlist) => match serde_json::to_string(allowlist) {
+            Ok(allowlist) => Some(allowlist),
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        },
+        None => None,
+    };
+
+    conn.execute(
+        "INSERT INTO namespace_defaults (namespace, buffer_request_threshold, buffer_response_threshold, egress_allowlist, max_replicas)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (namespace) DO UPDATE SET buffer_request_threshold = excluded.buffer_request_threshold,
+             buffer_response_threshold = excluded.buffer_response_threshold, egress_allowlist = excluded.egress_allowlist,
+             max_replicas = excluded.max_replicas",
+        rusqlite::params![namespace, defaults.buffer_request_threshold, defaults.buffer_response_threshold, egress_allowlist, defaults.max_replicas],
+    )?;
+
+    Ok(())
+}
+
+/// Takes or renews a lease for the given holder, returning true if the holder has the lease until it expires.
+/// The lease can only be taken if no one holds it or the last holder let it expire
+pub fn try_acquire_lease(conn: &Connection, name: &str, holder: &str, now: u64, expires_at: u64) -> Result<bool> {
+    match conn.execute(
+        "INSERT INTO leases (name, holder, expires_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT (name) DO UPDATE SET holder = excluded.holder, expires_at = excluded.expires_at
+         WHERE leases.holder = excluded.holder OR leases.expires_at <= ?4",
+        rusqlite::params![name, holder, expires_at, now],
+    ) {
+        Ok(changed) => Ok(changed > 0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gives up a lease if the given holder has it, so another process can take it straight away
+pub fn release_lease(conn: &Connection, name: &str, holder: &str) -> Result<()> {
+    match conn.execute("DELETE FROM leases WHERE name = ?1 AND holder = ?2", [name, holder]) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets all the leases, with who holds them and when they expire
+pub fn get_leases(conn: &Connection) -> Result<Vec<Lease>> {
+    let mut stmt = conn.prepare("SELECT name, holder, expires_at FROM leases ORDER BY name")?;
+    let leases = stmt.query_map([], |row| {
+        Ok(Lease {
+            name: row.get(0)?,
+            holder: row.get(1)?,
+            expires_at: row.get(2)?,
+            held_by_this_host: false,
+        })
+    })?;
+
+    leases.collect()
+}
+
+/// Adds an API key, storing the hash of the key rather than the key itself
+pub fn add_api_key(conn: &Connection, name: &str, key_hash: &str, created_at: u64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO api_keys (name, key_hash, created_at) VALUES (?1, ?2, ?3)",
+        rusqlite::params![name, key_hash, created_at],
+    )?;
+
+    Ok(())
+}
+
+/// Revokes the API key with the given name. Returns false if there is no key with that name that isn't already revoked
+pub fn revoke_api_key(conn: &Connection, name: &str, revoked_at: u64) -> Result<bool> {
+    let revoked = conn.execute(
+        "UPDATE api_keys SET revoked_at = ?2 WHERE name = ?1 AND revoked_at IS NULL",
+        rusqlite::params![name, revoked_at],
+    )?;
+
+    Ok(revoked > 0)
+}
+
+/// Gets if any API keys have been created. Revoked keys count, so revoking the last key doesn't open up the API
+pub fn has_api_keys(conn: &Connection) -> Result<bool> {
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM api_keys)", [], |row| row.get(0))
+}
+
+/// Gets the name of the API key with the hash, or None if there is no key with the hash or it has been revoked
+pub fn get_valid_api_key_name(conn: &Connection, key_hash: &str) -> Result<Option<String>> {
+    match conn.query_row("SELECT name FROM api_keys WHERE key_hash = ?1 AND revoked_at IS NULL", [key_hash], |row| row.get(0)) {
+        Ok(name) => Ok(Some(name)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets all the API keys, including revoked ones, without their hashes
+pub fn get_api_keys(conn: &Connection) -> Result<Vec<ApiKey>> {
+    let mut stmt = conn.prepare("SELECT name, created_at, revoked_at FROM api_keys ORDER BY created_at")?;
+    let keys = stmt.query_map([], |row| {
+        Ok(ApiKey {
+            name: row.get(0)?,
+            created_at: row.get(1)?,
+            revoked_at: row.get(2)?,
+        })
+    })?;
+
+    keys.collect()
+}
+
+/// Records a completed build so the duration can be used to estimate build queue wait times
+pub fn add_build(conn: &Connection, id: &Uuid, started_at: u64, duration: u64, succeeded: bool) -> Result<()> {
+    match conn.execute(
+        "INSERT INTO builds (function_app_id, started_at, duration, succeeded) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![id.to_string(), started_at, duration, succeeded],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Adds a deployment, returning the deployment number. Deployments are numbered from 1 for each function app.
+/// Deployments that don't need approval are approved when they are added
+pub fn add_deployment(conn: &Connection, id: &Uuid, created_at: u64, approved: bool, phases: &DeployPhases, content_hash: &str) -> Result<u32> {
+    let number: u32 = conn.query_row(
+        "SELECT COALESCE(MAX(number), 0) + 1 FROM deployments WHERE function_app_id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    let approved_at = if approved { Some(created_at) } else { None };
+    conn.execute(
+        "INSERT INTO deployments (function_app_id, number, created_at, approved, approved_at, received_at, extracted_at,
+                                  build_started_at, export_started_at, built_at, content_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        rusqlite::params![
+            id.to_string(),
+            number,
+            created_at,
+            approved,
+            approved_at,
+            phases.received_at,
+            phases.extracted_at,
+            phases.build_started_at,
+            phases.export_started_at,
+            phases.built_at,
+            content_hash,
+        ],
+    )?;
+
+    Ok(number)
+}
+
+/// Stores the software bill of materials for a deployment
+pub fn set_deployment_sbom(conn: &Connection, id: &Uuid, number: u32, sbom: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE deployments SET sbom = ?1 WHERE function_app_id = ?2 AND number = ?3",
+        rusqlite::params![sbom, id.to_string(), number],
+    )?;
+
+    Ok(())
+}
+
+/// Stores the README from the code for a deployment
+pub fn set_deployment_readme(conn: &Connection, id: &Uuid, number: u32, readme: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE deployments SET readme = ?1 WHERE function_app_id = ?2 AND number = ?3",
+        rusqlite::params![readme, id.to_string(), number],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the README from the code for the latest deployment of a function app, or None if the app has never been
+/// deployed or the code for the latest deployment didn't have a README
+pub fn get_latest_readme(conn: &Connection, id: &Uuid) -> Result<Option<String>> {
+    match conn.query_row(
+        "SELECT readme FROM deployments WHERE function_app_id = ? ORDER BY number DESC LIMIT 1",
+        [id.to_string()],
+        |row| row.get(0),
+    ) {
+        Ok(readme) => Ok(readme),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets the number of the latest deployment for a function app, or None if the app has never been deployed
+pub fn get_latest_deployment(conn: &Connection, id: &Uuid) -> Result<Option<u32>> {
+    conn.query_row(
+        "SELECT MAX(number) FROM deployments WHERE function_app_id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Gets the number, approval, and content hash of the latest deployment for a function app, or None if the app has
+/// never been deployed or the latest deployment was made before content hashes were stored
+pub fn get_latest_deployment_content_hash(conn: &Connection, id: &Uuid) -> Result<Option<(u32, bool, String)>> {
+    match conn.query_row(
+        "SELECT number, approved, content_hash FROM deployments WHERE function_app_id = ? ORDER BY number DESC LIMIT 1",
+        [id.to_string()],
+        |row| Ok((row.get::<_, u32>(0)?, row.get::<_, bool>(1)?, row.get::<_, Option<String>>(2)?)),
+    ) {
+        Ok((number, approved, Some(content_hash))) => Ok(Some((number, approved, content_hash))),
+        Ok((_, _, None)) => Ok(None),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Stores the signature for the image built for a deployment
+pub fn set_deployment_signature(conn: &Connection, id: &Uuid, number: u32, image_digest: &str, payload: &str, signature: &str, signed_at: u64) -> Result<()> {
+    conn.execute(
+        "UPDATE deployments SET image_digest = ?1, signature_payload = ?2, signature = ?3, signed_at = ?4
+         WHERE function_app_id = ?5 AND number = ?6",
+        rusqlite::params![image_digest, payload, signature, signed_at, id.to_string(), number],
+    )?;
+
+    Ok(())
+}
+
+/// The signature for the image built for a deployment
+pub struct DeploymentSignature {
+    // The deployment number
+    pub number: u32,
+
+    // The ID of the image that was signed
+    pub image_digest: String,
+
+    // The payload that was signed, in the cosign simple signing format
+    pub payload: String,
+
+    // The signature, base64 encoded
+    pub signature: String,
+
+    // When the image was signed, in seconds since the Unix epoch
+    pub signed_at: u64,
+}
+
+/// Gets the signature for the latest deployment of a function app, or None if the latest deployment isn't signed
+/// or the app has never been deployed
+pub fn get_latest_deployment_signature(conn: &Connection, id: &Uuid) -> Result<Option<DeploymentSignature>> {
+    let result = conn.query_row(
+        "SELECT number, image_digest, signature_payload, signature, signed_at FROM deployments
+         WHERE function_app_id = ? ORDER BY number DESC LIMIT 1",
+        [id.to_string()],
+        |row| {
+            // Deployments from before image signing was added, or that failed to sign, have no signature
+            let signed_at: Option<u64> = row.get(4)?;
+            match signed_at {
+                Some(signed_at) => Ok(Some(DeploymentSignature {
+                    number: row.get(0)?,
+                    image_digest: row.get(1)?,
+                    payload: row.get(2)?,
+                    signature: row.get(3)?,
+                    signed_at,
+                })),
+                None => Ok(None),
+            }
+        },
+    );
+
+    match result {
+        Ok(signature) => Ok(signature),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets the software bill of materials for a deployment. The outer option is None if the deployment doesn't exist,
+/// and the inner option is None if no bill of materials was generated for it
+pub fn get_deployment_sbom(conn: &Connection, id: &Uuid, number: u32) -> Result<Option<Option<String>>> {
+    match conn.query_row(
+        "SELECT sbom FROM deployments WHERE function_app_id = ?1 AND number = ?2",
+        rusqlite::params![id.to_string(), number],
+        |row| row.get(0),
+    ) {
+        Ok(sbom) => Ok(Some(sbom)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets the number of the deployment waiting for approval, or None if the latest deployment has been approved.
+/// Only the latest deployment can be waiting, as each upload replaces the image
+pub fn get_pending_deployment(conn: &Connection, id: &Uuid) -> Result<Option<u32>> {
+    match conn.query_row(
+        "SELECT number, approved FROM deployments WHERE function_app_id = ? ORDER BY number DESC LIMIT 1",
+        [id.to_string()],
+        |row| Ok((row.get::<_, u32>(0)?, row.get::<_, bool>(1)?)),
+    ) {
+        Ok((number, false)) => Ok(Some(number)),
+        Ok((_, true)) => Ok(None),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets the deployments of a function app, most recent first
+pub fn get_deployments(conn: &Connection, id: &Uuid) -> Result<Vec<Deployment>> {
+    let mut stmt = conn.prepare(
+        "SELECT number, created_at, approved, approved_at, image_digest, signed_at, sbom IS NOT NULL, received_at, extracted_at,
+                build_started_at, export_started_at, built_at, promoted_from, promoted_to FROM deployments
+         WHERE function_app_id = ? ORDER BY number DESC",
+    )?;
+
+    let deployments = stmt.query_map([id.to_string()], |row| {
+        Ok(Deployment {
+            number: row.get(0)?,
+            created_at: row.get(1)?,
+            approved: row.get(2)?,
+            approved_at: row.get(3)?,
+            image_digest: row.get(4)?,
+            signed_at: row.get(5)?,
+            has_sbom: row.get(6)?,
+            phases: DeployPhases {
+                received_at: row.get(7)?,
+                extracted_at: row.get(8)?,
+                build_started_at: row.get(9)?,
+                export_started_at: row.get(10)?,
+                built_at: row.get(11)?,
+            },
+            promoted_from: parse_promotion(row.get(12)?)?,
+            promoted_to: parse_promotions(row.get(13)?)?,
+        })
+    })?;
+
+    deployments.collect()
+}
+
+/// Parses where a deployment was promoted from, stored as JSON
+fn parse_promotion(promotion: Option<String>) -> Result<Option<Promotion>> {
+    match promotion {
+        Some(promotion) => match serde_json::from_str(&promotion) {
+            Ok(promotion) => Ok(Some(promotion)),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parses where a deployment has been promoted to, stored as JSON
+fn parse_promotions(promotions: Option<String>) -> Result<Vec<Promotion>> {
+    match promotions {
+        Some(promotions) => match serde_json::from_str(&promotions) {
+            Ok(promotions) => Ok(promotions),
+            Err(e) => Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        },
+        None => Ok(Vec::new()),
+    }
+}
+
+/// What is stored for a deployment, to export it so it can be promoted to another host
+pub struct StoredExport {
+    // Whether the deployment is approved to run
+    pub approved: bool,
+
+    // The ID of the image built for the deployment, if it was signed
+    pub image_digest: Option<String>,
+
+    // The SHA-256 hash of the code and build options, if the deployment was made after hashes were stored
+    pub content_hash: Option<String>,
+
+    // The bill of materials generated for the deployment, if there is one
+    pub sbom: Option<String>,
+
+    // The README from the code for the deployment, if it had one
+    pub readme: Option<String>,
+}
+
+/// Gets what is stored for a deployment to export it, or None if the deployment doesn't exist
+pub fn get_deployment_export(conn: &Connection, id: &Uuid, number: u32) -> Result<Option<StoredExport>> {
+    match conn.query_row(
+        "SELECT approved, image_digest, content_hash, sbom, readme FROM deployments WHERE function_app_id = ?1 AND number = ?2",
+        rusqlite::params![id.to_string(), number],
+        |row| Ok(StoredExport {
+            approved: row.get(0)?,
+            image_digest: row.get(1)?,
+            content_hash: row.get(2)?,
+            sbom: row.get(3)?,
+            readme: row.get(4)?,
+        }),
+    ) {
+        Ok(export) => Ok(Some(export)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Adds a deployment imported from another host, returning the deployment number. The deployment records where it
+/// was promoted from, along with the bill of materials and README exported with it
+pub fn add_imported_deployment(conn: &Connection, id: &Uuid, created_at: u64, export: &StoredExport, promoted_from: &Promotion) -> Result<u32> {
+    let promoted_from = match serde_json::to_string(promoted_from) {
+        Ok(promoted_from) => promoted_from,
+        Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+    };
+
+    let number: u32 = conn.query_row(
+        "SELECT COALESCE(MAX(number), 0) + 1 FROM deployments WHERE function_app_id = ?",
+        [id.to_string()],
+        |row| row.get(0),
+    )?;
+
+    let approved_at = if export.approved { Some(created_at) } else { None };
+    conn.execute(
+        "INSERT INTO deployments (function_app_id, number, created_at, approved, approved_at, content_hash, sbom, readme, promoted_from)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![
+            id.to_string(),
+            number,
+            created_at,
+            export.approved,
+            approved_at,
+            export.content_hash,
+            export.sbom,
+            export.readme,
+            promoted_from,
+        ],
+    )?;
+
+    Ok(number)
+}
+
+/// Records that a deployment was promoted to another host. Returns false if the deployment doesn't exist
+pub fn add_deployment_promotion(conn: &mut Connection, id: &Uuid, number: u32, promotion: &Promotion) -> Result<bool> {
+    with_transaction(conn, |tx| {
+        let promotions: Option<String> = match tx.query_row(
+            "SELECT promoted_to FROM deployments WHERE function_app_id = ?1 AND number = ?2",
+            rusqlite::params![id.to_string(), number],
+            |row| row.get(0),
+        ) {
+            Ok(promotions) => promotions,
+            Err(Error::QueryReturnedNoRows) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let mut promotions = parse_promotions(promotions)?;
+        promotions.push(promotion.clone());
+
+        let promotions = match serde_json::to_string(&promotions) {
+            Ok(promotions) => promotions,
+            Err(e) => return Err(Error::ToSqlConversionFailure(Box::new(e))),
+        };
+
+        tx.execute(
+            "UPDATE deployments SET promoted_to = ?1 WHERE function_app_id = ?2 AND number = ?3",
+            rusqlite::params![promotions, id.to_string(), number],
+        )?;
+
+        Ok(true)
+    })
+}
+
+/// Approves a deployment so the function app can be started
+pub fn approve_deployment(conn: &Connection, id: &Uuid, number: u32, approved_at: u64) -> Result<()> {
+    conn.execute(
+        "UPDATE deployments SET approved = 1, approved_at = ?1 WHERE function_app_id = ?2 AND number = ?3",
+        rusqlite::params![approved_at, id.to_string(), number],
+    )?;
+
+    Ok(())
+}
+
+/// Records a completed build and sets the status of the function app to ready or error depending on the result.
+/// Both updates are made in one transaction so the build history and app status always agree
+pub fn complete_build(conn: &mut Connection, id: &Uuid, started_at: u64, duration: u64, succeeded: bool) -> Result<()> {
+    let status = if succeeded { FunctionAppStatus::Ready } else { FunctionAppStatus::Error };
+
+    with_transaction(conn, |tx| {
+        add_build(tx, id, started_at, duration, succeeded)?;
+        set_function_app_status(tx, id, &status)
+    })
+}
+
+/// The status history event for an app that was started
+pub const STARTED_EVENT: &str = "started";
+
+/// The status history event for an app that was restarted onto a new container
+pub const RESTARTED_EVENT: &str = "restarted";
+
+/// The status history event for an app that stopped within the grace period
+const CLEAN_STOP_EVENT: &str = "stopped";
+
+/// The status history event for an app that was killed because it didn't stop within the grace period
+const FORCED_STOP_EVENT: &str = "killed";
+
+/// The status history event for an app whose container exited without being stopped
+pub const CRASHED_EVENT: &str = "crashed";
+
+/// Records a started or restarted function app in the status history, with how many ports were tried before its
+/// container started
+pub fn add_start_history(conn: &Connection, id: &Uuid, started_at: u64, event: &str, port_attempts: u32) -> Result<()> {
+    conn.execute(
+        "INSERT INTO status_history (function_app_id, changed_at, status, event, port_attempts) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![id.to_string(), started_at, FunctionAppStatus::Running as u8, event, port_attempts],
+    )?;
+
+    Ok(())
+}
+
+/// Records a stopped function app in the status history and sets the status of the app to ready, so no more
+/// requests are routed to it. Both updates are made in one transaction so the history and app status always agree
+pub fn complete_stop(conn: &mut Connection, id: &Uuid, stop: &AppStop) -> Result<()> {
+    let event = if stop.clean { CLEAN_STOP_EVENT } else { FORCED_STOP_EVENT };
+
+    with_transaction(conn, |tx| {
+        tx.execute(
+            "INSERT INTO status_history (function_app_id, changed_at, status, event, exit_code, duration, grace_period)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                id.to_string(),
+                stop.stopped_at,
+                FunctionAppStatus::Ready as u8,
+                event,
+                stop.exit_code,
+                stop.duration_secs,
+                stop.grace_period_secs
+            ],
+        )?;
+        set_function_app_status(tx, id, &FunctionAppStatus::Ready)
+    })
+}
+
+/// Records a running function app whose container exited without being stopped, with why it exited, and sets the
+/// status of the app to error. This returns false without changing anything if the app is no longer marked as
+/// running, such as if it was stopped while the crash was being checked
+pub fn complete_crash(conn: &mut Connection, id: &Uuid, crashed_at: u64, exit_code: Option<i32>, reason: &str) -> Result<bool> {
+    with_transaction(conn, |tx| {
+        if get_function_app_stored_status(tx, id)? as u8 != FunctionAppStatus::Running as u8 {
+            return Ok(false);
+        }
+
+        tx.execute(
+            "INSERT INTO status_history (function_app_id, changed_at, status, event, exit_code, reason) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![id.to_string(), crashed_at, FunctionAppStatus::Error as u8, CRASHED_EVENT, exit_code, reason],
+        )?;
+        set_function_app_status(tx, id, &FunctionAppStatus::Error)?;
+
+        Ok(true)
+    })
+}
+
+/// Gets how the container for the function app last exited without being stopped, with the memory it was using in
+/// the last resource sample before then, or None if it never has
+pub fn get_last_exit(conn: &Connection, id: &Uuid) -> Result<Option<AppExit>> {
+    let result = conn.query_row(
+        "SELECT changed_at, exit_code, reason,
+                (SELECT memory_bytes FROM resource_samples WHERE function_app_id = ?1 AND sampled_at <= changed_at ORDER BY sampled_at DESC LIMIT 1)
+         FROM status_history WHERE function_app_id = ?1 AND event = ?2 ORDER BY changed_at DESC, rowid DESC LIMIT 1",
+        rusqlite::params![id.to_string(), CRASHED_EVENT],
+        |row| {
+            let exit_code: Option<i32> = row.get(1)?;
+            let reason: Option<String> = row.get(2)?;
+
+            // Crashes recorded before reasons were added only have the exit code
+            let reason = match (reason, exit_code) {
+                (Some(reason), _) => reason,
+                (None, Some(exit_code)) => format!("exited with code {}", exit_code),
+                (None, None) => "exited".to_string(),
+            };
+
+            Ok(AppExit {
+                exited_at: row.get(0)?,
+                exit_code,
+                reason,
+                last_memory_bytes: row.get(3)?,
+            })
+        },
+    );
+
+    match result {
+        Ok(exit) => Ok(Some(exit)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Records a sample of the resources a running function app was using, removing samples older than the given time
+pub fn add_resource_sample(conn: &Connection, id: &Uuid, sample: &ResourceSample, keep_since: u64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO resource_samples (function_app_id, sampled_at, containers, cpu_percent, memory_bytes, memory_limit_bytes, network_rx_bytes, network_tx_bytes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        rusqlite::params![
+            id.to_string(),
+            sample.sampled_at,
+            sample.containers,
+            sample.cpu_percent,
+            sample.memory_bytes,
+            sample.memory_limit_bytes,
+            sample.network_rx_bytes,
+            sample.network_tx_bytes
+        ],
+    )?;
+
+    conn.execute(
+        "DELETE FROM resource_samples WHERE function_app_id = ?1 AND sampled_at < ?2",
+        rusqlite::params![id.to_string(), keep_since],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the most recent resource samples for a function app, newest first
+pub fn get_resource_samples(conn: &Connection, id: &Uuid, last: usize) -> Result<Vec<ResourceSample>> {
+    let mut stmt = conn.prepare(
+        "SELECT sampled_at, containers, cpu_percent, memory_bytes, memory_limit_bytes, network_rx_bytes, network_tx_bytes FROM resource_samples
+         WHERE function_app_id = ?1 ORDER BY sampled_at DESC, rowid DESC LIMIT ?2",
+    )?;
+
+    let samples = stmt.query_map(rusqlite::params![id.to_string(), last as i64], |row| {
+        Ok(ResourceSample {
+            sampled_at: row.get(0)?,
+            containers: row.get(1)?,
+            cpu_percent: row.get(2)?,
+            memory_bytes: row.get(3)?,
+            memory_limit_bytes: row.get(4)?,
+            network_rx_bytes: row.get(5)?,
+            network_tx_bytes: row.get(6)?,
+        })
+    })?;
+
+    samples.collect()
+}
+
+/// Gets the latest entry in the status history of the function app, as when it happened, the event, and the exit
+/// code, or None if there is no history
+pub fn get_last_status_event(conn: &Connection, id: &Uuid) -> Result<Option<(u64, String, Option<i32>)>> {
+    let result = conn.query_row(
+        "SELECT changed_at, event, exit_code FROM status_history WHERE function_app_id = ? ORDER BY changed_at DESC, rowid DESC LIMIT 1",
+        [id.to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    );
+
+    match result {
+        Ok(event) => Ok(Some(event)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets when each deployment of the function app made since the given time was created, oldest first
+pub fn get_deployment_times_since(conn: &Connection, id: &Uuid, since: u64) -> Result<Vec<u64>> {
+    let mut stmt = conn.prepare(
+        "SELECT created_at FROM deployments WHERE function_app_id = ?1 AND created_at >= ?2 ORDER BY created_at",
+    )?;
+    let times = stmt.query_map(rusqlite::params![id.to_string(), since], |row| row.get(0))?;
+
+    times.collect()
+}
+
+/// Gets the builds of the function app started since the given time, as when each started, how long it took in
+/// seconds, and whether it succeeded, oldest first
+pub fn get_builds_since(conn: &Connection, id: &Uuid, since: u64) -> Result<Vec<(u64, u64, bool)>> {
+    let mut stmt = conn.prepare(
+        "SELECT started_at, duration, succeeded FROM builds WHERE function_app_id = ?1 AND started_at >= ?2
+         ORDER BY started_at, rowid",
+    )?;
+    let builds = stmt.query_map(rusqlite::params![id.to_string(), since], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+
+    builds.collect()
+}
+
+/// Gets the entries in the status history of the function app made since the given time, as when each happened and
+/// the stored value of the status the app changed to, oldest first
+pub fn get_status_changes_since(conn: &Connection, id: &Uuid, since: u64) -> Result<Vec<(u64, u8)>> {
+    let mut stmt = conn.prepare(
+        "SELECT changed_at, status FROM status_history WHERE function_app_id = ?1 AND changed_at >= ?2
+         ORDER BY changed_at, rowid",
+    )?;
+    let changes = stmt.query_map(rusqlite::params![id.to_string(), since], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    changes.collect()
+}
+
+/// Gets if the latest build of the function app succeeded, or None if it has never been built
+pub fn get_last_build_succeeded(conn: &Connection, id: &Uuid) -> Result<Option<bool>> {
+    let result = conn.query_row(
+        "SELECT succeeded FROM builds WHERE function_app_id = ? ORDER BY started_at DESC, rowid DESC LIMIT 1",
+        [id.to_string()],
+        |row| row.get(0),
+    );
+
+    match result {
+        Ok(succeeded) => Ok(Some(succeeded)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets when the function app was last started or restarted, or None if it never has
+pub fn get_last_start(conn: &Connection, id: &Uuid) -> Result<Option<AppStart>> {
+    let result = conn.query_row(
+        "SELECT changed_at, port_attempts FROM status_history
+         WHERE function_app_id = ?1 AND event IN (?2, ?3) ORDER BY changed_at DESC, rowid DESC LIMIT 1",
+        rusqlite::params![id.to_string(), STARTED_EVENT, RESTARTED_EVENT],
+        |row| {
+            // Starts recorded before the attempts were added were all on the first port
+            let port_attempts: Option<u32> = row.get(1)?;
+            Ok(AppStart {
+                started_at: row.get(0)?,
+                port_attempts: port_attempts.unwrap_or(1),
+            })
+        },
+    );
+
+    match result {
+        Ok(start) => Ok(Some(start)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets how the function app last stopped, or None if it has never been stopped
+pub fn get_last_stop(conn: &Connection, id: &Uuid) -> Result<Option<AppStop>> {
+    let result = conn.query_row(
+        "SELECT changed_at, event, exit_code, duration, grace_period FROM status_history
+         WHERE function_app_id = ?1 AND event IN (?2, ?3) ORDER BY changed_at DESC, rowid DESC LIMIT 1",
+        rusqlite::params![id.to_string(), CLEAN_STOP_EVENT, FORCED_STOP_EVENT],
+        |row| {
+            let event: String = row.get(1)?;
+            Ok(AppStop {
+                stopped_at: row.get(0)?,
+                clean: event == CLEAN_STOP_EVENT,
+                exit_code: row.get(2)?,
+                duration_secs: row.get(3)?,
+                grace_period_secs: row.get(4)?,
+            })
+        },
+    );
+
+    match result {
+        Ok(stop) => Ok(Some(stop)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Records an invocation of a trigger in the trigger history of a function app
+pub fn add_trigger_run(conn: &Connection, id: &Uuid, run: &TriggerRun) -> Result<()> {
+    conn.execute(
+        "INSERT INTO trigger_runs (function_app_id, trigger, started_at, duration_ms, status_code, error, manual)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            id.to_string(),
+            run.trigger,
+            run.started_at,
+            run.duration_ms,
+            run.status_code,
+            run.error,
+            run.manual
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the most recent trigger invocations for a function app, newest first, optionally only for one trigger
+pub fn get_trigger_runs(conn: &Connection, id: &Uuid, trigger: &Option<String>, last: usize) -> Result<Vec<TriggerRun>> {
+    let mut stmt = conn.prepare(
+        "SELECT trigger, started_at, duration_ms, status_code, error, manual FROM trigger_runs
+         WHERE function_app_id = ?1 AND (CAST(?2 AS TEXT) IS NULL OR trigger = ?2) ORDER BY started_at DESC, rowid DESC LIMIT ?3",
+    )?;
+
+    let runs = stmt.query_map(rusqlite::params![id.to_string(), trigger, last as i64], |row| {
+        Ok(TriggerRun {
+            trigger: row.get(0)?,
+            started_at: row.get(1)?,
+            duration_ms: row.get(2)?,
+            status_code: row.get(3)?,
+            error: row.get(4)?,
+            manual: row.get(5)?,
+        })
+    })?;
+
+    runs.collect()
+}
+
+/// Saves the output of a finished build of a function app, removing the oldest logs for the app past the given number
+pub fn add_build_log(conn: &Connection, id: &Uuid, log: &BuildLog, keep: usize) -> Result<()> {
+    conn.execute(
+        "INSERT INTO build_logs (build_id, function_app_id, started_at, finished_at, result, output)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (build_id) DO UPDATE SET function_app_id = excluded.function_app_id, started_at = excluded.started_at,
+         finished_at = excluded.finished_at, result = excluded.result, output = excluded.output",
+        rusqlite::params![
+            log.build_id.to_string(),
+            id.to_string(),
+            log.started_at,
+            log.finished_at.unwrap_or(log.started_at),
+            log.result.as_deref().unwrap_or(BUILD_FAILED),
+            log.output
+        ],
+    )?;
+
+    conn.execute(
+        "DELETE FROM build_logs WHERE function_app_id = ?1 AND build_id NOT IN
+         (SELECT build_id FROM build_logs WHERE function_app_id = ?1 ORDER BY finished_at DESC, rowid DESC LIMIT ?2)",
+        rusqlite::params![id.to_string(), keep as i64],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the saved output of a build of a function app, or of its latest finished build if no build is given.
+/// Returns None if the build has no saved log
+pub fn get_build_log(conn: &Connection, id: &Uuid, build_id: &Option<Uuid>) -> Result<Option<BuildLog>> {
+    let result = conn.query_row(
+        "SELECT build_id, started_at, finished_at, result, output FROM build_logs
+         WHERE function_app_id = ?1 AND (CAST(?2 AS TEXT) IS NULL OR build_id = ?2) ORDER BY finished_at DESC, rowid DESC LIMIT 1",
+        rusqlite::params![id.to_string(), build_id.map(|build_id| build_id.to_string())],
+        |row| {
+            let build_id: String = row.get(0)?;
+            let build_id = match Uuid::parse_str(&build_id) {
+                Ok(build_id) => build_id,
+                Err(e) => return Err(Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+            };
+
+            Ok(BuildLog {
+                build_id,
+                started_at: row.get(1)?,
+                finished_at: row.get(2)?,
+                result: row.get(3)?,
+                output: row.get(4)?,
+            })
+        },
+    );
+
+    match result {
+        Ok(log) => Ok(Some(log)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets the average duration in seconds of the most recent successful builds, or None if nothing has been built yet
+pub fn get_average_build_duration(conn: &Connection) -> Result<Option<u64>> {
+    let mut stmt = conn
+        .prepare("SELECT CAST(AVG(duration) AS REAL) FROM (SELECT duration FROM builds WHERE succeeded = 1 ORDER BY started_at DESC LIMIT 10)")?;
+    let mut rows = stmt.query([])?;
+
+    match rows.next()? {
+        Some(row) => {
+            let average: Option<f64> = row.get(0)?;
+            Ok(average.map(|average| average.round() as u64))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Backs up the database for a namespace to the given file
+///
+/// This is only supported when namespace isolation is enabled, as otherwise the namespace shares the main database
+pub fn backup_namespace(namespace: &str, backup_file: &Path) -> Result<(), String> {
+    if !is_namespace_isolation_enabled() {
+        return Err("Namespace isolation is not enabled, so namespaces cannot be backed up separately".to_string());
+    }
+
+    validate_namespace(namespace)?;
+
+    if !get_namespace_db_file(namespace).exists() {
+        return Err(format!("Namespace {} not found", namespace));
+    }
+
+    // Use VACUUM INTO to get a consistent copy even if the database is being written to
+    let conn = create_namespace_connection(namespace)?;
+    match conn.execute("VACUUM INTO ?", [backup_file.to_string_lossy()]) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error backing up namespace {}: {}", namespace, e)),
+    }
+}
+
+/// Restores the database for a namespace from the given file, replacing the existing database
+pub fn restore_namespace(namespace: &str, backup_file: &Path) -> Result<(), String> {
+    if !is_namespace_isolation_enabled() {
+        return Err("Namespace isolation is not enabled, so namespaces cannot be restored separately".to_string());
+    }
+
+    validate_namespace(namespace)?;
+
+    // Check the backup is a valid database with the function apps table before replacing anything
+    let backup_conn = match open_database(backup_file) {
+        Ok(conn) => conn,
+        Err(e) => return Err(format!("Error opening backup: {}", e)),
+    };
+
+    let integrity: Result<String> = backup_conn.query_row("PRAGMA integrity_check", [], |row| row.get(0));
+    match integrity {
+        Ok(result) if result == "ok" => {},
+        _ => return Err("Backup is not a valid database".to_string()),
+    };
+
+    if backup_conn.prepare("SELECT id, name, status, created_at, port FROM function_apps").is_err() {
+        return Err("Backup does not contain any function apps".to_string());
+    }
+
+    // Names are unique across all namespaces, so the apps in the backup can't clash with apps in the others
+    let names = match backup_conn.prepare("SELECT name FROM function_apps")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>>>()) {
+        Ok(names) => names,
+        Err(e) => return Err(format!("Error reading apps from backup: {}", e)),
+    };
+
+    drop(backup_conn);
+
+    if let Some(name) = find_name_in_other_namespaces(&names, namespace)? {
+        return Err(format!("The backup has a function app named {}, which is already registered in another namespace", name));
+    }
+
+    // The backup is copied into the database through a pooled connection, so connections that are open see the
+    // restored contents rather than the file being replaced under them
+    let mut conn = create_namespace_connection(namespace)?;
+    if let Err(e) = conn.restore(backup_file) {
+        return Err(format!("Error restoring namespace {}: {}", namespace, e));
+    }
+
+    // The backup can be from an older version of the host, so make sure it has all the tables we need
+    migrations::migrate(&mut conn)
+}
+
+/// Backs up a database file to another file with VACUUM INTO, which gives a consistent copy even while hosts are
+/// writing to the database. Any existing backup is replaced
+pub fn backup_database_file(file: &Path, backup_file: &Path) -> Result<(), String> {
+    if backup_file.exists() {
+        if let Err(e) = fs::remove_file(backup_file) {
+            return Err(format!("Error removing old backup {}: {}", backup_file.display(), e));
+        }
+    }
+
+    let conn = open_database(file)?;
+    match conn.execute("VACUUM INTO ?", [backup_file.to_string_lossy()]) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error backing up {}: {}", file.display(), e)),
+    }
+}
+
+/// Restores a database file from a backup with SQLite's backup API, which copies the pages under the database's
+/// locks rather than replacing the file, so a database left open by a process that crashed isn't corrupted
+pub fn restore_database_file(file: &Path, backup_file: &Path) -> Result<(), String> {
+    let mut conn = open_database(file)?;
+    match conn.restore(rusqlite::DatabaseName::Main, backup_file, None::<fn(rusqlite::backup::Progress)>) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error restoring {}: {}", file.display(), e)),
+    }
+}
+
+/// Finds the first of the given function app names that is registered outside a namespace's database, in the main
+/// database or the database of another namespace
+fn find_name_in_other_namespaces(names: &[String], namespace: &str) -> Result<Option<String>, String> {
+    let mut connections = vec![create_connection()?];
+    for other in get_namespaces()?.iter().filter(|other| *other != namespace) {
+        connections.push(create_namespace_connection(other)?);
+    }
+
+    for conn in connections.iter() {
+        for name in names {
+            if is_name_in_use(conn, name).map_err(|e| e.to_string())? {
+                return Ok(Some(name.to_string()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Creates the tables we need in a Postgres database if they don't exist. Postgres databases are created with the
+/// latest schema, so they don't need the upgrades for databases created by older versions of the host
+fn create_postgres_tables(conn: &Connection) -> Result<(), String> {
+    // Tables whose rows are read in the order they were added have a rowid column, which SQLite adds to every table
+    let tables = [
+        "CREATE TABLE IF NOT EXISTS function_apps (
+                  id                         TEXT PRIMARY KEY,
+                  name                       TEXT NOT NULL UNIQUE,
+                  status                     BIGINT NOT NULL,
+                  created_at                 BIGINT NOT NULL,
+                  port                       BIGINT NOT NULL,
+                  namespace                  TEXT NOT NULL DEFAULT 'default',
+                  maintenance_message        TEXT,
+                  mirror_config              TEXT,
+                  record_capacity            BIGINT,
+                  timer_trigger              TEXT,
+                  egress_allowlist           TEXT,
+                  buffer_request_threshold   BIGINT,
+                  buffer_response_threshold  BIGINT,
+                  grpc_services              TEXT,
+                  build_id                   TEXT,
+                  build_error                TEXT,
+                  scale_profiles             TEXT,
+                  idle_stopped               BIGINT NOT NULL DEFAULT 0,
+                  last_request_at            BIGINT,
+                  replicas                   BIGINT NOT NULL DEFAULT 1,
+                  depends_on                 TEXT
+                  )",
+        "CREATE TABLE IF NOT EXISTS settings (
+                  key    TEXT PRIMARY KEY,
+                  value  TEXT NOT NULL
+                  )",
+        "CREATE TABLE IF NOT EXISTS leases (
+                  name        TEXT PRIMARY KEY,
+                  holder      TEXT NOT NULL,
+                  expires_at  BIGINT NOT NULL
+                  )",
+        "CREATE TABLE IF NOT EXISTS api_keys (
+                  name        TEXT PRIMARY KEY,
+                  key_hash    TEXT NOT NULL UNIQUE,
+                  created_at  BIGINT NOT NULL,
+                  revoked_at  BIGINT
+                  )",
+        "CREATE TABLE IF NOT EXISTS builds (
+                  rowid            BIGSERIAL,
+                  function_app_id  TEXT NOT NULL,
+                  started_at       BIGINT NOT NULL,
+                  duration         BIGINT NOT NULL,
+                  succeeded        BIGINT NOT NULL
+                  )",
+        "CREATE TABLE IF NOT EXISTS build_logs (
+                  rowid            BIGSERIAL,
+                  build_id         TEXT PRIMARY KEY,
+                  function_app_id  TEXT NOT NULL,
+                  started_at       BIGINT NOT NULL,
+                  finished_at      BIGINT NOT NULL,
+                  result           TEXT NOT NULL,
+                  output           TEXT NOT NULL
+                  )",
+        "CREATE TABLE IF NOT EXISTS status_history (
+                  rowid            BIGSERIAL,
+                  function_app_id  TEXT NOT NULL,
+                  changed_at       BIGINT NOT NULL,
+                  status           BIGINT NOT NULL,
+                  event            TEXT NOT NULL,
+                  exit_code        BIGINT,
+                  duration         BIGINT,
+                  grace_period     BIGINT,
+                  reason           TEXT,
+                  port_attempts    BIGINT
+                  )",
+        "CREATE TABLE IF NOT EXISTS trigger_runs (
+                  rowid            BIGSERIAL,
+                  function_app_id  TEXT NOT NULL,
+                  trigger          TEXT NOT NULL,
+                  started_at       BIGINT NOT NULL,
+                  duration_ms      BIGINT NOT NULL,
+                  status_code      BIGINT,
+                  error            TEXT,
+                  manual           BIGINT NOT NULL
+                  )",
+        "CREATE TABLE IF NOT EXISTS resource_samples (
+                  rowid               BIGSERIAL,
+                  function_app_id     TEXT NOT NULL,
+                  sampled_at          BIGINT NOT NULL,
+                  containers          BIGINT NOT NULL,
+                  cpu_percent         DOUBLE PRECISION NOT NULL,
+                  memory_bytes        BIGINT NOT NULL,
+                  memory_limit_bytes  BIGINT NOT NULL,
+                  network_rx_bytes    BIGINT NOT NULL,
+                  network_tx_bytes    BIGINT NOT NULL
+                  )",
+        "CREATE TABLE IF NOT EXISTS replicas (
+                  rowid            BIGSERIAL,
+                  function_app_id  TEXT NOT NULL,
+                  container_id     TEXT NOT NULL,
+                  port             BIGINT NOT NULL,
+                  started_at       BIGINT NOT NULL
+                  )",
+        "CREATE TABLE IF NOT EXISTS deployments (
+                  function_app_id    TEXT NOT NULL,
+                  number             BIGINT NOT NULL,
+                  created_at         BIGINT NOT NULL,
+                  approved           BIGINT NOT NULL,
+                  approved_at        BIGINT,
+                  sbom               TEXT,
+                  readme             TEXT,
+                  image_digest       TEXT,
+                  signature_payload  TEXT,
+                  signature          TEXT,
+                  signed_at          BIGINT,
+                  received_at        BIGINT,
+                  extracted_at       BIGINT,
+                  build_started_at   BIGINT,
+                  export_started_at  BIGINT,
+                  built_at           BIGINT,
+                  content_hash       TEXT,
+                  promoted_from      TEXT,
+                  promoted_to        TEXT,
+                  PRIMARY KEY (function_app_id, number)
+                  )",
+    ];
+
+    for table in tables {
+        if let Err(e) = conn.execute(table, []) {
+            return Err(format!("Error creating table: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the tables we need in the given database if they don't exist, adding any columns that are missing from
+/// tables created by older hosts. This is the first migration, so it also upgrades databases from before migrations
+pub fn create_tables(conn: &Connection) -> Result<(), String> {
+    if conn.backend() == StorageBackend::Postgres {
+        return create_postgres_tables(conn);
+    }
+
+    // We need a table to store the function app details. Create it if it doesn't exist
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS function_apps (
+                  id          TEXT PRIMARY KEY,
+                  name        TEXT NOT NULL UNIQUE,
+                  status      INTEGER NOT NULL,
+                  created_at  INTEGER NOT NULL,
+                  port        INTEGER NOT NULL,
+                  namespace   TEXT NOT NULL DEFAULT 'default'
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // Databases created before namespaces were added won't have the namespace column, so add it
+    if conn.prepare("SELECT namespace FROM function_apps LIMIT 0").is_err() {
+        let alter_sql = format!("ALTER TABLE function_apps ADD COLUMN namespace TEXT NOT NULL DEFAULT '{}'", DEFAULT_NAMESPACE);
+        if conn.execute(&alter_sql, []).is_err() {
+            return Err("Error adding namespace column".to_string());
+        }
+    }
+
+    // Databases created before maintenance mode was added won't have the maintenance column, so add it.
+    // The app is in maintenance mode when this is set, and the value is the message to show
+    if conn.prepare("SELECT maintenance_message FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN maintenance_message TEXT", []).is_err() {
+        return Err("Error adding maintenance column".to_string());
+    }
+
+    // Databases created before request mirroring was added won't have the mirror column, so add it.
+    // Requests to the app are mirrored when this is set, and the value is the mirror config as JSON
+    if conn.prepare("SELECT mirror_config FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN mirror_config TEXT", []).is_err() {
+        return Err("Error adding mirror column".to_string());
+    }
+
+    // Databases created before request recording was added won't have the recording column, so add it.
+    // Recent requests to the app are recorded when this is set, and the value is how many to keep
+    if conn.prepare("SELECT record_capacity FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN record_capacity INTEGER", []).is_err() {
+        return Err("Error adding recording column".to_string());
+    }
+
+    // Databases created before timer triggers were added won't have the timer trigger column, so add it.
+    // The app is called on a schedule when this is set, and the value is the trigger as JSON
+    if conn.prepare("SELECT timer_trigger FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN timer_trigger TEXT", []).is_err() {
+        return Err("Error adding timer trigger column".to_string());
+    }
+
+    // Databases created before the egress proxy was added won't have the egress allowlist column, so add it.
+    // The app can only call the destinations in the allowlist through the proxy when this is set
+    if conn.prepare("SELECT egress_allowlist FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN egress_allowlist TEXT", []).is_err() {
+        return Err("Error adding egress allowlist column".to_string());
+    }
+
+    // Databases created before body buffering could be tuned won't have the buffer threshold columns, so add them.
+    // The host default is used when these aren't set
+    if conn.prepare("SELECT buffer_request_threshold FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN buffer_request_threshold INTEGER", []).is_err() {
+        return Err("Error adding buffer request threshold column".to_string());
+    }
+
+    if conn.prepare("SELECT buffer_response_threshold FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN buffer_response_threshold INTEGER", []).is_err() {
+        return Err("Error adding buffer response threshold column".to_string());
+    }
+
+    // Databases created before the gRPC gateway was added won't have the gRPC services column, so add it.
+    // This holds the services the app serves, as a JSON list, so the gateway knows where to route calls
+    if conn.prepare("SELECT grpc_services FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN grpc_services TEXT", []).is_err() {
+        return Err("Error adding gRPC services column".to_string());
+    }
+
+    // Databases created before builds ran in the background won't have the build columns, so add them.
+    // These hold the latest build and why it failed, so callers can poll the status for the result
+    if conn.prepare("SELECT build_id FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN build_id TEXT", []).is_err() {
+        return Err("Error adding build ID column".to_string());
+    }
+
+    if conn.prepare("SELECT build_error FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN build_error TEXT", []).is_err() {
+        return Err("Error adding build error column".to_string());
+    }
+
+    // Databases created before scale profiles were added won't have the scale profiles column, so add it.
+    // The app is started and stopped on the profile schedules when this is set, and the value is the profiles as JSON
+    if conn.prepare("SELECT scale_profiles FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN scale_profiles TEXT", []).is_err() {
+        return Err("Error adding scale profiles column".to_string());
+    }
+
+    // Databases created before idle apps were scaled to zero won't have the idle stopped column, so add it.
+    // This is set when an app is stopped for being idle, so the next request starts it again
+    if conn.prepare("SELECT idle_stopped FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN idle_stopped INTEGER NOT NULL DEFAULT 0", []).is_err() {
+        return Err("Error adding idle stopped column".to_string());
+    }
+
+    // Databases created before idle apps were scaled to zero won't have the last request column, so add it.
+    // This is when the gateway last routed a request to the app, written at most every few seconds
+    if conn.prepare("SELECT last_request_at FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN last_request_at INTEGER", []).is_err() {
+        return Err("Error adding last request column".to_string());
+    }
+
+    // Databases created before apps could be scaled out won't have the replicas column, so add it.
+    // This is how many containers run the app when it is started
+    if conn.prepare("SELECT replicas FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN replicas INTEGER NOT NULL DEFAULT 1", []).is_err() {
+        return Err("Error adding replicas column".to_string());
+    }
+
+    // Databases created before apps could depend on each other won't have the dependencies column, so add it.
+    // This holds the names of the apps that are started before this one, as a JSON list
+    if conn.prepare("SELECT depends_on FROM function_apps LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE function_apps ADD COLUMN depends_on TEXT", []).is_err() {
+        return Err("Error adding dependencies column".to_string());
+    }
+
+    // Server wide settings, such as the default app, are stored as key value pairs
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+                  key    TEXT PRIMARY KEY,
+                  value  TEXT NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // Leases make sure background jobs such as the timer scheduler only run in one host process at a time.
+    // Each lease is held by one process until it expires, unless that process renews it
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS leases (
+                  name        TEXT PRIMARY KEY,
+                  holder      TEXT NOT NULL,
+                  expires_at  INTEGER NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // API keys allow access to the management API. Only a hash of each key is stored, and revoked keys are kept
+    // with when they were revoked
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_keys (
+                  name        TEXT PRIMARY KEY,
+                  key_hash    TEXT NOT NULL UNIQUE,
+                  created_at  INTEGER NOT NULL,
+                  revoked_at  INTEGER
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // We also need a table to store the history of builds, used to estimate build times
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS builds (
+                  function_app_id  TEXT NOT NULL,
+                  started_at       INTEGER NOT NULL,
+                  duration         INTEGER NOT NULL,
+                  succeeded        INTEGER NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // The output of the most recent builds of each app, so failed builds can be diagnosed remotely
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS build_logs (
+                  build_id         TEXT PRIMARY KEY,
+                  function_app_id  TEXT NOT NULL,
+                  started_at       INTEGER NOT NULL,
+                  finished_at      INTEGER NOT NULL,
+                  result           TEXT NOT NULL,
+                  output           TEXT NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // The history of the status changes of each app. Stops record if the app stopped cleanly within the
+    // grace period, or had to be killed
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS status_history (
+                  function_app_id  TEXT NOT NULL,
+                  changed_at       INTEGER NOT NULL,
+                  status           INTEGER NOT NULL,
+                  event            TEXT NOT NULL,
+                  exit_code        INTEGER,
+                  duration         INTEGER,
+                  grace_period     INTEGER
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // The history of trigger invocations for each app, whether fired by their schedule or manually
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS trigger_runs (
+                  function_app_id  TEXT NOT NULL,
+                  trigger          TEXT NOT NULL,
+                  started_at       INTEGER NOT NULL,
+                  duration_ms      INTEGER NOT NULL,
+                  status_code      INTEGER,
+                  error            TEXT,
+                  manual           INTEGER NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // Databases created before exit reasons were recorded won't have the reason column, so add it.
+    // This holds why the container for an app exited without being stopped, such as being killed for running out of memory
+    if conn.prepare("SELECT reason FROM status_history LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE status_history ADD COLUMN reason TEXT", []).is_err() {
+        return Err("Error adding reason column".to_string());
+    }
+
+    // Databases created before start attempts were recorded won't have the port_attempts column, so add it.
+    // This holds how many ports were tried before the container for an app started
+    if conn.prepare("SELECT port_attempts FROM status_history LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE status_history ADD COLUMN port_attempts INTEGER", []).is_err() {
+        return Err("Error adding port_attempts column".to_string());
+    }
+
+    // Samples of the resources each running app was using, taken periodically so there is a history to look back on
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS resource_samples (
+                  function_app_id     TEXT NOT NULL,
+                  sampled_at          INTEGER NOT NULL,
+                  containers          INTEGER NOT NULL,
+                  cpu_percent         REAL NOT NULL,
+                  memory_bytes        INTEGER NOT NULL,
+                  memory_limit_bytes  INTEGER NOT NULL,
+                  network_rx_bytes    INTEGER NOT NULL,
+                  network_tx_bytes    INTEGER NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // The containers running an app as well as the one on its port, so requests can be shared between them
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS replicas (
+                  function_app_id  TEXT NOT NULL,
+                  container_id     TEXT NOT NULL,
+                  port             INTEGER NOT NULL,
+                  started_at       INTEGER NOT NULL
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // Each successful build is a deployment. When approval is required, it must be approved before the app can start
+    match conn.execute(
+        "CREATE TABLE IF NOT EXISTS deployments (
+                  function_app_id  TEXT NOT NULL,
+                  number           INTEGER NOT NULL,
+                  created_at       INTEGER NOT NULL,
+                  approved         INTEGER NOT NULL,
+                  approved_at      INTEGER,
+                  PRIMARY KEY (function_app_id, number)
+                  )",
+        [],
+    ) {
+        Ok(_) => {},
+        Err(_) => {
+            return Err("Error creating table".to_string());
+        }
+    };
+
+    // Databases created before bills of materials were added won't have the sbom column, so add it.
+    // This holds the CycloneDX bill of materials generated when the deployment was built
+    if conn.prepare("SELECT sbom FROM deployments LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE deployments ADD COLUMN sbom TEXT", []).is_err() {
+        return Err("Error adding sbom column".to_string());
+    }
+
+    // Databases created before READMEs were served won't have the readme column, so add it.
+    // This holds the README from the code, so callers can find out how to call the app
+    if conn.prepare("SELECT readme FROM deployments LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE deployments ADD COLUMN readme TEXT", []).is_err() {
+        return Err("Error adding readme column".to_string());
+    }
+
+    // Databases created before image signing was added won't have the signature columns, so add them.
+    // These hold the ID of the image built for the deployment and the host's signature for it
+    for (column, column_type) in [("image_digest", "TEXT"), ("signature_payload", "TEXT"), ("signature", "TEXT"), ("signed_at", "INTEGER")] {
+        if conn.prepare(&format!("SELECT {} FROM deployments LIMIT 0", column)).is_err()
+            && conn.execute(&format!("ALTER TABLE deployments ADD COLUMN {} {}", column, column_type), []).is_err() {
+            return Err("Error adding signature columns".to_string());
+        }
+    }
+
+    // Databases created before deployment phases were recorded won't have the phase columns, so add them.
+    // These hold when each phase of the deployment finished
+    for column in ["received_at", "extracted_at", "build_started_at", "export_started_at", "built_at"] {
+        if conn.prepare(&format!("SELECT {} FROM deployments LIMIT 0", column)).is_err()
+            && conn.execute(&format!("ALTER TABLE deployments ADD COLUMN {} INTEGER", column), []).is_err() {
+            return Err("Error adding deployment phase columns".to_string());
+        }
+    }
+
+    // Databases created before uploads were deduplicated won't have the content hash column, so add it.
+    // This holds the hash of the code and build options, so uploading the same code again can skip the build
+    if conn.prepare("SELECT content_hash FROM deployments LIMIT 0").is_err()
+        && conn.execute("ALTER TABLE deployments ADD COLUMN content_hash TEXT", []).is_err() {
+        return Err("Error adding content hash column".to_string());
+    }
+
+    // Databases created before deployments could be promoted between hosts won't have the promotion columns, so add
+    // them. These hold where an imported deployment came from, and the hosts a deployment has been promoted to
+    for column in ["promoted_from", "promoted_to"] {
+        if conn.prepare(&format!("SELECT {} FROM deployments LIMIT 0", column)).is_err()
+            && conn.execute(&format!("ALTER TABLE deployments ADD COLUMN {} TEXT", column), []).is_err() {
+            return Err("Error adding promotion columns".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the table for the default settings of each namespace. This is the second migration
+pub fn create_namespace_defaults_table(conn: &Connection) -> Result<(), String> {
+    // Postgres stores the thresholds as BIGINT, the same as the other tables
+    let integer = match conn.backend() {
+        StorageBackend::Sqlite => "INTEGER",
+        StorageBackend::Postgres => "BIGINT",
+    };
+
+    match conn.execute(
+        &format!("CREATE TABLE IF NOT EXISTS namespace_defaults (
+                  namespace                  TEXT PRIMARY KEY,
+                  buffer_request_threshold   {},
+                  buffer_response_threshold  {},
+                  egress_allowlist           TEXT,
+                  max_replicas               {}
+                  )", integer, integer, integer),
+        [],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error creating table: {}", e)),
+    }
+}
+
+/// Adds the column holding the redaction rules for each function app, as JSON. Only the headers that are always
+/// redacted are redacted from the app's mirrored and recorded requests when this isn't set
+pub fn add_redaction_rules_column(conn: &Connection) -> Result<(), String> {
+    match conn.execute("ALTER TABLE function_apps ADD COLUMN redaction_rules TEXT", []) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error adding redaction rules column: {}", e)),
+    }
+}
+
+/// Adds the column holding the secret each function app's containers give the egress proxy. It is issued the first
+/// time a container is started for the app with the proxy turned on
+pub fn add_proxy_secret_column(conn: &Connection) -> Result<(), String> {
+    match conn.execute("ALTER TABLE function_apps ADD COLUMN proxy_secret TEXT", []) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error adding proxy secret column: {}", e)),
+    }
+}
+
+/// Checks the database has all the tables and columns the host needs, so any upgrades to older databases have been applied
+pub fn check_schema(conn: &Connection) -> Result<(), String> {
+    if let Some(upgrade) = migrations::check(conn)? {
+        return Err(upgrade);
+    }
+
+    let queries = [
+        "SELECT id, name, status, created_at, port, namespace, maintenance_message, mirror_config, record_capacity, timer_trigger, egress_allowlist, buffer_request_threshold, buffer_response_threshold, grpc_services, build_id, build_error, scale_profiles, idle_stopped, last_request_at, replicas, depends_on, redaction_rules, proxy_secret FROM function_apps LIMIT 0",
+        "SELECT key, value FROM settings LIMIT 0",
+        "SELECT name, holder, expires_at FROM leases LIMIT 0",
+        "SELECT name, key_hash, created_at, revoked_at FROM api_keys LIMIT 0",
+        "SELECT function_app_id, started_at, duration, succeeded FROM builds LIMIT 0",
+        "SELECT build_id, function_app_id, started_at, finished_at, result, output FROM build_logs LIMIT 0",
+        "SELECT function_app_id, number, created_at, approved, approved_at, sbom, image_digest, signature_payload, signature, signed_at, received_at, extracted_at, build_started_at, export_started_at, built_at, content_hash, readme, promoted_from, promoted_to FROM deployments LIMIT 0",
+        "SELECT function_app_id, changed_at, status, event, exit_code, duration, grace_period, reason, port_attempts FROM status_history LIMIT 0",
+        "SELECT function_app_id, sampled_at, containers, cpu_percent, memory_bytes, memory_limit_bytes, network_rx_bytes, network_tx_bytes FROM resource_samples LIMIT 0",
+        "SELECT function_app_id, trigger, started_at, duration_ms, status_code, error, manual FROM trigger_runs LIMIT 0",
+        "SELECT function_app_id, container_id, port, started_at FROM replicas LIMIT 0",
+        "SELECT namespace, buffer_request_threshold, buffer_response_threshold, egress_allowlist, max_replicas FROM namespace_defaults LIMIT 0",
+    ];
+
+    for query in queries {
+        if let Err(e) = conn.prepare(query) {
+            return Err(format!("Database schema is out of date: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets a connection to the main database from the pools shared by the server and the background jobs
+pub fn create_connection() -> Result<DbConnection, String> {
+    connect(&db_pool::get_pools())
+}
+
+/// Gets a connection to the main database from the given pools, such as the ones in the server's app data. The first
+/// time the database is connected to, which is when the host starts, the migrations the database doesn't have yet are
+/// applied
+pub fn connect(pools: &DbPools) -> Result<DbConnection, String> {
+    let conn = match database::get_storage_backend() {
+        StorageBackend::Sqlite => pools.get_connection(&get_db_file(), migrations::migrate),
+        StorageBackend::Postgres => pools.get_postgres_connection(&database::get_database_url()?, migrations::migrate),
+    };
+
+    match conn {
+        Ok(conn) => Ok(conn),
+        Err(e) => Err(format!("Error connecting to database: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an in-memory database with a single registered app
+    fn create_test_database() -> (rusqlite::Connection, Uuid) {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE function_apps (id TEXT PRIMARY KEY, name TEXT NOT NULL, status INTEGER NOT NULL, created_at INTEGER NOT NULL,
+                port INTEGER NOT NULL, namespace TEXT NOT NULL, idle_stopped INTEGER NOT NULL DEFAULT 0)",
+        ).unwrap();
+
+        let id = Uuid::new_v4();
+        rusqlite::Connection::execute(&conn, "INSERT INTO function_apps (id, name, status, created_at, port, namespace, idle_stopped) VALUES (?1, 'app', 1, 10, 0, 'default', 1)", [id.to_string()]).unwrap();
+        (conn, id)
+    }
+
+    #[test]
+    fn gets_all_apps() {
+        let (conn, id) = create_test_database();
+        let apps = conn.get_all_apps().unwrap();
+        assert_eq!(apps.len(), 1);
+        assert_eq!(apps[0].id, id);
+        assert_eq!(apps[0].name, "app");
+        assert_eq!(apps[0].status as u8, FunctionAppStatus::Registered as u8);
+    }
+
+    #[test]
+    fn set_status_reports_if_it_changed() {
+        let (conn, id) = create_test_database();
+        assert!(conn.set_status(&id, &FunctionAppStatus::Ready).unwrap());
+        assert!(!conn.set_status(&id, &FunctionAppStatus::Ready).unwrap());
+        assert_eq!(conn.get_status(&id).unwrap() as u8, FunctionAppStatus::Ready as u8);
+    }
+
+    #[test]
+    fn set_running_reports_if_it_started() {
+        let (conn, id) = create_test_database();
+        assert!(conn.set_running(&id, 8080).unwrap());
+        assert!(!conn.set_running(&id, 8081).unwrap());
+
+        let (port, idle_stopped): (u16, bool) = conn.query_row("SELECT port, idle_stopped FROM function_apps", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(port, 8081);
+        assert!(!idle_stopped);
+    }
+
+    #[test]
+    fn set_running_fails_for_unknown_apps() {
+        let (conn, _) = create_test_database();
+        assert!(matches!(conn.set_running(&Uuid::new_v4(), 8080), Err(Error::QueryReturnedNoRows)));
+    }
+
+    #[test]
+    fn rejects_unknown_statuses() {
+        assert!(to_status(7).is_err());
+        assert!(to_status(-1).is_err());
+    }
+}
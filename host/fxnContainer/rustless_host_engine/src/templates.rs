@@ -0,0 +1,226 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rust_embed::RustEmbed;
+
+use rustless_shared::{BuildOptions, BuildTemplate};
+
+/// The default Dockerfile templates from the container folder
+#[derive(RustEmbed)]
+#[folder = "container/"]
+struct ContainerFolder;
+
+/// The environment variable containing the folder to load Dockerfile templates from. A {name}.Dockerfile
+/// file in this folder overrides the embedded template with the same name, or adds a new template
+const TEMPLATES_DIR_ENV: &str = "RUSTLESS_TEMPLATES_DIR";
+
+/// The folder templates are loaded from if the environment variable isn't set
+const DEFAULT_TEMPLATES_DIR: &str = "templates";
+
+/// The environment variable containing the template to use when an app doesn't choose one
+const DEFAULT_TEMPLATE_ENV: &str = "RUSTLESS_DEFAULT_TEMPLATE";
+
+/// The template used when an app doesn't choose one and the environment variable isn't set
+const DEFAULT_TEMPLATE: &str = "rust";
+
+/// The toolchain used when an app doesn't choose one
+const DEFAULT_TOOLCHAIN: &str = "stable";
+
+/// The environment variable containing the network policy for builds: restricted, where only the step that
+/// downloads dependencies can use the network, or open, where every step can
+const BUILD_NETWORK_ENV: &str = "RUSTLESS_BUILD_NETWORK";
+
+/// The file extension for template files
+const TEMPLATE_EXTENSION: &str = ".Dockerfile";
+
+/// The templates embedded in the host, along with the base image each one uses by default
+const EMBEDDED_TEMPLATES: [(&str, &str); 2] = [
+    ("rust", "debian:bullseye"),
+    ("rust-slim", "rust:1-slim-bookworm"),
+];
+
+/// Gets the folder to load templates from
+fn get_templates_dir() -> PathBuf {
+    match std::env::var(TEMPLATES_DIR_ENV) {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(DEFAULT_TEMPLATES_DIR),
+    }
+}
+
+/// Gets the default base image for a template, or None for templates that aren't embedded
+fn get_default_base_image(name: &str) -> Option<&'static str> {
+    EMBEDDED_TEMPLATES.iter().find(|(template, _)| *template == name).map(|(_, base_image)| *base_image)
+}
+
+/// Checks a value only contains the allowed characters, so it can't change the structure of the Dockerfile
+fn validate_value(kind: &str, value: &str, allowed: &[char]) -> Result<(), String> {
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_alphanumeric() || allowed.contains(&c)) {
+        return Err(format!("Invalid {} '{}'", kind, value));
+    }
+
+    Ok(())
+}
+
+/// The network policy for the steps of a build that run code from the app or its dependencies
+#[derive(Clone, Copy, PartialEq)]
+pub enum BuildNetwork {
+    /// Build scripts and the app can't use the network, so they can't send host data anywhere or scan the
+    /// internal network. Dependencies are downloaded from crates.io in an earlier step that runs no app code
+    Restricted,
+
+    /// Every step can use the network, for apps whose build scripts need to download things
+    Open,
+}
+
+impl BuildNetwork {
+    /// Gets the name of the policy, as set in the environment variable
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuildNetwork::Restricted => "restricted",
+            BuildNetwork::Open => "open",
+        }
+    }
+
+    /// Gets the network the build steps that run code use, for RUN --network
+    fn docker_network(&self) -> &'static str {
+        match self {
+            BuildNetwork::Restricted => "none",
+            BuildNetwork::Open => "default",
+        }
+    }
+}
+
+/// Gets the network policy for builds. Builds are restricted unless the environment variable opens them up
+pub fn get_build_network() -> BuildNetwork {
+    match std::env::var(BUILD_NETWORK_ENV) {
+        Ok(value) if value.to_lowercase() == "open" => BuildNetwork::Open,
+        Ok(value) if value.to_lowercase() != "restricted" => {
+            println!("Ignoring unknown {}: {}, builds are restricted", BUILD_NETWORK_ENV, value);
+            BuildNetwork::Restricted
+        },
+        _ => BuildNetwork::Restricted,
+    }
+}
+
+/// Loads a template by name, from the templates folder if there is a file for it, otherwise from the embedded defaults
+fn load_template(name: &str) -> Result<String, String> {
+    // Template names are used as file names, so only allow safe characters
+    validate_value("template name", name, &['-', '_'])?;
+
+    let path = get_templates_dir().join(format!("{}{}", name, TEMPLATE_EXTENSION));
+    if path.is_file() {
+        return match fs::read_to_string(&path) {
+            Ok(template) => Ok(template),
+            Err(e) => Err(format!("Error reading template {}: {}", path.display(), e)),
+        };
+    }
+
+    let template = match ContainerFolder::get(&format!("{}{}", name, TEMPLATE_EXTENSION)) {
+        Some(template) => template,
+        None => return Err(format!("No template named '{}'", name)),
+    };
+
+    match std::str::from_utf8(template.data.as_ref()) {
+        Ok(template) => Ok(template.to_string()),
+        Err(e) => Err(format!("Error converting template {} to string: {}", name, e)),
+    }
+}
+
+/// Checks a rendered Dockerfile can be built before any build starts
+fn validate_dockerfile(name: &str, dockerfile: &str) -> Result<(), String> {
+    // Any placeholders left over are variables the template needs that weren't set
+    if let Some(start) = dockerfile.find("{{") {
+        let placeholder = dockerfile[start..].split("}}").next().unwrap_or_default();
+        return Err(format!("Template '{}' needs a value for {}}}}}", name, placeholder));
+    }
+
+    // The first instruction must be FROM, ignoring comments and build arguments for the base image
+    let first_instruction = dockerfile.lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#') && !line.to_uppercase().starts_with("ARG "));
+
+    match first_instruction {
+        Some(line) if line.to_uppercase().starts_with("FROM ") => {},
+        _ => return Err(format!("Template '{}' must start with a FROM instruction", name)),
+    }
+
+    // The uploaded code is in the code folder of the build context
+    if !dockerfile.contains("COPY code") {
+        return Err(format!("Template '{}' must copy the code folder into the image", name));
+    }
+
+    Ok(())
+}
+
+/// Renders the Dockerfile for a build from the template and variables in the build options, and checks it is valid
+///
+/// Templates use {{toolchain}} and {{base_image}} placeholders, which are replaced with the values from the
+/// build options, or the template defaults if they aren't set. The {{build_network}} placeholder is replaced
+/// with the network for RUN --network from the build network policy.
+pub fn render_dockerfile(options: &BuildOptions) -> Result<String, String> {
+    let default_template = std::env::var(DEFAULT_TEMPLATE_ENV).unwrap_or_else(|_| DEFAULT_TEMPLATE.to_string());
+    let name = options.template.clone().unwrap_or(default_template);
+    let template = load_template(&name)?;
+
+    // A template that doesn't cut its build steps off from the network would get round the policy
+    let build_network = get_build_network();
+    if build_network == BuildNetwork::Restricted && !template.contains("--network={{build_network}}") {
+        return Err(format!(
+            "Template '{}' must run the steps that build the code with RUN --network={{{{build_network}}}} while {} is restricted",
+            name, BUILD_NETWORK_ENV
+        ));
+    }
+
+    let toolchain = options.toolchain.clone().unwrap_or_else(|| DEFAULT_TOOLCHAIN.to_string());
+    validate_value("toolchain", &toolchain, &['.', '-', '_'])?;
+
+    let mut dockerfile = template
+        .replace("{{toolchain}}", &toolchain)
+        .replace("{{build_network}}", build_network.docker_network());
+
+    // Templates from the templates folder have no default base image, so apps using them must set one
+    // if the template needs it
+    let base_image = options.base_image.clone().or(get_default_base_image(&name).map(|base_image| base_image.to_string()));
+    if let Some(base_image) = base_image {
+        validate_value("base image", &base_image, &['.', '-', '_', '/', ':', '@'])?;
+        dockerfile = dockerfile.replace("{{base_image}}", &base_image);
+    }
+
+    validate_dockerfile(&name, &dockerfile)?;
+
+    Ok(dockerfile)
+}
+
+/// Lists the templates apps can be built with, including the embedded ones and any in the templates folder
+pub fn list_templates() -> Vec<BuildTemplate> {
+    let templates_dir = get_templates_dir();
+    let has_file = |name: &str| templates_dir.join(format!("{}{}", name, TEMPLATE_EXTENSION)).is_file();
+
+    let mut templates: Vec<BuildTemplate> = EMBEDDED_TEMPLATES.iter()
+        .map(|(name, base_image)| BuildTemplate {
+            name: name.to_string(),
+            source: if has_file(name) { "override" } else { "embedded" }.to_string(),
+            default_base_image: base_image.to_string(),
+            default_toolchain: DEFAULT_TOOLCHAIN.to_string(),
+        })
+        .collect();
+
+    // Add the templates that are only in the templates folder
+    if let Ok(entries) = fs::read_dir(&templates_dir) {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if let Some(name) = file_name.strip_suffix(TEMPLATE_EXTENSION) {
+                if get_default_base_image(name).is_none() {
+                    templates.push(BuildTemplate {
+                        name: name.to_string(),
+                        source: "file".to_string(),
+                        default_base_image: String::new(),
+                        default_toolchain: DEFAULT_TOOLCHAIN.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    templates
+}
@@ -0,0 +1,271 @@
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use uuid::Uuid;
+
+use rustless_shared::{TimerTrigger, TriggerRun, TIMER_TRIGGER};
+
+use crate::database::Connection;
+use crate::leases;
+use crate::platform;
+use crate::scaling;
+use crate::storage;
+
+/// How often the scheduler checks for timer triggers and scale profiles that are due
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The lease that makes sure timer triggers and scale profiles are only fired by one host process
+const SCHEDULER_LEASE: &str = "timer-scheduler";
+
+/// How long to wait for a function app to respond to a timer trigger
+const TRIGGER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The most upcoming runs that can be previewed at once
+pub const MAX_NEXT_RUNS: usize = 100;
+
+/// The header sent with trigger calls, so apps can tell them apart from requests through the gateway
+pub const TRIGGER_HEADER: &str = "x-rustless-trigger";
+
+/// Parses a cron schedule, returning an error that explains the problem if it isn't valid
+///
+/// The cron crate expects seconds as the first field, so standard 5 field expressions are run at the
+/// start of the minute. Schedules that will never run again, such as ones for a year in the past, are
+/// rejected as they would silently do nothing.
+pub fn parse_schedule(schedule: &str) -> Result<Schedule, String> {
+    let fields = schedule.split_whitespace().count();
+    let expression = match fields {
+        5 => format!("0 {}", schedule.trim()),
+        6 | 7 => schedule.trim().to_string(),
+        _ => return Err(format!(
+            "Invalid schedule '{}': expected 5 fields (minute hour day month weekday), or 6 or 7 fields starting with seconds, but found {}",
+            schedule, fields
+        )),
+    };
+
+    let parsed = match Schedule::from_str(&expression) {
+        Ok(parsed) => parsed,
+        Err(e) => return Err(format!("Invalid schedule '{}': {}", schedule, e)),
+    };
+
+    if parsed.upcoming(Utc).next().is_none() {
+        return Err(format!("Invalid schedule '{}': it will never run", schedule));
+    }
+
+    Ok(parsed)
+}
+
+/// Checks a timer trigger is valid before it is saved, returning the trigger with the route tidied up
+pub fn validate_trigger(trigger: &TimerTrigger) -> Result<TimerTrigger, String> {
+    parse_schedule(&trigger.schedule)?;
+
+    // Routes are relative to the app, so a leading slash is optional
+    let route = trigger.route.trim().trim_start_matches('/').to_string();
+    if route.contains("://") || route.contains("..") || route.chars().any(|c| c.is_whitespace()) {
+        return Err(format!("Invalid route '{}': routes must be a path on the function app", trigger.route));
+    }
+
+    Ok(TimerTrigger {
+        schedule: trigger.schedule.trim().to_string(),
+        route,
+    })
+}
+
+/// Gets the times of the next runs for a schedule, in seconds since the Unix epoch
+pub fn get_next_runs(schedule: &str, count: usize) -> Result<Vec<u64>, String> {
+    let parsed = parse_schedule(schedule)?;
+
+    Ok(parsed.upcoming(Utc)
+        .take(count.min(MAX_NEXT_RUNS))
+        .map(|run| run.timestamp().max(0) as u64)
+        .collect())
+}
+
+/// Calls the route for a timer trigger on the function app running on the given port, returning the status code
+fn fire_timer_trigger(port: u16, route: &str) -> Result<u16, String> {
+    let client = match reqwest::blocking::Client::builder().timeout(TRIGGER_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return Err(format!("Error creating HTTP client: {}", e)),
+    };
+
+    match client.post(format!("{}/{}", platform::get_app_url(port), route)).header(TRIGGER_HEADER, TIMER_TRIGGER).send() {
+        Ok(res) => Ok(res.status().as_u16()),
+        Err(e) => Err(format!("Error calling function app: {}", e)),
+    }
+}
+
+/// Calls the route for a timer trigger on the function app running on the given port, recording the invocation in
+/// the trigger history. An app that can't be called is recorded as a run with an error rather than returned as one
+pub fn invoke_timer_trigger(conn: &Connection, id: &Uuid, port: u16, trigger: &TimerTrigger, manual: bool) -> TriggerRun {
+    let started_at = SystemTime::now();
+    let result = fire_timer_trigger(port, &trigger.route);
+
+    let (status_code, error) = match result {
+        Ok(status_code) => (Some(status_code), None),
+        Err(e) => (None, Some(e)),
+    };
+
+    let run = TriggerRun {
+        trigger: TIMER_TRIGGER.to_string(),
+        started_at: started_at.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs(),
+        duration_ms: started_at.elapsed().unwrap_or_default().as_millis() as u64,
+        status_code,
+        error,
+        manual,
+    };
+
+    // The app has already been called, so a failure to record the run shouldn't be reported as the trigger failing
+    if let Err(e) = storage::add_trigger_run(conn, id, &run) {
+        println!("Error recording trigger run for {}: {}", id, e);
+    }
+
+    run
+}
+
+/// Fires the timer trigger for a function app if the app is running. This runs on its own thread so a slow
+/// app doesn't hold up the triggers for other apps
+fn run_timer_trigger(id: Uuid, name: String, trigger: TimerTrigger) {
+    thread::spawn(move || {
+        let conn = match storage::create_connection_for_app(&id) {
+            Ok(conn) => conn,
+            Err(e) => {
+                println!("Error running timer trigger for {}: {}", name, e);
+                return;
+            }
+        };
+
+        let port = match storage::get_function_app_port(&conn, &id) {
+            Ok(Some(port)) => port,
+            Ok(None) => {
+                println!("Skipping timer trigger for {} as it is not running", name);
+                return;
+            }
+            Err(e) => {
+                println!("Error running timer trigger for {}: {}", name, e);
+                return;
+            }
+        };
+
+        let run = invoke_timer_trigger(&conn, &id, port, &trigger, false);
+        match (run.status_code, run.error) {
+            (Some(status), _) => println!("Timer trigger for {} called /{} with status {}", name, trigger.route, status),
+            (None, Some(e)) => println!("Error running timer trigger for {}: {}", name, e),
+            (None, None) => {},
+        }
+    });
+}
+
+/// Checks every timer trigger, firing any that were due since the last check
+fn check_timer_triggers(last_check: &DateTime<Utc>, now: &DateTime<Utc>) {
+    let triggers = match storage::get_all_timer_triggers() {
+        Ok(triggers) => triggers,
+        Err(e) => {
+            println!("Error getting timer triggers: {}", e);
+            return;
+        }
+    };
+
+    for (id, name, trigger) in triggers {
+        // Schedules are checked when they are set, so this only fails if the database was edited
+        let schedule = match parse_schedule(&trigger.schedule) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                println!("Skipping timer trigger for {}: {}", name, e);
+                continue;
+            }
+        };
+
+        if let Some(next_run) = schedule.after(last_check).next() {
+            if next_run <= *now {
+                run_timer_trigger(id, name, trigger);
+            }
+        }
+    }
+}
+
+/// Starts the scheduler that fires timer triggers and starts scale profiles, on a background thread for the life of
+/// the host. If more than one host process shares the database, only the one holding the lease fires them
+pub fn start_scheduler() {
+    thread::spawn(|| {
+        let mut last_check = Utc::now();
+
+        loop {
+            thread::sleep(SCHEDULER_INTERVAL);
+
+            // Runs due while another host process held the lease were fired by that process, so they are
+            // skipped here by moving the last check on
+            let now = Utc::now();
+            if leases::is_leader(SCHEDULER_LEASE) {
+                check_timer_triggers(&last_check, &now);
+                scaling::check_scale_profiles(&last_check, &now);
+            }
+            last_check = now;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a timer trigger with the given schedule and route
+    fn trigger(schedule: &str, route: &str) -> TimerTrigger {
+        TimerTrigger { schedule: schedule.to_string(), route: route.to_string() }
+    }
+
+    #[test]
+    fn runs_five_field_schedules_at_the_start_of_the_minute() {
+        let runs = get_next_runs("*/5 * * * *", 3).unwrap();
+        assert_eq!(runs.len(), 3);
+        assert!(runs.iter().all(|run| run % 300 == 0));
+    }
+
+    #[test]
+    fn accepts_schedules_with_seconds_and_years() {
+        assert!(parse_schedule("30 0 12 * * *").is_ok());
+        assert!(parse_schedule("0 0 12 * * * 2099").is_ok());
+    }
+
+    #[test]
+    fn rejects_the_wrong_number_of_fields() {
+        let error = parse_schedule("* * * *").unwrap_err();
+        assert!(error.contains("found 4"));
+        assert!(parse_schedule("").is_err());
+        assert!(parse_schedule("0 0 0 * * * * *").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        assert!(parse_schedule("61 * * * *").is_err());
+        assert!(parse_schedule("* * * * funday").is_err());
+    }
+
+    #[test]
+    fn rejects_schedules_that_never_run() {
+        let error = parse_schedule("0 0 0 1 1 * 2000").unwrap_err();
+        assert!(error.contains("never run"));
+    }
+
+    #[test]
+    fn limits_the_number_of_next_runs() {
+        let runs = get_next_runs("* * * * * *", MAX_NEXT_RUNS + 50).unwrap();
+        assert_eq!(runs.len(), MAX_NEXT_RUNS);
+        assert!(runs.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn tidies_the_schedule_and_route() {
+        let validated = validate_trigger(&trigger("  0 * * * *  ", " /jobs/cleanup ")).unwrap();
+        assert_eq!(validated.schedule, "0 * * * *");
+        assert_eq!(validated.route, "jobs/cleanup");
+    }
+
+    #[test]
+    fn rejects_routes_off_the_app() {
+        assert!(validate_trigger(&trigger("0 * * * *", "http://example.com/jobs")).is_err());
+        assert!(validate_trigger(&trigger("0 * * * *", "/../admin")).is_err());
+        assert!(validate_trigger(&trigger("0 * * * *", "/jobs/clean up")).is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use rust_embed::RustEmbed;
+
+/// The web console, a single page app that uses the JSON APIs and the event stream
+#[derive(RustEmbed)]
+#[folder = "ui/"]
+struct UiFolder;
+
+/// The page served for any path that isn't an asset, so the console can handle it
+const INDEX_PAGE: &str = "index.html";
+
+/// Gets the content type for an asset from its extension
+fn get_content_type(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Gets an asset for the web console with its content type, falling back to the index page for unknown paths.
+/// Returns None if the console wasn't embedded
+pub fn get_asset(path: &str) -> Option<(Cow<'static, [u8]>, &'static str)> {
+    let path = match path.trim_start_matches('/') {
+        "" => INDEX_PAGE,
+        path => path,
+    };
+
+    match UiFolder::get(path) {
+        Some(asset) => Some((asset.data, get_content_type(path))),
+        None => UiFolder::get(INDEX_PAGE).map(|asset| (asset.data, get_content_type(INDEX_PAGE))),
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::Write;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use tempfile::TempDir;
+
+use rustless_shared::ApiError;
+
+use crate::errors;
+use crate::function_app_builder;
+use crate::quotas;
+
+/// The environment variable containing the largest zip file of code that can be uploaded, such as 512m
+const MAX_CODE_SIZE_ENV: &str = "RUSTLESS_MAX_CODE_SIZE";
+
+/// The largest zip file of code that can be uploaded if the environment variable isn't set
+const DEFAULT_MAX_CODE_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// The content types of code sent as the raw bytes of the zip file. Anything else is read as the zip file encoded
+/// as base64, which is how older CLIs send it
+const ZIP_CONTENT_TYPES: [&str; 2] = ["application/zip", "application/octet-stream"];
+
+/// Gets the largest zip file of code that can be uploaded, in bytes
+fn get_max_code_size() -> u64 {
+    match std::env::var(MAX_CODE_SIZE_ENV) {
+        Ok(value) => quotas::parse_size(&value).unwrap_or(DEFAULT_MAX_CODE_SIZE),
+        Err(_) => DEFAULT_MAX_CODE_SIZE,
+    }
+}
+
+/// Gets the response for code that is over the size limit
+fn too_large_response(max_size: u64) -> Box<HttpResponse> {
+    Box::new(errors::response(
+        ApiError::CodeTooLarge,
+        &format!("The code is larger than the {} byte limit set by {}", max_size, MAX_CODE_SIZE_ENV),
+    ))
+}
+
+/// Checks if code is sent as the raw bytes of the zip file, rather than encoded as base64
+fn is_zip_upload(req: &HttpRequest) -> bool {
+    let content_type = req.headers()
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    ZIP_CONTENT_TYPES.contains(&media_type.as_str())
+}
+
+/// Writes a piece of the uploaded zip file to disk, returning the error response if it can't be written or takes the
+/// zip file over the size limit
+fn write_code(zip_file: &mut File, data: &[u8], written: &mut u64, max_size: u64) -> Result<(), Box<HttpResponse>> {
+    *written += data.len() as u64;
+    if *written > max_size {
+        return Err(too_large_response(max_size));
+    }
+
+    match zip_file.write_all(data) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Box::new(errors::response(ApiError::Internal, &format!("Error writing zip file: {}", e)))),
+    }
+}
+
+/// Decodes the base64 encoded zip file in the pending data, writing it to disk. Base64 decodes in groups of 4
+/// characters, so any characters after the last whole group are left in the pending data for the next chunk,
+/// unless this is the end of the upload
+fn decode_code(zip_file: &mut File, pending: &mut Vec<u8>, is_last: bool, written: &mut u64, max_size: u64) -> Result<(), Box<HttpResponse>> {
+    let length = if is_last { pending.len() } else { pending.len() / 4 * 4 };
+    if length == 0 {
+        return Ok(());
+    }
+
+    let decoded = match base64::decode(&pending[..length]) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            println!("Error decoding base64: {}", e);
+            return Err(Box::new(errors::response(ApiError::BadRequest, &e.to_string())));
+        }
+    };
+
+    pending.drain(..length);
+    write_code(zip_file, &decoded, written, max_size)
+}
+
+/// Saves uploaded code to the zip file in the temporary directory the build runs in, returning the error response
+/// if it can't be saved
+///
+/// The code is written to disk as it arrives, so the whole upload is never held in memory. Code encoded as base64 is
+/// decoded a chunk at a time as it arrives. Uploads are rejected as soon as the zip file goes over the size limit
+pub async fn save_code(temp_dir: &TempDir, req: &HttpRequest, mut payload: web::Payload) -> Result<(), Box<HttpResponse>> {
+    let max_size = get_max_code_size();
+
+    let zip_file_path = temp_dir.path().join(function_app_builder::ZIP_FILE_NAME);
+    let mut zip_file = match File::create(&zip_file_path) {
+        Ok(file) => file,
+        Err(e) => return Err(Box::new(errors::response(ApiError::Internal, &format!("Error creating zip file: {}", e)))),
+    };
+
+    let is_zip = is_zip_upload(req);
+    let mut written = 0u64;
+    let mut pending = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => return Err(Box::new(errors::response(ApiError::BadRequest, &format!("Error reading code: {}", e)))),
+        };
+
+        if is_zip {
+            write_code(&mut zip_file, &chunk, &mut written, max_size)?;
+        } else {
+            pending.extend_from_slice(&chunk);
+            decode_code(&mut zip_file, &mut pending, false, &mut written, max_size)?;
+        }
+    }
+
+    decode_code(&mut zip_file, &mut pending, true, &mut written, max_size)
+}
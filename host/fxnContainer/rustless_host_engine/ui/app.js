@@ -0,0 +1,280 @@
+// The rustless web console. Uses the same JSON APIs as the CLI, and the event stream to stay up to date
+
+// The function apps, by ID
+const apps = new Map();
+
+// The ID of the app the details are shown for
+let selectedId = null;
+
+// The most events kept in the activity list
+const MAX_EVENTS = 100;
+
+const byId = (id) => document.getElementById(id);
+
+// Formats a Unix time in the local timezone
+function formatTime(timestamp) {
+  return timestamp ? new Date(timestamp * 1000).toLocaleString() : '';
+}
+
+// The path the host is served under behind a reverse proxy, such as /rustless, taken from where the console was
+// loaded from so API calls go through the proxy too
+const BASE_PATH = location.pathname.slice(0, Math.max(location.pathname.indexOf('/ui/'), 0));
+
+// Where the API key is kept in the browser, once one has been entered
+const API_KEY_STORAGE = 'rustless-api-key';
+
+// Calls the management API with the API key. If the host asks for a key, one is asked for and the call is tried again
+async function apiFetch(path) {
+  const key = localStorage.getItem(API_KEY_STORAGE);
+  const response = await fetch(BASE_PATH + path, { headers: key ? { Authorization: `Bearer ${key}` } : {} });
+  if (response.status !== 401) {
+    return response;
+  }
+
+  const entered = prompt('This host needs an API key. Create one with rustless-hostctl keys create');
+  if (!entered) {
+    return response;
+  }
+
+  localStorage.setItem(API_KEY_STORAGE, entered.trim());
+  return apiFetch(path);
+}
+
+// Gets the message from a failed response. The host sends errors as JSON with a code and message
+async function getErrorMessage(response) {
+  const text = await response.text();
+  try {
+    return JSON.parse(text).message;
+  } catch {
+    return `${response.status} ${text}`;
+  }
+}
+
+// Gets JSON from the host, throwing on errors so they can be shown
+async function getJson(path) {
+  const response = await apiFetch(path);
+  if (!response.ok) {
+    throw new Error(await getErrorMessage(response));
+  }
+  return response.json();
+}
+
+// Draws the list of function apps
+function renderApps() {
+  const list = byId('app-list');
+  list.replaceChildren();
+
+  const sorted = [...apps.values()].sort((a, b) => a.namespace.localeCompare(b.namespace) || a.name.localeCompare(b.name));
+  for (const app of sorted) {
+    const row = document.createElement('tr');
+    row.classList.toggle('selected', app.id === selectedId);
+    row.addEventListener('click', () => selectApp(app.id));
+
+    for (const value of [app.name, app.namespace, app.status]) {
+      const cell = document.createElement('td');
+      cell.textContent = value;
+      row.appendChild(cell);
+    }
+    row.lastChild.className = `status ${app.status}`;
+
+    list.appendChild(row);
+  }
+
+  byId('no-apps').hidden = apps.size > 0;
+}
+
+// Loads all the function apps
+async function loadApps() {
+  const list = await getJson('/function-apps');
+  apps.clear();
+  for (const app of list) {
+    apps.set(app.id, app);
+  }
+  renderApps();
+}
+
+// Formats how long the queue, compile, and export phases of a deployment took, if they were recorded
+function formatPhases(phases) {
+  const seconds = (from, to) => (from && to ? `${to - from}s` : '?');
+  if (!phases || !phases.built_at) {
+    return '';
+  }
+  return [
+    seconds(phases.extracted_at, phases.build_started_at),
+    seconds(phases.build_started_at, phases.export_started_at),
+    seconds(phases.export_started_at, phases.built_at),
+  ].join(' / ');
+}
+
+// Draws the deployments for the selected app
+async function loadDeployments() {
+  const deployments = await getJson(`/function-apps/${selectedId}/deployments`);
+  const list = byId('deployment-list');
+  list.replaceChildren();
+
+  for (const deployment of deployments) {
+    const row = document.createElement('tr');
+    const approved = deployment.approved ? formatTime(deployment.approved_at) || 'Yes' : 'Awaiting approval';
+    for (const value of [deployment.number, formatTime(deployment.created_at), formatPhases(deployment.phases), approved, formatTime(deployment.signed_at), deployment.has_sbom ? 'Yes' : 'No']) {
+      const cell = document.createElement('td');
+      cell.textContent = value;
+      row.appendChild(cell);
+    }
+    list.appendChild(row);
+  }
+
+  byId('no-deployments').hidden = deployments.length > 0;
+}
+
+// Loads the most recent container output for the selected app
+async function loadLogs() {
+  const logs = byId('logs');
+  try {
+    const response = await apiFetch(`/function-apps/${selectedId}/logs?tail=200`);
+    if (!response.ok) {
+      throw new Error(await getErrorMessage(response));
+    }
+    logs.textContent = (await response.text()) || 'No output.';
+  } catch (e) {
+    logs.textContent = `Error getting logs: ${e.message}`;
+  }
+}
+
+// Loads the README from the code for the selected app, which describes how to call it
+async function loadReadme() {
+  const readme = byId('readme');
+  try {
+    const response = await apiFetch(`/function-apps/${selectedId}/readme`);
+    readme.textContent = response.ok ? await response.text() : 'The app has no README.';
+  } catch (e) {
+    readme.textContent = `Error getting README: ${e.message}`;
+  }
+}
+
+// Shows the details for an app
+async function selectApp(id) {
+  selectedId = id;
+  const app = apps.get(id);
+  renderApps();
+
+  byId('details').hidden = false;
+  byId('details-name').textContent = `${app.name} [${app.namespace}]`;
+  renderStatus();
+
+  try {
+    await loadDeployments();
+  } catch (e) {
+    byId('deployment-list').replaceChildren();
+    byId('no-deployments').hidden = false;
+    byId('no-deployments').textContent = `Error getting deployments: ${e.message}`;
+  }
+  await loadReadme();
+  await loadLogs();
+}
+
+// Shows the status of the selected app
+function renderStatus() {
+  const status = byId('details-status');
+  const app = apps.get(selectedId);
+  status.textContent = app.status;
+  status.className = `status ${app.status}`;
+}
+
+// Adds an event to the activity list
+function addActivity(event) {
+  const item = document.createElement('li');
+  let text = `${formatTime(event.timestamp)}  ${event.app} [${event.namespace}]  `;
+
+  switch (event.event) {
+    case 'status_changed':
+      text += `status is now ${event.status}`;
+      break;
+    case 'deploy_progress':
+      text += `deployment ${event.stage}`;
+      if (event.deployment) text += ` (deployment ${event.deployment})`;
+      if (event.message) text += `: ${event.message}`;
+      break;
+    case 'container_crashed':
+      text += event.exit_code == null ? 'crashed' : `crashed with exit code ${event.exit_code}`;
+      item.className = 'crashed';
+      break;
+    default:
+      text += event.event;
+  }
+
+  item.textContent = text;
+  const list = byId('event-list');
+  list.prepend(item);
+  while (list.children.length > MAX_EVENTS) {
+    list.lastChild.remove();
+  }
+}
+
+// Updates the console for an event from the host
+function handleEvent(event) {
+  addActivity(event);
+
+  if (event.event === 'status_changed' && event.status) {
+    const app = apps.get(event.id);
+    if (app) {
+      app.status = event.status;
+    } else {
+      apps.set(event.id, { id: event.id, name: event.app, namespace: event.namespace, status: event.status });
+    }
+    renderApps();
+  }
+
+  if (event.id === selectedId) {
+    if (apps.has(selectedId)) renderStatus();
+    if (event.event === 'deploy_progress') loadDeployments().catch(() => {});
+    if (event.event === 'deploy_progress' && event.stage === 'deployed') loadReadme();
+    if (event.event === 'container_crashed') loadLogs();
+  }
+}
+
+// How long to wait before opening the event stream again after the host refused it, such as before the API key was
+// entered
+const EVENTS_RETRY_MS = 5000;
+
+// Browsers can't send headers when they open an event stream, so the API key is sent in a cookie that only goes to the
+// event stream
+function setEventsCookie() {
+  const key = localStorage.getItem(API_KEY_STORAGE);
+  if (key) {
+    const secure = location.protocol === 'https:' ? '; Secure' : '';
+    document.cookie = `rustless_api_key=${key}; path=${BASE_PATH}/events; SameSite=Strict${secure}`;
+  }
+}
+
+// Listens to the event stream, which the browser reconnects to if it drops. If the host refuses it, such as for a
+// missing API key, it is opened again after a wait with the latest key
+function watchEvents() {
+  const connection = byId('connection');
+  setEventsCookie();
+  const source = new EventSource(`${BASE_PATH}/events`);
+
+  source.onopen = () => {
+    connection.textContent = 'Live';
+    connection.className = 'connected';
+    // Events may have been missed while disconnected
+    loadApps().catch(() => {});
+  };
+  source.onerror = () => {
+    connection.textContent = 'Reconnecting…';
+    connection.className = 'disconnected';
+    if (source.readyState === EventSource.CLOSED) {
+      setTimeout(watchEvents, EVENTS_RETRY_MS);
+    }
+  };
+
+  for (const name of ['status_changed', 'deploy_progress', 'container_crashed']) {
+    source.addEventListener(name, (message) => handleEvent(JSON.parse(message.data)));
+  }
+}
+
+byId('refresh-logs').addEventListener('click', loadLogs);
+loadApps().catch((e) => {
+  byId('no-apps').hidden = false;
+  byId('no-apps').textContent = `Error getting function apps: ${e.message}`;
+});
+watchEvents();
@@ -1,8 +1,19 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// The namespace function apps are created in if no namespace is given
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Gets the default namespace, used when deserializing requests and apps that don't have a namespace
+pub fn default_namespace() -> String {
+    DEFAULT_NAMESPACE.to_string()
+}
+
 /// The status of the function app
 #[derive(Debug)]
+#[derive(Clone, Copy)]
 #[derive(Serialize)]
 #[derive(Deserialize)]
 pub enum FunctionAppStatus {
@@ -23,6 +34,9 @@ pub enum FunctionAppStatus {
 
     /// Error - the function app has encountered an error either building or running
     Error,
+
+    /// Cancelled - the last build was cancelled before it finished
+    Cancelled,
 }
 
 /// The function app details to store in the database
@@ -41,6 +55,10 @@ pub struct FunctionApp {
 
     // The date/time the app was created
     pub created_at: u64,
+
+    // The namespace the app belongs to
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
 }
 
 /// The contents of the request sent to create a new function app
@@ -48,6 +66,10 @@ pub struct FunctionApp {
 #[derive(Serialize)]
 pub struct FunctionAppNameRequest {
     pub name: String,
+
+    // The namespace to create the app in
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
 }
 
 // The status of the function app
@@ -56,4 +78,1983 @@ pub struct FunctionAppNameRequest {
 pub struct FunctionAppStatusResult {
     pub id: Uuid,
     pub status: FunctionAppStatus,
-}
\ No newline at end of file
+
+    // The position in the build queue if the app is waiting to be built
+    #[serde(default)]
+    pub queue_position: Option<usize>,
+
+    // The estimated time in seconds until the build starts if the app is waiting to be built
+    #[serde(default)]
+    pub estimated_wait_secs: Option<u64>,
+
+    // The number of the deployment waiting for approval, if the host requires approval before apps are started
+    #[serde(default)]
+    pub pending_deployment: Option<u32>,
+
+    // How the app last stopped, if it has been stopped
+    #[serde(default)]
+    pub last_stop: Option<AppStop>,
+
+    // How the container for the app last exited without being stopped, if it has
+    #[serde(default)]
+    pub last_exit: Option<AppExit>,
+
+    // When the app was last started or restarted, and how many ports were tried, if it is running
+    #[serde(default)]
+    pub last_start: Option<AppStart>,
+
+    // The ID of the latest build, if the code has been uploaded
+    #[serde(default)]
+    pub build_id: Option<Uuid>,
+
+    // Why the latest build failed or was cancelled, such as the compiler output, if it did
+    #[serde(default)]
+    pub build_error: Option<String>,
+}
+
+/// An overview of a function app for showing every app at once, from the bulk status endpoint
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct FunctionAppOverview {
+    // The app name
+    pub name: String,
+
+    // The app ID
+    pub id: Uuid,
+
+    // The namespace the app belongs to
+    pub namespace: String,
+
+    // The stored status of the app
+    pub status: FunctionAppStatus,
+
+    // The number of the latest deployment, which is the version of the app
+    pub deployment: Option<u32>,
+
+    // When the latest deployment was built
+    pub deployed_at: Option<u64>,
+
+    // When the app was started, if it is running
+    pub running_since: Option<u64>,
+
+    // Why the app is in error, if it is
+    pub error: Option<String>,
+
+    // The number of the deployment waiting for approval before the app can be restarted with it
+    pub pending_deployment: Option<u32>,
+}
+
+/// The options for getting the resources used by running function apps
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ResourceUsageOptions {
+    // Only get the resources used by the function app with this name
+    #[serde(default)]
+    pub app: Option<String>,
+}
+
+/// A sample of the resources a running function app was using, taken periodically by the host
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ResourceSample {
+    // When the sample was taken, in seconds since the Unix epoch
+    pub sampled_at: u64,
+
+    // The number of containers running for the app
+    pub containers: u32,
+
+    // The CPU used, as a percentage of one CPU
+    pub cpu_percent: f64,
+
+    // The memory used in bytes, not counting the page cache
+    pub memory_bytes: u64,
+
+    // The memory the app could use in bytes
+    pub memory_limit_bytes: u64,
+
+    // The bytes received over the network since the containers started
+    pub network_rx_bytes: u64,
+
+    // The bytes sent over the network since the containers started
+    pub network_tx_bytes: u64,
+}
+
+/// The default number of resource samples to get
+pub fn default_resource_samples_count() -> usize {
+    60
+}
+
+/// The options for getting the resource samples of a function app, sent as query parameters
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ResourceSamplesOptions {
+    // The number of most recent samples to get
+    #[serde(default = "default_resource_samples_count")]
+    pub last: usize,
+}
+
+/// The resources a running function app is using, summed over its containers, as measured by docker
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppResourceUsage {
+    // The app name
+    pub name: String,
+
+    // The app ID
+    pub id: Uuid,
+
+    // The namespace the app belongs to
+    pub namespace: String,
+
+    // The number of running containers for the app
+    pub containers: u32,
+
+    // The CPU the app is using, as a percentage of one CPU, so an app using two CPUs fully is at 200%
+    pub cpu_percent: f64,
+
+    // The memory the app is using in bytes, not counting the page cache
+    pub memory_bytes: u64,
+
+    // The memory the app can use in bytes, which is the memory of the machine if the app isn't limited
+    pub memory_limit_bytes: u64,
+
+    // The bytes the app has received over the network since its containers started
+    pub network_rx_bytes: u64,
+
+    // The bytes the app has sent over the network since its containers started
+    pub network_tx_bytes: u64,
+}
+
+/// How a function app stopped, recorded in the status history
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppStop {
+    // When the app was stopped, in seconds since the Unix epoch
+    pub stopped_at: u64,
+
+    // Whether the app exited by itself within the grace period. False if it had to be killed
+    pub clean: bool,
+
+    // The exit code of the app container
+    pub exit_code: i32,
+
+    // How long the app took to stop, in seconds
+    pub duration_secs: u64,
+
+    // How long the app was given to finish requests in flight before being killed, in seconds
+    pub grace_period_secs: u64,
+}
+
+/// How the container for a running function app exited without being stopped, recorded in the status history
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppExit {
+    // When the exit was noticed, in seconds since the Unix epoch
+    pub exited_at: u64,
+
+    // The exit code of the container, or None if the container was removed before it could be checked
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+
+    // Why the container exited, such as killed: out of memory, limit 256Mi
+    pub reason: String,
+
+    // The memory the app was using in the last resource sample before it exited, in bytes
+    #[serde(default)]
+    pub last_memory_bytes: Option<u64>,
+}
+
+/// When a function app was started, recorded in the status history
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppStart {
+    // When the app was started or restarted, in seconds since the Unix epoch
+    pub started_at: u64,
+
+    // How many ports were tried before the container started. More than one means the ports picked first were
+    // taken by something else before docker could publish the app on them
+    pub port_attempts: u32,
+}
+
+/// How a function app was moved onto a new container by a restart
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppRestart {
+    // When requests started going to the new container, in seconds since the Unix epoch
+    pub restarted_at: u64,
+
+    // The port the new container is running on
+    pub port: u16,
+
+    // How long the new container took to pass its health check, in milliseconds
+    pub health_check_ms: u64,
+
+    // How many ports were tried before the new container started
+    #[serde(default)]
+    pub port_attempts: u32,
+
+    // How the old container stopped once requests were going to the new one
+    pub old_container: AppStop,
+}
+
+/// The options for building the code uploaded for a function app, sent as query parameters
+#[derive(Default, Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BuildOptions {
+    // Treat warnings as errors and run clippy, failing the build if there are any warnings
+    #[serde(default)]
+    pub strict: bool,
+
+    // The Dockerfile template to build with, such as rust or rust-slim. The host default is used if this isn't set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+
+    // The Rust toolchain to build with, such as stable or 1.75. The template default is used if this isn't set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+
+    // The base image for the container. The template default is used if this isn't set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_image: Option<String>,
+
+    // Build even if the code and options match the latest deployment. Otherwise the latest deployment is returned
+    // without building
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// The errors the server returns when a request fails. Each error is sent as its code, such as name_in_use, so
+/// clients can tell errors apart without matching on the message
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiError {
+    /// The function app ID in the request isn't a valid ID
+    InvalidId,
+
+    /// No function app with the name or ID exists
+    AppNotFound,
+
+    /// Something other than a function app wasn't found
+    NotFound,
+
+    /// The request isn't valid
+    BadRequest,
+
+    /// Another function app already has the name
+    NameInUse,
+
+    /// The function app can't be started in its current status, such as while it is building
+    CannotStart,
+
+    /// The function app is not running
+    NotRunning,
+
+    /// The function app is not building
+    NoBuild,
+
+    /// A build is already running for the function app
+    BuildInProgress,
+
+    /// No log was found for the build
+    BuildLogNotFound,
+
+    /// The deployment doesn't exist
+    DeploymentNotFound,
+
+    /// The deployment number isn't valid
+    InvalidDeployment,
+
+    /// The deployment has to be approved before the function app can be started
+    ApprovalRequired,
+
+    /// The deployment is not waiting to be approved
+    NotPending,
+
+    /// No approver keys are set on the server
+    NoApprovers,
+
+    /// The key doesn't have the approver role
+    NotApprover,
+
+    /// An approver key wasn't given
+    NoKey,
+
+    /// The management API needs an API key, and one wasn't given
+    NoApiKey,
+
+    /// The API key isn't valid, or has been revoked
+    InvalidApiKey,
+
+    /// The signature of the function app image doesn't match
+    SignatureInvalid,
+
+    /// The uploaded code is larger than the server allows
+    CodeTooLarge,
+
+    /// The uploaded zip file isn't a single crate, has a path that would be unzipped outside the build directory, or
+    /// unzips to more than the server allows
+    InvalidArchive,
+
+    /// The server has reached its quota
+    QuotaExceeded,
+
+    /// The template for the build isn't valid
+    InvalidTemplate,
+
+    /// The function app doesn't have a timer trigger
+    NoTimerTrigger,
+
+    /// The function app doesn't have the trigger
+    UnknownTrigger,
+
+    /// The timer trigger isn't valid
+    InvalidTrigger,
+
+    /// The schedule isn't a valid cron expression
+    InvalidSchedule,
+
+    /// The egress allowlist isn't valid
+    InvalidAllowlist,
+
+    /// The gRPC services aren't valid
+    InvalidServices,
+
+    /// Another function app already serves the gRPC service
+    ServiceInUse,
+
+    /// The dependencies aren't valid, such as naming an app that doesn't exist or making a cycle
+    InvalidDependencies,
+
+    /// A function app the app depends on couldn't be started, or didn't pass its health check
+    DependencyNotStarted,
+
+    /// The number of replicas isn't valid
+    InvalidReplicas,
+
+    /// The scale profiles aren't valid
+    InvalidScaleProfiles,
+
+    /// The buffering thresholds aren't valid
+    InvalidThreshold,
+
+    /// The mirror settings aren't valid
+    InvalidMirror,
+
+    /// The request recording capacity isn't valid
+    InvalidCapacity,
+
+    /// The redaction rules aren't valid
+    InvalidRedaction,
+
+    /// The faults to inject aren't valid
+    InvalidFaults,
+
+    /// The default settings for a namespace aren't valid
+    InvalidDefaults,
+
+    /// The function app's code has no README
+    NoReadme,
+
+    /// The deployment has no software bill of materials
+    NoSbom,
+
+    /// The image for the deployment is no longer kept, as only the image for the latest deployment is
+    ImageGone,
+
+    /// The exported deployment sent to import isn't valid
+    InvalidExport,
+
+    /// The function app doesn't serve a route manifest
+    NoRouteManifest,
+
+    /// The function app didn't pass its health check after starting
+    HealthCheckFailed,
+
+    /// The function app couldn't be reached
+    BadGateway,
+
+    /// The server couldn't read or write its database
+    DatabaseError,
+
+    /// Any other error on the server
+    Internal,
+
+    /// An error from a newer server that this version doesn't know about
+    #[serde(other)]
+    Unknown,
+}
+
+impl ApiError {
+    /// Gets the HTTP status code the server returns with the error
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ApiError::InvalidId
+            | ApiError::BadRequest
+            | ApiError::InvalidDeployment
+            | ApiError::InvalidTemplate
+            | ApiError::InvalidTrigger
+            | ApiError::InvalidSchedule
+            | ApiError::InvalidAllowlist
+            | ApiError::InvalidServices
+            | ApiError::InvalidDependencies
+            | ApiError::InvalidReplicas
+            | ApiError::InvalidScaleProfiles
+            | ApiError::InvalidThreshold
+            | ApiError::InvalidMirror
+            | ApiError::InvalidCapacity
+            | ApiError::InvalidRedaction
+            | ApiError::InvalidFaults
+            | ApiError::InvalidDefaults
+            | ApiError::InvalidExport => 400,
+            ApiError::NoKey | ApiError::NoApiKey | ApiError::InvalidApiKey => 401,
+            ApiError::NoApprovers | ApiError::NotApprover | ApiError::SignatureInvalid => 403,
+            ApiError::AppNotFound
+            | ApiError::NotFound
+            | ApiError::NoBuild
+            | ApiError::BuildLogNotFound
+            | ApiError::DeploymentNotFound
+            | ApiError::NoTimerTrigger
+            | ApiError::UnknownTrigger
+            | ApiError::NoReadme
+            | ApiError::NoSbom
+            | ApiError::NoRouteManifest => 404,
+            ApiError::NameInUse
+            | ApiError::CannotStart
+            | ApiError::NotRunning
+            | ApiError::BuildInProgress
+            | ApiError::ApprovalRequired
+            | ApiError::NotPending
+            | ApiError::ServiceInUse => 409,
+            ApiError::ImageGone => 410,
+            ApiError::CodeTooLarge => 413,
+            ApiError::InvalidArchive => 422,
+            ApiError::DependencyNotStarted => 424,
+            ApiError::BadGateway => 502,
+            ApiError::HealthCheckFailed => 503,
+            ApiError::QuotaExceeded => 507,
+            ApiError::DatabaseError | ApiError::Internal | ApiError::Unknown => 500,
+        }
+    }
+}
+
+/// The body returned by the server when a request fails
+#[derive(Debug)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    // The error, sent as a short code such as name_in_use
+    pub code: ApiError,
+
+    // A description of the error to show to the user
+    pub message: String,
+
+    // The file the error is about, such as the file in an uploaded zip file that was rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+impl ErrorResponse {
+    /// Creates a new error response
+    pub fn new(code: ApiError, message: &str) -> ErrorResponse {
+        ErrorResponse {
+            code,
+            message: message.to_string(),
+            file: None,
+        }
+    }
+
+    /// Creates a new error response about a file
+    pub fn for_file(code: ApiError, message: &str, file: &str) -> ErrorResponse {
+        ErrorResponse {
+            code,
+            message: message.to_string(),
+            file: Some(file.to_string()),
+        }
+    }
+}
+
+/// The contents of the request sent to turn maintenance mode on or off for a function app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct MaintenanceRequest {
+    // Whether the app is in maintenance mode
+    pub enabled: bool,
+
+    // An optional message to show on the maintenance page, such as when the app will be back
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// The default function app for a server, which receives requests that don't match any other route
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct DefaultApp {
+    // The name of the default app, or None if there isn't one
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// What deploying code for a function app would do
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+pub enum DeployAction {
+    /// Create - the function app doesn't exist, so it would be registered then built
+    Create,
+
+    /// Update - the function app exists, so its code would be replaced and rebuilt
+    Update,
+}
+
+/// The plan for deploying code for a function app, worked out without making any changes
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct DeployPlan {
+    // Whether the app would be created or updated
+    pub action: DeployAction,
+
+    // The ID of the app if it already exists
+    #[serde(default)]
+    pub id: Option<Uuid>,
+
+    // The current status of the app if it already exists
+    #[serde(default)]
+    pub status: Option<FunctionAppStatus>,
+
+    // Problems that would cause the deployment to fail
+    #[serde(default)]
+    pub errors: Vec<String>,
+
+    // Things to be aware of that won't stop the deployment
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+/// A build that has been queued, returned from a code upload. The build runs in the background, so poll the
+/// status of the app until it is no longer building to get the result
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BuildAccepted {
+    // The ID of the build, which the status of the app reports until another build is queued
+    pub build_id: Uuid,
+}
+
+/// The result of a build that succeeded, recorded with its build log
+pub const BUILD_SUCCEEDED: &str = "succeeded";
+
+/// The result of a build that failed, recorded with its build log
+pub const BUILD_FAILED: &str = "failed";
+
+/// The result of a build that was cancelled, recorded with its build log
+pub const BUILD_CANCELLED: &str = "cancelled";
+
+/// The event sent on a build log stream for each line of output from the build
+pub const BUILD_OUTPUT_EVENT: &str = "output";
+
+/// The event sent on a build log stream when the build finishes, with the result as the data. The stream ends after it
+pub const BUILD_FINISHED_EVENT: &str = "finished";
+
+/// The output captured from a build of a function app, including the docker build and image push output
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BuildLog {
+    // The ID of the build, as returned when the code was uploaded
+    pub build_id: Uuid,
+
+    // When the build was queued, in seconds since the Unix epoch
+    pub started_at: u64,
+
+    // When the build finished, in seconds since the Unix epoch, or None if it is still queued or building
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+
+    // Whether the build succeeded, failed, or was cancelled, or None if it is still queued or building
+    #[serde(default)]
+    pub result: Option<String>,
+
+    // The output of the build. Only the end of the output is kept for very long builds
+    pub output: String,
+}
+
+/// The options for getting the build log of a function app, sent as query parameters
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BuildLogOptions {
+    // Get the log for this build rather than the latest one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<Uuid>,
+
+    // Stream the log as server sent events, with the output so far and then each line as the build writes it
+    #[serde(default)]
+    pub follow: bool,
+}
+
+/// The latest deployment, returned from a code upload when the code and build options match it so nothing was built
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct UnchangedDeployment {
+    // The deployment number
+    pub number: u32,
+
+    // Whether the deployment is approved to run. If not, it still needs approving before the app can be started
+    pub approved: bool,
+
+    // The SHA-256 hash of the uploaded code and build options, which matched the deployment
+    pub content_hash: String,
+}
+
+/// The default fraction of requests to mirror
+pub fn default_mirror_sample_rate() -> f64 {
+    1.0
+}
+
+/// The default maximum number of body bytes to include in a mirrored request
+pub fn default_mirror_max_body_bytes() -> usize {
+    4096
+}
+
+/// Where and how to mirror requests to a function app, for debugging and replay
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct MirrorConfig {
+    // Where to send the mirrored requests. An http or https URL gets each request posted as JSON,
+    // anything else is a file in the host's mirror folder that gets a line of JSON per request
+    pub sink: String,
+
+    // The fraction of requests to mirror, from 0 to 1
+    #[serde(default = "default_mirror_sample_rate")]
+    pub sample_rate: f64,
+
+    // The maximum number of body bytes to include. Longer bodies are truncated
+    #[serde(default = "default_mirror_max_body_bytes")]
+    pub max_body_bytes: usize,
+
+    // Headers to redact as well as the ones that are always redacted, such as Authorization and Cookie
+    #[serde(default)]
+    pub redact_headers: Vec<String>,
+}
+
+/// The request to turn request mirroring on or off for a function app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct MirrorRequest {
+    // Whether to mirror requests
+    pub enabled: bool,
+
+    // How to mirror requests. This is required when mirroring is enabled
+    #[serde(default)]
+    pub config: Option<MirrorConfig>,
+}
+
+/// The default number of recent requests to keep for a function app when recording is on
+pub fn default_recording_capacity() -> usize {
+    100
+}
+
+/// The request to turn recording recent requests on or off for a function app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct RecordingRequest {
+    // Whether to record requests
+    pub enabled: bool,
+
+    // The number of recent requests to keep, up to 1000. Older requests are dropped as new ones arrive
+    #[serde(default = "default_recording_capacity")]
+    pub capacity: usize,
+}
+
+/// The options for getting the recent requests recorded for a function app, sent as query parameters
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct RecordedRequestsOptions {
+    // The number of most recent requests to get
+    #[serde(default = "default_recording_capacity")]
+    pub last: usize,
+}
+
+/// A request to a function app recorded by the gateway, along with the response from the app
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct RecordedRequest {
+    // When the request was received, in seconds since the Unix epoch
+    pub timestamp: u64,
+
+    // The HTTP method
+    pub method: String,
+
+    // The route in the app, without the api/{name} prefix
+    pub route: String,
+
+    // The query string, without the leading ?
+    pub query: String,
+
+    // The request headers
+    pub headers: Vec<(String, String)>,
+
+    // The request body, base64 encoded, truncated if it was longer than the host records
+    pub body: String,
+
+    // Whether the request body was truncated
+    #[serde(default)]
+    pub body_truncated: bool,
+
+    // The HTTP status code the app returned
+    pub response_status: u16,
+
+    // The response headers
+    pub response_headers: Vec<(String, String)>,
+
+    // The response body, base64 encoded, truncated if it was longer than the host records
+    pub response_body: String,
+
+    // Whether the response body was truncated
+    #[serde(default)]
+    pub response_body_truncated: bool,
+}
+
+/// The value redacted headers and JSON fields are replaced with in mirrored and recorded requests
+pub const REDACTED_VALUE: &str = "[REDACTED]";
+
+/// What to redact from requests to a function app, and their responses, before they are mirrored or recorded. This
+/// is as well as the headers that are always redacted, such as Authorization and Cookie
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct RedactionRules {
+    // The names of headers to redact, in any case
+    #[serde(default)]
+    pub headers: Vec<String>,
+
+    // The paths of fields to redact in JSON bodies, such as user.password. A * matches any field or array item,
+    // such as cards.*.number
+    #[serde(default)]
+    pub json_fields: Vec<String>,
+}
+
+/// The version of the management API. This changes when the API changes in a way older CLIs can't use
+pub const API_VERSION: &str = "1";
+
+/// The version and build details of the host or CLI
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct VersionInfo {
+    // The package version, such as 0.1.0
+    pub version: String,
+
+    // The git commit the binary was built from
+    pub git_sha: String,
+
+    // When the binary was built, in UTC
+    pub build_date: String,
+
+    // The features compiled in, such as the supported runtimes and storage backends
+    pub features: Vec<String>,
+
+    // The versions of the management API that are supported
+    pub api_versions: Vec<String>,
+}
+
+/// A Dockerfile template that function apps can be built with
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BuildTemplate {
+    // The template name, used to select it when uploading code
+    pub name: String,
+
+    // Where the template comes from: embedded in the host, an override of an embedded template, or a file in the host templates folder
+    pub source: String,
+
+    // The base image used if the app doesn't set one
+    pub default_base_image: String,
+
+    // The toolchain used if the app doesn't set one
+    pub default_toolchain: String,
+}
+
+/// A route handled by a function app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppRoute {
+    // The HTTP method, such as GET or POST
+    pub method: String,
+
+    // The path of the route, relative to the app
+    pub path: String,
+}
+
+/// The routes handled by a function app, from the route manifest served by apps built with rustless_app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppRouteManifest {
+    // The name of the app, as set in the app code
+    pub app: String,
+
+    // The version of the app, as set in the app code
+    pub version: String,
+
+    // The routes the app handles
+    pub routes: Vec<AppRoute>,
+}
+
+/// A timer trigger, calling a route on a function app on a cron schedule
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct TimerTrigger {
+    // The cron schedule, evaluated in UTC. Standard 5 field expressions are supported, as are 6 or 7 field
+    // expressions that start with seconds and can end with years
+    pub schedule: String,
+
+    // The route on the function app that is called with a POST each time the trigger fires
+    pub route: String,
+}
+
+/// The request to turn the timer trigger on or off for a function app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct TimerTriggerRequest {
+    // Whether the timer trigger is on
+    pub enabled: bool,
+
+    // The schedule and route for the trigger. This is required when the trigger is enabled
+    #[serde(default)]
+    pub trigger: Option<TimerTrigger>,
+}
+
+/// The default number of upcoming runs to preview for a timer trigger
+pub fn default_next_runs_count() -> usize {
+    5
+}
+
+/// The options for previewing the upcoming runs of a timer trigger, sent as query parameters
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct NextRunsOptions {
+    // The number of upcoming runs to get
+    #[serde(default = "default_next_runs_count")]
+    pub count: usize,
+
+    // A schedule to preview instead of the one set for the app, to check it before setting it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+}
+
+/// The upcoming runs of a timer trigger
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct NextRuns {
+    // The schedule the runs are for
+    pub schedule: String,
+
+    // The route that is called, if the schedule is the one set for the app
+    pub route: Option<String>,
+
+    // The times of the upcoming runs, in seconds since the Unix epoch
+    pub next_runs: Vec<u64>,
+}
+
+/// A scale profile, setting how many replicas of a function app run from when its schedule fires until another
+/// profile's schedule fires. Profiles for 9am and 6pm on weekdays, with 1 and 0 replicas, run the app during
+/// working hours and scale it to zero otherwise
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ScaleProfile {
+    // The cron schedule the profile starts on, evaluated in UTC, in the same format as timer triggers
+    pub schedule: String,
+
+    // The number of replicas to run. 0 scales the app to zero by stopping it
+    pub replicas: u32,
+}
+
+/// The request to set the scale profiles for a function app. An empty list removes them
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ScaleProfilesRequest {
+    pub profiles: Vec<ScaleProfile>,
+}
+
+/// The scale profiles for a function app, and the replicas they set now and next
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ScaleProfilesReport {
+    // The scale profiles
+    pub profiles: Vec<ScaleProfile>,
+
+    // The replicas set by the profile that started most recently, or None if there are no profiles
+    pub current_replicas: Option<u32>,
+
+    // When the next profile starts, in seconds since the Unix epoch
+    pub next_change_at: Option<u64>,
+
+    // The replicas the next profile sets
+    pub next_replicas: Option<u32>,
+}
+
+/// The request to scale a function app to a number of replicas. 0 stops the app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ScaleRequest {
+    pub replicas: u32,
+}
+
+/// A container running a function app, one of the replicas requests are shared between
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct Replica {
+    // The port the container is published on
+    pub port: u16,
+
+    // Whether the container passed its last health check, so requests are routed to it
+    pub healthy: bool,
+}
+
+/// The replicas a function app is scaled to, and the containers running it
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ReplicasReport {
+    // The number of replicas the app runs when it is started
+    pub replicas: u32,
+
+    // The containers running the app, which is empty if the app is stopped
+    pub running: Vec<Replica>,
+}
+
+/// The name of the timer trigger, used when firing it manually and in the trigger history
+pub const TIMER_TRIGGER: &str = "timer";
+
+/// An invocation of a trigger on a function app, recorded in the trigger history
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct TriggerRun {
+    // The trigger that was invoked, such as timer
+    pub trigger: String,
+
+    // When the trigger was invoked, in seconds since the Unix epoch
+    pub started_at: u64,
+
+    // How long the app took to respond, in milliseconds
+    pub duration_ms: u64,
+
+    // The HTTP status code the app returned, or None if the app couldn't be called
+    #[serde(default)]
+    pub status_code: Option<u16>,
+
+    // Why the app couldn't be called, if it couldn't
+    #[serde(default)]
+    pub error: Option<String>,
+
+    // Whether the trigger was fired manually rather than by its schedule
+    pub manual: bool,
+}
+
+/// The default number of trigger invocations to get from the trigger history
+pub fn default_trigger_runs_count() -> usize {
+    20
+}
+
+/// The options for getting the trigger history of a function app, sent as query parameters
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct TriggerRunsOptions {
+    // The number of most recent invocations to get
+    #[serde(default = "default_trigger_runs_count")]
+    pub last: usize,
+
+    // Only get the invocations of this trigger, such as timer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<String>,
+}
+
+/// The request to restrict the destinations a function app can call through the egress proxy
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct EgressRequest {
+    // Whether the app is restricted to the allowlist. If not, the app can call any destination
+    pub restricted: bool,
+
+    // The destinations the app can call, such as api.example.com, *.example.com, or api.example.com:443
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+}
+
+/// The calls a function app has made to a destination through the egress proxy
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct EgressDestination {
+    // The host name or IP address called
+    pub host: String,
+
+    // The port called
+    pub port: u16,
+
+    // The number of connections allowed by the allowlist
+    pub allowed: u64,
+
+    // The number of connections denied by the allowlist
+    pub denied: u64,
+
+    // The number of bytes sent to the destination
+    pub bytes_sent: u64,
+
+    // The number of bytes received from the destination
+    pub bytes_received: u64,
+
+    // When the destination was last called, in seconds since the Unix epoch
+    pub last_seen: u64,
+}
+
+/// The egress settings of a function app and the destinations it has called since the host started
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct EgressReport {
+    // Whether the host is running the egress proxy. If not, apps call destinations directly and nothing is recorded
+    pub proxy_enabled: bool,
+
+    // The destinations the app can call, from its own allowlist or the default for its namespace, or None if it can
+    // call any destination
+    pub allowlist: Option<Vec<String>>,
+
+    // The destinations the app has called, most recent first
+    pub destinations: Vec<EgressDestination>,
+}
+
+/// The request to set the gRPC services a function app serves through the gRPC gateway
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct GrpcServicesRequest {
+    // The fully qualified services the app serves, such as helloworld.Greeter. Calls to these services are routed to
+    // the app. An empty list stops routing gRPC calls to it
+    #[serde(default)]
+    pub services: Vec<String>,
+}
+
+/// The calls made to a gRPC method of a function app through the gRPC gateway
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct GrpcMethodMetrics {
+    // The full method name, such as /helloworld.Greeter/SayHello
+    pub method: String,
+
+    // The number of calls, including ones still in progress
+    pub calls: u64,
+
+    // The number of calls still in progress, such as open streams
+    pub in_flight: u64,
+
+    // The number of finished calls that ended with a status other than OK
+    pub errors: u64,
+
+    // The number of finished calls that ended with each gRPC status code, such as 0 for OK or 14 for UNAVAILABLE
+    pub status_codes: BTreeMap<u32, u64>,
+
+    // The total time the finished calls took, from the request arriving to the response ending, in milliseconds
+    pub total_duration_ms: u64,
+
+    // The longest a finished call took, in milliseconds
+    pub max_duration_ms: u64,
+
+    // The number of bytes received from clients, including the gRPC message framing
+    pub bytes_received: u64,
+
+    // The number of bytes sent to clients, including the gRPC message framing
+    pub bytes_sent: u64,
+
+    // When the method was last called, in seconds since the Unix epoch
+    pub last_called: u64,
+}
+
+/// The gRPC services of a function app and the calls made to its methods since the host started
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct GrpcReport {
+    // Whether the host is running the gRPC gateway. If not, gRPC calls can't reach apps
+    pub gateway_enabled: bool,
+
+    // The services the app serves
+    pub services: Vec<String>,
+
+    // The methods that have been called, sorted by name
+    pub methods: Vec<GrpcMethodMetrics>,
+}
+
+/// Whether the image a function app runs from is signed by the host
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ImageVerification {
+    // How the host enforces signatures when starting apps: off, warn, or enforce
+    pub enforcement: String,
+
+    // The ID of the image the app runs from, or None if the app hasn't been built
+    pub image_digest: Option<String>,
+
+    // The deployment the signature is for, or None if the image isn't signed
+    pub deployment: Option<u32>,
+
+    // When the image was signed, in seconds since the Unix epoch
+    pub signed_at: Option<u64>,
+
+    // Whether the signature is valid for the image
+    pub verified: bool,
+
+    // Why the signature isn't valid, if it isn't
+    pub message: Option<String>,
+}
+
+/// The request to set when the gateway buffers or streams bodies for a function app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BufferingRequest {
+    // Request bodies up to this many bytes are buffered and sent with a content length, larger ones are streamed.
+    // None uses the host default
+    pub request_threshold: Option<u64>,
+
+    // Response bodies up to this many bytes are buffered and sent with a content length, larger ones are streamed.
+    // None uses the host default
+    pub response_threshold: Option<u64>,
+}
+
+/// How the gateway has buffered and streamed bodies for a function app since the host started
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BufferMetrics {
+    // The number of request bodies buffered
+    pub requests_buffered: u64,
+
+    // The number of request bodies streamed
+    pub requests_streamed: u64,
+
+    // The number of response bodies buffered
+    pub responses_buffered: u64,
+
+    // The number of response bodies streamed
+    pub responses_streamed: u64,
+
+    // The total number of bytes buffered
+    pub bytes_buffered: u64,
+
+    // The largest body buffered, in bytes
+    pub largest_buffer: u64,
+
+    // The number of bytes buffered for requests in flight
+    pub buffered_in_flight: u64,
+
+    // The most bytes buffered for requests in flight at once
+    pub peak_buffered_in_flight: u64,
+}
+
+/// The buffering thresholds for a function app and how bodies have been buffered
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BufferingReport {
+    // The request body threshold in use, in bytes
+    pub request_threshold: u64,
+
+    // The response body threshold in use, in bytes
+    pub response_threshold: u64,
+
+    // Whether the request threshold is a default, from the namespace or host, rather than set on the app
+    pub request_threshold_default: bool,
+
+    // Whether the response threshold is a default, from the namespace or host, rather than set on the app
+    pub response_threshold_default: bool,
+
+    // How bodies have been buffered and streamed since the host started
+    pub metrics: BufferMetrics,
+}
+
+/// The event sent when the status of a function app changes
+pub const STATUS_CHANGED_EVENT: &str = "status_changed";
+
+/// The event sent as a deployment moves through the build, such as queued, building, or deployed
+pub const DEPLOY_PROGRESS_EVENT: &str = "deploy_progress";
+
+/// The event sent when the container for a running function app exits without being stopped
+pub const CONTAINER_CRASHED_EVENT: &str = "container_crashed";
+
+/// The event sent when an app is added, built, or started while usage is over the soft limit for a quota
+pub const QUOTA_WARNING_EVENT: &str = "quota_warning";
+
+/// A lifecycle event for a function app, sent on the host event stream
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct HostEvent {
+    // The type of event, such as status_changed, deploy_progress, or container_crashed
+    pub event: String,
+
+    // The ID of the app
+    pub id: Uuid,
+
+    // The name of the app
+    pub app: String,
+
+    // The namespace the app belongs to
+    pub namespace: String,
+
+    // When the event happened, in seconds since the Unix epoch
+    pub timestamp: u64,
+
+    // The new status of the app, for status changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<FunctionAppStatus>,
+
+    // How far the deployment has got, for deploy progress: queued, building, failed, cancelled, awaiting_approval,
+    // approved, deployed, or unchanged if the code matched the latest deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+
+    // The deployment number, for deploy progress once the deployment has been recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<u32>,
+
+    // The exit code of the container, for crashes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+
+    // More detail about the event, such as why a build failed or a container exited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// The options for the host event stream, sent as query parameters
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct EventsOptions {
+    // Only send events for the app with this name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+
+    // Only send events for apps in this namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+/// A deployment of a function app, recorded each time its code is built
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct Deployment {
+    // The deployment number, counting up from 1 for each app
+    pub number: u32,
+
+    // When the build for the deployment started, in seconds since the Unix epoch
+    pub created_at: u64,
+
+    // Whether the deployment is approved to run. Deployments are approved straight away unless the host requires approval
+    pub approved: bool,
+
+    // When the deployment was approved, if it needed approval
+    pub approved_at: Option<u64>,
+
+    // The ID of the image built for the deployment, if it was signed
+    pub image_digest: Option<String>,
+
+    // When the image was signed, if it was
+    pub signed_at: Option<u64>,
+
+    // Whether a bill of materials was generated for the deployment
+    pub has_sbom: bool,
+
+    // When each phase of the deployment finished, so time spent in the queue isn't mistaken for a slow compile
+    #[serde(default)]
+    pub phases: DeployPhases,
+
+    // The host and deployment this deployment was promoted from, if it was imported rather than built
+    #[serde(default)]
+    pub promoted_from: Option<Promotion>,
+
+    // The hosts and deployments this deployment has been promoted to
+    #[serde(default)]
+    pub promoted_to: Vec<Promotion>,
+}
+
+/// A deployment promoted from one host to another, recorded on both hosts so it is clear where each image came from
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct Promotion {
+    // The other host, such as staging.example.com:8080
+    pub host: String,
+
+    // The number of the deployment on the other host
+    pub deployment: u32,
+
+    // The ID of the image that was promoted, which is the same on both hosts
+    pub image_id: String,
+
+    // When the deployment was promoted, in seconds since the Unix epoch
+    pub promoted_at: u64,
+}
+
+/// The details of a deployment exported to promote it to another host. An export is this as one line of JSON,
+/// followed by the image for the deployment saved with docker save
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct DeploymentExport {
+    // The name of the function app
+    pub app: String,
+
+    // The ID of the function app on the host it was exported from
+    pub app_id: Uuid,
+
+    // The number of the deployment on the host it was exported from
+    pub deployment: u32,
+
+    // The ID of the image, checked when it is imported so the image that runs is the one that was exported
+    pub image_id: String,
+
+    // The SHA-256 hash of the code and build options the image was built from
+    pub content_hash: Option<String>,
+
+    // The CycloneDX bill of materials generated when the image was built
+    pub sbom: Option<String>,
+
+    // The README from the code the image was built from
+    pub readme: Option<String>,
+}
+
+/// The header on an export with the number of the exported deployment
+pub const EXPORT_DEPLOYMENT_HEADER: &str = "x-rustless-deployment";
+
+/// The header on an export with the ID of the exported image
+pub const EXPORT_IMAGE_ID_HEADER: &str = "x-rustless-image-id";
+
+/// The options for importing a deployment exported from another host
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ImportOptions {
+    // The host the deployment was exported from, such as staging.example.com:8080, recorded with the deployment
+    #[serde(default)]
+    pub from: Option<String>,
+}
+
+/// The deployment made by importing a deployment exported from another host
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ImportedDeployment {
+    // The number of the new deployment
+    pub number: u32,
+
+    // Whether the deployment is approved to run. If not, it needs approving before the app can be started
+    pub approved: bool,
+
+    // The ID of the imported image
+    pub image_id: String,
+}
+
+/// The limits that apply to a function app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppLimits {
+    // How many bytes of a request body the gateway buffers before streaming it
+    pub request_buffer_threshold: u64,
+
+    // How many bytes of a response body the gateway buffers before streaming it
+    pub response_buffer_threshold: u64,
+
+    // The memory each container for the app can use, in bytes, or None if it isn't limited
+    pub memory_limit_bytes: Option<u64>,
+
+    // The most replicas the app can be scaled to
+    pub max_replicas: u32,
+}
+
+/// Everything about a function app in one place, for inspecting it without calling each endpoint
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct FunctionAppInfo {
+    // The app name
+    pub name: String,
+
+    // The namespace the app belongs to
+    pub namespace: String,
+
+    // The status of the app, with why it is in error if it is
+    pub status: FunctionAppStatusResult,
+
+    // The names of the settings made on the app, such as mirror_sink. The values are left out, as they can hold
+    // credentials or internal addresses
+    pub config: Vec<String>,
+
+    // The limits that apply to the app
+    pub limits: AppLimits,
+
+    // The most recent deployments, newest first
+    pub deployments: Vec<Deployment>,
+
+    // The timer trigger, if the app has one
+    pub timer_trigger: Option<TimerTrigger>,
+
+    // When the timer trigger next fires, in seconds since the Unix epoch
+    pub next_timer_run: Option<u64>,
+
+    // The profiles that scale the app on cron schedules
+    pub scale_profiles: ScaleProfilesReport,
+
+    // The replicas the app runs, and the containers running it
+    pub replicas: ReplicasReport,
+}
+
+/// The default settings for the function apps in a namespace. Apps use the defaults for the settings that aren't made
+/// on them, and settings that aren't set for the namespace use the host defaults
+#[derive(Clone, Default)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct NamespaceDefaults {
+    // How many bytes of a request body the gateway buffers before streaming it
+    #[serde(default)]
+    pub buffer_request_threshold: Option<u64>,
+
+    // How many bytes of a response body the gateway buffers before streaming it
+    #[serde(default)]
+    pub buffer_response_threshold: Option<u64>,
+
+    // The destinations apps can call through the egress proxy, for apps that don't have their own allowlist. None lets
+    // them call any destination
+    #[serde(default)]
+    pub egress_allowlist: Option<Vec<String>>,
+
+    // The most replicas an app can be scaled to, which can't be more than the host allows
+    #[serde(default)]
+    pub max_replicas: Option<u32>,
+}
+
+/// Where the value a function app uses for a setting comes from
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    /// App - the setting is made on the app
+    App,
+
+    /// Namespace - the app uses the default for its namespace
+    Namespace,
+
+    /// Host - the app uses the host default
+    Host,
+}
+
+/// The value a function app uses for a setting, and where it comes from
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct EffectiveSetting<T> {
+    // The value the app uses
+    pub value: T,
+
+    // Whether the value is set on the app, or comes from the namespace or host defaults
+    pub source: SettingSource,
+}
+
+/// The settings a function app uses, worked out from the settings made on it, the defaults for its namespace, and
+/// the host defaults
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct EffectiveConfig {
+    // The namespace the defaults come from
+    pub namespace: String,
+
+    // How many bytes of a request body the gateway buffers before streaming it
+    pub buffer_request_threshold: EffectiveSetting<u64>,
+
+    // How many bytes of a response body the gateway buffers before streaming it
+    pub buffer_response_threshold: EffectiveSetting<u64>,
+
+    // The destinations the app can call through the egress proxy, or None if it can call any destination
+    pub egress_allowlist: EffectiveSetting<Option<Vec<String>>>,
+
+    // The most replicas the app can be scaled to
+    pub max_replicas: EffectiveSetting<u32>,
+}
+
+/// When each phase of a deployment happened, in seconds since the Unix epoch. Deployments made before the phases
+/// were recorded have none of these set
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct DeployPhases {
+    // When the code was received
+    pub received_at: Option<u64>,
+
+    // When the code was extracted, and the build was queued
+    pub extracted_at: Option<u64>,
+
+    // When the build left the queue and started compiling
+    pub build_started_at: Option<u64>,
+
+    // When compiling finished and the image started being exported to docker
+    pub export_started_at: Option<u64>,
+
+    // When the image was exported and the build finished
+    pub built_at: Option<u64>,
+}
+
+/// The default number of log lines to get from a function app
+pub fn default_log_lines() -> usize {
+    200
+}
+
+/// The options for getting the logs of a function app, sent as query parameters
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct LogsOptions {
+    // The number of most recent lines to get
+    #[serde(default = "default_log_lines")]
+    pub tail: usize,
+
+    // Stream the logs as server sent events, with the most recent lines and then each line as the app writes it
+    #[serde(default)]
+    pub follow: bool,
+}
+
+/// The event sent on a log stream if docker stops sending the logs, with the error as the data. The stream ends
+/// after it. Lines of output are sent as unnamed events
+pub const LOGS_ERROR_EVENT: &str = "error";
+
+/// The limits the host puts on requests, to protect it from clients that send huge or slow requests
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct RequestLimits {
+    // The most headers a request can have
+    pub max_headers: usize,
+
+    // The most bytes the headers of a request can take up, counting names and values
+    pub max_header_bytes: usize,
+
+    // The most bytes the request line can take up, counting the method, path and query
+    pub max_request_line_bytes: usize,
+
+    // How long a client has to send the request line and headers before it is disconnected, in seconds
+    pub header_timeout_secs: u64,
+
+    // How long a client can go without sending any of the request body before it is disconnected, in seconds
+    pub body_idle_timeout_secs: u64,
+
+    // How long an idle keep-alive connection is kept open, in seconds
+    pub keep_alive_secs: u64,
+
+    // How long a client has to complete the TLS handshake, in seconds
+    pub tls_handshake_timeout_secs: u64,
+}
+
+/// The requests the host has rejected for breaking its limits since it started
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct RejectedRequests {
+    // Requests with too many headers
+    pub too_many_headers: u64,
+
+    // Requests with headers that were too large
+    pub headers_too_large: u64,
+
+    // Requests with a request line that was too long
+    pub request_line_too_long: u64,
+
+    // Requests that stopped sending their body
+    pub slow_body: u64,
+}
+
+/// The limits the host puts on requests and how many requests have broken them
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct RequestLimitsReport {
+    // The limits in use
+    pub limits: RequestLimits,
+
+    // The requests rejected since the host started
+    pub rejected: RejectedRequests,
+}
+
+/// How the crates.io cache on the host has been used since the host started, and what it has stored
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct CratesCacheStats {
+    // Whether the host is running the cache
+    pub enabled: bool,
+
+    // The registry URL builds download crates from, which can be an external cache
+    pub build_url: Option<String>,
+
+    // The number of index files served from the cache
+    pub index_hits: u64,
+
+    // The number of index files fetched from crates.io
+    pub index_misses: u64,
+
+    // The number of crates served from the cache
+    pub crate_hits: u64,
+
+    // The number of crates downloaded from crates.io
+    pub crate_misses: u64,
+
+    // The number of out of date index files served because crates.io couldn't be reached
+    pub stale_served: u64,
+
+    // The number of requests to crates.io that failed
+    pub upstream_errors: u64,
+
+    // The total bytes served to builds
+    pub bytes_served: u64,
+
+    // The number of files in the cache
+    pub cached_files: u64,
+
+    // The total size of the files in the cache, in bytes
+    pub cached_bytes: u64,
+}
+
+/// What was removed when the crates.io cache was purged
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct CratesCachePurge {
+    // The number of files removed
+    pub files_removed: u64,
+
+    // The number of bytes removed
+    pub bytes_removed: u64,
+}
+
+/// The faults a host built with the fault-injection feature is injecting, used by integration tests to
+/// exercise retries, the reconciler, and alerting
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct InjectedFaults {
+    // Whether the next build fails without running docker
+    #[serde(default)]
+    pub fail_next_build: bool,
+
+    // How many milliseconds the gateway waits before forwarding each request to an app
+    #[serde(default)]
+    pub proxy_delay_ms: u64,
+
+    // The percentage of app status writes that are silently dropped, from 0 to 100
+    #[serde(default)]
+    pub drop_status_writes_percent: u8,
+}
+
+/// The faults a host is injecting and how many times each has been hit since they were set
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct InjectedFaultsReport {
+    // The faults being injected
+    pub faults: InjectedFaults,
+
+    // The number of builds failed
+    pub failed_builds: u64,
+
+    // The number of requests delayed by the gateway
+    pub delayed_requests: u64,
+
+    // The number of status writes dropped
+    pub dropped_status_writes: u64,
+}
+
+/// A lease on a background job, such as the timer scheduler, which makes sure only one host process runs it
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct Lease {
+    // The name of the job
+    pub name: String,
+
+    // The host process holding the lease
+    pub holder: String,
+
+    // When the lease expires unless it is renewed, as a Unix timestamp
+    pub expires_at: u64,
+
+    // Whether the host answering the request holds the lease
+    pub held_by_this_host: bool,
+}
+
+/// The leases on background jobs, and which backend stores them
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct LeasesReport {
+    // The backend the leases are stored in
+    pub backend: String,
+
+    // The ID of the host process answering the request, as used for the lease holder
+    pub holder: String,
+
+    // The leases
+    pub leases: Vec<Lease>,
+}
+
+/// An API key that allows access to the management API. The key itself is only shown when it is created
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct ApiKey {
+    // The name the key was created with, used to revoke it
+    pub name: String,
+
+    // When the key was created, as a Unix timestamp
+    pub created_at: u64,
+
+    // When the key was revoked, as a Unix timestamp, or None if it can still be used
+    pub revoked_at: Option<u64>,
+}
+
+/// A function app registered again from the labels on its image, such as after the database was restored
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AdoptedApp {
+    // The ID of the app, kept from the image so the app keeps its ID
+    pub id: Uuid,
+
+    // The name of the app
+    pub name: String,
+
+    // The namespace the app was registered in
+    pub namespace: String,
+
+    // The number of the deployment the image was built for, or None if the image doesn't have one
+    pub deployment: Option<u32>,
+}
+
+/// An image with rustless labels that couldn't be adopted
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct SkippedImage {
+    // The name of the app on the image
+    pub name: String,
+
+    // Why the app couldn't be adopted
+    pub reason: String,
+}
+
+/// The function apps adopted from images with rustless labels that weren't in the database
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AdoptReport {
+    // The apps that were registered
+    pub adopted: Vec<AdoptedApp>,
+
+    // The images that were left alone, with why
+    pub skipped: Vec<SkippedImage>,
+}
+
+/// How much of a quota on the host is used, and the limits for it
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct QuotaUsage {
+    // The name of the quota: apps, disk, or memory
+    pub name: String,
+
+    // How much is used: a count of apps, or bytes for disk and memory
+    pub used: u64,
+
+    // The usage that sends warnings, if the quota has a limit
+    pub soft_limit: Option<u64>,
+
+    // The usage that rejects operations, if the quota has a limit
+    pub hard_limit: Option<u64>,
+
+    // The error from measuring the usage, such as docker not being reachable
+    pub error: Option<String>,
+}
+
+/// The quotas on the host and how much of each is used
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct QuotasReport {
+    // The percentage of each hard limit that sends warnings
+    pub warn_percent: u8,
+
+    // The quotas
+    pub quotas: Vec<QuotaUsage>,
+}
+
+/// The request to set the function apps a function app depends on. The app is only started once they are healthy
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct DependenciesRequest {
+    // The names of the apps to start first. An empty list removes the dependencies
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+/// The function apps a function app depends on, and the apps that depend on it
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct DependenciesReport {
+    // The names of the apps that are started before this one
+    pub depends_on: Vec<String>,
+
+    // The names of the apps that are started after this one
+    pub required_by: Vec<String>,
+}
+
+/// The options for starting a function app
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct StartOptions {
+    // Whether to start the apps it depends on first, waiting for each to pass its health check
+    #[serde(default)]
+    pub with_deps: bool,
+}
+
+/// The request to start a number of function apps at once
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BulkStartRequest {
+    // The names of the apps to start. The apps they depend on are started too. An empty list starts every app that
+    // is ready to start
+    #[serde(default)]
+    pub apps: Vec<String>,
+}
+
+/// What happened to a function app started with the others in a bulk start
+#[derive(Debug)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppStartResult {
+    // The app name
+    pub name: String,
+
+    // Whether the app was already running, so wasn't started
+    pub already_running: bool,
+
+    // Why the app couldn't be started, such as an app it depends on failing to start. None if it is running
+    pub error: Option<String>,
+}
+
+/// The function apps started by a bulk start, in the order they were started
+#[derive(Debug)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct BulkStartReport {
+    // The apps, with each app after the apps it depends on
+    pub apps: Vec<AppStartResult>,
+}
+
+/// The default number of weeks the deployment analytics cover
+pub fn default_analytics_weeks() -> u32 {
+    4
+}
+
+/// The options for getting the deployment analytics, sent as query parameters
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AnalyticsOptions {
+    // The number of weeks to cover, ending now
+    #[serde(default = "default_analytics_weeks")]
+    pub weeks: u32,
+
+    // Only get the analytics for the function app with this name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+
+    // Only get the analytics for the function apps in this namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+/// The deployments, builds, and crashes of a function app in one week
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct WeekAnalytics {
+    // When the week started, in seconds since the Unix epoch
+    pub starts_at: u64,
+
+    // The number of deployments made in the week
+    pub deployments: u32,
+
+    // The number of builds started in the week
+    pub builds: u32,
+
+    // The number of builds started in the week that failed
+    pub failed_builds: u32,
+
+    // The number of times the app crashed in the week
+    pub failures: u32,
+}
+
+/// The deployment analytics for a function app, in the style of the DORA metrics: how often it is deployed, how
+/// often and how quickly it builds, and how long it takes to recover when it crashes
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AppAnalytics {
+    // The app name
+    pub name: String,
+
+    // The app ID
+    pub id: Uuid,
+
+    // The namespace the app belongs to
+    pub namespace: String,
+
+    // The number of deployments made over the weeks covered
+    pub deployments: u32,
+
+    // The average number of deployments made each week
+    pub deploys_per_week: f64,
+
+    // The number of builds started over the weeks covered
+    pub builds: u32,
+
+    // The percentage of the builds that succeeded, or None if there were no builds
+    pub build_success_rate: Option<f64>,
+
+    // The average time the builds that succeeded took, in seconds, or None if none succeeded
+    pub mean_build_secs: Option<f64>,
+
+    // The number of times the app crashed over the weeks covered
+    pub failures: u32,
+
+    // The average time from the app crashing to it running again, in seconds, or None if it hasn't recovered from
+    // any crashes
+    pub mean_time_to_recovery_secs: Option<f64>,
+
+    // The figures for each week, oldest first
+    pub weeks: Vec<WeekAnalytics>,
+}
+
+/// The deployment analytics for the function apps on the host
+#[derive(Deserialize)]
+#[derive(Serialize)]
+pub struct AnalyticsReport {
+    // The number of weeks covered, ending now
+    pub weeks: u32,
+
+    // When the weeks covered started, in seconds since the Unix epoch
+    pub since: u64,
+
+    // The analytics for each function app
+    pub apps: Vec<AppAnalytics>,
+}